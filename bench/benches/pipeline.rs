@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nb2::batch::{Batch, Pipeline, Poll};
+use nb2::packets::ip::v4::Ipv4;
+use nb2::packets::{Ethernet, Packet, Udp};
+use nb2::testils::byte_arrays::UDP_PACKET;
+use nb2::testils::{NullTx, RepeatRx};
+
+const BATCH_SIZES: [usize; 5] = [1, 8, 32, 64, 128];
+
+fn forward(rx: RepeatRx) -> impl Pipeline {
+    Poll::new(rx).send(NullTx::new())
+}
+
+fn parse_and_rewrite(rx: RepeatRx) -> impl Pipeline {
+    Poll::new(rx)
+        .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+        .filter(|ipv4| ipv4.ttl() > 1)
+        .map(|mut ipv4| {
+            ipv4.set_ttl(ipv4.ttl() - 1);
+            ipv4.parse::<Udp<Ipv4>>()
+        })
+        .map(|mut udp| {
+            udp.set_dst_port(2000);
+            udp.cascade();
+            Ok(udp)
+        })
+        .send(NullTx::new())
+}
+
+fn bench_pipeline<P, F>(c: &mut Criterion, name: &str, f: F)
+where
+    P: Pipeline,
+    F: Fn(RepeatRx) -> P,
+{
+    let mut group = c.benchmark_group(name);
+
+    for &batch_size in BATCH_SIZES.iter() {
+        group.throughput(Throughput::Elements(batch_size as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(batch_size),
+            &batch_size,
+            |b, &batch_size| {
+                let mut pipeline = f(RepeatRx::new(&UDP_PACKET, batch_size));
+                b.iter(|| pipeline.run_once());
+            },
+        );
+    }
+
+    group.finish();
+}
+
+#[nb2::bench(mempool_capacity = 511)]
+fn pipeline_benchmark(c: &mut Criterion) {
+    bench_pipeline(c, "pipeline::forward", forward);
+    bench_pipeline(c, "pipeline::parse_and_rewrite", parse_and_rewrite);
+}
+
+criterion_group!(benches, pipeline_benchmark);
+criterion_main!(benches);
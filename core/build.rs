@@ -11,4 +11,8 @@ fn main() {
     println!("cargo:rustc-link-search=native={}/build/lib", rte_sdk);
     // need to statically link the mempool ring driver for `cargo test`
     println!("cargo:rustc-link-lib=static=rte_mempool_ring");
+
+    // the control plane service.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/control.proto").unwrap();
 }
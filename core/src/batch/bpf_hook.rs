@@ -0,0 +1,70 @@
+use super::{Batch, Disposition};
+use crate::bpf::{self, HookPoint, Program};
+use crate::packets::{data_slice, Packet};
+use crate::stats::{self, DropReason};
+use std::sync::Arc;
+
+/// A batch that filters the packets of the underlying batch with the
+/// classic BPF program attached to a named hook point.
+///
+/// While no program is attached, the packets pass through. The program is
+/// run on the whole frame, from the start of the buffer, and the packets
+/// it rejects are dropped and recorded as `DropReason::Filtered`.
+pub struct BpfHook<B: Batch> {
+    batch: B,
+    hook: Arc<HookPoint>,
+    // the program of the hook, as of the version.
+    version: usize,
+    program: Option<Arc<Program>>,
+}
+
+impl<B: Batch> BpfHook<B> {
+    #[inline]
+    pub fn new(batch: B, name: &str) -> Self {
+        let hook = bpf::hook_point(name);
+        let version = hook.version();
+        let program = hook.program();
+
+        BpfHook {
+            batch,
+            hook,
+            version,
+            program,
+        }
+    }
+}
+
+impl<B: Batch> Batch for BpfHook<B> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let version = self.hook.version();
+        if version != self.version {
+            self.version = version;
+            self.program = self.hook.program();
+        }
+
+        let program = &self.program;
+
+        self.batch.next().map(|disp| {
+            disp.map(|pkt| match program {
+                Some(program) => {
+                    let mbuf = pkt.mbuf();
+                    if program.matches(data_slice(mbuf, 0, mbuf.data_len())) {
+                        Disposition::Act(pkt)
+                    } else {
+                        stats::record_drop(DropReason::Filtered);
+                        Disposition::Drop(pkt.reset())
+                    }
+                }
+                None => Disposition::Act(pkt),
+            })
+        })
+    }
+}
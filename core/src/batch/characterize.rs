@@ -0,0 +1,77 @@
+use super::{Batch, Disposition};
+use crate::dpdk::{tsc, tsc_hz, PacketMeta};
+use crate::packets::Packet;
+use crate::stats::{self, TrafficCounters};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A batch that characterizes the traffic going through, by traffic class.
+///
+/// The packets are counted in the stats under the name of the
+/// characterization point, with a histogram of their sizes. The rates are
+/// of the last window that ended, so they are fresh to within `window`.
+/// Read them with `stats::traffic_stats`.
+///
+/// The packets dropped upstream are not counted.
+pub struct Characterize<B: Batch> {
+    batch: B,
+    counters: Arc<TrafficCounters>,
+    // the packets and bytes of the current window, by traffic class.
+    window: [(u64, u64); PacketMeta::CLASSES],
+    // the time stamp counter when the current window started.
+    start: u64,
+    cycles: u64,
+}
+
+impl<B: Batch> Characterize<B> {
+    #[inline]
+    pub fn new(batch: B, name: &str, window: Duration) -> Self {
+        let cycles = window.as_micros() as u64 * tsc_hz() / 1_000_000;
+
+        Characterize {
+            batch,
+            counters: stats::register_traffic(name),
+            window: [(0, 0); PacketMeta::CLASSES],
+            start: tsc(),
+            cycles: cycles.max(1),
+        }
+    }
+}
+
+impl<B: Batch> Batch for Characterize<B> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+
+        // the windows end between batches, so an idle class reports a
+        // rate of zero. an empty poll keeps the context of the last burst,
+        // so the time is read here.
+        let now = tsc();
+        let elapsed = now.wrapping_sub(self.start);
+        if elapsed >= self.cycles {
+            self.counters.record_window(&self.window, elapsed);
+            self.window = [(0, 0); PacketMeta::CLASSES];
+            self.start = now;
+        }
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let counters = &self.counters;
+        let window = &mut self.window;
+
+        self.batch.next().map(|disp| {
+            disp.map(|pkt| {
+                let mbuf = pkt.mbuf();
+                let class = mbuf.meta().traffic_class as usize;
+                let len = mbuf.pkt_len();
+                counters.record(class, len);
+                window[class].0 += 1;
+                window[class].1 += len as u64;
+                Disposition::Act(pkt)
+            })
+        })
+    }
+}
@@ -0,0 +1,317 @@
+use super::PacketTx;
+use crate::packets::checksum::{self, PseudoHeader};
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+use crate::packets::{data_slice, EtherTypes};
+use crate::{warn, Mbuf};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const VLAN_TAG_LEN: usize = 4;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+
+// the shortest ethernet frame without the FCS. shorter frames are padded.
+const MIN_FRAME_LEN: usize = 60;
+
+// the number of bytes of the packet dumped with a violation.
+const DUMP_LEN: usize = 128;
+
+/// An inconsistency in an outgoing packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Violation {
+    /// The packet is shorter than its headers.
+    Truncated,
+    /// The ether type does not match the version of the IP header.
+    EtherTypeMismatch,
+    /// The length in the IP header does not match the length of the
+    /// buffer.
+    IpLengthMismatch { ip_len: usize, buffer_len: usize },
+    /// The IPv4 header checksum is invalid.
+    BadIpChecksum,
+    /// The TCP, UDP or ICMPv6 checksum is invalid.
+    BadL4Checksum(ProtocolNumber),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Truncated => write!(f, "truncated headers"),
+            Violation::EtherTypeMismatch => write!(f, "ether type does not match L3"),
+            Violation::IpLengthMismatch { ip_len, buffer_len } => write!(
+                f,
+                "IP length {} does not match buffer length {}",
+                ip_len, buffer_len
+            ),
+            Violation::BadIpChecksum => write!(f, "bad IPv4 header checksum"),
+            Violation::BadL4Checksum(protocol) => write!(f, "bad {} checksum", protocol),
+        }
+    }
+}
+
+#[inline]
+fn be16(bytes: &[u8], offset: usize) -> usize {
+    (usize::from(bytes[offset]) << 8) | usize::from(bytes[offset + 1])
+}
+
+/// Checks the length of the IP packet starting at `offset` against the
+/// frame, which can be padded up to the minimum frame length.
+fn check_ip_len(offset: usize, ip_len: usize, buffer_len: usize) -> Result<(), Violation> {
+    let end = offset + ip_len;
+    if end == buffer_len || (end < buffer_len && buffer_len <= MIN_FRAME_LEN) {
+        Ok(())
+    } else {
+        Err(Violation::IpLengthMismatch { ip_len, buffer_len })
+    }
+}
+
+fn check_l4(
+    protocol: ProtocolNumber,
+    pseudo_header: PseudoHeader,
+    segment: &[u8],
+) -> Result<(), Violation> {
+    let checksum_offset = match protocol {
+        ProtocolNumbers::Tcp => 16,
+        ProtocolNumbers::Udp => 6,
+        ProtocolNumbers::Icmpv6 => 2,
+        _ => return Ok(()),
+    };
+
+    if segment.len() < checksum_offset + 2 {
+        return Err(Violation::Truncated);
+    }
+
+    // a zero UDP checksum over IPv4 means no checksum.
+    let unset = protocol == ProtocolNumbers::Udp && be16(segment, checksum_offset) == 0;
+    let is_v4 = match pseudo_header {
+        PseudoHeader::V4 { .. } => true,
+        PseudoHeader::V6 { .. } => false,
+    };
+
+    if (unset && is_v4) || checksum::compute(pseudo_header.sum(), segment) == 0 {
+        Ok(())
+    } else {
+        Err(Violation::BadL4Checksum(protocol))
+    }
+}
+
+fn check_v4(bytes: &[u8], offset: usize, checksums: bool) -> Result<(), Violation> {
+    if bytes.len() < offset + IPV4_HEADER_LEN {
+        return Err(Violation::Truncated);
+    }
+    let header = &bytes[offset..];
+    if header[0] >> 4 != 4 {
+        return Err(Violation::EtherTypeMismatch);
+    }
+
+    let ihl = usize::from(header[0] & 0x0f) * 4;
+    let ip_len = be16(header, 2);
+    if ihl < IPV4_HEADER_LEN || ip_len < ihl || bytes.len() < offset + ip_len {
+        return Err(Violation::Truncated);
+    }
+    check_ip_len(offset, ip_len, bytes.len())?;
+
+    if !checksums {
+        return Ok(());
+    }
+
+    if checksum::compute(0, &header[..ihl]) != 0 {
+        return Err(Violation::BadIpChecksum);
+    }
+
+    // only the first fragment has the transport header, and the checksum
+    // covers the whole datagram.
+    let more_fragments = header[6] & 0x20 != 0;
+    let fragment_offset = be16(header, 6) & 0x1fff;
+    if more_fragments || fragment_offset != 0 {
+        return Ok(());
+    }
+
+    let protocol = ProtocolNumber::new(header[9]);
+    let segment = &header[ihl..ip_len];
+    let pseudo_header = PseudoHeader::V4 {
+        src: Ipv4Addr::new(header[12], header[13], header[14], header[15]),
+        dst: Ipv4Addr::new(header[16], header[17], header[18], header[19]),
+        packet_len: segment.len() as u16,
+        protocol,
+    };
+    check_l4(protocol, pseudo_header, segment)
+}
+
+fn check_v6(bytes: &[u8], offset: usize, checksums: bool) -> Result<(), Violation> {
+    if bytes.len() < offset + IPV6_HEADER_LEN {
+        return Err(Violation::Truncated);
+    }
+    let header = &bytes[offset..];
+    if header[0] >> 4 != 6 {
+        return Err(Violation::EtherTypeMismatch);
+    }
+
+    let ip_len = IPV6_HEADER_LEN + be16(header, 4);
+    if bytes.len() < offset + ip_len {
+        return Err(Violation::Truncated);
+    }
+    check_ip_len(offset, ip_len, bytes.len())?;
+
+    if !checksums {
+        return Ok(());
+    }
+
+    // the transport behind extension headers is not checked.
+    let protocol = ProtocolNumber::new(header[6]);
+    let mut src = [0; 16];
+    let mut dst = [0; 16];
+    src.copy_from_slice(&header[8..24]);
+    dst.copy_from_slice(&header[24..40]);
+    let segment = &header[IPV6_HEADER_LEN..ip_len];
+    let pseudo_header = PseudoHeader::V6 {
+        src: Ipv6Addr::from(src),
+        dst: Ipv6Addr::from(dst),
+        packet_len: segment.len() as u16,
+        protocol,
+    };
+    check_l4(protocol, pseudo_header, segment)
+}
+
+/// Checks the invariants of an outgoing frame. Frames that are not IP are
+/// not checked past the ethernet header.
+pub fn check_invariants(bytes: &[u8], checksums: bool) -> Result<(), Violation> {
+    if bytes.len() < ETHERNET_HEADER_LEN {
+        return Err(Violation::Truncated);
+    }
+
+    let mut offset = ETHERNET_HEADER_LEN;
+    let mut ether_type = be16(bytes, 12) as u16;
+    if ether_type == EtherTypes::Vlan.0 {
+        if bytes.len() < ETHERNET_HEADER_LEN + VLAN_TAG_LEN {
+            return Err(Violation::Truncated);
+        }
+        ether_type = be16(bytes, 16) as u16;
+        offset += VLAN_TAG_LEN;
+    }
+
+    if ether_type == EtherTypes::Ipv4.0 {
+        check_v4(bytes, offset, checksums)
+    } else if ether_type == EtherTypes::Ipv6.0 {
+        check_v6(bytes, offset, checksums)
+    } else {
+        Ok(())
+    }
+}
+
+/// Formats the bytes as a hex dump, 16 bytes per line.
+fn hexdump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            format!("{:04x}: {}", line * 16, hex)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A transmit that checks the invariants of the outgoing packets before
+/// passing them on to the underlying transmit, in debug builds.
+///
+/// The IP length must match the length of the buffer, the IP version must
+/// match the ether type, and the IPv4 header, TCP, UDP and ICMPv6
+/// checksums must be valid. The violations are logged as warnings, with a
+/// dump of the start of the packet, and the packets are sent anyway. In
+/// release builds, the packets are passed on unchecked.
+///
+/// # Example
+///
+/// ```
+/// runtime.add_pipeline_to_port("eth1", |q| {
+///     Poll::new(q.clone()).map(nat).send(CheckedTx::new(q))
+/// })?;
+/// ```
+pub struct CheckedTx<Tx: PacketTx> {
+    tx: Tx,
+    checksums: bool,
+}
+
+impl<Tx: PacketTx> CheckedTx<Tx> {
+    /// Creates a new checked transmit in front of `tx`.
+    pub fn new(tx: Tx) -> Self {
+        CheckedTx {
+            tx,
+            checksums: true,
+        }
+    }
+
+    /// Skips the checksums, for ports that compute them on transmit, such
+    /// as with `tx_checksum_fixup`.
+    pub fn skip_checksums(mut self) -> Self {
+        self.checksums = false;
+        self
+    }
+
+    /// Checks the packets and passes them on.
+    pub(crate) fn transmit(&mut self, packets: Vec<Mbuf>) {
+        if cfg!(debug_assertions) {
+            for packet in &packets {
+                let bytes = data_slice(packet, 0, packet.data_len());
+                if let Err(violation) = check_invariants(bytes, self.checksums) {
+                    let dump = hexdump(&bytes[..bytes.len().min(DUMP_LEN)]);
+                    warn!(%violation, "invalid outgoing packet:\n{}", dump);
+                }
+            }
+        }
+
+        self.tx.transmit(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::{TCP_PACKET, UDP_PACKET};
+
+    #[test]
+    fn valid_packets() {
+        assert_eq!(Ok(()), check_invariants(&UDP_PACKET, true));
+        assert_eq!(Ok(()), check_invariants(&TCP_PACKET, true));
+    }
+
+    #[test]
+    fn detect_violations() {
+        let mut bytes = UDP_PACKET;
+        bytes[14] = 0x65;
+        assert_eq!(
+            Err(Violation::EtherTypeMismatch),
+            check_invariants(&bytes, true)
+        );
+
+        let mut bytes = UDP_PACKET;
+        bytes[22] = 1;
+        assert_eq!(
+            Err(Violation::BadIpChecksum),
+            check_invariants(&bytes, true)
+        );
+        assert_eq!(Ok(()), check_invariants(&bytes, false));
+
+        let mut bytes = UDP_PACKET;
+        bytes[51] ^= 0xff;
+        assert_eq!(
+            Err(Violation::BadL4Checksum(ProtocolNumbers::Udp)),
+            check_invariants(&bytes, true)
+        );
+
+        let mut bytes = UDP_PACKET.to_vec();
+        bytes.extend_from_slice(&[0; 16]);
+        assert_eq!(
+            Err(Violation::IpLengthMismatch {
+                ip_len: 38,
+                buffer_len: 68
+            }),
+            check_invariants(&bytes, true)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::dpdk::{tsc, CoreId, PortId};
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// The data shared by all the operators of a pipeline run, for the burst
+/// of packets being processed.
+///
+/// `Poll` starts a new context every time it receives a burst, so the
+/// values read once per burst, such as the time, are the same for every
+/// packet and every operator of the burst. An empty poll does not start
+/// one, so between the bursts the context is of the last burst. The
+/// operators access the context of the current core with `with_context`,
+/// and can attach their own values as extensions, keyed by type. The
+/// extensions are cleared when the next burst starts.
+///
+/// # Example
+///
+/// ```
+/// let batch = Poll::new(q.clone()).for_each(|packet| {
+///     let now = with_context(|ctx| ctx.timestamp());
+///     flows.touch(packet, now);
+///     Ok(())
+/// });
+/// ```
+pub struct Context {
+    timestamp: SystemTime,
+    tsc: u64,
+    port_id: Option<PortId>,
+    core_id: CoreId,
+    burst_len: usize,
+    extensions: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Context {
+    fn new() -> Self {
+        Context {
+            timestamp: SystemTime::now(),
+            tsc: tsc(),
+            port_id: None,
+            core_id: CoreId::current(),
+            burst_len: 0,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Returns the time the burst was received.
+    #[inline]
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Returns the time stamp counter when the burst was received.
+    #[inline]
+    pub fn tsc(&self) -> u64 {
+        self.tsc
+    }
+
+    /// Returns the port the burst was received on, or `None` if the
+    /// packets did not come from a port.
+    #[inline]
+    pub fn port_id(&self) -> Option<PortId> {
+        self.port_id
+    }
+
+    /// Returns the core the pipeline runs on.
+    #[inline]
+    pub fn core_id(&self) -> CoreId {
+        self.core_id
+    }
+
+    /// Returns the number of packets in the burst.
+    #[inline]
+    pub fn burst_len(&self) -> usize {
+        self.burst_len
+    }
+
+    /// Returns the extension of type `T`, if set for the burst.
+    #[inline]
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.extensions
+            .get(&TypeId::of::<T>())
+            .and_then(|ext| ext.downcast_ref())
+    }
+
+    /// Returns the extension of type `T`, if set for the burst.
+    #[inline]
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.extensions
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|ext| ext.downcast_mut())
+    }
+
+    /// Sets the extension of type `T` for the rest of the burst, replacing
+    /// the previous one of the same type.
+    #[inline]
+    pub fn insert<T: 'static>(&mut self, ext: T) {
+        self.extensions.insert(TypeId::of::<T>(), Box::new(ext));
+    }
+
+    /// Returns the extension of type `T`, setting it with `f` first if it
+    /// is not set for the burst yet.
+    #[inline]
+    pub fn get_or_insert_with<T: 'static, F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        self.extensions
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(f()))
+            .downcast_mut()
+            .unwrap()
+    }
+}
+
+thread_local! {
+    // the context of the burst being processed on the current core.
+    static CONTEXT: RefCell<Context> = RefCell::new(Context::new());
+}
+
+/// Starts the context of a new burst on the current core.
+pub(crate) fn begin_burst(port_id: Option<PortId>, burst_len: usize) {
+    CONTEXT.with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        ctx.timestamp = SystemTime::now();
+        ctx.tsc = tsc();
+        ctx.port_id = port_id;
+        ctx.burst_len = burst_len;
+        ctx.extensions.clear();
+    });
+}
+
+/// Calls `f` with the context of the burst being processed on the current
+/// core.
+///
+/// # Panics
+///
+/// Panics if called from within `with_context_mut`.
+#[inline]
+pub fn with_context<R, F: FnOnce(&Context) -> R>(f: F) -> R {
+    CONTEXT.with(|ctx| f(&ctx.borrow()))
+}
+
+/// Calls `f` with the context of the burst being processed on the current
+/// core, to set its extensions.
+///
+/// # Panics
+///
+/// Panics if called from within `with_context` or `with_context_mut`.
+#[inline]
+pub fn with_context_mut<R, F: FnOnce(&mut Context) -> R>(f: F) -> R {
+    CONTEXT.with(|ctx| f(&mut ctx.borrow_mut()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[nb2::test]
+    fn context_of_burst() {
+        let port_id = PortId::new(2);
+        begin_burst(Some(port_id), 4);
+
+        let (timestamp, tsc) = with_context(|ctx| {
+            assert_eq!(Some(port_id), ctx.port_id());
+            assert_eq!(CoreId::current(), ctx.core_id());
+            assert_eq!(4, ctx.burst_len());
+            (ctx.timestamp(), ctx.tsc())
+        });
+
+        with_context_mut(|ctx| ctx.insert(7u32));
+        with_context_mut(|ctx| *ctx.get_or_insert_with(|| 0u64) += 1);
+
+        // the same values for the rest of the burst.
+        with_context(|ctx| {
+            assert_eq!(timestamp, ctx.timestamp());
+            assert_eq!(tsc, ctx.tsc());
+            assert_eq!(Some(&7u32), ctx.get::<u32>());
+            assert_eq!(Some(&1u64), ctx.get::<u64>());
+        });
+
+        begin_burst(None, 0);
+        with_context(|ctx| {
+            assert_eq!(None, ctx.port_id());
+            assert!(ctx.get::<u32>().is_none());
+        });
+    }
+}
@@ -0,0 +1,224 @@
+use super::{with_context, Batch, Disposition};
+use crate::dpdk::tsc_hz;
+use crate::net::{packet_hash, BloomFilter, HashFields, HashKey};
+use crate::packets::Packet;
+use crate::stats::{self, DropReason};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+use std::time::Duration;
+
+// the false positive rate of the filters, the fraction of the packets
+// wrongly dropped as duplicates when the filters are at capacity.
+const FP_RATE: f64 = 1e-6;
+
+/// The parts of a packet compared to tell the duplicates apart.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DedupFields {
+    /// The whole frame, for the copies taken on the same link, by two
+    /// taps or by a tap and a mirror port.
+    Frame,
+    /// The addresses, protocol and identification of the IP header, and
+    /// the IP payload, for the copies taken on both sides of a router,
+    /// which change the link layer header and the TTL. The frames other
+    /// than IP are compared by their link layer payload.
+    Ip,
+    /// The fields of the set.
+    Fields(HashFields),
+}
+
+impl DedupFields {
+    fn hash_fields(self) -> HashFields {
+        match self {
+            DedupFields::Frame => HashFields::FRAME,
+            DedupFields::Ip => {
+                HashFields::ADDRS | HashFields::PROTOCOL | HashFields::IP_ID | HashFields::PAYLOAD
+            }
+            DedupFields::Fields(fields) => fields,
+        }
+    }
+}
+
+/// The digests seen within the last one to two windows.
+///
+/// The digests go in the filter of the current window. When the window
+/// ends, the filter of the previous window is cleared and becomes the
+/// filter of the current one. A digest is a duplicate if it is in either
+/// filter.
+struct Window {
+    current: BloomFilter,
+    previous: BloomFilter,
+    // the time stamp counter when the current window started.
+    start: u64,
+    cycles: u64,
+}
+
+impl Window {
+    fn new(cycles: u64, capacity: usize, now: u64) -> Self {
+        let filter = BloomFilter::with_rate(capacity, FP_RATE);
+        Window {
+            current: filter.clone(),
+            previous: filter,
+            start: now,
+            cycles: cycles.max(1),
+        }
+    }
+
+    /// Adds the digest seen at `now`, returning whether it is a duplicate.
+    fn check(&mut self, digest: u64, now: u64) -> bool {
+        let elapsed = now.wrapping_sub(self.start);
+        if elapsed >= self.cycles {
+            mem::swap(&mut self.current, &mut self.previous);
+            self.current.clear();
+            // nothing is recent enough to keep after two windows.
+            if elapsed >= self.cycles * 2 {
+                self.previous.clear();
+            }
+            self.start = now;
+        }
+
+        self.previous.contains(&digest) || !self.current.insert(&digest)
+    }
+}
+
+/// A batch that drops the packets already seen within a time window.
+///
+/// The packets are compared by the `packet_hash` of their `DedupFields`,
+/// keyed with a random SipHash key so the collisions cannot be crafted. The
+/// duplicates within `window` of the first copy are always dropped, the
+/// ones up to twice `window` after it may be. The filters are sized for
+/// `capacity` packets per window. A packet is wrongly dropped as a
+/// duplicate about once in a million when the filters are at capacity,
+/// and more often beyond.
+///
+/// The dropped packets are recorded in the stats as
+/// `DropReason::Duplicate`.
+pub struct Dedup<B: Batch> {
+    batch: B,
+    fields: HashFields,
+    key: HashKey,
+    window: Window,
+}
+
+/// Returns a random SipHash key, from the random keys of the std hash
+/// maps.
+fn random_key() -> HashKey {
+    let state = RandomState::new();
+    let k0 = state.build_hasher().finish();
+    let mut hasher = state.build_hasher();
+    hasher.write_u8(1);
+    HashKey::Sip(k0, hasher.finish())
+}
+
+impl<B: Batch> Dedup<B> {
+    #[inline]
+    pub fn new(batch: B, window: Duration, capacity: usize, fields: DedupFields) -> Self {
+        let cycles = window.as_micros() as u64 * tsc_hz() / 1_000_000;
+        let now = with_context(|ctx| ctx.tsc());
+
+        Dedup {
+            batch,
+            fields: fields.hash_fields(),
+            key: random_key(),
+            window: Window::new(cycles, capacity, now),
+        }
+    }
+}
+
+impl<B: Batch> Batch for Dedup<B> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let fields = self.fields;
+        let key = self.key;
+        let window = &mut self.window;
+
+        self.batch.next().map(|disp| {
+            disp.map(|pkt| {
+                let now = with_context(|ctx| ctx.tsc());
+                let digest = packet_hash(pkt.mbuf(), fields, key);
+                if window.check(digest, now) {
+                    stats::record_drop(DropReason::Duplicate);
+                    Disposition::Drop(pkt.reset())
+                } else {
+                    Disposition::Act(pkt)
+                }
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::{PacketTx, Poll};
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::Ethernet;
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+    use crate::Mbuf;
+    use std::sync::mpsc;
+
+    #[test]
+    fn forget_after_two_windows() {
+        let mut window = Window::new(100, 1000, 0);
+
+        assert!(!window.check(1, 0));
+        assert!(window.check(1, 50));
+        assert!(!window.check(2, 99));
+
+        // the previous window is still checked.
+        assert!(window.check(1, 150));
+        assert!(window.check(2, 150));
+
+        // two windows later, everything is forgotten.
+        assert!(!window.check(1, 450));
+        assert!(window.check(1, 460));
+    }
+
+    #[nb2::test]
+    fn ignore_ttl_of_ip_copies() {
+        let digest = |mbuf: &Mbuf, fields: DedupFields| {
+            packet_hash(mbuf, fields.hash_fields(), HashKey::default())
+        };
+
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let mut ipv4 = packet.parse::<Ethernet>().unwrap().parse::<Ipv4>().unwrap();
+        let before = digest(ipv4.mbuf(), DedupFields::Ip);
+        let frame = digest(ipv4.mbuf(), DedupFields::Frame);
+        ipv4.set_ttl(ipv4.ttl() - 1);
+
+        assert_eq!(before, digest(ipv4.mbuf(), DedupFields::Ip));
+        assert_ne!(frame, digest(ipv4.mbuf(), DedupFields::Frame));
+
+        let other = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        assert_ne!(before, digest(&other, DedupFields::Ip));
+    }
+
+    #[nb2::test]
+    fn keep_distinct_packets() {
+        let (mut tx, rx) = mpsc::channel();
+        let mut batch = Poll::new(rx).dedup(Duration::from_secs(60), 10_000, DedupFields::Ip);
+
+        // one at a time, the test mempool is small.
+        let mut drops = 0;
+        for id in 0..10_000u16 {
+            let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+            let mut ipv4 = packet.parse::<Ethernet>().unwrap().parse::<Ipv4>().unwrap();
+            ipv4.set_identification(id);
+            tx.transmit(vec![ipv4.reset()]);
+
+            batch.replenish();
+            if batch.next().unwrap().is_drop() {
+                drops += 1;
+            }
+        }
+
+        assert_eq!(0, drops);
+    }
+}
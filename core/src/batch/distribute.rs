@@ -0,0 +1,192 @@
+use super::{Batch, Disposition, PacketTx, Pipeline};
+use crate::dpdk::tsc;
+use crate::net::{ip_packet_hash, HashFields, HashKey};
+use crate::packets::Packet;
+use crate::stats::record_poll;
+use crate::Mbuf;
+use futures::{future, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_executor::current_thread;
+
+/// How `Distribute` picks the downstream target for each packet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Distribution {
+    /// Picks the target by the hash of the packet's 5-tuple, so all the
+    /// packets of a flow go to the same target and stay in order.
+    ///
+    /// Packets that are not TCP or UDP are hashed by their IP addresses.
+    /// Packets that are not IP all go to the first target.
+    FlowHash,
+    /// Picks the target by the `packet_hash` of the fields with the key,
+    /// so the packets hashed the same go to the same target. Packets that
+    /// are not IP all go to the first target.
+    Hash(HashFields, HashKey),
+    /// Spreads the packets in a smooth weighted round-robin, one weight per
+    /// target. Packets of the same flow can be reordered across targets.
+    Weighted(Vec<u32>),
+}
+
+/// Smooth weighted round-robin, same as nginx's upstream balancing. Higher
+/// weight targets are picked more often but not in long runs.
+struct WeightedRoundRobin {
+    weights: Vec<i64>,
+    current: Vec<i64>,
+    total: i64,
+}
+
+impl WeightedRoundRobin {
+    fn new(weights: &[u32]) -> Self {
+        let weights = weights.iter().map(|&w| i64::from(w)).collect::<Vec<_>>();
+        let total = weights.iter().sum();
+        assert!(total > 0, "at least one weight must be greater than 0.");
+
+        WeightedRoundRobin {
+            current: vec![0; weights.len()],
+            weights,
+            total,
+        }
+    }
+
+    fn next(&mut self) -> usize {
+        let mut best = 0;
+        for i in 0..self.weights.len() {
+            self.current[i] += self.weights[i];
+            if self.current[i] > self.current[best] {
+                best = i;
+            }
+        }
+        self.current[best] -= self.total;
+        best
+    }
+}
+
+enum Selector {
+    Hash(HashFields, HashKey),
+    Weighted(WeightedRoundRobin),
+}
+
+/// Spreads the batch across multiple downstream transmits.
+///
+/// Like `Send`, `Distribute` marks the end of the batch pipeline.
+pub struct Distribute<B: Batch, Tx: PacketTx> {
+    batch: B,
+    txs: Vec<Tx>,
+    selector: Selector,
+}
+
+impl<B: Batch, Tx: PacketTx> Distribute<B, Tx> {
+    /// Creates a new `Distribute` across the targets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `txs` is empty, or if the number of weights does not
+    /// match the number of targets, or if all the weights are 0.
+    #[inline]
+    pub fn new(batch: B, distribution: Distribution, txs: Vec<Tx>) -> Self {
+        assert!(!txs.is_empty(), "at least one target is required.");
+
+        let selector = match distribution {
+            Distribution::FlowHash => Selector::Hash(HashFields::FIVE_TUPLE, HashKey::default()),
+            Distribution::Hash(fields, key) => Selector::Hash(fields, key),
+            Distribution::Weighted(weights) => {
+                assert_eq!(
+                    txs.len(),
+                    weights.len(),
+                    "there must be one weight per target."
+                );
+                Selector::Weighted(WeightedRoundRobin::new(&weights))
+            }
+        };
+
+        Distribute {
+            batch,
+            txs,
+            selector,
+        }
+    }
+
+    #[inline]
+    fn select(&mut self, mbuf: &Mbuf) -> usize {
+        match self.selector {
+            Selector::Hash(fields, key) => ip_packet_hash(mbuf, fields, key)
+                .map_or(0, |hash| (hash % self.txs.len() as u64) as usize),
+            Selector::Weighted(ref mut wrr) => wrr.next(),
+        }
+    }
+
+    fn run(&mut self) {
+        let start = tsc();
+
+        self.batch.replenish();
+
+        let mut transmit_qs = self.txs.iter().map(|_| vec![]).collect::<Vec<_>>();
+        let mut drop_q = Vec::with_capacity(64);
+        let mut busy = false;
+
+        while let Some(disp) = self.batch.next() {
+            busy = true;
+            match disp {
+                Disposition::Act(packet) => {
+                    let mbuf = packet.reset();
+                    let index = self.select(&mbuf);
+                    transmit_qs[index].push(mbuf);
+                }
+                Disposition::Drop(mbuf) => drop_q.push(mbuf),
+                // nothing to do for abort and emit.
+                _ => (),
+            }
+        }
+
+        for (tx, transmit_q) in self.txs.iter_mut().zip(transmit_qs) {
+            if !transmit_q.is_empty() {
+                tx.transmit(transmit_q);
+            }
+        }
+
+        if !drop_q.is_empty() {
+            Mbuf::free_bulk(drop_q);
+        }
+
+        record_poll(tsc().wrapping_sub(start), busy);
+    }
+}
+
+impl<B: Batch + Unpin, Tx: PacketTx + Unpin> Future for Distribute<B, Tx> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().run();
+
+        let waker = cx.waker().clone();
+        current_thread::spawn(future::lazy(|_| waker.wake()));
+
+        Poll::Pending
+    }
+}
+
+impl<B: Batch + Unpin, Tx: PacketTx + Unpin> Pipeline for Distribute<B, Tx> {
+    fn run_once(&mut self) {
+        self.run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_weighted_round_robin() {
+        let mut wrr = WeightedRoundRobin::new(&[5, 1, 1]);
+        let picks = (0..7).map(|_| wrr.next()).collect::<Vec<_>>();
+
+        // the heavy target is interleaved with the light ones.
+        assert_eq!(vec![0, 0, 1, 0, 2, 0, 0], picks);
+    }
+
+    #[test]
+    fn zero_weight_is_skipped() {
+        let mut wrr = WeightedRoundRobin::new(&[1, 0]);
+        assert!((0..4).all(|_| wrr.next() == 0));
+    }
+}
@@ -0,0 +1,54 @@
+use super::{Batch, Disposition};
+use crate::net::Martians;
+use crate::packets::ip::IpPacket;
+use crate::stats::{self, DropReason};
+use std::sync::Arc;
+
+/// A batch that drops the packets with a martian source or destination
+/// address.
+///
+/// The dropped packets are recorded in the stats as `DropReason::Martian`.
+pub struct DropMartians<B: Batch>
+where
+    B::Item: IpPacket,
+{
+    batch: B,
+    martians: Arc<Martians>,
+}
+
+impl<B: Batch> DropMartians<B>
+where
+    B::Item: IpPacket,
+{
+    #[inline]
+    pub fn new(batch: B, martians: Arc<Martians>) -> Self {
+        DropMartians { batch, martians }
+    }
+}
+
+impl<B: Batch> Batch for DropMartians<B>
+where
+    B::Item: IpPacket,
+{
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let martians = &self.martians;
+        self.batch.next().map(|disp| {
+            disp.map(|pkt| {
+                if martians.check_packet(&pkt) {
+                    Disposition::Act(pkt)
+                } else {
+                    stats::record_drop(DropReason::Martian);
+                    Disposition::Drop(pkt.reset())
+                }
+            })
+        })
+    }
+}
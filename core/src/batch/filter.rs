@@ -1,16 +1,19 @@
 use super::{Batch, Disposition};
 use crate::packets::Packet;
+use crate::stats::{self, DropReason};
 
 /// A batch that filters the packets of the underlying batch.
 ///
 /// If the predicate evaluates to `false`, the packet is marked as dropped
-/// and will short-circuit the remainder of the pipeline.
+/// and will short-circuit the remainder of the pipeline. If the filter has
+/// a drop reason, the drop is also recorded in the stats.
 pub struct Filter<B: Batch, P>
 where
     P: FnMut(&B::Item) -> bool,
 {
     batch: B,
     predicate: P,
+    reason: Option<DropReason>,
 }
 
 impl<B: Batch, P> Filter<B, P>
@@ -19,7 +22,20 @@ where
 {
     #[inline]
     pub fn new(batch: B, predicate: P) -> Self {
-        Filter { batch, predicate }
+        Filter {
+            batch,
+            predicate,
+            reason: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_reason(batch: B, predicate: P, reason: DropReason) -> Self {
+        Filter {
+            batch,
+            predicate,
+            reason: Some(reason),
+        }
     }
 }
 
@@ -41,6 +57,9 @@ where
                 if (self.predicate)(&pkt) {
                     Disposition::Act(pkt)
                 } else {
+                    if let Some(reason) = self.reason {
+                        stats::record_drop(reason);
+                    }
                     Disposition::Drop(pkt.reset())
                 }
             })
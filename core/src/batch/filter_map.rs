@@ -1,5 +1,6 @@
 use super::{Batch, Disposition};
 use crate::packets::Packet;
+use crate::stats::{self, DropReason};
 use crate::{Mbuf, Result};
 
 /// The result of the filter map.
@@ -9,13 +10,17 @@ pub enum Either<T> {
 
     /// Drops the packet.
     Drop(Mbuf),
+
+    /// Drops the packet and records the reason in the stats.
+    Reject(Mbuf, DropReason),
 }
 
 /// A batch that both filters and maps the packets of the underlying batch.
 ///
-/// If the closure returns `Drop`, the packet is marked as dropped. On
-/// error, the packet is marked as aborted. In both scenarios, it will
-/// short-circuit the remainder of the pipeline.
+/// If the closure returns `Drop` or `Reject`, the packet is marked as
+/// dropped, with the reason of `Reject` recorded in the stats. On error,
+/// the packet is marked as aborted. Either way, it will short-circuit the
+/// remainder of the pipeline.
 pub struct FilterMap<B: Batch, T: Packet, F>
 where
     F: FnMut(B::Item) -> Result<Either<T>>,
@@ -48,10 +53,20 @@ where
     #[inline]
     fn next(&mut self) -> Option<Disposition<Self::Item>> {
         self.batch.next().map(|disp| {
-            disp.map(|orig| match (self.f)(orig) {
-                Ok(Either::Keep(new)) => Disposition::Act(new),
-                Ok(Either::Drop(mbuf)) => Disposition::Drop(mbuf),
-                Err(e) => Disposition::Abort(e),
+            disp.map(|orig| {
+                let port_id = orig.mbuf().port_id();
+                match (self.f)(orig) {
+                    Ok(Either::Keep(new)) => Disposition::Act(new),
+                    Ok(Either::Drop(mbuf)) => Disposition::Drop(mbuf),
+                    Ok(Either::Reject(mbuf, reason)) => {
+                        stats::record_drop(reason);
+                        Disposition::Drop(mbuf)
+                    }
+                    Err(e) => {
+                        stats::record_failure(port_id, &e);
+                        Disposition::Abort(e)
+                    }
+                }
             })
         })
     }
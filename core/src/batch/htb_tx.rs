@@ -0,0 +1,406 @@
+use super::wfq_tx::enqueue_by_color;
+use super::PacketTx;
+use crate::dpdk::RX_BURST_MAX;
+use crate::stats::{self, DropReason};
+use crate::{Mbuf, PacketMeta};
+use futures::{future, StreamExt};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tokio_executor::current_thread;
+use tokio_timer::Interval;
+
+/// The default number of packets a class queue holds.
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// The default burst of the buckets, in time at their rates.
+const DEFAULT_BURST_TIME: Duration = Duration::from_millis(1);
+
+/// A token bucket, in bytes.
+///
+/// A packet is sent as long as there are tokens left, even if it takes
+/// more than there are. The bucket goes into debt, so a slow rate can
+/// still send a full size frame, and pays it back before the next one.
+struct Bucket {
+    // the rate in bytes per second.
+    rate: f64,
+    depth: f64,
+    tokens: f64,
+}
+
+impl Bucket {
+    fn new(bps: u64, burst: Duration) -> Self {
+        let rate = bps as f64 / 8.0;
+        let depth = rate * burst.as_secs_f64();
+        Bucket {
+            rate,
+            depth,
+            tokens: depth,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.tokens = (self.tokens + self.rate * elapsed.as_secs_f64()).min(self.depth);
+    }
+
+    fn has_tokens(&self) -> bool {
+        self.tokens > 0.0
+    }
+}
+
+struct Class {
+    queue: VecDeque<Mbuf>,
+    capacity: usize,
+    // the assured rate.
+    assured: Bucket,
+    // the ceiling rate, assured plus borrowed.
+    ceil: Bucket,
+}
+
+struct Inner<Tx: PacketTx> {
+    tx: Tx,
+    parent: Bucket,
+    classes: Vec<Class>,
+    burst: usize,
+    last: Instant,
+}
+
+impl<Tx: PacketTx> Inner<Tx> {
+    fn enqueue(&mut self, packets: Vec<Mbuf>) {
+        let mut dropped = 0;
+
+        for mbuf in packets {
+            let class = &mut self.classes[mbuf.meta().traffic_class as usize];
+            if !enqueue_by_color(&mut class.queue, class.capacity, mbuf) {
+                dropped += 1;
+            }
+        }
+
+        if dropped > 0 {
+            stats::record_drops(DropReason::QueueFull, dropped);
+        }
+    }
+
+    /// Takes a packet from each class in turn while the class and the
+    /// parent have tokens, with the assured rate of the class or the
+    /// ceiling when borrowing.
+    fn serve(&mut self, packets: &mut Vec<Mbuf>, max: usize, borrow: bool) {
+        loop {
+            let mut progress = false;
+
+            for class in self.classes.iter_mut() {
+                if packets.len() == max || !self.parent.has_tokens() {
+                    return;
+                }
+
+                let eligible = if borrow {
+                    class.ceil.has_tokens()
+                } else {
+                    class.assured.has_tokens() && class.ceil.has_tokens()
+                };
+
+                if eligible {
+                    if let Some(mbuf) = class.queue.pop_front() {
+                        let len = mbuf.data_len() as f64;
+                        // borrowed bytes don't count against the assured
+                        // rate of the class.
+                        if !borrow {
+                            class.assured.tokens -= len;
+                        }
+                        class.ceil.tokens -= len;
+                        self.parent.tokens -= len;
+                        packets.push(mbuf);
+                        progress = true;
+                    }
+                }
+            }
+
+            if !progress {
+                return;
+            }
+        }
+    }
+
+    fn dequeue(&mut self, now: Instant) -> Vec<Mbuf> {
+        // the timer and the pipelines may race to the same instant.
+        let elapsed = if now > self.last {
+            now - self.last
+        } else {
+            Duration::default()
+        };
+        self.last = self.last.max(now);
+
+        self.parent.refill(elapsed);
+        for class in self.classes.iter_mut() {
+            class.assured.refill(elapsed);
+            class.ceil.refill(elapsed);
+        }
+
+        let max = self.burst;
+        let mut packets = Vec::with_capacity(max);
+        self.serve(&mut packets, max, false);
+        self.serve(&mut packets, max, true);
+        packets
+    }
+
+    fn drain(&mut self, now: Instant) {
+        let packets = self.dequeue(now);
+        if !packets.is_empty() {
+            self.tx.transmit(packets);
+        }
+    }
+}
+
+/// A transmit that shapes the packets to a port rate, shared by the
+/// traffic classes by hierarchical token buckets, in front of the
+/// underlying transmit.
+///
+/// The parent bucket limits the total rate. Each traffic class of
+/// `PacketMeta` has its own queue and two buckets, an assured rate it is
+/// always given, and a ceiling up to which it borrows the bandwidth the
+/// other classes leave unused. The classes are first served up to their
+/// assured rates, then the spare bandwidth of the parent is lent to the
+/// classes under their ceilings, a packet per class in turn. A class not
+/// set up has no assured rate and borrows up to the port rate.
+///
+/// It emulates subscriber rate plans, for example a plan of 50 Mbps
+/// bursting to 100 Mbps when the link is idle. The packets wait in the
+/// queues until there are tokens for them, so the queues are drained by a
+/// timer set with `drain_every`. When the queue of its class is full, a
+/// packet that is not red takes the place of the last red packet queued,
+/// and is dropped otherwise. Either drop is recorded as
+/// `DropReason::QueueFull`.
+///
+/// The clones share the same queues, and the shaper cannot leave the core
+/// it was created on.
+///
+/// # Example
+///
+/// ```
+/// runtime.add_pipeline_to_port("eth1", |q| {
+///     // a 100 Mbps plan, with at least 20 Mbps for the voice in class 5
+///     // and at most 80 Mbps for the bulk in class 1.
+///     let htb = HtbTx::new(q.clone(), 100_000_000)
+///         .with_class(5, 20_000_000, 100_000_000)
+///         .with_class(1, 10_000_000, 80_000_000);
+///     htb.drain_every(Duration::from_micros(100));
+///
+///     Poll::new(q.clone()).map(classify).send(htb)
+/// })?;
+/// ```
+pub struct HtbTx<Tx: PacketTx> {
+    inner: Rc<RefCell<Inner<Tx>>>,
+    // the burst of the buckets, kept for the classes set up later.
+    burst_time: Duration,
+}
+
+impl<Tx: PacketTx> HtbTx<Tx> {
+    /// Creates a new shaper of `rate` bits per second in front of `tx`.
+    ///
+    /// The buckets hold `1ms` of tokens at their rates, the class queues
+    /// hold `1024` packets, and up to `32` packets are passed on at a time.
+    pub fn new(tx: Tx, rate: u64) -> Self {
+        let burst_time = DEFAULT_BURST_TIME;
+        let classes = (0..PacketMeta::CLASSES)
+            .map(|_| Class {
+                queue: VecDeque::new(),
+                capacity: DEFAULT_CAPACITY,
+                assured: Bucket::new(0, burst_time),
+                ceil: Bucket::new(rate, burst_time),
+            })
+            .collect();
+
+        HtbTx {
+            inner: Rc::new(RefCell::new(Inner {
+                tx,
+                parent: Bucket::new(rate, burst_time),
+                classes,
+                burst: RX_BURST_MAX,
+                last: Instant::now(),
+            })),
+            burst_time,
+        }
+    }
+
+    /// Sets the assured and the ceiling rates of the class, in bits per
+    /// second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `traffic_class` is not a valid class, or the rates are not
+    /// in the order of `rate <= ceil <= port rate`.
+    pub fn with_class(self, traffic_class: u8, rate: u64, ceil: u64) -> Self {
+        assert!((traffic_class as usize) < PacketMeta::CLASSES);
+        {
+            let mut inner = self.inner.borrow_mut();
+            assert!(rate <= ceil && ceil as f64 / 8.0 <= inner.parent.rate);
+
+            let class = &mut inner.classes[traffic_class as usize];
+            class.assured = Bucket::new(rate, self.burst_time);
+            class.ceil = Bucket::new(ceil, self.burst_time);
+        }
+        self
+    }
+
+    /// Sets the number of packets the queue of the class holds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `traffic_class` is not a valid class.
+    pub fn with_capacity(self, traffic_class: u8, capacity: usize) -> Self {
+        assert!((traffic_class as usize) < PacketMeta::CLASSES);
+        self.inner.borrow_mut().classes[traffic_class as usize].capacity = capacity;
+        self
+    }
+
+    /// Sets the burst of all the buckets, in time at their rates. A longer
+    /// burst absorbs more jitter of the drain timer, at the cost of
+    /// sending more at once after an idle period.
+    ///
+    /// Applies to the parent bucket and to the buckets of all the classes,
+    /// whether they are set before or after.
+    pub fn with_burst_time(mut self, burst_time: Duration) -> Self {
+        self.burst_time = burst_time;
+        {
+            let mut inner = self.inner.borrow_mut();
+            let bps = (inner.parent.rate * 8.0) as u64;
+            inner.parent = Bucket::new(bps, burst_time);
+            for class in inner.classes.iter_mut() {
+                class.assured = Bucket::new((class.assured.rate * 8.0) as u64, burst_time);
+                class.ceil = Bucket::new((class.ceil.rate * 8.0) as u64, burst_time);
+            }
+        }
+        self
+    }
+
+    /// Sets the maximum number of packets passed on at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `burst` is `0`.
+    pub fn with_burst(self, burst: usize) -> Self {
+        assert!(burst > 0);
+        self.inner.borrow_mut().burst = burst;
+        self
+    }
+
+    /// Returns the number of packets queued in the class.
+    pub fn queue_len(&self, traffic_class: u8) -> usize {
+        self.inner.borrow().classes[traffic_class as usize]
+            .queue
+            .len()
+    }
+
+    /// Queues the packets by their class, and passes on the ones there are
+    /// tokens for.
+    pub(crate) fn transmit(&self, packets: Vec<Mbuf>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.enqueue(packets);
+        inner.drain(Instant::now());
+    }
+
+    /// Spawns a timer on the current core that passes on the queued
+    /// packets there are tokens for every `interval`.
+    ///
+    /// The interval should be well under the burst time of the buckets,
+    /// or the shaper falls short of the rates. Must be called on the core
+    /// the pipeline runs on, for example in the pipeline installer.
+    pub fn drain_every(&self, interval: Duration)
+    where
+        Tx: 'static,
+    {
+        let inner = Rc::downgrade(&self.inner);
+        let alive = inner.clone();
+
+        // stops once the last clone of the shaper is dropped.
+        let fut = Interval::new_interval(interval)
+            .take_while(move |_| future::ready(alive.upgrade().is_some()))
+            .for_each(move |_| {
+                if let Some(inner) = inner.upgrade() {
+                    inner.borrow_mut().drain(Instant::now());
+                }
+                future::ready(())
+            });
+        current_thread::spawn(fut);
+    }
+}
+
+impl<Tx: PacketTx> Clone for HtbTx<Tx> {
+    fn clone(&self) -> Self {
+        HtbTx {
+            inner: self.inner.clone(),
+            burst_time: self.burst_time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn packets(traffic_class: u8, count: usize) -> Vec<Mbuf> {
+        (0..count)
+            .map(|_| {
+                let mut mbuf = Mbuf::new().unwrap();
+                mbuf.extend(0, 500).unwrap();
+                mbuf.set_meta(PacketMeta::default().with_traffic_class(traffic_class));
+                mbuf
+            })
+            .collect()
+    }
+
+    fn count_by_class(rx: &mpsc::Receiver<Mbuf>) -> (usize, usize) {
+        rx.try_iter().fold((0, 0), |(c0, c1), mbuf| {
+            if mbuf.meta().traffic_class == 0 {
+                (c0 + 1, c1)
+            } else {
+                (c0, c1 + 1)
+            }
+        })
+    }
+
+    #[nb2::test]
+    fn assured_then_borrowed() {
+        let (tx, rx) = mpsc::channel();
+
+        // 10ms of tokens, 10000 bytes for the port, 1000 bytes assured to
+        // both classes, class 1 borrows up to 2000 bytes.
+        let htb = HtbTx::new(tx, 8_000_000)
+            .with_burst_time(Duration::from_millis(10))
+            .with_class(0, 800_000, 8_000_000)
+            .with_class(1, 800_000, 1_600_000);
+
+        let start = htb.inner.borrow().last;
+        {
+            let mut inner = htb.inner.borrow_mut();
+            inner.enqueue(packets(0, 40));
+            inner.enqueue(packets(1, 40));
+            inner.drain(start);
+        }
+
+        // 2 assured packets each, class 1 borrows up to its ceiling, and
+        // class 0 the rest of the port rate.
+        assert_eq!((16, 4), count_by_class(&rx));
+
+        // no tokens left until time passes.
+        htb.inner.borrow_mut().drain(start);
+        assert_eq!((0, 0), count_by_class(&rx));
+
+        // 1000 bytes for the port in 1ms go to the assured rates first.
+        htb.inner
+            .borrow_mut()
+            .drain(start + Duration::from_millis(1));
+        assert_eq!((1, 1), count_by_class(&rx));
+    }
+
+    #[nb2::test]
+    fn drop_when_class_full() {
+        let (tx, _rx) = mpsc::channel();
+        let htb = HtbTx::new(tx, 0).with_capacity(2, 3);
+
+        htb.transmit(packets(2, 5));
+        assert_eq!(3, htb.queue_len(2));
+    }
+}
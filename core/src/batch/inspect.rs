@@ -0,0 +1,111 @@
+use super::{Batch, Disposition, PacketTx};
+use crate::net::Ruleset;
+use crate::packets::{data_slice, Packet};
+use crate::stats::{self, DropReason};
+
+/// What to do with the packets whose payload matches a content rule.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatchAction {
+    /// Sets the ID of the lowest matching rule as the mark of the packet
+    /// metadata, and lets the packet through.
+    Tag,
+    /// Drops the packet, recorded in the stats as `DropReason::Content`.
+    Drop,
+}
+
+#[inline]
+fn first_match<P: Packet>(ruleset: &mut Ruleset, pkt: &P) -> Option<u32> {
+    let payload = data_slice(pkt.mbuf(), pkt.payload_offset(), pkt.payload_len());
+    ruleset.first_match(payload)
+}
+
+/// A batch that matches the payload of the packets against a ruleset,
+/// and tags or drops the packets that match.
+pub struct Inspect<B: Batch> {
+    batch: B,
+    ruleset: Ruleset,
+    action: MatchAction,
+}
+
+impl<B: Batch> Inspect<B> {
+    #[inline]
+    pub fn new(batch: B, ruleset: Ruleset, action: MatchAction) -> Self {
+        Inspect {
+            batch,
+            ruleset,
+            action,
+        }
+    }
+}
+
+impl<B: Batch> Batch for Inspect<B> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let ruleset = &mut self.ruleset;
+        let action = self.action;
+        self.batch.next().map(|disp| {
+            disp.map(|mut pkt| match (first_match(ruleset, &pkt), action) {
+                (None, _) => Disposition::Act(pkt),
+                (Some(id), MatchAction::Tag) => {
+                    let meta = pkt.mbuf().meta().with_mark(id);
+                    pkt.mbuf_mut().set_meta(meta);
+                    Disposition::Act(pkt)
+                }
+                (Some(_), MatchAction::Drop) => {
+                    stats::record_drop(DropReason::Content);
+                    Disposition::Drop(pkt.reset())
+                }
+            })
+        })
+    }
+}
+
+/// A batch that diverts the packets whose payload matches a ruleset to
+/// another `PacketTx`, tagged with the ID of the lowest matching rule.
+///
+/// Like `emit`, the send is immediate and not in batch.
+pub struct Divert<B: Batch, Tx: PacketTx> {
+    batch: B,
+    ruleset: Ruleset,
+    tx: Tx,
+}
+
+impl<B: Batch, Tx: PacketTx> Divert<B, Tx> {
+    #[inline]
+    pub fn new(batch: B, ruleset: Ruleset, tx: Tx) -> Self {
+        Divert { batch, ruleset, tx }
+    }
+}
+
+impl<B: Batch, Tx: PacketTx> Batch for Divert<B, Tx> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let ruleset = &mut self.ruleset;
+        let tx = &mut self.tx;
+        self.batch.next().map(|disp| {
+            disp.map(|mut pkt| match first_match(ruleset, &pkt) {
+                None => Disposition::Act(pkt),
+                Some(id) => {
+                    let meta = pkt.mbuf().meta().with_mark(id);
+                    pkt.mbuf_mut().set_meta(meta);
+                    tx.transmit(vec![pkt.reset()]);
+                    Disposition::Emit
+                }
+            })
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use super::{Batch, Disposition};
 use crate::packets::Packet;
+use crate::stats;
 use crate::Result;
 
 /// A batch that maps the packets of the underlying batch.
@@ -38,9 +39,15 @@ where
     #[inline]
     fn next(&mut self) -> Option<Disposition<Self::Item>> {
         self.batch.next().map(|disp| {
-            disp.map(|orig| match (self.f)(orig) {
-                Ok(new) => Disposition::Act(new),
-                Err(e) => Disposition::Abort(e),
+            disp.map(|orig| {
+                let port_id = orig.mbuf().port_id();
+                match (self.f)(orig) {
+                    Ok(new) => Disposition::Act(new),
+                    Err(e) => {
+                        stats::record_failure(port_id, &e);
+                        Disposition::Abort(e)
+                    }
+                }
             })
         })
     }
@@ -1,30 +1,87 @@
+mod bpf_hook;
+mod characterize;
+mod checked_tx;
+mod context;
+mod dedup;
+mod distribute;
+mod drop_martians;
 mod emit;
 mod filter;
 mod filter_map;
 mod for_each;
 mod group_by;
+mod htb_tx;
+mod inspect;
 mod map;
+mod netem_tx;
+mod normalize;
+mod pcap;
+mod pcapng;
+mod police;
 mod poll;
+mod profile;
 mod replace;
+mod respond;
+mod rewrite_ethernet;
 mod rxtx;
+mod schedule;
 mod send;
+mod send_to;
+mod sequence;
+mod split_ip;
+mod tag_prefix;
+mod tx_buffer;
+#[cfg(feature = "wasm")]
+mod wasm_plugin;
+mod wfq_tx;
 
+pub use self::bpf_hook::*;
+pub use self::characterize::*;
+pub use self::checked_tx::*;
+pub use self::context::*;
+pub use self::dedup::*;
+pub use self::distribute::*;
+pub use self::drop_martians::*;
 pub use self::emit::*;
 pub use self::filter::*;
 pub use self::filter_map::*;
 pub use self::for_each::*;
 pub use self::group_by::*;
+pub use self::htb_tx::*;
+pub use self::inspect::*;
 pub use self::map::*;
+pub use self::netem_tx::*;
+pub use self::normalize::*;
+pub use self::pcap::*;
+pub use self::pcapng::*;
+pub use self::police::*;
 pub use self::poll::*;
+pub use self::profile::*;
 pub use self::replace::*;
+pub use self::respond::*;
+pub use self::rewrite_ethernet::*;
 pub use self::rxtx::*;
+pub use self::schedule::*;
 pub use self::send::*;
+pub use self::send_to::*;
+pub use self::sequence::*;
+pub use self::split_ip::*;
+pub use self::tag_prefix::*;
+pub use self::tx_buffer::*;
+#[cfg(feature = "wasm")]
+pub use self::wasm_plugin::*;
+pub use self::wfq_tx::*;
 
+use crate::net::{MacAddr, Martians, PrefixTags, Ruleset};
+use crate::packets::ip::IpPacket;
 use crate::packets::Packet;
-use crate::{Mbuf, Result};
+use crate::stats::DropReason;
+use crate::{Mbuf, Result, Shared};
 use failure::Error;
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Way to categorize the packets of a batch inside a processing pipeline.
 /// The disposition instructs the combinators how to process a packet.
@@ -119,6 +176,100 @@ pub trait Batch {
     /// the next cycle, call `replenish` first.
     fn next(&mut self) -> Option<Disposition<Self::Item>>;
 
+    /// Turns the batch pipeline into an executable task that spreads the
+    /// packets across multiple transmits, usually the `RingTx` halves of
+    /// rings polled by worker cores.
+    ///
+    /// Use when a single receive queue needs to feed more cores than the
+    /// NIC has queues for. Like send, no more combinators can be appended
+    /// after distribute.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .distribute(Distribution::Weighted(vec![2, 1, 1]), vec![tx1, tx2, tx3]);
+    /// ```
+    #[inline]
+    fn distribute<Tx: PacketTx>(
+        self,
+        distribution: Distribution,
+        txs: Vec<Tx>,
+    ) -> Distribute<Self, Tx>
+    where
+        Self: Sized,
+    {
+        Distribute::new(self, distribution, txs)
+    }
+
+    /// Creates a batch that diverts the packets whose payload matches a
+    /// content rule to another `PacketTx`.
+    ///
+    /// The diverted packets are tagged with the ID of the lowest matching
+    /// rule, as the mark of the packet metadata, so the receiving pipeline
+    /// knows why. Use to hand the suspicious traffic over to an IDS or a
+    /// capture queue. The send is immediate and is not in batch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let ruleset = Ruleset::new(&[Rule::regex(1, r"^(GET|POST) /admin")])?;
+    ///
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>()?.parse::<Tcp4>())
+    ///     .divert(ruleset, ids_q.clone());
+    /// ```
+    #[inline]
+    fn divert<Tx: PacketTx>(self, ruleset: Ruleset, tx: Tx) -> Divert<Self, Tx>
+    where
+        Self: Sized,
+    {
+        Divert::new(self, ruleset, tx)
+    }
+
+    /// Creates a batch that drops the packets with a bogon or martian
+    /// source or destination address.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let martians = Arc::new(Martians::default());
+    ///
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+    ///     .drop_martians(martians.clone());
+    /// ```
+    #[inline]
+    fn drop_martians(self, martians: Arc<Martians>) -> DropMartians<Self>
+    where
+        Self::Item: IpPacket,
+        Self: Sized,
+    {
+        DropMartians::new(self, martians)
+    }
+
+    /// Creates a batch that drops the packets already seen within the
+    /// `window`, for the analytics pipelines fed by taps or mirror ports
+    /// that deliver the same packet twice.
+    ///
+    /// `capacity` is the number of packets expected per window, the
+    /// filters of seen packets are sized for it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .dedup(Duration::from_millis(50), 1_000_000, DedupFields::Ip)
+    ///     .map(|packet| packet.parse::<Ethernet>());
+    /// ```
+    #[inline]
+    fn dedup(self, window: Duration, capacity: usize, fields: DedupFields) -> Dedup<Self>
+    where
+        Self: Sized,
+    {
+        Dedup::new(self, window, capacity, fields)
+    }
+
     /// Creates a batch that transmits all packets through the specified
     /// `PacketTx`.
     ///
@@ -144,6 +295,67 @@ pub trait Batch {
         Filter::new(self, predicate)
     }
 
+    /// Creates a batch that uses a predicate to determine if a packet
+    /// should be processed or dropped, and records the dropped packets
+    /// in the stats under `reason`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = batch.filter_with(DropReason::TtlExceeded, |v4| v4.ttl() > 1);
+    /// ```
+    #[inline]
+    fn filter_with<P>(self, reason: DropReason, predicate: P) -> Filter<Self, P>
+    where
+        P: FnMut(&Self::Item) -> bool,
+        Self: Sized,
+    {
+        Filter::with_reason(self, predicate, reason)
+    }
+
+    /// Creates a batch that filters the packets with the classic BPF
+    /// program attached to the hook point `name`, if any.
+    ///
+    /// The program is attached at run time with `bpf::attach`, and can be
+    /// replaced or detached while the pipeline runs. The packets rejected
+    /// are recorded as `DropReason::Filtered`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = batch.bpf_hook("ingress").map(|p| p.parse::<Ethernet>());
+    /// ```
+    #[inline]
+    fn bpf_hook(self, name: &str) -> BpfHook<Self>
+    where
+        Self: Sized,
+    {
+        BpfHook::new(self, name)
+    }
+
+    /// Creates a batch that runs the frames through the WebAssembly plugin
+    /// loaded under `name`, if any.
+    ///
+    /// The plugin is loaded at run time with `wasm::load`, and can be
+    /// replaced or unloaded while the pipeline runs. The plugin sees the
+    /// whole frame, so it runs on the packets before they are parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .wasm_plugin("scrub")
+    ///     .map(|p| p.parse::<Ethernet>());
+    /// ```
+    #[cfg(feature = "wasm")]
+    #[inline]
+    fn wasm_plugin(self, name: &str) -> WasmPlugin<Self>
+    where
+        Self: Batch<Item = Mbuf> + Sized,
+    {
+        WasmPlugin::new(self, name)
+    }
+
     /// Creates a batch that both filters and maps.
     #[inline]
     fn filter_map<T: Packet, F>(self, f: F) -> FilterMap<Self, T, F>
@@ -225,6 +437,173 @@ pub trait Batch {
         GroupBy::new(self, selector, composer)
     }
 
+    /// Splits the packets into IPv4, IPv6, ARP and other sub batches, by
+    /// their ether type. Each sub batch runs through its own pipeline,
+    /// and are then merged back together, like with `group_by`.
+    ///
+    /// The VLAN tags are skipped, so tagged packets go down the branch of
+    /// the ether type they carry. The packets are not parsed, so each
+    /// branch starts from the packet type of the batch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone()).split_ip(
+    ///     |v4| v4.map(nat4),
+    ///     |v6| v6.map(nat6),
+    ///     |arp| arp.for_each(learn_neighbor),
+    ///     |other| other.filter(|_| false),
+    /// );
+    /// ```
+    #[inline]
+    fn split_ip<F4, F6, FA, FO, B4, B6, BA, BO>(
+        self,
+        ipv4: F4,
+        ipv6: F6,
+        arp: FA,
+        other: FO,
+    ) -> SplitIp<Self>
+    where
+        Self::Item: Packet,
+        F4: Fn(Bridge<Self::Item>) -> B4 + 'static,
+        F6: Fn(Bridge<Self::Item>) -> B6 + 'static,
+        FA: Fn(Bridge<Self::Item>) -> BA + 'static,
+        FO: Fn(Bridge<Self::Item>) -> BO + 'static,
+        B4: Batch<Item = Self::Item> + 'static,
+        B6: Batch<Item = Self::Item> + 'static,
+        BA: Batch<Item = Self::Item> + 'static,
+        BO: Batch<Item = Self::Item> + 'static,
+        Self: Sized,
+    {
+        split_ip::split_ip(self, ipv4, ipv6, arp, other)
+    }
+
+    /// Creates a batch that matches the payload of the packets against a
+    /// set of content rules.
+    ///
+    /// The packets that match are either tagged with the ID of the lowest
+    /// matching rule, as the mark of the packet metadata, or dropped. The
+    /// payload is what follows the header of the packet type of the batch,
+    /// so parse the packets up to the layer to inspect first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let ruleset = Ruleset::new(&[Rule::literal(1, "/etc/passwd")])?;
+    ///
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>()?.parse::<Tcp4>())
+    ///     .inspect(ruleset, MatchAction::Drop);
+    /// ```
+    #[inline]
+    fn inspect(self, ruleset: Ruleset, action: MatchAction) -> Inspect<Self>
+    where
+        Self: Sized,
+    {
+        Inspect::new(self, ruleset, action)
+    }
+
+    /// Creates a batch that scrubs the packets before they are processed.
+    ///
+    /// Drops the malformed packets and rewrites the others according to the
+    /// policy, so the stateful combinators that follow only see packets
+    /// with consistent headers. Use as the first stage of the pipeline,
+    /// before the packets are parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .normalize(NormalizePolicy {
+    ///         min_ttl: Some(16),
+    ///         ..NormalizePolicy::default()
+    ///     })
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>());
+    /// ```
+    #[inline]
+    fn normalize(self, policy: NormalizePolicy) -> Normalize<Self>
+    where
+        Self: Batch<Item = Mbuf> + Sized,
+    {
+        Normalize::new(self, policy)
+    }
+
+    /// Creates a profiling point that samples the cycles spent in the
+    /// combinators before it.
+    ///
+    /// The cycles are only sampled when profiling is turned on with
+    /// `stats::set_profiling`. Each point records a histogram under `name`
+    /// in `stats::operator_stats`, covering the combinators between it and
+    /// the previous profiling point.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+    ///     .profile("parse")
+    ///     .filter(|v4| v4.ttl() > 1)
+    ///     .map(rewrite)
+    ///     .profile("rewrite");
+    /// ```
+    #[inline]
+    fn profile(self, name: &'static str) -> Profile<Self>
+    where
+        Self: Sized,
+    {
+        Profile::new(self, name)
+    }
+
+    /// Creates a batch that counts the packets going through, with a
+    /// histogram of their sizes and their rates over `window`, by traffic
+    /// class, under `name` in the stats.
+    ///
+    /// Name the point after the port or the kind of traffic, so the
+    /// traffic can be characterized without custom counters.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .characterize("eth1", Duration::from_secs(1))
+    ///     .map(|packet| packet.parse::<Ethernet>());
+    ///
+    /// // later, on another core.
+    /// for (class, stats) in stats::traffic_stats()["eth1"].iter().enumerate() {
+    ///     println!("{}: {} bps, {} bytes mean", class, stats.bit_rate, stats.mean_size());
+    /// }
+    /// ```
+    #[inline]
+    fn characterize(self, name: &str, window: Duration) -> Characterize<Self>
+    where
+        Self: Sized,
+    {
+        Characterize::new(self, name, window)
+    }
+
+    /// Creates a batch that colors the packets by a committed rate of `cir`
+    /// bits per second, with a committed burst of `cbs` bytes and an excess
+    /// burst of `ebs` bytes.
+    ///
+    /// The color is set in the packet metadata. `WfqTx` and `HtbTx` drop
+    /// the red packets first when the queue of a class is full.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .map(classify)
+    ///     .police(10_000_000, 15_000, 30_000)
+    ///     .send(wfq);
+    /// ```
+    #[inline]
+    fn police(self, cir: u64, cbs: usize, ebs: usize) -> Police<Self>
+    where
+        Self: Sized,
+    {
+        Police::new(self, cir, cbs, ebs)
+    }
+
     /// A batch that replaces each packet with another packet.
     ///
     /// Use for pipelines that generate new outbound packets based on the
@@ -237,6 +616,94 @@ pub trait Batch {
         Replace::new(self, f)
     }
 
+    /// Creates a batch that answers the packets matched by the responder
+    /// and transmits the replies through the specified `PacketTx`.
+    ///
+    /// The answered packets are removed from the batch. Like `emit`, the
+    /// replies are sent immediately and not in batch.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .respond(EchoResponder::new(addrs), q.clone())
+    ///     .map(forward);
+    /// ```
+    #[inline]
+    fn respond<R: Responder, Tx: PacketTx>(self, responder: R, tx: Tx) -> Respond<Self, R, Tx>
+    where
+        Self: Sized,
+    {
+        Respond::new(self, responder, tx)
+    }
+
+    /// Creates a batch that rewrites the MAC addresses of the packets for
+    /// their next hop.
+    ///
+    /// The source MAC address is set to `smac`, usually the address of the
+    /// egress port, and the destination to the address `lookup` returns,
+    /// usually from the neighbor cache. The packets the lookup returns
+    /// `None` for are dropped, recorded in the stats as
+    /// `DropReason::Unresolved`. The upper layers are not parsed, so use
+    /// right before `send` once the route is decided.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+    ///     .map(route)
+    ///     .rewrite_ethernet(q.mac_addr(), |v4| neighbors.get(&v4.dst()).copied());
+    /// ```
+    #[inline]
+    fn rewrite_ethernet<F>(self, smac: MacAddr, lookup: F) -> RewriteEthernet<Self, F>
+    where
+        Self::Item: Packet,
+        F: FnMut(&Self::Item) -> Option<MacAddr>,
+        Self: Sized,
+    {
+        RewriteEthernet::new(self, smac, lookup)
+    }
+
+    /// Stamps the packets with consecutive sequence numbers.
+    ///
+    /// Use before `distribute`, so the order of the packets can be restored
+    /// with `ReorderTx` after they went through parallel workers.
+    #[inline]
+    fn sequence(self) -> Sequence<Self>
+    where
+        Self: Sized,
+    {
+        Sequence::new(self)
+    }
+
+    /// Creates a batch that tags the packets with the label of the longest
+    /// prefix matching their source or destination address.
+    ///
+    /// The tag is set as the mark of the packet metadata. The prefix set is
+    /// shared with the control plane, which can reload it at any time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let countries = Shared::new(PrefixTags::load_csv("countries.csv")?);
+    ///
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+    ///     .tag_prefix(&countries, TagBy::Src);
+    ///
+    /// // on SIGHUP.
+    /// countries.update(|tags| tags.reload_csv("countries.csv"))?;
+    /// ```
+    #[inline]
+    fn tag_prefix(self, tags: &Shared<PrefixTags>, by: TagBy) -> TagPrefix<Self>
+    where
+        Self::Item: IpPacket,
+        Self: Sized,
+    {
+        TagPrefix::new(self, tags.reader(), by)
+    }
+
     /// Turns the batch pipeline into an executable task.
     ///
     /// Send marks the end of the batch pipeline. No more combinators can be
@@ -248,6 +715,31 @@ pub trait Batch {
     {
         Send::new(self, tx)
     }
+
+    /// Turns the batch pipeline into an executable task that transmits each
+    /// packet through one of multiple egresses.
+    ///
+    /// `select` returns the index of the egress in `txs` for the packet,
+    /// usually from the route of its destination. The packets it returns
+    /// `None` or an index out of range for are dropped, recorded in the
+    /// stats as `DropReason::NoRoute`. Like send, no more combinators can be
+    /// appended after send_to.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+    ///     .send_to(vec![eth1, eth2], |v4| routes.lookup(v4.dst()));
+    /// ```
+    #[inline]
+    fn send_to<Tx: PacketTx, F>(self, txs: Vec<Tx>, select: F) -> SendTo<Self, Tx, F>
+    where
+        F: FnMut(&Self::Item) -> Option<usize>,
+        Self: Sized,
+    {
+        SendTo::new(self, txs, select)
+    }
 }
 
 /// Trait bound for batch pipelines. Can be used as a convenience for writing
@@ -276,10 +768,17 @@ pub fn splice<Rx: PacketRx + Unpin, Tx: PacketTx + Unpin>(rx: Rx, tx: Tx) -> imp
 mod tests {
     use super::*;
     use crate::compose;
+    use crate::dpdk::Color;
+    use crate::net::Rule;
+    use crate::packets::icmp::EchoResponder;
     use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::Ipv6;
     use crate::packets::ip::ProtocolNumbers;
-    use crate::packets::Ethernet;
-    use crate::testils::byte_arrays::{ICMPV4_PACKET, TCP_PACKET, UDP_PACKET};
+    use crate::packets::{Ethernet, Udp};
+    use crate::stats::{self, Anomaly};
+    use crate::testils::byte_arrays::{ICMPV4_PACKET, IPV6_PACKET, TCP_PACKET, UDP_PACKET};
+    use crate::PortId;
+    use std::iter;
     use std::sync::mpsc::{self, TryRecvError};
 
     fn new_batch(data: &[&[u8]]) -> impl Batch<Item = Mbuf> {
@@ -319,6 +818,169 @@ mod tests {
         assert!(batch.next().unwrap().is_drop());
     }
 
+    #[nb2::test]
+    fn filter_with_reason_batch() {
+        let reason = DropReason::User(0xbeef_0001);
+
+        let mut batch = new_batch(&[&UDP_PACKET]).filter_with(reason, |_| false);
+        assert!(batch.next().unwrap().is_drop());
+        assert_eq!(1, stats::drop_stats().get(reason));
+
+        let mut batch =
+            new_batch(&[&UDP_PACKET]).filter_map(|p| Ok(Either::<Mbuf>::Reject(p, reason)));
+        assert!(batch.next().unwrap().is_drop());
+        assert_eq!(2, stats::drop_stats().get(reason));
+    }
+
+    #[nb2::test]
+    fn bpf_hook_batch() {
+        // tcpdump -ddd ip and tcp
+        let tcp = "6\n40 0 0 12\n21 0 3 2048\n48 0 0 23\n21 0 1 6\n6 0 0 262144\n6 0 0 0";
+        let mut batch = new_batch(&[&UDP_PACKET, &TCP_PACKET, &UDP_PACKET]).bpf_hook("batch-test");
+
+        // nothing attached yet.
+        assert!(batch.next().unwrap().is_act());
+
+        crate::bpf::attach("batch-test", crate::bpf::Program::parse(tcp).unwrap());
+        assert!(batch.next().unwrap().is_act());
+        assert!(batch.next().unwrap().is_drop());
+        crate::bpf::detach("batch-test");
+    }
+
+    #[cfg(feature = "wasm")]
+    #[nb2::test]
+    fn wasm_plugin_batch() {
+        let mut batch =
+            new_batch(&[&UDP_PACKET, &TCP_PACKET, &UDP_PACKET]).wasm_plugin("batch-test");
+
+        // nothing loaded yet.
+        assert!(batch.next().unwrap().is_act());
+
+        crate::wasm::load("batch-test", crate::wasm::tests::DROP_UDP.to_vec()).unwrap();
+        assert!(batch.next().unwrap().is_act());
+        assert!(batch.next().unwrap().is_drop());
+        crate::wasm::unload("batch-test");
+    }
+
+    #[nb2::test]
+    fn drop_martians_batch() {
+        let mut martians = Martians::default();
+
+        let mut batch = new_batch(&[&UDP_PACKET])
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>())
+            .drop_martians(Arc::new(Martians::default()));
+        assert!(batch.next().unwrap().is_act());
+
+        martians.insert_v4("139.133.0.0/16".parse().unwrap());
+        let mut batch = new_batch(&[&UDP_PACKET])
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>())
+            .drop_martians(Arc::new(martians));
+        assert!(batch.next().unwrap().is_drop());
+        assert_eq!(1, stats::drop_stats().get(DropReason::Martian));
+    }
+
+    #[nb2::test]
+    fn tag_prefix_batch() {
+        let mut tags = PrefixTags::new();
+        tags.insert_v4("139.133.217.0/24".parse().unwrap(), "src");
+        let tags = Shared::new(tags);
+
+        let mut batch = new_batch(&[&UDP_PACKET])
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>())
+            .tag_prefix(&tags, TagBy::Src);
+        match batch.next().unwrap() {
+            Disposition::Act(v4) => assert_eq!(1, v4.mbuf().meta().mark),
+            _ => unreachable!(),
+        }
+    }
+
+    #[nb2::test]
+    fn inspect_batch() {
+        let rules = [Rule::literal(7, "hello"), Rule::regex(9, "^hel+o")];
+
+        let mut batch = new_batch(&[&UDP_PACKET])
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>()?.parse::<Udp<Ipv4>>())
+            .inspect(Ruleset::new(&rules).unwrap(), MatchAction::Tag);
+        match batch.next().unwrap() {
+            Disposition::Act(udp) => assert_eq!(7, udp.mbuf().meta().mark),
+            _ => unreachable!(),
+        }
+
+        let mut batch = new_batch(&[&UDP_PACKET])
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>()?.parse::<Udp<Ipv4>>())
+            .inspect(Ruleset::new(&rules).unwrap(), MatchAction::Drop);
+        assert!(batch.next().unwrap().is_drop());
+        assert_eq!(1, stats::drop_stats().get(DropReason::Content));
+
+        // the headers are not part of the payload.
+        let mut batch = new_batch(&[&UDP_PACKET])
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>()?.parse::<Udp<Ipv4>>())
+            .inspect(
+                Ruleset::new(&[Rule::literal(1, [0x99, 0xd0])]).unwrap(),
+                MatchAction::Drop,
+            );
+        assert!(batch.next().unwrap().is_act());
+    }
+
+    #[nb2::test]
+    fn divert_batch() {
+        let (tx, mut rx) = mpsc::channel();
+
+        let mut batch = new_batch(&[&UDP_PACKET, &TCP_PACKET])
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>())
+            .divert(Ruleset::new(&[Rule::literal(3, "hello")]).unwrap(), tx);
+
+        assert!(batch.next().unwrap().is_emit());
+        assert!(batch.next().unwrap().is_act());
+
+        let diverted = rx.receive();
+        assert_eq!(1, diverted.len());
+        assert_eq!(3, diverted[0].meta().mark);
+    }
+
+    #[nb2::test]
+    fn profile_batch() {
+        stats::set_profiling(true);
+
+        let mut batch = new_batch(&[&UDP_PACKET, &ICMPV4_PACKET])
+            .map(|p| p.parse::<Ethernet>())
+            .profile("batch_test_parse");
+        let first = batch.next().unwrap().is_act();
+        let second = batch.next().unwrap().is_act();
+        // turned off before asserting, so it doesn't leak into other tests.
+        stats::set_profiling(false);
+
+        assert!(first && second);
+        assert_eq!(2, stats::operator_stats()["batch_test_parse"].count());
+    }
+
+    #[nb2::test]
+    fn characterize_batch() {
+        let mut batch = new_batch(&[&UDP_PACKET, &ICMPV4_PACKET])
+            .characterize("batch_test_traffic", Duration::from_secs(1));
+        assert!(batch.next().unwrap().is_act());
+        assert!(batch.next().unwrap().is_act());
+
+        let stats = &stats::traffic_stats()["batch_test_traffic"][0];
+        assert_eq!(2, stats.packets);
+        assert_eq!((UDP_PACKET.len() + ICMPV4_PACKET.len()) as u64, stats.bytes);
+    }
+
+    #[nb2::test]
+    fn police_batch() {
+        let mut batch = new_batch(&[&UDP_PACKET, &UDP_PACKET, &UDP_PACKET]).police(
+            8,
+            UDP_PACKET.len(),
+            UDP_PACKET.len(),
+        );
+
+        let mut colors = vec![];
+        while let Some(Disposition::Act(pkt)) = batch.next() {
+            colors.push(pkt.meta().color);
+        }
+        assert_eq!(vec![Color::Green, Color::Yellow, Color::Red], colors);
+    }
+
     #[nb2::test]
     fn filter_map_batch() {
         let mut batch = new_batch(&[&UDP_PACKET, &ICMPV4_PACKET]).filter_map(|p| {
@@ -351,6 +1013,60 @@ mod tests {
         assert!(batch.next().unwrap().is_abort());
     }
 
+    #[nb2::test]
+    fn map_records_anomalies() {
+        // tests run in parallel, so use a port no other test records.
+        let port_id = PortId::new(0x7ff2);
+        let mut packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        packet.set_port_id(port_id);
+
+        let (mut tx, rx) = mpsc::channel();
+        tx.transmit(vec![packet]);
+        let mut batch = Poll::new(rx).map(|p| p.parse::<Ethernet>()?.parse::<Ipv6>());
+        batch.replenish();
+
+        assert!(batch.next().unwrap().is_abort());
+        let stats = stats::anomaly_stats();
+        assert_eq!(1, stats.get(port_id, Anomaly::BadVersion));
+        assert_eq!(1, stats.port_total(port_id));
+    }
+
+    #[nb2::test]
+    fn split_ip_batch() {
+        let mut arp = UDP_PACKET;
+        arp[12..14].copy_from_slice(&[0x08, 0x06]);
+
+        // the same IPv6 packet with an 802.1Q tag.
+        let mut tagged = IPV6_PACKET[..12].to_vec();
+        tagged.extend_from_slice(&[0x81, 0x00, 0x00, 0x64]);
+        tagged.extend_from_slice(&IPV6_PACKET[12..]);
+
+        let mut lldp = UDP_PACKET;
+        lldp[12..14].copy_from_slice(&[0x88, 0xcc]);
+
+        let mark = |batch: Bridge<Mbuf>, branch: u32| {
+            batch.map(move |mut p| {
+                let meta = p.meta().with_mark(branch);
+                p.set_meta(meta);
+                Ok(p)
+            })
+        };
+        let mut batch = new_batch(&[&UDP_PACKET, &IPV6_PACKET, &arp, &tagged, &lldp]).split_ip(
+            move |v4| mark(v4, 4),
+            move |v6| mark(v6, 6),
+            move |arp| mark(arp, 1),
+            move |other| mark(other, 0),
+        );
+
+        for &expected in &[4, 6, 1, 6, 0] {
+            match batch.next().unwrap() {
+                Disposition::Act(p) => assert_eq!(expected, p.meta().mark),
+                _ => panic!("packet not acted on"),
+            }
+        }
+        assert!(batch.next().is_none());
+    }
+
     #[nb2::test]
     fn for_each_batch() {
         let mut side_effect = false;
@@ -442,6 +1158,39 @@ mod tests {
         assert!(batch.next().is_none());
     }
 
+    #[nb2::test]
+    fn respond_batch() {
+        let (tx, mut rx) = mpsc::channel();
+        let echo = EchoResponder::new(vec!["174.137.42.77".parse().unwrap()]);
+
+        let mut batch = new_batch(&[&ICMPV4_PACKET, &UDP_PACKET]).respond(echo, tx);
+
+        // the echo request is answered, the udp packet passes through.
+        assert!(batch.next().unwrap().is_emit());
+        assert!(batch.next().unwrap().is_act());
+        assert_eq!(1, rx.receive().len());
+    }
+
+    #[nb2::test]
+    fn rewrite_ethernet_batch() {
+        let smac = MacAddr::new(0x02, 0, 0, 0, 0, 0x01);
+        let dmac = MacAddr::new(0x02, 0, 0, 0, 0, 0x02);
+
+        let mut batch = new_batch(&[&UDP_PACKET]).rewrite_ethernet(smac, |_| Some(dmac));
+        match batch.next().unwrap() {
+            Disposition::Act(packet) => {
+                let ethernet = packet.peek::<Ethernet>().unwrap();
+                assert_eq!(smac, ethernet.src());
+                assert_eq!(dmac, ethernet.dst());
+            }
+            _ => unreachable!(),
+        }
+
+        let mut batch = new_batch(&[&UDP_PACKET]).rewrite_ethernet(smac, |_| None);
+        assert!(batch.next().unwrap().is_drop());
+        assert_eq!(1, stats::drop_stats().get(DropReason::Unresolved));
+    }
+
     #[nb2::test]
     fn poll_fn_batch() {
         let mut batch = poll_fn(|| vec![Mbuf::new().unwrap()]);
@@ -451,6 +1200,29 @@ mod tests {
         assert!(batch.next().is_none());
     }
 
+    #[nb2::test]
+    fn empty_poll_keeps_context() {
+        let polls = std::cell::Cell::new(0);
+        let mut batch = poll_fn(|| {
+            polls.set(polls.get() + 1);
+            if polls.get() == 1 {
+                vec![Mbuf::new().unwrap(), Mbuf::new().unwrap()]
+            } else {
+                vec![]
+            }
+        });
+
+        batch.replenish();
+        let tsc = with_context(|ctx| ctx.tsc());
+        batch.replenish();
+
+        assert!(batch.next().is_none());
+        with_context(|ctx| {
+            assert_eq!(2, ctx.burst_len());
+            assert_eq!(tsc, ctx.tsc());
+        });
+    }
+
     #[nb2::test]
     fn splice_pipeline() {
         let (mut tx1, rx1) = mpsc::channel();
@@ -467,4 +1239,85 @@ mod tests {
         pipeline.run_once();
         assert!(rx2.try_recv().is_ok());
     }
+
+    #[nb2::test]
+    fn distribute_by_flow_hash() {
+        let (tx1, mut rx1) = mpsc::channel();
+        let (tx2, mut rx2) = mpsc::channel();
+
+        let (mut tx, rx) = mpsc::channel();
+        let mut pipeline = Poll::new(rx).distribute(Distribution::FlowHash, vec![tx1, tx2]);
+
+        // the same flow always lands on the same target.
+        tx.transmit(
+            (0..4)
+                .map(|_| Mbuf::from_bytes(&UDP_PACKET).unwrap())
+                .collect::<Vec<_>>(),
+        );
+        pipeline.run_once();
+
+        let counts = (rx1.receive().len(), rx2.receive().len());
+        assert!(counts == (4, 0) || counts == (0, 4));
+
+        // non-ip packets go to the first target.
+        tx.transmit(vec![Mbuf::new().unwrap()]);
+        pipeline.run_once();
+        assert_eq!(1, rx1.receive().len());
+        assert_eq!(TryRecvError::Empty, rx2.try_recv().unwrap_err());
+    }
+
+    #[nb2::test]
+    fn distribute_weighted() {
+        let (tx1, mut rx1) = mpsc::channel();
+        let (tx2, mut rx2) = mpsc::channel();
+
+        let (mut tx, rx) = mpsc::channel();
+        let mut pipeline =
+            Poll::new(rx).distribute(Distribution::Weighted(vec![3, 1]), vec![tx1, tx2]);
+
+        tx.transmit((0..8).map(|_| Mbuf::new().unwrap()).collect::<Vec<_>>());
+        pipeline.run_once();
+
+        assert_eq!(6, rx1.receive().len());
+        assert_eq!(2, rx2.receive().len());
+    }
+
+    #[nb2::test]
+    fn send_to_selected_egress() {
+        let (tx1, mut rx1) = mpsc::channel();
+        let (tx2, mut rx2) = mpsc::channel();
+
+        let (mut tx, rx) = mpsc::channel();
+        let mut pipeline = Poll::new(rx)
+            .map(|p| p.parse::<Ethernet>()?.parse::<Ipv4>())
+            .send_to(vec![tx1, tx2], |v4| match v4.protocol() {
+                ProtocolNumbers::Udp => Some(0),
+                ProtocolNumbers::Tcp => Some(1),
+                _ => None,
+            });
+
+        tx.transmit(vec![
+            Mbuf::from_bytes(&UDP_PACKET).unwrap(),
+            Mbuf::from_bytes(&TCP_PACKET).unwrap(),
+            Mbuf::from_bytes(&ICMPV4_PACKET).unwrap(),
+        ]);
+        pipeline.run_once();
+
+        assert_eq!(1, rx1.receive().len());
+        assert_eq!(1, rx2.receive().len());
+        assert_eq!(1, stats::drop_stats().get(DropReason::NoRoute));
+    }
+
+    #[nb2::test]
+    fn sequence_batch() {
+        let mut batch = new_batch(&[&UDP_PACKET, &TCP_PACKET]).sequence();
+
+        let seqns = iter::from_fn(|| batch.next())
+            .map(|disp| match disp {
+                Disposition::Act(packet) => packet.seqn(),
+                _ => panic!("not act!"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 1], seqns);
+    }
 }
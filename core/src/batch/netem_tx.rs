@@ -0,0 +1,347 @@
+use super::PacketTx;
+use crate::dpdk::tsc;
+use crate::net::{TimerWheel, Xoshiro256};
+use crate::stats::{self, DropReason};
+use crate::Mbuf;
+use futures::{future, StreamExt};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tokio_executor::current_thread;
+use tokio_timer::Interval;
+
+/// The resolution of the delays.
+const TICK: Duration = Duration::from_micros(100);
+
+/// The slots of the wheel, a turn of about 400ms. Longer delays take more
+/// than a turn, which the wheel handles.
+const SLOTS: usize = 4096;
+
+/// The distribution of the variation of the delay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Jitter {
+    /// Every packet is delayed the same.
+    None,
+    /// The delay varies uniformly by up to the duration either way.
+    Uniform(Duration),
+    /// The delay varies normally with the duration as the standard
+    /// deviation, cut at three deviations either way.
+    Normal(Duration),
+}
+
+struct Inner<Tx: PacketTx> {
+    tx: Tx,
+    delay: Duration,
+    jitter: Jitter,
+    loss: f64,
+    duplicate: f64,
+    reorder: f64,
+    rng: Xoshiro256,
+    wheel: TimerWheel<Mbuf>,
+}
+
+impl<Tx: PacketTx> Inner<Tx> {
+    /// Returns `true` with the probability.
+    fn chance(&mut self, probability: f64) -> bool {
+        let sample = (self.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        probability > 0.0 && sample < probability
+    }
+
+    /// Returns a uniform sample in `[-1, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.rng.next_u64() >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    /// Returns the delay of a packet.
+    fn sample_delay(&mut self) -> Duration {
+        let offset = match self.jitter {
+            Jitter::None => 0.0,
+            Jitter::Uniform(range) => range.as_secs_f64() * self.unit(),
+            Jitter::Normal(deviation) => {
+                // Box-Muller, with `1 - u1` to keep off `ln(0)`.
+                let u1 = (self.unit() + 1.0) / 2.0;
+                let u2 = (self.unit() + 1.0) / 2.0;
+                let z = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                deviation.as_secs_f64() * z.max(-3.0).min(3.0)
+            }
+        };
+
+        Duration::from_secs_f64((self.delay.as_secs_f64() + offset).max(0.0))
+    }
+
+    fn impair(&mut self, packets: Vec<Mbuf>, now: Instant) {
+        let hold = self.delay > Duration::from_secs(0) || self.jitter != Jitter::None;
+        let mut passed = vec![];
+        let mut lost = 0;
+
+        for mbuf in packets {
+            if self.chance(self.loss) {
+                lost += 1;
+                continue;
+            }
+
+            // the copy goes through the delay on its own.
+            let copy = if self.chance(self.duplicate) {
+                duplicate(&mbuf)
+            } else {
+                None
+            };
+
+            for mbuf in Some(mbuf).into_iter().chain(copy) {
+                // a reordered packet skips the delay, and goes ahead of the
+                // packets held.
+                if !hold || self.chance(self.reorder) {
+                    passed.push(mbuf);
+                } else {
+                    let delay = self.sample_delay();
+                    self.wheel.insert(now + delay, mbuf);
+                }
+            }
+        }
+
+        if lost > 0 {
+            stats::record_drops(DropReason::Impaired, lost);
+        }
+
+        passed.extend(self.wheel.advance(now));
+        if !passed.is_empty() {
+            self.tx.transmit(passed);
+        }
+    }
+
+    fn drain(&mut self, now: Instant) {
+        let packets = self.wheel.advance(now);
+        if !packets.is_empty() {
+            self.tx.transmit(packets);
+        }
+    }
+}
+
+/// Returns a copy of the packet in a new buffer, or `None` if the buffer
+/// cannot be allocated.
+fn duplicate(mbuf: &Mbuf) -> Option<Mbuf> {
+    mbuf.deep_copy().ok()
+}
+
+/// A `PacketTx` that impairs the packets before passing them on, the way
+/// `netem` does, so nb2 can stand in for a WAN when testing other
+/// systems.
+///
+/// The packets are lost, duplicated, delayed and reordered at random, each
+/// with its own probability:
+///
+/// * a lost packet is dropped, and recorded as `DropReason::Impaired`.
+/// * a duplicated packet is copied, and the copy is delayed on its own.
+/// * a packet is held for the delay, varied by the jitter. With enough
+///   jitter, the packets overtake each other.
+/// * a reordered packet skips the delay, and goes ahead of the packets
+///   held.
+///
+/// The held packets are kept in a `TimerWheel` of `100µs` ticks, passed on
+/// when the next packets are transmitted and on the timer of `drain_every`.
+/// All the impairments are off by default.
+///
+/// # Example
+///
+/// ```
+/// runtime.add_pipeline_to_port("eth1", |q| {
+///     // a lossy satellite link.
+///     let netem = NetemTx::new(q.clone())
+///         .delay(Duration::from_millis(300), Jitter::Normal(Duration::from_millis(20)))
+///         .loss(0.01)
+///         .reorder(0.001);
+///     netem.drain_every(Duration::from_micros(100));
+///
+///     Poll::new(q.clone()).send(netem)
+/// })?;
+/// ```
+pub struct NetemTx<Tx: PacketTx> {
+    inner: Rc<RefCell<Inner<Tx>>>,
+}
+
+impl<Tx: PacketTx> NetemTx<Tx> {
+    /// Creates an impairment in front of `tx`, passing the packets on
+    /// unchanged until the impairments are set.
+    pub fn new(tx: Tx) -> Self {
+        NetemTx {
+            inner: Rc::new(RefCell::new(Inner {
+                tx,
+                delay: Duration::from_secs(0),
+                jitter: Jitter::None,
+                loss: 0.0,
+                duplicate: 0.0,
+                reorder: 0.0,
+                rng: Xoshiro256::new(tsc()),
+                wheel: TimerWheel::new(TICK, SLOTS),
+            })),
+        }
+    }
+
+    /// Sets the delay of the packets, and its variation.
+    pub fn delay(self, delay: Duration, jitter: Jitter) -> Self {
+        {
+            let mut inner = self.inner.borrow_mut();
+            inner.delay = delay;
+            inner.jitter = jitter;
+        }
+        self
+    }
+
+    /// Sets the probability of a packet being lost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the probability is not in `[0, 1]`.
+    pub fn loss(self, probability: f64) -> Self {
+        assert!(probability >= 0.0 && probability <= 1.0);
+        self.inner.borrow_mut().loss = probability;
+        self
+    }
+
+    /// Sets the probability of a packet being duplicated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the probability is not in `[0, 1]`.
+    pub fn duplicate(self, probability: f64) -> Self {
+        assert!(probability >= 0.0 && probability <= 1.0);
+        self.inner.borrow_mut().duplicate = probability;
+        self
+    }
+
+    /// Sets the probability of a packet skipping the delay.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the probability is not in `[0, 1]`.
+    pub fn reorder(self, probability: f64) -> Self {
+        assert!(probability >= 0.0 && probability <= 1.0);
+        self.inner.borrow_mut().reorder = probability;
+        self
+    }
+
+    /// Seeds the random impairments, so a test run can be repeated. By
+    /// default, the seed is the time stamp counter.
+    pub fn seed(self, seed: u64) -> Self {
+        self.inner.borrow_mut().rng = Xoshiro256::new(seed);
+        self
+    }
+
+    /// Returns the number of packets held.
+    pub fn held(&self) -> usize {
+        self.inner.borrow().wheel.len()
+    }
+
+    /// Impairs the packets, and passes on the ones not held, with the held
+    /// ones that are due.
+    pub(crate) fn transmit(&self, packets: Vec<Mbuf>) {
+        self.inner.borrow_mut().impair(packets, Instant::now());
+    }
+
+    /// Spawns a timer on the current core that passes on the held packets
+    /// that are due every `interval`.
+    ///
+    /// The interval adds to the delays, so it should be well under them.
+    /// Must be called on the core the pipeline runs on, for example in the
+    /// pipeline installer.
+    pub fn drain_every(&self, interval: Duration)
+    where
+        Tx: 'static,
+    {
+        let inner = Rc::downgrade(&self.inner);
+        let alive = inner.clone();
+
+        // stops once the last clone of the impairment is dropped.
+        let fut = Interval::new_interval(interval)
+            .take_while(move |_| future::ready(alive.upgrade().is_some()))
+            .for_each(move |_| {
+                if let Some(inner) = inner.upgrade() {
+                    inner.borrow_mut().drain(Instant::now());
+                }
+                future::ready(())
+            });
+        current_thread::spawn(fut);
+    }
+}
+
+impl<Tx: PacketTx> Clone for NetemTx<Tx> {
+    fn clone(&self) -> Self {
+        NetemTx {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testils::byte_arrays::UDP_PACKET;
+    use std::sync::mpsc;
+
+    fn packets(n: usize) -> Vec<Mbuf> {
+        (0..n)
+            .map(|_| Mbuf::from_bytes(&UDP_PACKET).unwrap())
+            .collect()
+    }
+
+    #[nb2::test]
+    fn lose_and_duplicate() {
+        let (tx, rx) = mpsc::channel();
+        let netem = NetemTx::new(tx).seed(1).loss(1.0);
+        netem.transmit(packets(4));
+        assert!(rx.try_recv().is_err());
+
+        let (tx, rx) = mpsc::channel();
+        let netem = NetemTx::new(tx).seed(1).duplicate(1.0);
+        netem.transmit(packets(4));
+        assert_eq!(8, rx.try_iter().count());
+    }
+
+    #[nb2::test]
+    fn hold_for_the_delay() {
+        let (tx, rx) = mpsc::channel();
+        let netem = NetemTx::new(tx).seed(1).delay(
+            Duration::from_millis(10),
+            Jitter::Uniform(Duration::from_millis(2)),
+        );
+
+        let now = Instant::now();
+        netem.inner.borrow_mut().impair(packets(4), now);
+        assert_eq!(4, netem.held());
+        assert!(rx.try_recv().is_err());
+
+        netem
+            .inner
+            .borrow_mut()
+            .drain(now + Duration::from_millis(7));
+        assert_eq!(4, netem.held());
+        netem
+            .inner
+            .borrow_mut()
+            .drain(now + Duration::from_millis(13));
+        assert_eq!(0, netem.held());
+        assert_eq!(4, rx.try_iter().count());
+
+        // reordered packets skip the delay.
+        let netem = netem.reorder(1.0);
+        netem.inner.borrow_mut().impair(packets(2), now);
+        assert_eq!(0, netem.held());
+    }
+
+    #[test]
+    fn sample_delays_around_the_mean() {
+        let (tx, _rx) = mpsc::channel();
+        let netem = NetemTx::new(tx).seed(7).delay(
+            Duration::from_millis(50),
+            Jitter::Normal(Duration::from_millis(5)),
+        );
+        let mut inner = netem.inner.borrow_mut();
+
+        let samples = (0..10_000)
+            .map(|_| inner.sample_delay().as_secs_f64() * 1000.0)
+            .collect::<Vec<_>>();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((mean - 50.0).abs() < 0.5);
+        assert!(samples.iter().all(|&ms| ms >= 35.0 && ms <= 65.0));
+    }
+}
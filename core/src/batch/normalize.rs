@@ -0,0 +1,399 @@
+use super::{Batch, Disposition, Either};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::{Ipv6, Ipv6Packet};
+use crate::packets::ip::{IpPacket, ProtocolNumbers};
+use crate::packets::{EtherTypes, Ethernet, Packet, Tcp, Udp};
+use crate::stats::{self, DropReason};
+use crate::{Mbuf, Result};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const TCP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+
+/// Policy of the packet normalization.
+///
+/// The default policy enables every check but the minimum TTL.
+#[derive(Clone, Copy, Debug)]
+pub struct NormalizePolicy {
+    /// Drops the packets whose header lengths are inconsistent with each
+    /// other or with the buffer.
+    pub drop_bogus_lengths: bool,
+    /// Drops the TCP segments with a combination of flags no stack sends,
+    /// such as SYN with FIN, and clears the reserved bits of the others.
+    pub scrub_tcp_flags: bool,
+    /// Raises the TTL or hop limit of the packets below the minimum.
+    pub min_ttl: Option<u8>,
+    /// Removes the options of IPv4 packets.
+    pub strip_ip_options: bool,
+}
+
+impl Default for NormalizePolicy {
+    fn default() -> Self {
+        NormalizePolicy {
+            drop_bogus_lengths: true,
+            scrub_tcp_flags: true,
+            min_ttl: None,
+            strip_ip_options: true,
+        }
+    }
+}
+
+impl NormalizePolicy {
+    /// Normalizes the packet, or rejects it as malformed.
+    ///
+    /// The bytes trailing the IP packet, like the Ethernet padding, are
+    /// always removed. Packets that are not IP are kept as they are. The
+    /// transport header of a fragment, of an IPv6 packet with extension
+    /// headers, or of an IPv4 packet with options left in, is not checked,
+    /// and its checksum is not recomputed, as it covers the whole datagram
+    /// and not the fragment.
+    pub fn apply(&self, mbuf: Mbuf) -> Result<Either<Mbuf>> {
+        if mbuf.data_len() < ETHERNET_HEADER_LEN {
+            return Ok(self.bogus_length(mbuf));
+        }
+
+        let ethernet = mbuf.parse::<Ethernet>()?;
+        match ethernet.ether_type() {
+            EtherTypes::Ipv4 => self.apply_v4(ethernet),
+            EtherTypes::Ipv6 => self.apply_v6(ethernet),
+            _ => Ok(Either::Keep(ethernet.reset())),
+        }
+    }
+
+    fn apply_v4(&self, ethernet: Ethernet) -> Result<Either<Mbuf>> {
+        if ethernet.payload_len() < IPV4_HEADER_LEN {
+            return Ok(self.bogus_length(ethernet.reset()));
+        }
+
+        let mut v4 = ethernet.parse::<Ipv4>()?;
+        let header_len = v4.ihl() as usize * 4;
+        let total_len = v4.total_length() as usize;
+        if header_len < IPV4_HEADER_LEN || header_len > total_len || total_len > v4.len() {
+            if self.drop_bogus_lengths {
+                return Ok(Either::Reject(v4.reset(), DropReason::Malformed));
+            }
+        } else {
+            v4.envelope_mut().strip_padding()?;
+            if self.strip_ip_options {
+                v4.strip_options()?;
+            }
+        }
+
+        if let Some(min_ttl) = self.min_ttl {
+            if v4.ttl() < min_ttl {
+                v4.set_ttl(min_ttl);
+            }
+        }
+
+        if v4.is_fragment() || v4.options_len() > 0 {
+            return Ok(finish(v4));
+        }
+
+        match v4.protocol() {
+            ProtocolNumbers::Tcp => self.apply_tcp(v4),
+            ProtocolNumbers::Udp => self.apply_udp(v4),
+            _ => Ok(finish(v4)),
+        }
+    }
+
+    fn apply_v6(&self, ethernet: Ethernet) -> Result<Either<Mbuf>> {
+        if ethernet.payload_len() < IPV6_HEADER_LEN {
+            return Ok(self.bogus_length(ethernet.reset()));
+        }
+
+        let mut v6 = ethernet.parse::<Ipv6>()?;
+        let payload_len = v6.payload_length() as usize;
+        if payload_len > v6.payload_len() || (payload_len == 0 && v6.payload_len() > 0) {
+            // a payload length of `0` is only valid for jumbograms.
+            if self.drop_bogus_lengths && !v6.is_jumbogram() {
+                return Ok(Either::Reject(v6.reset(), DropReason::Malformed));
+            }
+        } else {
+            v6.envelope_mut().strip_padding()?;
+        }
+
+        if let Some(min_ttl) = self.min_ttl {
+            if v6.hop_limit() < min_ttl {
+                v6.set_hop_limit(min_ttl);
+            }
+        }
+
+        match v6.next_header() {
+            ProtocolNumbers::Tcp => self.apply_tcp(v6),
+            ProtocolNumbers::Udp => self.apply_udp(v6),
+            _ => Ok(finish(v6)),
+        }
+    }
+
+    fn apply_tcp<E: IpPacket>(&self, envelope: E) -> Result<Either<Mbuf>> {
+        let len = envelope.payload_len();
+        if len < TCP_HEADER_LEN {
+            return Ok(self.bogus_length(envelope.reset()));
+        }
+
+        let mut tcp = envelope.parse::<Tcp<E>>()?;
+        let header_len = tcp.data_offset() as usize * 4;
+        if self.drop_bogus_lengths && (header_len < TCP_HEADER_LEN || header_len > len) {
+            return Ok(Either::Reject(tcp.reset(), DropReason::Malformed));
+        }
+
+        if self.scrub_tcp_flags {
+            if !valid_flags(&tcp) {
+                return Ok(Either::Reject(tcp.reset(), DropReason::Malformed));
+            }
+            if tcp.reserved() != 0 {
+                tcp.clear_reserved();
+            }
+        }
+
+        Ok(finish(tcp))
+    }
+
+    fn apply_udp<E: IpPacket>(&self, envelope: E) -> Result<Either<Mbuf>> {
+        let len = envelope.payload_len();
+        if len < UDP_HEADER_LEN {
+            return Ok(self.bogus_length(envelope.reset()));
+        }
+
+        let udp = envelope.parse::<Udp<E>>()?;
+        let length = udp.length() as usize;
+        if self.drop_bogus_lengths && (length < UDP_HEADER_LEN || length > len) {
+            return Ok(Either::Reject(udp.reset(), DropReason::Malformed));
+        }
+
+        Ok(finish(udp))
+    }
+
+    /// Rejects the packet if bogus lengths are dropped, keeps it otherwise.
+    fn bogus_length(&self, mbuf: Mbuf) -> Either<Mbuf> {
+        if self.drop_bogus_lengths {
+            Either::Reject(mbuf, DropReason::Malformed)
+        } else {
+            Either::Keep(mbuf)
+        }
+    }
+}
+
+/// Returns whether the combination of TCP flags is one a stack sends.
+///
+/// Rejects segments without any flag, SYN with FIN or RST, and FIN, PSH
+/// or URG without ACK, which are only sent by scanners to fingerprint the
+/// target.
+fn valid_flags<E: IpPacket>(tcp: &Tcp<E>) -> bool {
+    let any = tcp.syn() || tcp.ack() || tcp.fin() || tcp.rst() || tcp.psh() || tcp.urg();
+    let syn_fin_rst = tcp.syn() && (tcp.fin() || tcp.rst());
+    let no_ack = !tcp.ack() && (tcp.fin() || tcp.psh() || tcp.urg());
+    any && !syn_fin_rst && !no_ack
+}
+
+fn finish<P: Packet>(mut packet: P) -> Either<Mbuf> {
+    packet.cascade();
+    Either::Keep(packet.reset())
+}
+
+/// A batch that normalizes the packets of the underlying batch.
+///
+/// The malformed packets are dropped and recorded in the stats as
+/// `DropReason::Malformed`.
+pub struct Normalize<B: Batch<Item = Mbuf>> {
+    batch: B,
+    policy: NormalizePolicy,
+}
+
+impl<B: Batch<Item = Mbuf>> Normalize<B> {
+    #[inline]
+    pub fn new(batch: B, policy: NormalizePolicy) -> Self {
+        Normalize { batch, policy }
+    }
+}
+
+impl<B: Batch<Item = Mbuf>> Batch for Normalize<B> {
+    type Item = Mbuf;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let policy = self.policy;
+        self.batch.next().map(|disp| {
+            disp.map(|mbuf| {
+                let port_id = mbuf.port_id();
+                match policy.apply(mbuf) {
+                    Ok(Either::Keep(mbuf)) => Disposition::Act(mbuf),
+                    Ok(Either::Drop(mbuf)) => Disposition::Drop(mbuf),
+                    Ok(Either::Reject(mbuf, reason)) => {
+                        stats::record_drop(reason);
+                        Disposition::Drop(mbuf)
+                    }
+                    Err(e) => {
+                        stats::record_failure(port_id, &e);
+                        Disposition::Abort(e)
+                    }
+                }
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::data_slice;
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+
+    fn apply(policy: &NormalizePolicy, data: &[u8]) -> Either<Mbuf> {
+        policy.apply(Mbuf::from_bytes(data).unwrap()).unwrap()
+    }
+
+    fn keep(either: Either<Mbuf>) -> Mbuf {
+        match either {
+            Either::Keep(mbuf) => mbuf,
+            _ => panic!("packet is not kept"),
+        }
+    }
+
+    fn is_rejected(either: &Either<Mbuf>) -> bool {
+        match either {
+            Either::Reject(_, DropReason::Malformed) => true,
+            _ => false,
+        }
+    }
+
+    #[nb2::test]
+    fn keep_valid_packets() {
+        let policy = NormalizePolicy::default();
+        assert_eq!(
+            TCP_PACKET.len(),
+            keep(apply(&policy, &TCP_PACKET)).data_len()
+        );
+        assert_eq!(
+            UDP_PACKET.len(),
+            keep(apply(&policy, &UDP_PACKET)).data_len()
+        );
+    }
+
+    #[nb2::test]
+    fn drop_bogus_lengths() {
+        let policy = NormalizePolicy::default();
+
+        // total length larger than the packet.
+        let mut data = TCP_PACKET.to_vec();
+        data[16] = 0xff;
+        assert!(is_rejected(&apply(&policy, &data)));
+
+        // IHL shorter than the fixed header.
+        let mut data = TCP_PACKET.to_vec();
+        data[14] = 0x44;
+        assert!(is_rejected(&apply(&policy, &data)));
+
+        // TCP data offset past the end of the segment.
+        let mut data = TCP_PACKET.to_vec();
+        data[46] = 0xf0;
+        assert!(is_rejected(&apply(&policy, &data)));
+
+        let lenient = NormalizePolicy {
+            drop_bogus_lengths: false,
+            ..NormalizePolicy::default()
+        };
+        assert!(!is_rejected(&apply(&lenient, &data)));
+    }
+
+    #[nb2::test]
+    fn strip_padding() {
+        let mut data = TCP_PACKET.to_vec();
+        data.extend_from_slice(&[0; 6]);
+
+        let mbuf = keep(apply(&NormalizePolicy::default(), &data));
+        assert_eq!(TCP_PACKET.len(), mbuf.data_len());
+    }
+
+    #[nb2::test]
+    fn scrub_tcp_flags() {
+        let policy = NormalizePolicy::default();
+
+        // SYN and FIN.
+        let mut data = TCP_PACKET.to_vec();
+        data[47] = 0x03;
+        assert!(is_rejected(&apply(&policy, &data)));
+
+        // no flags.
+        data[47] = 0x00;
+        assert!(is_rejected(&apply(&policy, &data)));
+
+        // FIN, PSH and URG, the xmas scan.
+        data[47] = 0x29;
+        assert!(is_rejected(&apply(&policy, &data)));
+
+        // reserved bits set on a plain SYN.
+        let mut data = TCP_PACKET.to_vec();
+        data[46] = 0x6e;
+        let tcp = keep(apply(&policy, &data))
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap()
+            .parse::<Tcp<Ipv4>>()
+            .unwrap();
+        assert_eq!(0, tcp.reserved());
+        assert_eq!(6, tcp.data_offset());
+        assert!(tcp.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn raise_min_ttl() {
+        let policy = NormalizePolicy {
+            min_ttl: Some(64),
+            ..NormalizePolicy::default()
+        };
+
+        let mut data = TCP_PACKET.to_vec();
+        data[22] = 1;
+        let v4 = keep(apply(&policy, &data))
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap();
+        assert_eq!(64, v4.ttl());
+        assert!(v4.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn strip_ip_options() {
+        // inserts 4 bytes of options, a router alert, after the fixed header.
+        let mut data = TCP_PACKET[..34].to_vec();
+        data[14] = 0x46;
+        data[17] += 4;
+        data.extend_from_slice(&[0x94, 0x04, 0x00, 0x00]);
+        data.extend_from_slice(&TCP_PACKET[34..]);
+
+        let v4 = keep(apply(&NormalizePolicy::default(), &data))
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap();
+        assert_eq!(5, v4.ihl());
+        assert_eq!(44, v4.total_length());
+        assert!(v4.verify_checksum());
+
+        let tcp = v4.parse::<Tcp<Ipv4>>().unwrap();
+        assert_eq!(23, tcp.dst_port());
+    }
+
+    #[nb2::test]
+    fn keep_fragment_checksum() {
+        // the first fragment, with more to follow, and a new sequence
+        // number the checksum doesn't cover.
+        let mut data = TCP_PACKET.to_vec();
+        data[20] |= 0x20;
+        data[41] ^= 0xff;
+
+        let mbuf = keep(apply(&NormalizePolicy::default(), &data));
+        let bytes = data_slice(&mbuf, 0, mbuf.data_len());
+        assert_eq!(&data[50..52], &bytes[50..52]);
+    }
+}
@@ -0,0 +1,158 @@
+use super::PacketTx;
+use crate::packets::data_slice;
+use crate::{warn, Mbuf, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// The snap length that captures the whole frame, the maximum of
+/// `Mbuf::data_len`.
+const SNAPLEN_MAX: usize = 65535;
+
+/// A transmit that writes the packets to a libpcap capture instead of a
+/// port.
+///
+/// Writes the classic pcap format with microsecond timestamps, in the
+/// native byte order. The packets are freed once written.
+///
+/// # Example
+///
+/// ```
+/// runtime.add_pipeline_to_port("eth0", |q| {
+///     let pcap = PcapTx::with_snaplen(File::create("eth0.pcap").unwrap(), 128).unwrap();
+///     Poll::new(q).send(pcap)
+/// })?;
+/// ```
+pub struct PcapTx<W: Write> {
+    writer: W,
+    snaplen: usize,
+}
+
+impl PcapTx<BufWriter<File>> {
+    /// Creates the pcap file at `path`, capturing the whole frames.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::create(path)?;
+        PcapTx::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> PcapTx<W> {
+    /// Creates a new capture that stores the whole frames, writing the pcap
+    /// global header.
+    pub fn new(writer: W) -> Result<Self> {
+        PcapTx::with_snaplen(writer, SNAPLEN_MAX)
+    }
+
+    /// Creates a new capture that only stores the first `snaplen` bytes of
+    /// each frame, writing the pcap global header.
+    ///
+    /// The original length of the frames is still recorded. Use a snap
+    /// length large enough for the headers of interest, for example `128`,
+    /// to keep the capture cheap at high rates.
+    pub fn with_snaplen(mut writer: W, snaplen: usize) -> Result<Self> {
+        let snaplen = snaplen.min(SNAPLEN_MAX).max(1);
+
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&MAGIC_MICROS.to_ne_bytes());
+        header.extend_from_slice(&2u16.to_ne_bytes());
+        header.extend_from_slice(&4u16.to_ne_bytes());
+        // the timezone offset and the timestamp accuracy, both always 0.
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&(snaplen as u32).to_ne_bytes());
+        header.extend_from_slice(&LINKTYPE_ETHERNET.to_ne_bytes());
+        writer.write_all(&header)?;
+
+        Ok(PcapTx { writer, snaplen })
+    }
+
+    /// Returns the snap length of the capture.
+    #[inline]
+    pub fn snaplen(&self) -> usize {
+        self.snaplen
+    }
+
+    fn write_packet(&mut self, mbuf: &Mbuf) -> Result<()> {
+        let len = mbuf.data_len();
+        let incl_len = len.min(self.snaplen);
+        let data = data_slice(mbuf, 0, incl_len);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(&(now.as_secs() as u32).to_ne_bytes());
+        header[4..8].copy_from_slice(&now.subsec_micros().to_ne_bytes());
+        header[8..12].copy_from_slice(&(incl_len as u32).to_ne_bytes());
+        header[12..].copy_from_slice(&(len as u32).to_ne_bytes());
+
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        Ok(())
+    }
+
+    /// Writes the packets to the capture.
+    pub(crate) fn transmit(&mut self, packets: Vec<Mbuf>) {
+        for packet in packets.iter() {
+            if let Err(err) = self.write_packet(packet) {
+                warn!(message = "failed to write to pcap.", ?err);
+                break;
+            }
+        }
+
+        Mbuf::free_bulk(packets);
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+    use crate::testils::PcapReader;
+
+    #[nb2::test]
+    fn write_pcap() {
+        let mut pcap = PcapTx::new(vec![]).unwrap();
+        pcap.transmit(vec![
+            Mbuf::from_bytes(&UDP_PACKET).unwrap(),
+            Mbuf::from_bytes(&TCP_PACKET).unwrap(),
+        ]);
+
+        let bytes = pcap.into_inner();
+        let packets = PcapReader::new(&bytes[..])
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(2, packets.len());
+        assert_eq!(UDP_PACKET.len(), packets[0].data_len());
+        assert_eq!(TCP_PACKET.len(), packets[1].data_len());
+    }
+
+    #[nb2::test]
+    fn write_pcap_with_snaplen() {
+        let mut pcap = PcapTx::with_snaplen(vec![], 34).unwrap();
+        pcap.transmit(vec![Mbuf::from_bytes(&UDP_PACKET).unwrap()]);
+
+        let bytes = pcap.into_inner();
+        // the original length is kept in the record header.
+        assert_eq!(
+            UDP_PACKET.len() as u32,
+            u32::from_ne_bytes([bytes[36], bytes[37], bytes[38], bytes[39]])
+        );
+
+        let packets = PcapReader::new(&bytes[..])
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(34, packets[0].data_len());
+    }
+}
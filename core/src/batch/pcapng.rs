@@ -0,0 +1,370 @@
+use super::{with_context, PacketTx};
+use crate::packets::data_slice;
+use crate::{warn, Mbuf, PortId, PortInfo, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// the block types.
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const INTERFACE_STATISTICS_BLOCK: u32 = 5;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const LINKTYPE_ETHERNET: u16 = 1;
+
+// the option codes.
+const OPT_ENDOFOPT: u16 = 0;
+const IF_NAME: u16 = 2;
+const IF_SPEED: u16 = 8;
+const IF_TSRESOL: u16 = 9;
+const EPB_DROPCOUNT: u16 = 4;
+const ISB_IFRECV: u16 = 4;
+const ISB_IFDROP: u16 = 5;
+
+// the timestamps are in nanoseconds, 10^-9.
+const TSRESOL_NANOS: u8 = 9;
+
+/// The snap length that captures the whole frame, the maximum of
+/// `Mbuf::data_len`.
+const SNAPLEN_MAX: usize = 65535;
+
+/// The description of an interface of a pcapng capture.
+#[derive(Clone, Debug)]
+pub struct PcapngInterface {
+    name: String,
+    port_id: Option<PortId>,
+    speed: Option<u64>,
+    snaplen: usize,
+}
+
+impl PcapngInterface {
+    /// Creates the description of the interface `name`, capturing the
+    /// whole frames.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        PcapngInterface {
+            name: name.into(),
+            port_id: None,
+            speed: None,
+            snaplen: SNAPLEN_MAX,
+        }
+    }
+
+    /// Sets the port whose packets are recorded on the interface.
+    pub fn port_id(mut self, port_id: PortId) -> Self {
+        self.port_id = Some(port_id);
+        self
+    }
+
+    /// Sets the link speed, in bits per second.
+    pub fn speed(mut self, speed: u64) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    /// Only stores the first `snaplen` bytes of each frame.
+    pub fn snaplen(mut self, snaplen: usize) -> Self {
+        self.snaplen = snaplen.min(SNAPLEN_MAX).max(1);
+        self
+    }
+}
+
+impl From<&PortInfo> for PcapngInterface {
+    /// Describes the port by its device name and the speed of its link.
+    fn from(info: &PortInfo) -> Self {
+        let interface = PcapngInterface::new(info.device.clone()).port_id(info.port_id);
+        if info.link.up {
+            interface.speed(u64::from(info.link.speed) * 1_000_000)
+        } else {
+            interface
+        }
+    }
+}
+
+/// Appends an option, padded to 32 bits.
+fn push_option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_ne_bytes());
+    block.extend_from_slice(&(value.len() as u16).to_ne_bytes());
+    block.extend_from_slice(value);
+    pad(block);
+}
+
+/// Pads the block body to 32 bits.
+fn pad(block: &mut Vec<u8>) {
+    let padding = (4 - block.len() % 4) % 4;
+    block.extend_from_slice(&[0; 3][..padding]);
+}
+
+/// Returns the time since the epoch in nanoseconds, split in the high and
+/// low 32 bits.
+fn timestamp(time: SystemTime) -> (u32, u32) {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let nanos = since.as_secs() * 1_000_000_000 + u64::from(since.subsec_nanos());
+    ((nanos >> 32) as u32, nanos as u32)
+}
+
+/// A transmit that writes the packets to a pcapng capture instead of a
+/// port.
+///
+/// Unlike `PcapTx`, the capture describes the interfaces the packets are
+/// recorded on, with the port name and the link speed, the timestamps
+/// are in nanoseconds, and the packets dropped before the capture are
+/// recorded, so Wireshark shows where the capture has gaps. The blocks are
+/// written in the native byte order. The packets are freed once written.
+///
+/// The packets are stamped with the time their burst was received, from
+/// the context of the burst, so the packets of a burst share a timestamp.
+///
+/// A packet is recorded on the interface of its port, or on the first
+/// interface if no interface is described for its port.
+///
+/// # Example
+///
+/// ```
+/// let interface = PcapngInterface::new("eth0").speed(10_000_000_000).snaplen(128);
+///
+/// runtime.add_pipeline_to_port("eth0", move |q| {
+///     let pcapng = PcapngTx::create("eth0.pcapng", interface.clone()).unwrap();
+///     Poll::new(q).send(pcapng)
+/// })?;
+/// ```
+pub struct PcapngTx<W: Write> {
+    writer: W,
+    snaplens: Vec<usize>,
+    ports: HashMap<PortId, u32>,
+    // the packets dropped since the last packet, by interface.
+    dropped: Vec<u64>,
+}
+
+impl PcapngTx<BufWriter<File>> {
+    /// Creates the pcapng file at `path`, with a first interface.
+    pub fn create<P: AsRef<Path>>(path: P, interface: PcapngInterface) -> Result<Self> {
+        let file = File::create(path)?;
+        PcapngTx::new(BufWriter::new(file), interface)
+    }
+}
+
+impl<W: Write> PcapngTx<W> {
+    /// Creates a new capture, writing the section header and the
+    /// description of the first interface.
+    pub fn new(mut writer: W, interface: PcapngInterface) -> Result<Self> {
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_ne_bytes());
+        // version 1.0
+        body.extend_from_slice(&1u16.to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes());
+        // the section length is not known up front.
+        body.extend_from_slice(&(-1i64).to_ne_bytes());
+        write_block(&mut writer, SECTION_HEADER_BLOCK, &body)?;
+
+        let mut pcapng = PcapngTx {
+            writer,
+            snaplens: vec![],
+            ports: HashMap::new(),
+            dropped: vec![],
+        };
+        pcapng.add_interface(interface)?;
+        Ok(pcapng)
+    }
+
+    /// Describes another interface, and returns its ID in the capture.
+    pub fn add_interface(&mut self, interface: PcapngInterface) -> Result<u32> {
+        let mut body = Vec::with_capacity(64);
+        body.extend_from_slice(&LINKTYPE_ETHERNET.to_ne_bytes());
+        body.extend_from_slice(&0u16.to_ne_bytes());
+        body.extend_from_slice(&(interface.snaplen as u32).to_ne_bytes());
+        push_option(&mut body, IF_NAME, interface.name.as_bytes());
+        if let Some(speed) = interface.speed {
+            push_option(&mut body, IF_SPEED, &speed.to_ne_bytes());
+        }
+        push_option(&mut body, IF_TSRESOL, &[TSRESOL_NANOS]);
+        push_option(&mut body, OPT_ENDOFOPT, &[]);
+        write_block(&mut self.writer, INTERFACE_DESCRIPTION_BLOCK, &body)?;
+
+        let id = self.snaplens.len() as u32;
+        self.snaplens.push(interface.snaplen);
+        self.dropped.push(0);
+        if let Some(port_id) = interface.port_id {
+            self.ports.insert(port_id, id);
+        }
+        Ok(id)
+    }
+
+    /// Records packets of the interface that were dropped before they
+    /// could be captured, such as the packets missed by the port. The
+    /// count is written with the next packet of the interface.
+    pub fn record_dropped(&mut self, interface_id: u32, count: u64) {
+        if let Some(dropped) = self.dropped.get_mut(interface_id as usize) {
+            *dropped += count;
+        }
+    }
+
+    /// Writes the statistics of the interface, the packets it received
+    /// and dropped since the capture started.
+    pub fn write_statistics(
+        &mut self,
+        interface_id: u32,
+        received: u64,
+        dropped: u64,
+    ) -> Result<()> {
+        let (high, low) = timestamp(SystemTime::now());
+
+        let mut body = Vec::with_capacity(48);
+        body.extend_from_slice(&interface_id.to_ne_bytes());
+        body.extend_from_slice(&high.to_ne_bytes());
+        body.extend_from_slice(&low.to_ne_bytes());
+        push_option(&mut body, ISB_IFRECV, &received.to_ne_bytes());
+        push_option(&mut body, ISB_IFDROP, &dropped.to_ne_bytes());
+        push_option(&mut body, OPT_ENDOFOPT, &[]);
+        write_block(&mut self.writer, INTERFACE_STATISTICS_BLOCK, &body)
+    }
+
+    fn write_packet(&mut self, mbuf: &Mbuf, (high, low): (u32, u32)) -> Result<()> {
+        let interface_id = mbuf
+            .port_id()
+            .and_then(|port_id| self.ports.get(&port_id).cloned())
+            .unwrap_or(0);
+
+        let len = mbuf.data_len();
+        let incl_len = len.min(self.snaplens[interface_id as usize]);
+        let data = data_slice(mbuf, 0, incl_len);
+
+        let mut body = Vec::with_capacity(32 + incl_len);
+        body.extend_from_slice(&interface_id.to_ne_bytes());
+        body.extend_from_slice(&high.to_ne_bytes());
+        body.extend_from_slice(&low.to_ne_bytes());
+        body.extend_from_slice(&(incl_len as u32).to_ne_bytes());
+        body.extend_from_slice(&(len as u32).to_ne_bytes());
+        body.extend_from_slice(data);
+        pad(&mut body);
+
+        let dropped = std::mem::replace(&mut self.dropped[interface_id as usize], 0);
+        if dropped > 0 {
+            push_option(&mut body, EPB_DROPCOUNT, &dropped.to_ne_bytes());
+            push_option(&mut body, OPT_ENDOFOPT, &[]);
+        }
+
+        write_block(&mut self.writer, ENHANCED_PACKET_BLOCK, &body)
+    }
+
+    /// Writes the packets to the capture.
+    pub(crate) fn transmit(&mut self, packets: Vec<Mbuf>) {
+        let time = timestamp(with_context(|ctx| ctx.timestamp()));
+
+        for packet in packets.iter() {
+            if let Err(err) = self.write_packet(packet, time) {
+                warn!(message = "failed to write to pcapng.", ?err);
+                break;
+            }
+        }
+
+        Mbuf::free_bulk(packets);
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writes a block, the body framed by the type and the total length.
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> Result<()> {
+    let total_len = (body.len() + 12) as u32;
+    writer.write_all(&block_type.to_ne_bytes())?;
+    writer.write_all(&total_len.to_ne_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&total_len.to_ne_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_ne_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_ne_bytes(word)
+    }
+
+    // splits the capture into the type and body of each block.
+    fn blocks(bytes: &[u8]) -> Vec<(u32, &[u8])> {
+        let mut blocks = vec![];
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let len = u32_at(bytes, offset + 4) as usize;
+            assert_eq!(len as u32, u32_at(bytes, offset + len - 4));
+            blocks.push((u32_at(bytes, offset), &bytes[offset + 8..offset + len - 4]));
+            offset += len;
+        }
+        blocks
+    }
+
+    #[nb2::test]
+    fn write_pcapng() {
+        let port_id = PortId::new(3);
+        let mut pcapng =
+            PcapngTx::new(vec![], PcapngInterface::new("eth0").speed(1_000_000_000)).unwrap();
+        let id = pcapng
+            .add_interface(PcapngInterface::new("eth1").port_id(port_id).snaplen(34))
+            .unwrap();
+        assert_eq!(1, id);
+
+        let mut packet = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        packet.set_port_id(port_id);
+        pcapng.record_dropped(1, 5);
+        pcapng.transmit(vec![Mbuf::from_bytes(&UDP_PACKET).unwrap(), packet]);
+        pcapng.write_statistics(1, 10, 5).unwrap();
+
+        let bytes = pcapng.into_inner();
+        let blocks = blocks(&bytes);
+        let types = blocks.iter().map(|&(t, _)| t).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                SECTION_HEADER_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                INTERFACE_STATISTICS_BLOCK,
+            ],
+            types
+        );
+
+        // the name of the first interface is its first option.
+        let idb = blocks[1].1;
+        assert_eq!(IF_NAME, u16_at(idb, 8));
+        assert_eq!(4, u16_at(idb, 10));
+        assert_eq!(b"eth0", &idb[12..16]);
+
+        // the udp packet is recorded whole on the first interface.
+        let epb = blocks[3].1;
+        assert_eq!(0, u32_at(epb, 0));
+        assert_eq!(UDP_PACKET.len() as u32, u32_at(epb, 12));
+        assert_eq!(&UDP_PACKET[..], &epb[20..20 + UDP_PACKET.len()]);
+
+        // the tcp packet is cut to the snap length of its port, and
+        // carries the drops.
+        let epb = blocks[4].1;
+        assert_eq!(1, u32_at(epb, 0));
+        assert_eq!(34, u32_at(epb, 12));
+        assert_eq!(TCP_PACKET.len() as u32, u32_at(epb, 16));
+        assert_eq!(EPB_DROPCOUNT, u16_at(epb, 56));
+        assert_eq!(&5u64.to_ne_bytes(), &epb[60..68]);
+
+        // the packets are stamped with the time of their burst.
+        let (high, low) = timestamp(with_context(|ctx| ctx.timestamp()));
+        for &(_, epb) in &blocks[3..5] {
+            assert_eq!(high, u32_at(epb, 4));
+            assert_eq!(low, u32_at(epb, 8));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use super::{Batch, Disposition};
+use crate::dpdk::{tsc, tsc_hz, Color};
+use crate::packets::Packet;
+
+/// A batch that colors the packets with a single rate three color marker,
+/// as of RFC 2697, in color blind mode.
+///
+/// The committed and the excess buckets are both filled at the committed
+/// rate, the excess bucket with what overflows the committed one. A packet
+/// is green if the committed bucket has tokens for it, yellow if the
+/// excess bucket has, and red otherwise. The color is set in the packet
+/// metadata for the schedulers downstream, which drop the red packets
+/// first when a queue is full. No packet is dropped by the policer.
+///
+/// The buckets are filled once per batch.
+pub struct Police<B: Batch> {
+    batch: B,
+    // the committed rate in bytes per second.
+    rate: f64,
+    cbs: f64,
+    ebs: f64,
+    committed: f64,
+    excess: f64,
+    // the time stamp counter when the buckets were last filled.
+    last: u64,
+}
+
+impl<B: Batch> Police<B> {
+    #[inline]
+    pub fn new(batch: B, cir: u64, cbs: usize, ebs: usize) -> Self {
+        Police {
+            batch,
+            rate: cir as f64 / 8.0,
+            cbs: cbs as f64,
+            ebs: ebs as f64,
+            committed: cbs as f64,
+            excess: ebs as f64,
+            last: tsc(),
+        }
+    }
+}
+
+impl<B: Batch> Batch for Police<B> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+
+        let now = tsc();
+        let elapsed = now.wrapping_sub(self.last) as f64 / tsc_hz() as f64;
+        self.last = now;
+
+        let committed = self.committed + self.rate * elapsed;
+        self.committed = committed.min(self.cbs);
+        self.excess = (self.excess + committed - self.committed).min(self.ebs);
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let committed = &mut self.committed;
+        let excess = &mut self.excess;
+
+        self.batch.next().map(|disp| {
+            disp.map(|mut pkt| {
+                let len = pkt.mbuf().pkt_len() as f64;
+                let color = if *committed >= len {
+                    *committed -= len;
+                    Color::Green
+                } else if *excess >= len {
+                    *excess -= len;
+                    Color::Yellow
+                } else {
+                    Color::Red
+                };
+
+                let meta = pkt.mbuf().meta().with_color(color);
+                pkt.mbuf_mut().set_meta(meta);
+                Disposition::Act(pkt)
+            })
+        })
+    }
+}
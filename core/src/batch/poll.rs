@@ -1,3 +1,4 @@
+use super::context;
 use super::{Batch, Disposition, PacketRx, PollRx};
 use crate::Mbuf;
 use std::collections::VecDeque;
@@ -24,13 +25,20 @@ impl<Rx: PacketRx> Batch for Poll<Rx> {
     /// Replenishes the batch with new packets from the RX source.
     ///
     /// If there are still packets left in the current queue, they are lost.
+    /// The context of the new burst is started on the core, unless the
+    /// poll came back empty.
     #[inline]
     fn replenish(&mut self) {
+        let packets = self.rx.receive();
+        if !packets.is_empty() {
+            context::begin_burst(packets.first().and_then(Mbuf::port_id), packets.len());
+        }
+
         // `VecDeque` is not the ideal structure here. We are relying on the
         // conversion from `Vec` to `VecDeque` to be allocation-free. but
         // unfortunately that's not always the case. We need an efficient and
         // allocation-free data structure with pop semantic.
-        self.packets = Some(self.rx.receive().into());
+        self.packets = Some(packets.into());
     }
 
     #[inline]
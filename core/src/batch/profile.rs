@@ -0,0 +1,39 @@
+use super::{Batch, Disposition};
+use crate::stats;
+
+/// A batch that profiles the cycles spent in the underlying batch.
+///
+/// The cycles are recorded per packet under the name of the profiling
+/// point, excluding the cycles already recorded by the profiling points
+/// further upstream. So each point measures the combinators between it and
+/// the previous point.
+pub struct Profile<B: Batch> {
+    batch: B,
+    name: &'static str,
+}
+
+impl<B: Batch> Profile<B> {
+    #[inline]
+    pub fn new(batch: B, name: &'static str) -> Self {
+        Profile { batch, name }
+    }
+}
+
+impl<B: Batch> Batch for Profile<B> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        if stats::is_profiling() {
+            let batch = &mut self.batch;
+            stats::sample(self.name, || batch.next())
+        } else {
+            self.batch.next()
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use super::{Batch, Disposition};
 use crate::packets::Packet;
+use crate::stats;
 use crate::Result;
 
 /// A batch that replaces each packet of the batch with another packet.
@@ -60,7 +61,10 @@ where
                             self.slot.replace(orig);
                             Disposition::Act(new)
                         }
-                        Err(e) => Disposition::Abort(e),
+                        Err(e) => {
+                            stats::record_failure(orig.mbuf().port_id(), &e);
+                            Disposition::Abort(e)
+                        }
                     }
                 })
             })
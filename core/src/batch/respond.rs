@@ -0,0 +1,59 @@
+use super::{Batch, Disposition, PacketTx};
+use crate::packets::Packet;
+use crate::{Mbuf, Result};
+
+/// Types that answer certain packets on behalf of the appliance, such as
+/// ping or neighbor discovery.
+pub trait Responder {
+    /// Returns whether the packet is one the responder answers.
+    fn matches(&self, mbuf: &Mbuf) -> bool;
+
+    /// Turns the matched packet into the reply.
+    fn reply(&self, mbuf: Mbuf) -> Result<Mbuf>;
+}
+
+/// A batch that answers the packets matched by the `Responder` and
+/// transmits the replies through the specified `PacketTx`.
+///
+/// The matched packets are consumed, and the rest pass through unchanged.
+pub struct Respond<B: Batch, R: Responder, Tx: PacketTx> {
+    batch: B,
+    responder: R,
+    tx: Tx,
+}
+
+impl<B: Batch, R: Responder, Tx: PacketTx> Respond<B, R, Tx> {
+    #[inline]
+    pub fn new(batch: B, responder: R, tx: Tx) -> Self {
+        Respond {
+            batch,
+            responder,
+            tx,
+        }
+    }
+}
+
+impl<B: Batch, R: Responder, Tx: PacketTx> Batch for Respond<B, R, Tx> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        self.batch.next().map(|disp| match disp {
+            Disposition::Act(pkt) if self.responder.matches(pkt.mbuf()) => {
+                match self.responder.reply(pkt.reset()) {
+                    Ok(reply) => {
+                        self.tx.transmit(vec![reply]);
+                        Disposition::Emit
+                    }
+                    Err(e) => Disposition::Abort(e),
+                }
+            }
+            _ => disp,
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use super::{Batch, Disposition};
+use crate::net::MacAddr;
+use crate::packets::Packet;
+use crate::stats::{self, DropReason};
+
+/// A batch that rewrites the MAC addresses of the packets to forward them
+/// to their next hop.
+///
+/// The source is set to the MAC address of the egress port, and the
+/// destination to the address the lookup resolves the next hop to. Both
+/// addresses are written in one copy at the start of the buffer, so the
+/// packets can be of any type and nothing is parsed.
+pub struct RewriteEthernet<B: Batch, F>
+where
+    F: FnMut(&B::Item) -> Option<MacAddr>,
+{
+    batch: B,
+    smac: MacAddr,
+    lookup: F,
+}
+
+impl<B: Batch, F> RewriteEthernet<B, F>
+where
+    F: FnMut(&B::Item) -> Option<MacAddr>,
+{
+    #[inline]
+    pub fn new(batch: B, smac: MacAddr, lookup: F) -> Self {
+        RewriteEthernet {
+            batch,
+            smac,
+            lookup,
+        }
+    }
+}
+
+impl<B: Batch, F> Batch for RewriteEthernet<B, F>
+where
+    B::Item: Packet,
+    F: FnMut(&B::Item) -> Option<MacAddr>,
+{
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let smac = self.smac;
+        let lookup = &mut self.lookup;
+        self.batch.next().map(|disp| {
+            disp.map(|mut pkt| match lookup(&pkt) {
+                Some(dmac) => {
+                    // the destination is followed by the source in the header.
+                    if pkt.mbuf_mut().write_data_slice(0, &[dmac, smac]).is_ok() {
+                        Disposition::Act(pkt)
+                    } else {
+                        stats::record_drop(DropReason::Malformed);
+                        Disposition::Drop(pkt.reset())
+                    }
+                }
+                None => {
+                    stats::record_drop(DropReason::Unresolved);
+                    Disposition::Drop(pkt.reset())
+                }
+            })
+        })
+    }
+}
@@ -6,11 +6,37 @@
 //!
 //! `PacketTx` implemented for `KniTxQueue`.
 //!
+//! Implemented for the `Ring` halves, `RingRx` and `RingTx`.
+//!
+//! `PacketRx` implemented for `ThrottledRx`.
+//!
+//! `PacketTx` implemented for `ReorderTx`.
+//!
+//! `PacketTx` implemented for `PcapTx`.
+//!
+//! `PacketTx` implemented for `PcapngTx`.
+//!
+//! `PacketTx` implemented for `TxBuffer`.
+//!
+//! `PacketTx` implemented for `CheckedTx`.
+//!
+//! `PacketTx` implemented for `WfqTx`.
+//!
+//! `PacketTx` implemented for `HtbTx`.
+//!
+//! `PacketTx` implemented for `NetemTx`.
+//!
+//! Implemented for `WorkerQueue`.
+//!
+//! Implemented for `FailoverPair`.
+//!
 //! Implemented for the MPSC channel so it can be used as a batch source
 //! mostly in tests.
 
-use super::{PacketRx, PacketTx};
-use crate::{KniRx, KniTxQueue, Mbuf, PortQueue};
+use super::{CheckedTx, HtbTx, NetemTx, PacketRx, PacketTx, PcapTx, PcapngTx, TxBuffer, WfqTx};
+use crate::dpdk::{FailoverPair, ReorderTx, RingRx, RingTx, ThrottledRx};
+use crate::{KniRx, KniTxQueue, Mbuf, PortQueue, WorkerQueue};
+use std::io::Write;
 use std::iter;
 use std::sync::mpsc::{Receiver, Sender};
 
@@ -38,6 +64,96 @@ impl PacketTx for KniTxQueue {
     }
 }
 
+impl PacketRx for RingRx {
+    fn receive(&mut self) -> Vec<Mbuf> {
+        RingRx::dequeue(self)
+    }
+}
+
+impl PacketTx for RingTx {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        RingTx::enqueue(self, packets)
+    }
+}
+
+impl PacketRx for ThrottledRx {
+    fn receive(&mut self) -> Vec<Mbuf> {
+        ThrottledRx::receive(self)
+    }
+}
+
+impl<Tx: PacketTx> PacketTx for ReorderTx<Tx> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        ReorderTx::transmit(self, packets)
+    }
+}
+
+impl<W: Write> PacketTx for PcapTx<W> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        PcapTx::transmit(self, packets)
+    }
+}
+
+impl<W: Write> PacketTx for PcapngTx<W> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        PcapngTx::transmit(self, packets)
+    }
+}
+
+impl<Tx: PacketTx> PacketTx for TxBuffer<Tx> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        TxBuffer::transmit(self, packets)
+    }
+}
+
+impl<Tx: PacketTx> PacketTx for CheckedTx<Tx> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        CheckedTx::transmit(self, packets)
+    }
+}
+
+impl<Tx: PacketTx> PacketTx for WfqTx<Tx> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        WfqTx::transmit(self, packets)
+    }
+}
+
+impl<Tx: PacketTx> PacketTx for HtbTx<Tx> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        HtbTx::transmit(self, packets)
+    }
+}
+
+impl<Tx: PacketTx> PacketTx for NetemTx<Tx> {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        NetemTx::transmit(self, packets)
+    }
+}
+
+impl PacketRx for WorkerQueue {
+    fn receive(&mut self) -> Vec<Mbuf> {
+        WorkerQueue::receive(self)
+    }
+}
+
+impl PacketTx for WorkerQueue {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        WorkerQueue::transmit(self, packets)
+    }
+}
+
+impl PacketRx for FailoverPair {
+    fn receive(&mut self) -> Vec<Mbuf> {
+        FailoverPair::receive(self)
+    }
+}
+
+impl PacketTx for FailoverPair {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        FailoverPair::transmit(self, packets)
+    }
+}
+
 impl PacketRx for Receiver<Mbuf> {
     fn receive(&mut self) -> Vec<Mbuf> {
         iter::from_fn(|| self.try_recv().ok()).collect::<Vec<_>>()
@@ -0,0 +1,227 @@
+use super::Pipeline;
+use crate::dpdk::tsc;
+use crate::stats::{register_pipeline, PipelineCounters};
+use futures::{future, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio_executor::current_thread;
+
+/// The default cycle budget of a pipeline per round.
+const DEFAULT_BUDGET: u64 = 100_000;
+
+/// The default number of consecutive rounds a pipeline can be skipped.
+const DEFAULT_MAX_SKIPS: usize = 4;
+
+struct Scheduled {
+    pipeline: Box<dyn Pipeline>,
+    // cycles the pipeline is allowed to consume, negative when it is in
+    // debt from overrunning its budget.
+    credit: i64,
+    skipped: usize,
+    counters: Arc<PipelineCounters>,
+}
+
+/// Runs multiple pipelines on one core with fair round-robin scheduling.
+///
+/// Pipelines spawned separately onto the same core run in whatever order
+/// the executor polls them, so a pipeline that is expensive per batch
+/// takes a larger share of the core. The scheduler instead runs the
+/// pipelines in rounds and charges each run against a per-round cycle
+/// budget. A pipeline that overruns its budget carries the debt over and
+/// is skipped for the following rounds until the debt is paid back, but
+/// never for more than `max_skips` consecutive rounds, so a busy pipeline
+/// cannot starve.
+///
+/// The cycles consumed by each pipeline are exposed through
+/// `stats::pipeline_stats`.
+///
+/// # Example
+///
+/// ```
+/// Runtime::build(config)?
+///     .add_pipeline_to_core(1, |qs| {
+///         Scheduler::new()
+///             .add("eth0", batch::splice(qs["eth0"].clone(), qs["eth1"].clone()))
+///             .add("eth1", batch::splice(qs["eth1"].clone(), qs["eth0"].clone()))
+///     })?
+///     .execute()
+/// ```
+pub struct Scheduler {
+    pipelines: Vec<Scheduled>,
+    budget: u64,
+    max_skips: usize,
+    // the pipeline that goes first in the next round.
+    next: usize,
+}
+
+impl Scheduler {
+    /// Creates a new scheduler with no pipelines.
+    pub fn new() -> Self {
+        Scheduler {
+            pipelines: vec![],
+            budget: DEFAULT_BUDGET,
+            max_skips: DEFAULT_MAX_SKIPS,
+            next: 0,
+        }
+    }
+
+    /// Sets the cycle budget of each pipeline per round.
+    pub fn budget(mut self, cycles: u64) -> Self {
+        self.budget = cycles;
+        self
+    }
+
+    /// Sets the maximum number of consecutive rounds a pipeline can be
+    /// skipped for being over budget.
+    pub fn max_skips(mut self, rounds: usize) -> Self {
+        self.max_skips = rounds;
+        self
+    }
+
+    /// Adds a pipeline to the scheduler.
+    ///
+    /// `name` identifies the pipeline in the stats.
+    pub fn add<P: Pipeline + 'static>(mut self, name: &str, pipeline: P) -> Self {
+        self.pipelines.push(Scheduled {
+            pipeline: Box::new(pipeline),
+            credit: 0,
+            skipped: 0,
+            counters: register_pipeline(name),
+        });
+        self
+    }
+
+    /// Runs one round of the pipelines.
+    fn run(&mut self) {
+        let len = self.pipelines.len();
+        if len == 0 {
+            return;
+        }
+
+        let budget = self.budget as i64;
+        // the debt is capped so an overrun is paid back within the
+        // starvation limit.
+        let max_debt = -budget * self.max_skips as i64;
+
+        for i in 0..len {
+            let scheduled = &mut self.pipelines[(self.next + i) % len];
+
+            // unused credit does not accumulate across rounds.
+            scheduled.credit = (scheduled.credit + budget).min(budget);
+
+            if scheduled.credit > 0 || scheduled.skipped >= self.max_skips {
+                let start = tsc();
+                scheduled.pipeline.run_once();
+                let cycles = tsc().wrapping_sub(start);
+
+                scheduled.credit = (scheduled.credit - cycles as i64).max(max_debt);
+                scheduled.skipped = 0;
+                scheduled.counters.record_run(cycles);
+            } else {
+                scheduled.skipped += 1;
+                scheduled.counters.record_skip();
+            }
+        }
+
+        // rotates who goes first so no pipeline always sees the
+        // freshest queues.
+        self.next = (self.next + 1) % len;
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+/// Each time the future is polled, it runs one round of the pipelines
+/// before returning the `Poll::Pending` status and yields.
+impl Future for Scheduler {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().run();
+
+        let waker = cx.waker().clone();
+        current_thread::spawn(future::lazy(|_| waker.wake()));
+
+        Poll::Pending
+    }
+}
+
+impl Pipeline for Scheduler {
+    fn run_once(&mut self) {
+        self.run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::pipeline_stats;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // a pipeline that busy-spins for `cost` cycles each run.
+    struct Spin {
+        cost: u64,
+        runs: Rc<Cell<usize>>,
+    }
+
+    impl Future for Spin {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Self::Output> {
+            Poll::Pending
+        }
+    }
+
+    impl Pipeline for Spin {
+        fn run_once(&mut self) {
+            let start = tsc();
+            while tsc().wrapping_sub(start) < self.cost {}
+            self.runs.set(self.runs.get() + 1);
+        }
+    }
+
+    #[test]
+    fn skip_pipeline_over_budget() {
+        let light = Rc::new(Cell::new(0));
+        let heavy = Rc::new(Cell::new(0));
+
+        let mut scheduler = Scheduler::new()
+            .budget(1_000_000)
+            .max_skips(3)
+            .add(
+                "schedule_test_light",
+                Spin {
+                    cost: 0,
+                    runs: light.clone(),
+                },
+            )
+            .add(
+                "schedule_test_heavy",
+                Spin {
+                    cost: 5_000_000,
+                    runs: heavy.clone(),
+                },
+            );
+
+        for _ in 0..12 {
+            scheduler.run_once();
+        }
+
+        // the heavy pipeline is skipped while it pays back its debt, but
+        // runs at least once every `max_skips + 1` rounds.
+        assert!(heavy.get() < light.get());
+        assert!(heavy.get() >= 3);
+
+        let stats = pipeline_stats();
+        let heavy_stats = stats["schedule_test_heavy"];
+        assert_eq!(heavy.get() as u64, heavy_stats.runs);
+        assert_eq!(12, heavy_stats.runs + heavy_stats.skips);
+        assert!(heavy_stats.cycles_per_run() >= 5_000_000);
+    }
+}
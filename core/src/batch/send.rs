@@ -1,5 +1,7 @@
 use super::{Batch, Disposition, PacketTx, Pipeline};
+use crate::dpdk::tsc;
 use crate::packets::Packet;
+use crate::stats::record_poll;
 use crate::Mbuf;
 use futures::{future, Future};
 use std::pin::Pin;
@@ -19,14 +21,18 @@ impl<B: Batch, Tx: PacketTx> Send<B, Tx> {
     }
 
     fn run(&mut self) {
+        let start = tsc();
+
         // let's get a new batch
         self.batch.replenish();
 
         let mut transmit_q = Vec::with_capacity(64);
         let mut drop_q = Vec::with_capacity(64);
+        let mut busy = false;
 
         // consume the whole batch to completion
         while let Some(disp) = self.batch.next() {
+            busy = true;
             match disp {
                 Disposition::Act(packet) => transmit_q.push(packet.reset()),
                 Disposition::Drop(mbuf) => drop_q.push(mbuf),
@@ -42,6 +48,8 @@ impl<B: Batch, Tx: PacketTx> Send<B, Tx> {
         if !drop_q.is_empty() {
             Mbuf::free_bulk(drop_q);
         }
+
+        record_poll(tsc().wrapping_sub(start), busy);
     }
 }
 
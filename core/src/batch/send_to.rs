@@ -0,0 +1,104 @@
+use super::{Batch, Disposition, PacketTx, Pipeline};
+use crate::dpdk::tsc;
+use crate::packets::Packet;
+use crate::stats::{self, record_poll, DropReason};
+use crate::Mbuf;
+use futures::{future, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_executor::current_thread;
+
+/// Turns the batch pipeline into an executable task that transmits each
+/// packet through the egress chosen for it.
+///
+/// Like `Send`, `SendTo` marks the end of the batch pipeline. The packets
+/// are still transmitted in batch, one batch per egress.
+pub struct SendTo<B: Batch, Tx: PacketTx, F>
+where
+    F: FnMut(&B::Item) -> Option<usize>,
+{
+    batch: B,
+    txs: Vec<Tx>,
+    select: F,
+}
+
+impl<B: Batch, Tx: PacketTx, F> SendTo<B, Tx, F>
+where
+    F: FnMut(&B::Item) -> Option<usize>,
+{
+    /// Creates a new `SendTo` across the egresses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `txs` is empty.
+    #[inline]
+    pub fn new(batch: B, txs: Vec<Tx>, select: F) -> Self {
+        assert!(!txs.is_empty(), "at least one egress is required.");
+        SendTo { batch, txs, select }
+    }
+
+    fn run(&mut self) {
+        let start = tsc();
+
+        self.batch.replenish();
+
+        let mut transmit_qs = self.txs.iter().map(|_| vec![]).collect::<Vec<_>>();
+        let mut drop_q = Vec::with_capacity(64);
+        let mut busy = false;
+
+        while let Some(disp) = self.batch.next() {
+            busy = true;
+            match disp {
+                Disposition::Act(packet) => match (self.select)(&packet) {
+                    Some(index) if index < transmit_qs.len() => {
+                        transmit_qs[index].push(packet.reset())
+                    }
+                    _ => {
+                        stats::record_drop(DropReason::NoRoute);
+                        drop_q.push(packet.reset());
+                    }
+                },
+                Disposition::Drop(mbuf) => drop_q.push(mbuf),
+                // nothing to do for abort and emit.
+                _ => (),
+            }
+        }
+
+        for (tx, transmit_q) in self.txs.iter_mut().zip(transmit_qs) {
+            if !transmit_q.is_empty() {
+                tx.transmit(transmit_q);
+            }
+        }
+
+        if !drop_q.is_empty() {
+            Mbuf::free_bulk(drop_q);
+        }
+
+        record_poll(tsc().wrapping_sub(start), busy);
+    }
+}
+
+impl<B: Batch + Unpin, Tx: PacketTx + Unpin, F> Future for SendTo<B, Tx, F>
+where
+    F: FnMut(&B::Item) -> Option<usize> + Unpin,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().run();
+
+        let waker = cx.waker().clone();
+        current_thread::spawn(future::lazy(|_| waker.wake()));
+
+        Poll::Pending
+    }
+}
+
+impl<B: Batch + Unpin, Tx: PacketTx + Unpin, F> Pipeline for SendTo<B, Tx, F>
+where
+    F: FnMut(&B::Item) -> Option<usize> + Unpin,
+{
+    fn run_once(&mut self) {
+        self.run()
+    }
+}
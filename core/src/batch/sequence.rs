@@ -0,0 +1,35 @@
+use super::{Batch, Disposition};
+use crate::packets::Packet;
+
+/// A batch that stamps the packets with consecutive sequence numbers.
+pub struct Sequence<B: Batch> {
+    batch: B,
+    next: u32,
+}
+
+impl<B: Batch> Sequence<B> {
+    #[inline]
+    pub fn new(batch: B) -> Self {
+        Sequence { batch, next: 0 }
+    }
+}
+
+impl<B: Batch> Batch for Sequence<B> {
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        self.batch.next().map(|disp| {
+            disp.map(|mut pkt| {
+                pkt.mbuf_mut().set_seqn(self.next);
+                self.next = self.next.wrapping_add(1);
+                Disposition::Act(pkt)
+            })
+        })
+    }
+}
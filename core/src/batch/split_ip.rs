@@ -0,0 +1,78 @@
+use super::{Batch, Bridge, GroupBy, GroupByBatchBuilder};
+use crate::packets::{EtherTypes, Packet, RawPacket};
+use std::collections::HashMap;
+
+/// The branch of `split_ip` a packet goes down.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IpBranch {
+    Ipv4,
+    Ipv6,
+    Arp,
+    /// Any other ether type.
+    Other,
+}
+
+impl IpBranch {
+    /// Returns the branch of the packet, by the ether type past the VLAN
+    /// tags.
+    ///
+    /// The frame is read from the start of the buffer, so the packet can
+    /// be of any type.
+    #[inline]
+    pub fn of<T: Packet>(packet: &T) -> IpBranch {
+        match RawPacket::classify(packet.mbuf()).ether_type() {
+            EtherTypes::Ipv4 => IpBranch::Ipv4,
+            EtherTypes::Ipv6 => IpBranch::Ipv6,
+            EtherTypes::Arp => IpBranch::Arp,
+            _ => IpBranch::Other,
+        }
+    }
+}
+
+/// A batch that splits the underlying batch into IPv4, IPv6, ARP and other
+/// sub batches, created by `split_ip`.
+pub type SplitIp<B> = GroupBy<B, IpBranch, fn(&<B as Batch>::Item) -> IpBranch>;
+
+/// Boxes the builder of a branch for the composer of `GroupBy`.
+fn builder<T: Packet, F, B>(build: F) -> Box<GroupByBatchBuilder<T>>
+where
+    F: Fn(Bridge<T>) -> B + 'static,
+    B: Batch<Item = T> + 'static,
+{
+    Box::new(move |bridge| Box::new(build(bridge)))
+}
+
+#[inline]
+pub(crate) fn split_ip<B, F4, F6, FA, FO, B4, B6, BA, BO>(
+    batch: B,
+    ipv4: F4,
+    ipv6: F6,
+    arp: FA,
+    other: FO,
+) -> SplitIp<B>
+where
+    B: Batch,
+    B::Item: Packet,
+    F4: Fn(Bridge<B::Item>) -> B4 + 'static,
+    F6: Fn(Bridge<B::Item>) -> B6 + 'static,
+    FA: Fn(Bridge<B::Item>) -> BA + 'static,
+    FO: Fn(Bridge<B::Item>) -> BO + 'static,
+    B4: Batch<Item = B::Item> + 'static,
+    B6: Batch<Item = B::Item> + 'static,
+    BA: Batch<Item = B::Item> + 'static,
+    BO: Batch<Item = B::Item> + 'static,
+{
+    let selector: fn(&B::Item) -> IpBranch = IpBranch::of;
+
+    GroupBy::new(
+        batch,
+        selector,
+        |groups: &mut HashMap<Option<IpBranch>, Box<GroupByBatchBuilder<B::Item>>>| {
+            groups.insert(Some(IpBranch::Ipv4), builder(ipv4));
+            groups.insert(Some(IpBranch::Ipv6), builder(ipv6));
+            groups.insert(Some(IpBranch::Arp), builder(arp));
+            // the other branch is the catch all.
+            groups.insert(None, builder(other));
+        },
+    )
+}
@@ -0,0 +1,69 @@
+use super::{Batch, Disposition};
+use crate::net::PrefixTags;
+use crate::packets::ip::IpPacket;
+use crate::packets::Packet;
+use crate::SharedReader;
+
+/// The address of the packet to tag by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TagBy {
+    Src,
+    Dst,
+}
+
+/// A batch that tags the packets with the label of their address.
+///
+/// The tag of the longest prefix matching the address is set as the mark
+/// of the packet metadata. The mark of the packets that match no prefix
+/// is left as is. The prefix set is refreshed once per batch, so a reload
+/// applies from the next batch on.
+pub struct TagPrefix<B: Batch>
+where
+    B::Item: IpPacket,
+{
+    batch: B,
+    tags: SharedReader<PrefixTags>,
+    by: TagBy,
+}
+
+impl<B: Batch> TagPrefix<B>
+where
+    B::Item: IpPacket,
+{
+    #[inline]
+    pub fn new(batch: B, tags: SharedReader<PrefixTags>, by: TagBy) -> Self {
+        TagPrefix { batch, tags, by }
+    }
+}
+
+impl<B: Batch> Batch for TagPrefix<B>
+where
+    B::Item: IpPacket,
+{
+    type Item = B::Item;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+        self.tags.refresh();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let tags = self.tags.get();
+        let by = self.by;
+        self.batch.next().map(|disp| {
+            disp.map(|mut pkt| {
+                let addr = match by {
+                    TagBy::Src => pkt.src(),
+                    TagBy::Dst => pkt.dst(),
+                };
+                if let Some(tag) = tags.lookup(addr) {
+                    let meta = pkt.mbuf().meta().with_mark(tag);
+                    pkt.mbuf_mut().set_meta(meta);
+                }
+                Disposition::Act(pkt)
+            })
+        })
+    }
+}
@@ -0,0 +1,180 @@
+use super::PacketTx;
+use crate::Mbuf;
+use futures::{future, StreamExt};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_executor::current_thread;
+use tokio_timer::Interval;
+
+struct Inner<Tx: PacketTx> {
+    tx: Tx,
+    packets: Vec<Mbuf>,
+    capacity: usize,
+    // whether the packets were already buffered at the last tick.
+    stale: bool,
+}
+
+impl<Tx: PacketTx> Inner<Tx> {
+    fn flush(&mut self) {
+        if !self.packets.is_empty() {
+            let packets = std::mem::replace(&mut self.packets, Vec::with_capacity(self.capacity));
+            self.tx.transmit(packets);
+        }
+        self.stale = false;
+    }
+
+    fn tick(&mut self) {
+        if self.stale {
+            self.flush();
+        } else {
+            self.stale = !self.packets.is_empty();
+        }
+    }
+}
+
+impl<Tx: PacketTx> Drop for Inner<Tx> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// A transmit that accumulates the packets on the core, and passes them
+/// on to the underlying transmit in bursts, like `rte_eth_tx_buffer`.
+///
+/// Operators that emit one packet at a time, such as `emit` or a
+/// responder, cost a full transmit call per packet. Buffering them turns
+/// the sporadic sends into full bursts. The buffer is flushed when it
+/// holds `capacity` packets, when `flush` is called, or by a timer set
+/// with `flush_every` while the core is idle, so a slow trickle of packets
+/// is not held back forever. The rest of the packets are flushed when the
+/// last clone of the buffer is dropped.
+///
+/// The clones share the same buffer, and the buffer cannot leave the
+/// core it was created on.
+///
+/// # Example
+///
+/// ```
+/// runtime.add_pipeline_to_port("eth1", |q| {
+///     let replies = TxBuffer::new(q.clone(), 32);
+///     replies.flush_every(Duration::from_micros(100));
+///
+///     Poll::new(q.clone())
+///         .respond(EchoResponder::new(addrs.clone()), replies)
+///         .send(q)
+/// })?;
+/// ```
+pub struct TxBuffer<Tx: PacketTx> {
+    inner: Rc<RefCell<Inner<Tx>>>,
+}
+
+impl<Tx: PacketTx> TxBuffer<Tx> {
+    /// Creates a new buffer of `capacity` packets in front of `tx`.
+    pub fn new(tx: Tx, capacity: usize) -> Self {
+        TxBuffer {
+            inner: Rc::new(RefCell::new(Inner {
+                tx,
+                packets: Vec::with_capacity(capacity),
+                capacity,
+                stale: false,
+            })),
+        }
+    }
+
+    /// Returns the number of packets buffered.
+    pub fn len(&self) -> usize {
+        self.inner.borrow().packets.len()
+    }
+
+    /// Returns whether no packet is buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Buffers the packets, and passes them all on once the buffer is
+    /// full.
+    pub(crate) fn transmit(&self, packets: Vec<Mbuf>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.packets.extend(packets);
+        if inner.packets.len() >= inner.capacity {
+            inner.flush();
+        }
+    }
+
+    /// Passes the buffered packets on to the underlying transmit.
+    pub fn flush(&self) {
+        self.inner.borrow_mut().flush();
+    }
+
+    /// Spawns a timer on the current core that flushes the packets that
+    /// have been buffered for at least `interval`.
+    ///
+    /// The packets wait at most twice the interval. Must be called on the
+    /// core the pipeline runs on, for example in the pipeline installer.
+    pub fn flush_every(&self, interval: Duration)
+    where
+        Tx: 'static,
+    {
+        let inner = Rc::downgrade(&self.inner);
+        let alive = inner.clone();
+
+        // stops once the last clone of the buffer is dropped.
+        let fut = Interval::new_interval(interval)
+            .take_while(move |_| future::ready(alive.upgrade().is_some()))
+            .for_each(move |_| {
+                if let Some(inner) = inner.upgrade() {
+                    inner.borrow_mut().tick();
+                }
+                future::ready(())
+            });
+        current_thread::spawn(fut);
+    }
+}
+
+impl<Tx: PacketTx> Clone for TxBuffer<Tx> {
+    fn clone(&self) -> Self {
+        TxBuffer {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[nb2::test]
+    fn flush_when_full() {
+        let (tx, rx) = mpsc::channel();
+        let buffer = TxBuffer::new(tx, 4);
+
+        buffer.transmit((0..3).map(|_| Mbuf::new().unwrap()).collect());
+        assert!(rx.try_recv().is_err());
+        assert_eq!(3, buffer.len());
+
+        buffer.transmit((0..2).map(|_| Mbuf::new().unwrap()).collect());
+        assert_eq!(5, rx.try_iter().count());
+        assert!(buffer.is_empty());
+
+        // the rest goes out when the buffer is dropped.
+        buffer.transmit(vec![Mbuf::new().unwrap()]);
+        drop(buffer);
+        assert_eq!(1, rx.try_iter().count());
+    }
+
+    #[nb2::test]
+    fn flush_stale_packets_on_tick() {
+        let (tx, rx) = mpsc::channel();
+        let buffer = TxBuffer::new(tx, 32);
+
+        buffer.transmit(vec![Mbuf::new().unwrap()]);
+        buffer.inner.borrow_mut().tick();
+        assert!(rx.try_recv().is_err());
+
+        // buffered since the last tick.
+        buffer.inner.borrow_mut().tick();
+        assert_eq!(1, rx.try_iter().count());
+    }
+}
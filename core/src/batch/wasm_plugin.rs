@@ -0,0 +1,81 @@
+use super::{Batch, Disposition};
+use crate::packets::Packet;
+use crate::stats::{self, DropReason};
+use crate::wasm::{self, Plugin, PluginSlot, Verdict};
+use crate::{warn, Mbuf};
+use std::sync::Arc;
+
+/// A batch that runs the frames of the underlying batch through the
+/// WebAssembly plugin loaded under a name.
+///
+/// While no plugin is loaded, the frames pass through. The frames the
+/// plugin drops are recorded as `DropReason::Filtered`, and the frames the
+/// plugin fails on are aborted.
+pub struct WasmPlugin<B: Batch<Item = Mbuf>> {
+    batch: B,
+    slot: Arc<PluginSlot>,
+    // the instance of the plugin on this core, as of the version.
+    version: usize,
+    plugin: Option<Plugin>,
+}
+
+impl<B: Batch<Item = Mbuf>> WasmPlugin<B> {
+    #[inline]
+    pub fn new(batch: B, name: &str) -> Self {
+        let slot = wasm::plugin_slot(name);
+        let version = slot.version();
+        let plugin = instantiate(&slot);
+
+        WasmPlugin {
+            batch,
+            slot,
+            version,
+            plugin,
+        }
+    }
+}
+
+fn instantiate(slot: &PluginSlot) -> Option<Plugin> {
+    slot.bytes().and_then(|bytes| match Plugin::new(&bytes) {
+        Ok(plugin) => Some(plugin),
+        Err(err) => {
+            // the module is checked when loaded, so it's unexpected.
+            warn!(message = "failed to instantiate plugin.", ?err);
+            None
+        }
+    })
+}
+
+impl<B: Batch<Item = Mbuf>> Batch for WasmPlugin<B> {
+    type Item = Mbuf;
+
+    #[inline]
+    fn replenish(&mut self) {
+        self.batch.replenish();
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Disposition<Self::Item>> {
+        let version = self.slot.version();
+        if version != self.version {
+            self.version = version;
+            self.plugin = instantiate(&self.slot);
+        }
+
+        let plugin = &mut self.plugin;
+
+        self.batch.next().map(|disp| {
+            disp.map(|mut mbuf| match plugin {
+                Some(plugin) => match plugin.process(&mut mbuf) {
+                    Ok(Verdict::Drop) => {
+                        stats::record_drop(DropReason::Filtered);
+                        Disposition::Drop(mbuf.reset())
+                    }
+                    Ok(_) => Disposition::Act(mbuf),
+                    Err(err) => Disposition::Abort(err),
+                },
+                None => Disposition::Act(mbuf),
+            })
+        })
+    }
+}
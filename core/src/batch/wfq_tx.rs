@@ -0,0 +1,388 @@
+use super::PacketTx;
+use crate::dpdk::RX_BURST_MAX;
+use crate::stats::{self, DropReason};
+use crate::{Color, Mbuf, PacketMeta};
+use futures::{future, StreamExt};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+use tokio_executor::current_thread;
+use tokio_timer::Interval;
+
+/// The default quantum of a class, a full ethernet frame.
+const DEFAULT_QUANTUM: usize = 1514;
+
+/// The default number of packets a class queue holds.
+const DEFAULT_CAPACITY: usize = 1024;
+
+struct Class {
+    queue: VecDeque<Mbuf>,
+    capacity: usize,
+    // the bytes the class may send per round.
+    quantum: usize,
+    // the bytes the class has left to send in the current round.
+    deficit: usize,
+    priority: bool,
+}
+
+impl Class {
+    fn new() -> Self {
+        Class {
+            queue: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+            quantum: DEFAULT_QUANTUM,
+            deficit: 0,
+            priority: false,
+        }
+    }
+}
+
+/// Queues the packet unless the queue is full. A full queue makes room for
+/// a packet that is not red by dropping the last red packet queued.
+///
+/// Returns `false` if a packet was dropped, the offered one or a red one.
+pub(super) fn enqueue_by_color(queue: &mut VecDeque<Mbuf>, capacity: usize, mbuf: Mbuf) -> bool {
+    if queue.len() < capacity {
+        queue.push_back(mbuf);
+        return true;
+    }
+
+    if mbuf.meta().color != Color::Red {
+        if let Some(index) = queue.iter().rposition(|m| m.meta().color == Color::Red) {
+            queue.remove(index);
+            queue.push_back(mbuf);
+        }
+    }
+
+    false
+}
+
+struct Inner<Tx: PacketTx> {
+    tx: Tx,
+    classes: Vec<Class>,
+    burst: usize,
+    // the class the round robin is on, and whether it got its quantum.
+    current: usize,
+    credited: bool,
+}
+
+impl<Tx: PacketTx> Inner<Tx> {
+    fn enqueue(&mut self, packets: Vec<Mbuf>) {
+        let mut dropped = 0;
+
+        for mbuf in packets {
+            let class = &mut self.classes[mbuf.meta().traffic_class as usize];
+            if !enqueue_by_color(&mut class.queue, class.capacity, mbuf) {
+                dropped += 1;
+            }
+        }
+
+        if dropped > 0 {
+            stats::record_drops(DropReason::QueueFull, dropped);
+        }
+    }
+
+    /// Takes up to `max` packets, the priority classes first, then the
+    /// other classes by deficit round robin.
+    fn dequeue(&mut self, max: usize) -> Vec<Mbuf> {
+        let mut packets = Vec::with_capacity(max);
+
+        for class in self.classes.iter_mut().filter(|c| c.priority) {
+            while packets.len() < max {
+                match class.queue.pop_front() {
+                    Some(mbuf) => packets.push(mbuf),
+                    None => break,
+                }
+            }
+        }
+
+        while packets.len() < max
+            && self
+                .classes
+                .iter()
+                .any(|c| !c.priority && !c.queue.is_empty())
+        {
+            let class = &mut self.classes[self.current];
+
+            if !class.priority && !class.queue.is_empty() {
+                if !self.credited {
+                    class.deficit += class.quantum;
+                    self.credited = true;
+                }
+
+                while packets.len() < max {
+                    match class.queue.front() {
+                        Some(mbuf) if mbuf.data_len() <= class.deficit => {
+                            class.deficit -= mbuf.data_len();
+                            packets.push(class.queue.pop_front().unwrap());
+                        }
+                        _ => break,
+                    }
+                }
+
+                if class.queue.is_empty() {
+                    // an idle class does not save up credit.
+                    class.deficit = 0;
+                } else if packets.len() == max {
+                    // the class resumes its turn on the next dequeue.
+                    break;
+                }
+            }
+
+            self.current = (self.current + 1) % self.classes.len();
+            self.credited = false;
+        }
+
+        packets
+    }
+
+    fn drain(&mut self) {
+        let packets = self.dequeue(self.burst);
+        if !packets.is_empty() {
+            self.tx.transmit(packets);
+        }
+    }
+}
+
+/// A transmit that schedules the packets across the traffic classes with
+/// weighted fair queuing, in front of the underlying transmit.
+///
+/// Each traffic class of `PacketMeta` has its own queue. Every time
+/// packets are transmitted, they are queued by their class, and up to a
+/// burst of packets is passed on to the underlying transmit. The classes
+/// marked as priority are served first, for the latency sensitive
+/// traffic. The other classes share the rest of the burst by deficit
+/// round robin, each sending up to its quantum of bytes per round, so the
+/// bandwidth is split in proportion to the quanta regardless of the packet
+/// sizes.
+///
+/// The queues build up when more packets are offered than a burst per
+/// transmit, for example when several pipelines of the core send through
+/// the same scheduler. When the queue of its class is full, a packet that
+/// is not red takes the place of the last red packet queued, and is
+/// dropped otherwise. Either drop is recorded as `DropReason::QueueFull`.
+/// The queues are drained while the core is idle by a timer set with
+/// `drain_every`.
+///
+/// The clones share the same queues, and the scheduler cannot leave the
+/// core it was created on.
+///
+/// # Example
+///
+/// ```
+/// runtime.add_pipeline_to_port("eth1", |q| {
+///     // voice in class 5 goes first, bulk in class 1 gets a quarter of
+///     // the bandwidth of the default class.
+///     let wfq = WfqTx::new(q.clone())
+///         .with_priority(5)
+///         .with_quantum(0, 4 * 1514)
+///         .with_quantum(1, 1514);
+///     wfq.drain_every(Duration::from_micros(50));
+///
+///     Poll::new(q.clone()).map(classify).send(wfq)
+/// })?;
+/// ```
+pub struct WfqTx<Tx: PacketTx> {
+    inner: Rc<RefCell<Inner<Tx>>>,
+}
+
+impl<Tx: PacketTx> WfqTx<Tx> {
+    /// Creates a new scheduler in front of `tx`.
+    ///
+    /// All the classes start with the same quantum of `1514` bytes and
+    /// queues of `1024` packets, and up to `32` packets are passed on at a
+    /// time.
+    pub fn new(tx: Tx) -> Self {
+        WfqTx {
+            inner: Rc::new(RefCell::new(Inner {
+                tx,
+                classes: (0..PacketMeta::CLASSES).map(|_| Class::new()).collect(),
+                burst: RX_BURST_MAX,
+                current: 0,
+                credited: false,
+            })),
+        }
+    }
+
+    fn class_mut<F: FnOnce(&mut Class)>(self, traffic_class: u8, f: F) -> Self {
+        assert!((traffic_class as usize) < PacketMeta::CLASSES);
+        f(&mut self.inner.borrow_mut().classes[traffic_class as usize]);
+        self
+    }
+
+    /// Sets the bytes the class sends per round, its weight relative to
+    /// the other classes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `traffic_class` is not a valid class or `quantum` is `0`.
+    pub fn with_quantum(self, traffic_class: u8, quantum: usize) -> Self {
+        assert!(quantum > 0);
+        self.class_mut(traffic_class, |class| class.quantum = quantum)
+    }
+
+    /// Sets the number of packets the queue of the class holds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `traffic_class` is not a valid class.
+    pub fn with_capacity(self, traffic_class: u8, capacity: usize) -> Self {
+        self.class_mut(traffic_class, |class| class.capacity = capacity)
+    }
+
+    /// Serves the class ahead of the weighted classes. The priority
+    /// classes are served in the order of their class number.
+    ///
+    /// A busy priority class starves the others, so it should be reserved
+    /// for traffic that is known to be light, or rate limited upstream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `traffic_class` is not a valid class.
+    pub fn with_priority(self, traffic_class: u8) -> Self {
+        self.class_mut(traffic_class, |class| class.priority = true)
+    }
+
+    /// Sets the maximum number of packets passed on at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `burst` is `0`.
+    pub fn with_burst(self, burst: usize) -> Self {
+        assert!(burst > 0);
+        self.inner.borrow_mut().burst = burst;
+        self
+    }
+
+    /// Returns the number of packets queued in the class.
+    pub fn queue_len(&self, traffic_class: u8) -> usize {
+        self.inner.borrow().classes[traffic_class as usize]
+            .queue
+            .len()
+    }
+
+    /// Queues the packets by their class, and passes a burst on.
+    pub(crate) fn transmit(&self, packets: Vec<Mbuf>) {
+        let mut inner = self.inner.borrow_mut();
+        inner.enqueue(packets);
+        inner.drain();
+    }
+
+    /// Spawns a timer on the current core that passes a burst on every
+    /// `interval`, until the queues are empty.
+    ///
+    /// Must be called on the core the pipeline runs on, for example in the
+    /// pipeline installer.
+    pub fn drain_every(&self, interval: Duration)
+    where
+        Tx: 'static,
+    {
+        let inner = Rc::downgrade(&self.inner);
+        let alive = inner.clone();
+
+        // stops once the last clone of the scheduler is dropped.
+        let fut = Interval::new_interval(interval)
+            .take_while(move |_| future::ready(alive.upgrade().is_some()))
+            .for_each(move |_| {
+                if let Some(inner) = inner.upgrade() {
+                    inner.borrow_mut().drain();
+                }
+                future::ready(())
+            });
+        current_thread::spawn(fut);
+    }
+}
+
+impl<Tx: PacketTx> Clone for WfqTx<Tx> {
+    fn clone(&self) -> Self {
+        WfqTx {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn packets(traffic_class: u8, count: usize) -> Vec<Mbuf> {
+        (0..count)
+            .map(|_| {
+                let mut mbuf = Mbuf::new().unwrap();
+                mbuf.extend(0, 100).unwrap();
+                mbuf.set_meta(PacketMeta::default().with_traffic_class(traffic_class));
+                mbuf
+            })
+            .collect()
+    }
+
+    #[nb2::test]
+    fn share_by_quantum() {
+        let (tx, rx) = mpsc::channel();
+        let wfq = WfqTx::new(tx)
+            .with_quantum(0, 300)
+            .with_quantum(1, 100)
+            .with_burst(8);
+
+        let mut offered = packets(0, 20);
+        offered.extend(packets(1, 20));
+        wfq.transmit(offered);
+
+        // 3 packets of class 0 for every packet of class 1.
+        let sent = rx
+            .try_iter()
+            .map(|mbuf| mbuf.meta().traffic_class)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 0, 0, 1, 0, 0, 0, 1], sent);
+        assert_eq!(14, wfq.queue_len(0));
+        assert_eq!(18, wfq.queue_len(1));
+    }
+
+    #[nb2::test]
+    fn priority_goes_first() {
+        let (tx, rx) = mpsc::channel();
+        let wfq = WfqTx::new(tx).with_priority(5).with_burst(4);
+
+        let mut offered = packets(0, 4);
+        offered.extend(packets(5, 2));
+        wfq.transmit(offered);
+
+        let sent = rx
+            .try_iter()
+            .map(|mbuf| mbuf.meta().traffic_class)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![5, 5, 0, 0], sent);
+    }
+
+    #[nb2::test]
+    fn drop_when_class_full() {
+        let (tx, _rx) = mpsc::channel();
+        let wfq = WfqTx::new(tx).with_capacity(2, 3).with_burst(1);
+
+        wfq.transmit(packets(2, 5));
+        // one sent, two left queued, two dropped.
+        assert_eq!(2, wfq.queue_len(2));
+    }
+
+    #[nb2::test]
+    fn drop_red_first() {
+        let (tx, rx) = mpsc::channel();
+        let wfq = WfqTx::new(tx).with_capacity(0, 2).with_burst(1);
+
+        // the queue is full at the third packet, which takes the place of
+        // the red one.
+        let mut offered = packets(0, 3);
+        offered[1].set_meta(PacketMeta::default().with_color(Color::Red));
+        wfq.transmit(offered);
+        wfq.transmit(vec![]);
+        assert_eq!(0, wfq.queue_len(0));
+
+        let colors = rx
+            .try_iter()
+            .map(|mbuf| mbuf.meta().color)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Color::Green, Color::Green], colors);
+    }
+}
@@ -0,0 +1,327 @@
+//! A port-to-port forwarder for measuring the baseline performance of the
+//! platform, comparable to the `l2fwd` and `l3fwd` DPDK sample apps.
+//!
+//! The ports in the config file are paired up in order, the first with
+//! the second, the third with the fourth and so on. Packets received on a
+//! port are forwarded out its peer. A port without a peer forwards back
+//! out itself. When the peer has no queue on the core a packet is received
+//! on, the packet crosses over through a ring to the first core of the
+//! peer, like a cross-socket `l2fwd` setup.
+//!
+//! ```
+//! home$ nb2-bench -f config.toml -m l3 -r 10.0.0.0/8=02:00:00:00:00:01
+//! ```
+
+use clap::{clap_app, value_t};
+use nb2::batch::{self, Either, PacketTx, Scheduler};
+use nb2::net::{Ipv4Cidr, MacAddr, RouteTable};
+use nb2::packets::ip::v4::Ipv4;
+use nb2::packets::{Ethernet, Packet};
+use nb2::settings::load_config_file;
+use nb2::stats::{core_stats, drop_stats, DropReason};
+use nb2::{Batch, Mbuf, Poll, PortQueue, Result, Ring, RingRx, RingTx, Runtime, SocketId};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::Level;
+use tracing_subscriber::fmt;
+
+/// Forwarding mode.
+#[derive(Clone, Copy, Debug)]
+enum Mode {
+    /// Forwards the packets untouched.
+    Io,
+    /// Rewrites the MAC addresses like `l2fwd`.
+    L2,
+    /// Routes the IPv4 packets like `l3fwd`.
+    L3,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "io" => Ok(Mode::Io),
+            "l2" => Ok(Mode::L2),
+            "l3" => Ok(Mode::L3),
+            _ => Err(format!("unknown mode '{}'.", s)),
+        }
+    }
+}
+
+/// Packets received, by port.
+type Counters = Arc<HashMap<String, AtomicU64>>;
+
+/// Parses a `cidr=mac` route.
+fn parse_route(s: &str) -> Result<(Ipv4Cidr, MacAddr)> {
+    let mut parts = s.splitn(2, '=');
+    let cidr = parts.next().unwrap_or_default().parse::<Ipv4Cidr>()?;
+    let mac = parts.next().unwrap_or_default().parse::<MacAddr>()?;
+    Ok((cidr, mac))
+}
+
+/// The capacity of the rings between the cores.
+const RING_SIZE: usize = 4096;
+
+/// Installs the forwarding from port `rx` to port `tx`, either the queue
+/// of the port or the ring to its core.
+fn forward<Tx: PacketTx + Unpin + 'static>(
+    scheduler: Scheduler,
+    mode: Mode,
+    (rx_name, rx): (&str, PortQueue),
+    (tx_index, src, tx): (u8, MacAddr, Tx),
+    routes: Arc<RouteTable<MacAddr>>,
+    counters: Counters,
+) -> Scheduler {
+    let name = rx_name.to_owned();
+    let count = move |_: &Mbuf| -> Result<()> {
+        counters[&name].fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    };
+
+    match mode {
+        Mode::Io => scheduler.add(rx_name, Poll::new(rx).for_each(count).send(tx)),
+        Mode::L2 => {
+            // same as l2fwd, the destination is a locally administered
+            // address numbered after the tx port.
+            let dst = MacAddr::new(0x02, 0, 0, 0, 0, tx_index);
+            let pipeline = Poll::new(rx)
+                .for_each(count)
+                .map(move |packet| {
+                    let mut ethernet = packet.parse::<Ethernet>()?;
+                    ethernet.set_src(src);
+                    ethernet.set_dst(dst);
+                    Ok(ethernet)
+                })
+                .send(tx);
+            scheduler.add(rx_name, pipeline)
+        }
+        Mode::L3 => {
+            let pipeline = Poll::new(rx)
+                .for_each(count)
+                .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+                .filter_with(DropReason::TtlExceeded, |v4| v4.ttl() > 1)
+                .filter_map(move |mut v4| match routes.lookup(IpAddr::V4(v4.dst())) {
+                    Some(&dst) => {
+                        let ttl = v4.ttl();
+                        v4.set_ttl(ttl - 1);
+                        v4.envelope_mut().set_src(src);
+                        v4.envelope_mut().set_dst(dst);
+                        v4.cascade();
+                        Ok(Either::Keep(v4))
+                    }
+                    None => Ok(Either::Reject(v4.reset(), DropReason::NoRoute)),
+                })
+                .send(tx);
+            scheduler.add(rx_name, pipeline)
+        }
+    }
+}
+
+/// Prints the packet rates and the core utilization every `interval`.
+fn report(counters: Counters, interval: Duration) {
+    let mut last = counters
+        .keys()
+        .map(|name| (name.clone(), 0))
+        .collect::<HashMap<_, _>>();
+    let mut last_drops = 0;
+    let mut last_cores = core_stats();
+    let mut then = Instant::now();
+
+    loop {
+        thread::sleep(interval);
+        let elapsed = then.elapsed().as_secs_f64();
+        then = Instant::now();
+
+        let mut names = counters.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let total = counters[name].load(Ordering::Relaxed);
+            let delta = total - last[name];
+            last.insert(name.clone(), total);
+            println!(
+                "{:>8}: {:>12.0} pps {:>16} total",
+                name,
+                delta as f64 / elapsed,
+                total
+            );
+        }
+
+        let drops = drop_stats();
+        let total = drops.total();
+        println!(
+            "{:>8}: {:>12.0} pps {:>16} total",
+            "dropped",
+            (total - last_drops) as f64 / elapsed,
+            total
+        );
+        last_drops = total;
+
+        let cores = core_stats();
+        let mut core_ids = cores.keys().collect::<Vec<_>>();
+        core_ids.sort();
+        for core_id in core_ids {
+            let stats = match last_cores.get(core_id) {
+                Some(last) => cores[core_id].since(last),
+                None => cores[core_id],
+            };
+            println!(
+                "{:>8}: {:>11.1}% busy",
+                format!("{:?}", core_id),
+                stats.utilization()
+            );
+        }
+        last_cores = cores;
+    }
+}
+
+fn main() -> Result<()> {
+    let matches = clap_app!(nb2_bench =>
+        (version: "0.1.0")
+        (about: "Forwards packets between pairs of ports and reports the rates.")
+        (@arg file: -f --file +required +takes_value "configuration file")
+        (@arg mode: -m --mode +takes_value possible_values(&["io", "l2", "l3"]) default_value("io")
+            "forwarding mode")
+        (@arg route: -r --route +takes_value +multiple
+            "l3 route as <cidr>=<next hop mac>")
+        (@arg interval: -i --interval +takes_value default_value("1")
+            "seconds between the stats reports")
+        (@arg verbose: -v --verbose "prints the runtime logs")
+    )
+    .get_matches();
+
+    let level = if matches.is_present("verbose") {
+        Level::DEBUG
+    } else {
+        Level::WARN
+    };
+    let subscriber = fmt::Subscriber::builder().with_max_level(level).finish();
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let mode = value_t!(matches, "mode", Mode).unwrap_or_else(|e| e.exit());
+    let interval = value_t!(matches, "interval", u64).unwrap_or_else(|e| e.exit());
+
+    let mut routes = RouteTable::new();
+    for route in matches.values_of("route").into_iter().flatten() {
+        let (cidr, mac) = parse_route(route)?;
+        routes.insert_v4(cidr, mac);
+    }
+    let routes = Arc::new(routes);
+
+    let config = load_config_file(matches.value_of("file").unwrap())?;
+
+    // pairs up the ports in order.
+    let names = config
+        .ports
+        .iter()
+        .map(|p| p.name.clone())
+        .collect::<Vec<_>>();
+    let peers = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let peer = if i % 2 == 0 { i + 1 } else { i - 1 };
+            let peer = if peer < names.len() { peer } else { i };
+            (name.clone(), (peer as u8, names[peer].clone()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let counters: Counters = Arc::new(
+        names
+            .iter()
+            .map(|name| (name.clone(), AtomicU64::new(0)))
+            .collect(),
+    );
+
+    let port_cores = config
+        .ports
+        .iter()
+        .map(|p| (p.name.clone(), p.cores.iter().map(|c| c.id()).collect()))
+        .collect::<HashMap<String, Vec<usize>>>();
+    let devices = config
+        .ports
+        .iter()
+        .map(|p| (p.device.clone(), p.name.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut cores = port_cores.values().flatten().cloned().collect::<Vec<_>>();
+    cores.sort();
+    cores.dedup();
+
+    let mut runtime = Runtime::build(config)?;
+
+    // the mac addresses of the peers not on the core of the rx port.
+    let macs: HashMap<String, MacAddr> = runtime
+        .inventory()
+        .into_iter()
+        .filter_map(|info| {
+            devices
+                .get(&info.device)
+                .map(|name| (name.clone(), info.mac))
+        })
+        .collect();
+
+    // a ring for every port with a core the peer is not on, polled on the
+    // first core of the peer.
+    let mut rings: HashMap<String, (RingTx, RingRx)> = HashMap::new();
+    for (name, (_, peer)) in peers.iter() {
+        let peer_cores = &port_cores[peer];
+        if port_cores[name].iter().any(|c| !peer_cores.contains(c)) {
+            let ring = Ring::new(RING_SIZE, SocketId::ANY)?.split();
+            rings.insert(name.clone(), ring);
+        }
+    }
+
+    // the pipelines of all the ports on a core share the core fairly.
+    for core in cores {
+        let peers = peers.clone();
+        let port_cores = port_cores.clone();
+        let macs = macs.clone();
+        let rings = rings.clone();
+        let routes = routes.clone();
+        let counters = counters.clone();
+
+        runtime.add_pipeline_to_core(core, move |qs: HashMap<String, PortQueue>| {
+            let mut scheduler = Scheduler::new();
+            for (name, q) in qs.iter() {
+                let (peer_index, peer) = &peers[name];
+                let rx = (name.as_str(), q.clone());
+
+                scheduler = match (qs.get(peer), rings.get(name)) {
+                    (Some(peer_q), _) => {
+                        let tx = (*peer_index, peer_q.mac_addr(), peer_q.clone());
+                        forward(scheduler, mode, rx, tx, routes.clone(), counters.clone())
+                    }
+                    (None, Some((ring_tx, _))) => {
+                        let mac = macs.get(peer).cloned().unwrap_or_default();
+                        let tx = (*peer_index, mac, ring_tx.clone());
+                        forward(scheduler, mode, rx, tx, routes.clone(), counters.clone())
+                    }
+                    (None, None) => scheduler,
+                };
+            }
+
+            // the packets crossing over from the cores of the peers.
+            for (name, (_, ring_rx)) in rings.iter() {
+                let (_, peer) = &peers[name];
+                if port_cores[peer].first() == Some(&core) {
+                    let name = format!("{}-ring", name);
+                    scheduler =
+                        scheduler.add(&name, batch::splice(ring_rx.clone(), qs[peer].clone()));
+                }
+            }
+
+            scheduler
+        })?;
+    }
+
+    let interval = Duration::from_secs(interval);
+    thread::spawn(move || report(counters, interval));
+
+    runtime.execute()
+}
@@ -0,0 +1,143 @@
+//! Prints the packets received on the configured ports, decoded with the
+//! parsers of the crate.
+//!
+//! ```
+//! home$ nb2-dump -f config.toml -p eth0
+//! ```
+
+use clap::clap_app;
+use colored::*;
+use nb2::packets::icmp::v6::{Icmpv6Message, Icmpv6Parse};
+use nb2::packets::ip::v4::Ipv4;
+use nb2::packets::ip::v6::{Ipv6, Ipv6Packet};
+use nb2::packets::ip::{IpPacket, ProtocolNumbers};
+use nb2::packets::{EtherTypes, Ethernet, Gtpc, L2tpv3, Packet, Pppoe, Tcp, Udp, L2TP_PORT};
+use nb2::settings::load_config_file;
+use nb2::{Batch, Mbuf, Pipeline, Poll, PortQueue, Result, Runtime};
+use std::fmt;
+use tracing::Level;
+use tracing_subscriber::fmt as subscriber;
+
+fn print_layer<T: fmt::Debug>(depth: usize, layer: &T, color: Color) {
+    println!(
+        "{}{}",
+        "  ".repeat(depth),
+        format!("{:?}", layer).color(color)
+    );
+}
+
+fn dump_ethernet(port: &str, packet: &Mbuf) -> Result<()> {
+    println!(
+        "{}",
+        format!("{} {} bytes {}", port, packet.data_len(), packet.layers()).bold()
+    );
+
+    let ethernet = packet.peek::<Ethernet>()?;
+    print_layer(1, &*ethernet, Color::Magenta);
+
+    match ethernet.ether_type() {
+        EtherTypes::Ipv4 => dump_ip(2, &*ethernet.peek::<Ipv4>()?),
+        EtherTypes::Ipv6 => dump_v6(2, &*ethernet.peek::<Ipv6>()?),
+        EtherTypes::PppoeDiscovery | EtherTypes::PppoeSession => {
+            // the IP parsers only take Ethernet as the envelope, so the
+            // dump stops at the PPP protocol.
+            let pppoe = ethernet.peek::<Pppoe>()?;
+            print_layer(2, &*pppoe, Color::White);
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn dump_v6<E: Ipv6Packet + fmt::Debug>(depth: usize, ip: &E) -> Result<()> {
+    if ip.next_proto() == ProtocolNumbers::Icmpv6 {
+        print_layer(depth, ip, Color::Cyan);
+        match ip.clone().parse_icmpv6()? {
+            Icmpv6Message::EchoRequest(p) => print_layer(depth + 1, &p, Color::Green),
+            Icmpv6Message::EchoReply(p) => print_layer(depth + 1, &p, Color::Green),
+            Icmpv6Message::NeighborAdvertisement(p) => print_layer(depth + 1, &p, Color::Green),
+            Icmpv6Message::NeighborSolicitation(p) => print_layer(depth + 1, &p, Color::Green),
+            Icmpv6Message::RouterAdvertisement(p) => print_layer(depth + 1, &p, Color::Green),
+            Icmpv6Message::RouterSolicitation(p) => print_layer(depth + 1, &p, Color::Green),
+            Icmpv6Message::Undefined(p) => print_layer(depth + 1, &p, Color::Green),
+        }
+        Ok(())
+    } else {
+        dump_ip(depth, ip)
+    }
+}
+
+fn dump_ip<E: IpPacket + fmt::Debug>(depth: usize, ip: &E) -> Result<()> {
+    print_layer(depth, ip, Color::Yellow);
+
+    match ip.next_proto() {
+        ProtocolNumbers::Tcp => {
+            let tcp = ip.peek::<Tcp<E>>()?;
+            print_layer(depth + 1, &*tcp, Color::Green);
+            print_layer(depth + 1, &tcp.flow(), Color::BrightBlue);
+        }
+        ProtocolNumbers::Udp => {
+            let udp = ip.peek::<Udp<E>>()?;
+            print_layer(depth + 1, &*udp, Color::Green);
+            print_layer(depth + 1, &udp.flow(), Color::BrightBlue);
+
+            if Gtpc::is_gtpc(&udp) {
+                let gtpc = udp.peek::<Gtpc<E>>()?;
+                print_layer(depth + 2, &*gtpc, Color::White);
+            } else if udp.dst_port() == L2TP_PORT || udp.src_port() == L2TP_PORT {
+                let l2tp = udp.peek::<L2tpv3<Udp<E>>>()?;
+                print_layer(depth + 2, &*l2tp, Color::White);
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn install(port: String, q: PortQueue) -> impl Pipeline {
+    Poll::new(q.clone())
+        .for_each(move |packet| {
+            if let Err(err) = dump_ethernet(&port, packet) {
+                println!("  {}", format!("{}", err).red());
+            }
+            Ok(())
+        })
+        // a dump only observes, nothing is sent back out.
+        .filter(|_| false)
+        .send(q)
+}
+
+fn main() -> Result<()> {
+    let matches = clap_app!(nb2_dump =>
+        (version: "0.1.0")
+        (about: "Prints the decoded packets received on the ports.")
+        (@arg file: -f --file +required +takes_value "configuration file")
+        (@arg port: -p --port +takes_value +multiple "port to dump, defaults to all ports")
+        (@arg verbose: -v --verbose "prints the runtime logs")
+    )
+    .get_matches();
+
+    let level = if matches.is_present("verbose") {
+        Level::DEBUG
+    } else {
+        Level::WARN
+    };
+    let subscriber = subscriber::Subscriber::builder()
+        .with_max_level(level)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let config = load_config_file(matches.value_of("file").unwrap())?;
+    let ports = match matches.values_of("port") {
+        Some(ports) => ports.map(str::to_owned).collect::<Vec<_>>(),
+        None => config.ports.iter().map(|p| p.name.clone()).collect(),
+    };
+
+    let mut runtime = Runtime::build(config)?;
+    for port in ports {
+        let name = port.clone();
+        runtime.add_pipeline_to_port(&port, move |q| install(name.clone(), q))?;
+    }
+    runtime.execute()
+}
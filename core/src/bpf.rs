@@ -0,0 +1,591 @@
+//! Classic BPF filters loaded at run time.
+//!
+//! A pipeline declares named hook points with `Batch::bpf_hook`. A filter
+//! compiled to classic BPF, for example with `tcpdump -ddd`, can then be
+//! attached to a hook point, replaced or detached while the pipelines
+//! run, so a diagnostic filter is added without recompiling the
+//! application. The hook points are global, and the control channel of
+//! the application attaches the filters with `attach` and `detach`, or a
+//! controller does with the `AttachBpf` and `DetachBpf` calls of the gRPC
+//! service.
+//!
+//! The programs are checked when loaded, so a bad program is rejected
+//! before it gets to a pipeline, and run by an interpreter. A packet is
+//! kept when the program returns a non-zero value, as with socket filters.
+//!
+//! # Example
+//!
+//! ```
+//! // the output of `tcpdump -ddd udp port 53`.
+//! let program = bpf::Program::parse(&text)?;
+//! bpf::attach("ingress", program);
+//! ```
+
+use crate::ensure;
+use crate::Result;
+use failure::Fail;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// The maximum number of instructions of a program.
+pub const BPF_MAXINSNS: usize = 4096;
+
+/// The number of words of the scratch memory.
+const BPF_MEMWORDS: usize = 16;
+
+// instruction classes.
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+
+// load sizes.
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+
+// load modes.
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+const BPF_MSH: u16 = 0xa0;
+
+// alu operations and jump conditions.
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+// operand sources.
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// Error indicating a program is invalid.
+#[derive(Debug, Fail)]
+pub enum BpfError {
+    /// The program has no instructions, or more than `BPF_MAXINSNS`.
+    #[fail(display = "Program has {} instructions.", _0)]
+    BadLength(usize),
+
+    /// The opcode of the instruction is unknown.
+    #[fail(display = "Unknown opcode {:#x} at {}.", _1, _0)]
+    BadOpcode(usize, u16),
+
+    /// The jump of the instruction lands past the end of the program.
+    #[fail(display = "Jump out of the program at {}.", _0)]
+    BadJump(usize),
+
+    /// The instruction accesses a scratch memory word out of bounds.
+    #[fail(display = "Scratch memory index out of bounds at {}.", _0)]
+    BadMemoryIndex(usize),
+
+    /// The instruction divides by a constant zero.
+    #[fail(display = "Division by zero at {}.", _0)]
+    DivisionByZero(usize),
+
+    /// The last instruction is not a return.
+    #[fail(display = "Program does not end with a return.")]
+    NoReturn,
+
+    /// The text of the program is malformed.
+    #[fail(display = "Malformed program at line {}.", _0)]
+    Malformed(usize),
+}
+
+/// A classic BPF instruction, as `struct sock_filter`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Instruction {
+    /// Creates a new instruction.
+    pub fn new(code: u16, jt: u8, jf: u8, k: u32) -> Self {
+        Instruction { code, jt, jf, k }
+    }
+}
+
+/// A checked classic BPF program.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Program {
+    insns: Vec<Instruction>,
+}
+
+impl Program {
+    /// Creates a new program from the instructions.
+    ///
+    /// # Errors
+    ///
+    /// If the program could read or jump out of bounds, could divide by a
+    /// constant zero, or does not end with a return, `BpfError` is
+    /// returned.
+    pub fn new(insns: Vec<Instruction>) -> Result<Self> {
+        ensure!(
+            !insns.is_empty() && insns.len() <= BPF_MAXINSNS,
+            BpfError::BadLength(insns.len())
+        );
+
+        for (pc, insn) in insns.iter().enumerate() {
+            check(pc, insn, insns.len())?;
+        }
+
+        ensure!(
+            insns[insns.len() - 1].code & 0x07 == BPF_RET,
+            BpfError::NoReturn
+        );
+
+        Ok(Program { insns })
+    }
+
+    /// Parses a program in the decimal format of `tcpdump -ddd`, the
+    /// number of instructions on the first line, and an instruction of
+    /// `code jt jf k` per line after.
+    ///
+    /// # Errors
+    ///
+    /// If the text is malformed, or the program is invalid, `BpfError` is
+    /// returned.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let count = match lines.next() {
+            Some((lineno, line)) => line
+                .parse::<usize>()
+                .map_err(|_| BpfError::Malformed(lineno))?,
+            None => return Err(BpfError::BadLength(0).into()),
+        };
+
+        let insns = lines
+            .map(|(lineno, line)| {
+                let fields = line
+                    .split_whitespace()
+                    .map(|field| field.parse::<u32>())
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(|_| BpfError::Malformed(lineno))?;
+                ensure!(
+                    fields.len() == 4
+                        && fields[0] <= 0xffff
+                        && fields[1] <= 0xff
+                        && fields[2] <= 0xff,
+                    BpfError::Malformed(lineno)
+                );
+                Ok(Instruction::new(
+                    fields[0] as u16,
+                    fields[1] as u8,
+                    fields[2] as u8,
+                    fields[3],
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        ensure!(insns.len() == count, BpfError::BadLength(insns.len()));
+        Program::new(insns)
+    }
+
+    /// Loads a program from an array of `struct sock_filter`, in the
+    /// native byte order, as compiled by `pcap_compile`.
+    ///
+    /// # Errors
+    ///
+    /// If the length is not a multiple of 8 bytes, or the program is
+    /// invalid, `BpfError` is returned.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        ensure!(bytes.len() % 8 == 0, BpfError::BadLength(bytes.len() / 8));
+
+        let insns = bytes
+            .chunks(8)
+            .map(|b| {
+                Instruction::new(
+                    u16::from_ne_bytes([b[0], b[1]]),
+                    b[2],
+                    b[3],
+                    u32::from_ne_bytes([b[4], b[5], b[6], b[7]]),
+                )
+            })
+            .collect();
+
+        Program::new(insns)
+    }
+
+    /// Returns the instructions of the program.
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.insns
+    }
+
+    /// Runs the program on the packet, and returns its result, the number
+    /// of bytes to keep. `0` rejects the packet.
+    ///
+    /// A load past the end of the packet rejects the packet.
+    #[allow(clippy::cognitive_complexity)]
+    pub fn run(&self, packet: &[u8]) -> u32 {
+        let mut a = 0u32;
+        let mut x = 0u32;
+        let mut mem = [0u32; BPF_MEMWORDS];
+        let mut pc = 0;
+
+        macro_rules! load {
+            ($offset:expr, $size:expr) => {
+                match load(packet, $offset, $size) {
+                    Some(value) => value,
+                    None => return 0,
+                }
+            };
+        }
+
+        loop {
+            let insn = self.insns[pc];
+            let k = insn.k;
+            pc += 1;
+
+            match insn.code & 0x07 {
+                BPF_LD => {
+                    a = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_ABS => load!(k as usize, insn.code & 0x18),
+                        BPF_IND => load!(x.wrapping_add(k) as usize, insn.code & 0x18),
+                        BPF_MEM => mem[k as usize],
+                        _ => packet.len() as u32,
+                    }
+                }
+                BPF_LDX => {
+                    x = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_MEM => mem[k as usize],
+                        BPF_LEN => packet.len() as u32,
+                        _ => (load!(k as usize, BPF_B) & 0xf) << 2,
+                    }
+                }
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                BPF_ALU => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    a = match insn.code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(operand),
+                        BPF_SUB => a.wrapping_sub(operand),
+                        BPF_MUL => a.wrapping_mul(operand),
+                        BPF_DIV if operand == 0 => return 0,
+                        BPF_DIV => a / operand,
+                        BPF_MOD if operand == 0 => return 0,
+                        BPF_MOD => a % operand,
+                        BPF_OR => a | operand,
+                        BPF_AND => a & operand,
+                        BPF_LSH => a.checked_shl(operand).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(operand).unwrap_or(0),
+                        BPF_NEG => a.wrapping_neg(),
+                        _ => a ^ operand,
+                    }
+                }
+                BPF_JMP => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    let taken = match insn.code & 0xf0 {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == operand,
+                        BPF_JGT => a > operand,
+                        BPF_JGE => a >= operand,
+                        _ => a & operand != 0,
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                }
+                BPF_RET => {
+                    return match insn.code & 0x18 {
+                        BPF_K => k,
+                        BPF_X => x,
+                        _ => a,
+                    }
+                }
+                // the misc class, register transfers.
+                _ => {
+                    if insn.code & 0xf8 == BPF_TXA {
+                        a = x;
+                    } else {
+                        x = a;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns whether the program keeps the packet.
+    #[inline]
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        self.run(packet) != 0
+    }
+}
+
+/// Checks an instruction at `pc` of a program of `len` instructions. The
+/// interpreter relies on the checks to not go out of bounds.
+fn check(pc: usize, insn: &Instruction, len: usize) -> Result<()> {
+    let code = insn.code;
+    let k = insn.k as usize;
+
+    match code & 0x07 {
+        BPF_LD | BPF_LDX => {
+            let size = code & 0x18;
+            let mode = code & 0xe0;
+            let valid = if code & 0x07 == BPF_LD {
+                match mode {
+                    BPF_ABS | BPF_IND => size != 0x18,
+                    BPF_IMM | BPF_MEM | BPF_LEN => size == BPF_W,
+                    _ => false,
+                }
+            } else {
+                match mode {
+                    BPF_IMM | BPF_MEM | BPF_LEN => size == BPF_W,
+                    BPF_MSH => size == BPF_B,
+                    _ => false,
+                }
+            };
+            ensure!(valid, BpfError::BadOpcode(pc, code));
+            ensure!(
+                mode != BPF_MEM || k < BPF_MEMWORDS,
+                BpfError::BadMemoryIndex(pc)
+            );
+        }
+        BPF_ST | BPF_STX => {
+            ensure!(code & 0xf8 == 0, BpfError::BadOpcode(pc, code));
+            ensure!(k < BPF_MEMWORDS, BpfError::BadMemoryIndex(pc));
+        }
+        BPF_ALU => {
+            let op = code & 0xf0;
+            ensure!(op <= BPF_XOR, BpfError::BadOpcode(pc, code));
+            ensure!(
+                !((op == BPF_DIV || op == BPF_MOD) && code & BPF_X == 0 && k == 0),
+                BpfError::DivisionByZero(pc)
+            );
+        }
+        BPF_JMP => {
+            let op = code & 0xf0;
+            ensure!(op <= BPF_JSET, BpfError::BadOpcode(pc, code));
+            if op == BPF_JA {
+                ensure!(code & BPF_X == 0, BpfError::BadOpcode(pc, code));
+                ensure!(pc + 1 + k < len, BpfError::BadJump(pc));
+            } else {
+                let far = usize::from(insn.jt.max(insn.jf));
+                ensure!(pc + 1 + far < len, BpfError::BadJump(pc));
+            }
+        }
+        BPF_RET => {
+            let src = code & 0x18;
+            ensure!(
+                code & 0xe0 == 0 && (src == BPF_K || src == BPF_X || src == BPF_A),
+                BpfError::BadOpcode(pc, code)
+            );
+        }
+        // the misc class, register transfers.
+        _ => {
+            ensure!(
+                code & 0xf8 == BPF_TAX || code & 0xf8 == BPF_TXA,
+                BpfError::BadOpcode(pc, code)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads a big endian word, half word or byte from the packet.
+#[inline]
+fn load(packet: &[u8], offset: usize, size: u16) -> Option<u32> {
+    let len = match size {
+        BPF_W => 4,
+        BPF_H => 2,
+        _ => 1,
+    };
+
+    packet
+        .get(offset..offset.checked_add(len)?)
+        .map(|bytes| bytes.iter().fold(0u32, |acc, &b| (acc << 8) | u32::from(b)))
+}
+
+/// A named point in a pipeline that filters can be attached to.
+pub(crate) struct HookPoint {
+    // bumped every time the program changes, so the pipelines only take
+    // the lock when there's a new program.
+    version: AtomicUsize,
+    program: Mutex<Option<Arc<Program>>>,
+}
+
+impl HookPoint {
+    /// Returns the version of the program attached.
+    #[inline]
+    pub(crate) fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Returns the program attached.
+    pub(crate) fn program(&self) -> Option<Arc<Program>> {
+        self.program.lock().unwrap().clone()
+    }
+
+    fn set(&self, program: Option<Arc<Program>>) {
+        *self.program.lock().unwrap() = program;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+}
+
+lazy_static! {
+    static ref HOOKS: RwLock<HashMap<String, Arc<HookPoint>>> = RwLock::new(HashMap::new());
+}
+
+/// Returns the hook point of the name, creating it if it is new.
+pub(crate) fn hook_point(name: &str) -> Arc<HookPoint> {
+    if let Some(hook) = HOOKS.read().unwrap().get(name) {
+        return hook.clone();
+    }
+
+    HOOKS
+        .write()
+        .unwrap()
+        .entry(name.to_owned())
+        .or_insert_with(|| {
+            Arc::new(HookPoint {
+                version: AtomicUsize::new(0),
+                program: Mutex::new(None),
+            })
+        })
+        .clone()
+}
+
+/// Attaches the program to the hook point, replacing the program attached
+/// before.
+///
+/// The pipelines pick up the new program with the next packet. The hook
+/// point doesn't need to be declared by a pipeline yet.
+pub fn attach(hook: &str, program: Program) {
+    hook_point(hook).set(Some(Arc::new(program)));
+}
+
+/// Detaches the program from the hook point. The packets pass through
+/// the hook point unfiltered afterwards.
+pub fn detach(hook: &str) {
+    if let Some(hook) = HOOKS.read().unwrap().get(hook) {
+        hook.set(None);
+    }
+}
+
+/// Returns the names of the hook points, and the programs attached.
+pub fn hooks() -> Vec<(String, Option<Arc<Program>>)> {
+    HOOKS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, hook)| (name.clone(), hook.program()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+
+    // tcpdump -ddd ip and udp
+    const IP_AND_UDP: &str = "
+        6
+        40 0 0 12
+        21 0 3 2048
+        48 0 0 23
+        21 0 1 17
+        6 0 0 262144
+        6 0 0 0
+    ";
+
+    #[test]
+    fn parse_and_run_program() {
+        let program = Program::parse(IP_AND_UDP).unwrap();
+        assert_eq!(6, program.instructions().len());
+
+        assert!(program.matches(&UDP_PACKET));
+        assert!(!program.matches(&TCP_PACKET));
+        // a load past the end rejects the packet.
+        assert!(!program.matches(&UDP_PACKET[..20]));
+    }
+
+    #[test]
+    fn load_from_bytes() {
+        let bytes = Program::parse(IP_AND_UDP)
+            .unwrap()
+            .instructions()
+            .iter()
+            .flat_map(|insn| {
+                let mut b = insn.code.to_ne_bytes().to_vec();
+                b.extend_from_slice(&[insn.jt, insn.jf]);
+                b.extend_from_slice(&insn.k.to_ne_bytes());
+                b
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            Program::parse(IP_AND_UDP).unwrap(),
+            Program::from_bytes(&bytes).unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_invalid_programs() {
+        // jumps past the end.
+        assert!(Program::new(vec![Instruction::new(BPF_JMP | BPF_JA, 0, 0, 1)]).is_err());
+        // divides by zero.
+        assert!(Program::new(vec![
+            Instruction::new(BPF_ALU | BPF_DIV | BPF_K, 0, 0, 0),
+            Instruction::new(BPF_RET | BPF_A, 0, 0, 0),
+        ])
+        .is_err());
+        // out of the scratch memory.
+        assert!(Program::new(vec![
+            Instruction::new(BPF_ST, 0, 0, 16),
+            Instruction::new(BPF_RET | BPF_A, 0, 0, 0),
+        ])
+        .is_err());
+        // falls off the end.
+        assert!(Program::new(vec![Instruction::new(BPF_LD | BPF_IMM, 0, 0, 1)]).is_err());
+        assert!(Program::parse("2\n6 0 0 1").is_err());
+    }
+
+    #[test]
+    fn attach_and_detach() {
+        let hook = hook_point("bpf-test");
+        let version = hook.version();
+        assert!(hook.program().is_none());
+
+        attach("bpf-test", Program::parse(IP_AND_UDP).unwrap());
+        assert!(hook.version() > version);
+        assert!(hook.program().is_some());
+
+        detach("bpf-test");
+        assert!(hook.program().is_none());
+    }
+}
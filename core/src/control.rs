@@ -0,0 +1,820 @@
+//! A gRPC service for external control planes.
+//!
+//! A controller, an SDN application for example, programs the dataplane
+//! through the typed messages of `proto/control.proto` instead of a
+//! bespoke socket protocol. The service attaches the ingress ACLs of the
+//! ports, adds and removes routes and static NAT mappings, streams the
+//! counters and returns the events of the journal. It also attaches the
+//! classic BPF filters to the hook points of the pipelines.
+//!
+//! The service also reads and writes the tables registered in
+//! `nb2::tables`, the same way for every table, so a table added by the
+//! application is programmable without a new message.
+//!
+//! The routes and the NAT mappings are held in `Shared` values the
+//! pipelines read, so the service needs nothing from the pipelines beyond
+//! the handles it is given. The service programs them through the tables
+//! `routes` and `nat` it registers, so a route added with `AddRoute` is an
+//! entry of the table `routes`, and the two ways of writing agree. The
+//! service owns the entries of the tables only: a route added by the
+//! controller replaces the route of the same prefix loaded by the
+//! application, and the other routes of the application are left alone.
+//!
+//! The service runs on its own thread, off the cores of the pipelines.
+//!
+//! The feature is enabled with `grpc`.
+//!
+//! # Example
+//!
+//! ```
+//! let routes = Shared::new(RouteTable::new());
+//! let nat = Shared::new(HashMap::new());
+//!
+//! let mut runtime = Runtime::build(config)?;
+//! ControlService::new()
+//!     .acl("eth1", runtime.acl_handle("eth1")?)
+//!     .routes(routes.clone())
+//!     .nat(nat.clone())
+//!     .serve("127.0.0.1:50051".parse()?)?;
+//!
+//! runtime.add_pipeline_to_port("eth1", move |q| install(q, &routes, &nat))?
+//!     .execute()
+//! ```
+
+use crate::bpf::{self, Instruction, Program};
+use crate::journal::{self, Entry, Event};
+use crate::net::{Acl, AclAction, AclRule, Ipv4Cidr, Ipv6Cidr, RouteTable};
+use crate::packets::ip::ProtocolNumber;
+use crate::shared::Shared;
+use crate::tables::{self, FieldMatch, NatTable, RoutesTable, Table, TableEntry, Update, Value};
+use crate::{info, stats, warn, AclHandle, Result};
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio_timer::Interval;
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status};
+
+/// The messages and the service generated from `proto/control.proto`.
+pub mod proto {
+    tonic::include_proto!("nb2.control");
+}
+
+use self::proto::server::{Control, ControlServer};
+
+/// The control plane service, with the dataplane state it programs.
+///
+/// A request for state the service is not given, such as the ACL of a
+/// port without a handle, fails with `NOT_FOUND`.
+#[derive(Default)]
+pub struct ControlService {
+    acls: HashMap<String, AclHandle>,
+    routes: Option<Arc<Table>>,
+    nat: Option<Arc<Table>>,
+}
+
+impl ControlService {
+    /// Creates a service with no state to program.
+    pub fn new() -> Self {
+        ControlService::default()
+    }
+
+    /// Lets the controller attach the ingress ACL of the port.
+    pub fn acl(mut self, port: &str, handle: AclHandle) -> Self {
+        self.acls.insert(port.to_owned(), handle);
+        self
+    }
+
+    /// Lets the controller program the routes, with the next hop as the
+    /// value of a route. Registers the table `routes` of them.
+    pub fn routes(mut self, routes: Shared<RouteTable<IpAddr>>) -> Self {
+        let table = Table::new("routes", RoutesTable::new(routes));
+        self.routes = Some(tables::register(table));
+        self
+    }
+
+    /// Lets the controller program the static NAT mappings, from the
+    /// internal address to the external one. Registers the table `nat` of
+    /// them.
+    pub fn nat(mut self, nat: Shared<HashMap<IpAddr, IpAddr>>) -> Self {
+        let table = Table::new("nat", NatTable::new(nat));
+        self.nat = Some(tables::register(table));
+        self
+    }
+
+    /// Serves the service on the address, on a new thread.
+    ///
+    /// # Errors
+    ///
+    /// If the thread cannot be spawned, `io::Error` is returned. The
+    /// failures of the server itself, such as the address being in use,
+    /// are logged on the thread.
+    pub fn serve(self, addr: SocketAddr) -> Result<thread::JoinHandle<()>> {
+        let handle = thread::Builder::new()
+            .name("nb2-control".to_owned())
+            .spawn(move || {
+                let mut runtime = match tokio::runtime::Runtime::new() {
+                    Ok(runtime) => runtime,
+                    Err(err) => {
+                        warn!(message = "failed to start control runtime.", ?err);
+                        return;
+                    }
+                };
+
+                let server = Server::builder().serve(addr, ControlServer::new(self));
+                if let Err(err) = runtime.block_on(server) {
+                    warn!(message = "control server failed.", ?err);
+                }
+            })?;
+
+        info!("serving control plane on {}.", addr);
+        Ok(handle)
+    }
+}
+
+fn invalid(message: String) -> Status {
+    Status::new(Code::InvalidArgument, message)
+}
+
+fn not_found(message: String) -> Status {
+    Status::new(Code::NotFound, message)
+}
+
+fn parse_addr(addr: &str) -> std::result::Result<IpAddr, Status> {
+    addr.parse()
+        .map_err(|_| invalid(format!("invalid address '{}'", addr)))
+}
+
+fn acl_action(action: i32, mark: u32) -> std::result::Result<AclAction, Status> {
+    match proto::AclAction::from_i32(action) {
+        Some(proto::AclAction::Permit) => Ok(AclAction::Permit),
+        Some(proto::AclAction::Deny) => Ok(AclAction::Deny),
+        Some(proto::AclAction::Mark) => Ok(AclAction::Mark(mark)),
+        None => Err(invalid(format!("invalid action {}", action))),
+    }
+}
+
+fn port_range(first: u32, last: u32) -> std::result::Result<Option<(u16, u16)>, Status> {
+    if last == 0 {
+        Ok(None)
+    } else if first > 0xffff || last > 0xffff {
+        Err(invalid(format!("invalid port range {}-{}", first, last)))
+    } else {
+        Ok(Some((first as u16, last as u16)))
+    }
+}
+
+/// Converts a rule of the message, checked when the list is compiled.
+fn acl_rule(rule: &proto::AclRule) -> std::result::Result<AclRule, Status> {
+    let mut acl_rule = AclRule::new(acl_action(rule.action, rule.mark)?);
+
+    for (prefix, src) in [(&rule.src, true), (&rule.dst, false)].iter() {
+        if prefix.is_empty() {
+            continue;
+        }
+
+        acl_rule = if let Ok(cidr) = prefix.parse::<Ipv4Cidr>() {
+            if *src {
+                acl_rule.src_v4(cidr)
+            } else {
+                acl_rule.dst_v4(cidr)
+            }
+        } else if let Ok(cidr) = prefix.parse::<Ipv6Cidr>() {
+            if *src {
+                acl_rule.src_v6(cidr)
+            } else {
+                acl_rule.dst_v6(cidr)
+            }
+        } else {
+            return Err(invalid(format!("invalid prefix '{}'", prefix)));
+        };
+    }
+
+    if rule.protocol > 0xff {
+        return Err(invalid(format!("invalid protocol {}", rule.protocol)));
+    } else if rule.protocol != 0 {
+        acl_rule = acl_rule.protocol(ProtocolNumber(rule.protocol as u8));
+    }
+
+    if let Some((first, last)) = port_range(rule.src_port_first, rule.src_port_last)? {
+        acl_rule = acl_rule.src_ports(first, last);
+    }
+    if let Some((first, last)) = port_range(rule.dst_port_first, rule.dst_port_last)? {
+        acl_rule = acl_rule.dst_ports(first, last);
+    }
+
+    Ok(acl_rule)
+}
+
+fn parse_value(value: &str) -> std::result::Result<Value, Status> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("invalid value '{}'", value)))
+}
+
+fn parse_prefix(prefix: &str) -> std::result::Result<(IpAddr, usize), Status> {
+    let mut parts = prefix.splitn(2, '/');
+    match (
+        parts.next().map(str::parse::<IpAddr>),
+        parts.next().map(str::parse::<usize>),
+    ) {
+        (Some(Ok(addr)), Some(Ok(len))) => Ok((addr, len)),
+        _ => Err(invalid(format!("invalid prefix '{}'", prefix))),
+    }
+}
+
+/// Returns the entry of the route, of the table `routes`. Without a next
+/// hop, the entry is only good for a delete.
+fn route_entry(route: &proto::Route, remove: bool) -> std::result::Result<TableEntry, Status> {
+    let (addr, len) = parse_prefix(&route.prefix)?;
+    let next_hop = if remove {
+        addr
+    } else {
+        parse_addr(&route.next_hop)?
+    };
+
+    Ok(TableEntry {
+        matches: vec![("dst".to_owned(), FieldMatch::Lpm(addr, len))],
+        action: "forward".to_owned(),
+        params: vec![Value::Addr(next_hop)],
+        priority: 0,
+    })
+}
+
+/// Returns the entry of the mapping, of the table `nat`. Without an
+/// external address, the entry is only good for a delete.
+fn nat_entry(mapping: &proto::NatMapping, remove: bool) -> std::result::Result<TableEntry, Status> {
+    let internal = parse_addr(&mapping.internal)?;
+    let external = if remove {
+        internal
+    } else {
+        parse_addr(&mapping.external)?
+    };
+
+    Ok(TableEntry {
+        matches: vec![(
+            "internal".to_owned(),
+            FieldMatch::Exact(Value::Addr(internal)),
+        )],
+        action: "translate".to_owned(),
+        params: vec![Value::Addr(external)],
+        priority: 0,
+    })
+}
+
+/// Inserts the entry, or replaces the one of the same matches, the way the
+/// legacy messages replace a route or a mapping.
+fn upsert(table: &Table, entry: TableEntry) -> std::result::Result<(), Status> {
+    let exists = table.entries().iter().any(|e| e.matches == entry.matches);
+    let rule = entry.to_string();
+    let update = if exists {
+        Update::Modify(entry)
+    } else {
+        Update::Insert(entry)
+    };
+    table
+        .write(update)
+        .map_err(|err| invalid(err.to_string()))?;
+
+    // the table records the inserts only.
+    if exists {
+        journal::record_event(Event::RuleAdded {
+            table: table.name().to_owned(),
+            rule,
+        });
+    }
+    Ok(())
+}
+
+/// Deletes the entry of the same matches, if the table has one.
+fn remove(table: &Table, entry: TableEntry) -> std::result::Result<(), Status> {
+    if table.entries().iter().any(|e| e.matches == entry.matches) {
+        table
+            .write(Update::Delete(entry))
+            .map_err(|err| invalid(err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Converts an entry of the message, checked when it is written.
+fn table_entry(entry: &proto::TableEntry) -> std::result::Result<TableEntry, Status> {
+    use self::proto::field_match::Kind;
+
+    let matches = entry
+        .matches
+        .iter()
+        .map(|m| {
+            let field_match = match &m.kind {
+                Some(Kind::Exact(value)) => FieldMatch::Exact(parse_value(value)?),
+                Some(Kind::Lpm(prefix)) => {
+                    let (addr, len) = parse_prefix(prefix)?;
+                    FieldMatch::Lpm(addr, len)
+                }
+                Some(Kind::Range(range)) => FieldMatch::Range(range.low, range.high),
+                None => return Err(invalid(format!("no match for field '{}'", m.field))),
+            };
+            Ok((m.field.clone(), field_match))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let params = entry
+        .params
+        .iter()
+        .map(|param| parse_value(param))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok(TableEntry {
+        matches,
+        action: entry.action.clone(),
+        params,
+        priority: entry.priority,
+    })
+}
+
+fn proto_entry(entry: &TableEntry) -> proto::TableEntry {
+    use self::proto::field_match::Kind;
+
+    proto::TableEntry {
+        matches: entry
+            .matches
+            .iter()
+            .map(|(field, m)| proto::FieldMatch {
+                field: field.clone(),
+                kind: Some(match *m {
+                    FieldMatch::Exact(value) => Kind::Exact(value.to_string()),
+                    FieldMatch::Lpm(addr, len) => Kind::Lpm(format!("{}/{}", addr, len)),
+                    FieldMatch::Range(low, high) => Kind::Range(proto::Range { low, high }),
+                }),
+            })
+            .collect(),
+        action: entry.action.clone(),
+        params: entry.params.iter().map(ToString::to_string).collect(),
+        priority: entry.priority,
+    }
+}
+
+/// Returns a snapshot of the counters.
+fn snapshot() -> proto::Stats {
+    proto::Stats {
+        drops: stats::drop_stats()
+            .iter()
+            .map(|(reason, count)| (reason.to_string(), count))
+            .collect(),
+        acls: stats::acl_stats()
+            .iter()
+            .map(|(port_id, rule, counts)| proto::AclCounters {
+                port_id: u32::from(port_id.raw()),
+                rule: rule.map_or(-1, |rule| rule as i32),
+                permitted: counts.permitted,
+                denied: counts.denied,
+                marked: counts.marked,
+            })
+            .collect(),
+    }
+}
+
+fn bpf_program(
+    program: proto::attach_bpf_request::Program,
+) -> std::result::Result<Program, Status> {
+    use self::proto::attach_bpf_request::Program as Source;
+
+    match program {
+        Source::Text(text) => Program::parse(&text),
+        Source::Instructions(program) => {
+            let insns = program
+                .instructions
+                .iter()
+                .map(|insn| {
+                    if insn.code > 0xffff || insn.jt > 0xff || insn.jf > 0xff {
+                        Err(invalid(format!("invalid instruction {:?}", insn)))
+                    } else {
+                        Ok(Instruction::new(
+                            insn.code as u16,
+                            insn.jt as u8,
+                            insn.jf as u8,
+                            insn.k,
+                        ))
+                    }
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            Program::new(insns)
+        }
+    }
+    .map_err(|err| invalid(err.to_string()))
+}
+
+fn proto_instruction(insn: &Instruction) -> proto::BpfInstruction {
+    proto::BpfInstruction {
+        code: u32::from(insn.code),
+        jt: u32::from(insn.jt),
+        jf: u32::from(insn.jf),
+        k: insn.k,
+    }
+}
+
+fn journal_entry(entry: &Entry) -> proto::JournalEntry {
+    let since_epoch = entry
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    proto::JournalEntry {
+        timestamp_us: since_epoch.as_micros() as u64,
+        event: entry.event.to_string(),
+    }
+}
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    async fn set_acl(
+        &self,
+        request: Request<proto::SetAclRequest>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        let handle = self
+            .acls
+            .get(&request.port)
+            .ok_or_else(|| not_found(format!("no acl for port '{}'", request.port)))?;
+
+        if request.detach {
+            handle.set(None);
+            info!("detached ingress acl of port {}.", request.port);
+            return Ok(Response::new(proto::Empty {}));
+        }
+
+        let rules = request
+            .rules
+            .iter()
+            .map(acl_rule)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let default = acl_action(request.default_action, request.default_mark)?;
+        let acl = Acl::new(&rules, default).map_err(|err| invalid(err.to_string()))?;
+
+        handle.set(Some(acl));
+        journal::record_event(Event::RuleAdded {
+            table: format!("acl {}", request.port),
+            rule: format!("{} rules", rules.len()),
+        });
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn add_route(
+        &self,
+        request: Request<proto::Route>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let routes = self
+            .routes
+            .as_ref()
+            .ok_or_else(|| not_found("no routes".to_owned()))?;
+
+        upsert(routes, route_entry(&request.into_inner(), false)?)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn remove_route(
+        &self,
+        request: Request<proto::Route>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let routes = self
+            .routes
+            .as_ref()
+            .ok_or_else(|| not_found("no routes".to_owned()))?;
+
+        remove(routes, route_entry(&request.into_inner(), true)?)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn add_nat_mapping(
+        &self,
+        request: Request<proto::NatMapping>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let nat = self
+            .nat
+            .as_ref()
+            .ok_or_else(|| not_found("no nat mappings".to_owned()))?;
+
+        upsert(nat, nat_entry(&request.into_inner(), false)?)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn remove_nat_mapping(
+        &self,
+        request: Request<proto::NatMapping>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let nat = self
+            .nat
+            .as_ref()
+            .ok_or_else(|| not_found("no nat mappings".to_owned()))?;
+
+        remove(nat, nat_entry(&request.into_inner(), true)?)?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    type StreamStatsStream =
+        Pin<Box<dyn Stream<Item = std::result::Result<proto::Stats, Status>> + Send + 'static>>;
+
+    async fn stream_stats(
+        &self,
+        request: Request<proto::StatsRequest>,
+    ) -> std::result::Result<Response<Self::StreamStatsStream>, Status> {
+        let interval_ms = request.into_inner().interval_ms;
+        if interval_ms == 0 {
+            return Err(invalid("interval must be positive".to_owned()));
+        }
+
+        let stream = Interval::new_interval(Duration::from_millis(u64::from(interval_ms)))
+            .map(|_| Ok(snapshot()));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn write_table(
+        &self,
+        request: Request<proto::WriteTableRequest>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        let table = tables::table(&request.table).map_err(|err| not_found(err.to_string()))?;
+        let entry = request
+            .entry
+            .as_ref()
+            .ok_or_else(|| invalid("no entry".to_owned()))
+            .and_then(table_entry)?;
+
+        let update = match proto::Update::from_i32(request.update) {
+            Some(proto::Update::Insert) => Update::Insert(entry),
+            Some(proto::Update::Modify) => Update::Modify(entry),
+            Some(proto::Update::Delete) => Update::Delete(entry),
+            None => return Err(invalid(format!("invalid update {}", request.update))),
+        };
+        table
+            .write(update)
+            .map_err(|err| invalid(err.to_string()))?;
+
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn read_table(
+        &self,
+        request: Request<proto::ReadTableRequest>,
+    ) -> std::result::Result<Response<proto::ReadTableResponse>, Status> {
+        let request = request.into_inner();
+        let table = tables::table(&request.table).map_err(|err| not_found(err.to_string()))?;
+
+        let entries = table
+            .counters()
+            .iter()
+            .map(|(entry, packets)| proto::TableEntryCounters {
+                entry: Some(proto_entry(entry)),
+                counted: packets.is_some(),
+                packets: packets.unwrap_or(0),
+            })
+            .collect();
+        Ok(Response::new(proto::ReadTableResponse { entries }))
+    }
+
+    async fn list_tables(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> std::result::Result<Response<proto::TableList>, Status> {
+        Ok(Response::new(proto::TableList {
+            tables: tables::tables(),
+        }))
+    }
+
+    async fn query_journal(
+        &self,
+        request: Request<proto::JournalRequest>,
+    ) -> std::result::Result<Response<proto::JournalResponse>, Status> {
+        let since = UNIX_EPOCH + Duration::from_micros(request.into_inner().since_us);
+        let entries = journal::entries_since(since)
+            .iter()
+            .map(journal_entry)
+            .collect();
+        Ok(Response::new(proto::JournalResponse { entries }))
+    }
+
+    async fn attach_bpf(
+        &self,
+        request: Request<proto::AttachBpfRequest>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        if request.hook.is_empty() {
+            return Err(invalid("no hook point".to_owned()));
+        }
+        let program = request
+            .program
+            .ok_or_else(|| invalid("no program".to_owned()))
+            .and_then(bpf_program)?;
+
+        let len = program.instructions().len();
+        bpf::attach(&request.hook, program);
+        info!("attached bpf filter to hook {}.", request.hook);
+        journal::record_event(Event::RuleAdded {
+            table: format!("bpf {}", request.hook),
+            rule: format!("{} instructions", len),
+        });
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn detach_bpf(
+        &self,
+        request: Request<proto::DetachBpfRequest>,
+    ) -> std::result::Result<Response<proto::Empty>, Status> {
+        let hook = request.into_inner().hook;
+        bpf::detach(&hook);
+        info!("detached bpf filter of hook {}.", hook);
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn list_bpf_hooks(
+        &self,
+        _request: Request<proto::Empty>,
+    ) -> std::result::Result<Response<proto::BpfHookList>, Status> {
+        let hooks = bpf::hooks()
+            .into_iter()
+            .map(|(hook, program)| proto::BpfHook {
+                hook,
+                instructions: program.map_or_else(Vec::new, |program| {
+                    program
+                        .instructions()
+                        .iter()
+                        .map(proto_instruction)
+                        .collect()
+                }),
+            })
+            .collect();
+        Ok(Response::new(proto::BpfHookList { hooks }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_acl_rules() {
+        let rule = proto::AclRule {
+            action: proto::AclAction::Deny as i32,
+            dst: "10.0.0.0/8".to_owned(),
+            protocol: 6,
+            dst_port_first: 22,
+            dst_port_last: 22,
+            ..Default::default()
+        };
+        let expected = AclRule::new(AclAction::Deny)
+            .dst_v4("10.0.0.0/8".parse().unwrap())
+            .protocol(ProtocolNumber(6))
+            .dst_ports(22, 22);
+        assert_eq!(expected, acl_rule(&rule).unwrap());
+
+        let mark = proto::AclRule {
+            action: proto::AclAction::Mark as i32,
+            mark: 7,
+            src: "2001:db8::/32".to_owned(),
+            ..Default::default()
+        };
+        let expected = AclRule::new(AclAction::Mark(7)).src_v6("2001:db8::/32".parse().unwrap());
+        assert_eq!(expected, acl_rule(&mark).unwrap());
+
+        let bad = proto::AclRule {
+            src: "10.0.0.0/33".to_owned(),
+            ..Default::default()
+        };
+        assert!(acl_rule(&bad).is_err());
+
+        let bad = proto::AclRule {
+            dst_port_first: 1,
+            dst_port_last: 70000,
+            ..Default::default()
+        };
+        assert!(acl_rule(&bad).is_err());
+    }
+
+    #[test]
+    fn convert_table_entries() {
+        use self::proto::field_match::Kind;
+
+        let entry = proto::TableEntry {
+            matches: vec![
+                proto::FieldMatch {
+                    field: "dst".to_owned(),
+                    kind: Some(Kind::Lpm("10.0.0.0/8".to_owned())),
+                },
+                proto::FieldMatch {
+                    field: "dst_port".to_owned(),
+                    kind: Some(Kind::Range(proto::Range { low: 22, high: 22 })),
+                },
+            ],
+            action: "mark".to_owned(),
+            params: vec!["7".to_owned()],
+            priority: 10,
+        };
+        let expected = TableEntry {
+            matches: vec![
+                (
+                    "dst".to_owned(),
+                    FieldMatch::Lpm("10.0.0.0".parse().unwrap(), 8),
+                ),
+                ("dst_port".to_owned(), FieldMatch::Range(22, 22)),
+            ],
+            action: "mark".to_owned(),
+            params: vec![Value::Uint(7)],
+            priority: 10,
+        };
+        assert_eq!(expected, table_entry(&entry).unwrap());
+        assert_eq!(entry, proto_entry(&expected));
+
+        let bad = proto::TableEntry {
+            matches: vec![proto::FieldMatch {
+                field: "dst".to_owned(),
+                kind: Some(Kind::Lpm("10.0.0.0".to_owned())),
+            }],
+            ..Default::default()
+        };
+        assert!(table_entry(&bad).is_err());
+    }
+
+    #[test]
+    fn route_through_table() {
+        let routes = Shared::new(RouteTable::new());
+        let table = Table::new("routes", RoutesTable::new(routes.clone()));
+        let route = |next_hop: &str| proto::Route {
+            prefix: "10.0.0.0/8".to_owned(),
+            next_hop: next_hop.to_owned(),
+        };
+        let addr = "10.1.2.3".parse().unwrap();
+
+        upsert(&table, route_entry(&route("192.168.0.1"), false).unwrap()).unwrap();
+        upsert(&table, route_entry(&route("192.168.0.2"), false).unwrap()).unwrap();
+        assert_eq!(1, table.entries().len());
+        assert_eq!(
+            Some(&"192.168.0.2".parse().unwrap()),
+            routes.load().lookup(addr)
+        );
+
+        remove(&table, route_entry(&route(""), true).unwrap()).unwrap();
+        remove(&table, route_entry(&route(""), true).unwrap()).unwrap();
+        assert!(table.entries().is_empty());
+        assert_eq!(None, routes.load().lookup(addr));
+
+        assert!(route_entry(&route(""), false).is_err());
+    }
+
+    #[test]
+    fn convert_bpf_programs() {
+        use self::proto::attach_bpf_request::Program as Source;
+
+        // tcpdump -ddd ip
+        let text = "4\n40 0 0 12\n21 0 1 2048\n6 0 0 262144\n6 0 0 0\n";
+        let program = bpf_program(Source::Text(text.to_owned())).unwrap();
+        assert_eq!(4, program.instructions().len());
+
+        let insns = program
+            .instructions()
+            .iter()
+            .map(proto_instruction)
+            .collect::<Vec<_>>();
+        let same = bpf_program(Source::Instructions(proto::BpfProgram {
+            instructions: insns,
+        }))
+        .unwrap();
+        assert_eq!(program.instructions(), same.instructions());
+
+        let bad = proto::BpfInstruction {
+            code: 0x10000,
+            ..Default::default()
+        };
+        assert!(bpf_program(Source::Instructions(proto::BpfProgram {
+            instructions: vec![bad],
+        }))
+        .is_err());
+        assert!(bpf_program(Source::Text("1\n".to_owned())).is_err());
+    }
+
+    #[test]
+    fn convert_journal_entries() {
+        let entry = Entry {
+            timestamp: UNIX_EPOCH + Duration::from_micros(1_500_000),
+            event: Event::PortDown {
+                port: "eth1".to_owned(),
+            },
+        };
+        assert_eq!(
+            proto::JournalEntry {
+                timestamp_us: 1_500_000,
+                event: "port eth1 down".to_owned(),
+            },
+            journal_entry(&entry)
+        );
+
+        // a clock set before the epoch does not fail the query.
+        let entry = Entry {
+            timestamp: UNIX_EPOCH - Duration::from_secs(1),
+            ..entry
+        };
+        assert_eq!(0, journal_entry(&entry).timestamp_us);
+    }
+}
@@ -0,0 +1,188 @@
+use super::{Mbuf, PortId, PortQueue};
+use crate::journal::{self, Event};
+use crate::warn;
+use futures::{future, StreamExt};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_executor::current_thread;
+use tokio_timer::Interval;
+
+lazy_static! {
+    // the port each pair transmits on, shared by the cores of the pair so
+    // they all switch together.
+    static ref ACTIVE: Mutex<HashMap<(PortId, PortId), Arc<AtomicUsize>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// An active and standby pair of ports, for the common appliance with two
+/// uplinks.
+///
+/// The packets are received on both ports and transmitted on the active
+/// one. When the link of the active port goes down and the link of the
+/// standby port is up, the transmission switches over to the standby port.
+/// Unlike bonding, nothing is negotiated with the peers, the other end
+/// only sees the traffic move from one link to the other.
+///
+/// The links are checked by `monitor_every`. The pairs of the same two
+/// ports on all the cores share the active port, so the cores switch
+/// together and the switchover is recorded once in the journal.
+///
+/// By default, the pair stays on the standby port after the primary port
+/// comes back, so a flapping link does not move the traffic back and
+/// forth. A revertive pair switches back as soon as the primary link is
+/// up.
+///
+/// # Example
+///
+/// ```
+/// Runtime::build(config)?
+///     .add_pipeline_to_ports(&["uplink1", "uplink2", "lan"], |qs| {
+///         let uplink = FailoverPair::new(
+///             ("uplink1", qs["uplink1"].clone()),
+///             ("uplink2", qs["uplink2"].clone()),
+///         );
+///         uplink.monitor_every(Duration::from_millis(100));
+///
+///         let up = batch::splice(qs["lan"].clone(), uplink.clone());
+///         let down = batch::splice(uplink, qs["lan"].clone());
+///         Scheduler::new().add("up", up).add("down", down)
+///     })?
+///     .execute()
+/// ```
+#[derive(Clone)]
+pub struct FailoverPair {
+    // the primary port first, then the standby port.
+    ports: Rc<[(String, PortQueue); 2]>,
+    active: Arc<AtomicUsize>,
+    revertive: bool,
+}
+
+impl FailoverPair {
+    /// Creates a pair that transmits on the primary port while its link
+    /// is up.
+    pub fn new(primary: (&str, PortQueue), standby: (&str, PortQueue)) -> Self {
+        let key = (primary.1.port_id(), standby.1.port_id());
+        let active = ACTIVE
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(AtomicUsize::new(0)))
+            .clone();
+
+        FailoverPair {
+            ports: Rc::new([
+                (primary.0.to_owned(), primary.1),
+                (standby.0.to_owned(), standby.1),
+            ]),
+            active,
+            revertive: false,
+        }
+    }
+
+    /// Sets whether the pair switches back to the primary port as soon as
+    /// its link is up again. The default is `false`.
+    pub fn revertive(mut self, revertive: bool) -> Self {
+        self.revertive = revertive;
+        self
+    }
+
+    /// Returns the name of the port the pair transmits on.
+    pub fn active(&self) -> &str {
+        &self.ports[self.active.load(Ordering::Relaxed)].0
+    }
+
+    /// Receives a burst from each of the ports.
+    pub(crate) fn receive(&self) -> Vec<Mbuf> {
+        let mut mbufs = self.ports[0].1.receive();
+        mbufs.extend(self.ports[1].1.receive());
+        mbufs
+    }
+
+    /// Transmits the packets on the active port.
+    pub(crate) fn transmit(&self, packets: Vec<Mbuf>) {
+        self.ports[self.active.load(Ordering::Relaxed)]
+            .1
+            .transmit(packets)
+    }
+
+    /// Spawns a timer on the current core that checks the links of the
+    /// ports every `interval`, and switches the active port over.
+    ///
+    /// Must be called on the core the pipeline runs on, for example in the
+    /// pipeline installer. The link is read without waiting, so checking
+    /// every 100ms or so is cheap enough.
+    pub fn monitor_every(&self, interval: Duration) {
+        let ports = Rc::downgrade(&self.ports);
+        let alive = ports.clone();
+        let active = self.active.clone();
+        let revertive = self.revertive;
+
+        // stops once the last clone of the pair is dropped.
+        let fut = Interval::new_interval(interval)
+            .take_while(move |_| future::ready(alive.upgrade().is_some()))
+            .for_each(move |_| {
+                if let Some(ports) = ports.upgrade() {
+                    check_links(&ports, &active, revertive);
+                }
+                future::ready(())
+            });
+        current_thread::spawn(fut);
+    }
+}
+
+fn check_links(ports: &[(String, PortQueue); 2], active: &AtomicUsize, revertive: bool) {
+    let current = active.load(Ordering::Acquire);
+    let up = [ports[0].1.link().up, ports[1].1.link().up];
+    let next = next_active(current, up, revertive);
+
+    // another core of the pair may have switched already.
+    if next != current && active.compare_and_swap(current, next, Ordering::AcqRel) == current {
+        let from = ports[current].0.clone();
+        let to = ports[next].0.clone();
+        warn!("failover from port {} to {}.", from, to);
+        journal::record_event(Event::Failover { from, to });
+    }
+}
+
+/// Returns the port to transmit on, given the link states of the primary
+/// and standby ports.
+fn next_active(current: usize, up: [bool; 2], revertive: bool) -> usize {
+    let standby = 1 - current;
+    if !up[current] && up[standby] {
+        standby
+    } else if revertive && current != 0 && up[0] {
+        0
+    } else {
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_over_on_link_down() {
+        // stays while the active link is up, whatever the standby link.
+        assert_eq!(0, next_active(0, [true, false], false));
+        assert_eq!(0, next_active(0, [true, true], false));
+
+        // switches when only the other link is up.
+        assert_eq!(1, next_active(0, [false, true], false));
+        assert_eq!(0, next_active(1, [true, false], false));
+
+        // nowhere better to go.
+        assert_eq!(0, next_active(0, [false, false], false));
+    }
+
+    #[test]
+    fn revert_to_primary() {
+        assert_eq!(1, next_active(1, [true, true], false));
+        assert_eq!(0, next_active(1, [true, true], true));
+        assert_eq!(1, next_active(1, [false, true], true));
+    }
+}
@@ -0,0 +1,300 @@
+use super::{CoreId, PortId, RxQueueIndex};
+use crate::ffi::{self, AsStr};
+use crate::{debug, Result};
+use failure::Fail;
+use std::any::Any;
+use std::os::raw;
+use std::ptr;
+
+/// Control protocols that can be steered to a dedicated receive queue.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ControlProtocol {
+    /// Address Resolution Protocol.
+    Arp,
+    /// IPv6 Neighbor Discovery Protocol, the router and neighbor
+    /// solicitations and advertisements, and redirects.
+    Ndp,
+    /// Border Gateway Protocol, TCP port 179 over IPv4 and IPv6.
+    Bgp,
+    /// Bidirectional Forwarding Detection, single hop and multihop, over
+    /// IPv4 and IPv6.
+    Bfd,
+}
+
+/// Error indicating the flow rules cannot be installed.
+#[derive(Debug, Fail)]
+pub enum FlowError {
+    /// The port has no receive queue on the core.
+    #[fail(display = "Port has no receive queue on {:?}.", _0)]
+    QueueNotFound(CoreId),
+
+    /// The device rejected the rule.
+    #[fail(display = "Steering {:?} is rejected: {}", _0, _1)]
+    Rejected(ControlProtocol, String),
+}
+
+const ETHER_TYPE_ARP: u16 = 0x0806;
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+const PROTO_ICMPV6: u8 = 58;
+const PORT_BGP: u16 = 179;
+const PORT_BFD: u16 = 3784;
+const PORT_BFD_MULTIHOP: u16 = 4784;
+
+/// One layer of a match pattern.
+#[derive(Clone, Copy, Debug)]
+enum Item {
+    /// Any ethernet frame.
+    Eth,
+    EtherType(u16),
+    Ipv4(u8),
+    Ipv6(u8),
+    TcpSrc(u16),
+    TcpDst(u16),
+    UdpDst(u16),
+    Icmpv6(u8),
+}
+
+impl ControlProtocol {
+    /// Returns the match patterns of the protocol.
+    fn patterns(self) -> Vec<Vec<Item>> {
+        match self {
+            ControlProtocol::Arp => vec![vec![Item::EtherType(ETHER_TYPE_ARP)]],
+            ControlProtocol::Ndp => (133..=137)
+                .map(|t| vec![Item::Eth, Item::Ipv6(PROTO_ICMPV6), Item::Icmpv6(t)])
+                .collect(),
+            ControlProtocol::Bgp => vec![
+                vec![Item::Eth, Item::Ipv4(PROTO_TCP), Item::TcpDst(PORT_BGP)],
+                vec![Item::Eth, Item::Ipv4(PROTO_TCP), Item::TcpSrc(PORT_BGP)],
+                vec![Item::Eth, Item::Ipv6(PROTO_TCP), Item::TcpDst(PORT_BGP)],
+                vec![Item::Eth, Item::Ipv6(PROTO_TCP), Item::TcpSrc(PORT_BGP)],
+            ],
+            ControlProtocol::Bfd => vec![
+                vec![Item::Eth, Item::Ipv4(PROTO_UDP), Item::UdpDst(PORT_BFD)],
+                vec![
+                    Item::Eth,
+                    Item::Ipv4(PROTO_UDP),
+                    Item::UdpDst(PORT_BFD_MULTIHOP),
+                ],
+                vec![Item::Eth, Item::Ipv6(PROTO_UDP), Item::UdpDst(PORT_BFD)],
+                vec![
+                    Item::Eth,
+                    Item::Ipv6(PROTO_UDP),
+                    Item::UdpDst(PORT_BFD_MULTIHOP),
+                ],
+            ],
+        }
+    }
+}
+
+/// Keeps the specs and masks of a pattern alive while the rule is built.
+#[derive(Default)]
+struct Pattern {
+    items: Vec<ffi::rte_flow_item>,
+    storage: Vec<Box<dyn Any>>,
+}
+
+impl Pattern {
+    fn push<T: 'static>(&mut self, type_: ffi::rte_flow_item_type::Type, spec: T, mask: T) {
+        let spec = Box::new(spec);
+        let mask = Box::new(mask);
+
+        self.items.push(ffi::rte_flow_item {
+            type_,
+            spec: &*spec as *const T as *const raw::c_void,
+            last: ptr::null(),
+            mask: &*mask as *const T as *const raw::c_void,
+        });
+
+        // the boxed values do not move on the heap.
+        self.storage.push(spec);
+        self.storage.push(mask);
+    }
+
+    fn push_any(&mut self, type_: ffi::rte_flow_item_type::Type) {
+        self.items.push(ffi::rte_flow_item {
+            type_,
+            spec: ptr::null(),
+            last: ptr::null(),
+            mask: ptr::null(),
+        });
+    }
+
+    fn new(items: &[Item]) -> Self {
+        use ffi::rte_flow_item_type::*;
+
+        let mut pattern = Pattern::default();
+
+        for item in items {
+            match *item {
+                Item::Eth => pattern.push_any(RTE_FLOW_ITEM_TYPE_ETH),
+                Item::EtherType(ether_type) => {
+                    let mut spec = ffi::rte_flow_item_eth::default();
+                    spec.type_ = ether_type.to_be();
+                    let mut mask = ffi::rte_flow_item_eth::default();
+                    mask.type_ = 0xffff;
+                    pattern.push(RTE_FLOW_ITEM_TYPE_ETH, spec, mask);
+                }
+                Item::Ipv4(proto) => {
+                    let mut spec = ffi::rte_flow_item_ipv4::default();
+                    spec.hdr.next_proto_id = proto;
+                    let mut mask = ffi::rte_flow_item_ipv4::default();
+                    mask.hdr.next_proto_id = 0xff;
+                    pattern.push(RTE_FLOW_ITEM_TYPE_IPV4, spec, mask);
+                }
+                Item::Ipv6(proto) => {
+                    let mut spec = ffi::rte_flow_item_ipv6::default();
+                    spec.hdr.proto = proto;
+                    let mut mask = ffi::rte_flow_item_ipv6::default();
+                    mask.hdr.proto = 0xff;
+                    pattern.push(RTE_FLOW_ITEM_TYPE_IPV6, spec, mask);
+                }
+                Item::TcpSrc(port) => {
+                    let mut spec = ffi::rte_flow_item_tcp::default();
+                    spec.hdr.src_port = port.to_be();
+                    let mut mask = ffi::rte_flow_item_tcp::default();
+                    mask.hdr.src_port = 0xffff;
+                    pattern.push(RTE_FLOW_ITEM_TYPE_TCP, spec, mask);
+                }
+                Item::TcpDst(port) => {
+                    let mut spec = ffi::rte_flow_item_tcp::default();
+                    spec.hdr.dst_port = port.to_be();
+                    let mut mask = ffi::rte_flow_item_tcp::default();
+                    mask.hdr.dst_port = 0xffff;
+                    pattern.push(RTE_FLOW_ITEM_TYPE_TCP, spec, mask);
+                }
+                Item::UdpDst(port) => {
+                    let mut spec = ffi::rte_flow_item_udp::default();
+                    spec.hdr.dst_port = port.to_be();
+                    let mut mask = ffi::rte_flow_item_udp::default();
+                    mask.hdr.dst_port = 0xffff;
+                    pattern.push(RTE_FLOW_ITEM_TYPE_UDP, spec, mask);
+                }
+                Item::Icmpv6(msg_type) => {
+                    let mut spec = ffi::rte_flow_item_icmp6::default();
+                    spec.type_ = msg_type;
+                    let mut mask = ffi::rte_flow_item_icmp6::default();
+                    mask.type_ = 0xff;
+                    pattern.push(RTE_FLOW_ITEM_TYPE_ICMP6, spec, mask);
+                }
+            }
+        }
+
+        pattern.push_any(RTE_FLOW_ITEM_TYPE_END);
+        pattern
+    }
+}
+
+/// Installs the flow rules that steer the protocol to the receive queue.
+///
+/// Returns the handles of the installed rules.
+pub(crate) fn steer(
+    port_id: PortId,
+    protocol: ControlProtocol,
+    rxq_index: RxQueueIndex,
+) -> Result<Vec<*mut ffi::rte_flow>> {
+    use ffi::rte_flow_action_type::*;
+
+    let mut attr = ffi::rte_flow_attr::default();
+    attr.set_ingress(1);
+
+    let queue = ffi::rte_flow_action_queue {
+        index: rxq_index.raw(),
+    };
+    let actions = [
+        ffi::rte_flow_action {
+            type_: RTE_FLOW_ACTION_TYPE_QUEUE,
+            conf: &queue as *const _ as *const raw::c_void,
+        },
+        ffi::rte_flow_action {
+            type_: RTE_FLOW_ACTION_TYPE_END,
+            conf: ptr::null(),
+        },
+    ];
+
+    let mut flows = vec![];
+    for items in protocol.patterns() {
+        let pattern = Pattern::new(&items);
+        let mut error = ffi::rte_flow_error::default();
+
+        let flow = unsafe {
+            let res = ffi::rte_flow_validate(
+                port_id.raw(),
+                &attr,
+                pattern.items.as_ptr(),
+                actions.as_ptr(),
+                &mut error,
+            );
+            if res == 0 {
+                ffi::rte_flow_create(
+                    port_id.raw(),
+                    &attr,
+                    pattern.items.as_ptr(),
+                    actions.as_ptr(),
+                    &mut error,
+                )
+            } else {
+                ptr::null_mut()
+            }
+        };
+
+        if flow.is_null() {
+            let message = if error.message.is_null() {
+                "unknown error".to_owned()
+            } else {
+                error.message.as_str().to_owned()
+            };
+
+            // rolls back the rules already installed for the protocol.
+            destroy(port_id, &flows);
+            return Err(FlowError::Rejected(protocol, message).into());
+        }
+
+        debug!(
+            message = "installed flow rule.",
+            ?port_id,
+            ?protocol,
+            ?items
+        );
+        flows.push(flow);
+    }
+
+    Ok(flows)
+}
+
+/// Removes the flow rules.
+pub(crate) fn destroy(port_id: PortId, flows: &[*mut ffi::rte_flow]) {
+    for &flow in flows {
+        let mut error = ffi::rte_flow_error::default();
+        unsafe {
+            ffi::rte_flow_destroy(port_id.raw(), flow, &mut error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_protocol_patterns() {
+        assert_eq!(1, ControlProtocol::Arp.patterns().len());
+        assert_eq!(5, ControlProtocol::Ndp.patterns().len());
+        assert_eq!(4, ControlProtocol::Bgp.patterns().len());
+        assert_eq!(4, ControlProtocol::Bfd.patterns().len());
+    }
+
+    #[test]
+    fn pattern_ends_with_end_item() {
+        let pattern = Pattern::new(&[Item::Eth, Item::Ipv4(PROTO_TCP), Item::TcpDst(PORT_BGP)]);
+
+        assert_eq!(4, pattern.items.len());
+        assert_eq!(
+            ffi::rte_flow_item_type::RTE_FLOW_ITEM_TYPE_END,
+            pattern.items[3].type_
+        );
+        // the any ethernet item has no spec.
+        assert!(pattern.items[0].spec.is_null());
+        assert!(!pattern.items[2].spec.is_null());
+    }
+}
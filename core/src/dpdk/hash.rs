@@ -0,0 +1,582 @@
+use super::SocketId;
+use crate::ffi::{self, ToCString, ToResult};
+use crate::packets::ip::Flow;
+use crate::{debug, ensure, Result};
+use failure::Fail;
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::raw;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// A global counter used to generate a unique name for new hash tables.
+static HASH_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The maximum length of a key, in bytes.
+pub const MAX_KEY_LEN: usize = 64;
+
+// the most keys `rte_hash_lookup_bulk` takes at once.
+const LOOKUP_BULK_MAX: usize = 64;
+
+/// Error indicating the key of an exact match table is too long.
+#[derive(Debug, Fail)]
+#[fail(display = "Key length {} is longer than {} bytes.", _0, MAX_KEY_LEN)]
+pub struct KeyLenError(usize);
+
+/// A key of an exact match table.
+///
+/// `librte_hash` compares the keys byte by byte, so the key must be
+/// written out as a fixed number of bytes, without the padding its Rust
+/// representation may have.
+pub trait HashKey: Copy + Eq {
+    /// The length of the key, in bytes.
+    const LEN: usize;
+
+    /// Writes the key into the first `LEN` bytes of the buffer.
+    fn write(&self, buf: &mut [u8]);
+}
+
+impl HashKey for u16 {
+    const LEN: usize = 2;
+
+    #[inline]
+    fn write(&self, buf: &mut [u8]) {
+        buf[..2].copy_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl HashKey for u32 {
+    const LEN: usize = 4;
+
+    #[inline]
+    fn write(&self, buf: &mut [u8]) {
+        buf[..4].copy_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl HashKey for u64 {
+    const LEN: usize = 8;
+
+    #[inline]
+    fn write(&self, buf: &mut [u8]) {
+        buf[..8].copy_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl HashKey for Ipv4Addr {
+    const LEN: usize = 4;
+
+    #[inline]
+    fn write(&self, buf: &mut [u8]) {
+        buf[..4].copy_from_slice(&self.octets());
+    }
+}
+
+impl HashKey for Ipv6Addr {
+    const LEN: usize = 16;
+
+    #[inline]
+    fn write(&self, buf: &mut [u8]) {
+        buf[..16].copy_from_slice(&self.octets());
+    }
+}
+
+impl HashKey for IpAddr {
+    const LEN: usize = 16;
+
+    #[inline]
+    fn write(&self, buf: &mut [u8]) {
+        let octets = match self {
+            IpAddr::V4(addr) => addr.to_ipv6_mapped().octets(),
+            IpAddr::V6(addr) => addr.octets(),
+        };
+        buf[..16].copy_from_slice(&octets);
+    }
+}
+
+impl HashKey for Flow {
+    const LEN: usize = 37;
+
+    #[inline]
+    fn write(&self, buf: &mut [u8]) {
+        self.src_ip().write(&mut buf[..16]);
+        self.dst_ip().write(&mut buf[16..32]);
+        buf[32..34].copy_from_slice(&self.src_port().to_be_bytes());
+        buf[34..36].copy_from_slice(&self.dst_port().to_be_bytes());
+        buf[36] = self.protocol().0;
+    }
+}
+
+#[inline]
+fn key_bytes<K: HashKey>(key: &K) -> [u8; MAX_KEY_LEN] {
+    let mut buf = [0; MAX_KEY_LEN];
+    key.write(&mut buf);
+    buf
+}
+
+fn create_hash<K: HashKey>(
+    capacity: usize,
+    socket_id: SocketId,
+    extra_flag: u32,
+) -> Result<NonNull<ffi::rte_hash>> {
+    ensure!(K::LEN <= MAX_KEY_LEN, KeyLenError(K::LEN));
+
+    let n = HASH_COUNT.fetch_add(1, Ordering::Relaxed);
+    let name = format!("hash{}", n).to_cstring();
+    let params = ffi::rte_hash_parameters {
+        name: name.as_ptr(),
+        entries: capacity as u32,
+        key_len: K::LEN as u32,
+        hash_func: Some(ffi::_rte_hash_crc),
+        hash_func_init_val: 0,
+        socket_id: socket_id.raw(),
+        extra_flag: extra_flag as u8,
+        ..Default::default()
+    };
+
+    unsafe { ffi::rte_hash_create(&params).to_result() }
+}
+
+/// A hash table of exact match keys, a wrapper of `librte_hash`.
+///
+/// The keys are hashed with the CRC32 instructions and stored in a
+/// cuckoo hash table, which performs better than `HashMap` for fixed size
+/// keys such as the 5-tuple. The capacity is fixed when the table is
+/// created.
+///
+/// The table is owned by a single pipeline. To share a table across
+/// cores, use a `ConcurrentMatchTable`.
+///
+/// # Example
+///
+/// ```
+/// let mut nat = ExactMatchTable::<Flow, Flow>::new(65536, SocketId::current())?;
+///
+/// nat.insert(flow, translated)?;
+/// if let Some(translated) = nat.get(&v4.flow()) {
+///     ...
+/// }
+/// ```
+pub struct ExactMatchTable<K: HashKey, V> {
+    raw: NonNull<ffi::rte_hash>,
+    capacity: usize,
+    // the entries indexed by the key positions returned by `librte_hash`.
+    entries: Vec<Option<(K, V)>>,
+    len: usize,
+}
+
+impl<K: HashKey, V> ExactMatchTable<K, V> {
+    /// Creates a new table with room for `capacity` keys.
+    ///
+    /// `socket_id` is the socket where the memory should be allocated.
+    ///
+    /// # Errors
+    ///
+    /// If the key is longer than `MAX_KEY_LEN`, `KeyLenError` is returned.
+    /// If allocation fails, then `DpdkError` is returned.
+    pub fn new(capacity: usize, socket_id: SocketId) -> Result<Self> {
+        let raw = create_hash::<K>(capacity, socket_id, 0)?;
+
+        Ok(ExactMatchTable {
+            raw,
+            capacity,
+            entries: vec![],
+            len: 0,
+        })
+    }
+
+    /// Returns the number of keys the table can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of keys in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the table is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    fn position(&self, key: &K) -> Option<usize> {
+        let bytes = key_bytes(key);
+        let pos = unsafe { ffi::rte_hash_lookup(self.raw.as_ptr(), bytes.as_ptr() as *const _) };
+        if pos < 0 {
+            None
+        } else {
+            Some(pos as usize)
+        }
+    }
+
+    /// Inserts the key and its value, returning the previous value of the
+    /// key.
+    ///
+    /// # Errors
+    ///
+    /// If there is no room left for the key, `DpdkError` is returned.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let bytes = key_bytes(&key);
+        let pos = unsafe {
+            ffi::rte_hash_add_key(self.raw.as_ptr(), bytes.as_ptr() as *const _).to_result()?
+        } as usize;
+
+        if pos >= self.entries.len() {
+            self.entries.resize_with(pos + 1, || None);
+        }
+
+        let prev = self.entries[pos].replace((key, value));
+        if prev.is_none() {
+            self.len += 1;
+        }
+        Ok(prev.map(|(_, value)| value))
+    }
+
+    /// Returns the value of the key.
+    #[inline]
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let pos = self.position(key)?;
+        self.entries[pos].as_ref().map(|(_, value)| value)
+    }
+
+    /// Returns the mutable value of the key.
+    #[inline]
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let pos = self.position(key)?;
+        self.entries[pos].as_mut().map(|(_, value)| value)
+    }
+
+    /// Returns the values of the keys, looked up in bulk.
+    ///
+    /// Looking up a batch of keys at once hides the latency of the memory
+    /// accesses, so it is faster than looking up the keys one by one.
+    pub fn get_bulk(&self, keys: &[K]) -> Vec<Option<&V>> {
+        let mut values = Vec::with_capacity(keys.len());
+        let mut bytes = [[0; MAX_KEY_LEN]; LOOKUP_BULK_MAX];
+        let mut ptrs = [ptr::null(); LOOKUP_BULK_MAX];
+        let mut positions = [0i32; LOOKUP_BULK_MAX];
+
+        for chunk in keys.chunks(LOOKUP_BULK_MAX) {
+            for (i, key) in chunk.iter().enumerate() {
+                bytes[i] = key_bytes(key);
+                ptrs[i] = bytes[i].as_ptr() as *const raw::c_void;
+            }
+
+            unsafe {
+                ffi::rte_hash_lookup_bulk(
+                    self.raw.as_ptr(),
+                    ptrs.as_mut_ptr(),
+                    chunk.len() as u32,
+                    positions.as_mut_ptr(),
+                );
+            }
+
+            values.extend(positions[..chunk.len()].iter().map(|&pos| {
+                if pos < 0 {
+                    None
+                } else {
+                    self.entries[pos as usize].as_ref().map(|(_, value)| value)
+                }
+            }));
+        }
+
+        values
+    }
+
+    /// Removes the key, returning its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let bytes = key_bytes(key);
+        let pos = unsafe { ffi::rte_hash_del_key(self.raw.as_ptr(), bytes.as_ptr() as *const _) };
+        if pos < 0 {
+            return None;
+        }
+
+        let (_, value) = self.entries[pos as usize].take()?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// Removes all the keys.
+    pub fn clear(&mut self) {
+        unsafe {
+            ffi::rte_hash_reset(self.raw.as_ptr());
+        }
+        self.entries.clear();
+        self.len = 0;
+    }
+
+    /// Returns an iterator over the keys and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.as_ref().map(|(key, value)| (key, value)))
+    }
+}
+
+impl<K: HashKey + fmt::Debug, V> fmt::Debug for ExactMatchTable<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExactMatchTable")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<K: HashKey, V> Drop for ExactMatchTable<K, V> {
+    fn drop(&mut self) {
+        debug!("freeing exact match table.");
+
+        unsafe {
+            ffi::rte_hash_free(self.raw.as_ptr());
+        }
+    }
+}
+
+// the table is only used by one core at a time.
+unsafe impl<K: HashKey + Send, V: Send> Send for ExactMatchTable<K, V> {}
+
+/// A hash table of exact match keys shared by several cores, with
+/// lock-free lookups.
+///
+/// The table is created with the lock-free read-write concurrency of
+/// `librte_hash`, so the lookups never wait on the writers. The value of
+/// a key is a `usize` stored in the table itself, and updated atomically,
+/// typically an index into an array or the ID of a port.
+///
+/// A removed key may still be seen by a lookup that was already under
+/// way. The slot of the key is only reused after `reclaim` is called,
+/// which the application should do once all the cores have moved on to
+/// a new batch of packets.
+///
+/// # Example
+///
+/// ```
+/// let routes = Arc::new(ConcurrentMatchTable::<Ipv4Addr>::new(1024, SocketId::current())?);
+///
+/// runtime.add_pipeline_to_port("eth1", move |q| {
+///     let routes = routes.clone();
+///     Poll::new(q.clone())
+///         .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+///         .filter(move |v4| routes.get(&v4.dst()).is_some())
+///         .send(q)
+/// })?;
+/// ```
+pub struct ConcurrentMatchTable<K: HashKey> {
+    raw: NonNull<ffi::rte_hash>,
+    capacity: usize,
+    retired: Mutex<Vec<i32>>,
+    _phantom: PhantomData<K>,
+}
+
+impl<K: HashKey> ConcurrentMatchTable<K> {
+    /// Creates a new table with room for `capacity` keys.
+    ///
+    /// `socket_id` is the socket where the memory should be allocated.
+    ///
+    /// # Errors
+    ///
+    /// If the key is longer than `MAX_KEY_LEN`, `KeyLenError` is returned.
+    /// If allocation fails, then `DpdkError` is returned.
+    pub fn new(capacity: usize, socket_id: SocketId) -> Result<Self> {
+        let extra_flag = ffi::RTE_HASH_EXTRA_FLAGS_RW_CONCURRENCY_LF
+            | ffi::RTE_HASH_EXTRA_FLAGS_MULTI_WRITER_ADD;
+        let raw = create_hash::<K>(capacity, socket_id, extra_flag)?;
+
+        Ok(ConcurrentMatchTable {
+            raw,
+            capacity,
+            retired: Mutex::new(vec![]),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Returns the number of keys the table can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of keys in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        unsafe { ffi::rte_hash_count(self.raw.as_ptr()).max(0) as usize }
+    }
+
+    /// Returns whether the table is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts the key and its value, or updates the value if the key is
+    /// already in the table.
+    ///
+    /// # Errors
+    ///
+    /// If there is no room left for the key, `DpdkError` is returned.
+    pub fn insert(&self, key: K, value: usize) -> Result<()> {
+        let bytes = key_bytes(&key);
+        unsafe {
+            ffi::rte_hash_add_key_data(
+                self.raw.as_ptr(),
+                bytes.as_ptr() as *const _,
+                value as *mut raw::c_void,
+            )
+            .to_result()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the value of the key.
+    #[inline]
+    pub fn get(&self, key: &K) -> Option<usize> {
+        let bytes = key_bytes(key);
+        let mut data = ptr::null_mut();
+        let pos = unsafe {
+            ffi::rte_hash_lookup_data(self.raw.as_ptr(), bytes.as_ptr() as *const _, &mut data)
+        };
+
+        if pos < 0 {
+            None
+        } else {
+            Some(data as usize)
+        }
+    }
+
+    /// Removes the key, returning whether it was in the table.
+    pub fn remove(&self, key: &K) -> bool {
+        let bytes = key_bytes(key);
+        let pos = unsafe { ffi::rte_hash_del_key(self.raw.as_ptr(), bytes.as_ptr() as *const _) };
+        if pos < 0 {
+            return false;
+        }
+
+        self.retired.lock().unwrap().push(pos);
+        true
+    }
+
+    /// Frees the slots of the keys removed so far, so they can be reused.
+    ///
+    /// Only call once no core can be in the middle of a lookup that
+    /// started before the keys were removed.
+    pub fn reclaim(&self) {
+        let retired = std::mem::replace(&mut *self.retired.lock().unwrap(), vec![]);
+        for pos in retired {
+            unsafe {
+                ffi::rte_hash_free_key_with_position(self.raw.as_ptr(), pos);
+            }
+        }
+    }
+}
+
+impl<K: HashKey> fmt::Debug for ConcurrentMatchTable<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConcurrentMatchTable")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<K: HashKey> Drop for ConcurrentMatchTable<K> {
+    fn drop(&mut self) {
+        debug!("freeing concurrent match table.");
+
+        unsafe {
+            ffi::rte_hash_free(self.raw.as_ptr());
+        }
+    }
+}
+
+// `librte_hash` synchronizes the lookups with the writers.
+unsafe impl<K: HashKey> Send for ConcurrentMatchTable<K> {}
+unsafe impl<K: HashKey> Sync for ConcurrentMatchTable<K> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::ProtocolNumbers;
+
+    fn flow(src_port: u16) -> Flow {
+        Flow::new(
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            src_port,
+            80,
+            ProtocolNumbers::Tcp,
+        )
+    }
+
+    #[test]
+    fn write_flow_key() {
+        let bytes = key_bytes(&flow(1234));
+        assert_eq!(
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 10, 0, 0, 1],
+            bytes[..16]
+        );
+        assert_eq!([0x04, 0xd2, 0, 80, 0x06], bytes[32..37]);
+    }
+
+    #[nb2::test]
+    fn insert_and_remove() {
+        let mut table = ExactMatchTable::new(1024, SocketId::ANY).unwrap();
+
+        for port in 0..100 {
+            assert!(table.insert(flow(port), port).unwrap().is_none());
+        }
+        assert_eq!(Some(50), table.insert(flow(50), 500).unwrap());
+        assert_eq!(100, table.len());
+
+        assert_eq!(Some(&500), table.get(&flow(50)));
+        *table.get_mut(&flow(1)).unwrap() += 1;
+        assert_eq!(Some(&2), table.get(&flow(1)));
+
+        assert_eq!(Some(2), table.remove(&flow(1)));
+        assert_eq!(None, table.get(&flow(1)));
+        assert_eq!(None, table.remove(&flow(1)));
+        assert_eq!(99, table.len());
+        assert_eq!(99, table.iter().count());
+
+        table.clear();
+        assert!(table.is_empty());
+        assert_eq!(None, table.get(&flow(2)));
+    }
+
+    #[nb2::test]
+    fn lookup_in_bulk() {
+        let mut table = ExactMatchTable::new(1024, SocketId::ANY).unwrap();
+        for port in (0..200u32).step_by(2) {
+            table.insert(port, port * 10).unwrap();
+        }
+
+        let keys = (0..200).collect::<Vec<_>>();
+        let values = table.get_bulk(&keys);
+        assert_eq!(200, values.len());
+        assert_eq!(Some(&0), values[0]);
+        assert_eq!(None, values[1]);
+        assert_eq!(Some(&1980), values[198]);
+    }
+
+    #[nb2::test]
+    fn concurrent_lookups() {
+        let table = ConcurrentMatchTable::new(1024, SocketId::ANY).unwrap();
+        let addr = Ipv4Addr::new(10, 0, 0, 1);
+
+        table.insert(addr, 1).unwrap();
+        table.insert(addr, 2).unwrap();
+        assert_eq!(Some(2), table.get(&addr));
+        assert_eq!(1, table.len());
+
+        assert!(table.remove(&addr));
+        assert_eq!(None, table.get(&addr));
+        table.reclaim();
+        assert!(table.is_empty());
+    }
+}
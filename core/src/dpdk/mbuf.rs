@@ -1,5 +1,6 @@
-use super::MEMPOOL;
+use super::{cache_hit, PacketMeta, PacketType, PortId, HEADROOM, MEMPOOL};
 use crate::ffi::{self, ToResult};
+use crate::stats::record_cache_lookup;
 use crate::{ensure, trace, Result};
 use failure::Fail;
 use std::convert::From;
@@ -9,6 +10,30 @@ use std::os::raw;
 use std::ptr::{self, NonNull};
 use std::slice;
 
+// the port of the buffers not received on a port, `MBUF_INVALID_PORT`.
+const INVALID_PORT: u16 = u16::max_value();
+
+// `EXT_ATTACHED_MBUF` and `IND_ATTACHED_MBUF`, the flags of a buffer
+// attached to the data of another.
+const ATTACHED_MBUF_FLAGS: u64 = (1 << 61) | (1 << 62);
+
+// the fault injection hooks only exist in the tests and with `testils`,
+// everywhere else they are no-ops that compile away.
+#[cfg(any(test, feature = "testils"))]
+use crate::testils::{check_alloc, check_extend};
+
+#[cfg(not(any(test, feature = "testils")))]
+#[inline(always)]
+fn check_alloc(_n: usize) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(any(test, feature = "testils")))]
+#[inline(always)]
+fn check_extend() -> Result<()> {
+    Ok(())
+}
+
 /// Blanketly implemented for all types so we can conveniently find the
 /// byte size when this trait is imported. Size of the structs are used
 /// for bound checks when reading and writing packets.
@@ -43,6 +68,33 @@ pub enum BufferError {
     OutOfBuffer(usize, usize),
 }
 
+/// The checksum validation result of a received packet reported by the
+/// ethernet device.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RxChecksum {
+    /// The device did not validate the checksum.
+    Unknown,
+
+    /// The checksum is valid, or the checksum in the packet is not
+    /// correct but the integrity of the data is verified.
+    Good,
+
+    /// The checksum is invalid.
+    Bad,
+}
+
+/// The state and data of a buffer, saved to roll back a failed series of
+/// edits.
+pub(crate) struct Snapshot {
+    raw: NonNull<ffi::rte_mbuf>,
+    data_off: u16,
+    data_len: u16,
+    pkt_len: u32,
+    packet_type: PacketType,
+    meta: PacketMeta,
+    data: Vec<u8>,
+}
+
 /// A DPDK message buffer that carries the network packet.
 ///
 /// # Remarks
@@ -58,13 +110,22 @@ impl Mbuf {
     /// Creates a new message buffer.
     ///
     /// The Mbuf is allocated from the `Mempool` assigned to the current
-    /// executing thread by the `Runtime`. The call will fail if invoked
-    /// from a thread not managed by the `Runtime`.
+    /// executing thread by the `Runtime`, and starts with the headroom of
+    /// the mempool settings. The call will fail if invoked from a thread
+    /// not managed by the `Runtime`.
     #[inline]
     pub fn new() -> Result<Self> {
+        check_alloc(1)?;
+
         let mempool = MEMPOOL.with(|tls| tls.get());
+        if let Some(hit) = cache_hit(mempool, 1) {
+            record_cache_lookup(hit);
+        }
         let raw = unsafe { ffi::_rte_pktmbuf_alloc(mempool).to_result()? };
-        Ok(raw.into())
+        let mut mbuf: Mbuf = raw.into();
+        mbuf.set_meta(PacketMeta::default());
+        mbuf.reserve_headroom(HEADROOM.with(|tls| tls.get()));
+        Ok(mbuf)
     }
 
     /// Creates a new message buffer from a byte array.
@@ -94,6 +155,143 @@ impl Mbuf {
         self.raw().data_len as usize
     }
 
+    /// Returns the total amount of data stored in all the segments of the
+    /// buffer.
+    #[inline]
+    pub fn pkt_len(&self) -> usize {
+        self.raw().pkt_len as usize
+    }
+
+    /// Returns the number of segments the buffer is made of.
+    #[inline]
+    pub fn num_segments(&self) -> usize {
+        self.raw().nb_segs as usize
+    }
+
+    /// Returns the device's validation result of the IP header checksum.
+    ///
+    /// Only meaningful for received packets that are not yet modified.
+    #[inline]
+    pub fn rx_ip_checksum(&self) -> RxChecksum {
+        let flags = self.raw().ol_flags & u64::from(ffi::PKT_RX_IP_CKSUM_MASK);
+        if flags == u64::from(ffi::PKT_RX_IP_CKSUM_BAD) {
+            RxChecksum::Bad
+        } else if flags == u64::from(ffi::PKT_RX_IP_CKSUM_UNKNOWN) {
+            RxChecksum::Unknown
+        } else {
+            RxChecksum::Good
+        }
+    }
+
+    /// Sets the IP header checksum flags, as the device would on receive.
+    #[cfg(test)]
+    pub(crate) fn set_rx_ip_checksum(&mut self, checksum: RxChecksum) {
+        let flags = match checksum {
+            RxChecksum::Good => ffi::PKT_RX_IP_CKSUM_GOOD,
+            RxChecksum::Bad => ffi::PKT_RX_IP_CKSUM_BAD,
+            RxChecksum::Unknown => ffi::PKT_RX_IP_CKSUM_UNKNOWN,
+        };
+        let raw = self.raw_mut();
+        raw.ol_flags = (raw.ol_flags & !u64::from(ffi::PKT_RX_IP_CKSUM_MASK)) | u64::from(flags);
+    }
+
+    /// Returns the device's validation result of the L4 checksum.
+    ///
+    /// Only meaningful for received packets that are not yet modified.
+    #[inline]
+    pub fn rx_l4_checksum(&self) -> RxChecksum {
+        let flags = self.raw().ol_flags & u64::from(ffi::PKT_RX_L4_CKSUM_MASK);
+        if flags == u64::from(ffi::PKT_RX_L4_CKSUM_BAD) {
+            RxChecksum::Bad
+        } else if flags == u64::from(ffi::PKT_RX_L4_CKSUM_UNKNOWN) {
+            RxChecksum::Unknown
+        } else {
+            RxChecksum::Good
+        }
+    }
+
+    /// Returns the packet type classified by the device.
+    ///
+    /// Only meaningful for received packets. The type is forgotten once a
+    /// header is inserted into or removed from the buffer.
+    #[inline]
+    pub fn packet_type(&self) -> PacketType {
+        PacketType(unsafe { ffi::_rte_mbuf_packet_type(self.raw.as_ptr()) })
+    }
+
+    /// Sets the packet type of the buffer.
+    #[inline]
+    pub fn set_packet_type(&mut self, packet_type: PacketType) {
+        unsafe { ffi::_rte_mbuf_set_packet_type(self.raw.as_ptr(), packet_type.0) }
+    }
+
+    /// Returns the lengths of the L2 and L3 headers, as recorded in the
+    /// buffer.
+    ///
+    /// The lengths are `0` unless set with `set_header_lens`, by the
+    /// application or by `RawPacket::cache`.
+    #[inline]
+    pub fn header_lens(&self) -> (usize, usize) {
+        let lens = unsafe { ffi::_rte_mbuf_header_lens(self.raw.as_ptr()) };
+        ((lens >> 16) as usize, (lens & 0xffff) as usize)
+    }
+
+    /// Sets the lengths of the L2 and L3 headers.
+    ///
+    /// The lengths are also the ones the device reads for the TX checksum
+    /// offloads. The L2 length is at most `127` and the L3 length at most
+    /// `511` bytes.
+    #[inline]
+    pub fn set_header_lens(&mut self, l2_len: usize, l3_len: usize) {
+        unsafe { ffi::_rte_mbuf_set_header_lens(self.raw.as_ptr(), l2_len as u16, l3_len as u16) }
+    }
+
+    /// Returns the QoS metadata of the packet.
+    #[inline]
+    pub fn meta(&self) -> PacketMeta {
+        PacketMeta::from_raw(unsafe { ffi::_rte_mbuf_udata64(self.raw.as_ptr()) })
+    }
+
+    /// Sets the QoS metadata of the packet.
+    #[inline]
+    pub fn set_meta(&mut self, meta: PacketMeta) {
+        unsafe { ffi::_rte_mbuf_set_udata64(self.raw.as_ptr(), meta.into_raw()) }
+    }
+
+    /// Returns the ID of the port the packet was received on, or `None`
+    /// if the packet was allocated by the application.
+    #[inline]
+    pub fn port_id(&self) -> Option<PortId> {
+        match self.raw().port {
+            INVALID_PORT => None,
+            port => Some(PortId::new(port)),
+        }
+    }
+
+    /// Sets the ID of the port the packet was received on.
+    ///
+    /// Use for packets that enter a pipeline from elsewhere, such as a
+    /// ring or a capture file, so their stats are attributed to a port.
+    #[inline]
+    pub fn set_port_id(&mut self, port_id: PortId) {
+        self.raw_mut().port = port_id.raw();
+    }
+
+    /// Returns the sequence number of the buffer.
+    ///
+    /// The sequence number is stamped with `Batch::sequence` and used by
+    /// `ReorderTx` to restore the order of the packets.
+    #[inline]
+    pub fn seqn(&self) -> u32 {
+        self.raw().seqn
+    }
+
+    /// Sets the sequence number of the buffer.
+    #[inline]
+    pub fn set_seqn(&mut self, seqn: u32) {
+        self.raw_mut().seqn = seqn;
+    }
+
     /// Returns the raw pointer from the offset
     #[inline]
     unsafe fn data_address(&self, offset: usize) -> *mut u8 {
@@ -101,6 +299,34 @@ impl Mbuf {
         (raw.buf_addr as *mut u8).offset(raw.data_off as isize + offset as isize)
     }
 
+    /// Moves the start of the data of a newly allocated buffer, so there
+    /// are `headroom` bytes in front of it.
+    ///
+    /// The allocation resets the buffer to the default headroom, a larger
+    /// one is reserved from the data room the mempool made room for.
+    #[inline]
+    fn reserve_headroom(&mut self, headroom: u16) {
+        let raw = self.raw_mut();
+        if raw.data_off != headroom {
+            raw.data_off = headroom.min(raw.buf_len);
+        }
+    }
+
+    /// Returns the amount of bytes in front of the data.
+    ///
+    /// # Remarks
+    ///
+    /// The headroom of the mempool settings is only reserved for the
+    /// buffers allocated with `Mbuf::new`, `Mbuf::from_bytes` and
+    /// `Mbuf::alloc_bulk`. The devices place the received packets after
+    /// the default headroom of `RTE_PKTMBUF_HEADROOM` bytes, whatever the
+    /// mempool made room for, so a received packet has `128` bytes of
+    /// headroom at most.
+    #[inline]
+    pub fn headroom(&self) -> usize {
+        self.raw().data_off as usize
+    }
+
     /// Returns the amount of bytes left in the buffer.
     #[inline]
     fn tailroom(&self) -> usize {
@@ -108,14 +334,85 @@ impl Mbuf {
         (raw.buf_len - raw.data_off - raw.data_len) as usize
     }
 
+    /// Makes the `len` bytes starting at `offset` contiguous in the first
+    /// segment.
+    ///
+    /// The bytes that spill over into the following segments are copied
+    /// into the tailroom of the first segment. Segments drained by the copy
+    /// are freed. It's a noop if the range is already in the first segment.
+    /// Header parsers can call this before reading a header so they only
+    /// ever need to deal with a single segment.
+    ///
+    /// # Errors
+    ///
+    /// If the range exceeds the total length of the buffer, or the first
+    /// segment does not have enough tailroom, `BufferError` is returned.
+    pub fn pullup(&mut self, offset: usize, len: usize) -> Result<()> {
+        let end = offset + len;
+        ensure!(
+            end <= self.pkt_len(),
+            BufferError::OutOfBuffer(end, self.pkt_len())
+        );
+
+        if end <= self.data_len() {
+            return Ok(());
+        }
+
+        let mut remaining = end - self.data_len();
+        ensure!(remaining <= self.tailroom(), BufferError::NotResized);
+
+        while remaining > 0 {
+            unsafe {
+                let dst = self.data_address(self.data_len());
+                let head = self.raw_mut();
+                // total length covers the range, so there's always a next
+                // segment while there are bytes remaining.
+                let seg = head.next;
+                let seg_len = (*seg).data_len as usize;
+                let to_copy = remaining.min(seg_len);
+
+                let src = ((*seg).buf_addr as *mut u8).offset((*seg).data_off as isize);
+                ptr::copy_nonoverlapping(src, dst, to_copy);
+                head.data_len += to_copy as u16;
+
+                if to_copy == seg_len {
+                    // the segment is drained, unlink it from the chain
+                    // before freeing it so the rest of the chain is kept.
+                    head.next = (*seg).next;
+                    head.nb_segs -= 1;
+                    (*seg).next = ptr::null_mut();
+                    (*seg).nb_segs = 1;
+                    ffi::_rte_pktmbuf_free(seg);
+                } else {
+                    (*seg).data_off += to_copy as u16;
+                    (*seg).data_len -= to_copy as u16;
+                }
+
+                remaining -= to_copy;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Extends the data buffer at offset by `len` bytes.
     ///
     /// If the offset is not at the end of the data. The data after the
-    /// offset is shifted down to make room.
+    /// offset is shifted down to make room. At offset `0`, the room is
+    /// taken from the headroom instead if there's enough of it.
     #[inline]
     pub fn extend(&mut self, offset: usize, len: usize) -> Result<()> {
         ensure!(len > 0, BufferError::NotResized);
         ensure!(offset <= self.data_len(), BufferError::NotResized);
+
+        check_extend()?;
+
+        // inserting in front of the data, takes the room from the headroom
+        // instead of shifting the whole packet down.
+        if offset == 0 && self.data_len() > 0 && len <= self.headroom() {
+            return self.prepend(len);
+        }
+
         ensure!(len < self.tailroom(), BufferError::NotResized);
 
         // shifts down data to make room
@@ -126,6 +423,8 @@ impl Mbuf {
                 let dst = self.data_address(offset + len);
                 ptr::copy(src, dst, to_copy);
             }
+            // the layers moved, the classified type no longer holds.
+            self.set_packet_type(PacketType::default());
         }
 
         // do some record keeping
@@ -137,12 +436,17 @@ impl Mbuf {
 
     /// Shrinks the data buffer at offset by `len` bytes.
     ///
-    /// The data at offset is shifted up.
+    /// The data at offset is shifted up. At offset `0`, the bytes are
+    /// given back to the headroom instead.
     #[inline]
     pub fn shrink(&mut self, offset: usize, len: usize) -> Result<()> {
         ensure!(len > 0, BufferError::NotResized);
         ensure!(offset + len <= self.data_len(), BufferError::NotResized);
 
+        if offset == 0 {
+            return self.adj(len);
+        }
+
         // shifts up data to fill the room
         let to_copy = self.data_len() - offset - len;
         if to_copy > 0 {
@@ -151,6 +455,7 @@ impl Mbuf {
                 let dst = self.data_address(offset);
                 ptr::copy(src, dst, to_copy);
             }
+            self.set_packet_type(PacketType::default());
         }
 
         // do some record keeping
@@ -160,6 +465,51 @@ impl Mbuf {
         Ok(())
     }
 
+    /// Prepends `len` bytes to the front of the data, taken from the
+    /// headroom.
+    ///
+    /// Unlike inserting with `extend`, the data is not moved, only the
+    /// start of the buffer. Use it to push outer headers, such as a VLAN
+    /// tag or a tunnel encapsulation, onto a raw packet. The new bytes are
+    /// not initialized.
+    ///
+    /// A received packet only has the default headroom, see `headroom`.
+    ///
+    /// # Errors
+    ///
+    /// If the headroom is smaller than `len`, `BufferError::NotResized` is
+    /// returned.
+    #[inline]
+    pub fn prepend(&mut self, len: usize) -> Result<()> {
+        ensure!(len > 0, BufferError::NotResized);
+        ensure!(len <= self.headroom(), BufferError::NotResized);
+
+        unsafe {
+            ffi::_rte_pktmbuf_prepend(self.raw.as_ptr(), len as u16);
+        }
+        self.set_packet_type(PacketType::default());
+
+        Ok(())
+    }
+
+    /// Removes `len` bytes from the front of the data, giving them back to
+    /// the headroom.
+    ///
+    /// The data is not moved. Use it to pop outer headers off a raw
+    /// packet.
+    #[inline]
+    pub fn adj(&mut self, len: usize) -> Result<()> {
+        ensure!(len > 0, BufferError::NotResized);
+        ensure!(len <= self.data_len(), BufferError::NotResized);
+
+        unsafe {
+            ffi::_rte_pktmbuf_adj(self.raw.as_ptr(), len as u16);
+        }
+        self.set_packet_type(PacketType::default());
+
+        Ok(())
+    }
+
     /// Resizes the data buffer.
     #[inline]
     pub fn resize(&mut self, offset: usize, len: isize) -> Result<()> {
@@ -269,6 +619,103 @@ impl Mbuf {
         self.read_data_slice(offset, count)
     }
 
+    /// Returns a copy of the packet in a new buffer.
+    ///
+    /// The data of all the segments is copied into the one segment of the
+    /// copy. The metadata, the packet type, the header lengths, the offload
+    /// flags and the port are carried over.
+    ///
+    /// # Errors
+    ///
+    /// If the packet does not fit in a single buffer, `BufferError` is
+    /// returned.
+    pub(crate) fn deep_copy(&self) -> Result<Mbuf> {
+        let mut copy = Mbuf::new()?;
+        copy.extend(0, self.pkt_len())?;
+
+        unsafe {
+            let mut dst = copy.data_address(0);
+            let mut seg = self.raw.as_ptr();
+            while !seg.is_null() {
+                let seg_len = (*seg).data_len as usize;
+                let src = ((*seg).buf_addr as *mut u8).offset((*seg).data_off as isize);
+                ptr::copy_nonoverlapping(src, dst, seg_len);
+                dst = dst.add(seg_len);
+                seg = (*seg).next;
+            }
+        }
+
+        // the flags of an attached buffer are of the buffer, not the packet.
+        copy.raw_mut().ol_flags = self.raw().ol_flags & !ATTACHED_MBUF_FLAGS;
+        copy.set_packet_type(self.packet_type());
+        let (l2_len, l3_len) = self.header_lens();
+        copy.set_header_lens(l2_len, l3_len);
+        copy.set_meta(self.meta());
+        if let Some(port_id) = self.port_id() {
+            copy.set_port_id(port_id);
+        }
+
+        Ok(copy)
+    }
+
+    /// Saves the state and the data of the buffer.
+    ///
+    /// A chained buffer is pulled up into its first segment first, so the
+    /// snapshot covers the whole packet.
+    ///
+    /// # Errors
+    ///
+    /// If the first segment does not have the tailroom for the rest of the
+    /// chain, `BufferError` is returned.
+    pub(crate) fn snapshot(&mut self) -> Result<Snapshot> {
+        if self.num_segments() > 1 {
+            let len = self.pkt_len();
+            self.pullup(0, len)?;
+        }
+
+        let raw = self.raw();
+        let data = unsafe { slice::from_raw_parts(self.data_address(0), self.data_len()) };
+
+        Ok(Snapshot {
+            raw: self.raw,
+            data_off: raw.data_off,
+            data_len: raw.data_len,
+            pkt_len: raw.pkt_len,
+            packet_type: self.packet_type(),
+            meta: self.meta(),
+            data: data.to_vec(),
+        })
+    }
+
+    /// Returns whether the snapshot is of this buffer.
+    #[inline]
+    pub(crate) fn is_snapshot_of(&self, snapshot: &Snapshot) -> bool {
+        self.raw == snapshot.raw && self.num_segments() == 1
+    }
+
+    /// Restores the buffer to the saved snapshot.
+    ///
+    /// Nothing is restored if the snapshot is not of this buffer.
+    pub(crate) fn restore(&mut self, snapshot: Snapshot) {
+        if !self.is_snapshot_of(&snapshot) {
+            return;
+        }
+
+        {
+            let raw = self.raw_mut();
+            raw.data_off = snapshot.data_off;
+            raw.data_len = snapshot.data_len;
+            raw.pkt_len = snapshot.pkt_len;
+        }
+        self.set_packet_type(snapshot.packet_type);
+        self.set_meta(snapshot.meta);
+
+        unsafe {
+            let dst = self.data_address(0);
+            ptr::copy_nonoverlapping(snapshot.data.as_ptr(), dst, snapshot.data.len());
+        }
+    }
+
     /// Acquires the underlying raw struct pointer.
     ///
     /// The `Mbuf` is consumed. It is the caller's the responsibility to
@@ -281,11 +728,19 @@ impl Mbuf {
     }
 
     /// Allocates a Vec of `Mbuf`s of `len` size.
+    ///
+    /// As with `Mbuf::new`, the buffers start with the headroom of the
+    /// mempool settings.
     pub fn alloc_bulk(len: usize) -> Result<Vec<Mbuf>> {
+        check_alloc(len)?;
+
         let mut ptrs = Vec::with_capacity(len);
         let mempool = MEMPOOL.with(|tls| tls.get());
+        if let Some(hit) = cache_hit(mempool, len) {
+            record_cache_lookup(hit);
+        }
 
-        let mbufs = unsafe {
+        let mut mbufs = unsafe {
             ffi::_rte_pktmbuf_alloc_bulk(mempool, ptrs.as_mut_ptr(), len as raw::c_uint)
                 .to_result()?;
 
@@ -294,6 +749,12 @@ impl Mbuf {
         };
 
         mem::forget(ptrs);
+
+        let headroom = HEADROOM.with(|tls| tls.get());
+        mbufs.iter_mut().for_each(|mbuf| {
+            mbuf.set_meta(PacketMeta::default());
+            mbuf.reserve_headroom(headroom);
+        });
         Ok(mbufs)
     }
 
@@ -370,6 +831,7 @@ unsafe impl Send for Mbuf {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dpdk::{Mempool, SocketId};
 
     const BUFFER: [u8; 16] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
 
@@ -382,6 +844,80 @@ mod tests {
         assert_eq!(BUFFER, slice);
     }
 
+    #[nb2::test]
+    fn prepend_and_adj_in_headroom() {
+        let mut mbuf = Mbuf::from_bytes(&BUFFER).unwrap();
+        let headroom = mbuf.headroom();
+
+        mbuf.prepend(4).unwrap();
+        assert_eq!(headroom - 4, mbuf.headroom());
+        assert_eq!(20, mbuf.data_len());
+        let slice = mbuf.read_data_slice::<u8>(4, 16).unwrap();
+        assert_eq!(BUFFER, unsafe { slice.as_ref() });
+
+        mbuf.adj(4).unwrap();
+        assert_eq!(headroom, mbuf.headroom());
+        assert_eq!(16, mbuf.data_len());
+        assert!(mbuf.prepend(headroom + 1).is_err());
+        assert!(mbuf.adj(17).is_err());
+    }
+
+    #[nb2::test]
+    fn extend_front_into_headroom() {
+        let mut mbuf = Mbuf::from_bytes(&BUFFER).unwrap();
+        let headroom = mbuf.headroom();
+
+        mbuf.extend(0, 4).unwrap();
+        assert_eq!(headroom - 4, mbuf.headroom());
+        let slice = mbuf.read_data_slice::<u8>(4, 16).unwrap();
+        assert_eq!(BUFFER, unsafe { slice.as_ref() });
+
+        mbuf.shrink(0, 4).unwrap();
+        assert_eq!(headroom, mbuf.headroom());
+        assert_eq!(16, mbuf.data_len());
+    }
+
+    #[nb2::test]
+    fn reserve_configured_headroom() {
+        assert!(Mempool::with_headroom(15, 0, 64, 2048, SocketId::ANY).is_err());
+
+        let mut mempool = Mempool::with_headroom(15, 0, 256, 2048, SocketId::ANY).unwrap();
+        let previous = MEMPOOL.with(|tls| tls.replace(mempool.raw_mut()));
+        HEADROOM.with(|tls| tls.set(256));
+
+        let mbuf = Mbuf::new().unwrap();
+        assert_eq!(256, mbuf.headroom());
+        assert!(mbuf.tailroom() >= 2048);
+        drop(mbuf);
+
+        HEADROOM.with(|tls| tls.set(ffi::RTE_PKTMBUF_HEADROOM as u16));
+        MEMPOOL.with(|tls| tls.set(previous));
+    }
+
+    #[nb2::test]
+    fn restore_snapshot() {
+        let mut mbuf = Mbuf::from_bytes(&BUFFER).unwrap();
+        let snapshot = mbuf.snapshot().unwrap();
+
+        mbuf.extend(4, 8).unwrap();
+        mbuf.write_data_slice(0, &[0u8; 24]).unwrap();
+        mbuf.restore(snapshot);
+
+        assert_eq!(16, mbuf.data_len());
+        let slice = mbuf.read_data_slice::<u8>(0, 16).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(BUFFER, slice);
+    }
+
+    #[nb2::test]
+    fn port_id_of_allocated_buffer() {
+        let mut mbuf = Mbuf::new().unwrap();
+        assert_eq!(None, mbuf.port_id());
+
+        mbuf.set_port_id(PortId::new(1));
+        assert_eq!(Some(PortId::new(1)), mbuf.port_id());
+    }
+
     #[nb2::test]
     fn extend_data_buffer_tail() {
         let mut mbuf = Mbuf::new().unwrap();
@@ -431,6 +967,19 @@ mod tests {
         assert!(mbuf.extend(0, 999_999).is_err());
     }
 
+    #[nb2::test]
+    fn forget_packet_type_on_insert() {
+        let mut mbuf = Mbuf::from_bytes(&BUFFER).unwrap();
+        mbuf.set_packet_type(PacketType(ffi::RTE_PTYPE_L2_ETHER));
+
+        // appending to the end keeps the layers where they are.
+        assert!(mbuf.extend(16, 4).is_ok());
+        assert!(!mbuf.packet_type().is_unknown());
+
+        assert!(mbuf.extend(0, 4).is_ok());
+        assert!(mbuf.packet_type().is_unknown());
+    }
+
     #[nb2::test]
     fn shrink_data_buffer_tail() {
         let mut mbuf = Mbuf::new().unwrap();
@@ -516,6 +1065,100 @@ mod tests {
         assert!(mbuf.read_data::<[u8; 16]>(10).is_err());
     }
 
+    /// Chains `tail` to the end of `head` as a second segment.
+    fn chain(mut head: Mbuf, tail: Mbuf) -> Mbuf {
+        let tail_len = tail.data_len() as u32;
+        let raw = head.raw_mut();
+        raw.next = tail.into_ptr();
+        raw.nb_segs += 1;
+        raw.pkt_len += tail_len;
+        head
+    }
+
+    #[nb2::test]
+    fn pullup_from_next_segment() {
+        let head = Mbuf::from_bytes(&BUFFER[..8]).unwrap();
+        let tail = Mbuf::from_bytes(&BUFFER[8..]).unwrap();
+        let mut mbuf = chain(head, tail);
+        assert_eq!(8, mbuf.data_len());
+        assert_eq!(16, mbuf.pkt_len());
+        assert_eq!(2, mbuf.num_segments());
+
+        // already contiguous
+        assert!(mbuf.pullup(0, 8).is_ok());
+        assert_eq!(8, mbuf.data_len());
+
+        // partially pulls up the second segment
+        assert!(mbuf.pullup(4, 8).is_ok());
+        assert_eq!(12, mbuf.data_len());
+        assert_eq!(16, mbuf.pkt_len());
+        assert_eq!(2, mbuf.num_segments());
+
+        let slice = mbuf.read_data_slice::<u8>(0, 12).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(BUFFER[..12], *slice);
+
+        // drains the second segment
+        assert!(mbuf.pullup(0, 16).is_ok());
+        assert_eq!(16, mbuf.data_len());
+        assert_eq!(1, mbuf.num_segments());
+
+        let slice = mbuf.read_data_slice::<u8>(0, 16).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(BUFFER, *slice);
+
+        // beyond the end of the buffer
+        assert!(mbuf.pullup(8, 16).is_err());
+    }
+
+    #[nb2::test]
+    fn snapshot_chained_buffer() {
+        let head = Mbuf::from_bytes(&BUFFER[..8]).unwrap();
+        let tail = Mbuf::from_bytes(&BUFFER[8..]).unwrap();
+        let mut mbuf = chain(head, tail);
+
+        // the chain is pulled up so the whole packet is saved.
+        let snapshot = mbuf.snapshot().unwrap();
+        assert_eq!(1, mbuf.num_segments());
+
+        mbuf.write_data_slice(8, &[0u8; 8]).unwrap();
+        mbuf.restore(snapshot);
+
+        let slice = mbuf.read_data_slice::<u8>(0, 16).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(BUFFER, *slice);
+    }
+
+    #[nb2::test]
+    fn deep_copy_chained_buffer() {
+        let head = Mbuf::from_bytes(&BUFFER[..8]).unwrap();
+        let tail = Mbuf::from_bytes(&BUFFER[8..]).unwrap();
+        let mut mbuf = chain(head, tail);
+        mbuf.set_header_lens(14, 20);
+        mbuf.set_meta(PacketMeta::default().with_mark(7));
+
+        let copy = mbuf.deep_copy().unwrap();
+        assert_eq!(1, copy.num_segments());
+        assert_eq!(16, copy.pkt_len());
+        assert_eq!((14, 20), copy.header_lens());
+        assert_eq!(7, copy.meta().mark);
+
+        let slice = copy.read_data_slice::<u8>(0, 16).unwrap();
+        let slice = unsafe { slice.as_ref() };
+        assert_eq!(BUFFER, *slice);
+    }
+
+    #[nb2::test]
+    fn restore_snapshot_of_other_buffer() {
+        let mut mbuf = Mbuf::from_bytes(&BUFFER).unwrap();
+        let snapshot = mbuf.snapshot().unwrap();
+
+        let mut other = Mbuf::from_bytes(&BUFFER[..8]).unwrap();
+        assert!(!other.is_snapshot_of(&snapshot));
+        other.restore(snapshot);
+        assert_eq!(8, other.data_len());
+    }
+
     #[nb2::test]
     fn read_and_write_data_slice() {
         let mut mbuf = Mbuf::new().unwrap();
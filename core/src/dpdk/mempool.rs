@@ -1,6 +1,7 @@
-use super::SocketId;
+use super::{CoreId, SocketId};
 use crate::ffi::{self, AsStr, ToCString, ToResult};
-use crate::{debug, Result};
+use crate::{debug, ensure, Result};
+use failure::Fail;
 use std::cell::Cell;
 use std::fmt;
 use std::os::raw;
@@ -10,10 +11,24 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 // A global counter used to generate a unique name for new mempools.
 static MEMPOOL_COUNT: AtomicUsize = AtomicUsize::new(0);
 
+/// Mempool errors.
+#[derive(Debug, Fail)]
+pub enum MempoolError {
+    /// The data room does not fit in the 16-bit buffer length.
+    #[fail(display = "Data room of {} bytes is too large.", _0)]
+    DataroomTooLarge(usize),
+
+    /// The headroom is smaller than the one of the received buffers.
+    #[fail(display = "Headroom of {} bytes is less than the default {}.", _0, _1)]
+    HeadroomTooSmall(usize, usize),
+}
+
 /// A memory pool is an allocator of message buffers, or `Mbuf`. For best
 /// performance, each socket should have a dedicated `Mempool`.
 pub struct Mempool {
     raw: NonNull<ffi::rte_mempool>,
+    headroom: usize,
+    dataroom: usize,
 }
 
 impl Mempool {
@@ -34,6 +49,73 @@ impl Mempool {
     ///
     /// If allocation fails, then `DpdkError` is returned.
     pub fn new(capacity: usize, cache_size: usize, socket_id: SocketId) -> Result<Self> {
+        Mempool::with_dataroom(
+            capacity,
+            cache_size,
+            ffi::RTE_MBUF_DEFAULT_DATAROOM as usize,
+            socket_id,
+        )
+    }
+
+    /// Creates a new `Mempool` for `Mbuf` with `dataroom` bytes of data
+    /// space in each buffer, not including the headroom.
+    ///
+    /// Use a larger data room for ports that receive jumbo frames, so each
+    /// frame fits in a single buffer.
+    ///
+    /// # Errors
+    ///
+    /// If the buffer size is over 64KB, `MempoolError::DataroomTooLarge` is
+    /// returned. If allocation fails, then `DpdkError` is returned.
+    pub fn with_dataroom(
+        capacity: usize,
+        cache_size: usize,
+        dataroom: usize,
+        socket_id: SocketId,
+    ) -> Result<Self> {
+        Mempool::with_headroom(
+            capacity,
+            cache_size,
+            ffi::RTE_PKTMBUF_HEADROOM as usize,
+            dataroom,
+            socket_id,
+        )
+    }
+
+    /// Creates a new `Mempool` for `Mbuf` with `headroom` bytes reserved
+    /// in front of the data and `dataroom` bytes of data space in each
+    /// buffer.
+    ///
+    /// The buffers the application allocates on the cores using the
+    /// `Mempool` start with the whole headroom, so outer headers as large
+    /// as the headroom can be pushed with `Mbuf::prepend`, without moving
+    /// the packet. The device places the received packets after the
+    /// default headroom of `RTE_PKTMBUF_HEADROOM` bytes regardless.
+    ///
+    /// # Errors
+    ///
+    /// If the headroom is less than `RTE_PKTMBUF_HEADROOM`,
+    /// `MempoolError::HeadroomTooSmall` is returned. If the buffer size is
+    /// over 64KB, `MempoolError::DataroomTooLarge` is returned. If
+    /// allocation fails, then `DpdkError` is returned.
+    pub fn with_headroom(
+        capacity: usize,
+        cache_size: usize,
+        headroom: usize,
+        dataroom: usize,
+        socket_id: SocketId,
+    ) -> Result<Self> {
+        ensure!(
+            headroom >= ffi::RTE_PKTMBUF_HEADROOM as usize,
+            MempoolError::HeadroomTooSmall(headroom, ffi::RTE_PKTMBUF_HEADROOM as usize)
+        );
+
+        let buf_size = dataroom + headroom;
+        ensure!(
+            buf_size <= u16::max_value() as usize,
+            MempoolError::DataroomTooLarge(dataroom)
+        );
+
         let n = MEMPOOL_COUNT.fetch_add(1, Ordering::Relaxed);
         let name = format!("mempool{}", n).to_cstring();
         let raw = unsafe {
@@ -42,13 +124,17 @@ impl Mempool {
                 capacity as raw::c_uint,
                 cache_size as raw::c_uint,
                 0,
-                ffi::RTE_MBUF_DEFAULT_BUF_SIZE as u16,
+                buf_size as u16,
                 socket_id.raw(),
             )
             .to_result()?
         };
 
-        Ok(Self { raw })
+        Ok(Self {
+            raw,
+            headroom,
+            dataroom,
+        })
     }
 
     /// Returns the raw struct needed for FFI calls.
@@ -63,6 +149,20 @@ impl Mempool {
         unsafe { self.raw.as_mut() }
     }
 
+    /// Returns the size of the headroom reserved in the buffers allocated
+    /// by the application.
+    #[inline]
+    pub fn headroom(&self) -> usize {
+        self.headroom
+    }
+
+    /// Returns the size of the data room of the buffers, not including the
+    /// headroom.
+    #[inline]
+    pub fn dataroom(&self) -> usize {
+        self.dataroom
+    }
+
     /// Returns the name of the `Mempool`.
     #[inline]
     pub fn name(&self) -> &str {
@@ -77,6 +177,8 @@ impl fmt::Debug for Mempool {
             .field("capacity", &raw.size)
             .field("populated", &raw.populated_size)
             .field("cache_size", &raw.cache_size)
+            .field("headroom", &self.headroom())
+            .field("dataroom", &self.dataroom())
             .field("flags", &format_args!("{:#x}", raw.flags))
             .field("socket", &raw.socket_id)
             .finish()
@@ -93,10 +195,35 @@ impl Drop for Mempool {
     }
 }
 
+/// Returns whether allocating `n` objects on the current core can be
+/// served from the core's cache of the mempool, without going to the
+/// shared pool. `None` if the mempool has no cache for the core.
+///
+/// Mirrors the check in `rte_mempool_generic_get`. Requests as large as
+/// the cache bypass it, and a cache that holds fewer than `n` objects is
+/// refilled from the shared pool.
+#[inline]
+pub(crate) fn cache_hit(mempool: *const ffi::rte_mempool, n: usize) -> Option<bool> {
+    let lcore_id = CoreId::current().raw() as usize;
+    let mempool = unsafe { mempool.as_ref()? };
+
+    if mempool.cache_size == 0 || lcore_id >= ffi::RTE_MAX_LCORE as usize {
+        return None;
+    }
+
+    let cache = unsafe { &*mempool.local_cache.add(lcore_id) };
+    Some(n < cache.size as usize && n <= cache.len as usize)
+}
+
 thread_local! {
     /// `Mempool` on the same socket as the current core.
     ///
     /// It's set when the core is first initialized. New `Mbuf` is allocated
     /// from this `Mempool` when executed on this core.
     pub static MEMPOOL: Cell<*mut ffi::rte_mempool> = Cell::new(ptr::null_mut());
+
+    /// Headroom of the `Mbuf` allocated on the current core.
+    ///
+    /// It's set along with `MEMPOOL`, to the headroom of that `Mempool`.
+    pub static HEADROOM: Cell<u16> = Cell::new(ffi::RTE_PKTMBUF_HEADROOM as u16);
 }
@@ -0,0 +1,135 @@
+use std::fmt;
+
+/// The color of a packet, as marked by a traffic meter.
+///
+/// The colors of the RFC 2697 and RFC 2698 markers. Green packets conform
+/// to the committed rate, yellow packets exceed it and red packets exceed
+/// the peak rate or burst.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Color {
+    Green,
+    Yellow,
+    Red,
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::Green
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::Green => write!(f, "green"),
+            Color::Yellow => write!(f, "yellow"),
+            Color::Red => write!(f, "red"),
+        }
+    }
+}
+
+/// The QoS metadata carried in the buffer along with the packet.
+///
+/// The metadata is the convention the operators use to pass the QoS
+/// decisions along the pipeline. A classifier sets the traffic class, a
+/// meter sets the color, a scheduler reads both, and the transmit stats
+/// are aggregated by the traffic class. The mark is free for the
+/// application to use, for example to tag the packets of a tenant.
+///
+/// The metadata is not part of the packet and is never transmitted. It is
+/// reset when a buffer is allocated or received, and kept when the packet
+/// is passed to another core through a ring.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct PacketMeta {
+    /// The traffic class, from `0` to `PacketMeta::CLASSES - 1`. `0` is
+    /// the default class.
+    pub traffic_class: u8,
+    /// The color marked by a meter. The default is green.
+    pub color: Color,
+    /// An application defined mark.
+    pub mark: u32,
+}
+
+impl PacketMeta {
+    /// The number of traffic classes.
+    pub const CLASSES: usize = 8;
+
+    /// Returns a copy with the traffic class set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `traffic_class` is not a valid class.
+    pub fn with_traffic_class(mut self, traffic_class: u8) -> Self {
+        assert!((traffic_class as usize) < PacketMeta::CLASSES);
+        self.traffic_class = traffic_class;
+        self
+    }
+
+    /// Returns a copy with the color set.
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Returns a copy with the mark set.
+    pub fn with_mark(mut self, mark: u32) -> Self {
+        self.mark = mark;
+        self
+    }
+
+    /*  The layout of the metadata word.
+
+         63              42 41 40 39     32 31                      0
+        +------------------+-----+---------+-------------------------+
+        |     reserved     |color|  class  |          mark           |
+        +------------------+-----+---------+-------------------------+
+    */
+
+    #[inline]
+    pub(crate) fn from_raw(raw: u64) -> Self {
+        let color = match (raw >> 40) & 0b11 {
+            1 => Color::Yellow,
+            2 => Color::Red,
+            _ => Color::Green,
+        };
+
+        PacketMeta {
+            traffic_class: (raw >> 32) as u8 & (PacketMeta::CLASSES as u8 - 1),
+            color,
+            mark: raw as u32,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn into_raw(self) -> u64 {
+        let color = match self.color {
+            Color::Green => 0,
+            Color::Yellow => 1,
+            Color::Red => 2,
+        };
+
+        (color << 40) | (u64::from(self.traffic_class) << 32) | u64::from(self.mark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meta_round_trip() {
+        let meta = PacketMeta::default()
+            .with_traffic_class(5)
+            .with_color(Color::Red)
+            .with_mark(0xdead_beef);
+
+        assert_eq!(meta, PacketMeta::from_raw(meta.into_raw()));
+        assert_eq!(PacketMeta::default(), PacketMeta::from_raw(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn invalid_traffic_class() {
+        PacketMeta::default().with_traffic_class(8);
+    }
+}
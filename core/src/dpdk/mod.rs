@@ -1,21 +1,36 @@
+mod failover;
+mod flow;
+mod hash;
 mod kni;
 mod mbuf;
 mod mempool;
+mod meta;
 mod port;
+mod ptype;
+mod reorder;
+mod ring;
 
+pub use self::failover::*;
+pub use self::flow::*;
+pub use self::hash::*;
 pub use self::kni::*;
 pub use self::mbuf::*;
 pub use self::mempool::*;
+pub use self::meta::*;
 pub use self::port::*;
+pub use self::ptype::*;
+pub use self::reorder::*;
+pub use self::ring::*;
 
 use crate::ffi::{self, AsStr, ToCString, ToResult};
 use crate::net::MacAddr;
-use crate::{debug, Result};
+use crate::{debug, error, info, warn, Result};
 use failure::Fail;
 use libc;
 use std::fmt;
 use std::mem;
 use std::os::raw;
+use std::slice;
 
 /// An error generated in `libdpdk`.
 ///
@@ -103,6 +118,13 @@ impl CoreId {
         unsafe { CoreId(ffi::_rte_lcore_id()) }
     }
 
+    /// Returns the numeric ID assigned to the core by the system.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    #[inline]
+    pub fn id(&self) -> usize {
+        self.0 as usize
+    }
+
     /// Returns the ID of the socket the core is on.
     #[allow(clippy::trivially_copy_pass_by_ref)]
     #[inline]
@@ -140,10 +162,64 @@ impl fmt::Debug for CoreId {
     }
 }
 
+/// Returns the current value of the time stamp counter.
+#[inline]
+pub fn tsc() -> u64 {
+    unsafe { ffi::_rte_rdtsc() }
+}
+
+/// Returns the number of time stamp counter cycles in one second.
+///
+/// Only valid once the EAL is initialized.
+#[inline]
+pub fn tsc_hz() -> u64 {
+    unsafe { ffi::rte_get_tsc_hz() }
+}
+
+/// Forwards a log message of `libdpdk` to the tracing subscriber, under
+/// the `dpdk` target.
+///
+/// The component the message is prefixed with, like `EAL` or `PMD`, is
+/// recorded as a field of its own.
+extern "C" fn forward_log(level: u32, _logtype: u32, msg: *const raw::c_char, len: libc::size_t) {
+    let msg = unsafe { slice::from_raw_parts(msg as *const u8, len as usize) };
+    let msg = String::from_utf8_lossy(msg);
+    let msg = msg.trim_end();
+    if msg.is_empty() {
+        return;
+    }
+
+    let (component, msg) = match msg.find(": ") {
+        Some(idx)
+            if idx > 0
+                && msg[..idx]
+                    .bytes()
+                    .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_') =>
+        {
+            (&msg[..idx], &msg[idx + 2..])
+        }
+        _ => ("", msg),
+    };
+
+    match level {
+        ffi::RTE_LOG_EMERG..=ffi::RTE_LOG_ERR => error!(target: "dpdk", %component, "{}", msg),
+        ffi::RTE_LOG_WARNING => warn!(target: "dpdk", %component, "{}", msg),
+        ffi::RTE_LOG_NOTICE | ffi::RTE_LOG_INFO => info!(target: "dpdk", %component, "{}", msg),
+        _ => debug!(target: "dpdk", %component, "{}", msg),
+    }
+}
+
 /// Initializes the Environment Abstraction Layer (EAL).
+///
+/// The log messages of `libdpdk` are routed to the tracing subscriber
+/// instead of stderr, before the EAL starts logging.
 pub fn eal_init(args: Vec<String>) -> Result<()> {
     debug!(arguments=?args);
 
+    if unsafe { ffi::_rte_openlog_callback(Some(forward_log)) } != 0 {
+        warn!("failed to route the DPDK logs, logging to stderr.");
+    }
+
     let len = args.len() as raw::c_int;
     let mut args = args
         .into_iter()
@@ -1,20 +1,40 @@
-use super::{CoreId, Kni, KniBuilder, KniTxQueue, Mbuf, SocketId};
+use super::{
+    ControlProtocol, CoreId, FlowError, Kni, KniBuilder, KniTxQueue, Mbuf, PacketMeta, SocketId,
+};
 use crate::ffi::{self, AsStr, ToCString, ToResult};
-use crate::net::MacAddr;
+use crate::journal::{self, Event};
+use crate::net::{Acl, AclAction, MacAddr};
+use crate::packets::{append_fcs, checksum, strip_fcs};
 use crate::runtime::MempoolMap2;
+use crate::stats::{
+    record_acl, record_class_tx, record_drop, record_drops, record_tx_dropped, record_tx_full,
+    record_tx_retry, reset_acl, AclCounts, DropReason,
+};
 use crate::{debug, ensure, info, warn, Result};
 use failure::Fail;
+use serde::Deserialize;
+use std::cell::{Cell, Ref, RefCell};
 use std::collections::HashMap;
 use std::fmt;
 use std::mem;
 use std::os::raw;
 use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// An opaque identifier for an ethernet device port.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Eq, Hash, PartialEq)]
 pub struct PortId(u16);
 
 impl PortId {
+    /// Creates a new `PortId` from the numeric ID assigned to the port
+    /// by DPDK.
+    #[inline]
+    pub(crate) fn new(id: u16) -> PortId {
+        PortId(id)
+    }
+
     /// Returns the ID of the socket the port is connected to.
     ///
     /// Virtual devices do not have real socket IDs. The value returned
@@ -45,12 +65,229 @@ impl fmt::Debug for PortId {
 }
 
 /// The index of a receive queue.
-#[derive(Copy, Clone)]
-struct RxQueueIndex(u16);
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RxQueueIndex(u16);
+
+impl RxQueueIndex {
+    /// Creates a new `RxQueueIndex`.
+    #[inline]
+    pub(crate) fn new(idx: u16) -> RxQueueIndex {
+        RxQueueIndex(idx)
+    }
+
+    /// Returns the raw value needed for FFI calls.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    #[inline]
+    pub(crate) fn raw(&self) -> u16 {
+        self.0
+    }
+}
 
 /// The index of a transmit queue.
-#[derive(Copy, Clone)]
-struct TxQueueIndex(u16);
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TxQueueIndex(u16);
+
+impl TxQueueIndex {
+    /// Creates a new `TxQueueIndex`.
+    #[inline]
+    pub(crate) fn new(idx: u16) -> TxQueueIndex {
+        TxQueueIndex(idx)
+    }
+
+    /// Returns the raw value needed for FFI calls.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    #[inline]
+    pub(crate) fn raw(&self) -> u16 {
+        self.0
+    }
+}
+
+/// The longest wait before a retry of a full transmit queue, however many
+/// times the wait has doubled. The core is held up while it waits.
+const TX_BACKOFF_MAX: Duration = Duration::from_micros(100);
+
+/// How a full transmit queue is retried. Shared by the queues of a port,
+/// so it can be changed while the port runs.
+pub(crate) struct TxBackoff {
+    retries: AtomicU32,
+    cycles: AtomicU64,
+    max_cycles: u64,
+}
+
+impl TxBackoff {
+    fn new(retries: u32, backoff: Duration) -> Self {
+        let tx_backoff = TxBackoff {
+            retries: AtomicU32::new(0),
+            cycles: AtomicU64::new(0),
+            max_cycles: TX_BACKOFF_MAX.as_micros() as u64 * super::tsc_hz() / 1_000_000,
+        };
+        tx_backoff.set(retries, backoff);
+        tx_backoff
+    }
+
+    fn set(&self, retries: u32, backoff: Duration) {
+        let cycles = backoff.as_micros() as u64 * super::tsc_hz() / 1_000_000;
+        self.cycles.store(cycles, Ordering::Relaxed);
+        self.retries.store(retries, Ordering::Relaxed);
+    }
+}
+
+/// The ingress ACL of a port. Shared by the queues of the port, so it can
+/// be replaced while the port runs.
+#[derive(Default)]
+pub(crate) struct IngressAcl {
+    // bumped every time the list changes, so the queues only take the
+    // lock when there's a new list.
+    version: AtomicUsize,
+    acl: Mutex<Option<Arc<Acl>>>,
+}
+
+impl IngressAcl {
+    #[inline]
+    fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
+    fn get(&self) -> Option<Arc<Acl>> {
+        self.acl.lock().unwrap().clone()
+    }
+
+    fn set(&self, port_id: PortId, acl: Option<Acl>) {
+        *self.acl.lock().unwrap() = acl.map(Arc::new);
+        self.version.fetch_add(1, Ordering::Release);
+        // the counters are by position, they'd count for the wrong rules.
+        reset_acl(port_id);
+    }
+}
+
+/// The ingress ACL of a port as of the last burst received from a queue.
+#[derive(Clone)]
+struct AclCache {
+    acl: Arc<IngressAcl>,
+    version: Cell<usize>,
+    cached: RefCell<Option<Arc<Acl>>>,
+}
+
+impl AclCache {
+    fn new(acl: Arc<IngressAcl>) -> Self {
+        let version = acl.version();
+        let cached = acl.get();
+
+        AclCache {
+            acl,
+            version: Cell::new(version),
+            cached: RefCell::new(cached),
+        }
+    }
+
+    /// Returns the list attached, picking up the new list if it has been
+    /// replaced since the last burst. Costs a single atomic load when it
+    /// has not.
+    #[inline]
+    fn get(&self) -> Ref<'_, Option<Arc<Acl>>> {
+        let version = self.acl.version();
+        if version != self.version.get() {
+            *self.cached.borrow_mut() = self.acl.get();
+            self.version.set(version);
+        }
+        self.cached.borrow()
+    }
+}
+
+/// A handle to the ingress ACL of a port, to replace the list from outside
+/// the pipelines, for example from a control plane thread.
+#[derive(Clone)]
+pub struct AclHandle {
+    port_id: PortId,
+    acl: Arc<IngressAcl>,
+}
+
+impl AclHandle {
+    /// Returns the id of the port, to read the counters of the ACL.
+    pub fn port_id(&self) -> PortId {
+        self.port_id
+    }
+
+    /// Attaches the ACL to the port, replacing the one attached before, or
+    /// detaches it with `None`, as with `Port::set_acl`.
+    pub fn set(&self, acl: Option<Acl>) {
+        self.acl.set(self.port_id, acl);
+    }
+}
+
+/// Fixes up the checksums of a burst to transmit, and drops the packets
+/// that cannot be fixed up as malformed.
+fn fixup_checksums(mbufs: Vec<Mbuf>) -> Vec<Mbuf> {
+    mbufs
+        .into_iter()
+        .filter_map(|mbuf| match checksum::fixup(mbuf) {
+            Ok(mbuf) => Some(mbuf),
+            Err(err) => {
+                debug!(message = "failed to fix up checksums.", ?err);
+                record_drop(DropReason::Malformed);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Applies the ACL to a received burst, and returns the packets that go on
+/// to the pipelines.
+fn apply_acl(port_id: PortId, acl: &Acl, mbufs: Vec<Mbuf>) -> Vec<Mbuf> {
+    let mut passed = Vec::with_capacity(mbufs.len());
+    let mut denied = vec![];
+    // the counts of the burst by rule, recorded once per burst.
+    let mut tally: Vec<(Option<usize>, AclCounts)> = vec![];
+
+    for mut mbuf in mbufs {
+        let verdict = match acl.classify(&mbuf) {
+            Some(verdict) => verdict,
+            None => {
+                passed.push(mbuf);
+                continue;
+            }
+        };
+
+        let idx = match tally.iter().position(|(rule, _)| *rule == verdict.rule) {
+            Some(idx) => idx,
+            None => {
+                tally.push((verdict.rule, AclCounts::default()));
+                tally.len() - 1
+            }
+        };
+        let counts = &mut tally[idx].1;
+
+        match verdict.action {
+            AclAction::Permit => {
+                counts.permitted += 1;
+                passed.push(mbuf);
+            }
+            AclAction::Deny => {
+                counts.denied += 1;
+                denied.push(mbuf);
+            }
+            AclAction::Mark(mark) => {
+                counts.marked += 1;
+                mbuf.set_meta(mbuf.meta().with_mark(mark));
+                passed.push(mbuf);
+            }
+        }
+    }
+
+    for (rule, counts) in tally.iter() {
+        record_acl(port_id, *rule, counts);
+    }
+
+    if !denied.is_empty() {
+        record_drops(DropReason::AclDeny, denied.len() as u64);
+        Mbuf::free_bulk(denied);
+    }
+
+    passed
+}
+
+/// The maximum number of packets received from a queue at a time.
+pub(crate) const RX_BURST_MAX: usize = 32;
 
 /// The receive and transmit queue abstraction. Instead of modeling them
 /// as two standalone queues, in the run-to-completion mode, they are modeled
@@ -62,37 +299,91 @@ pub struct PortQueue {
     rxq_index: RxQueueIndex,
     txq_index: TxQueueIndex,
     kni: Option<KniTxQueue>,
+    strip_fcs: bool,
+    append_fcs: bool,
+    fixup_checksums: bool,
+    tx_backoff: Arc<TxBackoff>,
+    acl: AclCache,
 }
 
 impl PortQueue {
     /// Receives a burst of packets from the receive queue, up to a maximum
     /// of 32 packets.
     #[allow(clippy::trivially_copy_pass_by_ref)]
+    #[inline]
     pub(crate) fn receive(&self) -> Vec<Mbuf> {
-        const RX_BURST_MAX: usize = 32;
-        let mut ptrs = Vec::with_capacity(RX_BURST_MAX);
+        self.receive_burst(RX_BURST_MAX)
+    }
+
+    /// Receives a burst of packets from the receive queue, up to a maximum
+    /// of `max` packets.
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub(crate) fn receive_burst(&self, max: usize) -> Vec<Mbuf> {
+        let mut ptrs = Vec::with_capacity(max);
 
         let len = unsafe {
             ffi::_rte_eth_rx_burst(
                 self.port_id.0,
                 self.rxq_index.0,
                 ptrs.as_mut_ptr(),
-                RX_BURST_MAX as u16,
+                max as u16,
             )
         };
 
-        let mbufs = unsafe {
+        let mut mbufs = unsafe {
             // does a no-copy conversion to avoid extra allocation.
-            Vec::from_raw_parts(ptrs.as_mut_ptr() as *mut Mbuf, len as usize, RX_BURST_MAX)
+            Vec::from_raw_parts(ptrs.as_mut_ptr() as *mut Mbuf, len as usize, max)
         };
 
         mem::forget(ptrs);
+
+        // the buffers come back from the device with the metadata and the
+        // header lengths of their previous packets. the device sets the
+        // packet type, but not the lengths.
+        mbufs.iter_mut().for_each(|mbuf| {
+            mbuf.set_meta(PacketMeta::default());
+            mbuf.set_header_lens(0, 0);
+        });
+
+        if self.strip_fcs {
+            mbufs.iter_mut().for_each(|mbuf| {
+                if let Err(err) = strip_fcs(mbuf) {
+                    debug!(message = "failed to strip fcs.", ?err);
+                }
+            });
+        }
+
+        if let Some(acl) = self.acl.get().as_ref() {
+            mbufs = apply_acl(self.port_id, acl, mbufs);
+        }
+
         mbufs
     }
 
     /// Sends the packets to the transmit queue.
+    ///
+    /// If the queue takes none of the packets, the burst is offered again
+    /// up to the configured number of retries, with the wait doubled after
+    /// every retry. The packets still not sent after that are dropped.
     #[allow(clippy::trivially_copy_pass_by_ref)]
     pub(crate) fn transmit(&self, mut packets: Vec<Mbuf>) {
+        // the checksums go first, the FCS covers them.
+        if self.fixup_checksums {
+            packets = fixup_checksums(packets);
+        }
+
+        record_class_tx(&packets);
+
+        if self.append_fcs {
+            packets.iter_mut().for_each(|mbuf| {
+                if let Err(err) = append_fcs(mbuf) {
+                    debug!(message = "failed to append fcs.", ?err);
+                }
+            });
+        }
+
+        let max_retries = self.tx_backoff.retries.load(Ordering::Relaxed);
+        let mut retries = 0;
         loop {
             let to_send = packets.len() as u16;
             let sent = unsafe {
@@ -106,6 +397,9 @@ impl PortQueue {
             };
 
             if sent > 0 {
+                // the queue is draining, so the backoff starts over.
+                retries = 0;
+
                 if to_send - sent > 0 {
                     // still have packets not sent. tx queue is full but still making
                     // progress. we will keep trying until all packets are sent. drains
@@ -120,10 +414,22 @@ impl PortQueue {
                     mem::forget(packets);
                     break;
                 }
+            } else if retries < max_retries {
+                // tx queue is full, waits for the device to catch up before
+                // trying again.
+                record_tx_retry(self.port_id, self.txq_index);
+                let cycles = self.tx_backoff.cycles.load(Ordering::Relaxed);
+                let wait = (cycles << retries.min(16)).min(self.tx_backoff.max_cycles);
+                let until = super::tsc() + wait;
+                while super::tsc() < until {}
+                retries += 1;
             } else {
                 // tx queue is full and we can't make progress, start dropping packets
                 // to avoid potentially stuck in an endless loop.
                 warn!("tx full, dropped {} packets.", to_send);
+                record_tx_full(self.port_id, self.txq_index);
+                record_tx_dropped(self.port_id, self.txq_index, u64::from(to_send));
+                record_drops(DropReason::TxFull, u64::from(to_send));
                 Mbuf::free_bulk(packets);
                 break;
             }
@@ -140,6 +446,26 @@ impl PortQueue {
     pub fn mac_addr(&self) -> MacAddr {
         super::eth_macaddr_get(self.port_id.0)
     }
+
+    /// Returns the ID of the port.
+    pub fn port_id(&self) -> PortId {
+        self.port_id
+    }
+
+    /// Returns the link state of the port, read at the time of the call
+    /// without waiting for the link to settle.
+    pub fn link(&self) -> LinkInfo {
+        link_info(self.port_id)
+    }
+
+    /// Attaches the ingress ACL to the port, replacing the one attached
+    /// before, or detaches it with `None`, as with `Port::set_acl`.
+    ///
+    /// The list is shared by all the queues of the port, so a pipeline of
+    /// any of them can replace it while the port runs.
+    pub fn set_acl(&self, acl: Option<Acl>) {
+        self.acl.acl.set(self.port_id, acl);
+    }
 }
 
 /// Error indicating failed to initialize the port.
@@ -161,6 +487,37 @@ pub enum PortError {
     /// assigned to the port.
     #[fail(display = "Insufficient number of TX queues '{}'.", _0)]
     InsufficientTxQueues(usize),
+
+    /// The device can't be configured to keep the FCS on receive.
+    #[fail(display = "Keeping the FCS is not supported.")]
+    KeepFcsNotSupported,
+
+    /// The ports are not bound to any common cores.
+    #[fail(display = "Ports {:?} do not share any cores.", _0)]
+    NoSharedCore(Vec<String>),
+}
+
+/// How the ethernet frame check sequence of received frames is handled.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RxFcs {
+    /// The device strips the FCS. This is the default.
+    Strip,
+
+    /// The device keeps the FCS at the end of the frame. The device must
+    /// support the keep CRC receive offload.
+    Keep,
+
+    /// The device delivers the FCS as is but it should be stripped, for
+    /// example when replaying a trace captured with the FCS through
+    /// `net_pcap`. The FCS is removed in software.
+    SoftStrip,
+}
+
+impl Default for RxFcs {
+    fn default() -> Self {
+        RxFcs::Strip
+    }
 }
 
 /// An ethernet device port.
@@ -171,6 +528,11 @@ pub struct Port {
     queues: HashMap<CoreId, PortQueue>,
     kni: Option<Kni>,
     dev_info: ffi::rte_eth_dev_info,
+    flows: Vec<*mut ffi::rte_flow>,
+    tx_backoff: Arc<TxBackoff>,
+    acl: Arc<IngressAcl>,
+    // whether the link was up when last checked.
+    link_up: Cell<bool>,
 }
 
 impl Port {
@@ -187,6 +549,19 @@ impl Port {
         super::eth_macaddr_get(self.id.0)
     }
 
+    /// Returns the link state of the port, read at the time of the call
+    /// without waiting for the link to settle.
+    pub fn link(&self) -> LinkInfo {
+        link_info(self.id)
+    }
+
+    /// Returns whether the device classifies the L3 and L4 types of the
+    /// received packets, filling in `Mbuf::packet_type`.
+    pub fn has_packet_types(&self) -> bool {
+        let mask = ffi::RTE_PTYPE_L3_MASK | ffi::RTE_PTYPE_L4_MASK;
+        unsafe { ffi::rte_eth_dev_get_supported_ptypes(self.id.0, mask, ptr::null_mut(), 0) > 0 }
+    }
+
     /// Returns the available port queues.
     pub fn queues(&self) -> &HashMap<CoreId, PortQueue> {
         &self.queues
@@ -197,6 +572,40 @@ impl Port {
         self.kni.as_mut()
     }
 
+    /// Sets how a burst is retried when a transmit queue of the port is
+    /// full, as with `PortBuilder::tx_backoff`.
+    ///
+    /// Can be changed while the port runs. The queues pick up the change
+    /// with their next burst.
+    pub fn set_tx_backoff(&self, retries: u32, backoff: Duration) {
+        self.tx_backoff.set(retries, backoff);
+    }
+
+    /// Attaches the ingress ACL to the port, replacing the one attached
+    /// before, or detaches it with `None`.
+    ///
+    /// The list is applied to the packets as they are received, before any
+    /// pipeline sees them, the way the ACL of a switch port is. The denied
+    /// packets are dropped and recorded as `DropReason::AclDeny`, and the
+    /// marked ones carry the mark in their `PacketMeta`. The packets are
+    /// counted by rule in `stats::acl_stats`, and the counts are cleared
+    /// when the list is replaced.
+    ///
+    /// Can be changed while the port runs. The queues pick up the change
+    /// with their next burst.
+    pub fn set_acl(&self, acl: Option<Acl>) {
+        self.acl.set(self.id, acl);
+    }
+
+    /// Returns a handle to the ingress ACL of the port, that can be sent
+    /// to another thread.
+    pub fn acl_handle(&self) -> AclHandle {
+        AclHandle {
+            port_id: self.id,
+            acl: self.acl.clone(),
+        }
+    }
+
     /// Starts the port. This is the final step before packets can be
     /// received or transmitted on this port. Promiscuous mode is also
     /// enabled automatically.
@@ -211,6 +620,57 @@ impl Port {
         }
 
         info!("started port {}.", self.name());
+        self.check_link();
+        Ok(())
+    }
+
+    /// Records in the journal whether the link went up or down since it
+    /// was last checked.
+    ///
+    /// The runtime checks the links of the started ports periodically, so
+    /// the journal has the link changes and not only the start and the
+    /// stop of the ports.
+    pub(crate) fn check_link(&self) {
+        let up = self.link().up;
+        if up != self.link_up.replace(up) {
+            let port = self.name().to_owned();
+            if up {
+                info!("link of port {} is up.", port);
+                journal::record_event(Event::PortUp { port });
+            } else {
+                warn!("link of port {} is down.", port);
+                journal::record_event(Event::PortDown { port });
+            }
+        }
+    }
+
+    /// Steers the control protocols to the receive queue of a core, so
+    /// the other cores of the port never see the control traffic.
+    ///
+    /// The steering is done in the NIC with `rte_flow` rules, so not every
+    /// device supports it. The rules are removed when the port is dropped.
+    ///
+    /// # Errors
+    ///
+    /// If the port has no queue on the core, `FlowError::QueueNotFound` is
+    /// returned. If the device rejects a rule, `FlowError::Rejected` is
+    /// returned and none of the rules for that protocol are installed.
+    pub fn steer(&mut self, protocols: &[ControlProtocol], core_id: CoreId) -> Result<()> {
+        let rxq_index = self
+            .queues
+            .get(&core_id)
+            .map(|q| q.rxq_index)
+            .ok_or_else(|| FlowError::QueueNotFound(core_id))?;
+
+        for &protocol in protocols {
+            let flows = super::flow::steer(self.id, protocol, rxq_index)?;
+            self.flows.extend(flows);
+            info!(
+                "steered {:?} on port {} to {:?}.",
+                protocol, self.name, core_id
+            );
+        }
+
         Ok(())
     }
 
@@ -221,6 +681,12 @@ impl Port {
         }
 
         info!("stopped port {}.", self.name());
+        // a link already down was recorded when it went down.
+        if self.link_up.replace(false) {
+            journal::record_event(Event::PortDown {
+                port: self.name().to_owned(),
+            });
+        }
     }
 }
 
@@ -245,12 +711,143 @@ impl Drop for Port {
     fn drop(&mut self) {
         debug!("freeing {}.", self.name);
 
+        super::flow::destroy(self.id, &self.flows);
+
         unsafe {
             ffi::rte_eth_dev_close(self.id.0);
         }
     }
 }
 
+/// The link state of a port.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LinkInfo {
+    pub up: bool,
+    /// The negotiated speed, in Mbps.
+    pub speed: u32,
+    pub full_duplex: bool,
+    pub autoneg: bool,
+}
+
+impl fmt::Display for LinkInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.up {
+            let duplex = if self.full_duplex { "full" } else { "half" };
+            write!(f, "up {}Mbps {}-duplex", self.speed, duplex)
+        } else {
+            write!(f, "down")
+        }
+    }
+}
+
+/// Returns the link state of the port, without waiting for the link to
+/// settle.
+pub(crate) fn link_info(port_id: PortId) -> LinkInfo {
+    let mut link = ffi::rte_eth_link::default();
+    unsafe {
+        ffi::rte_eth_link_get_nowait(port_id.0, &mut link);
+    }
+
+    LinkInfo {
+        up: link.link_status() != 0,
+        speed: link.link_speed,
+        full_duplex: link.link_duplex() != 0,
+        autoneg: link.link_autoneg() != 0,
+    }
+}
+
+/// A port detected by the EAL, whether the application uses it or not.
+#[derive(Clone, Debug)]
+pub struct PortInfo {
+    pub port_id: PortId,
+    /// The device name, either the PCI address or the name of the virtual
+    /// device.
+    pub device: String,
+    pub driver: String,
+    /// The PCI address, `None` for a virtual device.
+    pub pci_addr: Option<String>,
+    pub mac: MacAddr,
+    /// The link state when the inventory was taken.
+    pub link: LinkInfo,
+    pub socket_id: Option<SocketId>,
+    pub max_rx_queues: u16,
+    pub max_tx_queues: u16,
+    pub max_rxd: u16,
+    pub max_txd: u16,
+}
+
+impl PortInfo {
+    fn new(port_id: PortId) -> Self {
+        let mut dev_info = ffi::rte_eth_dev_info::default();
+        let mut name = [0 as raw::c_char; ffi::RTE_ETH_NAME_MAX_LEN as usize];
+        unsafe {
+            ffi::rte_eth_dev_info_get(port_id.0, &mut dev_info);
+            ffi::rte_eth_dev_get_name_by_port(port_id.0, name.as_mut_ptr());
+        }
+
+        let device = (name.as_ptr() as *const raw::c_char).as_str().to_owned();
+        let pci_addr = if is_pci_addr(&device) {
+            Some(device.clone())
+        } else {
+            None
+        };
+
+        PortInfo {
+            port_id,
+            device,
+            driver: dev_info.driver_name.as_str().to_owned(),
+            pci_addr,
+            mac: super::eth_macaddr_get(port_id.0),
+            link: link_info(port_id),
+            socket_id: port_id.socket_id(),
+            max_rx_queues: dev_info.max_rx_queues,
+            max_tx_queues: dev_info.max_tx_queues,
+            max_rxd: dev_info.rx_desc_lim.nb_max,
+            max_txd: dev_info.tx_desc_lim.nb_max,
+        }
+    }
+}
+
+impl fmt::Display for PortInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} ({}) {} link {}, {:?}, {} rxq {} txq",
+            self.port_id,
+            self.device,
+            self.driver,
+            self.mac,
+            self.link,
+            self.socket_id,
+            self.max_rx_queues,
+            self.max_tx_queues
+        )
+    }
+}
+
+/// Returns whether the device name is a PCI address, like `0000:02:00.0`.
+fn is_pci_addr(device: &str) -> bool {
+    let parts = device.split(|c| c == ':' || c == '.').collect::<Vec<_>>();
+    parts.len() == 4
+        && [4, 2, 2, 1]
+            .iter()
+            .zip(&parts)
+            .all(|(&len, part)| part.len() == len && part.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Returns the ports the EAL detected.
+///
+/// Only valid once the EAL is initialized.
+pub fn port_inventory() -> Vec<PortInfo> {
+    let mut ports = vec![];
+    let mut id = unsafe { ffi::rte_eth_find_next(0) };
+    while u32::from(id) < ffi::RTE_MAX_ETHPORTS {
+        ports.push(PortInfo::new(PortId(id)));
+        id = unsafe { ffi::rte_eth_find_next(id + 1) };
+    }
+    ports
+}
+
 /// Builds a port from the configuration values.
 pub struct PortBuilder<'a> {
     name: String,
@@ -261,6 +858,11 @@ pub struct PortBuilder<'a> {
     mempools: MempoolMap2<'a>,
     rxd: u16,
     txd: u16,
+    rx_fcs: RxFcs,
+    tx_append_fcs: bool,
+    tx_fixup_checksums: bool,
+    tx_retries: u32,
+    tx_backoff: Duration,
 }
 
 impl<'a> PortBuilder<'a> {
@@ -297,6 +899,11 @@ impl<'a> PortBuilder<'a> {
             mempools: Default::default(),
             rxd: 0,
             txd: 0,
+            rx_fcs: RxFcs::Strip,
+            tx_append_fcs: false,
+            tx_fixup_checksums: false,
+            tx_retries: 0,
+            tx_backoff: Duration::from_micros(0),
         })
     }
 
@@ -366,6 +973,63 @@ impl<'a> PortBuilder<'a> {
         Ok(self)
     }
 
+    /// Sets how the ethernet FCS is handled.
+    ///
+    /// `rx` sets the receive side behavior. When `tx_append` is `true`, the
+    /// FCS is computed and appended in software before the packets are
+    /// transmitted, for backends that don't do it themselves, such as
+    /// `net_pcap` and `net_tap`.
+    ///
+    /// # Errors
+    ///
+    /// If `rx` is `RxFcs::Keep` but the device does not support keeping
+    /// the FCS, `PortError` is returned.
+    pub fn fcs(&mut self, rx: RxFcs, tx_append: bool) -> Result<&mut Self> {
+        if rx == RxFcs::Keep {
+            ensure!(
+                self.dev_info.rx_offload_capa & u64::from(ffi::DEV_RX_OFFLOAD_KEEP_CRC) != 0,
+                PortError::KeepFcsNotSupported
+            );
+        }
+
+        self.rx_fcs = rx;
+        self.tx_append_fcs = tx_append;
+        Ok(self)
+    }
+
+    /// Sets whether the IPv4, TCP and UDP checksums are recomputed in
+    /// software before the packets are transmitted.
+    ///
+    /// Emulates checksum offload for backends without it, such as
+    /// `net_pcap` and `net_tap`, so packets built without computing their
+    /// checksums aren't emitted broken.
+    pub fn tx_checksum_fixup(&mut self, fixup: bool) -> &mut Self {
+        self.tx_fixup_checksums = fixup;
+        self
+    }
+
+    /// Sets how a burst is retried when the transmit queue is full.
+    ///
+    /// When the queue takes none of the packets, the burst is offered again
+    /// up to `retries` times, busy waiting `backoff` before the first retry
+    /// and twice as long before each one after, up to 100 microseconds. A
+    /// retry holds up the core, so it trades latency for fewer drops on
+    /// short congestion. The default is no retries.
+    pub fn tx_backoff(&mut self, retries: u32, backoff: Duration) -> &mut Self {
+        self.tx_retries = retries;
+        self.tx_backoff = backoff;
+        self
+    }
+
+    /// Returns the socket the port is connected to.
+    ///
+    /// If the port is virtual, it is the socket of the first assigned core.
+    pub fn socket_id(&self) -> SocketId {
+        self.port_id
+            .socket_id()
+            .unwrap_or_else(|| self.cores[0].socket_id())
+    }
+
     /// Sets the available mempools.
     pub fn mempools(&'a mut self, mempools: MempoolMap2<'a>) -> &'a mut Self {
         self.mempools = mempools;
@@ -376,19 +1040,18 @@ impl<'a> PortBuilder<'a> {
     #[allow(clippy::cognitive_complexity)]
     pub fn finish(&mut self, with_kni: bool) -> Result<Port> {
         let len = self.cores.len() as u16;
-        let conf = ffi::rte_eth_conf::default();
+        let mut conf = ffi::rte_eth_conf::default();
+
+        if self.rx_fcs == RxFcs::Keep {
+            conf.rxmode.offloads |= u64::from(ffi::DEV_RX_OFFLOAD_KEEP_CRC);
+        }
 
         // must configure the device first before everything else.
         unsafe {
             ffi::rte_eth_dev_configure(self.port_id.0, len, len, &conf).to_result()?;
         }
 
-        // if the port is virtual, we will allocate it to the socket of
-        // the first assigned core.
-        let socket_id = self
-            .port_id
-            .socket_id()
-            .unwrap_or_else(|| self.cores[0].socket_id());
+        let socket_id = self.socket_id();
         debug!("{} connected to {:?}.", self.name, socket_id);
 
         // the socket determines which pool to allocate mbufs from.
@@ -407,6 +1070,9 @@ impl<'a> PortBuilder<'a> {
             None
         };
 
+        let tx_backoff = Arc::new(TxBackoff::new(self.tx_retries, self.tx_backoff));
+        let acl = Arc::new(IngressAcl::default());
+
         let mut queues = HashMap::new();
 
         // for each core, we setup a rx/tx queue pair. for simplicity, we
@@ -423,7 +1089,7 @@ impl<'a> PortBuilder<'a> {
             );
 
             // configures the RX queue with defaults
-            let rxq_index = RxQueueIndex(idx as u16);
+            let rxq_index = RxQueueIndex::new(idx as u16);
             unsafe {
                 ffi::rte_eth_rx_queue_setup(
                     self.port_id.0,
@@ -437,7 +1103,7 @@ impl<'a> PortBuilder<'a> {
             }
 
             // configures the TX queue with defaults
-            let txq_index = TxQueueIndex(idx as u16);
+            let txq_index = TxQueueIndex::new(idx as u16);
             unsafe {
                 ffi::rte_eth_tx_queue_setup(
                     self.port_id.0,
@@ -454,6 +1120,11 @@ impl<'a> PortBuilder<'a> {
                 rxq_index,
                 txq_index,
                 kni: kni.as_ref().map(|v| v.txq()),
+                strip_fcs: self.rx_fcs == RxFcs::SoftStrip,
+                append_fcs: self.tx_append_fcs,
+                fixup_checksums: self.tx_fixup_checksums,
+                tx_backoff: tx_backoff.clone(),
+                acl: AclCache::new(acl.clone()),
             };
 
             queues.insert(core_id, queue);
@@ -469,6 +1140,28 @@ impl<'a> PortBuilder<'a> {
             queues,
             kni,
             dev_info: self.dev_info,
+            flows: vec![],
+            tx_backoff,
+            acl,
+            link_up: Cell::new(false),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats;
+    use crate::testils::byte_arrays::TCP_PACKET;
+
+    #[nb2::test]
+    fn drop_malformed_on_checksum_fixup() {
+        let before = stats::drop_stats().get(DropReason::Malformed);
+
+        let truncated = Mbuf::from_bytes(&TCP_PACKET[..TCP_PACKET.len() - 4]).unwrap();
+        let fixed = fixup_checksums(vec![truncated, Mbuf::from_bytes(&TCP_PACKET).unwrap()]);
+
+        assert_eq!(1, fixed.len());
+        assert_eq!(1, stats::drop_stats().get(DropReason::Malformed) - before);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::ffi;
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+use crate::packets::{EtherType, EtherTypes};
+use std::fmt;
+
+/// The packet type of a received packet, as classified by the device.
+///
+/// Devices that classify in hardware fill the type in while receiving, so
+/// the layers are known without reading the headers. Not every device
+/// does, and the ones that do may only know some of the layers. Each
+/// accessor returns `None` for the layer the device did not classify, and
+/// the caller falls back to reading the headers. Use `Port::has_packet_types`
+/// to find out whether a device classifies at all.
+///
+/// The type describes the packet as received. The buffer forgets it once
+/// a header is inserted or removed.
+#[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
+pub struct PacketType(pub u32);
+
+impl PacketType {
+    /// Returns whether the device did not classify the packet.
+    #[inline]
+    pub fn is_unknown(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether the packet is a plain ethernet frame, without VLAN
+    /// tags or other L2 headers before the L3 header.
+    #[inline]
+    pub fn is_plain_ether(self) -> bool {
+        self.0 & ffi::RTE_PTYPE_L2_MASK == ffi::RTE_PTYPE_L2_ETHER
+    }
+
+    /// Returns whether the packet is tunneled, in which case the L3 and L4
+    /// types are of the outer headers.
+    #[inline]
+    pub fn is_tunnel(self) -> bool {
+        self.0 & ffi::RTE_PTYPE_TUNNEL_MASK != 0
+    }
+
+    /// Returns the ether type of the L3 header.
+    #[inline]
+    pub fn l3(self) -> Option<EtherType> {
+        match self.0 & ffi::RTE_PTYPE_L3_MASK {
+            ffi::RTE_PTYPE_L3_IPV4
+            | ffi::RTE_PTYPE_L3_IPV4_EXT
+            | ffi::RTE_PTYPE_L3_IPV4_EXT_UNKNOWN => Some(EtherTypes::Ipv4),
+            ffi::RTE_PTYPE_L3_IPV6
+            | ffi::RTE_PTYPE_L3_IPV6_EXT
+            | ffi::RTE_PTYPE_L3_IPV6_EXT_UNKNOWN => Some(EtherTypes::Ipv6),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the L3 header is known to have no IPv4 options or
+    /// IPv6 extension headers, so the L4 header follows at a fixed offset.
+    #[inline]
+    pub fn is_l3_fixed_len(self) -> bool {
+        match self.0 & ffi::RTE_PTYPE_L3_MASK {
+            ffi::RTE_PTYPE_L3_IPV4 | ffi::RTE_PTYPE_L3_IPV6 => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the protocol of the L4 header.
+    #[inline]
+    pub fn l4(self) -> Option<ProtocolNumber> {
+        match self.0 & ffi::RTE_PTYPE_L4_MASK {
+            ffi::RTE_PTYPE_L4_TCP => Some(ProtocolNumbers::Tcp),
+            ffi::RTE_PTYPE_L4_UDP => Some(ProtocolNumbers::Udp),
+            ffi::RTE_PTYPE_L4_SCTP => Some(ProtocolNumbers::Sctp),
+            ffi::RTE_PTYPE_L4_ICMP => match self.l3() {
+                Some(EtherTypes::Ipv6) => Some(ProtocolNumbers::Icmpv6),
+                _ => Some(ProtocolNumbers::Icmpv4),
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns whether the packet is an IP fragment.
+    #[inline]
+    pub fn is_fragment(self) -> bool {
+        self.0 & ffi::RTE_PTYPE_L4_MASK == ffi::RTE_PTYPE_L4_FRAG
+    }
+}
+
+impl fmt::Debug for PacketType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("packet_type")
+            .field("l3", &self.l3())
+            .field("l4", &self.l4())
+            .field("tunnel", &self.is_tunnel())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv4_tcp_packet_type() {
+        let ptype =
+            PacketType(ffi::RTE_PTYPE_L2_ETHER | ffi::RTE_PTYPE_L3_IPV4 | ffi::RTE_PTYPE_L4_TCP);
+
+        assert!(!ptype.is_unknown());
+        assert!(ptype.is_plain_ether());
+        assert!(ptype.is_l3_fixed_len());
+        assert_eq!(Some(EtherTypes::Ipv4), ptype.l3());
+        assert_eq!(Some(ProtocolNumbers::Tcp), ptype.l4());
+    }
+
+    #[test]
+    fn ipv6_icmp_packet_type() {
+        let ptype = PacketType(
+            ffi::RTE_PTYPE_L2_ETHER | ffi::RTE_PTYPE_L3_IPV6_EXT_UNKNOWN | ffi::RTE_PTYPE_L4_ICMP,
+        );
+
+        assert!(!ptype.is_l3_fixed_len());
+        assert_eq!(Some(EtherTypes::Ipv6), ptype.l3());
+        assert_eq!(Some(ProtocolNumbers::Icmpv6), ptype.l4());
+    }
+
+    #[test]
+    fn unknown_packet_type() {
+        let ptype = PacketType::default();
+
+        assert!(ptype.is_unknown());
+        assert_eq!(None, ptype.l3());
+        assert_eq!(None, ptype.l4());
+    }
+}
@@ -0,0 +1,243 @@
+use super::{Mbuf, SocketId};
+use crate::batch::PacketTx;
+use crate::ffi::{self, ToCString, ToResult};
+use crate::{debug, ensure, Result};
+use failure::Fail;
+use std::fmt;
+use std::mem;
+use std::os::raw;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// A global counter used to generate a unique name for new reorder buffers.
+static REORDER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Error indicating the size of the reorder buffer is not a power of 2.
+#[derive(Debug, Fail)]
+#[fail(display = "Reorder buffer size {} is not a power of 2.", _0)]
+pub struct ReorderSizeError(usize);
+
+/// A buffer that puts the packets back in the order of their sequence
+/// numbers, a wrapper of `librte_reorder`.
+///
+/// The buffer holds a window of `size` sequence numbers. Packets inserted
+/// ahead of the next expected sequence number are held until the gap is
+/// filled, or until the window has to move forward to make room, in which
+/// case the gap is skipped.
+pub struct ReorderBuffer {
+    raw: NonNull<ffi::rte_reorder_buffer>,
+    size: usize,
+}
+
+impl ReorderBuffer {
+    /// Creates a new reorder buffer with a window of `size` packets.
+    ///
+    /// `socket_id` is the socket where the memory should be allocated.
+    ///
+    /// # Errors
+    ///
+    /// If `size` is not a power of 2, `ReorderSizeError` is returned. If
+    /// allocation fails, then `DpdkError` is returned.
+    pub fn new(size: usize, socket_id: SocketId) -> Result<Self> {
+        ensure!(size.is_power_of_two(), ReorderSizeError(size));
+
+        let n = REORDER_COUNT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("reorder{}", n).to_cstring();
+        let raw = unsafe {
+            ffi::rte_reorder_create(
+                name.as_ptr(),
+                socket_id.raw() as raw::c_uint,
+                size as raw::c_uint,
+            )
+            .to_result()?
+        };
+
+        Ok(ReorderBuffer { raw, size })
+    }
+
+    /// Returns the size of the window.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Inserts the packet into the buffer.
+    ///
+    /// # Errors
+    ///
+    /// If the packet's sequence number is too old or too far ahead of the
+    /// window, or the buffer is full, the packet is returned.
+    pub fn insert(&mut self, mbuf: Mbuf) -> std::result::Result<(), Mbuf> {
+        let ptr = mbuf.into_ptr();
+        let res = unsafe { ffi::rte_reorder_insert(self.raw.as_ptr(), ptr) };
+
+        if res == 0 {
+            Ok(())
+        } else {
+            // the buffer did not take ownership of the packet.
+            Err(NonNull::new(ptr).unwrap().into())
+        }
+    }
+
+    /// Removes the packets that are in order from the front of the buffer,
+    /// up to a maximum of `size` packets.
+    pub fn drain(&mut self) -> Vec<Mbuf> {
+        let mut ptrs = Vec::<*mut ffi::rte_mbuf>::with_capacity(self.size);
+
+        let len = unsafe {
+            ffi::rte_reorder_drain(
+                self.raw.as_ptr(),
+                ptrs.as_mut_ptr(),
+                self.size as raw::c_uint,
+            )
+        };
+
+        let mbufs = unsafe {
+            // does a no-copy conversion to avoid extra allocation.
+            Vec::from_raw_parts(ptrs.as_mut_ptr() as *mut Mbuf, len as usize, self.size)
+        };
+
+        mem::forget(ptrs);
+
+        mbufs
+    }
+}
+
+impl fmt::Debug for ReorderBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReorderBuffer")
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Drop for ReorderBuffer {
+    fn drop(&mut self) {
+        debug!("freeing reorder buffer.");
+
+        // also frees the packets still in the buffer.
+        unsafe {
+            ffi::rte_reorder_free(self.raw.as_ptr());
+        }
+    }
+}
+
+// the buffer is only used by one core at a time.
+unsafe impl Send for ReorderBuffer {}
+
+/// A transmit that restores the order of the packets stamped by
+/// `Batch::sequence` before passing them on to the underlying transmit.
+///
+/// Use it at the TX stage, after the packets have been spread across
+/// parallel workers with `Batch::distribute`.
+///
+/// # Example
+///
+/// ```
+/// let (tx, rx) = Ring::new(1024, SocketId::current())?.split();
+///
+/// runtime.add_pipeline_to_core(1, move |qs| {
+///     Poll::new(qs["eth0"].clone())
+///         .sequence()
+///         .distribute(Distribution::Weighted(vec![1, 1]), vec![w1.clone(), w2.clone()])
+/// })?;
+/// // workers on cores 2 and 3 process the packets and send them into `tx`.
+/// runtime.add_pipeline_to_core(4, move |qs| {
+///     let reorder = ReorderTx::new(qs["eth1"].clone(), 1024, SocketId::current()).unwrap();
+///     batch::splice(rx.clone(), reorder)
+/// })?;
+/// ```
+///
+/// # Remarks
+///
+/// A packet dropped by a worker leaves a gap in the sequence, which holds
+/// back the packets behind it until the window moves past the gap. Packets
+/// that do not fit in the window are transmitted right away, out of order,
+/// rather than dropped.
+pub struct ReorderTx<Tx: PacketTx> {
+    buffer: ReorderBuffer,
+    tx: Tx,
+}
+
+impl<Tx: PacketTx> ReorderTx<Tx> {
+    /// Creates a new reorder transmit in front of `tx`, with a window of
+    /// `size` packets.
+    ///
+    /// # Errors
+    ///
+    /// If `size` is not a power of 2, `ReorderSizeError` is returned. If
+    /// allocation fails, then `DpdkError` is returned.
+    pub fn new(tx: Tx, size: usize, socket_id: SocketId) -> Result<Self> {
+        let buffer = ReorderBuffer::new(size, socket_id)?;
+        Ok(ReorderTx { buffer, tx })
+    }
+
+    /// Inserts the packets into the reorder buffer and transmits the ones
+    /// that are in order.
+    pub(crate) fn transmit(&mut self, packets: Vec<Mbuf>) {
+        let mut late = vec![];
+
+        for packet in packets {
+            if let Err(packet) = self.buffer.insert(packet) {
+                late.push(packet);
+            }
+        }
+
+        if !late.is_empty() {
+            self.tx.transmit(late);
+        }
+
+        let ready = self.buffer.drain();
+        if !ready.is_empty() {
+            self.tx.transmit(ready);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stamped(seqn: u32) -> Mbuf {
+        let mut mbuf = Mbuf::new().unwrap();
+        mbuf.set_seqn(seqn);
+        mbuf
+    }
+
+    #[nb2::test]
+    fn size_must_be_power_of_two() {
+        assert!(ReorderBuffer::new(100, SocketId::ANY).is_err());
+    }
+
+    #[nb2::test]
+    fn restores_order() {
+        let mut buffer = ReorderBuffer::new(8, SocketId::ANY).unwrap();
+
+        buffer.insert(stamped(0)).unwrap();
+        buffer.insert(stamped(2)).unwrap();
+        buffer.insert(stamped(3)).unwrap();
+
+        // held back by the gap at 1.
+        let ready = buffer.drain();
+        assert_eq!(vec![0], ready.iter().map(Mbuf::seqn).collect::<Vec<_>>());
+
+        buffer.insert(stamped(1)).unwrap();
+        let ready = buffer.drain();
+        assert_eq!(
+            vec![1, 2, 3],
+            ready.iter().map(Mbuf::seqn).collect::<Vec<_>>()
+        );
+    }
+
+    #[nb2::test]
+    fn reorder_tx() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut reorder = ReorderTx::new(tx, 8, SocketId::ANY).unwrap();
+
+        reorder.transmit(vec![stamped(1), stamped(0), stamped(3)]);
+        reorder.transmit(vec![stamped(2)]);
+
+        let seqns = rx.try_iter().map(|mbuf| mbuf.seqn()).collect::<Vec<_>>();
+        assert_eq!(vec![0, 1, 2, 3], seqns);
+    }
+}
@@ -0,0 +1,383 @@
+use super::port::RX_BURST_MAX;
+use super::{Mbuf, PortQueue, SocketId};
+use crate::ffi::{self, AsStr, ToCString, ToResult};
+use crate::stats::{record_drops, DropReason};
+use crate::{debug, Result};
+use std::fmt;
+use std::mem;
+use std::os::raw;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// A global counter used to generate a unique name for new rings.
+static RING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// lets the ring hold exactly the requested number of packets instead of
+// the next power of two minus one. not covered by the bindgen whitelist.
+const RING_F_EXACT_SZ: raw::c_uint = 0x0004;
+
+/// The maximum number of packets dequeued from the ring at a time.
+const RING_BURST_MAX: usize = RX_BURST_MAX;
+
+/// What the producer does when the consumer is falling behind.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Backpressure {
+    /// The producer keeps enqueuing. The upstream receive is expected to
+    /// check `RingTx::is_congested` and slow down, for example with
+    /// `ThrottledRx`.
+    Throttle,
+    /// The producer drops the packets while the ring is congested and
+    /// records them as `DropReason::Backpressure`.
+    DropEarly,
+}
+
+struct RingInner {
+    raw: NonNull<ffi::rte_ring>,
+    capacity: usize,
+    high: usize,
+    low: usize,
+    policy: Backpressure,
+    congested: AtomicBool,
+}
+
+impl RingInner {
+    #[inline]
+    fn count(&self) -> usize {
+        unsafe { ffi::_rte_ring_count(self.raw.as_ptr()) as usize }
+    }
+
+    /// Updates the congestion state with hysteresis, so it does not flap
+    /// when the ring hovers around a single threshold.
+    #[inline]
+    fn is_congested(&self) -> bool {
+        let count = self.count();
+        let congested = self.congested.load(Ordering::Relaxed);
+
+        if !congested && count >= self.high {
+            self.congested.store(true, Ordering::Relaxed);
+            true
+        } else if congested && count <= self.low {
+            self.congested.store(false, Ordering::Relaxed);
+            false
+        } else {
+            congested
+        }
+    }
+
+    #[inline]
+    fn dequeue(&self) -> Vec<Mbuf> {
+        self.dequeue_n(RING_BURST_MAX)
+    }
+
+    fn dequeue_n(&self, max: usize) -> Vec<Mbuf> {
+        let mut ptrs = Vec::<*mut ffi::rte_mbuf>::with_capacity(max);
+
+        let len = unsafe {
+            ffi::_rte_ring_dequeue_burst(
+                self.raw.as_ptr(),
+                ptrs.as_mut_ptr() as *mut *mut raw::c_void,
+                max as raw::c_uint,
+                ptr::null_mut(),
+            )
+        };
+
+        let mbufs = unsafe {
+            // does a no-copy conversion to avoid extra allocation.
+            Vec::from_raw_parts(ptrs.as_mut_ptr() as *mut Mbuf, len as usize, max)
+        };
+
+        mem::forget(ptrs);
+
+        mbufs
+    }
+}
+
+impl Drop for RingInner {
+    fn drop(&mut self) {
+        let name = unsafe { self.raw.as_ref().name[..].as_str() };
+        debug!("freeing {}.", name);
+
+        // frees the packets still in the ring.
+        loop {
+            let packets = self.dequeue();
+            if packets.is_empty() {
+                break;
+            }
+            Mbuf::free_bulk(packets);
+        }
+
+        unsafe {
+            ffi::rte_ring_free(self.raw.as_ptr());
+        }
+    }
+}
+
+// the ring is created multi-producer and multi-consumer safe, and the
+// congestion state is atomic.
+unsafe impl Send for RingInner {}
+unsafe impl Sync for RingInner {}
+
+/// A lockless FIFO of packets for chaining pipelines across cores.
+///
+/// One pipeline sends the packets into the ring with the `RingTx` half and
+/// another pipeline, usually on a different core, polls them out with the
+/// `RingRx` half.
+///
+/// The ring has a high and a low watermark. When the ring fills past the
+/// high watermark, the consumer is considered congested until the ring
+/// drains back below the low watermark. What the producer does about the
+/// congestion is set by the `Backpressure` policy. Without watermarks,
+/// packets are only dropped when the ring is full. Either way the drops
+/// are recorded in the drop stats.
+///
+/// # Example
+///
+/// ```
+/// let (tx, rx) = Ring::new(1024, SocketId::current())?
+///     .watermarks(768, 256)
+///     .backpressure(Backpressure::Throttle)
+///     .split();
+///
+/// runtime.add_pipeline_to_core(1, move |qs| {
+///     Poll::new(ThrottledRx::new(qs["eth0"].clone(), tx.clone())).send(tx.clone())
+/// })?;
+/// runtime.add_pipeline_to_core(2, move |qs| batch::splice(rx.clone(), qs["eth1"].clone()))?;
+/// ```
+pub struct Ring {
+    inner: RingInner,
+}
+
+impl Ring {
+    /// Creates a new `Ring` that holds up to `capacity` packets.
+    ///
+    /// `socket_id` is the socket where the memory should be allocated. For
+    /// best performance, it should be the socket of the consumer core.
+    ///
+    /// # Errors
+    ///
+    /// If allocation fails, then `DpdkError` is returned.
+    pub fn new(capacity: usize, socket_id: SocketId) -> Result<Self> {
+        let n = RING_COUNT.fetch_add(1, Ordering::Relaxed);
+        let name = format!("ring{}", n).to_cstring();
+        let raw = unsafe {
+            ffi::rte_ring_create(
+                name.as_ptr(),
+                capacity as raw::c_uint,
+                socket_id.raw(),
+                RING_F_EXACT_SZ,
+            )
+            .to_result()?
+        };
+
+        Ok(Ring {
+            inner: RingInner {
+                raw,
+                capacity,
+                high: capacity,
+                low: capacity,
+                policy: Backpressure::Throttle,
+                congested: AtomicBool::new(false),
+            },
+        })
+    }
+
+    /// Sets the high and low watermarks, in number of packets.
+    ///
+    /// The watermarks are capped at the capacity of the ring and `low` is
+    /// capped at `high`.
+    pub fn watermarks(mut self, high: usize, low: usize) -> Self {
+        self.inner.high = high.min(self.inner.capacity);
+        self.inner.low = low.min(self.inner.high);
+        self
+    }
+
+    /// Sets what the producer does when the ring is congested. The default
+    /// is `Backpressure::Throttle`.
+    pub fn backpressure(mut self, policy: Backpressure) -> Self {
+        self.inner.policy = policy;
+        self
+    }
+
+    /// Splits the ring into the producer and consumer halves.
+    ///
+    /// Both halves can be cloned, to have multiple producers or consumers.
+    pub fn split(self) -> (RingTx, RingRx) {
+        let inner = Arc::new(self.inner);
+
+        let rx = RingRx {
+            inner: inner.clone(),
+        };
+        (RingTx { inner }, rx)
+    }
+}
+
+impl fmt::Debug for Ring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = &self.inner;
+        let raw = unsafe { inner.raw.as_ref() };
+        f.debug_struct(raw.name[..].as_str())
+            .field("capacity", &inner.capacity)
+            .field("high", &inner.high)
+            .field("low", &inner.low)
+            .field("policy", &inner.policy)
+            .finish()
+    }
+}
+
+/// The producer half of a `Ring`.
+#[derive(Clone)]
+pub struct RingTx {
+    inner: Arc<RingInner>,
+}
+
+impl RingTx {
+    /// Returns the number of packets in the ring.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Returns whether the consumer is congested.
+    ///
+    /// The ring becomes congested when it fills past the high watermark,
+    /// and stays congested until it drains below the low watermark.
+    #[inline]
+    pub fn is_congested(&self) -> bool {
+        self.inner.is_congested()
+    }
+
+    /// Enqueues the packets into the ring. The packets that do not fit
+    /// are dropped.
+    pub(crate) fn enqueue(&self, mut packets: Vec<Mbuf>) {
+        if packets.is_empty() {
+            return;
+        }
+
+        if self.inner.policy == Backpressure::DropEarly && self.is_congested() {
+            record_drops(DropReason::Backpressure, packets.len() as u64);
+            Mbuf::free_bulk(packets);
+            return;
+        }
+
+        let enqueued = unsafe {
+            ffi::_rte_ring_enqueue_burst(
+                self.inner.raw.as_ptr(),
+                packets.as_ptr() as *const *mut raw::c_void,
+                packets.len() as raw::c_uint,
+                ptr::null_mut(),
+            )
+        } as usize;
+
+        // ownership given to the ring, don't free them.
+        let sent = packets.drain(..enqueued).collect::<Vec<_>>();
+        mem::forget(sent);
+
+        if !packets.is_empty() {
+            record_drops(DropReason::RingFull, packets.len() as u64);
+            Mbuf::free_bulk(packets);
+        }
+    }
+}
+
+/// The consumer half of a `Ring`.
+#[derive(Clone)]
+pub struct RingRx {
+    inner: Arc<RingInner>,
+}
+
+impl RingRx {
+    /// Returns the number of packets in the ring.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Dequeues a burst of packets from the ring, up to a maximum of 32
+    /// packets.
+    #[inline]
+    pub(crate) fn dequeue(&self) -> Vec<Mbuf> {
+        self.inner.dequeue()
+    }
+}
+
+/// A port queue receive that slows down while a downstream ring is
+/// congested.
+///
+/// While the ring is congested, the receive burst is reduced to a quarter,
+/// so the excess packets back up into the NIC queue, where the drops are
+/// counted by the device, instead of overflowing the ring.
+pub struct ThrottledRx {
+    q: PortQueue,
+    ring: RingTx,
+}
+
+impl ThrottledRx {
+    /// Creates a new receive on the port queue that is throttled by the
+    /// ring.
+    pub fn new(q: PortQueue, ring: RingTx) -> Self {
+        ThrottledRx { q, ring }
+    }
+
+    /// Receives a burst of packets, a smaller one while the ring is
+    /// congested.
+    pub(crate) fn receive(&self) -> Vec<Mbuf> {
+        if self.ring.is_congested() {
+            self.q.receive_burst(RX_BURST_MAX / 4)
+        } else {
+            self.q.receive_burst(RX_BURST_MAX)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[nb2::test]
+    fn ring_full_drops() {
+        let (tx, rx) = Ring::new(4, SocketId::ANY).unwrap().split();
+
+        let packets = (0..6).map(|_| Mbuf::new().unwrap()).collect::<Vec<_>>();
+        tx.enqueue(packets);
+
+        assert_eq!(4, tx.count());
+        assert_eq!(4, rx.dequeue().len());
+        assert_eq!(0, rx.count());
+    }
+
+    #[nb2::test]
+    fn congestion_hysteresis() {
+        let (tx, rx) = Ring::new(8, SocketId::ANY)
+            .unwrap()
+            .watermarks(6, 2)
+            .split();
+
+        tx.enqueue((0..5).map(|_| Mbuf::new().unwrap()).collect());
+        assert!(!tx.is_congested());
+
+        tx.enqueue(vec![Mbuf::new().unwrap()]);
+        assert!(tx.is_congested());
+
+        // stays congested until drained to the low watermark.
+        Mbuf::free_bulk(rx.inner.dequeue_n(3));
+        assert!(tx.is_congested());
+        Mbuf::free_bulk(rx.inner.dequeue_n(1));
+        assert!(!tx.is_congested());
+    }
+
+    #[nb2::test]
+    fn drop_early_when_congested() {
+        let (tx, _rx) = Ring::new(8, SocketId::ANY)
+            .unwrap()
+            .watermarks(2, 1)
+            .backpressure(Backpressure::DropEarly)
+            .split();
+
+        tx.enqueue((0..2).map(|_| Mbuf::new().unwrap()).collect());
+        tx.enqueue((0..2).map(|_| Mbuf::new().unwrap()).collect());
+
+        // the second burst is dropped, the ring is past the high watermark.
+        assert_eq!(2, tx.count());
+    }
+}
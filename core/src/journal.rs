@@ -0,0 +1,169 @@
+//! A journal of the state changes of the dataplane.
+//!
+//! The runtime records when the links of the ports go up and down and when
+//! pipelines are installed, and the application records its own changes,
+//! such as the rules it adds or a failover. The latest events are kept in
+//! memory with the time they happened, to piece together what led to an
+//! incident on a long-running appliance. The journal can be queried at any
+//! time, and it is dumped to stderr if the process panics.
+
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic;
+use std::sync::{Mutex, Once};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of events kept in the journal. The oldest events are
+/// discarded first.
+pub const JOURNAL_CAPACITY: usize = 1024;
+
+/// A state change of the dataplane.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// The link of the port is up, once the port is started.
+    PortUp { port: String },
+    /// The link of the port is down, or the port is stopped.
+    PortDown { port: String },
+    /// A pipeline is installed, on a port or a core.
+    PipelineInstalled { target: String },
+    /// A rule is added to a table.
+    RuleAdded { table: String, rule: String },
+    /// The traffic fails over from one port to another.
+    Failover { from: String, to: String },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::PortUp { port } => write!(f, "port {} up", port),
+            Event::PortDown { port } => write!(f, "port {} down", port),
+            Event::PipelineInstalled { target } => write!(f, "pipeline installed for {}", target),
+            Event::RuleAdded { table, rule } => write!(f, "rule added to {}: {}", table, rule),
+            Event::Failover { from, to } => write!(f, "failover from {} to {}", from, to),
+        }
+    }
+}
+
+/// An event of the journal, with the time it was recorded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub timestamp: SystemTime,
+    pub event: Event,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let since_epoch = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        write!(
+            f,
+            "[{}.{:06}] {}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_micros(),
+            self.event
+        )
+    }
+}
+
+lazy_static! {
+    static ref JOURNAL: Mutex<VecDeque<Entry>> =
+        Mutex::new(VecDeque::with_capacity(JOURNAL_CAPACITY));
+}
+
+/// Records an event in the journal.
+///
+/// The journal is shared by all the cores, so it is meant for the state
+/// changes, not for events on the packet path.
+pub fn record_event(event: Event) {
+    let mut journal = JOURNAL.lock().unwrap();
+    if journal.len() == JOURNAL_CAPACITY {
+        journal.pop_front();
+    }
+    journal.push_back(Entry {
+        timestamp: SystemTime::now(),
+        event,
+    });
+}
+
+/// Returns the events in the journal, oldest first.
+pub fn entries() -> Vec<Entry> {
+    JOURNAL.lock().unwrap().iter().cloned().collect()
+}
+
+/// Returns the events recorded at or after `since`, oldest first.
+pub fn entries_since(since: SystemTime) -> Vec<Entry> {
+    JOURNAL
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|entry| entry.timestamp >= since)
+        .cloned()
+        .collect()
+}
+
+/// Dumps the journal to stderr when the process panics, before the
+/// previous panic hook runs.
+///
+/// The runtime installs the hook when it is built. Installing it more than
+/// once has no effect.
+pub fn dump_on_panic() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // the panicking thread may hold the lock, or have poisoned it.
+            match JOURNAL.try_lock() {
+                Ok(journal) => {
+                    eprintln!("journal of the last {} events:", journal.len());
+                    for entry in journal.iter() {
+                        eprintln!("{}", entry);
+                    }
+                }
+                Err(_) => eprintln!("journal is not available."),
+            }
+            previous(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_query_events() {
+        // the journal is shared with the other tests, so only the events
+        // of this test are checked.
+        let port = "journal-test".to_owned();
+        let mine = |entries: Vec<Entry>| {
+            entries
+                .into_iter()
+                .filter(|entry| match &entry.event {
+                    Event::PortUp { port: p } | Event::PortDown { port: p } => p == &port,
+                    _ => false,
+                })
+                .map(|entry| entry.event)
+                .collect::<Vec<_>>()
+        };
+
+        record_event(Event::PortUp { port: port.clone() });
+        let since = SystemTime::now();
+        record_event(Event::PortDown { port: port.clone() });
+
+        assert_eq!(
+            vec![
+                Event::PortUp { port: port.clone() },
+                Event::PortDown { port: port.clone() }
+            ],
+            mine(entries())
+        );
+        assert_eq!(
+            vec![Event::PortDown { port: port.clone() }],
+            mine(entries_since(since))
+        );
+    }
+}
@@ -5,19 +5,37 @@
 extern crate self as nb2;
 
 pub mod batch;
+pub mod bpf;
+#[cfg(feature = "grpc")]
+pub mod control;
 mod dpdk;
 mod ffi;
+pub mod journal;
 mod macros;
 pub mod net;
 pub mod packets;
 mod runtime;
 pub mod settings;
+mod shared;
+pub mod stats;
+pub mod tables;
 #[cfg(any(test, feature = "testils"))]
 pub mod testils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use self::batch::{Batch, Pipeline, Poll};
-pub use self::dpdk::{KniRx, KniTxQueue, Mbuf, PortQueue, SizeOf};
-pub use self::runtime::{Runtime, UnixSignal};
+pub use self::dpdk::{
+    AclHandle, Backpressure, Color, ConcurrentMatchTable, ControlProtocol, CoreId, ExactMatchTable,
+    FailoverPair, HashKey, KniRx, KniTxQueue, LinkInfo, Mbuf, PacketMeta, PacketType, PortId,
+    PortInfo, PortQueue, ReorderTx, Ring, RingRx, RingTx, RxChecksum, RxFcs, RxQueueIndex, SizeOf,
+    SocketId, ThrottledRx, TxQueueIndex,
+};
+pub use self::runtime::{
+    Check, CheckStatus, ExecutionError, ExecutionMode, Hugepages, IovaMode, MemoryError,
+    MemoryInfo, Runtime, UnixSignal, ValidationReport, WorkerQueue,
+};
+pub use self::shared::{Shared, SharedReader};
 #[cfg(any(test, feature = "testils"))]
 pub use nb2_macros::{bench, test};
 
@@ -0,0 +1,421 @@
+use super::{Cidr, Ipv4Cidr, Ipv6Cidr};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::ip::{IpPacket, ProtocolNumber, ProtocolNumbers};
+use crate::packets::{EtherTypes, Ethernet, Packet, Tcp, Udp};
+use crate::{Mbuf, Result};
+use failure::Fail;
+use std::net::IpAddr;
+
+/// Error indicating a rule of an access control list cannot be compiled.
+#[derive(Debug, Fail)]
+pub enum AclError {
+    /// The rule has both IPv4 and IPv6 prefixes.
+    #[fail(display = "Rule {} mixes IPv4 and IPv6 prefixes.", _0)]
+    MixedFamilies(usize),
+
+    /// The rule has ports, but not the TCP or UDP protocol.
+    #[fail(display = "Rule {} has ports without TCP or UDP as protocol.", _0)]
+    PortsWithoutProtocol(usize),
+
+    /// The rule has a port range with the first port after the last one.
+    #[fail(display = "Rule {} has an empty port range.", _0)]
+    EmptyPortRange(usize),
+}
+
+/// What is done with the packets matching a rule.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AclAction {
+    /// The packet goes on to the pipelines.
+    Permit,
+    /// The packet is dropped.
+    Deny,
+    /// The packet goes on to the pipelines, with the mark of its
+    /// `PacketMeta` set.
+    Mark(u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Prefix {
+    V4(Ipv4Cidr),
+    V6(Ipv6Cidr),
+}
+
+impl Prefix {
+    fn contains(&self, addr: IpAddr) -> bool {
+        match self {
+            Prefix::V4(cidr) => cidr.contains_ip(addr),
+            Prefix::V6(cidr) => cidr.contains_ip(addr),
+        }
+    }
+
+    fn is_v4(&self) -> bool {
+        match self {
+            Prefix::V4(_) => true,
+            Prefix::V6(_) => false,
+        }
+    }
+}
+
+/// A rule of an access control list. A rule with no criteria matches all
+/// the IP packets.
+///
+/// # Example
+///
+/// ```
+/// let ssh = AclRule::new(AclAction::Deny)
+///     .dst_v4("10.0.0.0/8".parse()?)
+///     .protocol(ProtocolNumbers::Tcp)
+///     .dst_ports(22, 22);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct AclRule {
+    action: AclAction,
+    src: Option<Prefix>,
+    dst: Option<Prefix>,
+    protocol: Option<ProtocolNumber>,
+    src_ports: Option<(u16, u16)>,
+    dst_ports: Option<(u16, u16)>,
+}
+
+impl AclRule {
+    /// Creates a rule with the action, matching all the IP packets.
+    pub fn new(action: AclAction) -> Self {
+        AclRule {
+            action,
+            src: None,
+            dst: None,
+            protocol: None,
+            src_ports: None,
+            dst_ports: None,
+        }
+    }
+
+    /// Matches the IPv4 packets from the prefix.
+    pub fn src_v4(mut self, cidr: Ipv4Cidr) -> Self {
+        self.src = Some(Prefix::V4(cidr));
+        self
+    }
+
+    /// Matches the IPv4 packets to the prefix.
+    pub fn dst_v4(mut self, cidr: Ipv4Cidr) -> Self {
+        self.dst = Some(Prefix::V4(cidr));
+        self
+    }
+
+    /// Matches the IPv6 packets from the prefix.
+    pub fn src_v6(mut self, cidr: Ipv6Cidr) -> Self {
+        self.src = Some(Prefix::V6(cidr));
+        self
+    }
+
+    /// Matches the IPv6 packets to the prefix.
+    pub fn dst_v6(mut self, cidr: Ipv6Cidr) -> Self {
+        self.dst = Some(Prefix::V6(cidr));
+        self
+    }
+
+    /// Matches the packets of the protocol. For IPv6, the protocol is the
+    /// next header of the fixed header, the extension headers are not
+    /// walked.
+    pub fn protocol(mut self, protocol: ProtocolNumber) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Matches the TCP or UDP packets from a port in `first..=last`.
+    pub fn src_ports(mut self, first: u16, last: u16) -> Self {
+        self.src_ports = Some((first, last));
+        self
+    }
+
+    /// Matches the TCP or UDP packets to a port in `first..=last`.
+    pub fn dst_ports(mut self, first: u16, last: u16) -> Self {
+        self.dst_ports = Some((first, last));
+        self
+    }
+
+    fn check(&self, index: usize) -> Result<()> {
+        let families = self
+            .src
+            .iter()
+            .chain(self.dst.iter())
+            .map(Prefix::is_v4)
+            .collect::<Vec<_>>();
+        if families.len() == 2 && families[0] != families[1] {
+            return Err(AclError::MixedFamilies(index).into());
+        }
+
+        if self.src_ports.is_some() || self.dst_ports.is_some() {
+            if self.protocol != Some(ProtocolNumbers::Tcp)
+                && self.protocol != Some(ProtocolNumbers::Udp)
+            {
+                return Err(AclError::PortsWithoutProtocol(index).into());
+            }
+
+            let empty = |range: Option<(u16, u16)>| range.map_or(false, |(f, l)| f > l);
+            if empty(self.src_ports) || empty(self.dst_ports) {
+                return Err(AclError::EmptyPortRange(index).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn matches(&self, key: &Key) -> bool {
+        let in_range = |range: Option<(u16, u16)>, port: Option<u16>| match range {
+            Some((first, last)) => port.map_or(false, |port| port >= first && port <= last),
+            None => true,
+        };
+
+        self.src.as_ref().map_or(true, |src| src.contains(key.src))
+            && self.dst.as_ref().map_or(true, |dst| dst.contains(key.dst))
+            && self
+                .protocol
+                .map_or(true, |protocol| protocol == key.protocol)
+            && in_range(self.src_ports, key.src_port)
+            && in_range(self.dst_ports, key.dst_port)
+    }
+}
+
+/// The fields of a packet the rules match on. The ports are unknown for
+/// the protocols other than TCP and UDP, and for the fragments other than
+/// the first one.
+struct Key {
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: ProtocolNumber,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+}
+
+impl Key {
+    fn of(mbuf: &Mbuf) -> Option<Key> {
+        let ethernet = mbuf.peek::<Ethernet>().ok()?;
+        match ethernet.ether_type() {
+            EtherTypes::Ipv4 => {
+                let ipv4 = ethernet.peek::<Ipv4>().ok()?;
+                let first = ipv4.fragment_offset() == 0;
+                Some(Key::with_ports(&*ipv4, first))
+            }
+            EtherTypes::Ipv6 => {
+                let ipv6 = ethernet.peek::<Ipv6>().ok()?;
+                Some(Key::with_ports(&*ipv6, true))
+            }
+            _ => None,
+        }
+    }
+
+    fn with_ports<E: IpPacket>(ip: &E, first: bool) -> Key {
+        let protocol = ip.next_proto();
+        let ports = if !first {
+            None
+        } else if protocol == ProtocolNumbers::Tcp {
+            ip.peek::<Tcp<E>>()
+                .ok()
+                .map(|tcp| (tcp.src_port(), tcp.dst_port()))
+        } else if protocol == ProtocolNumbers::Udp {
+            ip.peek::<Udp<E>>()
+                .ok()
+                .map(|udp| (udp.src_port(), udp.dst_port()))
+        } else {
+            None
+        };
+
+        Key {
+            src: ip.src(),
+            dst: ip.dst(),
+            protocol,
+            src_port: ports.map(|(src, _)| src),
+            dst_port: ports.map(|(_, dst)| dst),
+        }
+    }
+}
+
+/// The rule a packet matches, and the action of the rule.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AclVerdict {
+    /// The position of the rule in the list, or `None` for the default
+    /// action.
+    pub rule: Option<usize>,
+    pub action: AclAction,
+}
+
+/// A compiled access control list, matching the addresses, protocol and
+/// ports of IP packets.
+///
+/// The rules are checked in order and the first rule that matches decides,
+/// the way the ACLs of switches and routers do. The packets no rule matches
+/// get the default action. The rules are split by address family when
+/// compiled, so an IPv4 packet is never checked against an IPv6 rule.
+///
+/// The frames other than IPv4 and IPv6, ARP for one, are not subject to
+/// the list.
+///
+/// # Example
+///
+/// ```
+/// let acl = Acl::new(
+///     &[
+///         AclRule::new(AclAction::Permit).src_v4("10.0.0.0/8".parse()?),
+///         AclRule::new(AclAction::Mark(7)).protocol(ProtocolNumbers::Udp),
+///     ],
+///     AclAction::Deny,
+/// )?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Acl {
+    // the rules of each family, with their position in the list.
+    v4: Vec<(usize, AclRule)>,
+    v6: Vec<(usize, AclRule)>,
+    default: AclAction,
+    len: usize,
+}
+
+impl Acl {
+    /// Compiles the rules, with the action for the packets no rule
+    /// matches.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AclError` for the first rule that can never match.
+    pub fn new(rules: &[AclRule], default: AclAction) -> Result<Self> {
+        let mut v4 = vec![];
+        let mut v6 = vec![];
+
+        for (index, rule) in rules.iter().enumerate() {
+            rule.check(index)?;
+
+            let family = rule.src.as_ref().or_else(|| rule.dst.as_ref());
+            match family.map(Prefix::is_v4) {
+                Some(true) => v4.push((index, rule.clone())),
+                Some(false) => v6.push((index, rule.clone())),
+                None => {
+                    v4.push((index, rule.clone()));
+                    v6.push((index, rule.clone()));
+                }
+            }
+        }
+
+        Ok(Acl {
+            v4,
+            v6,
+            default,
+            len: rules.len(),
+        })
+    }
+
+    /// Returns the number of rules.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the rule the packet matches and its action, or `None` if
+    /// the packet is not an IP packet.
+    #[inline]
+    pub fn classify(&self, mbuf: &Mbuf) -> Option<AclVerdict> {
+        let key = Key::of(mbuf)?;
+        let rules = match key.src {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => &self.v6,
+        };
+
+        let verdict = rules
+            .iter()
+            .find(|(_, rule)| rule.matches(&key))
+            .map(|&(index, ref rule)| AclVerdict {
+                rule: Some(index),
+                action: rule.action,
+            })
+            .unwrap_or(AclVerdict {
+                rule: None,
+                action: self.default,
+            });
+
+        Some(verdict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testils::byte_arrays::{IPV6_PACKET, TCP_PACKET, UDP_PACKET};
+
+    #[test]
+    fn reject_invalid_rules() {
+        let mixed = AclRule::new(AclAction::Deny)
+            .src_v4("10.0.0.0/8".parse().unwrap())
+            .dst_v6("2001:db8::/32".parse().unwrap());
+        assert!(Acl::new(&[mixed], AclAction::Permit).is_err());
+
+        let ports = AclRule::new(AclAction::Deny).dst_ports(22, 22);
+        assert!(Acl::new(&[ports], AclAction::Permit).is_err());
+
+        let empty = AclRule::new(AclAction::Deny)
+            .protocol(ProtocolNumbers::Tcp)
+            .dst_ports(80, 22);
+        assert!(Acl::new(&[empty], AclAction::Permit).is_err());
+    }
+
+    #[nb2::test]
+    fn first_matching_rule_decides() {
+        let acl = Acl::new(
+            &[
+                AclRule::new(AclAction::Mark(7)).protocol(ProtocolNumbers::Udp),
+                AclRule::new(AclAction::Permit)
+                    .src_v4("0.0.0.0/0".parse().unwrap())
+                    .protocol(ProtocolNumbers::Tcp)
+                    .dst_ports(0, 1023),
+                AclRule::new(AclAction::Deny).protocol(ProtocolNumbers::Tcp),
+            ],
+            AclAction::Permit,
+        )
+        .unwrap();
+
+        let udp = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        assert_eq!(
+            Some(AclVerdict {
+                rule: Some(0),
+                action: AclAction::Mark(7)
+            }),
+            acl.classify(&udp)
+        );
+
+        // telnet, to port 23.
+        let tcp = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        assert_eq!(
+            Some(AclVerdict {
+                rule: Some(1),
+                action: AclAction::Permit
+            }),
+            acl.classify(&tcp)
+        );
+    }
+
+    #[nb2::test]
+    fn default_action_and_non_ip() {
+        let acl = Acl::new(
+            &[AclRule::new(AclAction::Permit).src_v4("10.0.0.0/8".parse().unwrap())],
+            AclAction::Deny,
+        )
+        .unwrap();
+
+        let ipv6 = Mbuf::from_bytes(&IPV6_PACKET).unwrap();
+        assert_eq!(
+            Some(AclVerdict {
+                rule: None,
+                action: AclAction::Deny
+            }),
+            acl.classify(&ipv6)
+        );
+
+        // an ethernet frame with the ether type 0.
+        let frame = Mbuf::from_bytes(&[0; 64]).unwrap();
+        assert_eq!(None, acl.classify(&frame));
+    }
+}
@@ -0,0 +1,184 @@
+use super::sketch::{hash_key64, mix64, nth_hash, split_hash};
+use crate::{ensure, Result};
+use failure::Fail;
+use std::hash::Hash;
+
+// bits per block, one 64-byte cache line.
+const BLOCK_BITS: usize = 512;
+const BLOCK_WORDS: usize = BLOCK_BITS / 64;
+
+/// Error indicating filters of different sizes cannot be merged.
+#[derive(Debug, Fail)]
+#[fail(display = "Filter sizes {} and {} do not match.", _0, _1)]
+pub struct FilterMismatch(pub usize, pub usize);
+
+/// A blocked Bloom filter, an approximate set with no false negatives.
+///
+/// All the bits of a key are in the same 512-bit block, so a lookup or an
+/// insert touches a single cache line, whatever the number of hashes. The
+/// block and the bits are picked by independent hashes of the key. The
+/// price is a slightly higher false positive rate than a classic Bloom
+/// filter of the same size. Keys cannot be removed, use a `CuckooFilter`
+/// for that.
+///
+/// The filter is meant to be owned by a single pipeline and updated
+/// without synchronization. The filters of several cores can be merged
+/// into their union.
+///
+/// # Example
+///
+/// ```
+/// let mut seen = BloomFilter::with_rate(1_000_000, 0.001);
+///
+/// batch.filter(move |tcp| seen.insert(&tcp.flow()))
+/// ```
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    words: Vec<u64>,
+    blocks: usize,
+    hashes: usize,
+}
+
+impl BloomFilter {
+    /// Creates a new filter of at least `bits` bits, set with `hashes`
+    /// hashes per key. The number of blocks is rounded up to a power of
+    /// two.
+    pub fn new(bits: usize, hashes: usize) -> Self {
+        let blocks = (bits / BLOCK_BITS).max(1).next_power_of_two();
+
+        BloomFilter {
+            words: vec![0; blocks * BLOCK_WORDS],
+            blocks,
+            hashes: hashes.max(1),
+        }
+    }
+
+    /// Creates a new filter sized for `capacity` keys at a false positive
+    /// rate of `fp_rate`.
+    pub fn with_rate(capacity: usize, fp_rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity.max(1) as f64) * fp_rate.ln() / (ln2 * ln2)).ceil();
+        let hashes = (bits / capacity.max(1) as f64 * ln2).round();
+        BloomFilter::new(bits as usize, hashes as usize)
+    }
+
+    /// Returns the number of bits of the filter.
+    pub fn bits(&self) -> usize {
+        self.blocks * BLOCK_BITS
+    }
+
+    /// Returns the first word of the block of the key, and the hashes of
+    /// its bits in the block.
+    #[inline]
+    fn locate<K: Hash + ?Sized>(&self, key: &K) -> (usize, (u32, u32)) {
+        let hash = hash_key64(key);
+        let block = ((hash >> 32) as usize & (self.blocks - 1)) * BLOCK_WORDS;
+        // the bits of the keys of a block must not depend on the block,
+        // they are hashed again rather than taken from the same bits.
+        (block, split_hash(mix64(hash)))
+    }
+
+    /// Adds the key, returning whether it was not in the set already.
+    #[inline]
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K) -> bool {
+        let (block, hashes) = self.locate(key);
+        let mut added = false;
+
+        for n in 1..=self.hashes {
+            let bit = nth_hash(hashes, n) as usize % BLOCK_BITS;
+            let word = &mut self.words[block + bit / 64];
+            let mask = 1 << (bit % 64);
+            added |= *word & mask == 0;
+            *word |= mask;
+        }
+
+        added
+    }
+
+    /// Returns whether the key may be in the set. A `false` is definite.
+    #[inline]
+    pub fn contains<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        let (block, hashes) = self.locate(key);
+
+        (1..=self.hashes).all(|n| {
+            let bit = nth_hash(hashes, n) as usize % BLOCK_BITS;
+            self.words[block + bit / 64] & (1 << (bit % 64)) != 0
+        })
+    }
+
+    /// Adds the keys of another filter of the same size and hashes.
+    pub fn merge(&mut self, other: &BloomFilter) -> Result<()> {
+        ensure!(
+            self.bits() == other.bits() && self.hashes == other.hashes,
+            FilterMismatch(self.bits(), other.bits())
+        );
+
+        self.words
+            .iter_mut()
+            .zip(other.words.iter())
+            .for_each(|(a, b)| *a |= b);
+        Ok(())
+    }
+
+    /// Returns the fraction of the bits set, an indication of how full
+    /// the filter is.
+    pub fn fill_ratio(&self) -> f64 {
+        let set = self
+            .words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum::<usize>();
+        set as f64 / self.bits() as f64
+    }
+
+    /// Removes all the keys.
+    pub fn clear(&mut self) {
+        self.words.iter_mut().for_each(|word| *word = 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_false_negatives() {
+        let mut filter = BloomFilter::with_rate(10_000, 0.01);
+        for n in 0..10_000u32 {
+            assert!(filter.insert(&n));
+        }
+        for n in 0..10_000u32 {
+            assert!(filter.contains(&n));
+        }
+        assert!(!filter.insert(&1u32));
+    }
+
+    #[test]
+    fn bounded_false_positives() {
+        let mut filter = BloomFilter::with_rate(10_000, 0.01);
+        for n in 0..10_000u32 {
+            filter.insert(&n);
+        }
+
+        let false_positives = (10_000..110_000u32).filter(|n| filter.contains(n)).count();
+        // the blocked filter trades some accuracy for locality.
+        assert!(
+            false_positives < 2_000,
+            "{} false positives",
+            false_positives
+        );
+    }
+
+    #[test]
+    fn merge_filters() {
+        let mut a = BloomFilter::new(4096, 4);
+        let mut b = BloomFilter::new(4096, 4);
+        a.insert("a");
+        b.insert("b");
+
+        a.merge(&b).unwrap();
+        assert!(a.contains("a"));
+        assert!(a.contains("b"));
+        assert!(a.merge(&BloomFilter::new(8192, 4)).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::packets::ip::Flow;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A tracked flow and its counters.
+#[derive(Clone, Debug)]
+pub struct FlowRecord<V> {
+    /// The application state of the flow.
+    pub value: V,
+    /// When the first packet of the flow was seen.
+    pub created: Instant,
+    /// When the last packet of the flow was seen.
+    pub last_seen: Instant,
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+/// Why a flow left the table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FlowEnd {
+    /// The flow was idle for longer than the timeout.
+    Expired,
+    /// The flow was removed by the application, for example when the
+    /// connection closed.
+    Removed,
+}
+
+type CreateCallback<V> = Box<dyn FnMut(&Flow, &FlowRecord<V>)>;
+type EndCallback<V> = Box<dyn FnMut(&Flow, &FlowRecord<V>, FlowEnd)>;
+
+/// A connection tracking table, keyed by the 5-tuple.
+///
+/// The table is not thread-safe by design. Each core owns the table of the
+/// flows it handles, which is how RSS distributes them, so the lookups
+/// need no synchronization.
+///
+/// Callbacks can be registered for when a flow is created and when it
+/// ends, to log connection records or synchronize the state to a peer.
+/// The idle flows are only expired when `expire` is called, which the
+/// application should do periodically, for example from a timer task.
+///
+/// # Example
+///
+/// ```
+/// let mut flows = FlowTable::new(Duration::from_secs(60));
+/// flows.on_end(|flow, record, _| {
+///     info!(?flow, bytes = record.bytes, duration = ?(record.last_seen - record.created));
+/// });
+///
+/// let record = flows.track(flow, packet.data_len(), Instant::now(), || State::New);
+/// ```
+pub struct FlowTable<V> {
+    flows: HashMap<Flow, FlowRecord<V>>,
+    idle_timeout: Duration,
+    on_create: Vec<CreateCallback<V>>,
+    on_end: Vec<EndCallback<V>>,
+}
+
+impl<V> FlowTable<V> {
+    /// Creates a new table that expires flows idle for `idle_timeout`.
+    pub fn new(idle_timeout: Duration) -> Self {
+        FlowTable::with_capacity(0, idle_timeout)
+    }
+
+    /// Creates a new table with room for `capacity` flows before it
+    /// reallocates.
+    pub fn with_capacity(capacity: usize, idle_timeout: Duration) -> Self {
+        FlowTable {
+            flows: HashMap::with_capacity(capacity),
+            idle_timeout,
+            on_create: vec![],
+            on_end: vec![],
+        }
+    }
+
+    /// Registers a callback invoked when a flow is created.
+    pub fn on_create<F>(&mut self, f: F)
+    where
+        F: FnMut(&Flow, &FlowRecord<V>) + 'static,
+    {
+        self.on_create.push(Box::new(f));
+    }
+
+    /// Registers a callback invoked when a flow expires or is removed.
+    pub fn on_end<F>(&mut self, f: F)
+    where
+        F: FnMut(&Flow, &FlowRecord<V>, FlowEnd) + 'static,
+    {
+        self.on_end.push(Box::new(f));
+    }
+
+    /// Returns the idle timeout of the flows.
+    #[inline]
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Returns the number of flows tracked.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Returns whether no flow is tracked.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// Returns the record of a flow.
+    #[inline]
+    pub fn get(&self, flow: &Flow) -> Option<&FlowRecord<V>> {
+        self.flows.get(flow)
+    }
+
+    /// Returns the mutable record of a flow.
+    #[inline]
+    pub fn get_mut(&mut self, flow: &Flow) -> Option<&mut FlowRecord<V>> {
+        self.flows.get_mut(flow)
+    }
+
+    /// Records a packet of `bytes` of the flow seen at `now`, creating the
+    /// flow with the state returned by `init` if it is not tracked yet.
+    pub fn track<F>(
+        &mut self,
+        flow: Flow,
+        bytes: usize,
+        now: Instant,
+        init: F,
+    ) -> &mut FlowRecord<V>
+    where
+        F: FnOnce() -> V,
+    {
+        let on_create = &mut self.on_create;
+        let record = self.flows.entry(flow).or_insert_with(|| {
+            let record = FlowRecord {
+                value: init(),
+                created: now,
+                last_seen: now,
+                packets: 0,
+                bytes: 0,
+            };
+            on_create.iter_mut().for_each(|f| f(&flow, &record));
+            record
+        });
+
+        record.last_seen = now;
+        record.packets += 1;
+        record.bytes += bytes as u64;
+        record
+    }
+
+    /// Removes a flow, invoking the end callbacks.
+    pub fn remove(&mut self, flow: &Flow) -> Option<FlowRecord<V>> {
+        let record = self.flows.remove(flow)?;
+        self.on_end
+            .iter_mut()
+            .for_each(|f| f(flow, &record, FlowEnd::Removed));
+        Some(record)
+    }
+
+    /// Removes the flows idle since before `now - idle_timeout`, invoking
+    /// the end callbacks. Returns the number of flows expired.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let idle_timeout = self.idle_timeout;
+        let expired = self
+            .flows
+            .iter()
+            .filter(|(_, record)| now > record.last_seen + idle_timeout)
+            .map(|(flow, _)| *flow)
+            .collect::<Vec<_>>();
+
+        for flow in expired.iter() {
+            if let Some(record) = self.flows.remove(flow) {
+                self.on_end
+                    .iter_mut()
+                    .for_each(|f| f(flow, &record, FlowEnd::Expired));
+            }
+        }
+
+        expired.len()
+    }
+
+    /// Inserts or replaces a flow synchronized from the active instance,
+    /// without invoking the create callbacks.
+    pub(crate) fn restore(&mut self, flow: Flow, value: V, packets: u64, bytes: u64, now: Instant) {
+        let created = self.flows.get(&flow).map_or(now, |record| record.created);
+        self.flows.insert(
+            flow,
+            FlowRecord {
+                value,
+                created,
+                last_seen: now,
+                packets,
+                bytes,
+            },
+        );
+    }
+
+    /// Removes a flow ended on the active instance, without invoking the
+    /// end callbacks.
+    pub(crate) fn discard(&mut self, flow: &Flow) {
+        self.flows.remove(flow);
+    }
+
+    /// Returns an iterator over the tracked flows, to export them.
+    pub fn iter(&self) -> impl Iterator<Item = (&Flow, &FlowRecord<V>)> {
+        self.flows.iter()
+    }
+
+    /// Returns a copy of the tracked flows, which can be sent to another
+    /// thread to export while the table keeps changing.
+    pub fn snapshot(&self) -> Vec<(Flow, FlowRecord<V>)>
+    where
+        V: Clone,
+    {
+        self.flows
+            .iter()
+            .map(|(flow, record)| (*flow, record.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::ProtocolNumbers;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn flow(src_port: u16) -> Flow {
+        Flow::new(
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            src_port,
+            80,
+            ProtocolNumbers::Tcp,
+        )
+    }
+
+    #[test]
+    fn track_flows() {
+        let mut flows = FlowTable::new(Duration::from_secs(60));
+        let now = Instant::now();
+
+        flows.track(flow(1), 100, now, || 0);
+        let record = flows.track(flow(1), 50, now, || 0);
+        record.value += 1;
+
+        let record = flows.get(&flow(1)).unwrap();
+        assert_eq!(2, record.packets);
+        assert_eq!(150, record.bytes);
+        assert_eq!(1, record.value);
+        assert_eq!(1, flows.len());
+    }
+
+    #[test]
+    fn callbacks_on_create_and_end() {
+        let created = Rc::new(RefCell::new(vec![]));
+        let ended = Rc::new(RefCell::new(vec![]));
+
+        let mut flows = FlowTable::new(Duration::from_secs(10));
+        let log = created.clone();
+        flows.on_create(move |flow, _: &FlowRecord<()>| log.borrow_mut().push(flow.src_port()));
+        let log = ended.clone();
+        flows.on_end(move |flow, record, end| {
+            log.borrow_mut()
+                .push((flow.src_port(), record.packets, end))
+        });
+
+        let start = Instant::now();
+        flows.track(flow(1), 10, start, || ());
+        flows.track(flow(1), 10, start, || ());
+        flows.track(flow(2), 10, start, || ());
+        flows.track(flow(3), 10, start + Duration::from_secs(8), || ());
+        assert_eq!(vec![1, 2, 3], *created.borrow());
+
+        assert!(flows.remove(&flow(2)).is_some());
+        assert_eq!(1, flows.expire(start + Duration::from_secs(15)));
+
+        assert_eq!(
+            vec![(2, 1, FlowEnd::Removed), (1, 2, FlowEnd::Expired)],
+            *ended.borrow()
+        );
+        assert_eq!(1, flows.snapshot().len());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::packets::ip::Flow;
+use lazy_static::lazy_static;
+use std::net::IpAddr;
+
+// the reflected CRC-32C (Castagnoli) polynomial, the one SSE4.2 computes.
+const CRC32C_POLY: u32 = 0x82f6_3b78;
+
+lazy_static! {
+    // the lookup table of the software fallback.
+    static ref CRC32C_TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ CRC32C_POLY } else { crc >> 1 };
+            }
+            *entry = crc;
+        }
+        table
+    };
+}
+
+/// Computes the CRC-32C of the data, continuing from `init`.
+///
+/// Uses the SSE4.2 `crc32` instruction when the CPU has it, which every
+/// x86 CPU DPDK runs on does, and a table driven fallback otherwise. This
+/// is the hash `rte_hash_crc` computes, so the values agree with the DPDK
+/// libraries.
+#[inline]
+pub fn crc32c(init: u32, data: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse4.2") {
+            return unsafe { crc32c_sse42(init, data) };
+        }
+    }
+
+    crc32c_soft(init, data)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(init: u32, data: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+
+    let mut crc = u64::from(init);
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(word));
+    }
+
+    let mut crc = crc as u32;
+    for &byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, byte);
+    }
+    crc
+}
+
+fn crc32c_soft(init: u32, data: &[u8]) -> u32 {
+    data.iter().fold(init, |crc, &byte| {
+        (crc >> 8) ^ CRC32C_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize]
+    })
+}
+
+#[inline]
+fn crc32c_addr(init: u32, addr: IpAddr) -> u32 {
+    match addr {
+        IpAddr::V4(addr) => crc32c(init, &addr.octets()),
+        IpAddr::V6(addr) => crc32c(init, &addr.octets()),
+    }
+}
+
+/// Returns the hash of the flow's 5-tuple.
+///
+/// Cheap enough to compute per packet, for load balancing, sampling and
+/// flow tables that do not need a keyed hash.
+#[inline]
+pub fn hash_flow(flow: &Flow) -> u32 {
+    let crc = crc32c_addr(!0, flow.src_ip());
+    let crc = crc32c_addr(crc, flow.dst_ip());
+    let mut tail = [0u8; 5];
+    tail[..2].copy_from_slice(&flow.src_port().to_be_bytes());
+    tail[2..4].copy_from_slice(&flow.dst_port().to_be_bytes());
+    tail[4] = flow.protocol().0;
+    !crc32c(crc, &tail)
+}
+
+/// Returns the hash of the flow's 5-tuple that is the same for both
+/// directions of the flow, so a connection is always handled by the same
+/// core.
+#[inline]
+pub fn hash_flow_symmetric(flow: &Flow) -> u32 {
+    if (flow.src_ip(), flow.src_port()) <= (flow.dst_ip(), flow.dst_port()) {
+        hash_flow(flow)
+    } else {
+        hash_flow(&flow.reverse())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::ProtocolNumbers;
+
+    #[test]
+    fn crc32c_check_value() {
+        // the check value of the CRC-32C catalogue entry.
+        assert_eq!(0xe306_9283, !crc32c(!0, b"123456789"));
+        assert_eq!(0xe306_9283, !crc32c_soft(!0, b"123456789"));
+    }
+
+    #[test]
+    fn symmetric_flow_hash() {
+        let flow = Flow::new(
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            1234,
+            80,
+            ProtocolNumbers::Tcp,
+        );
+
+        assert_ne!(hash_flow(&flow), hash_flow(&flow.reverse()));
+        assert_eq!(
+            hash_flow_symmetric(&flow),
+            hash_flow_symmetric(&flow.reverse())
+        );
+    }
+}
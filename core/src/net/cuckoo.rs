@@ -0,0 +1,252 @@
+use super::bloom::FilterMismatch;
+use super::crc32c;
+use super::sketch::hash_key64;
+use super::Xoshiro256;
+use crate::{ensure, Result};
+use failure::Fail;
+use std::hash::Hash;
+
+// fingerprints per bucket, 4 fills a 64-bit word.
+const BUCKET_SIZE: usize = 4;
+// the number of evictions before an insert gives up.
+const MAX_KICKS: usize = 500;
+
+/// Error indicating the cuckoo filter is too full to insert a key.
+#[derive(Debug, Fail)]
+#[fail(display = "Cuckoo filter is full.")]
+pub struct CuckooFull;
+
+/// A cuckoo filter, an approximate set with no false negatives that also
+/// supports removing keys.
+///
+/// Implements "Cuckoo Filter: Practically Better Than Bloom" by Fan et
+/// al. A key is stored as a 16-bit fingerprint in one of two buckets of
+/// four, so a lookup touches at most two cache lines. The filter fills up
+/// to about 95% of its capacity before inserts start to fail.
+///
+/// Like `BloomFilter`, it is meant to be owned by a single pipeline. The
+/// filters of several cores can be merged as long as the result fits.
+///
+/// # Example
+///
+/// ```
+/// let mut pending = CuckooFilter::new(65536);
+///
+/// // on SYN.
+/// pending.insert(&tcp.flow())?;
+/// // on the completion of the handshake.
+/// pending.remove(&tcp.flow());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CuckooFilter {
+    buckets: Vec<[u16; BUCKET_SIZE]>,
+    len: usize,
+    rand: Xoshiro256,
+}
+
+impl CuckooFilter {
+    /// Creates a new filter for at least `capacity` keys. The number of
+    /// buckets is rounded up to a power of two.
+    pub fn new(capacity: usize) -> Self {
+        let buckets = (capacity / BUCKET_SIZE).max(1).next_power_of_two();
+
+        CuckooFilter {
+            buckets: vec![[0; BUCKET_SIZE]; buckets],
+            len: 0,
+            rand: Xoshiro256::new(0xc0c0_f117_e25e_ed00),
+        }
+    }
+
+    /// Returns the number of keys the filter can hold.
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    /// Returns the number of keys in the filter.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the filter is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    fn mask(&self) -> usize {
+        self.buckets.len() - 1
+    }
+
+    /// Returns the fingerprint and the first bucket of the key.
+    #[inline]
+    fn locate<K: Hash + ?Sized>(&self, key: &K) -> (u16, usize) {
+        // the fingerprint and the bucket are independent bits of the hash.
+        let hash = hash_key64(key);
+        // `0` marks an empty slot.
+        let fingerprint = hash as u16;
+        let fingerprint = if fingerprint == 0 { 1 } else { fingerprint };
+        (fingerprint, (hash >> 32) as usize & self.mask())
+    }
+
+    /// Returns the other bucket of the fingerprint.
+    #[inline]
+    fn alt(&self, index: usize, fingerprint: u16) -> usize {
+        (index ^ crc32c(0, &fingerprint.to_le_bytes()) as usize) & self.mask()
+    }
+
+    fn try_put(&mut self, index: usize, fingerprint: u16) -> bool {
+        match self.buckets[index].iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fingerprint;
+                self.len += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn put(&mut self, index: usize, fingerprint: u16) -> Result<()> {
+        let alt = self.alt(index, fingerprint);
+        if self.try_put(index, fingerprint) || self.try_put(alt, fingerprint) {
+            return Ok(());
+        }
+
+        // evicts a random fingerprint to its other bucket, repeatedly.
+        let mut index = if self.rand.one_in(2) { index } else { alt };
+        let mut fingerprint = fingerprint;
+        let mut path = vec![];
+        for _ in 0..MAX_KICKS {
+            let slot = self.rand.below(BUCKET_SIZE as u32) as usize;
+            path.push((index, slot));
+            std::mem::swap(&mut fingerprint, &mut self.buckets[index][slot]);
+            index = self.alt(index, fingerprint);
+            if self.try_put(index, fingerprint) {
+                return Ok(());
+            }
+        }
+
+        // undoes the evictions, so a failed insert leaves the filter as is.
+        for (index, slot) in path.into_iter().rev() {
+            std::mem::swap(&mut fingerprint, &mut self.buckets[index][slot]);
+        }
+        Err(CuckooFull.into())
+    }
+
+    /// Adds the key.
+    ///
+    /// A key inserted twice is stored twice, and needs to be removed twice.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CuckooFull` if there is no room left for the key.
+    #[inline]
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K) -> Result<()> {
+        let (fingerprint, index) = self.locate(key);
+        self.put(index, fingerprint)
+    }
+
+    /// Returns whether the key may be in the set. A `false` is definite.
+    #[inline]
+    pub fn contains<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        let (fingerprint, index) = self.locate(key);
+        let alt = self.alt(index, fingerprint);
+        self.buckets[index].contains(&fingerprint) || self.buckets[alt].contains(&fingerprint)
+    }
+
+    /// Removes the key, returning whether it was found.
+    ///
+    /// Only remove keys that were inserted, or a colliding key may be
+    /// removed instead.
+    pub fn remove<K: Hash + ?Sized>(&mut self, key: &K) -> bool {
+        let (fingerprint, index) = self.locate(key);
+        let alt = self.alt(index, fingerprint);
+
+        for &i in [index, alt].iter() {
+            if let Some(slot) = self.buckets[i]
+                .iter_mut()
+                .find(|slot| **slot == fingerprint)
+            {
+                *slot = 0;
+                self.len -= 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Adds the keys of another filter of the same capacity.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CuckooFull` if the keys do not all fit. The keys merged
+    /// before the error are kept.
+    pub fn merge(&mut self, other: &CuckooFilter) -> Result<()> {
+        ensure!(
+            self.capacity() == other.capacity(),
+            FilterMismatch(self.capacity(), other.capacity())
+        );
+
+        for (index, bucket) in other.buckets.iter().enumerate() {
+            for &fingerprint in bucket.iter().filter(|&&fp| fp != 0) {
+                self.put(index, fingerprint)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes all the keys.
+    pub fn clear(&mut self) {
+        self.buckets
+            .iter_mut()
+            .for_each(|bucket| *bucket = [0; BUCKET_SIZE]);
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut filter = CuckooFilter::new(1024);
+        for n in 0..900u32 {
+            filter.insert(&n).unwrap();
+        }
+        assert_eq!(900, filter.len());
+        assert!((0..900u32).all(|n| filter.contains(&n)));
+
+        for n in 0..450u32 {
+            assert!(filter.remove(&n));
+        }
+        assert_eq!(450, filter.len());
+        assert!((450..900u32).all(|n| filter.contains(&n)));
+    }
+
+    #[test]
+    fn fail_when_full() {
+        let mut filter = CuckooFilter::new(64);
+        let inserted = (0..1000u32)
+            .take_while(|n| filter.insert(n).is_ok())
+            .count();
+
+        assert!(inserted >= 48 && inserted <= 64);
+        // a failed insert leaves the filter as is.
+        assert_eq!(inserted, filter.len());
+        assert!((0..inserted as u32).all(|n| filter.contains(&n)));
+    }
+
+    #[test]
+    fn merge_filters() {
+        let mut a = CuckooFilter::new(1024);
+        let mut b = CuckooFilter::new(1024);
+        a.insert("a").unwrap();
+        b.insert("b").unwrap();
+
+        a.merge(&b).unwrap();
+        assert!(a.contains("a"));
+        assert!(a.contains("b"));
+        assert_eq!(2, a.len());
+        assert!(a.merge(&CuckooFilter::new(2048)).is_err());
+    }
+}
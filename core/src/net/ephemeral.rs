@@ -0,0 +1,245 @@
+use failure::Fail;
+use std::fmt;
+
+/// Error indicating the port range is invalid.
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid ephemeral port range {}-{}.", _0, _1)]
+pub struct PortRangeError(u16, u16);
+
+/// An ephemeral port allocator for source port selection.
+///
+/// The allocator is not thread-safe by design. Each core should own its
+/// own allocator, with the configured range partitioned among the cores
+/// using `EphemeralPorts::for_core`, so ports can be picked at line rate
+/// without any synchronization. Two cores will never hand out the same
+/// port because their partitions do not overlap.
+///
+/// # Example
+///
+/// ```
+/// let mut ports = EphemeralPorts::for_core(49152, 65535, core_idx, num_cores)?;
+/// let port = ports.allocate_with(|port| flows.contains_key(&(ip, port)));
+/// ```
+pub struct EphemeralPorts {
+    start: u16,
+    len: usize,
+    cursor: usize,
+    in_use: usize,
+    bitmap: Vec<u64>,
+}
+
+impl EphemeralPorts {
+    /// Creates a new allocator handing out ports from `start` to `end`,
+    /// both inclusive.
+    ///
+    /// # Errors
+    ///
+    /// If `start` is greater than `end`, `PortRangeError` is returned.
+    pub fn new(start: u16, end: u16) -> Result<Self, PortRangeError> {
+        if start > end {
+            return Err(PortRangeError(start, end));
+        }
+
+        let len = (end - start) as usize + 1;
+        Ok(EphemeralPorts {
+            start,
+            len,
+            cursor: 0,
+            in_use: 0,
+            bitmap: vec![0; (len + 63) / 64],
+        })
+    }
+
+    /// Creates a new allocator for the `idx`-th of `count` cores sharing
+    /// the port range from `start` to `end`, both inclusive.
+    ///
+    /// The range is split into `count` contiguous partitions of roughly
+    /// equal size, and the allocator is assigned the `idx`-th partition.
+    ///
+    /// # Errors
+    ///
+    /// If the range is invalid or too small to give every core at least
+    /// one port, or `idx` is not less than `count`, `PortRangeError` is
+    /// returned.
+    pub fn for_core(
+        start: u16,
+        end: u16,
+        idx: usize,
+        count: usize,
+    ) -> Result<Self, PortRangeError> {
+        let len = if start <= end {
+            (end - start) as usize + 1
+        } else {
+            0
+        };
+
+        if count == 0 || idx >= count || len < count {
+            return Err(PortRangeError(start, end));
+        }
+
+        let size = len / count;
+        let first = start as usize + idx * size;
+        // the last core picks up the remainder
+        let last = if idx == count - 1 {
+            end as usize
+        } else {
+            first + size - 1
+        };
+
+        EphemeralPorts::new(first as u16, last as u16)
+    }
+
+    /// Returns the first port of the range.
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// Returns the last port of the range.
+    pub fn end(&self) -> u16 {
+        self.start + (self.len - 1) as u16
+    }
+
+    /// Returns the number of ports currently allocated.
+    pub fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// Returns the number of ports still available.
+    pub fn available(&self) -> usize {
+        self.len - self.in_use
+    }
+
+    /// Returns whether the port is allocated by this allocator.
+    pub fn is_allocated(&self, port: u16) -> bool {
+        match self.index(port) {
+            Some(idx) => self.bitmap[idx / 64] & (1 << (idx % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// Allocates the next free port.
+    ///
+    /// Returns `None` if all the ports in the range are allocated.
+    #[inline]
+    pub fn allocate(&mut self) -> Option<u16> {
+        self.allocate_with(|_| false)
+    }
+
+    /// Allocates the next free port that is not in use elsewhere.
+    ///
+    /// Allocated ports are tracked by the allocator. `in_use` is consulted
+    /// for ports that are free here but may still collide with something
+    /// else, for example an existing entry in the flow table for the same
+    /// address. Ports are handed out round-robin so a recently released
+    /// port is not immediately reused.
+    ///
+    /// Returns `None` if no port in the range is available.
+    pub fn allocate_with<F>(&mut self, in_use: F) -> Option<u16>
+    where
+        F: Fn(u16) -> bool,
+    {
+        if self.available() == 0 {
+            return None;
+        }
+
+        for _ in 0..self.len {
+            let idx = self.cursor;
+            self.cursor = (self.cursor + 1) % self.len;
+
+            if self.bitmap[idx / 64] & (1 << (idx % 64)) == 0 {
+                let port = self.start + idx as u16;
+                if !in_use(port) {
+                    self.bitmap[idx / 64] |= 1 << (idx % 64);
+                    self.in_use += 1;
+                    return Some(port);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Releases a previously allocated port.
+    ///
+    /// Returns `false` if the port was not allocated by this allocator.
+    pub fn release(&mut self, port: u16) -> bool {
+        if !self.is_allocated(port) {
+            return false;
+        }
+
+        // `is_allocated` already checked the port is in range.
+        let idx = (port - self.start) as usize;
+        self.bitmap[idx / 64] &= !(1 << (idx % 64));
+        self.in_use -= 1;
+        true
+    }
+
+    /// Returns the bitmap index of the port if it's in range.
+    #[inline]
+    fn index(&self, port: u16) -> Option<usize> {
+        if port >= self.start && port <= self.end() {
+            Some((port - self.start) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Debug for EphemeralPorts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ephemeral_ports")
+            .field("start", &self.start())
+            .field("end", &self.end())
+            .field("in_use", &self.in_use())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_port_range() {
+        assert!(EphemeralPorts::new(2000, 1000).is_err());
+        assert!(EphemeralPorts::for_core(1000, 1002, 0, 4).is_err());
+        assert!(EphemeralPorts::for_core(1000, 2000, 4, 4).is_err());
+    }
+
+    #[test]
+    fn allocate_and_release() {
+        let mut ports = EphemeralPorts::new(1000, 1002).unwrap();
+
+        assert_eq!(Some(1000), ports.allocate());
+        assert_eq!(Some(1001), ports.allocate());
+        assert_eq!(Some(1002), ports.allocate());
+        assert_eq!(None, ports.allocate());
+        assert_eq!(3, ports.in_use());
+
+        assert!(ports.release(1001));
+        assert!(!ports.release(1001));
+        assert!(!ports.release(999));
+        assert_eq!(Some(1001), ports.allocate());
+    }
+
+    #[test]
+    fn allocate_skips_ports_in_use() {
+        let mut ports = EphemeralPorts::new(1000, 1003).unwrap();
+
+        assert_eq!(Some(1002), ports.allocate_with(|port| port < 1002));
+        assert_eq!(None, ports.allocate_with(|_| true));
+        assert!(!ports.is_allocated(1000));
+    }
+
+    #[test]
+    fn partition_range_for_cores() {
+        let first = EphemeralPorts::for_core(49152, 65535, 0, 3).unwrap();
+        let second = EphemeralPorts::for_core(49152, 65535, 1, 3).unwrap();
+        let third = EphemeralPorts::for_core(49152, 65535, 2, 3).unwrap();
+
+        assert_eq!(49152, first.start());
+        assert_eq!(first.end() + 1, second.start());
+        assert_eq!(second.end() + 1, third.start());
+        assert_eq!(65535, third.end());
+    }
+}
@@ -0,0 +1,552 @@
+use super::crc32c;
+use crate::packets::data_slice;
+use crate::packets::ip::{IpPacket, ProtocolNumbers};
+use crate::Shared;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+// the number of slots the sliding window is divided into.
+const SLOTS: usize = 8;
+// the number of bins the sources are hashed into to estimate the entropy.
+const SOURCE_BINS: usize = 16;
+// the destinations checked for eviction per new destination, so a flood of
+// new destinations costs a bounded amount of work per packet.
+const EVICT_SCAN: usize = 8;
+// the offset of the flags in the TCP header.
+const TCP_FLAGS: usize = 13;
+const SYN: u8 = 0x02;
+const ACK: u8 = 0x10;
+
+/// Kind of flood attack.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum FloodKind {
+    /// TCP segments with SYN but not ACK.
+    Syn,
+    /// UDP datagrams.
+    Udp,
+}
+
+impl FloodKind {
+    fn index(self) -> usize {
+        match self {
+            FloodKind::Syn => 0,
+            FloodKind::Udp => 1,
+        }
+    }
+
+    /// Returns the kind of flood the packet would be part of.
+    pub fn of<P: IpPacket>(packet: &P) -> Option<FloodKind> {
+        match packet.next_proto() {
+            ProtocolNumbers::Tcp => {
+                let flags = data_slice(packet.mbuf(), packet.payload_offset() + TCP_FLAGS, 1);
+                match flags {
+                    [flags] if flags & (SYN | ACK) == SYN => Some(FloodKind::Syn),
+                    _ => None,
+                }
+            }
+            ProtocolNumbers::Udp => Some(FloodKind::Udp),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FloodKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FloodKind::Syn => write!(f, "syn_flood"),
+            FloodKind::Udp => write!(f, "udp_flood"),
+        }
+    }
+}
+
+/// The thresholds above which traffic to a destination is a flood.
+#[derive(Clone, Copy, Debug)]
+pub struct FloodThresholds {
+    /// SYN segments per second.
+    pub syn_rate: u64,
+    /// UDP datagrams per second.
+    pub udp_rate: u64,
+    /// The minimum entropy of the sources, from `0.0`, all the packets are
+    /// from a single source, to `1.0`, the sources are evenly spread. Set
+    /// to only flag the floods from many, typically spoofed, sources.
+    pub min_src_entropy: f64,
+}
+
+impl Default for FloodThresholds {
+    fn default() -> Self {
+        FloodThresholds {
+            syn_rate: 10_000,
+            udp_rate: 100_000,
+            min_src_entropy: 0.0,
+        }
+    }
+}
+
+/// A flood detected by the `FloodDetector`.
+#[derive(Clone, Copy, Debug)]
+pub struct FloodAlert {
+    pub dst: IpAddr,
+    pub kind: FloodKind,
+    /// The rate of the flood, in packets per second.
+    pub rate: u64,
+    /// The entropy of the sources, from `0.0` to `1.0`.
+    pub src_entropy: f64,
+}
+
+/// The rules dropping the flood traffic to the attacked destinations.
+///
+/// The rules are inserted by the `FloodDetector` and expire after the
+/// hold time.
+///
+/// # Example
+///
+/// ```
+/// let blocklist = Shared::new(FloodBlocklist::new());
+/// let mut rules = blocklist.reader();
+///
+/// Poll::new(q.clone())
+///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+///     .filter_with(DropReason::Flood, move |v4| {
+///         rules.refresh();
+///         !rules.get().blocks(v4)
+///     })
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FloodBlocklist {
+    rules: HashMap<(IpAddr, FloodKind), Instant>,
+}
+
+impl FloodBlocklist {
+    /// Creates an empty blocklist.
+    pub fn new() -> Self {
+        FloodBlocklist::default()
+    }
+
+    /// Blocks the flood traffic to `dst` until `until`.
+    pub fn insert(&mut self, dst: IpAddr, kind: FloodKind, until: Instant) {
+        self.rules.insert((dst, kind), until);
+    }
+
+    /// Unblocks the flood traffic to `dst`.
+    pub fn remove(&mut self, dst: IpAddr, kind: FloodKind) {
+        self.rules.remove(&(dst, kind));
+    }
+
+    /// Removes the expired rules.
+    pub fn prune(&mut self, now: Instant) {
+        self.rules.retain(|_, until| *until > now);
+    }
+
+    /// Returns whether the flood traffic to `dst` is blocked at `now`.
+    pub fn is_blocked(&self, dst: IpAddr, kind: FloodKind, now: Instant) -> bool {
+        self.rules
+            .get(&(dst, kind))
+            .map_or(false, |until| *until > now)
+    }
+
+    /// Returns whether the packet is flood traffic to a blocked
+    /// destination.
+    #[inline]
+    pub fn blocks<P: IpPacket>(&self, packet: &P) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+
+        FloodKind::of(packet).map_or(false, |kind| {
+            self.is_blocked(packet.dst(), kind, Instant::now())
+        })
+    }
+
+    /// Returns the number of rules.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Returns whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Slot {
+    counts: [u64; 2],
+    sources: [u32; SOURCE_BINS],
+}
+
+struct DstState {
+    slots: [Slot; SLOTS],
+    head: usize,
+    head_start: Instant,
+    alerted: [bool; 2],
+}
+
+impl DstState {
+    fn new(now: Instant) -> Self {
+        DstState {
+            slots: [Slot::default(); SLOTS],
+            head: 0,
+            head_start: now,
+            alerted: [false; 2],
+        }
+    }
+
+    /// Rotates the slots up to `now`, returning whether any slot has
+    /// completed.
+    fn advance(&mut self, now: Instant, slot_len: Duration) -> bool {
+        let elapsed = now.duration_since(self.head_start);
+        let steps = (elapsed.as_nanos() / slot_len.as_nanos().max(1)) as usize;
+        if steps == 0 {
+            return false;
+        }
+
+        if steps >= SLOTS {
+            self.slots = [Slot::default(); SLOTS];
+            self.head_start = now;
+        } else {
+            for _ in 0..steps {
+                self.head = (self.head + 1) % SLOTS;
+                self.slots[self.head] = Slot::default();
+            }
+            self.head_start += slot_len * steps as u32;
+        }
+        true
+    }
+
+    fn count(&self, kind: FloodKind) -> u64 {
+        self.slots
+            .iter()
+            .map(|slot| slot.counts[kind.index()])
+            .sum()
+    }
+
+    /// Returns the normalized Shannon entropy of the sources.
+    fn src_entropy(&self) -> f64 {
+        let mut bins = [0u64; SOURCE_BINS];
+        for slot in self.slots.iter() {
+            for (bin, count) in bins.iter_mut().zip(slot.sources.iter()) {
+                *bin += u64::from(*count);
+            }
+        }
+
+        let total = bins.iter().sum::<u64>() as f64;
+        if total == 0.0 {
+            return 0.0;
+        }
+
+        let entropy = bins
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum::<f64>();
+        entropy / (SOURCE_BINS as f64).log2()
+    }
+}
+
+/// Detector of SYN and UDP floods.
+///
+/// Counts the SYN segments and UDP datagrams to each destination over a
+/// sliding window, one second by default, along with an estimate of the
+/// entropy of their sources. When the rate to a destination crosses a
+/// threshold, the alert callback is invoked, and the flood traffic to the
+/// destination is blocked if the detector has a blocklist. The alert is
+/// raised again only after the rate went back below the threshold.
+///
+/// The detector is owned by a single pipeline, so the thresholds apply to
+/// the traffic each core sees. The blocklist can be shared by the
+/// detectors of all the cores.
+///
+/// # Example
+///
+/// ```
+/// let blocklist = Shared::new(FloodBlocklist::new());
+/// let mut detector = FloodDetector::new(FloodThresholds::default())
+///     .on_alert(|alert| warn!(?alert, "flood detected."))
+///     .block(blocklist.clone(), Duration::from_secs(60));
+///
+/// Poll::new(q.clone())
+///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+///     .for_each(move |v4| {
+///         detector.observe(v4);
+///         Ok(())
+///     })
+/// ```
+pub struct FloodDetector {
+    thresholds: FloodThresholds,
+    slot_len: Duration,
+    max_dsts: usize,
+    dsts: HashMap<IpAddr, DstState>,
+    // the destinations tracked, oldest first.
+    order: VecDeque<IpAddr>,
+    on_alert: Option<Box<dyn FnMut(&FloodAlert) + Send>>,
+    blocklist: Option<(Shared<FloodBlocklist>, Duration)>,
+}
+
+impl FloodDetector {
+    /// The maximum number of destinations tracked at the same time.
+    pub const DEFAULT_MAX_DESTINATIONS: usize = 65536;
+
+    /// Creates a new detector with a one second window.
+    pub fn new(thresholds: FloodThresholds) -> Self {
+        FloodDetector {
+            thresholds,
+            slot_len: Duration::from_secs(1) / SLOTS as u32,
+            max_dsts: Self::DEFAULT_MAX_DESTINATIONS,
+            dsts: HashMap::new(),
+            order: VecDeque::new(),
+            on_alert: None,
+            blocklist: None,
+        }
+    }
+
+    /// Sets the length of the sliding window.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.slot_len = window / SLOTS as u32;
+        self
+    }
+
+    /// Sets the maximum number of destinations tracked at the same time.
+    ///
+    /// When the limit is reached, a destination without traffic in the
+    /// last window is forgotten to make room for a new one. Only a few of
+    /// the oldest destinations are checked each time, the ones still
+    /// active are checked again later. If none can be forgotten, the new
+    /// destination is not tracked.
+    pub fn max_destinations(mut self, max_dsts: usize) -> Self {
+        self.max_dsts = max_dsts;
+        self
+    }
+
+    /// Sets the callback invoked when a flood is detected.
+    pub fn on_alert<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&FloodAlert) + Send + 'static,
+    {
+        self.on_alert = Some(Box::new(f));
+        self
+    }
+
+    /// Blocks the flood traffic to the attacked destinations for `hold`.
+    pub fn block(mut self, blocklist: Shared<FloodBlocklist>, hold: Duration) -> Self {
+        self.blocklist = Some((blocklist, hold));
+        self
+    }
+
+    /// Counts the packet if it is a SYN segment or a UDP datagram.
+    #[inline]
+    pub fn observe<P: IpPacket>(&mut self, packet: &P) {
+        if let Some(kind) = FloodKind::of(packet) {
+            self.observe_at(packet.dst(), packet.src(), kind, Instant::now());
+        }
+    }
+
+    /// Counts a packet of `kind` from `src` to `dst` seen at `now`.
+    pub fn observe_at(&mut self, dst: IpAddr, src: IpAddr, kind: FloodKind, now: Instant) {
+        if !self.dsts.contains_key(&dst) {
+            if self.dsts.len() >= self.max_dsts && !self.evict(now) {
+                return;
+            }
+            self.order.push_back(dst);
+        }
+
+        let slot_len = self.slot_len;
+        let state = self.dsts.entry(dst).or_insert_with(|| DstState::new(now));
+        let completed = state.advance(now, slot_len);
+
+        let slot = &mut state.slots[state.head];
+        slot.counts[kind.index()] += 1;
+        let bin = crc32c(0, &src_bytes(src)) as usize % SOURCE_BINS;
+        slot.sources[bin] = slot.sources[bin].saturating_add(1);
+
+        if completed {
+            self.evaluate(dst, now);
+        }
+    }
+
+    /// Forgets one of the oldest destinations without traffic in the last
+    /// window, returning whether one was forgotten.
+    fn evict(&mut self, now: Instant) -> bool {
+        let window = self.slot_len * SLOTS as u32;
+        for _ in 0..EVICT_SCAN.min(self.order.len()) {
+            let dst = self.order.pop_front().unwrap();
+            if now.duration_since(self.dsts[&dst].head_start) >= window {
+                self.dsts.remove(&dst);
+                return true;
+            }
+
+            // still active, checked again after the others.
+            self.order.push_back(dst);
+        }
+
+        false
+    }
+
+    /// Checks the rates to the destination against the thresholds.
+    fn evaluate(&mut self, dst: IpAddr, now: Instant) {
+        let window = self.slot_len * SLOTS as u32;
+        let secs = window.as_secs() as f64 + f64::from(window.subsec_nanos()) / 1e9;
+        let state = match self.dsts.get_mut(&dst) {
+            Some(state) => state,
+            None => return,
+        };
+
+        let src_entropy = state.src_entropy();
+        let mut alerts = vec![];
+        for &(kind, threshold) in [
+            (FloodKind::Syn, self.thresholds.syn_rate),
+            (FloodKind::Udp, self.thresholds.udp_rate),
+        ]
+        .iter()
+        {
+            let rate = (state.count(kind) as f64 / secs) as u64;
+            let flooding = rate >= threshold && src_entropy >= self.thresholds.min_src_entropy;
+            let alerted = &mut state.alerted[kind.index()];
+            if flooding && !*alerted {
+                alerts.push(FloodAlert {
+                    dst,
+                    kind,
+                    rate,
+                    src_entropy,
+                });
+            }
+            *alerted = flooding;
+        }
+
+        for alert in alerts {
+            if let Some(f) = self.on_alert.as_mut() {
+                f(&alert);
+            }
+            if let Some((blocklist, hold)) = self.blocklist.as_ref() {
+                let until = now + *hold;
+                let _ = blocklist.update(|rules| {
+                    let mut rules = rules.clone();
+                    rules.prune(now);
+                    rules.insert(alert.dst, alert.kind, until);
+                    Ok(rules)
+                });
+            }
+        }
+    }
+}
+
+fn src_bytes(src: IpAddr) -> [u8; 16] {
+    match src {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped().octets(),
+        IpAddr::V6(addr) => addr.octets(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::sync::{Arc, Mutex};
+
+    fn dst() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))
+    }
+
+    fn src(n: u32) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + n))
+    }
+
+    #[test]
+    fn alert_on_syn_flood() {
+        let alerts = Arc::new(Mutex::new(vec![]));
+        let sink = alerts.clone();
+        let thresholds = FloodThresholds {
+            syn_rate: 100,
+            ..FloodThresholds::default()
+        };
+        let mut detector =
+            FloodDetector::new(thresholds).on_alert(move |alert| sink.lock().unwrap().push(*alert));
+
+        // 200 SYNs over one second, from many sources.
+        let now = Instant::now();
+        for i in 0..200 {
+            let at = now + Duration::from_millis(i as u64 * 5);
+            detector.observe_at(dst(), src(i), FloodKind::Syn, at);
+        }
+
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(1, alerts.len());
+        assert_eq!(FloodKind::Syn, alerts[0].kind);
+        assert!(alerts[0].rate >= 100);
+        assert!(alerts[0].src_entropy > 0.8);
+    }
+
+    #[test]
+    fn ignore_below_threshold() {
+        let blocklist = Shared::new(FloodBlocklist::new());
+        let mut detector = FloodDetector::new(FloodThresholds {
+            udp_rate: 1000,
+            ..FloodThresholds::default()
+        })
+        .block(blocklist.clone(), Duration::from_secs(60));
+
+        let now = Instant::now();
+        for i in 0..200 {
+            let at = now + Duration::from_millis(i as u64 * 10);
+            detector.observe_at(dst(), src(1), FloodKind::Udp, at);
+        }
+
+        assert!(blocklist.load().is_empty());
+    }
+
+    #[test]
+    fn block_on_udp_flood() {
+        let blocklist = Shared::new(FloodBlocklist::new());
+        let mut detector = FloodDetector::new(FloodThresholds {
+            udp_rate: 100,
+            min_src_entropy: 0.5,
+            ..FloodThresholds::default()
+        })
+        .block(blocklist.clone(), Duration::from_secs(60));
+
+        // a single source has no entropy.
+        let now = Instant::now();
+        for i in 0..400 {
+            let at = now + Duration::from_millis(i as u64 * 2);
+            detector.observe_at(dst(), src(1), FloodKind::Udp, at);
+        }
+        assert!(blocklist.load().is_empty());
+
+        for i in 0..400 {
+            let at = now + Duration::from_millis(800 + i as u64 * 2);
+            detector.observe_at(dst(), src(i), FloodKind::Udp, at);
+        }
+        let rules = blocklist.load();
+        assert!(rules.is_blocked(dst(), FloodKind::Udp, now + Duration::from_secs(2)));
+        assert!(!rules.is_blocked(dst(), FloodKind::Syn, now + Duration::from_secs(2)));
+        assert!(!rules.is_blocked(dst(), FloodKind::Udp, now + Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn evict_idle_destinations() {
+        let mut detector = FloodDetector::new(FloodThresholds::default()).max_destinations(2);
+
+        let now = Instant::now();
+        let to = |n| IpAddr::V4(Ipv4Addr::new(192, 0, 2, n));
+        detector.observe_at(to(1), src(1), FloodKind::Udp, now);
+        detector.observe_at(to(2), src(1), FloodKind::Udp, now);
+
+        // both are active, the new one is not tracked.
+        detector.observe_at(to(3), src(1), FloodKind::Udp, now);
+        assert_eq!(2, detector.dsts.len());
+        assert!(!detector.dsts.contains_key(&to(3)));
+
+        // the first went idle, it makes room for the new one.
+        let later = now + Duration::from_secs(2);
+        detector.observe_at(to(2), src(1), FloodKind::Udp, later);
+        detector.observe_at(to(3), src(1), FloodKind::Udp, later);
+        assert!(!detector.dsts.contains_key(&to(1)));
+        assert!(detector.dsts.contains_key(&to(2)));
+        assert!(detector.dsts.contains_key(&to(3)));
+        assert_eq!(2, detector.order.len());
+    }
+}
@@ -0,0 +1,701 @@
+use super::FlowTable;
+use crate::packets::ip::{Flow, ProtocolNumber};
+use crate::{ensure, warn, Result};
+use failure::Fail;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::cell::RefCell;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::rc::Rc;
+use std::time::Instant;
+
+/// The version of the wire format. Bumped whenever the format changes, a
+/// standby rejects the messages of another version.
+pub const FLOW_SYNC_VERSION: u8 = 2;
+
+// "NB", the first bytes of every message.
+const MAGIC: u16 = 0x4e42;
+const HEADER_LEN: usize = 6;
+
+// the HMAC-SHA256 of the message, at its end.
+const TAG_LEN: usize = 32;
+
+// small enough to never fragment on an ethernet link, the tag included.
+const MAX_MESSAGE_LEN: usize = 1400;
+
+const FAMILY_V4: u8 = 4;
+const FAMILY_V6: u8 = 6;
+
+const KIND_UPSERT: u8 = 1;
+const KIND_REMOVE: u8 = 2;
+
+/// Flow synchronization errors.
+#[derive(Debug, Fail)]
+pub enum FlowSyncError {
+    /// The message is not a flow synchronization message.
+    #[fail(display = "Not a flow sync message.")]
+    BadMagic,
+
+    /// The message is of another version of the wire format.
+    #[fail(display = "Unsupported flow sync version {}.", _0)]
+    UnsupportedVersion(u8),
+
+    /// The message is shorter than its contents.
+    #[fail(display = "Truncated flow sync message.")]
+    Truncated,
+
+    /// The application state of a flow cannot be decoded.
+    #[fail(display = "Invalid flow state in flow sync message.")]
+    BadState,
+
+    /// The address family of a flow is neither IPv4 nor IPv6.
+    #[fail(display = "Unknown address family {} in flow sync message.", _0)]
+    UnknownFamily(u8),
+
+    /// The message is not authenticated by the shared key.
+    #[fail(display = "Flow sync message failed authentication.")]
+    BadTag,
+
+    /// The shared key is empty.
+    #[fail(display = "Flow sync key is empty.")]
+    EmptyKey,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The key shared by the active and the standby instances, authenticating
+/// every message with an HMAC-SHA256.
+///
+/// Messages that are not authenticated by the key are rejected, so a host
+/// on the synchronization network cannot inject or alter flows.
+#[derive(Clone)]
+pub struct FlowSyncKey(Vec<u8>);
+
+impl FlowSyncKey {
+    /// Creates a new key.
+    ///
+    /// # Errors
+    ///
+    /// If the key is empty, `FlowSyncError::EmptyKey` is returned.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        ensure!(!key.is_empty(), FlowSyncError::EmptyKey);
+        Ok(FlowSyncKey(key.to_vec()))
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        // HMAC takes keys of any length.
+        HmacSha256::new_varkey(&self.0).unwrap()
+    }
+
+    /// Returns the tag of the message.
+    fn sign(&self, data: &[u8]) -> [u8; TAG_LEN] {
+        let mut mac = self.mac();
+        mac.input(data);
+        let mut tag = [0u8; TAG_LEN];
+        tag.copy_from_slice(&mac.result().code());
+        tag
+    }
+
+    /// Checks the tag of the message, in constant time.
+    fn verify(&self, data: &[u8], tag: &[u8]) -> bool {
+        let mut mac = self.mac();
+        mac.input(data);
+        mac.verify(tag).is_ok()
+    }
+}
+
+// the key is a secret, never printed.
+impl std::fmt::Debug for FlowSyncKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FlowSyncKey(..)")
+    }
+}
+
+/// The application state of a flow that is synchronized to the standby.
+pub trait SyncState: Sized {
+    /// Appends the encoded state to the buffer.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Decodes the state, `None` if it is invalid.
+    fn decode(data: &[u8]) -> Option<Self>;
+}
+
+impl SyncState for () {
+    fn encode(&self, _buf: &mut Vec<u8>) {}
+
+    fn decode(_data: &[u8]) -> Option<Self> {
+        Some(())
+    }
+}
+
+impl SyncState for u32 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() == 4 {
+            Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+        } else {
+            None
+        }
+    }
+}
+
+/// A change to the flow table.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlowDelta<V> {
+    /// The flow is created or its counters changed.
+    Upsert {
+        flow: Flow,
+        value: V,
+        packets: u64,
+        bytes: u64,
+    },
+    /// The flow ended.
+    Remove(Flow),
+}
+
+fn encode_flow(flow: &Flow, buf: &mut Vec<u8>) {
+    match (flow.src_ip(), flow.dst_ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            buf.push(FAMILY_V4);
+            buf.extend_from_slice(&src.octets());
+            buf.extend_from_slice(&dst.octets());
+        }
+        (src, dst) => {
+            let v6 = |addr| match addr {
+                IpAddr::V4(addr) => addr.to_ipv6_mapped(),
+                IpAddr::V6(addr) => addr,
+            };
+            buf.push(FAMILY_V6);
+            buf.extend_from_slice(&v6(src).octets());
+            buf.extend_from_slice(&v6(dst).octets());
+        }
+    }
+    buf.extend_from_slice(&flow.src_port().to_be_bytes());
+    buf.extend_from_slice(&flow.dst_port().to_be_bytes());
+    buf.push(flow.protocol().0);
+}
+
+/// Appends the encoded delta to the buffer.
+pub fn encode_delta<V: SyncState>(delta: &FlowDelta<V>, buf: &mut Vec<u8>) {
+    match delta {
+        FlowDelta::Upsert {
+            flow,
+            value,
+            packets,
+            bytes,
+        } => {
+            buf.push(KIND_UPSERT);
+            encode_flow(flow, buf);
+            buf.extend_from_slice(&packets.to_be_bytes());
+            buf.extend_from_slice(&bytes.to_be_bytes());
+
+            // the length is written once the state is encoded.
+            let len_at = buf.len();
+            buf.extend_from_slice(&[0, 0]);
+            value.encode(buf);
+            let len = (buf.len() - len_at - 2) as u16;
+            buf[len_at..len_at + 2].copy_from_slice(&len.to_be_bytes());
+        }
+        FlowDelta::Remove(flow) => {
+            buf.push(KIND_REMOVE);
+            encode_flow(flow, buf);
+        }
+    }
+}
+
+/// Packs the encoded deltas into as few messages as possible, each signed
+/// with the key and no larger than a datagram that does not fragment.
+///
+/// A delta too large to fit in a message on its own is dropped.
+pub fn encode_messages(key: &FlowSyncKey, deltas: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut messages = vec![];
+    let mut message = Vec::with_capacity(MAX_MESSAGE_LEN);
+    let mut count = 0u16;
+
+    let mut finish = |message: &mut Vec<u8>, count: u16| {
+        let mut header = Vec::with_capacity(MAX_MESSAGE_LEN);
+        header.extend_from_slice(&MAGIC.to_be_bytes());
+        header.push(FLOW_SYNC_VERSION);
+        header.push(0);
+        header.extend_from_slice(&count.to_be_bytes());
+        header.append(message);
+        let tag = key.sign(&header);
+        header.extend_from_slice(&tag);
+        messages.push(header);
+    };
+
+    for delta in deltas {
+        if HEADER_LEN + delta.len() + TAG_LEN > MAX_MESSAGE_LEN {
+            warn!(message = "flow sync delta too large.", len = delta.len());
+            continue;
+        }
+        if count > 0
+            && (count == u16::max_value()
+                || HEADER_LEN + message.len() + delta.len() + TAG_LEN > MAX_MESSAGE_LEN)
+        {
+            finish(&mut message, count);
+            count = 0;
+        }
+        message.extend_from_slice(delta);
+        count += 1;
+    }
+
+    if count > 0 {
+        finish(&mut message, count);
+    }
+
+    messages
+}
+
+/// A cursor over the bytes of a message.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(self.data.len() >= len, FlowSyncError::Truncated);
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn flow(&mut self) -> Result<Flow> {
+        let (src, dst): (IpAddr, IpAddr) = match self.u8()? {
+            FAMILY_V4 => {
+                let mut src = [0u8; 4];
+                let mut dst = [0u8; 4];
+                src.copy_from_slice(self.take(4)?);
+                dst.copy_from_slice(self.take(4)?);
+                (Ipv4Addr::from(src).into(), Ipv4Addr::from(dst).into())
+            }
+            FAMILY_V6 => {
+                let mut src = [0u8; 16];
+                let mut dst = [0u8; 16];
+                src.copy_from_slice(self.take(16)?);
+                dst.copy_from_slice(self.take(16)?);
+                (Ipv6Addr::from(src).into(), Ipv6Addr::from(dst).into())
+            }
+            family => return Err(FlowSyncError::UnknownFamily(family).into()),
+        };
+        let src_port = self.u16()?;
+        let dst_port = self.u16()?;
+        let protocol = ProtocolNumber::new(self.u8()?);
+        Ok(Flow::new(src, dst, src_port, dst_port, protocol))
+    }
+}
+
+/// Decodes the deltas of a message signed with the key.
+///
+/// # Errors
+///
+/// If the message is not valid, of another version or not authenticated
+/// by the key, `FlowSyncError` is returned.
+pub fn decode_message<V: SyncState>(key: &FlowSyncKey, data: &[u8]) -> Result<Vec<FlowDelta<V>>> {
+    let mut reader = Reader { data };
+    ensure!(reader.u16()? == MAGIC, FlowSyncError::BadMagic);
+    let version = reader.u8()?;
+    ensure!(
+        version == FLOW_SYNC_VERSION,
+        FlowSyncError::UnsupportedVersion(version)
+    );
+
+    // nothing is decoded before the message is authenticated.
+    ensure!(data.len() >= HEADER_LEN + TAG_LEN, FlowSyncError::Truncated);
+    let (signed, tag) = data.split_at(data.len() - TAG_LEN);
+    ensure!(key.verify(signed, tag), FlowSyncError::BadTag);
+    reader.data = &signed[3..];
+    let _reserved = reader.u8()?;
+    let count = reader.u16()?;
+
+    let mut deltas = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let delta = match reader.u8()? {
+            KIND_UPSERT => {
+                let flow = reader.flow()?;
+                let packets = reader.u64()?;
+                let bytes = reader.u64()?;
+                let len = reader.u16()? as usize;
+                let value = V::decode(reader.take(len)?).ok_or(FlowSyncError::BadState)?;
+                FlowDelta::Upsert {
+                    flow,
+                    value,
+                    packets,
+                    bytes,
+                }
+            }
+            KIND_REMOVE => FlowDelta::Remove(reader.flow()?),
+            _ => return Err(FlowSyncError::BadState.into()),
+        };
+        deltas.push(delta);
+    }
+    ensure!(reader.data.is_empty(), FlowSyncError::BadState);
+
+    Ok(deltas)
+}
+
+/// Ships the changes of the flow table of the active instance to the
+/// standby instance, over UDP.
+///
+/// Once attached to the table, the created and ended flows are queued as
+/// they happen, and sent on `flush`. The counters are not synchronized per
+/// packet. Use `sync_all` periodically to refresh them and to bring a
+/// newly started standby up to date.
+///
+/// The sender uses a socket of the kernel stack, so the synchronization
+/// goes through a management interface rather than a DPDK port. To use a
+/// dedicated port instead, call `take_messages` and send the messages in
+/// packets.
+///
+/// The messages are signed with the key shared with the standby.
+///
+/// # Example
+///
+/// ```
+/// let key = FlowSyncKey::new(b"shared secret")?;
+/// let sync = FlowSyncTx::connect("192.168.100.2:4790".parse()?, key)?;
+/// sync.attach(&mut flows);
+/// ...
+/// // once per poll loop or from a timer.
+/// sync.flush()?;
+/// ```
+pub struct FlowSyncTx<V> {
+    socket: Option<UdpSocket>,
+    key: FlowSyncKey,
+    pending: Rc<RefCell<Vec<Vec<u8>>>>,
+    _state: std::marker::PhantomData<V>,
+}
+
+impl<V: SyncState + Clone + 'static> FlowSyncTx<V> {
+    /// Creates a new sender to the standby at `peer`.
+    pub fn connect(peer: SocketAddr, key: FlowSyncKey) -> Result<Self> {
+        let local: SocketAddr = if peer.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(FlowSyncTx {
+            socket: Some(socket),
+            key,
+            pending: Rc::new(RefCell::new(vec![])),
+            _state: std::marker::PhantomData,
+        })
+    }
+
+    /// Creates a new sender without a socket, for sending the messages
+    /// through a port with `take_messages`.
+    pub fn detached(key: FlowSyncKey) -> Self {
+        FlowSyncTx {
+            socket: None,
+            key,
+            pending: Rc::new(RefCell::new(vec![])),
+            _state: std::marker::PhantomData,
+        }
+    }
+
+    /// Queues the created and ended flows of the table.
+    pub fn attach(&self, table: &mut FlowTable<V>) {
+        let pending = self.pending.clone();
+        table.on_create(move |flow, record| {
+            let mut buf = vec![];
+            encode_delta(
+                &FlowDelta::Upsert {
+                    flow: *flow,
+                    value: record.value.clone(),
+                    packets: record.packets,
+                    bytes: record.bytes,
+                },
+                &mut buf,
+            );
+            pending.borrow_mut().push(buf);
+        });
+
+        let pending = self.pending.clone();
+        table.on_end(move |flow, _, _| {
+            let mut buf = vec![];
+            encode_delta(&FlowDelta::<V>::Remove(*flow), &mut buf);
+            pending.borrow_mut().push(buf);
+        });
+    }
+
+    /// Queues all the flows of the table with their current counters.
+    pub fn sync_all(&self, table: &FlowTable<V>) {
+        let mut pending = self.pending.borrow_mut();
+        for (flow, record) in table.iter() {
+            let mut buf = vec![];
+            encode_delta(
+                &FlowDelta::Upsert {
+                    flow: *flow,
+                    value: record.value.clone(),
+                    packets: record.packets,
+                    bytes: record.bytes,
+                },
+                &mut buf,
+            );
+            pending.push(buf);
+        }
+    }
+
+    /// Returns the messages of the queued changes, emptying the queue.
+    pub fn take_messages(&self) -> Vec<Vec<u8>> {
+        let deltas = self.pending.replace(vec![]);
+        encode_messages(&self.key, &deltas)
+    }
+
+    /// Sends the queued changes to the standby. Returns the number of
+    /// messages sent.
+    ///
+    /// The messages that do not fit in the socket buffer are dropped, the
+    /// next `sync_all` makes up for them.
+    pub fn flush(&self) -> Result<usize> {
+        let messages = self.take_messages();
+        let socket = match &self.socket {
+            Some(socket) => socket,
+            None => return Ok(0),
+        };
+
+        let mut sent = 0;
+        for message in messages.iter() {
+            match socket.send(message) {
+                Ok(_) => sent += 1,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(sent)
+    }
+}
+
+/// Receives the changes from the active instance and applies them to the
+/// flow table of the standby instance.
+///
+/// The restored flows start their idle timeout when they are received, and
+/// the callbacks of the standby's table are not invoked for them. The
+/// messages not signed with the shared key are dropped.
+pub struct FlowSyncRx<V> {
+    socket: UdpSocket,
+    key: FlowSyncKey,
+    _state: std::marker::PhantomData<V>,
+}
+
+impl<V: SyncState> FlowSyncRx<V> {
+    /// Creates a new receiver listening on `addr`.
+    pub fn bind(addr: SocketAddr, key: FlowSyncKey) -> Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(FlowSyncRx {
+            socket,
+            key,
+            _state: std::marker::PhantomData,
+        })
+    }
+
+    /// Returns the local address of the receiver.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Applies the received changes to the table, without blocking.
+    /// Returns the number of changes applied.
+    ///
+    /// The messages that fail to decode are skipped.
+    pub fn apply(&self, table: &mut FlowTable<V>) -> Result<usize> {
+        // one byte more to tell the oversized messages apart.
+        let mut buf = [0u8; MAX_MESSAGE_LEN + 1];
+        let mut applied = 0;
+
+        loop {
+            let len = match self.socket.recv(&mut buf) {
+                Ok(len) => len,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            if len > MAX_MESSAGE_LEN {
+                warn!(message = "oversized flow sync message.", len);
+                continue;
+            }
+
+            let deltas = match decode_message::<V>(&self.key, &buf[..len]) {
+                Ok(deltas) => deltas,
+                Err(err) => {
+                    warn!(message = "invalid flow sync message.", ?err);
+                    continue;
+                }
+            };
+
+            let now = Instant::now();
+            for delta in deltas {
+                match delta {
+                    FlowDelta::Upsert {
+                        flow,
+                        value,
+                        packets,
+                        bytes,
+                    } => table.restore(flow, value, packets, bytes, now),
+                    FlowDelta::Remove(flow) => table.discard(&flow),
+                }
+                applied += 1;
+            }
+        }
+
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::ProtocolNumbers;
+    use std::time::Duration;
+
+    fn key() -> FlowSyncKey {
+        FlowSyncKey::new(b"flow sync test").unwrap()
+    }
+
+    fn flow(src_port: u16) -> Flow {
+        Flow::new(
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            src_port,
+            80,
+            ProtocolNumbers::Tcp,
+        )
+    }
+
+    #[test]
+    fn round_trip_deltas() {
+        let v6 = Flow::new(
+            "2001:db8::1".parse().unwrap(),
+            "2001:db8::2".parse().unwrap(),
+            1234,
+            53,
+            ProtocolNumbers::Udp,
+        );
+        let deltas = vec![
+            FlowDelta::Upsert {
+                flow: flow(1),
+                value: 42u32,
+                packets: 3,
+                bytes: 1500,
+            },
+            FlowDelta::Remove(v6),
+        ];
+
+        let encoded = deltas
+            .iter()
+            .map(|delta| {
+                let mut buf = vec![];
+                encode_delta(delta, &mut buf);
+                buf
+            })
+            .collect::<Vec<_>>();
+        let messages = encode_messages(&key(), &encoded);
+        assert_eq!(1, messages.len());
+
+        let decoded = decode_message::<u32>(&key(), &messages[0]).unwrap();
+        assert_eq!(deltas, decoded);
+    }
+
+    #[test]
+    fn reject_other_version() {
+        let mut message = encode_messages(&key(), &[vec![KIND_REMOVE]]).remove(0);
+        message[2] = FLOW_SYNC_VERSION + 1;
+        assert!(decode_message::<()>(&key(), &message).is_err());
+    }
+
+    #[test]
+    fn reject_unauthenticated() {
+        let mut buf = vec![];
+        encode_delta(&FlowDelta::<()>::Remove(flow(1)), &mut buf);
+        let message = encode_messages(&key(), &[buf]).remove(0);
+
+        let other = FlowSyncKey::new(b"another key").unwrap();
+        assert!(decode_message::<()>(&other, &message).is_err());
+
+        let mut altered = message.clone();
+        altered[HEADER_LEN + 10] ^= 1;
+        assert!(decode_message::<()>(&key(), &altered).is_err());
+
+        assert!(FlowSyncKey::new(b"").is_err());
+    }
+
+    #[test]
+    fn reject_unknown_family() {
+        let mut buf = vec![];
+        encode_delta(&FlowDelta::<()>::Remove(flow(1)), &mut buf);
+        buf[1] = 5;
+        let message = encode_messages(&key(), &[buf]).remove(0);
+
+        let err = decode_message::<()>(&key(), &message).unwrap_err();
+        match err.downcast_ref::<FlowSyncError>() {
+            Some(FlowSyncError::UnknownFamily(5)) => (),
+            _ => panic!("unexpected error {}", err),
+        }
+    }
+
+    #[test]
+    fn split_into_messages() {
+        let delta = vec![0u8; 100];
+        let messages = encode_messages(&key(), &vec![delta; 30]);
+        assert_eq!(3, messages.len());
+        assert!(messages.iter().all(|m| m.len() <= MAX_MESSAGE_LEN));
+
+        // a delta that cannot fit on its own is dropped.
+        let messages = encode_messages(&key(), &[vec![0u8; MAX_MESSAGE_LEN]]);
+        assert!(messages.is_empty());
+    }
+
+    #[test]
+    fn sync_over_udp() {
+        let rx = FlowSyncRx::<u32>::bind("127.0.0.1:0".parse().unwrap(), key()).unwrap();
+        let tx = FlowSyncTx::<u32>::connect(rx.local_addr().unwrap(), key()).unwrap();
+
+        let mut active = FlowTable::new(Duration::from_secs(60));
+        tx.attach(&mut active);
+        let now = Instant::now();
+        active.track(flow(1), 100, now, || 7);
+        active.track(flow(2), 100, now, || 8);
+        active.remove(&flow(2));
+        assert_eq!(1, tx.flush().unwrap());
+
+        let mut standby = FlowTable::new(Duration::from_secs(60));
+        // loopback delivery is not instant on every system.
+        let mut applied = 0;
+        for _ in 0..100 {
+            applied += rx.apply(&mut standby).unwrap();
+            if applied == 3 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(3, applied);
+        assert_eq!(1, standby.len());
+        assert_eq!(7, standby.get(&flow(1)).unwrap().value);
+    }
+}
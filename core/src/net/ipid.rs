@@ -0,0 +1,81 @@
+use crate::dpdk::CoreId;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+/// A generator of IPv4 identification values.
+///
+/// RFC 6864 only asks that the identification is unique for a source,
+/// destination and protocol within the lifetime of the fragments, so a
+/// wrapping counter is enough. The counter is atomic, so a generator can
+/// be shared through an `Arc`. Shared by the cores, it becomes a point of
+/// contention though. The intended use is one generator per core, which
+/// `next_ip_id` provides.
+///
+/// # Example
+///
+/// ```
+/// let mut v4 = ethernet.push::<Ipv4>()?;
+/// v4.set_identification(next_ip_id());
+/// ```
+#[derive(Debug, Default)]
+pub struct IpIdGenerator {
+    next: AtomicU16,
+}
+
+impl IpIdGenerator {
+    /// Creates a new generator with `start` as the first ID.
+    pub fn new(start: u16) -> Self {
+        IpIdGenerator {
+            next: AtomicU16::new(start),
+        }
+    }
+
+    /// Creates a new generator for a core.
+    ///
+    /// The cores start far apart in the ID space, so their sequences do not
+    /// overlap until each core has handed out thousands of IDs.
+    pub fn for_core(core_id: CoreId) -> Self {
+        // multiplicative hashing spreads the consecutive core ids.
+        let start = (core_id.id() as u32).wrapping_mul(0x9e37_79b9) >> 16;
+        IpIdGenerator::new(start as u16)
+    }
+
+    /// Returns the next ID, wrapping around at `u16::MAX`.
+    #[inline]
+    pub fn next(&self) -> u16 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+thread_local! {
+    // the generator of the current core.
+    static IP_ID: IpIdGenerator = IpIdGenerator::for_core(CoreId::current());
+}
+
+/// Returns the next IPv4 identification from the current core's generator.
+#[inline]
+pub fn next_ip_id() -> u16 {
+    IP_ID.with(IpIdGenerator::next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_around() {
+        let ids = IpIdGenerator::new(u16::max_value());
+        assert_eq!(u16::max_value(), ids.next());
+        assert_eq!(0, ids.next());
+        assert_eq!(1, ids.next());
+    }
+
+    #[test]
+    fn cores_start_apart() {
+        let first = IpIdGenerator::for_core(CoreId::new(0)).next();
+        let second = IpIdGenerator::for_core(CoreId::new(1)).next();
+        let third = IpIdGenerator::for_core(CoreId::new(2)).next();
+
+        assert!(first.wrapping_sub(second) > 1000 && second.wrapping_sub(first) > 1000);
+        assert!(second.wrapping_sub(third) > 1000 && third.wrapping_sub(second) > 1000);
+    }
+}
@@ -0,0 +1,196 @@
+use super::{Cidr, Ipv4Cidr, Ipv6Cidr};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Routes of one address family, keyed by prefix length then by prefix.
+#[derive(Clone)]
+struct Prefixes<K, V> {
+    by_len: Vec<HashMap<K, V>>,
+    // non-empty prefix lengths, longest first.
+    lens: Vec<usize>,
+}
+
+impl<K: Copy + Eq + std::hash::Hash, V> Prefixes<K, V> {
+    fn new(bits: usize) -> Self {
+        Prefixes {
+            by_len: (0..=bits).map(|_| HashMap::new()).collect(),
+            lens: vec![],
+        }
+    }
+
+    fn insert(&mut self, len: usize, prefix: K, value: V) -> Option<V> {
+        if !self.lens.contains(&len) {
+            self.lens.push(len);
+            self.lens.sort_unstable_by(|a, b| b.cmp(a));
+        }
+        self.by_len[len].insert(prefix, value)
+    }
+
+    fn remove(&mut self, len: usize, prefix: K) -> Option<V> {
+        let removed = self.by_len[len].remove(&prefix);
+        if self.by_len[len].is_empty() {
+            self.lens.retain(|&l| l != len);
+        }
+        removed
+    }
+
+    fn lookup<M: Fn(usize) -> K>(&self, mask: M) -> Option<(usize, &V)> {
+        self.lens
+            .iter()
+            .filter_map(|&len| self.by_len[len].get(&mask(len)).map(|v| (len, v)))
+            .next()
+    }
+
+    fn len(&self) -> usize {
+        self.by_len.iter().map(HashMap::len).sum()
+    }
+}
+
+#[inline]
+fn mask_v4(addr: u32, len: usize) -> u32 {
+    match len {
+        0 => 0,
+        _ => addr & (u32::max_value() << (32 - len)),
+    }
+}
+
+#[inline]
+fn mask_v6(addr: u128, len: usize) -> u128 {
+    match len {
+        0 => 0,
+        _ => addr & (u128::max_value() << (128 - len)),
+    }
+}
+
+/// A routing table with longest prefix match lookup.
+///
+/// Holds both IPv4 and IPv6 routes. The value of a route is typically
+/// the next hop or the egress port. Lookups only probe the prefix lengths
+/// that have at least one route, longest first.
+///
+/// # Example
+///
+/// ```
+/// let mut routes = RouteTable::new();
+/// routes.insert_v4("10.0.0.0/8".parse()?, port0);
+/// routes.insert_v4("10.1.0.0/16".parse()?, port1);
+///
+/// assert_eq!(Some(&port1), routes.lookup("10.1.2.3".parse()?));
+/// ```
+#[derive(Clone)]
+pub struct RouteTable<V> {
+    v4: Prefixes<u32, V>,
+    v6: Prefixes<u128, V>,
+}
+
+impl<V> RouteTable<V> {
+    /// Creates an empty routing table.
+    pub fn new() -> Self {
+        RouteTable {
+            v4: Prefixes::new(32),
+            v6: Prefixes::new(128),
+        }
+    }
+
+    /// Adds an IPv4 route, returning the value of the route it replaces.
+    pub fn insert_v4(&mut self, cidr: Ipv4Cidr, value: V) -> Option<V> {
+        let len = cidr.length();
+        let prefix = mask_v4(u32::from(cidr.address()), len);
+        self.v4.insert(len, prefix, value)
+    }
+
+    /// Adds an IPv6 route, returning the value of the route it replaces.
+    pub fn insert_v6(&mut self, cidr: Ipv6Cidr, value: V) -> Option<V> {
+        let len = cidr.length();
+        let prefix = mask_v6(u128::from(cidr.address()), len);
+        self.v6.insert(len, prefix, value)
+    }
+
+    /// Removes an IPv4 route, returning its value.
+    pub fn remove_v4(&mut self, cidr: &Ipv4Cidr) -> Option<V> {
+        let len = cidr.length();
+        self.v4.remove(len, mask_v4(u32::from(cidr.address()), len))
+    }
+
+    /// Removes an IPv6 route, returning its value.
+    pub fn remove_v6(&mut self, cidr: &Ipv6Cidr) -> Option<V> {
+        let len = cidr.length();
+        self.v6
+            .remove(len, mask_v6(u128::from(cidr.address()), len))
+    }
+
+    /// Returns the value of the longest prefix route matching the address.
+    #[inline]
+    pub fn lookup(&self, addr: IpAddr) -> Option<&V> {
+        self.lookup_with_len(addr).map(|(_, value)| value)
+    }
+
+    /// Returns the prefix length and the value of the longest prefix route
+    /// matching the address.
+    pub fn lookup_with_len(&self, addr: IpAddr) -> Option<(usize, &V)> {
+        match addr {
+            IpAddr::V4(addr) => {
+                let addr = u32::from(addr);
+                self.v4.lookup(|len| mask_v4(addr, len))
+            }
+            IpAddr::V6(addr) => {
+                let addr = u128::from(addr);
+                self.v6.lookup(|len| mask_v6(addr, len))
+            }
+        }
+    }
+
+    /// Returns the number of routes.
+    pub fn len(&self) -> usize {
+        self.v4.len() + self.v6.len()
+    }
+
+    /// Returns whether the table has no routes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<V> Default for RouteTable<V> {
+    fn default() -> Self {
+        RouteTable::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_match_v4() {
+        let mut routes = RouteTable::new();
+        routes.insert_v4("0.0.0.0/0".parse().unwrap(), 0);
+        routes.insert_v4("10.0.0.0/8".parse().unwrap(), 1);
+        routes.insert_v4("10.1.0.0/16".parse().unwrap(), 2);
+
+        assert_eq!(Some(&2), routes.lookup("10.1.2.3".parse().unwrap()));
+        assert_eq!(Some(&1), routes.lookup("10.2.2.3".parse().unwrap()));
+        assert_eq!(
+            Some((0, &0)),
+            routes.lookup_with_len("192.168.0.1".parse().unwrap())
+        );
+
+        assert_eq!(Some(2), routes.remove_v4(&"10.1.0.0/16".parse().unwrap()));
+        assert_eq!(Some(&1), routes.lookup("10.1.2.3".parse().unwrap()));
+        assert_eq!(2, routes.len());
+    }
+
+    #[test]
+    fn longest_prefix_match_v6() {
+        let mut routes = RouteTable::new();
+        routes.insert_v6("2001:db8::/32".parse().unwrap(), 1);
+        routes.insert_v6("2001:db8:85a3::/48".parse().unwrap(), 2);
+
+        assert_eq!(Some(&2), routes.lookup("2001:db8:85a3::1".parse().unwrap()));
+        assert_eq!(Some(&1), routes.lookup("2001:db8:1::1".parse().unwrap()));
+        assert_eq!(None, routes.lookup("2001:db9::1".parse().unwrap()));
+
+        // address families do not mix.
+        assert_eq!(None, routes.lookup("10.0.0.1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,236 @@
+use super::{Ipv4Cidr, Ipv6Cidr, RouteTable};
+use crate::packets::ip::IpPacket;
+use std::fmt;
+use std::net::IpAddr;
+
+/// A built-in set of special purpose prefixes that should not be seen on
+/// the public internet.
+///
+/// The sets follow the IANA special purpose address registries, RFC 6890.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PrefixSet {
+    /// Private addresses, RFC 1918, and unique local IPv6 addresses,
+    /// RFC 4193.
+    Private,
+    /// The shared address space of carrier-grade NATs, RFC 6598.
+    Shared,
+    /// Link-local addresses.
+    LinkLocal,
+    /// Loopback addresses.
+    Loopback,
+    /// Documentation addresses, RFC 5737 and RFC 3849.
+    Documentation,
+    /// Benchmarking addresses, RFC 2544 and RFC 5180.
+    Benchmarking,
+    /// The "this network" block, the addresses reserved for future use and
+    /// the limited broadcast.
+    Reserved,
+    /// Multicast addresses. Only matches as a source address, multicast
+    /// destinations are legitimate.
+    Multicast,
+}
+
+impl PrefixSet {
+    /// All the built-in sets.
+    pub const ALL: [PrefixSet; 8] = [
+        PrefixSet::Private,
+        PrefixSet::Shared,
+        PrefixSet::LinkLocal,
+        PrefixSet::Loopback,
+        PrefixSet::Documentation,
+        PrefixSet::Benchmarking,
+        PrefixSet::Reserved,
+        PrefixSet::Multicast,
+    ];
+
+    /// Returns the IPv4 prefixes of the set.
+    pub fn v4_prefixes(self) -> &'static [&'static str] {
+        match self {
+            PrefixSet::Private => &["10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16"],
+            PrefixSet::Shared => &["100.64.0.0/10"],
+            PrefixSet::LinkLocal => &["169.254.0.0/16"],
+            PrefixSet::Loopback => &["127.0.0.0/8"],
+            PrefixSet::Documentation => &["192.0.2.0/24", "198.51.100.0/24", "203.0.113.0/24"],
+            PrefixSet::Benchmarking => &["198.18.0.0/15"],
+            PrefixSet::Reserved => &["0.0.0.0/8", "240.0.0.0/4"],
+            PrefixSet::Multicast => &["224.0.0.0/4"],
+        }
+    }
+
+    /// Returns the IPv6 prefixes of the set.
+    pub fn v6_prefixes(self) -> &'static [&'static str] {
+        match self {
+            PrefixSet::Private => &["fc00::/7"],
+            PrefixSet::Shared => &[],
+            PrefixSet::LinkLocal => &["fe80::/10"],
+            PrefixSet::Loopback => &["::1/128"],
+            PrefixSet::Documentation => &["2001:db8::/32"],
+            PrefixSet::Benchmarking => &["2001:2::/48"],
+            PrefixSet::Reserved => &["::/128", "100::/64"],
+            PrefixSet::Multicast => &["ff00::/8"],
+        }
+    }
+}
+
+impl fmt::Display for PrefixSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefixSet::Private => write!(f, "private"),
+            PrefixSet::Shared => write!(f, "shared"),
+            PrefixSet::LinkLocal => write!(f, "link_local"),
+            PrefixSet::Loopback => write!(f, "loopback"),
+            PrefixSet::Documentation => write!(f, "documentation"),
+            PrefixSet::Benchmarking => write!(f, "benchmarking"),
+            PrefixSet::Reserved => write!(f, "reserved"),
+            PrefixSet::Multicast => write!(f, "multicast"),
+        }
+    }
+}
+
+/// A filter of bogon and martian addresses, the addresses that cannot be
+/// the source or the destination of a packet received at the edge.
+///
+/// The filter is a `RouteTable` of the prefixes of the selected built-in
+/// sets, plus any prefix added to it, such as the unallocated blocks or
+/// the prefixes of the local network.
+///
+/// # Example
+///
+/// ```
+/// let mut martians = Martians::with_sets(&PrefixSet::ALL);
+/// martians.insert_v4("192.0.0.0/24".parse()?);
+/// let martians = Arc::new(martians);
+///
+/// Poll::new(q.clone())
+///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+///     .drop_martians(martians.clone())
+///     .send(q)
+/// ```
+pub struct Martians {
+    prefixes: RouteTable<Option<PrefixSet>>,
+}
+
+impl Martians {
+    /// Creates an empty filter.
+    pub fn new() -> Self {
+        Martians {
+            prefixes: RouteTable::new(),
+        }
+    }
+
+    /// Creates a filter of the built-in sets.
+    pub fn with_sets(sets: &[PrefixSet]) -> Self {
+        let mut martians = Martians::new();
+        sets.iter().for_each(|&set| martians.add_set(set));
+        martians
+    }
+
+    /// Adds the prefixes of a built-in set.
+    pub fn add_set(&mut self, set: PrefixSet) {
+        for prefix in set.v4_prefixes() {
+            let cidr = prefix.parse::<Ipv4Cidr>().unwrap();
+            self.prefixes.insert_v4(cidr, Some(set));
+        }
+        for prefix in set.v6_prefixes() {
+            let cidr = prefix.parse::<Ipv6Cidr>().unwrap();
+            self.prefixes.insert_v6(cidr, Some(set));
+        }
+    }
+
+    /// Adds a user defined IPv4 prefix.
+    pub fn insert_v4(&mut self, cidr: Ipv4Cidr) {
+        self.prefixes.insert_v4(cidr, None);
+    }
+
+    /// Adds a user defined IPv6 prefix.
+    pub fn insert_v6(&mut self, cidr: Ipv6Cidr) {
+        self.prefixes.insert_v6(cidr, None);
+    }
+
+    /// Returns the longest prefix matching the address, as the built-in
+    /// set it belongs to, or `None` for a user defined prefix.
+    #[inline]
+    pub fn lookup(&self, addr: IpAddr) -> Option<Option<PrefixSet>> {
+        self.prefixes.lookup(addr).cloned()
+    }
+
+    /// Returns whether the address is a martian source.
+    #[inline]
+    pub fn is_martian_src(&self, addr: IpAddr) -> bool {
+        self.lookup(addr).is_some()
+    }
+
+    /// Returns whether the address is a martian destination.
+    ///
+    /// Unlike sources, multicast destinations are not martian.
+    #[inline]
+    pub fn is_martian_dst(&self, addr: IpAddr) -> bool {
+        match self.lookup(addr) {
+            Some(Some(PrefixSet::Multicast)) | None => false,
+            Some(_) => true,
+        }
+    }
+
+    /// Returns whether neither the source nor the destination address of
+    /// the packet is martian.
+    #[inline]
+    pub fn check_packet<P: IpPacket>(&self, packet: &P) -> bool {
+        !self.is_martian_src(packet.src()) && !self.is_martian_dst(packet.dst())
+    }
+}
+
+impl Default for Martians {
+    /// Creates a filter of all the built-in sets.
+    fn default() -> Self {
+        Martians::with_sets(&PrefixSet::ALL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_builtin_sets() {
+        // `add_set` panics on an invalid prefix.
+        let martians = Martians::default();
+        assert_eq!(
+            Some(Some(PrefixSet::Documentation)),
+            martians.lookup("2001:db8::1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn martian_addresses() {
+        let martians = Martians::default();
+
+        assert!(martians.is_martian_src("10.1.1.1".parse().unwrap()));
+        assert!(martians.is_martian_src("127.0.0.1".parse().unwrap()));
+        assert!(martians.is_martian_src("fe80::1".parse().unwrap()));
+        assert!(martians.is_martian_dst("255.255.255.255".parse().unwrap()));
+        assert!(!martians.is_martian_src("8.8.8.8".parse().unwrap()));
+        assert!(!martians.is_martian_dst("2606:4700::1111".parse().unwrap()));
+    }
+
+    #[test]
+    fn multicast_only_as_source() {
+        let martians = Martians::with_sets(&[PrefixSet::Multicast]);
+
+        assert!(martians.is_martian_src("224.0.0.5".parse().unwrap()));
+        assert!(!martians.is_martian_dst("224.0.0.5".parse().unwrap()));
+        assert!(martians.is_martian_src("ff02::1".parse().unwrap()));
+        assert!(!martians.is_martian_dst("ff02::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn user_defined_prefixes() {
+        let mut martians = Martians::with_sets(&[PrefixSet::Private]);
+        martians.insert_v4("192.168.1.0/24".parse().unwrap());
+        martians.insert_v6("2001:db9::/32".parse().unwrap());
+
+        // the user defined prefix is longer than the built-in one.
+        assert_eq!(Some(None), martians.lookup("192.168.1.1".parse().unwrap()));
+        assert!(martians.is_martian_dst("192.168.1.1".parse().unwrap()));
+        assert!(martians.is_martian_src("2001:db9::1".parse().unwrap()));
+    }
+}
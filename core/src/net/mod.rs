@@ -1,5 +1,51 @@
+mod acl;
+mod bloom;
 mod cidr;
+mod conntrack;
+mod crc;
+mod cuckoo;
+mod ephemeral;
+mod flood;
+mod flowsync;
+mod ipid;
+mod lpm;
 mod mac;
+mod martians;
+mod packet_hash;
+mod prefix_tags;
+mod rand;
+mod replay;
+mod ruleset;
+mod scope;
+mod sketch;
+mod timer_wheel;
+mod urpf;
 
-pub use self::cidr::{CidrParseError, Ipv4Cidr, Ipv6Cidr};
+pub use self::acl::{Acl, AclAction, AclError, AclRule, AclVerdict};
+pub use self::bloom::{BloomFilter, FilterMismatch};
+pub use self::cidr::{Cidr, CidrParseError, Ipv4Cidr, Ipv6Cidr};
+pub use self::conntrack::{FlowEnd, FlowRecord, FlowTable};
+pub use self::crc::{crc32c, hash_flow, hash_flow_symmetric};
+pub use self::cuckoo::{CuckooFilter, CuckooFull};
+pub use self::ephemeral::{EphemeralPorts, PortRangeError};
+pub use self::flood::{FloodAlert, FloodBlocklist, FloodDetector, FloodKind, FloodThresholds};
+pub use self::flowsync::{
+    decode_message, encode_delta, encode_messages, FlowDelta, FlowSyncError, FlowSyncKey,
+    FlowSyncRx, FlowSyncTx, SyncState, FLOW_SYNC_VERSION,
+};
+pub use self::ipid::{next_ip_id, IpIdGenerator};
+pub use self::lpm::RouteTable;
 pub use self::mac::{MacAddr, MacParseError};
+pub use self::martians::{Martians, PrefixSet};
+pub use self::packet_hash::{packet_hash, HashFields, HashKey};
+pub use self::prefix_tags::{PrefixFileError, PrefixTags};
+pub use self::rand::{fast_rand, fast_rand_below, Xoshiro256};
+pub use self::replay::ReplayWindow;
+pub use self::ruleset::{Pattern, Rule, Ruleset, RulesetError};
+pub use self::scope::{Ipv6Class, Ipv6Scope, ZonedIpv6Addr, ZonedIpv6ParseError};
+pub use self::sketch::{merge_top_k, CountMinSketch, HeavyKeeper, SketchMismatch};
+pub use self::timer_wheel::TimerWheel;
+pub use self::urpf::{Urpf, UrpfMode};
+
+pub(crate) use self::packet_hash::ip_packet_hash;
+pub(crate) use self::sketch::KeyHasher;
@@ -0,0 +1,411 @@
+use super::KeyHasher;
+use crate::packets::data_slice;
+use crate::packets::ip::v6::is_ipv6_extension;
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+use crate::Mbuf;
+use std::hash::Hasher;
+use std::ops::BitOr;
+
+/// A set of the fields of a packet hashed by `packet_hash`.
+///
+/// The sets combine with `|`, as `HashFields::ADDRS | HashFields::VLAN`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct HashFields(u8);
+
+impl HashFields {
+    /// No field, every packet hashes the same.
+    pub const NONE: HashFields = HashFields(0);
+    /// The VLAN IDs of the 802.1Q and 802.1ad tags.
+    pub const VLAN: HashFields = HashFields(1);
+    /// The source and destination IP addresses.
+    pub const ADDRS: HashFields = HashFields(1 << 1);
+    /// The IP protocol, or the next header after the IPv6 extension
+    /// headers.
+    pub const PROTOCOL: HashFields = HashFields(1 << 2);
+    /// The source and destination ports of TCP, UDP and SCTP, past the
+    /// IPv6 extension headers. The other protocols, and the fragments
+    /// after the first, have none.
+    pub const PORTS: HashFields = HashFields(1 << 3);
+    /// The identification of IPv4, or the flow label of IPv6.
+    pub const IP_ID: HashFields = HashFields(1 << 4);
+    /// The bytes after the IP header, or after the link layer header for
+    /// the frames other than IP.
+    pub const PAYLOAD: HashFields = HashFields(1 << 5);
+    /// The whole frame, byte for byte, which includes all the fields
+    /// above.
+    pub const FRAME: HashFields = HashFields(1 << 6);
+    /// Hashes the source and destination of the addresses and ports in
+    /// order, so the two directions of a flow hash the same.
+    pub const SYMMETRIC: HashFields = HashFields(1 << 7);
+
+    /// The 5-tuple of the flow.
+    pub const FIVE_TUPLE: HashFields = HashFields(0b1110);
+
+    /// Returns whether the set has all the fields of `other`.
+    #[inline]
+    pub fn contains(self, other: HashFields) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for HashFields {
+    type Output = HashFields;
+
+    #[inline]
+    fn bitor(self, other: HashFields) -> HashFields {
+        HashFields(self.0 | other.0)
+    }
+}
+
+/// The hash function and key of `packet_hash`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum HashKey {
+    /// Two CRC-32C lanes computed in hardware, the cheapest.
+    ///
+    /// The hash is unkeyed. The seed only changes the starting values of
+    /// the lanes, and CRC is linear, so the packets colliding with one
+    /// seed collide with all of them. The two lanes are not independent
+    /// either, the hash has about 32 bits of entropy. Only for the hashes
+    /// nobody has a reason to steer, on traffic that is trusted.
+    Crc(u32),
+    /// SipHash-2-4 with the 128-bit key, for the hashes an attacker must
+    /// not be able to predict, such as the ones picking a core for the
+    /// traffic of the Internet.
+    Sip(u64, u64),
+}
+
+impl Default for HashKey {
+    /// SipHash with a zero key, the same on every core and every run.
+    fn default() -> Self {
+        HashKey::Sip(0, 0)
+    }
+}
+
+/// Returns the hash of the fields of the packet.
+///
+/// The dedup and the distribution to cores hash the packets with this
+/// function, and so should the sampling or the ECMP next hop selection of
+/// the applications, so the same fields and key give the same hash
+/// wherever it is computed, on any core.
+///
+/// The fields are read from the frame as it is, up to two VLAN tags deep,
+/// without parsing the packet first. The fields the packet does not have,
+/// such as the ports of an ICMP packet, are left out of the hash.
+///
+/// # Example
+///
+/// ```
+/// let key = HashKey::Sip(0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+/// let core = packet_hash(packet.mbuf(), HashFields::FIVE_TUPLE, key) % cores;
+/// ```
+pub fn packet_hash(packet: &Mbuf, fields: HashFields, key: HashKey) -> u64 {
+    hash_with(packet, fields, key).0
+}
+
+/// Returns the hash of the fields of the packet, or `None` if the packet
+/// is not IP.
+pub(crate) fn ip_packet_hash(packet: &Mbuf, fields: HashFields, key: HashKey) -> Option<u64> {
+    match hash_with(packet, fields, key) {
+        (hash, true) => Some(hash),
+        (_, false) => None,
+    }
+}
+
+fn hash_with(packet: &Mbuf, fields: HashFields, key: HashKey) -> (u64, bool) {
+    match key {
+        HashKey::Crc(seed) => {
+            let mut hasher = KeyHasher::with_seed(seed);
+            let ip = write_fields(packet, fields, &mut hasher);
+            (hasher.finish(), ip)
+        }
+        HashKey::Sip(k0, k1) => {
+            // the keyed SipHash of std is deprecated only in favor of
+            // `DefaultHasher`, which takes no key.
+            #[allow(deprecated)]
+            let mut hasher = std::hash::SipHasher::new_with_keys(k0, k1);
+            let ip = write_fields(packet, fields, &mut hasher);
+            (hasher.finish(), ip)
+        }
+    }
+}
+
+const ETHER_TYPE_VLAN: u16 = 0x8100;
+const ETHER_TYPE_QINQ: u16 = 0x88a8;
+const ETHER_TYPE_IPV4: u16 = 0x0800;
+const ETHER_TYPE_IPV6: u16 = 0x86dd;
+
+#[inline]
+fn be16(frame: &[u8], offset: usize) -> Option<u16> {
+    frame
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// The IP fields of a packet.
+struct Ip<'a> {
+    src: &'a [u8],
+    dst: &'a [u8],
+    protocol: u8,
+    id: &'a [u8],
+    // the offset of the IP payload.
+    payload: usize,
+    // whether the payload starts with the transport header.
+    first: bool,
+}
+
+impl<'a> Ip<'a> {
+    fn v4(frame: &'a [u8], offset: usize) -> Option<Self> {
+        let header = frame.get(offset..offset + 20)?;
+        let ihl = usize::from(header[0] & 0x0f) * 4;
+        let fragment_offset = u16::from_be_bytes([header[6], header[7]]) & 0x1fff;
+
+        Some(Ip {
+            src: &header[12..16],
+            dst: &header[16..20],
+            protocol: header[9],
+            id: &header[4..6],
+            payload: offset + ihl.max(20),
+            first: fragment_offset == 0,
+        })
+    }
+
+    fn v6(frame: &'a [u8], offset: usize) -> Option<Self> {
+        let header = frame.get(offset..offset + 40)?;
+
+        // walks the extension headers to the upper layer protocol. a
+        // truncated chain ends at the extension header, without ports.
+        let mut protocol = header[6];
+        let mut payload = offset + 40;
+        let mut first = true;
+        while first && is_ipv6_extension(ProtocolNumber::new(protocol)) {
+            let extension = match frame.get(payload..payload + 8) {
+                Some(extension) => extension,
+                None => break,
+            };
+
+            let len = match ProtocolNumber::new(protocol) {
+                ProtocolNumbers::Ah => (usize::from(extension[1]) + 2) * 4,
+                ProtocolNumbers::Ipv6Frag => {
+                    first = u16::from_be_bytes([extension[2], extension[3]]) >> 3 == 0;
+                    8
+                }
+                _ => (usize::from(extension[1]) + 1) * 8,
+            };
+            protocol = extension[0];
+            payload += len;
+        }
+
+        Some(Ip {
+            src: &header[8..24],
+            dst: &header[24..40],
+            protocol,
+            // the flow label, with the low nibble of the traffic class.
+            id: &header[1..4],
+            payload,
+            first,
+        })
+    }
+
+    fn ports(&self, frame: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+        match self.protocol {
+            // TCP, UDP and SCTP.
+            6 | 17 | 132 if self.first => frame
+                .get(self.payload..self.payload + 4)
+                .map(|ports| (&ports[..2], &ports[2..])),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the fields of the packet to the hasher, and returns whether the
+/// packet is IP.
+fn write_fields<H: Hasher>(packet: &Mbuf, fields: HashFields, hasher: &mut H) -> bool {
+    let frame = data_slice(packet, 0, packet.data_len());
+
+    // the frame has all the other fields, the headers are only walked to
+    // tell whether the packet is IP.
+    let fields = if fields.contains(HashFields::FRAME) {
+        hasher.write(frame);
+        HashFields::NONE
+    } else {
+        fields
+    };
+
+    // walks the VLAN tags to the ether type.
+    let mut offset = 12;
+    let mut ether_type = be16(frame, offset);
+    for _ in 0..2 {
+        match ether_type {
+            Some(ETHER_TYPE_VLAN) | Some(ETHER_TYPE_QINQ) => {
+                if fields.contains(HashFields::VLAN) {
+                    if let Some(tci) = be16(frame, offset + 2) {
+                        hasher.write_u16(tci & 0x0fff);
+                    }
+                }
+                offset += 4;
+                ether_type = be16(frame, offset);
+            }
+            _ => break,
+        }
+    }
+
+    // the link layer header ends after the ether type.
+    offset += 2;
+    let ip = match ether_type {
+        Some(ETHER_TYPE_IPV4) => Ip::v4(frame, offset),
+        Some(ETHER_TYPE_IPV6) => Ip::v6(frame, offset),
+        _ => None,
+    };
+
+    let ip = match ip {
+        Some(ip) => ip,
+        None => {
+            if fields.contains(HashFields::PAYLOAD) {
+                hasher.write(frame.get(offset..).unwrap_or(&[]));
+            }
+            return false;
+        }
+    };
+
+    let ports = ip.ports(frame);
+    let (src, dst) = match ports {
+        // orders the ends with their ports, so a flow between two ports of
+        // the same host is still symmetric.
+        Some((src_port, dst_port))
+            if fields.contains(HashFields::SYMMETRIC)
+                && (ip.dst, dst_port) < (ip.src, src_port) =>
+        {
+            ((ip.dst, Some(dst_port)), (ip.src, Some(src_port)))
+        }
+        Some((src_port, dst_port)) => ((ip.src, Some(src_port)), (ip.dst, Some(dst_port))),
+        None if fields.contains(HashFields::SYMMETRIC) && ip.dst < ip.src => {
+            ((ip.dst, None), (ip.src, None))
+        }
+        None => ((ip.src, None), (ip.dst, None)),
+    };
+
+    if fields.contains(HashFields::ADDRS) {
+        hasher.write(src.0);
+        hasher.write(dst.0);
+    }
+    if fields.contains(HashFields::PROTOCOL) {
+        hasher.write_u8(ip.protocol);
+    }
+    if fields.contains(HashFields::PORTS) {
+        if let (Some(src_port), Some(dst_port)) = (src.1, dst.1) {
+            hasher.write(src_port);
+            hasher.write(dst_port);
+        }
+    }
+    if fields.contains(HashFields::IP_ID) {
+        hasher.write(ip.id);
+    }
+    if fields.contains(HashFields::PAYLOAD) {
+        hasher.write(frame.get(ip.payload..).unwrap_or(&[]));
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::IPV6_EXTENSIONS_PACKET;
+    use crate::packets::{Ethernet, Packet, Udp};
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+
+    #[nb2::test]
+    fn hash_selected_fields() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let mut udp = packet
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap()
+            .parse::<Udp<Ipv4>>()
+            .unwrap();
+        let key = HashKey::default();
+
+        let addrs = packet_hash(udp.mbuf(), HashFields::ADDRS, key);
+        let tuple = packet_hash(udp.mbuf(), HashFields::FIVE_TUPLE, key);
+        assert_ne!(addrs, tuple);
+        assert_ne!(
+            tuple,
+            packet_hash(udp.mbuf(), HashFields::FIVE_TUPLE, HashKey::Crc(1))
+        );
+        assert_ne!(
+            tuple,
+            packet_hash(udp.mbuf(), HashFields::FIVE_TUPLE, HashKey::Sip(0, 1))
+        );
+
+        let tcp = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        assert_ne!(tuple, packet_hash(&tcp, HashFields::FIVE_TUPLE, key));
+
+        udp.set_src_port(udp.src_port() + 1);
+        assert_eq!(addrs, packet_hash(udp.mbuf(), HashFields::ADDRS, key));
+        assert_ne!(tuple, packet_hash(udp.mbuf(), HashFields::FIVE_TUPLE, key));
+    }
+
+    #[nb2::test]
+    fn symmetric_and_vlan() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let mut udp = packet
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap()
+            .parse::<Udp<Ipv4>>()
+            .unwrap();
+        let key = HashKey::Sip(7, 11);
+        let fields = HashFields::FIVE_TUPLE | HashFields::SYMMETRIC;
+        let forward = packet_hash(udp.mbuf(), fields, key);
+        let one_way = packet_hash(udp.mbuf(), HashFields::FIVE_TUPLE, key);
+
+        let (src, dst) = (udp.envelope().src(), udp.envelope().dst());
+        let (src_port, dst_port) = (udp.src_port(), udp.dst_port());
+        udp.envelope_mut().set_src(dst);
+        udp.envelope_mut().set_dst(src);
+        udp.set_src_port(dst_port);
+        udp.set_dst_port(src_port);
+        assert_eq!(forward, packet_hash(udp.mbuf(), fields, key));
+        assert_ne!(
+            one_way,
+            packet_hash(udp.mbuf(), HashFields::FIVE_TUPLE, key)
+        );
+
+        // a tag in front of the IP header, as 802.1Q puts it.
+        let mut tagged = UDP_PACKET[..12].to_vec();
+        tagged.extend_from_slice(&[0x81, 0x00, 0x00, 0x2a]);
+        tagged.extend_from_slice(&UDP_PACKET[12..]);
+        let tagged = Mbuf::from_bytes(&tagged).unwrap();
+        let untagged = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+
+        let tuple = packet_hash(&untagged, HashFields::FIVE_TUPLE, key);
+        assert_eq!(tuple, packet_hash(&tagged, HashFields::FIVE_TUPLE, key));
+        assert_ne!(
+            tuple,
+            packet_hash(&tagged, HashFields::FIVE_TUPLE | HashFields::VLAN, key)
+        );
+    }
+
+    #[nb2::test]
+    fn ports_past_ipv6_extensions() {
+        let key = HashKey::default();
+        let packet = Mbuf::from_bytes(&IPV6_EXTENSIONS_PACKET).unwrap();
+        let none = packet_hash(&packet, HashFields::NONE, key);
+        assert_ne!(none, packet_hash(&packet, HashFields::PORTS, key));
+
+        // the same ports in a fragment after the first are only data.
+        let mut fragment = IPV6_EXTENSIONS_PACKET.to_vec();
+        // the destination options become a fragment header, offset 185.
+        fragment[54] = 0x2c;
+        fragment[62..64].copy_from_slice(&[0x11, 0x00]);
+        fragment[64..66].copy_from_slice(&(185u16 << 3).to_be_bytes());
+        let fragment = Mbuf::from_bytes(&fragment).unwrap();
+        assert_eq!(
+            packet_hash(&fragment, HashFields::NONE, key),
+            packet_hash(&fragment, HashFields::PORTS, key)
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use super::{Ipv4Cidr, Ipv6Cidr, RouteTable};
+use crate::Result;
+use failure::Fail;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Error indicating an invalid line in a prefix file.
+#[derive(Debug, Fail)]
+#[fail(display = "Invalid prefix at line {}: {}", _0, _1)]
+pub struct PrefixFileError(usize, String);
+
+/// A set of prefixes labeled by the user, such as the country of the
+/// network or the customer it belongs to.
+///
+/// Each distinct label is assigned a tag, a number from `1` that fits the
+/// mark of `PacketMeta`, so the packets can be tagged with the label of
+/// their address by longest prefix match. The tags of the labels already
+/// known are kept when the set is reloaded.
+///
+/// The prefixes are loaded from a CSV file of `cidr,label` lines. Further
+/// columns are ignored, so the blocks files of the GeoLite2 CSV databases
+/// load as is, labeled with the geoname ID. MMDB databases are not read
+/// directly and need to be exported to CSV first.
+pub struct PrefixTags {
+    prefixes: RouteTable<u32>,
+    labels: Vec<String>,
+    tags: HashMap<String, u32>,
+}
+
+impl PrefixTags {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        PrefixTags {
+            prefixes: RouteTable::new(),
+            labels: vec![],
+            tags: HashMap::new(),
+        }
+    }
+
+    /// Loads the prefixes from a CSV file.
+    pub fn load_csv<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut tags = PrefixTags::new();
+        tags.read_csv(BufReader::new(File::open(path)?))?;
+        Ok(tags)
+    }
+
+    /// Loads a new set of prefixes from a CSV file, keeping the tags of
+    /// the labels of this set.
+    pub fn reload_csv<P: AsRef<Path>>(&self, path: P) -> Result<Self> {
+        let mut tags = PrefixTags {
+            prefixes: RouteTable::new(),
+            labels: self.labels.clone(),
+            tags: self.tags.clone(),
+        };
+        tags.read_csv(BufReader::new(File::open(path)?))?;
+        Ok(tags)
+    }
+
+    /// Reads `cidr,label` lines, skipping blank lines, `#` comments and
+    /// the `network` header line.
+    pub fn read_csv<R: BufRead>(&mut self, reader: R) -> Result<()> {
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(',').map(str::trim);
+            let (cidr, label) = match (fields.next(), fields.next()) {
+                (Some("network"), _) => continue,
+                (Some(cidr), Some(label)) if !label.is_empty() => (cidr, label),
+                _ => return Err(PrefixFileError(n + 1, line.to_owned()).into()),
+            };
+
+            if cidr.contains(':') {
+                let cidr = cidr
+                    .parse::<Ipv6Cidr>()
+                    .map_err(|_| PrefixFileError(n + 1, line.to_owned()))?;
+                self.insert_v6(cidr, label);
+            } else {
+                let cidr = cidr
+                    .parse::<Ipv4Cidr>()
+                    .map_err(|_| PrefixFileError(n + 1, line.to_owned()))?;
+                self.insert_v4(cidr, label);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the tag of the label, assigning a new one if the label is
+    /// not known yet.
+    fn intern(&mut self, label: &str) -> u32 {
+        if let Some(&tag) = self.tags.get(label) {
+            return tag;
+        }
+
+        self.labels.push(label.to_owned());
+        let tag = self.labels.len() as u32;
+        self.tags.insert(label.to_owned(), tag);
+        tag
+    }
+
+    /// Adds an IPv4 prefix with its label.
+    pub fn insert_v4(&mut self, cidr: Ipv4Cidr, label: &str) {
+        let tag = self.intern(label);
+        self.prefixes.insert_v4(cidr, tag);
+    }
+
+    /// Adds an IPv6 prefix with its label.
+    pub fn insert_v6(&mut self, cidr: Ipv6Cidr, label: &str) {
+        let tag = self.intern(label);
+        self.prefixes.insert_v6(cidr, tag);
+    }
+
+    /// Returns the tag of the longest prefix matching the address.
+    #[inline]
+    pub fn lookup(&self, addr: IpAddr) -> Option<u32> {
+        self.prefixes.lookup(addr).cloned()
+    }
+
+    /// Returns the tag of the label.
+    pub fn tag(&self, label: &str) -> Option<u32> {
+        self.tags.get(label).cloned()
+    }
+
+    /// Returns the label of the tag.
+    pub fn label(&self, tag: u32) -> Option<&str> {
+        self.labels
+            .get((tag as usize).wrapping_sub(1))
+            .map(String::as_str)
+    }
+
+    /// Returns the number of prefixes.
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    /// Returns whether the set has no prefixes.
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+}
+
+impl Default for PrefixTags {
+    fn default() -> Self {
+        PrefixTags::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const CSV: &str = "\
+network,geoname_id,registered_country_geoname_id
+# a comment
+1.0.0.0/24,2077456,2077456
+1.0.1.0/24,1814991,1814991
+2001:200::/32,1861060,1861060
+";
+
+    #[test]
+    fn read_csv_prefixes() {
+        let mut tags = PrefixTags::new();
+        tags.read_csv(Cursor::new(CSV)).unwrap();
+
+        assert_eq!(3, tags.len());
+        let tag = tags.lookup("1.0.1.1".parse().unwrap()).unwrap();
+        assert_eq!(Some("1814991"), tags.label(tag));
+        assert_eq!(
+            tags.tag("1861060"),
+            tags.lookup("2001:200::1".parse().unwrap())
+        );
+        assert_eq!(None, tags.lookup("8.8.8.8".parse().unwrap()));
+        assert_eq!(None, tags.label(0));
+    }
+
+    #[test]
+    fn reject_invalid_lines() {
+        let mut tags = PrefixTags::new();
+        assert!(tags.read_csv(Cursor::new("1.0.0.0/24\n")).is_err());
+        assert!(tags.read_csv(Cursor::new("1.0.0.0/33,a\n")).is_err());
+    }
+
+    #[test]
+    fn keep_tags_on_reload() {
+        let dir = std::env::temp_dir().join(format!("nb2-prefix-tags-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tags.csv");
+
+        std::fs::write(&path, "10.0.0.0/8,a\n10.1.0.0/16,b\n").unwrap();
+        let tags = PrefixTags::load_csv(&path).unwrap();
+
+        std::fs::write(&path, "10.2.0.0/16,c\n10.1.0.0/16,b\n").unwrap();
+        let reloaded = tags.reload_csv(&path).unwrap();
+
+        assert_eq!(tags.tag("b"), reloaded.tag("b"));
+        assert_eq!(Some(3), reloaded.tag("c"));
+        assert_eq!(None, reloaded.lookup("10.0.0.1".parse().unwrap()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
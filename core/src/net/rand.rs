@@ -0,0 +1,128 @@
+use crate::ffi;
+use std::cell::RefCell;
+
+/// A xoshiro256** pseudo-random number generator.
+///
+/// Fast and small, for the datapath decisions that need randomness, such
+/// as sampling, load balancing and port selection. It is not a
+/// cryptographic generator, never use it for secrets or for anything an
+/// attacker must not predict.
+///
+/// For the current core's generator, use `fast_rand`.
+#[derive(Clone, Debug)]
+pub struct Xoshiro256 {
+    state: [u64; 4],
+}
+
+impl Xoshiro256 {
+    /// Creates a new generator from a seed.
+    ///
+    /// The state is expanded from the seed with splitmix64, as recommended
+    /// by the authors, so any seed including `0` is fine.
+    pub fn new(seed: u64) -> Self {
+        let mut seed = seed;
+        let mut splitmix = || {
+            seed = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        Xoshiro256 {
+            state: [splitmix(), splitmix(), splitmix(), splitmix()],
+        }
+    }
+
+    /// Returns the next random `u64`.
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Returns the next random `u32`.
+    #[inline]
+    pub fn next_u32(&mut self) -> u32 {
+        // the upper bits are the better ones.
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Returns a random number from `0` to `n`, excluding `n`.
+    ///
+    /// Uses the multiply and shift reduction instead of a modulo. The bias
+    /// is negligible for the small `n` of the datapath.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is `0`.
+    #[inline]
+    pub fn below(&mut self, n: u32) -> u32 {
+        assert!(n > 0);
+        ((u64::from(self.next_u32()) * u64::from(n)) >> 32) as u32
+    }
+
+    /// Returns `true` with the probability of `1 / n`, for `1 in n`
+    /// sampling.
+    #[inline]
+    pub fn one_in(&mut self, n: u32) -> bool {
+        self.below(n) == 0
+    }
+}
+
+thread_local! {
+    // the generator of the current core, seeded by the time stamp counter.
+    static RNG: RefCell<Xoshiro256> = RefCell::new(Xoshiro256::new(unsafe { ffi::_rte_rdtsc() }));
+}
+
+/// Returns a random `u64` from the current core's generator.
+#[inline]
+pub fn fast_rand() -> u64 {
+    RNG.with(|rng| rng.borrow_mut().next_u64())
+}
+
+/// Returns a random number from `0` to `n`, excluding `n`, from the
+/// current core's generator.
+#[inline]
+pub fn fast_rand_below(n: u32) -> u32 {
+    RNG.with(|rng| rng.borrow_mut().below(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_for_seed() {
+        let mut a = Xoshiro256::new(42);
+        let mut b = Xoshiro256::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+
+        let mut c = Xoshiro256::new(43);
+        assert_ne!(Xoshiro256::new(42).next_u64(), c.next_u64());
+    }
+
+    #[test]
+    fn below_is_in_range() {
+        let mut rng = Xoshiro256::new(0);
+        let mut seen = [false; 10];
+        for _ in 0..1000 {
+            let n = rng.below(10);
+            assert!(n < 10);
+            seen[n as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+}
@@ -0,0 +1,169 @@
+/// A sliding window anti-replay check, as described in RFC 4303 section
+/// 3.4.3 and used by IPsec and WireGuard.
+///
+/// The window tracks the highest sequence number accepted and which of
+/// the `size` sequence numbers below it have been seen. A sequence number
+/// is accepted once, if it is above the window or within the window and
+/// not seen yet. Anything older than the window is rejected.
+///
+/// The check is meant to be owned by the pipeline that handles the flow,
+/// one window per flow or security association.
+///
+/// # Example
+///
+/// ```
+/// let mut window = ReplayWindow::new(128);
+///
+/// if !window.check_and_update(seq_no) {
+///     return Ok(Disposition::Drop(...));
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReplayWindow {
+    size: u32,
+    // the highest sequence number accepted, 0 when none is.
+    top: u64,
+    // bit `n` is set when `top - n` has been seen.
+    bitmap: u128,
+}
+
+impl ReplayWindow {
+    /// The largest window size supported.
+    pub const MAX_SIZE: u32 = 128;
+
+    /// Creates a new window of `size` sequence numbers. RFC 4303 requires
+    /// at least `32` and recommends `64`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is `0` or greater than `ReplayWindow::MAX_SIZE`.
+    pub fn new(size: u32) -> Self {
+        assert!(size > 0 && size <= ReplayWindow::MAX_SIZE);
+        ReplayWindow {
+            size,
+            top: 0,
+            bitmap: 0,
+        }
+    }
+
+    /// Returns the size of the window.
+    #[inline]
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the highest sequence number accepted.
+    #[inline]
+    pub fn top(&self) -> u64 {
+        self.top
+    }
+
+    /// Returns whether the sequence number would be accepted, without
+    /// marking it as seen.
+    ///
+    /// Use it before authenticating the packet, and `update` once the
+    /// packet is authentic, so forged packets can't move the window.
+    #[inline]
+    pub fn check(&self, seq_no: u64) -> bool {
+        // 0 is never sent, the sequence numbers start at 1.
+        if seq_no == 0 {
+            return false;
+        }
+
+        if seq_no > self.top {
+            return true;
+        }
+
+        let offset = self.top - seq_no;
+        offset < u64::from(self.size) && self.bitmap & (1 << offset) == 0
+    }
+
+    /// Marks the sequence number as seen, moving the window forward if it
+    /// is above the window. Returns whether it is accepted.
+    pub fn update(&mut self, seq_no: u64) -> bool {
+        if !self.check(seq_no) {
+            return false;
+        }
+
+        if seq_no > self.top {
+            let shift = seq_no - self.top;
+            self.bitmap = if shift < u64::from(ReplayWindow::MAX_SIZE) {
+                self.bitmap << shift
+            } else {
+                0
+            };
+            self.top = seq_no;
+            self.bitmap |= 1;
+        } else {
+            self.bitmap |= 1 << (self.top - seq_no);
+        }
+
+        true
+    }
+
+    /// Checks the sequence number and marks it as seen if accepted.
+    #[inline]
+    pub fn check_and_update(&mut self, seq_no: u64) -> bool {
+        self.update(seq_no)
+    }
+
+    /// Resets the window, for a rekeyed association.
+    pub fn reset(&mut self) {
+        self.top = 0;
+        self.bitmap = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_in_order() {
+        let mut window = ReplayWindow::new(64);
+        for seq_no in 1..200 {
+            assert!(window.check_and_update(seq_no));
+        }
+        assert_eq!(199, window.top());
+    }
+
+    #[test]
+    fn reject_replayed() {
+        let mut window = ReplayWindow::new(64);
+        assert!(window.check_and_update(5));
+        assert!(!window.check_and_update(5));
+        assert!(!window.check_and_update(0));
+    }
+
+    #[test]
+    fn accept_out_of_order_within_window() {
+        let mut window = ReplayWindow::new(64);
+        assert!(window.check_and_update(100));
+        assert!(window.check_and_update(90));
+        assert!(window.check_and_update(37));
+        assert!(!window.check_and_update(90));
+
+        // 100 - 64, just outside of the window.
+        assert!(!window.check(36));
+    }
+
+    #[test]
+    fn slide_past_window() {
+        let mut window = ReplayWindow::new(128);
+        assert!(window.check_and_update(1));
+        assert!(window.check_and_update(1000));
+        assert!(!window.check(1));
+        assert!(window.check(999));
+        assert!(window.check(873));
+        assert!(!window.check(872));
+    }
+
+    #[test]
+    fn check_does_not_update() {
+        let mut window = ReplayWindow::new(32);
+        assert!(window.check(7));
+        assert!(window.check(7));
+        assert!(window.update(7));
+        assert!(!window.check(7));
+    }
+}
@@ -0,0 +1,338 @@
+use crate::Result;
+use failure::Fail;
+
+/// Error indicating the rules cannot be compiled.
+#[derive(Debug, Fail)]
+pub enum RulesetError {
+    /// The pattern of the rule is invalid.
+    #[fail(display = "Invalid pattern of rule {}: {}", _0, _1)]
+    InvalidPattern(u32, String),
+
+    /// The patterns are valid on their own, but cannot be compiled into
+    /// one set, for example when the set is too large.
+    #[fail(display = "Cannot compile the rules: {}", _0)]
+    CompileFailed(String),
+}
+
+/// The pattern of a content rule.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pattern {
+    /// A sequence of bytes matched anywhere in the payload.
+    Literal(Vec<u8>),
+    /// A regular expression matched against the bytes of the payload.
+    Regex(String),
+}
+
+/// A content rule, matching a pattern in the payload of the packets.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rule {
+    /// The ID of the rule, reported when the rule matches.
+    pub id: u32,
+    pub pattern: Pattern,
+}
+
+impl Rule {
+    /// Creates a rule matching a literal.
+    pub fn literal<T: AsRef<[u8]>>(id: u32, literal: T) -> Self {
+        Rule {
+            id,
+            pattern: Pattern::Literal(literal.as_ref().to_vec()),
+        }
+    }
+
+    /// Creates a rule matching a regular expression.
+    pub fn regex(id: u32, expr: &str) -> Self {
+        Rule {
+            id,
+            pattern: Pattern::Regex(expr.to_owned()),
+        }
+    }
+}
+
+/// A compiled set of content rules, matched against packet payloads.
+///
+/// By default, the literals are compiled into an Aho-Corasick automaton
+/// and the regular expressions into a `RegexSet`, so each payload is
+/// scanned once per kind of pattern whatever the number of rules. With
+/// the `hyperscan` feature, all the patterns are compiled into a single
+/// Hyperscan database instead.
+///
+/// The payload of each packet is matched on its own. Patterns split
+/// across the segments of a stream are not found.
+///
+/// The ruleset holds scan state, so each pipeline compiles its own from
+/// the same rules.
+///
+/// # Example
+///
+/// ```
+/// let rules = vec![
+///     Rule::literal(1, "/etc/passwd"),
+///     Rule::regex(2, r"(?i)union\s+select"),
+/// ];
+///
+/// runtime.add_pipeline_to_port("eth1", move |q| {
+///     let ruleset = Ruleset::new(&rules).unwrap();
+///     Poll::new(q.clone())
+///         .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>()?.parse::<Tcp4>())
+///         .inspect(ruleset, MatchAction::Drop)
+///         .send(q)
+/// })?;
+/// ```
+pub struct Ruleset {
+    len: usize,
+    engine: engine::Engine,
+}
+
+impl Ruleset {
+    /// Compiles the rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RulesetError::InvalidPattern` with the ID of the first rule
+    /// whose pattern is invalid, or `RulesetError::CompileFailed` if the
+    /// patterns cannot be compiled together.
+    pub fn new(rules: &[Rule]) -> Result<Self> {
+        Ok(Ruleset {
+            len: rules.len(),
+            engine: engine::Engine::new(rules)?,
+        })
+    }
+
+    /// Returns the number of rules.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns whether any rule matches the payload.
+    #[inline]
+    pub fn is_match(&mut self, payload: &[u8]) -> bool {
+        self.first_match(payload).is_some()
+    }
+
+    /// Returns the lowest ID of the rules matching the payload.
+    #[inline]
+    pub fn first_match(&mut self, payload: &[u8]) -> Option<u32> {
+        if self.len == 0 || payload.is_empty() {
+            return None;
+        }
+
+        let mut first = None;
+        self.engine.scan(payload, |id| {
+            first = Some(first.map_or(id, |first: u32| first.min(id)));
+        });
+        first
+    }
+
+    /// Returns the IDs of all the rules matching the payload, in
+    /// ascending order.
+    pub fn matches(&mut self, payload: &[u8]) -> Vec<u32> {
+        let mut ids = vec![];
+        if self.len > 0 && !payload.is_empty() {
+            self.engine.scan(payload, |id| ids.push(id));
+            ids.sort();
+            ids.dedup();
+        }
+        ids
+    }
+}
+
+#[cfg(not(feature = "hyperscan"))]
+mod engine {
+    use super::{Pattern, Rule, RulesetError};
+    use crate::Result;
+    use aho_corasick::AhoCorasick;
+    use regex::bytes::{Regex, RegexSet};
+
+    pub(super) struct Engine {
+        literals: Option<AhoCorasick>,
+        literal_ids: Vec<u32>,
+        regexes: Option<RegexSet>,
+        regex_ids: Vec<u32>,
+    }
+
+    impl Engine {
+        pub(super) fn new(rules: &[Rule]) -> Result<Self> {
+            let mut literals = vec![];
+            let mut literal_ids = vec![];
+            let mut exprs = vec![];
+            let mut regex_ids = vec![];
+
+            for rule in rules {
+                match &rule.pattern {
+                    Pattern::Literal(literal) => {
+                        if literal.is_empty() {
+                            return Err(RulesetError::InvalidPattern(
+                                rule.id,
+                                "empty literal".to_owned(),
+                            )
+                            .into());
+                        }
+                        literals.push(literal.as_slice());
+                        literal_ids.push(rule.id);
+                    }
+                    Pattern::Regex(expr) => {
+                        // compiles each on its own to blame the right rule.
+                        Regex::new(expr).map_err(|err| {
+                            RulesetError::InvalidPattern(rule.id, err.to_string())
+                        })?;
+                        exprs.push(expr.as_str());
+                        regex_ids.push(rule.id);
+                    }
+                }
+            }
+
+            let literals = if literals.is_empty() {
+                None
+            } else {
+                Some(AhoCorasick::new(literals))
+            };
+            let regexes = if exprs.is_empty() {
+                None
+            } else {
+                Some(
+                    RegexSet::new(exprs)
+                        .map_err(|err| RulesetError::CompileFailed(err.to_string()))?,
+                )
+            };
+
+            Ok(Engine {
+                literals,
+                literal_ids,
+                regexes,
+                regex_ids,
+            })
+        }
+
+        #[inline]
+        pub(super) fn scan<F: FnMut(u32)>(&mut self, payload: &[u8], mut on_match: F) {
+            if let Some(literals) = &self.literals {
+                for m in literals.find_overlapping_iter(payload) {
+                    on_match(self.literal_ids[m.pattern()]);
+                }
+            }
+
+            if let Some(regexes) = &self.regexes {
+                for index in regexes.matches(payload).iter() {
+                    on_match(self.regex_ids[index]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "hyperscan")]
+mod engine {
+    use super::{Pattern, Rule, RulesetError};
+    use crate::Result;
+    use hyperscan::prelude::{
+        BlockDatabase, Builder, CompileFlags, Matching, Pattern as HsPattern, Patterns, Scratch,
+    };
+    use std::fmt::Write;
+
+    pub(super) struct Engine {
+        database: Option<(BlockDatabase, Scratch)>,
+    }
+
+    impl Engine {
+        pub(super) fn new(rules: &[Rule]) -> Result<Self> {
+            let mut patterns = vec![];
+
+            for rule in rules {
+                let expr = match &rule.pattern {
+                    Pattern::Literal(literal) => {
+                        if literal.is_empty() {
+                            return Err(RulesetError::InvalidPattern(
+                                rule.id,
+                                "empty literal".to_owned(),
+                            )
+                            .into());
+                        }
+                        // escapes every byte, so the literal matches as is.
+                        let mut expr = String::with_capacity(literal.len() * 4);
+                        for byte in literal {
+                            let _ = write!(expr, "\\x{:02x}", byte);
+                        }
+                        expr
+                    }
+                    Pattern::Regex(expr) => expr.clone(),
+                };
+
+                let mut pattern = HsPattern::with_flags(expr, CompileFlags::DOTALL)
+                    .map_err(|err| RulesetError::InvalidPattern(rule.id, err.to_string()))?;
+                pattern.id = Some(rule.id as usize);
+                // compiles each on its own to blame the right rule.
+                let _: BlockDatabase = pattern
+                    .build()
+                    .map_err(|err| RulesetError::InvalidPattern(rule.id, err.to_string()))?;
+                patterns.push(pattern);
+            }
+
+            if patterns.is_empty() {
+                return Ok(Engine { database: None });
+            }
+
+            let database: BlockDatabase = Patterns(patterns)
+                .build()
+                .map_err(|err| RulesetError::CompileFailed(err.to_string()))?;
+            let scratch = database
+                .alloc_scratch()
+                .map_err(|err| RulesetError::CompileFailed(err.to_string()))?;
+
+            Ok(Engine {
+                database: Some((database, scratch)),
+            })
+        }
+
+        #[inline]
+        pub(super) fn scan<F: FnMut(u32)>(&mut self, payload: &[u8], mut on_match: F) {
+            if let Some((database, scratch)) = &self.database {
+                let _ = database.scan(payload, scratch, |id, _, _, _| {
+                    on_match(id);
+                    Matching::Continue
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ruleset() -> Ruleset {
+        Ruleset::new(&[
+            Rule::literal(3, "/etc/passwd"),
+            Rule::literal(1, "passwd"),
+            Rule::regex(2, r"(?i)union\s+select"),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn match_literals_and_regexes() {
+        let mut ruleset = ruleset();
+
+        assert_eq!(vec![1, 3], ruleset.matches(b"GET /etc/passwd HTTP/1.1"));
+        assert_eq!(Some(1), ruleset.first_match(b"GET /etc/passwd HTTP/1.1"));
+        assert_eq!(Some(2), ruleset.first_match(b"id=1 UNION  SELECT *"));
+        assert!(!ruleset.is_match(b"GET /index.html HTTP/1.1"));
+        assert!(!ruleset.is_match(b""));
+    }
+
+    #[test]
+    fn reject_invalid_rules() {
+        let err = Ruleset::new(&[Rule::literal(1, "a"), Rule::regex(7, "(unclosed")]).err();
+        match err.unwrap().downcast::<RulesetError>() {
+            Ok(RulesetError::InvalidPattern(7, _)) => (),
+            _ => panic!("rule 7 not blamed"),
+        }
+        assert!(Ruleset::new(&[Rule::literal(1, "")]).is_err());
+        assert!(Ruleset::new(&[]).unwrap().is_empty());
+    }
+}
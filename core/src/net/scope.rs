@@ -0,0 +1,250 @@
+use crate::dpdk::PortId;
+use failure::Fail;
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+/// The scope of an IPv6 address, from the narrowest to the widest.
+///
+/// The scopes of the multicast addresses are the ones of RFC 7346. The
+/// unicast addresses have the interface-local scope for the loopback, the
+/// link-local scope for `fe80::/10` and the global scope otherwise. The
+/// unique local addresses are global in scope, RFC 4193 only restricts
+/// them by routing policy.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Ipv6Scope {
+    InterfaceLocal,
+    LinkLocal,
+    RealmLocal,
+    AdminLocal,
+    SiteLocal,
+    OrganizationLocal,
+    Global,
+}
+
+impl Ipv6Scope {
+    /// Returns whether addresses of the scope are only unique on a link,
+    /// and so are ambiguous without the port they are on.
+    pub fn is_zoned(self) -> bool {
+        self <= Ipv6Scope::LinkLocal
+    }
+
+    /// Returns the scope of the 4-bit scope field of a multicast address.
+    /// The reserved and unassigned values are treated as global.
+    fn from_multicast(scop: u8) -> Self {
+        match scop {
+            0x1 => Ipv6Scope::InterfaceLocal,
+            0x2 => Ipv6Scope::LinkLocal,
+            0x3 => Ipv6Scope::RealmLocal,
+            0x4 => Ipv6Scope::AdminLocal,
+            0x5 => Ipv6Scope::SiteLocal,
+            0x8 => Ipv6Scope::OrganizationLocal,
+            _ => Ipv6Scope::Global,
+        }
+    }
+}
+
+/// The kind of an IPv6 address.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Ipv6Class {
+    /// `::`.
+    Unspecified,
+    /// `::1`.
+    Loopback,
+    /// `fe80::/10`.
+    LinkLocal,
+    /// `fec0::/10`, deprecated by RFC 3879 but still seen in the wild.
+    SiteLocal,
+    /// `fc00::/7`, the unique local addresses of RFC 4193.
+    UniqueLocal,
+    /// `ff00::/8`, with the scope of the group.
+    Multicast(Ipv6Scope),
+    /// Everything else.
+    Global,
+}
+
+impl Ipv6Class {
+    /// Classifies the address.
+    pub fn of(addr: Ipv6Addr) -> Self {
+        let segments = addr.segments();
+        if addr.is_unspecified() {
+            Ipv6Class::Unspecified
+        } else if addr.is_loopback() {
+            Ipv6Class::Loopback
+        } else if segments[0] & 0xff00 == 0xff00 {
+            Ipv6Class::Multicast(Ipv6Scope::from_multicast((segments[0] & 0x000f) as u8))
+        } else if segments[0] & 0xffc0 == 0xfe80 {
+            Ipv6Class::LinkLocal
+        } else if segments[0] & 0xffc0 == 0xfec0 {
+            Ipv6Class::SiteLocal
+        } else if segments[0] & 0xfe00 == 0xfc00 {
+            Ipv6Class::UniqueLocal
+        } else {
+            Ipv6Class::Global
+        }
+    }
+
+    /// Returns the scope of the addresses of the class. The unspecified
+    /// address has no scope.
+    pub fn scope(self) -> Option<Ipv6Scope> {
+        match self {
+            Ipv6Class::Unspecified => None,
+            Ipv6Class::Loopback => Some(Ipv6Scope::InterfaceLocal),
+            Ipv6Class::LinkLocal => Some(Ipv6Scope::LinkLocal),
+            Ipv6Class::SiteLocal => Some(Ipv6Scope::SiteLocal),
+            Ipv6Class::Multicast(scope) => Some(scope),
+            Ipv6Class::UniqueLocal | Ipv6Class::Global => Some(Ipv6Scope::Global),
+        }
+    }
+}
+
+/// An IPv6 address with the port it is scoped to.
+///
+/// Link-local addresses are only unique on their link, so `fe80::1` seen
+/// on two ports can be two different neighbors. Keying the tables of a
+/// multi-port application, a neighbor cache for example, by `ZonedIpv6Addr`
+/// rather than `Ipv6Addr` keeps them apart. The zone is only kept for the
+/// addresses that need it, so the global addresses compare equal across
+/// ports.
+///
+/// The address is displayed and parsed in the `fe80::1%port0` form of
+/// RFC 4007.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ZonedIpv6Addr {
+    addr: Ipv6Addr,
+    zone: Option<PortId>,
+}
+
+impl ZonedIpv6Addr {
+    /// Creates the address as seen on the port. The port is dropped if the
+    /// address is not zoned.
+    pub fn new(addr: Ipv6Addr, port: PortId) -> Self {
+        ZonedIpv6Addr {
+            addr,
+            zone: if is_zoned(addr) { Some(port) } else { None },
+        }
+    }
+
+    /// Returns the address.
+    pub fn addr(&self) -> Ipv6Addr {
+        self.addr
+    }
+
+    /// Returns the port the address is scoped to, or `None` if the address
+    /// is not zoned.
+    pub fn zone(&self) -> Option<PortId> {
+        self.zone
+    }
+}
+
+fn is_zoned(addr: Ipv6Addr) -> bool {
+    Ipv6Class::of(addr)
+        .scope()
+        .map_or(false, Ipv6Scope::is_zoned)
+}
+
+impl fmt::Display for ZonedIpv6Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.zone {
+            Some(port) => write!(f, "{}%{:?}", self.addr, port),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Failed to parse '{}' as zoned IPv6 address.", _0)]
+pub struct ZonedIpv6ParseError(String);
+
+impl FromStr for ZonedIpv6Addr {
+    type Err = ZonedIpv6ParseError;
+
+    /// Parses `addr%portN` or `addr%N`. The zone is required for the
+    /// zoned addresses, and is dropped for the others.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let error = || ZonedIpv6ParseError(s.to_owned());
+        let mut parts = s.splitn(2, '%');
+        let addr = parts
+            .next()
+            .and_then(|addr| addr.parse::<Ipv6Addr>().ok())
+            .ok_or_else(error)?;
+
+        match parts.next() {
+            Some(zone) => {
+                let id = zone.trim_start_matches("port");
+                let id = id.parse::<u16>().map_err(|_| error())?;
+                Ok(ZonedIpv6Addr::new(addr, PortId::new(id)))
+            }
+            None if is_zoned(addr) => Err(error()),
+            None => Ok(ZonedIpv6Addr { addr, zone: None }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(s: &str) -> Ipv6Class {
+        Ipv6Class::of(s.parse().unwrap())
+    }
+
+    #[test]
+    fn classify_addresses() {
+        assert_eq!(Ipv6Class::Unspecified, class("::"));
+        assert_eq!(Ipv6Class::Loopback, class("::1"));
+        assert_eq!(Ipv6Class::LinkLocal, class("fe80::1"));
+        assert_eq!(Ipv6Class::LinkLocal, class("febf::1"));
+        assert_eq!(Ipv6Class::SiteLocal, class("fec0::1"));
+        assert_eq!(Ipv6Class::UniqueLocal, class("fd12:3456::1"));
+        assert_eq!(Ipv6Class::Global, class("2001:db8::1"));
+        assert_eq!(Ipv6Class::Multicast(Ipv6Scope::LinkLocal), class("ff02::1"));
+        assert_eq!(
+            Ipv6Class::Multicast(Ipv6Scope::SiteLocal),
+            class("ff05::1:3")
+        );
+        assert_eq!(Ipv6Class::Multicast(Ipv6Scope::Global), class("ff0e::1"));
+
+        assert_eq!(None, class("::").scope());
+        assert_eq!(Some(Ipv6Scope::Global), class("fd12:3456::1").scope());
+        assert!(Ipv6Scope::InterfaceLocal.is_zoned());
+        assert!(!Ipv6Scope::SiteLocal.is_zoned());
+    }
+
+    #[test]
+    fn zone_only_scoped_addresses() {
+        let port0 = PortId::new(0);
+        let port1 = PortId::new(1);
+
+        let a = ZonedIpv6Addr::new("fe80::1".parse().unwrap(), port0);
+        let b = ZonedIpv6Addr::new("fe80::1".parse().unwrap(), port1);
+        assert_ne!(a, b);
+        assert_eq!(Some(port0), a.zone());
+        assert_eq!("fe80::1%port0", a.to_string());
+
+        let a = ZonedIpv6Addr::new("2001:db8::1".parse().unwrap(), port0);
+        let b = ZonedIpv6Addr::new("2001:db8::1".parse().unwrap(), port1);
+        assert_eq!(a, b);
+        assert_eq!("2001:db8::1", a.to_string());
+
+        let a = ZonedIpv6Addr::new("ff02::1".parse().unwrap(), port0);
+        assert_eq!(Some(port0), a.zone());
+    }
+
+    #[test]
+    fn parse_zoned_addresses() {
+        let port1 = PortId::new(1);
+
+        let addr = "fe80::1%port1".parse::<ZonedIpv6Addr>().unwrap();
+        assert_eq!(Some(port1), addr.zone());
+        let addr = "fe80::1%1".parse::<ZonedIpv6Addr>().unwrap();
+        assert_eq!(Some(port1), addr.zone());
+
+        let addr = "2001:db8::1%port1".parse::<ZonedIpv6Addr>().unwrap();
+        assert_eq!(None, addr.zone());
+        assert!("2001:db8::1".parse::<ZonedIpv6Addr>().is_ok());
+
+        assert!("fe80::1".parse::<ZonedIpv6Addr>().is_err());
+        assert!("fe80::1%eth0".parse::<ZonedIpv6Addr>().is_err());
+    }
+}
@@ -0,0 +1,492 @@
+use super::{crc32c, Xoshiro256};
+use crate::{ensure, Result};
+use failure::Fail;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Error indicating sketches of different dimensions cannot be merged.
+#[derive(Debug, Fail)]
+#[fail(display = "Sketch dimensions {:?} and {:?} do not match.", _0, _1)]
+pub struct SketchMismatch(pub (usize, usize), pub (usize, usize));
+
+/// A hasher computing two 32-bit CRC-32C lanes of the key, in hardware.
+///
+/// The lanes are not independent, CRC is linear so they differ by a value
+/// that only depends on the length of the key. Use `MixHasher` for hashes
+/// that must be.
+#[derive(Clone, Copy)]
+pub(crate) struct KeyHasher {
+    h1: u32,
+    h2: u32,
+}
+
+impl Default for KeyHasher {
+    fn default() -> Self {
+        KeyHasher {
+            h1: 0,
+            h2: 0x9e37_79b9,
+        }
+    }
+}
+
+impl KeyHasher {
+    /// Creates a hasher with both lanes seeded with `seed`. The seed `0`
+    /// is the default hasher.
+    ///
+    /// The seed is not a key, the keys colliding with one seed collide
+    /// with all of them.
+    pub(crate) fn with_seed(seed: u32) -> Self {
+        let default = KeyHasher::default();
+        KeyHasher {
+            h1: default.h1 ^ seed,
+            h2: default.h2 ^ seed,
+        }
+    }
+}
+
+impl Hasher for KeyHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.h1 = crc32c(self.h1, bytes);
+        self.h2 = crc32c(self.h2, bytes);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        (u64::from(self.h1) << 32) | u64::from(self.h2)
+    }
+}
+
+/// The splitmix64 finaliser.
+#[inline]
+pub(crate) fn mix64(z: u64) -> u64 {
+    let z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A hasher mixing the key 8 bytes at a time with the splitmix64
+/// finaliser.
+///
+/// The CRC lanes of `KeyHasher` are linear in the key, `h1 ^ h2` is the
+/// same for all the keys of a length. The two halves of this hash are
+/// independent, so the sketches and filters can derive their rows, blocks,
+/// bits and fingerprints from them.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct MixHasher {
+    state: u64,
+    len: u64,
+}
+
+impl Hasher for MixHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.state =
+                mix64(self.state ^ u64::from_le_bytes(word)).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        }
+        self.len += bytes.len() as u64;
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        mix64(self.state ^ self.len)
+    }
+}
+
+/// The 64-bit hash of the key.
+#[inline]
+pub(crate) fn hash_key64<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = MixHasher::default();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Splits a 64-bit hash into the two independent hashes of `nth_hash`.
+#[inline]
+pub(crate) fn split_hash(hash: u64) -> (u32, u32) {
+    // an odd step visits every slot of a power of two row.
+    ((hash >> 32) as u32, hash as u32 | 1)
+}
+
+/// The two independent hashes of the key.
+#[inline]
+pub(crate) fn hash_key<K: Hash + ?Sized>(key: &K) -> (u32, u32) {
+    split_hash(hash_key64(key))
+}
+
+/// The `n`th hash derived from the two hashes of the key.
+///
+/// As described in "Less Hashing, Same Performance" by Kirsch and
+/// Mitzenmacher, any number of rows costs two hashes.
+#[inline]
+pub(crate) fn nth_hash((h1, h2): (u32, u32), n: usize) -> u32 {
+    h1.wrapping_add((n as u32).wrapping_mul(h2))
+}
+
+/// A count-min sketch, estimating the count of each key in a fixed amount
+/// of memory.
+///
+/// The estimate is never below the actual count, and is above it by at
+/// most `epsilon` times the total count with probability `1 - delta`.
+///
+/// The sketch is meant to be owned by a single pipeline and updated
+/// without synchronization. The sketches of all the cores are merged
+/// periodically, by the control plane, to query the totals.
+///
+/// # Example
+///
+/// ```
+/// let mut sketch = CountMinSketch::with_error(0.0001, 0.01);
+///
+/// batch.for_each(move |v4| {
+///     sketch.add(&v4.src(), v4.len() as u64);
+///     Ok(())
+/// })
+/// ```
+#[derive(Clone, Debug)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    /// Creates a new sketch of `depth` rows of `width` counters. The width
+    /// is rounded up to a power of two.
+    pub fn new(width: usize, depth: usize) -> Self {
+        let width = width.max(1).next_power_of_two();
+        let depth = depth.max(1);
+
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+            total: 0,
+        }
+    }
+
+    /// Creates a new sketch sized for an error of at most `epsilon` times
+    /// the total count with probability `1 - delta`.
+    pub fn with_error(epsilon: f64, delta: f64) -> Self {
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil() as usize;
+        CountMinSketch::new(width, depth)
+    }
+
+    /// Returns the dimensions of the sketch, as width and depth.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.depth)
+    }
+
+    #[inline]
+    fn index(&self, hashes: (u32, u32), row: usize) -> usize {
+        row * self.width + (nth_hash(hashes, row) as usize & (self.width - 1))
+    }
+
+    /// Adds `count` to the key.
+    #[inline]
+    pub fn add<K: Hash + ?Sized>(&mut self, key: &K, count: u64) {
+        let hashes = hash_key(key);
+        for row in 0..self.depth {
+            let index = self.index(hashes, row);
+            self.counters[index] = self.counters[index].saturating_add(count);
+        }
+        self.total = self.total.saturating_add(count);
+    }
+
+    /// Returns the estimated count of the key.
+    #[inline]
+    pub fn estimate<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        let hashes = hash_key(key);
+        (0..self.depth)
+            .map(|row| self.counters[self.index(hashes, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Returns the total count of all the keys.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Adds the counts of another sketch of the same dimensions.
+    pub fn merge(&mut self, other: &CountMinSketch) -> Result<()> {
+        ensure!(
+            self.dimensions() == other.dimensions(),
+            SketchMismatch(self.dimensions(), other.dimensions())
+        );
+
+        self.counters
+            .iter_mut()
+            .zip(other.counters.iter())
+            .for_each(|(a, b)| *a = a.saturating_add(*b));
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+
+    /// Resets all the counts to `0`.
+    pub fn clear(&mut self) {
+        self.counters.iter_mut().for_each(|c| *c = 0);
+        self.total = 0;
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct HeavyBucket {
+    fingerprint: u32,
+    count: u64,
+}
+
+/// A HeavyKeeper, finding the top-k keys by count in a fixed amount of
+/// memory.
+///
+/// Implements "HeavyKeeper: An Accurate Algorithm for Finding Top-k
+/// Elephant Flows" by Gong et al. Each bucket holds the fingerprint of a
+/// key and its count. A key colliding with a bucket held by another key
+/// decays the count with a probability exponentially decreasing with the
+/// count, so the elephants keep their buckets while the mice are evicted.
+/// The counts are rarely overestimated, unlike the count-min sketch.
+///
+/// Like `CountMinSketch`, it is meant to be owned by a single pipeline.
+/// Since the flows are spread across the cores by RSS, the top-k lists
+/// of the cores are merged with `merge_top_k` to find the overall top-k.
+#[derive(Clone, Debug)]
+pub struct HeavyKeeper<K: Hash + Eq + Clone> {
+    width: usize,
+    depth: usize,
+    k: usize,
+    buckets: Vec<HeavyBucket>,
+    top: HashMap<K, u64>,
+    rand: Xoshiro256,
+}
+
+impl<K: Hash + Eq + Clone> HeavyKeeper<K> {
+    // the base of the decay probability, `1.08` in the paper.
+    const DECAY_BASE: f64 = 1.08;
+
+    /// Creates a new keeper of the top `k` keys, with `depth` rows of
+    /// `width` buckets. The width is rounded up to a power of two.
+    pub fn new(k: usize, width: usize, depth: usize) -> Self {
+        let width = width.max(1).next_power_of_two();
+        let depth = depth.max(1);
+
+        HeavyKeeper {
+            width,
+            depth,
+            k,
+            buckets: vec![HeavyBucket::default(); width * depth],
+            top: HashMap::with_capacity(k + 1),
+            rand: Xoshiro256::new(0x5eed_4ea7_4ee9_e400),
+        }
+    }
+
+    /// Adds `count` to the key.
+    ///
+    /// A colliding key is decayed as if added one unit of `count` at a
+    /// time, so count the packets of the keys rather than their bytes.
+    pub fn add(&mut self, key: &K, count: u64) {
+        let hashes = hash_key(key);
+        // `0` marks an empty bucket.
+        let fingerprint = hashes.0 | 1;
+        let mut estimate = 0;
+
+        for row in 0..self.depth {
+            let index = row * self.width + (nth_hash(hashes, row + 1) as usize & (self.width - 1));
+            let bucket = &mut self.buckets[index];
+
+            if bucket.count == 0 || bucket.fingerprint == fingerprint {
+                bucket.fingerprint = fingerprint;
+                bucket.count = bucket.count.saturating_add(count);
+                estimate = estimate.max(bucket.count);
+                continue;
+            }
+
+            // draws the number of units until the next decay rather than
+            // a decision per unit, the decays get rarer as the count grows.
+            let mut remaining = count;
+            while remaining > 0 {
+                let p = Self::DECAY_BASE.powf(-(bucket.count as f64));
+                // uniform in (0, 1], the geometric draw never takes `ln(0)`.
+                let u = ((self.rand.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+                let units = (u.ln() / (-p).ln_1p()).ceil().max(1.0);
+                if units > remaining as f64 {
+                    break;
+                }
+
+                remaining -= units as u64;
+                bucket.count -= 1;
+                if bucket.count == 0 {
+                    // the units left go to the key taking the bucket.
+                    bucket.fingerprint = fingerprint;
+                    bucket.count = remaining + 1;
+                    estimate = estimate.max(bucket.count);
+                    break;
+                }
+            }
+        }
+
+        self.update_top(key, estimate);
+    }
+
+    fn update_top(&mut self, key: &K, estimate: u64) {
+        if estimate == 0 {
+            return;
+        }
+
+        if let Some(count) = self.top.get_mut(key) {
+            *count = (*count).max(estimate);
+            return;
+        }
+
+        if self.top.len() < self.k {
+            self.top.insert(key.clone(), estimate);
+            return;
+        }
+
+        let min = self
+            .top
+            .iter()
+            .min_by_key(|entry| *entry.1)
+            .map(|(key, count)| (key.clone(), *count));
+        if let Some((min_key, min_count)) = min {
+            if estimate > min_count {
+                self.top.remove(&min_key);
+                self.top.insert(key.clone(), estimate);
+            }
+        }
+    }
+
+    /// Returns the top-k keys with their estimated counts, largest first.
+    pub fn top_k(&self) -> Vec<(K, u64)> {
+        let mut top = self
+            .top
+            .iter()
+            .map(|(key, &count)| (key.clone(), count))
+            .collect::<Vec<_>>();
+        top.sort_by(|a, b| b.1.cmp(&a.1));
+        top
+    }
+
+    /// Resets all the counts.
+    pub fn clear(&mut self) {
+        self.buckets
+            .iter_mut()
+            .for_each(|b| *b = HeavyBucket::default());
+        self.top.clear();
+    }
+}
+
+/// Merges the top-k lists of several keepers into the overall top `k`,
+/// adding up the counts of the keys found in more than one list.
+pub fn merge_top_k<K: Hash + Eq + Clone>(lists: &[Vec<(K, u64)>], k: usize) -> Vec<(K, u64)> {
+    let mut counts = HashMap::new();
+    for (key, count) in lists.iter().flatten() {
+        *counts.entry(key.clone()).or_insert(0u64) += count;
+    }
+
+    let mut top = counts.into_iter().collect::<Vec<_>>();
+    top.sort_by(|a, b| b.1.cmp(&a.1));
+    top.truncate(k);
+    top
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn addr(n: u32) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + n))
+    }
+
+    #[test]
+    fn count_min_never_underestimates() {
+        let mut sketch = CountMinSketch::new(256, 4);
+        for n in 0..1000 {
+            sketch.add(&addr(n), u64::from(n % 7) + 1);
+        }
+
+        for n in 0..1000 {
+            assert!(sketch.estimate(&addr(n)) >= u64::from(n % 7) + 1);
+        }
+        assert_eq!(0, CountMinSketch::new(256, 4).estimate(&addr(1)));
+    }
+
+    #[test]
+    fn merge_count_min() {
+        let mut a = CountMinSketch::with_error(0.001, 0.01);
+        let mut b = a.clone();
+        a.add(&addr(1), 100);
+        b.add(&addr(1), 50);
+        b.add(&addr(2), 10);
+
+        a.merge(&b).unwrap();
+        assert_eq!(150, a.estimate(&addr(1)));
+        assert_eq!(10, a.estimate(&addr(2)));
+        assert_eq!(160, a.total());
+
+        assert!(a.merge(&CountMinSketch::new(16, 2)).is_err());
+    }
+
+    #[test]
+    fn heavy_keeper_finds_elephants() {
+        let mut keeper = HeavyKeeper::new(3, 64, 2);
+
+        // 3 elephants among many mice.
+        for round in 0..100 {
+            for elephant in 0..3 {
+                keeper.add(&addr(elephant), 10);
+            }
+            for mouse in 0..20 {
+                keeper.add(&addr(1000 + round * 20 + mouse), 1);
+            }
+        }
+
+        let top = keeper.top_k();
+        assert_eq!(3, top.len());
+        let mut keys = top.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(vec![addr(0), addr(1), addr(2)], keys);
+        assert!(top.iter().all(|(_, count)| *count <= 1000));
+    }
+
+    #[test]
+    fn independent_hashes() {
+        // the lanes of the same length keys used to xor to a constant.
+        let xors = (0..1000)
+            .map(|n| {
+                let (h1, h2) = hash_key(&addr(n));
+                h1 ^ h2
+            })
+            .collect::<HashSet<_>>();
+        assert!(xors.len() > 990);
+    }
+
+    #[test]
+    fn heavy_keeper_decays_large_count() {
+        let mut keeper = HeavyKeeper::new(1, 1, 1);
+        keeper.add(&addr(0), 10);
+
+        // decays the elephant away without a decision per unit.
+        let count = 1 << 40;
+        keeper.add(&addr(1), count);
+
+        let top = keeper.top_k();
+        assert_eq!(addr(1), top[0].0);
+        assert!(top[0].1 > count / 2);
+    }
+
+    #[test]
+    fn merge_top_k_lists() {
+        let a = vec![(addr(1), 100), (addr(2), 50)];
+        let b = vec![(addr(3), 120), (addr(2), 60)];
+
+        let top = merge_top_k(&[a, b], 2);
+        assert_eq!(vec![(addr(3), 120), (addr(2), 110)], top);
+    }
+}
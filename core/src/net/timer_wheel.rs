@@ -0,0 +1,156 @@
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// A hashed timer wheel, holding items until their deadlines.
+///
+/// The time is cut in ticks, and the wheel has a slot per tick, reused
+/// every turn. Inserting and expiring an item is constant time whatever
+/// the number of items, at the price of the deadlines being rounded up to
+/// the next tick. The deadlines further than a turn ahead stay in their
+/// slot until the turn they are due in.
+///
+/// The wheel is driven by `advance`, from a timer or from the pipeline,
+/// and is meant to be owned by a single pipeline.
+///
+/// # Example
+///
+/// ```
+/// let mut wheel = TimerWheel::new(Duration::from_micros(100), 4096);
+/// wheel.insert(Instant::now() + Duration::from_millis(20), mbuf);
+///
+/// // later, on a timer.
+/// tx.transmit(wheel.advance(Instant::now()));
+/// ```
+pub struct TimerWheel<T> {
+    // the items of each slot, with the tick they are due at.
+    slots: Vec<Vec<(u64, T)>>,
+    mask: usize,
+    tick: Duration,
+    start: Instant,
+    // the next tick to expire, the ones before have all expired.
+    current: u64,
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates an empty wheel of ticks of `tick`, with at least `slots`
+    /// slots. The number of slots is rounded up to a power of two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tick` is zero.
+    pub fn new(tick: Duration, slots: usize) -> Self {
+        assert!(tick > Duration::from_secs(0), "the tick must be positive.");
+        let slots = slots.max(1).next_power_of_two();
+
+        TimerWheel {
+            slots: (0..slots).map(|_| vec![]).collect(),
+            mask: slots - 1,
+            tick,
+            start: Instant::now(),
+            current: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of items in the wheel.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the wheel is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the ticks from the start of the wheel to `time`, rounded
+    /// down.
+    fn ticks(&self, time: Instant) -> u64 {
+        if time > self.start {
+            ((time - self.start).as_nanos() / self.tick.as_nanos()) as u64
+        } else {
+            0
+        }
+    }
+
+    /// Adds the item, due at the deadline. An item past due expires on the
+    /// next tick.
+    pub fn insert(&mut self, deadline: Instant, item: T) {
+        // rounded up, so the item never expires early.
+        let due = if deadline > self.start {
+            let nanos = (deadline - self.start).as_nanos();
+            let tick = self.tick.as_nanos();
+            ((nanos + tick - 1) / tick) as u64
+        } else {
+            0
+        };
+        let due = due.max(self.current);
+
+        self.slots[due as usize & self.mask].push((due, item));
+        self.len += 1;
+    }
+
+    /// Removes the items due by `now`, in the order of their deadlines by
+    /// tick, and in the order inserted within a tick. The order is only
+    /// kept if the wheel is advanced at least once a turn.
+    pub fn advance(&mut self, now: Instant) -> Vec<T> {
+        let target = self.ticks(now);
+        if target < self.current {
+            return vec![];
+        }
+
+        let mut expired = vec![];
+        // after a long pause, a single turn covers all the slots.
+        let steps = (target - self.current + 1).min(self.slots.len() as u64);
+
+        for step in 0..steps {
+            let slot = (self.current + step) as usize & self.mask;
+            if self.slots[slot].is_empty() {
+                continue;
+            }
+
+            let items = mem::replace(&mut self.slots[slot], vec![]);
+            for (due, item) in items {
+                if due <= target {
+                    expired.push(item);
+                } else {
+                    self.slots[slot].push((due, item));
+                }
+            }
+        }
+
+        self.current = target + 1;
+        self.len -= expired.len();
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expire_in_deadline_order() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8);
+        let now = wheel.start;
+        let ms = Duration::from_millis;
+
+        wheel.insert(now + ms(5), "c");
+        wheel.insert(now + ms(2), "a");
+        wheel.insert(now + ms(2), "b");
+        // more than a turn ahead, in the same slot as the first.
+        wheel.insert(now + ms(13), "d");
+        assert_eq!(4, wheel.len());
+
+        assert!(wheel.advance(now + ms(1)).is_empty());
+        assert_eq!(vec!["a", "b"], wheel.advance(now + ms(2)));
+        assert_eq!(vec!["c"], wheel.advance(now + ms(12)));
+        assert_eq!(vec!["d"], wheel.advance(now + ms(100)));
+        assert!(wheel.is_empty());
+
+        // past due, expires on the next tick.
+        wheel.insert(now, "e");
+        assert!(wheel.advance(now + ms(100)).is_empty());
+        assert_eq!(vec!["e"], wheel.advance(now + ms(101)));
+    }
+}
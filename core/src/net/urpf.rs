@@ -0,0 +1,99 @@
+use super::RouteTable;
+use crate::packets::ip::IpPacket;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Mode of the unicast reverse path forwarding check.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UrpfMode {
+    /// The route back to the source must go out the ingress interface.
+    Strict,
+    /// A route back to the source must exist, through any interface. The
+    /// default route does not count.
+    Loose,
+}
+
+/// Unicast reverse path forwarding check, as described in RFC 3704.
+///
+/// Validates the source address of incoming packets against the routing
+/// table to drop spoofed traffic. The routing table maps prefixes to the
+/// egress interface, such as the `PortId` the route goes out of. In strict
+/// mode, `ingress` is the interface the packets are received on.
+///
+/// # Example
+///
+/// ```
+/// let urpf = Urpf::new(routes.clone(), UrpfMode::Strict, q.port_id());
+///
+/// Poll::new(q.clone())
+///     .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+///     .filter_with(DropReason::SpoofedSource, move |v4| urpf.check_packet(v4))
+///     .send(q)
+/// ```
+pub struct Urpf<V: PartialEq> {
+    routes: Arc<RouteTable<V>>,
+    mode: UrpfMode,
+    ingress: V,
+}
+
+impl<V: PartialEq> Urpf<V> {
+    /// Creates a new check for packets received on `ingress`.
+    pub fn new(routes: Arc<RouteTable<V>>, mode: UrpfMode, ingress: V) -> Self {
+        Urpf {
+            routes,
+            mode,
+            ingress,
+        }
+    }
+
+    /// Returns whether the source address passes the check.
+    pub fn check(&self, src: IpAddr) -> bool {
+        match self.routes.lookup_with_len(src) {
+            Some((len, egress)) => match self.mode {
+                UrpfMode::Strict => *egress == self.ingress,
+                UrpfMode::Loose => len > 0,
+            },
+            None => false,
+        }
+    }
+
+    /// Returns whether the source address of the packet passes the check.
+    #[inline]
+    pub fn check_packet<P: IpPacket>(&self, packet: &P) -> bool {
+        self.check(packet.src())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes() -> Arc<RouteTable<u16>> {
+        let mut routes = RouteTable::new();
+        routes.insert_v4("0.0.0.0/0".parse().unwrap(), 0);
+        routes.insert_v4("10.0.0.0/8".parse().unwrap(), 1);
+        routes.insert_v4("192.168.0.0/16".parse().unwrap(), 2);
+        Arc::new(routes)
+    }
+
+    #[test]
+    fn strict_urpf() {
+        let urpf = Urpf::new(routes(), UrpfMode::Strict, 1);
+
+        assert!(urpf.check("10.1.1.1".parse().unwrap()));
+        // the route back goes out a different interface.
+        assert!(!urpf.check("192.168.1.1".parse().unwrap()));
+        assert!(!urpf.check("8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn loose_urpf() {
+        let urpf = Urpf::new(routes(), UrpfMode::Loose, 1);
+
+        assert!(urpf.check("10.1.1.1".parse().unwrap()));
+        assert!(urpf.check("192.168.1.1".parse().unwrap()));
+        // only the default route matches.
+        assert!(!urpf.check("8.8.8.8".parse().unwrap()));
+        assert!(!urpf.check("2001:db8::1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,534 @@
+use crate::packets::icmp::v6::Icmpv6;
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::ip::{Flow, IpPacket, ProtocolNumber, ProtocolNumbers};
+use crate::packets::{EtherType, EtherTypes, Ethernet, Packet, ParseError, Tcp, Udp};
+use crate::Result;
+use std::fmt;
+use std::net::IpAddr;
+
+/// A packet of any of the built-in L3 and L4 types.
+///
+/// Parsing into `AnyPacket` goes as deep as the built-in parsers know,
+/// so a pipeline can handle a mix of IPv4 and IPv6 traffic with a single
+/// chain of operators instead of one per generic type parameter. The
+/// common accessors work across all the variants. Match on the variants
+/// for the protocol specific fields.
+///
+/// When the device classified the packet on receive, the L3 and L4 types
+/// are taken from its packet type instead of the headers. Otherwise, or
+/// once a header has been inserted or removed, the headers are read.
+///
+/// # Example
+///
+/// ```
+/// Poll::new(q.clone())
+///     .map(|packet| packet.parse::<Ethernet>()?.parse::<AnyPacket>())
+///     .filter(|packet| packet.dst_port() == Some(53))
+///     .for_each(|packet| {
+///         println!("{} -> {}", packet.src(), packet.dst());
+///         Ok(())
+///     })
+///     .send(q)
+/// ```
+#[derive(Clone)]
+pub enum AnyPacket {
+    /// IPv4 packet with a payload that is not TCP or UDP.
+    Ipv4(Ipv4),
+    /// IPv6 packet with a payload that is not TCP, UDP or ICMPv6.
+    Ipv6(Ipv6),
+    Tcp4(Tcp<Ipv4>),
+    Tcp6(Tcp<Ipv6>),
+    Udp4(Udp<Ipv4>),
+    Udp6(Udp<Ipv6>),
+    Icmpv6(Icmpv6<Ipv6, ()>),
+}
+
+// applies the same expression to whatever packet the variant holds.
+macro_rules! dispatch {
+    ($packet:expr, $p:ident => $e:expr) => {
+        match $packet {
+            AnyPacket::Ipv4($p) => $e,
+            AnyPacket::Ipv6($p) => $e,
+            AnyPacket::Tcp4($p) => $e,
+            AnyPacket::Tcp6($p) => $e,
+            AnyPacket::Udp4($p) => $e,
+            AnyPacket::Udp6($p) => $e,
+            AnyPacket::Icmpv6($p) => $e,
+        }
+    };
+}
+
+impl AnyPacket {
+    /// Returns whether the packet is IPv4.
+    #[inline]
+    pub fn is_ipv4(&self) -> bool {
+        match self {
+            AnyPacket::Ipv4(_) | AnyPacket::Tcp4(_) | AnyPacket::Udp4(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the packet is IPv6.
+    #[inline]
+    pub fn is_ipv6(&self) -> bool {
+        !self.is_ipv4()
+    }
+
+    /// Returns the source IP address.
+    #[inline]
+    pub fn src(&self) -> IpAddr {
+        match self {
+            AnyPacket::Ipv4(p) => IpPacket::src(p),
+            AnyPacket::Ipv6(p) => IpPacket::src(p),
+            AnyPacket::Tcp4(p) => IpPacket::src(p.envelope()),
+            AnyPacket::Tcp6(p) => IpPacket::src(p.envelope()),
+            AnyPacket::Udp4(p) => IpPacket::src(p.envelope()),
+            AnyPacket::Udp6(p) => IpPacket::src(p.envelope()),
+            AnyPacket::Icmpv6(p) => IpPacket::src(p.envelope()),
+        }
+    }
+
+    /// Sets the source IP address.
+    ///
+    /// # Errors
+    ///
+    /// If the address family does not match the packet's,
+    /// `IpAddrMismatchError` is returned.
+    #[inline]
+    pub fn set_src(&mut self, src: IpAddr) -> Result<()> {
+        match self {
+            AnyPacket::Ipv4(p) => IpPacket::set_src(p, src),
+            AnyPacket::Ipv6(p) => IpPacket::set_src(p, src),
+            AnyPacket::Tcp4(p) => IpPacket::set_src(p.envelope_mut(), src),
+            AnyPacket::Tcp6(p) => IpPacket::set_src(p.envelope_mut(), src),
+            AnyPacket::Udp4(p) => IpPacket::set_src(p.envelope_mut(), src),
+            AnyPacket::Udp6(p) => IpPacket::set_src(p.envelope_mut(), src),
+            AnyPacket::Icmpv6(p) => IpPacket::set_src(p.envelope_mut(), src),
+        }
+    }
+
+    /// Returns the destination IP address.
+    #[inline]
+    pub fn dst(&self) -> IpAddr {
+        match self {
+            AnyPacket::Ipv4(p) => IpPacket::dst(p),
+            AnyPacket::Ipv6(p) => IpPacket::dst(p),
+            AnyPacket::Tcp4(p) => IpPacket::dst(p.envelope()),
+            AnyPacket::Tcp6(p) => IpPacket::dst(p.envelope()),
+            AnyPacket::Udp4(p) => IpPacket::dst(p.envelope()),
+            AnyPacket::Udp6(p) => IpPacket::dst(p.envelope()),
+            AnyPacket::Icmpv6(p) => IpPacket::dst(p.envelope()),
+        }
+    }
+
+    /// Sets the destination IP address.
+    ///
+    /// # Errors
+    ///
+    /// If the address family does not match the packet's,
+    /// `IpAddrMismatchError` is returned.
+    #[inline]
+    pub fn set_dst(&mut self, dst: IpAddr) -> Result<()> {
+        match self {
+            AnyPacket::Ipv4(p) => IpPacket::set_dst(p, dst),
+            AnyPacket::Ipv6(p) => IpPacket::set_dst(p, dst),
+            AnyPacket::Tcp4(p) => IpPacket::set_dst(p.envelope_mut(), dst),
+            AnyPacket::Tcp6(p) => IpPacket::set_dst(p.envelope_mut(), dst),
+            AnyPacket::Udp4(p) => IpPacket::set_dst(p.envelope_mut(), dst),
+            AnyPacket::Udp6(p) => IpPacket::set_dst(p.envelope_mut(), dst),
+            AnyPacket::Icmpv6(p) => IpPacket::set_dst(p.envelope_mut(), dst),
+        }
+    }
+
+    /// Returns the protocol carried by the IP packet.
+    #[inline]
+    pub fn protocol(&self) -> ProtocolNumber {
+        match self {
+            AnyPacket::Ipv4(p) => p.next_proto(),
+            AnyPacket::Ipv6(p) => p.next_proto(),
+            AnyPacket::Tcp4(_) | AnyPacket::Tcp6(_) => ProtocolNumbers::Tcp,
+            AnyPacket::Udp4(_) | AnyPacket::Udp6(_) => ProtocolNumbers::Udp,
+            AnyPacket::Icmpv6(_) => ProtocolNumbers::Icmpv6,
+        }
+    }
+
+    /// Returns the IPv4 time-to-live or the IPv6 hop limit.
+    #[inline]
+    pub fn ttl(&self) -> u8 {
+        match self {
+            AnyPacket::Ipv4(p) => p.ttl(),
+            AnyPacket::Ipv6(p) => p.hop_limit(),
+            AnyPacket::Tcp4(p) => p.envelope().ttl(),
+            AnyPacket::Tcp6(p) => p.envelope().hop_limit(),
+            AnyPacket::Udp4(p) => p.envelope().ttl(),
+            AnyPacket::Udp6(p) => p.envelope().hop_limit(),
+            AnyPacket::Icmpv6(p) => p.envelope().hop_limit(),
+        }
+    }
+
+    /// Sets the IPv4 time-to-live or the IPv6 hop limit.
+    #[inline]
+    pub fn set_ttl(&mut self, ttl: u8) {
+        match self {
+            AnyPacket::Ipv4(p) => p.set_ttl(ttl),
+            AnyPacket::Ipv6(p) => p.set_hop_limit(ttl),
+            AnyPacket::Tcp4(p) => p.envelope_mut().set_ttl(ttl),
+            AnyPacket::Tcp6(p) => p.envelope_mut().set_hop_limit(ttl),
+            AnyPacket::Udp4(p) => p.envelope_mut().set_ttl(ttl),
+            AnyPacket::Udp6(p) => p.envelope_mut().set_hop_limit(ttl),
+            AnyPacket::Icmpv6(p) => p.envelope_mut().set_hop_limit(ttl),
+        }
+    }
+
+    /// Returns the source port of a TCP or UDP packet.
+    #[inline]
+    pub fn src_port(&self) -> Option<u16> {
+        match self {
+            AnyPacket::Tcp4(p) => Some(p.src_port()),
+            AnyPacket::Tcp6(p) => Some(p.src_port()),
+            AnyPacket::Udp4(p) => Some(p.src_port()),
+            AnyPacket::Udp6(p) => Some(p.src_port()),
+            _ => None,
+        }
+    }
+
+    /// Returns the destination port of a TCP or UDP packet.
+    #[inline]
+    pub fn dst_port(&self) -> Option<u16> {
+        match self {
+            AnyPacket::Tcp4(p) => Some(p.dst_port()),
+            AnyPacket::Tcp6(p) => Some(p.dst_port()),
+            AnyPacket::Udp4(p) => Some(p.dst_port()),
+            AnyPacket::Udp6(p) => Some(p.dst_port()),
+            _ => None,
+        }
+    }
+
+    /// Returns the 5-tuple of a TCP or UDP packet.
+    #[inline]
+    pub fn flow(&self) -> Option<Flow> {
+        match self {
+            AnyPacket::Tcp4(p) => Some(p.flow()),
+            AnyPacket::Tcp6(p) => Some(p.flow()),
+            AnyPacket::Udp4(p) => Some(p.flow()),
+            AnyPacket::Udp6(p) => Some(p.flow()),
+            _ => None,
+        }
+    }
+}
+
+impl From<Ipv4> for AnyPacket {
+    fn from(packet: Ipv4) -> Self {
+        AnyPacket::Ipv4(packet)
+    }
+}
+
+impl From<Ipv6> for AnyPacket {
+    fn from(packet: Ipv6) -> Self {
+        AnyPacket::Ipv6(packet)
+    }
+}
+
+impl From<Tcp<Ipv4>> for AnyPacket {
+    fn from(packet: Tcp<Ipv4>) -> Self {
+        AnyPacket::Tcp4(packet)
+    }
+}
+
+impl From<Tcp<Ipv6>> for AnyPacket {
+    fn from(packet: Tcp<Ipv6>) -> Self {
+        AnyPacket::Tcp6(packet)
+    }
+}
+
+impl From<Udp<Ipv4>> for AnyPacket {
+    fn from(packet: Udp<Ipv4>) -> Self {
+        AnyPacket::Udp4(packet)
+    }
+}
+
+impl From<Udp<Ipv6>> for AnyPacket {
+    fn from(packet: Udp<Ipv6>) -> Self {
+        AnyPacket::Udp6(packet)
+    }
+}
+
+impl From<Icmpv6<Ipv6, ()>> for AnyPacket {
+    fn from(packet: Icmpv6<Ipv6, ()>) -> Self {
+        AnyPacket::Icmpv6(packet)
+    }
+}
+
+impl fmt::Debug for AnyPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        dispatch!(self, p => fmt::Debug::fmt(p, f))
+    }
+}
+
+/// `AnyPacket` behaves like the innermost packet it holds, with the
+/// ethernet frame as its envelope.
+impl Packet for AnyPacket {
+    type Header = ();
+    type Envelope = Ethernet;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        match self {
+            AnyPacket::Ipv4(p) => p.envelope(),
+            AnyPacket::Ipv6(p) => p.envelope(),
+            AnyPacket::Tcp4(p) => p.envelope().envelope(),
+            AnyPacket::Tcp6(p) => p.envelope().envelope(),
+            AnyPacket::Udp4(p) => p.envelope().envelope(),
+            AnyPacket::Udp6(p) => p.envelope().envelope(),
+            AnyPacket::Icmpv6(p) => p.envelope().envelope(),
+        }
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        match self {
+            AnyPacket::Ipv4(p) => p.envelope_mut(),
+            AnyPacket::Ipv6(p) => p.envelope_mut(),
+            AnyPacket::Tcp4(p) => p.envelope_mut().envelope_mut(),
+            AnyPacket::Tcp6(p) => p.envelope_mut().envelope_mut(),
+            AnyPacket::Udp4(p) => p.envelope_mut().envelope_mut(),
+            AnyPacket::Udp6(p) => p.envelope_mut().envelope_mut(),
+            AnyPacket::Icmpv6(p) => p.envelope_mut().envelope_mut(),
+        }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header(&self) -> &Self::Header {
+        unreachable!("any packet has no defined header!");
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header_mut(&mut self) -> &mut Self::Header {
+        unreachable!("any packet has no defined header!");
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        dispatch!(self, p => p.offset())
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        dispatch!(self, p => p.header_len())
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn do_parse(envelope: Self::Envelope) -> Result<Self> {
+        let (ether_type, protocol) = match device_types(&envelope) {
+            Some((ether_type, protocol)) => (ether_type, protocol),
+            None => (envelope.ether_type(), None),
+        };
+
+        match ether_type {
+            EtherTypes::Ipv4 => {
+                let ipv4 = envelope.parse::<Ipv4>()?;
+                match protocol.unwrap_or_else(|| ipv4.next_proto()) {
+                    ProtocolNumbers::Tcp => Ok(AnyPacket::Tcp4(ipv4.parse()?)),
+                    ProtocolNumbers::Udp => Ok(AnyPacket::Udp4(ipv4.parse()?)),
+                    _ => Ok(AnyPacket::Ipv4(ipv4)),
+                }
+            }
+            EtherTypes::Ipv6 => {
+                let ipv6 = envelope.parse::<Ipv6>()?;
+                match protocol.unwrap_or_else(|| ipv6.next_proto()) {
+                    ProtocolNumbers::Tcp => Ok(AnyPacket::Tcp6(ipv6.parse()?)),
+                    ProtocolNumbers::Udp => Ok(AnyPacket::Udp6(ipv6.parse()?)),
+                    ProtocolNumbers::Icmpv6 => Ok(AnyPacket::Icmpv6(ipv6.parse()?)),
+                    _ => Ok(AnyPacket::Ipv6(ipv6)),
+                }
+            }
+            _ => Err(ParseError::new("Packet is not IPv4 or IPv6.").into()),
+        }
+    }
+
+    /// `AnyPacket` cannot be pushed, push the concrete packet type and
+    /// convert it with `From` instead.
+    #[doc(hidden)]
+    #[inline]
+    fn do_push(_envelope: Self::Envelope) -> Result<Self> {
+        Err(ParseError::new("AnyPacket cannot be pushed.").into())
+    }
+
+    /// Removes the header of the innermost packet.
+    #[inline]
+    fn remove(self) -> Result<Self::Envelope> {
+        match self {
+            AnyPacket::Ipv4(p) => p.remove(),
+            AnyPacket::Ipv6(p) => p.remove(),
+            AnyPacket::Tcp4(p) => Ok(p.remove()?.deparse()),
+            AnyPacket::Tcp6(p) => Ok(p.remove()?.deparse()),
+            AnyPacket::Udp4(p) => Ok(p.remove()?.deparse()),
+            AnyPacket::Udp6(p) => Ok(p.remove()?.deparse()),
+            AnyPacket::Icmpv6(p) => Ok(p.remove()?.deparse()),
+        }
+    }
+
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        dispatch!(self, p => p.is_dirty())
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self) {
+        dispatch!(self, p => p.mark_dirty())
+    }
+
+    #[inline]
+    fn cascade(&mut self) {
+        dispatch!(self, p => p.cascade())
+    }
+
+    #[inline]
+    fn cascade_depth(&mut self, depth: usize) {
+        dispatch!(self, p => p.cascade_depth(depth))
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        match self {
+            AnyPacket::Ipv4(p) => p.deparse(),
+            AnyPacket::Ipv6(p) => p.deparse(),
+            AnyPacket::Tcp4(p) => p.deparse().deparse(),
+            AnyPacket::Tcp6(p) => p.deparse().deparse(),
+            AnyPacket::Udp4(p) => p.deparse().deparse(),
+            AnyPacket::Udp6(p) => p.deparse().deparse(),
+            AnyPacket::Icmpv6(p) => p.deparse().deparse(),
+        }
+    }
+}
+
+/// Returns the L3 type and, if known, the L4 type of the packet as the
+/// device classified it, so the headers don't have to be read.
+///
+/// The types are only of use for the outermost frame of a plain ethernet
+/// packet. The L4 type is only taken when the L3 header has no options or
+/// extension headers, the protocol of an IPv6 packet with extension
+/// headers is not the next header of its fixed header.
+#[inline]
+fn device_types(envelope: &Ethernet) -> Option<(EtherType, Option<ProtocolNumber>)> {
+    if envelope.offset() != 0 {
+        return None;
+    }
+
+    let ptype = envelope.mbuf().packet_type();
+    if !ptype.is_plain_ether() || ptype.is_tunnel() {
+        return None;
+    }
+
+    let ether_type = ptype.l3()?;
+    let protocol = if ptype.is_l3_fixed_len() {
+        ptype.l4()
+    } else {
+        None
+    };
+
+    Some((ether_type, protocol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::icmp::v6::ICMPV6_PACKET;
+    use crate::packets::ip::v6::IPV6_PACKET;
+    use crate::packets::UDP_PACKET;
+    use crate::{ffi, Mbuf, PacketType};
+    use std::net::Ipv4Addr;
+
+    #[nb2::test]
+    fn parse_any_udp_packet() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let any = ethernet.parse::<AnyPacket>().unwrap();
+
+        assert!(any.is_ipv4());
+        assert_eq!(ProtocolNumbers::Udp, any.protocol());
+        assert_eq!(Some(39376), any.src_port());
+        assert_eq!(34, any.offset());
+        assert_eq!(8, any.header_len());
+        assert!(any.flow().is_some());
+    }
+
+    #[nb2::test]
+    fn parse_any_with_packet_type() {
+        let mut packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        // a device would never claim TCP, the headers are not read.
+        packet.set_packet_type(PacketType(
+            ffi::RTE_PTYPE_L2_ETHER | ffi::RTE_PTYPE_L3_IPV4 | ffi::RTE_PTYPE_L4_TCP,
+        ));
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let any = ethernet.parse::<AnyPacket>().unwrap();
+        assert_eq!(ProtocolNumbers::Tcp, any.protocol());
+
+        // the L4 type is not taken when the L3 header has options.
+        let mut packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        packet.set_packet_type(PacketType(
+            ffi::RTE_PTYPE_L2_ETHER | ffi::RTE_PTYPE_L3_IPV4_EXT | ffi::RTE_PTYPE_L4_TCP,
+        ));
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let any = ethernet.parse::<AnyPacket>().unwrap();
+        assert_eq!(ProtocolNumbers::Udp, any.protocol());
+    }
+
+    #[nb2::test]
+    fn parse_any_tcp_v6_packet() {
+        let packet = Mbuf::from_bytes(&IPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let any = ethernet.parse::<AnyPacket>().unwrap();
+
+        if let AnyPacket::Tcp6(ref tcp) = any {
+            assert_eq!(23, tcp.dst_port());
+        } else {
+            panic!("not a tcp packet.");
+        }
+        assert!(any.is_ipv6());
+        assert_eq!(2, any.ttl());
+    }
+
+    #[nb2::test]
+    fn parse_any_icmpv6_packet() {
+        let packet = Mbuf::from_bytes(&ICMPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let any = ethernet.parse::<AnyPacket>().unwrap();
+
+        assert_eq!(ProtocolNumbers::Icmpv6, any.protocol());
+        assert_eq!(None, any.dst_port());
+    }
+
+    #[nb2::test]
+    fn set_any_packet_fields() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut any = ethernet.parse::<AnyPacket>().unwrap();
+
+        let dst = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        any.set_dst(dst).unwrap();
+        any.set_ttl(5);
+        assert!(any.set_src("::1".parse().unwrap()).is_err());
+        any.cascade();
+
+        let ethernet = any.deparse();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert_eq!(Ipv4Addr::new(10, 0, 0, 1), ipv4.dst());
+        assert_eq!(5, ipv4.ttl());
+    }
+
+    #[nb2::test]
+    fn convert_into_any_packet() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let any = AnyPacket::from(ipv4);
+
+        assert_eq!(ProtocolNumbers::Udp, any.protocol());
+        assert_eq!(None, any.src_port());
+    }
+}
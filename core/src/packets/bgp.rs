@@ -0,0 +1,283 @@
+use crate::packets::ip::IpPacket;
+use crate::packets::{data_slice, Packet, Tcp};
+use crate::{ensure, Result};
+use failure::Fail;
+use std::fmt;
+
+/*  From https://tools.ietf.org/html/rfc4271#section-4.1
+    BGP Message Header Format
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                                                               |
+    +                                                               +
+    |                                                               |
+    +                                                               +
+    |                           Marker                              |
+    +                                                               +
+    |                                                               |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |          Length               |      Type     |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    Marker      16 octets, all ones.
+
+    Length      The total length of the message, including the header,
+                from 19 to 4096. RFC 8654 extends the maximum to 65535
+                for all the messages but the open and the keepalive.
+*/
+
+/// The well-known TCP port of BGP.
+pub const BGP_PORT: u16 = 179;
+
+/// The length of the BGP message header.
+pub const BGP_HEADER_LEN: usize = 19;
+
+const MARKER_LEN: usize = 16;
+
+/// BGP message header errors.
+#[derive(Debug, Fail)]
+pub enum BgpError {
+    /// The data is shorter than a message header.
+    #[fail(display = "Truncated BGP message header.")]
+    Truncated,
+
+    /// The marker is not all ones.
+    #[fail(display = "Bad BGP message marker.")]
+    BadMarker,
+
+    /// The length is shorter than the header.
+    #[fail(display = "Bad BGP message length {}.", _0)]
+    BadLength(u16),
+}
+
+/// Type of BGP message.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct BgpType(pub u8);
+
+impl BgpType {
+    pub fn new(value: u8) -> Self {
+        BgpType(value)
+    }
+}
+
+/// Supported BGP message types.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod BgpTypes {
+    use super::BgpType;
+
+    pub const Open: BgpType = BgpType(1);
+    pub const Update: BgpType = BgpType(2);
+    pub const Notification: BgpType = BgpType(3);
+    pub const Keepalive: BgpType = BgpType(4);
+    pub const RouteRefresh: BgpType = BgpType(5);
+}
+
+impl fmt::Display for BgpType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                BgpTypes::Open => "Open".to_string(),
+                BgpTypes::Update => "Update".to_string(),
+                BgpTypes::Notification => "Notification".to_string(),
+                BgpTypes::Keepalive => "Keepalive".to_string(),
+                BgpTypes::RouteRefresh => "Route Refresh".to_string(),
+                _ => format!("{}", self.0),
+            }
+        )
+    }
+}
+
+/// The header of a BGP message.
+///
+/// BGP runs over TCP, so a message can span segments and a segment can
+/// carry several messages. `BgpHeader` is parsed from bytes rather than
+/// from a packet, either from the start of a TCP payload that starts a
+/// message, with `first_in_segment`, or from a reassembled stream, with
+/// `BgpMessages`.
+///
+/// # Example
+///
+/// ```
+/// if is_bgp(&tcp) {
+///     if let Ok(header) = BgpHeader::first_in_segment(&tcp) {
+///         if header.msg_type == BgpTypes::Keepalive {
+///             // network control, so keepalives are not queued behind data.
+///             tcp.envelope_mut().set_dscp(48);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BgpHeader {
+    /// The length of the message, including the header.
+    pub length: u16,
+    pub msg_type: BgpType,
+}
+
+impl BgpHeader {
+    /// Parses the message header at the start of `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BgpError` if the data is shorter than a header, the marker
+    /// is not all ones or the length is shorter than a header.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() >= BGP_HEADER_LEN, BgpError::Truncated);
+        ensure!(
+            data[..MARKER_LEN].iter().all(|&b| b == 0xff),
+            BgpError::BadMarker
+        );
+
+        let length = u16::from_be_bytes([data[16], data[17]]);
+        ensure!(
+            length as usize >= BGP_HEADER_LEN,
+            BgpError::BadLength(length)
+        );
+
+        Ok(BgpHeader {
+            length,
+            msg_type: BgpType::new(data[18]),
+        })
+    }
+
+    /// Parses the message header at the start of the segment's payload.
+    ///
+    /// Only meaningful for a segment that starts a message, such as the
+    /// first segment after the session is established. Any other segment
+    /// most likely fails on the marker.
+    pub fn first_in_segment<E: IpPacket>(tcp: &Tcp<E>) -> Result<Self> {
+        BgpHeader::parse(data_slice(
+            tcp.mbuf(),
+            tcp.payload_offset(),
+            tcp.payload_len(),
+        ))
+    }
+}
+
+/// Returns whether the segment is to or from the BGP port.
+#[inline]
+pub fn is_bgp<E: IpPacket>(tcp: &Tcp<E>) -> bool {
+    tcp.dst_port() == BGP_PORT || tcp.src_port() == BGP_PORT
+}
+
+/// An iterator over the message headers of a reassembled BGP stream.
+///
+/// Each item is the header of a complete message and the message bytes,
+/// header included. The iteration stops at the first incomplete message,
+/// whose offset is then returned by `consumed`, so the rest can be kept
+/// until more of the stream arrives. It also stops at an invalid header,
+/// which is returned as an error.
+pub struct BgpMessages<'a> {
+    data: &'a [u8],
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> BgpMessages<'a> {
+    /// Creates a new iterator over the messages in `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        BgpMessages {
+            data,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    /// Returns the number of bytes of complete messages iterated over.
+    pub fn consumed(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for BgpMessages<'a> {
+    type Item = Result<(BgpHeader, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.data[self.offset..];
+        if self.failed || rest.len() < BGP_HEADER_LEN {
+            return None;
+        }
+
+        match BgpHeader::parse(rest) {
+            Ok(header) if header.length as usize <= rest.len() => {
+                self.offset += header.length as usize;
+                Some(Ok((header, &rest[..header.length as usize])))
+            }
+            Ok(_) => None,
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::Ethernet;
+    use crate::Mbuf;
+
+    fn keepalive() -> Vec<u8> {
+        let mut message = vec![0xff; MARKER_LEN];
+        message.extend_from_slice(&[0x00, 0x13, 0x04]);
+        message
+    }
+
+    #[test]
+    fn parse_keepalive_header() {
+        let header = BgpHeader::parse(&keepalive()).unwrap();
+        assert_eq!(19, header.length);
+        assert_eq!(BgpTypes::Keepalive, header.msg_type);
+        assert_eq!("Keepalive", header.msg_type.to_string());
+    }
+
+    #[test]
+    fn reject_bad_headers() {
+        let mut message = keepalive();
+        assert!(BgpHeader::parse(&message[..18]).is_err());
+
+        message[17] = 0x12;
+        assert!(BgpHeader::parse(&message).is_err());
+
+        message[0] = 0;
+        assert!(BgpHeader::parse(&message).is_err());
+    }
+
+    #[test]
+    fn iterate_stream_messages() {
+        let mut stream = keepalive();
+        stream.extend(keepalive());
+        // the first half of a third message.
+        stream.extend(&keepalive()[..10]);
+
+        let mut messages = BgpMessages::new(&stream);
+        assert_eq!(2, messages.by_ref().filter_map(|m| m.ok()).count());
+        assert_eq!(38, messages.consumed());
+    }
+
+    #[nb2::test]
+    fn parse_first_in_segment() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let mut tcp = ipv4.push::<Tcp<Ipv4>>().unwrap();
+        tcp.set_dst_port(BGP_PORT);
+        let offset = tcp.payload_offset();
+        tcp.mbuf_mut().extend(offset, BGP_HEADER_LEN).unwrap();
+        tcp.mbuf_mut()
+            .write_data_slice(offset, &keepalive())
+            .unwrap();
+
+        assert!(is_bgp(&tcp));
+        let header = BgpHeader::first_in_segment(&tcp).unwrap();
+        assert_eq!(BgpTypes::Keepalive, header.msg_type);
+    }
+}
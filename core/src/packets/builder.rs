@@ -0,0 +1,256 @@
+use crate::net::MacAddr;
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Payload};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::{Ipv6, Ipv6Packet};
+use crate::packets::ip::IpPacket;
+use crate::packets::{Ethernet, Packet, Tcp, Udp};
+use crate::{Mbuf, Result};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The default TTL or hop limit of packets built with `PacketBuilder`.
+const DEFAULT_TTL: u8 = 64;
+
+/// Fluent builder for constructing packets layer by layer.
+///
+/// Each layer is pushed onto a newly allocated message buffer in order.
+/// `build` cascades the lengths and checksums through all the layers and
+/// returns the typed outermost packet. If any step fails, the error is
+/// carried through and returned by `build`.
+///
+/// # Example
+///
+/// ```
+/// let udp = PacketBuilder::ethernet(src_mac, dst_mac)
+///     .ipv4(src_ip, dst_ip)
+///     .udp(39376, 1087)
+///     .payload(b"hello")
+///     .build()?;
+/// ```
+///
+/// Fields without a dedicated builder method are set through `with`.
+///
+/// ```
+/// let syn = PacketBuilder::ethernet(src_mac, dst_mac)
+///     .ipv6(src_ip, dst_ip)
+///     .tcp(3464, 80)
+///     .with(|tcp| tcp.set_syn())
+///     .build()?;
+/// ```
+pub struct PacketBuilder<T: Packet> {
+    packet: Result<T>,
+}
+
+impl PacketBuilder<Ethernet> {
+    /// Starts a new packet with an Ethernet frame.
+    pub fn ethernet(src: MacAddr, dst: MacAddr) -> Self {
+        let packet = Mbuf::new()
+            .and_then(Mbuf::push::<Ethernet>)
+            .map(|mut ethernet| {
+                ethernet.set_src(src);
+                ethernet.set_dst(dst);
+                ethernet
+            });
+
+        PacketBuilder { packet }
+    }
+
+    /// Pushes an IPv4 packet with a TTL of 64.
+    pub fn ipv4(self, src: Ipv4Addr, dst: Ipv4Addr) -> PacketBuilder<Ipv4> {
+        self.then(|ethernet| {
+            let mut ipv4 = ethernet.push::<Ipv4>()?;
+            ipv4.set_src(src);
+            ipv4.set_dst(dst);
+            ipv4.set_ttl(DEFAULT_TTL);
+            Ok(ipv4)
+        })
+    }
+
+    /// Pushes an IPv6 packet with a hop limit of 64.
+    pub fn ipv6(self, src: Ipv6Addr, dst: Ipv6Addr) -> PacketBuilder<Ipv6> {
+        self.then(|ethernet| {
+            let mut ipv6 = ethernet.push::<Ipv6>()?;
+            ipv6.set_src(src);
+            ipv6.set_dst(dst);
+            ipv6.set_hop_limit(DEFAULT_TTL);
+            Ok(ipv6)
+        })
+    }
+}
+
+impl<E: IpPacket> PacketBuilder<E> {
+    /// Pushes a TCP segment.
+    pub fn tcp(self, src_port: u16, dst_port: u16) -> PacketBuilder<Tcp<E>> {
+        self.then(|envelope| {
+            let mut tcp = envelope.push::<Tcp<E>>()?;
+            tcp.set_src_port(src_port);
+            tcp.set_dst_port(dst_port);
+            Ok(tcp)
+        })
+    }
+
+    /// Pushes a UDP datagram.
+    pub fn udp(self, src_port: u16, dst_port: u16) -> PacketBuilder<Udp<E>> {
+        self.then(|envelope| {
+            let mut udp = envelope.push::<Udp<E>>()?;
+            udp.set_src_port(src_port);
+            udp.set_dst_port(dst_port);
+            Ok(udp)
+        })
+    }
+}
+
+impl<E: Ipv6Packet> PacketBuilder<E> {
+    /// Pushes an ICMPv6 message of type `P`.
+    pub fn icmpv6<P: Icmpv6Payload>(self) -> PacketBuilder<Icmpv6<E, P>> {
+        self.then(|envelope| envelope.push::<Icmpv6<E, P>>())
+    }
+}
+
+impl<T: Packet> PacketBuilder<T> {
+    #[inline]
+    fn then<U: Packet, F>(self, f: F) -> PacketBuilder<U>
+    where
+        F: FnOnce(T) -> Result<U>,
+    {
+        PacketBuilder {
+            packet: self.packet.and_then(f),
+        }
+    }
+
+    /// Modifies the current layer.
+    pub fn with<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut T),
+    {
+        PacketBuilder {
+            packet: self.packet.map(|mut packet| {
+                f(&mut packet);
+                packet
+            }),
+        }
+    }
+
+    /// Appends the bytes to the end of the packet as payload. An empty
+    /// payload leaves the packet as is.
+    pub fn payload(self, data: &[u8]) -> Self {
+        self.then(|mut packet| {
+            // the buffer can't be extended by nothing.
+            if data.is_empty() {
+                return Ok(packet);
+            }
+
+            let offset = packet.mbuf().data_len();
+            packet.mbuf_mut().extend(offset, data.len())?;
+            packet.mbuf_mut().write_data_slice(offset, data)?;
+            Ok(packet)
+        })
+    }
+
+    /// Finishes the packet.
+    ///
+    /// The lengths and checksums of all the layers are fixed up before
+    /// the packet is returned.
+    pub fn build(self) -> Result<T> {
+        self.packet.map(|mut packet| {
+            packet.cascade();
+            packet
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::icmp::v6::{EchoRequest, Icmpv6Packet};
+    use crate::packets::ip::ProtocolNumbers;
+    use crate::packets::EtherTypes;
+
+    fn macs() -> (MacAddr, MacAddr) {
+        (
+            MacAddr::new(0, 0, 0, 0, 0, 1),
+            MacAddr::new(0, 0, 0, 0, 0, 2),
+        )
+    }
+
+    #[nb2::test]
+    fn build_ipv4_udp_packet() {
+        let (src, dst) = macs();
+        let udp = PacketBuilder::ethernet(src, dst)
+            .ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+            .udp(39376, 1087)
+            .payload(b"hello")
+            .build()
+            .unwrap();
+
+        assert_eq!(39376, udp.src_port());
+        assert_eq!(1087, udp.dst_port());
+        assert_eq!(13, udp.length());
+        assert_eq!(b"hello", udp.payload());
+        assert!(udp.verify_checksum());
+
+        let ipv4 = udp.envelope();
+        assert_eq!(ProtocolNumbers::Udp, ipv4.protocol());
+        assert_eq!(33, ipv4.total_length());
+        assert_eq!(64, ipv4.ttl());
+        assert!(ipv4.verify_checksum());
+
+        let ethernet = ipv4.envelope();
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+        assert_eq!(src, ethernet.src());
+        assert_eq!(dst, ethernet.dst());
+    }
+
+    #[nb2::test]
+    fn build_with_empty_payload() {
+        let (src, dst) = macs();
+        let udp = PacketBuilder::ethernet(src, dst)
+            .ipv4(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+            .udp(39376, 1087)
+            .payload(&[])
+            .build()
+            .unwrap();
+
+        assert_eq!(8, udp.length());
+        assert!(udp.payload().is_empty());
+        assert!(udp.verify_checksum());
+        assert_eq!(28, udp.envelope().total_length());
+    }
+
+    #[nb2::test]
+    fn build_ipv6_tcp_packet() {
+        let (src, dst) = macs();
+        let tcp = PacketBuilder::ethernet(src, dst)
+            .ipv6(
+                "2001:db8::1".parse().unwrap(),
+                "2001:db8::2".parse().unwrap(),
+            )
+            .tcp(3464, 80)
+            .with(|tcp| tcp.set_syn())
+            .build()
+            .unwrap();
+
+        assert_eq!(3464, tcp.src_port());
+        assert_eq!(80, tcp.dst_port());
+        assert!(tcp.syn());
+        assert!(tcp.verify_checksum());
+        assert_eq!(ProtocolNumbers::Tcp, tcp.envelope().next_header());
+        assert_eq!(20, tcp.envelope().payload_length());
+    }
+
+    #[nb2::test]
+    fn build_icmpv6_packet() {
+        let (src, dst) = macs();
+        let echo = PacketBuilder::ethernet(src, dst)
+            .ipv6(
+                "2001:db8::1".parse().unwrap(),
+                "2001:db8::2".parse().unwrap(),
+            )
+            .icmpv6::<EchoRequest>()
+            .with(|echo| echo.set_identifier(42))
+            .build()
+            .unwrap();
+
+        assert_eq!(42, echo.identifier());
+        assert!(echo.verify_checksum());
+    }
+}
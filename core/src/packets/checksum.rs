@@ -1,5 +1,9 @@
-use crate::packets::ip::{IpAddrMismatchError, ProtocolNumber};
-use crate::Result;
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::ip::{IpAddrMismatchError, IpPacket, ProtocolNumber, ProtocolNumbers};
+use crate::packets::{EtherTypes, Ethernet, Packet, ParseError, Tcp, Udp};
+use crate::stats::Anomaly;
+use crate::{debug, Mbuf, Result};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::slice;
 
@@ -177,6 +181,16 @@ pub fn compute_inc(old_checksum: u16, old_value: &[u16], new_value: &[u16]) -> u
     !(checksum as u16)
 }
 
+/// Returns the 1's complement sum of the octets.
+///
+/// The sums of a range before and after a change are the old and new
+/// values to `compute_inc` for that range, as long as the range starts at
+/// an even offset of the checksummed data. An odd trailing octet is padded
+/// like in `compute`.
+pub fn sum(data: &[u8]) -> u16 {
+    !compute(0, data)
+}
+
 /// Incrementally computes the new checksum for an IP address change.
 pub fn compute_with_ipaddr(
     old_checksum: u16,
@@ -198,12 +212,169 @@ pub fn compute_with_ipaddr(
     }
 }
 
+/// Recomputes the IPv4, TCP and UDP checksums of the frame, like the
+/// kernel does for sockets with checksum offload.
+///
+/// The backends without hardware offload, such as `net_pcap` and
+/// `net_tap`, transmit the frames as they are. Frames that are not IP, or
+/// that fail to parse, are returned unchanged. The TCP and UDP checksums
+/// cover the whole datagram, so they are left alone on the fragments, and
+/// only the IPv4 header checksum of a fragment is recomputed.
+///
+/// The frame is checked before it is changed, so a frame that is not
+/// returned as is comes back with the checksums fixed. An error is
+/// returned for an IP packet shorter than the length in its header, as
+/// the checksums would not cover the bytes the receiver expects.
+pub(crate) fn fixup(mbuf: Mbuf) -> Result<Mbuf> {
+    let (ether_type, l4, padded) = match mbuf.peek::<Ethernet>() {
+        Ok(ethernet) => {
+            let ether_type = ethernet.ether_type();
+            let l4 = match ether_type {
+                EtherTypes::Ipv4 => match ethernet.peek::<Ipv4>() {
+                    Ok(ipv4) if ipv4.total_length() as usize > ethernet.payload_len() => {
+                        return Err(truncated().into());
+                    }
+                    Ok(ipv4) if ipv4.is_fragment() => None,
+                    Ok(ipv4) => l4_protocol(&*ipv4),
+                    Err(_) => return Ok(mbuf),
+                },
+                // a fragment header, or any extension header, is the next
+                // protocol and not the transport.
+                EtherTypes::Ipv6 => match ethernet.peek::<Ipv6>() {
+                    Ok(ipv6) if ipv6.payload_length() as usize > ipv6.payload_len() => {
+                        return Err(truncated().into());
+                    }
+                    Ok(ipv6) => l4_protocol(&*ipv6),
+                    Err(_) => return Ok(mbuf),
+                },
+                _ => return Ok(mbuf),
+            };
+            (ether_type, l4, ethernet.padding_len() > 0)
+        }
+        Err(_) => return Ok(mbuf),
+    };
+
+    let mut ethernet = mbuf.parse::<Ethernet>()?;
+    // the IP lengths are reset to the buffer length on cascade.
+    ethernet.strip_padding()?;
+
+    let mut ethernet = if ether_type == EtherTypes::Ipv4 {
+        fixup_ip(ethernet.parse::<Ipv4>()?, l4)?
+    } else {
+        fixup_ip(ethernet.parse::<Ipv6>()?, l4)?
+    };
+
+    // the padding was there before, so there's room for it. if not, the
+    // device pads the short frame.
+    if padded && ethernet.pad().is_err() {
+        debug!("failed to pad the frame after the checksum fixup.");
+    }
+
+    Ok(ethernet.reset())
+}
+
+fn truncated() -> ParseError {
+    ParseError::with_anomaly(Anomaly::Truncated, "IP packet shorter than its length.")
+}
+
+/// Returns the transport protocol of the packet, if it's TCP or UDP with a
+/// full header.
+fn l4_protocol<E: IpPacket<Envelope = Ethernet>>(ip: &E) -> Option<ProtocolNumber> {
+    match ip.next_proto() {
+        ProtocolNumbers::Tcp if ip.peek::<Tcp<E>>().is_ok() => Some(ProtocolNumbers::Tcp),
+        ProtocolNumbers::Udp if ip.peek::<Udp<E>>().is_ok() => Some(ProtocolNumbers::Udp),
+        _ => None,
+    }
+}
+
+fn fixup_ip<E: IpPacket<Envelope = Ethernet>>(
+    mut ip: E,
+    l4: Option<ProtocolNumber>,
+) -> Result<Ethernet> {
+    // so the header checksum is recomputed on cascade.
+    ip.mark_dirty();
+
+    let ip = match l4 {
+        Some(ProtocolNumbers::Tcp) => {
+            let mut tcp = ip.parse::<Tcp<E>>()?;
+            tcp.cascade();
+            tcp.deparse()
+        }
+        Some(ProtocolNumbers::Udp) => {
+            let mut udp = ip.parse::<Udp<E>>()?;
+            udp.cascade();
+            udp.deparse()
+        }
+        _ => {
+            ip.cascade();
+            ip
+        }
+    };
+
+    Ok(ip.deparse())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
 
     #[test]
     fn compute_checksum_incrementally() {
         assert_eq!(0x0000, compute_inc(0xdd2f, &[0x5555], &[0x3285]));
     }
+
+    #[nb2::test]
+    fn fixup_tcp_checksum() {
+        let packet = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp<Ipv4>>().unwrap();
+        // a deferred checksum, never computed.
+        tcp.set_seq_no(42);
+        assert!(!tcp.verify_checksum());
+
+        let packet = fixup(tcp.reset()).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert!(ipv4.verify_checksum());
+        let tcp = ipv4.parse::<Tcp<Ipv4>>().unwrap();
+        assert_eq!(42, tcp.seq_no());
+        assert!(tcp.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn fixup_keeps_valid_checksum() {
+        let packet = fixup(Mbuf::from_bytes(&UDP_PACKET).unwrap()).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        assert!(udp.verify_checksum());
+        assert_eq!(UDP_PACKET.len(), udp.mbuf().data_len());
+    }
+
+    #[nb2::test]
+    fn fixup_skips_fragment_l4_checksum() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        ipv4.set_more_fragments();
+        let mut udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        udp.set_src_port(1234);
+
+        let packet = fixup(udp.reset()).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert!(ipv4.more_fragments());
+        assert!(ipv4.verify_checksum());
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        assert_eq!(1234, udp.src_port());
+        assert!(!udp.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn fixup_rejects_truncated_packet() {
+        let packet = Mbuf::from_bytes(&TCP_PACKET[..TCP_PACKET.len() - 4]).unwrap();
+        assert!(fixup(packet).is_err());
+    }
 }
@@ -1,6 +1,7 @@
+use crate::dpdk::BufferError;
 use crate::net::MacAddr;
-use crate::packets::{CondRc, Header, Packet};
-use crate::{Mbuf, Result, SizeOf};
+use crate::packets::{be16, CondRc, Header, Packet};
+use crate::{ensure, Mbuf, Result, SizeOf};
 use std::fmt;
 use std::ptr::NonNull;
 
@@ -22,8 +23,55 @@ use std::ptr::NonNull;
 
    Ether Type           16-bit indicator. Identifies which protocol is
                         encapsulated in the payload of the frame.
+
+   FCS                  32-bit CRC of the frame, trailing the payload. Most
+                        devices strip it on receive and compute it on
+                        transmit, so it's normally not in the buffer.
 */
 
+/// The length of the ethernet frame check sequence.
+pub const FCS_LEN: usize = 4;
+
+/// The minimum length of an ethernet frame, not including the FCS. Shorter
+/// frames are padded with trailing zeros to this length.
+pub const MIN_FRAME_LEN: usize = 60;
+
+// the length of the fixed IPv6 header, which the payload length field does
+// not count.
+const IPV6_HEADER_LEN: usize = 40;
+
+/// Computes the IEEE 802.3 CRC-32 of the data.
+///
+/// This is the bitwise variant. It's only used by backends that don't
+/// handle the FCS in hardware, where throughput is not a concern.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Computes the FCS of the frame in the buffer and appends it to the end.
+pub(crate) fn append_fcs(mbuf: &mut Mbuf) -> Result<()> {
+    let len = mbuf.data_len();
+    let fcs = unsafe { crc32(mbuf.read_data_slice::<u8>(0, len)?.as_ref()) };
+    mbuf.extend(len, FCS_LEN)?;
+    mbuf.write_data_slice(len, &fcs.to_le_bytes())?;
+    Ok(())
+}
+
+/// Removes the trailing FCS from the frame in the buffer.
+pub(crate) fn strip_fcs(mbuf: &mut Mbuf) -> Result<()> {
+    let len = mbuf.data_len();
+    ensure!(len > FCS_LEN, BufferError::NotResized);
+    mbuf.truncate(len - FCS_LEN)
+}
+
 /// The protocol type in the ethernet packet payload.
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 #[repr(C, packed)]
@@ -45,6 +93,28 @@ pub mod EtherTypes {
     pub const Ipv4: EtherType = EtherType(0x0800);
     // Internet Protocol version 6
     pub const Ipv6: EtherType = EtherType(0x86DD);
+    // PPP over Ethernet discovery stage
+    pub const PppoeDiscovery: EtherType = EtherType(0x8863);
+    // PPP over Ethernet session stage
+    pub const PppoeSession: EtherType = EtherType(0x8864);
+    // Address Resolution Protocol
+    pub const Arp: EtherType = EtherType(0x0806);
+    // Reverse Address Resolution Protocol
+    pub const Rarp: EtherType = EtherType(0x8035);
+    // IEEE 802.1Q VLAN tag
+    pub const Vlan: EtherType = EtherType(0x8100);
+    // IEEE 802.1ad service VLAN tag, for QinQ
+    pub const Qinq: EtherType = EtherType(0x88A8);
+    // MPLS unicast
+    pub const Mpls: EtherType = EtherType(0x8847);
+    // MPLS multicast
+    pub const MplsMulticast: EtherType = EtherType(0x8848);
+    // Link Layer Discovery Protocol
+    pub const Lldp: EtherType = EtherType(0x88CC);
+    // Ethernet flow control, the pause frames
+    pub const FlowControl: EtherType = EtherType(0x8808);
+    // Slow protocols, such as LACP
+    pub const Slow: EtherType = EtherType(0x8809);
 }
 
 impl fmt::Display for EtherType {
@@ -55,6 +125,17 @@ impl fmt::Display for EtherType {
             match *self {
                 EtherTypes::Ipv4 => "IPv4".to_string(),
                 EtherTypes::Ipv6 => "IPv6".to_string(),
+                EtherTypes::PppoeDiscovery => "PPPoE Discovery".to_string(),
+                EtherTypes::PppoeSession => "PPPoE Session".to_string(),
+                EtherTypes::Arp => "ARP".to_string(),
+                EtherTypes::Rarp => "RARP".to_string(),
+                EtherTypes::Vlan => "802.1Q".to_string(),
+                EtherTypes::Qinq => "802.1ad".to_string(),
+                EtherTypes::Mpls => "MPLS".to_string(),
+                EtherTypes::MplsMulticast => "MPLS Multicast".to_string(),
+                EtherTypes::Lldp => "LLDP".to_string(),
+                EtherTypes::FlowControl => "Flow Control".to_string(),
+                EtherTypes::Slow => "Slow Protocols".to_string(),
                 _ => {
                     let t = self.0;
                     format!("0x{:04x}", t)
@@ -70,7 +151,7 @@ impl fmt::Display for EtherType {
 pub struct EthernetHeader {
     dst: MacAddr,
     src: MacAddr,
-    ether_type: u16,
+    ether_type: be16,
 }
 
 impl Header for EthernetHeader {}
@@ -106,12 +187,12 @@ impl Ethernet {
 
     #[inline]
     pub fn ether_type(&self) -> EtherType {
-        EtherType::new(u16::from_be(self.header().ether_type))
+        EtherType::new(self.header().ether_type.get())
     }
 
     #[inline]
     pub fn set_ether_type(&mut self, ether_type: EtherType) {
-        self.header_mut().ether_type = u16::to_be(ether_type.0)
+        self.header_mut().ether_type = ether_type.0.into()
     }
 
     #[inline]
@@ -121,6 +202,90 @@ impl Ethernet {
         self.set_src(dst);
         self.set_dst(src);
     }
+
+    /// Returns the length of the IP packet in the payload, as stated in its
+    /// header, or `None` if the payload is not IP.
+    fn ip_len(&self) -> Option<usize> {
+        let offset = self.payload_offset();
+        let (field, extra) = match self.ether_type() {
+            EtherTypes::Ipv4 => (offset + 2, 0),
+            EtherTypes::Ipv6 => (offset + 4, IPV6_HEADER_LEN),
+            _ => return None,
+        };
+
+        self.mbuf()
+            .read_data::<be16>(field)
+            .ok()
+            .map(|len| unsafe { len.as_ref() }.get() as usize + extra)
+    }
+
+    /// Returns the number of bytes trailing the IP packet in the payload.
+    ///
+    /// Frames shorter than `MIN_FRAME_LEN` are padded on the wire, and the
+    /// padding is counted in the length of the buffer. Returns `0` if the
+    /// payload is not IP.
+    ///
+    /// # Remarks
+    ///
+    /// If the port keeps the FCS, it is counted as padding as well.
+    pub fn padding_len(&self) -> usize {
+        self.ip_len()
+            .map_or(0, |ip_len| self.payload_len().saturating_sub(ip_len))
+    }
+
+    /// Removes the bytes trailing the IP packet in the payload.
+    ///
+    /// Call before modifying a short packet received from the wire, or the
+    /// lengths of the upper layers will include the padding on `cascade`.
+    pub fn strip_padding(&mut self) -> Result<()> {
+        let padding_len = self.padding_len();
+        if padding_len > 0 {
+            let len = self.mbuf().data_len();
+            self.mbuf_mut().truncate(len - padding_len)?;
+        }
+        Ok(())
+    }
+
+    /// Pads the frame with zeros to `MIN_FRAME_LEN`.
+    ///
+    /// Most devices pad short frames on transmit. Use for the ones that do
+    /// not, or when the frame is written to a trace instead. Call after
+    /// `cascade`, so the upper layer lengths do not include the padding.
+    pub fn pad(&mut self) -> Result<()> {
+        let len = self.len();
+        if len < MIN_FRAME_LEN {
+            let end = self.mbuf().data_len();
+            let padding_len = MIN_FRAME_LEN - len;
+            self.mbuf_mut().extend(end, padding_len)?;
+            self.mbuf_mut()
+                .write_data_slice(end, &[0u8; MIN_FRAME_LEN][..padding_len])?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the trailing 4 bytes of the frame are a valid FCS.
+    ///
+    /// # Remarks
+    ///
+    /// The frame only carries the FCS if the port is configured to keep
+    /// it, or the packets are replayed from a trace captured with it.
+    /// Otherwise the last 4 bytes are just payload and the check fails.
+    pub fn verify_fcs(&self) -> bool {
+        let len = self.len();
+        if len < self.header_len() + FCS_LEN {
+            return false;
+        }
+
+        match self.mbuf().read_data_slice::<u8>(self.offset(), len) {
+            Ok(frame) => {
+                let frame = unsafe { frame.as_ref() };
+                let (data, fcs) = frame.split_at(len - FCS_LEN);
+                let expected = u32::from_le_bytes([fcs[0], fcs[1], fcs[2], fcs[3]]);
+                crc32(data) == expected
+            }
+            Err(_) => false,
+        }
+    }
 }
 
 impl fmt::Debug for Ethernet {
@@ -225,6 +390,8 @@ mod tests {
     fn ether_type_to_string() {
         assert_eq!("IPv4", EtherTypes::Ipv4.to_string());
         assert_eq!("IPv6", EtherTypes::Ipv6.to_string());
+        assert_eq!("ARP", EtherTypes::Arp.to_string());
+        assert_eq!("LLDP", EtherTypes::Lldp.to_string());
         assert_eq!("0x0000", EtherType::new(0).to_string());
     }
 
@@ -248,6 +415,60 @@ mod tests {
         assert_eq!("00:00:00:00:00:01", ethernet.src().to_string());
     }
 
+    #[nb2::test]
+    fn pad_short_frame() {
+        // a 42 byte frame, short of the minimum.
+        let packet = Mbuf::from_bytes(&UDP_PACKET[..42]).unwrap();
+        let mut ethernet = packet.parse::<Ethernet>().unwrap();
+        assert_eq!(0, ethernet.padding_len());
+
+        ethernet.pad().unwrap();
+        assert_eq!(MIN_FRAME_LEN, ethernet.len());
+        let padding = unsafe {
+            ethernet
+                .mbuf()
+                .read_data_slice::<u8>(42, 18)
+                .unwrap()
+                .as_ref()
+        };
+        assert_eq!(&[0u8; 18], padding);
+    }
+
+    #[nb2::test]
+    fn strip_padding() {
+        let mut bytes = UDP_PACKET.to_vec();
+        bytes.extend_from_slice(&[0; 8]);
+        let packet = Mbuf::from_bytes(&bytes).unwrap();
+        let mut ethernet = packet.parse::<Ethernet>().unwrap();
+        assert_eq!(8, ethernet.padding_len());
+
+        ethernet.strip_padding().unwrap();
+        assert_eq!(0, ethernet.padding_len());
+        assert_eq!(UDP_PACKET.len(), ethernet.len());
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+    }
+
+    #[nb2::test]
+    fn append_and_verify_fcs() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        assert!(!ethernet.verify_fcs());
+
+        let mut packet = ethernet.reset();
+        append_fcs(&mut packet).unwrap();
+        assert_eq!(UDP_PACKET.len() + FCS_LEN, packet.data_len());
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        assert!(ethernet.verify_fcs());
+
+        let mut packet = ethernet.reset();
+        strip_fcs(&mut packet).unwrap();
+        assert_eq!(UDP_PACKET.len(), packet.data_len());
+    }
+
     #[nb2::test]
     fn push_ethernet_packet() {
         let packet = Mbuf::new().unwrap();
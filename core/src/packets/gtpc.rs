@@ -0,0 +1,429 @@
+use crate::packets::ip::IpPacket;
+use crate::packets::{be16, data_slice, data_slice_mut, CondRc, Header, Packet, ParseError, Udp};
+use crate::{ensure, Result, SizeOf};
+use std::fmt;
+use std::ptr::NonNull;
+
+/*  From https://www.etsi.org/deliver/etsi_ts/129200_129299/129274/
+    GTPv2-C Header (3GPP TS 29.274, section 5.1)
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |Ver  |P|T|Spare| Message Type  |        Message Length         |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |            Tunnel Endpoint Identifier (if T = 1)              |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                Sequence Number                |     Spare     |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    Message Length  Length of the message in octets, excluding the first
+                    4 octets of the header.
+
+    GTPv1-C Header (3GPP TS 29.060, section 6)
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |Ver  |P|*|E|S|N| Message Type  |        Message Length         |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                  Tunnel Endpoint Identifier                   |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |        Sequence Number        |   N-PDU Number  |  Next Ext   |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    The last 4 octets are present if any of E, S or N flags is set.
+
+    Message Length  Length of the message in octets, excluding the first
+                    8 octets of the header.
+*/
+
+/// The well-known UDP port of GTP-C.
+pub const GTPC_PORT: u16 = 2123;
+
+const VERSION_1: u8 = 1;
+const VERSION_2: u8 = 2;
+
+// GTPv1 flags
+const V1_OPTIONALS: u8 = 0b0000_0111;
+const V1_S_FLAG: u8 = 0b0000_0010;
+
+// GTPv2 flags
+const V2_T_FLAG: u8 = 0b0000_1000;
+
+/// The fixed portion of the GTP-C header.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct GtpcHeader {
+    flags: u8,
+    msg_type: u8,
+    length: be16,
+}
+
+impl Default for GtpcHeader {
+    fn default() -> GtpcHeader {
+        // version 2 with a TEID.
+        GtpcHeader {
+            flags: (VERSION_2 << 5) | V2_T_FLAG,
+            msg_type: 0,
+            length: be16::default(),
+        }
+    }
+}
+
+impl Header for GtpcHeader {}
+
+/// GTP control plane message, version 1 or 2.
+///
+/// Only the header is parsed, enough to classify, steer and count the
+/// control traffic. The information elements in the message body are
+/// not interpreted.
+#[derive(Clone)]
+pub struct Gtpc<E: IpPacket> {
+    envelope: CondRc<Udp<E>>,
+    header: NonNull<GtpcHeader>,
+    offset: usize,
+}
+
+impl<E: IpPacket> Gtpc<E> {
+    /// Returns whether the UDP datagram is addressed to or from the GTP-C
+    /// port.
+    #[inline]
+    pub fn is_gtpc(udp: &Udp<E>) -> bool {
+        udp.dst_port() == GTPC_PORT || udp.src_port() == GTPC_PORT
+    }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.header().flags >> 5
+    }
+
+    #[inline]
+    pub fn message_type(&self) -> u8 {
+        self.header().msg_type
+    }
+
+    #[inline]
+    pub fn set_message_type(&mut self, msg_type: u8) {
+        self.header_mut().msg_type = msg_type;
+    }
+
+    #[inline]
+    pub fn length(&self) -> u16 {
+        self.header().length.get()
+    }
+
+    #[inline]
+    fn set_length(&mut self, length: u16) {
+        self.header_mut().length = length.into();
+    }
+
+    /// Returns the tunnel endpoint identifier.
+    ///
+    /// A GTPv2-C message without the T flag does not have a TEID.
+    #[inline]
+    pub fn teid(&self) -> Option<u32> {
+        if self.has_teid() {
+            let bytes = data_slice(self.mbuf(), self.offset + 4, 4);
+            Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        } else {
+            None
+        }
+    }
+
+    /// Sets the tunnel endpoint identifier.
+    ///
+    /// Does nothing if the message does not have a TEID.
+    #[inline]
+    pub fn set_teid(&mut self, teid: u32) {
+        if self.has_teid() {
+            let offset = self.offset + 4;
+            data_slice_mut(self.mbuf_mut(), offset, 4).copy_from_slice(&teid.to_be_bytes());
+            self.envelope_mut().mark_dirty();
+        }
+    }
+
+    /// Returns the sequence number.
+    ///
+    /// The sequence number is 16 bits in GTPv1-C and 24 bits in GTPv2-C.
+    /// A GTPv1-C message without the S flag does not have a sequence.
+    #[inline]
+    pub fn sequence(&self) -> Option<u32> {
+        match self.version() {
+            VERSION_1 if self.header().flags & V1_S_FLAG != 0 => {
+                let bytes = data_slice(self.mbuf(), self.offset + 8, 2);
+                Some(u32::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+            }
+            VERSION_2 => {
+                let bytes = data_slice(self.mbuf(), self.seq_offset(), 3);
+                Some(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets the sequence number.
+    ///
+    /// Only the lower bits that fit in the version's sequence field are
+    /// used. Does nothing if the message does not have a sequence.
+    #[inline]
+    pub fn set_sequence(&mut self, sequence: u32) {
+        let bytes = sequence.to_be_bytes();
+        match self.version() {
+            VERSION_1 if self.header().flags & V1_S_FLAG != 0 => {
+                let offset = self.offset + 8;
+                data_slice_mut(self.mbuf_mut(), offset, 2).copy_from_slice(&bytes[2..]);
+            }
+            VERSION_2 => {
+                let offset = self.seq_offset();
+                data_slice_mut(self.mbuf_mut(), offset, 3).copy_from_slice(&bytes[1..]);
+            }
+            _ => return,
+        }
+
+        self.envelope_mut().mark_dirty();
+    }
+
+    #[inline]
+    fn has_teid(&self) -> bool {
+        self.version() == VERSION_1 || self.header().flags & V2_T_FLAG != 0
+    }
+
+    #[inline]
+    fn seq_offset(&self) -> usize {
+        if self.has_teid() {
+            self.offset + 8
+        } else {
+            self.offset + 4
+        }
+    }
+
+    /// Returns the length of the header for the version and flags.
+    #[inline]
+    fn header_len_of(flags: u8) -> usize {
+        let has_optionals = match flags >> 5 {
+            VERSION_1 => flags & V1_OPTIONALS != 0,
+            _ => flags & V2_T_FLAG != 0,
+        };
+
+        if has_optionals {
+            12
+        } else {
+            8
+        }
+    }
+}
+
+impl<E: IpPacket> fmt::Debug for Gtpc<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("gtpc")
+            .field("version", &self.version())
+            .field("message_type", &self.message_type())
+            .field("length", &self.length())
+            .field("teid", &self.teid())
+            .field("sequence", &self.sequence())
+            .finish()
+    }
+}
+
+impl<E: IpPacket> Packet for Gtpc<E> {
+    type Envelope = Udp<E>;
+    type Header = GtpcHeader;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header(&self) -> &Self::Header {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header_mut(&mut self) -> &mut Self::Header {
+        // the udp checksum covers the message.
+        self.envelope_mut().mark_dirty();
+        unsafe { self.header.as_mut() }
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        Self::header_len_of(self.header().flags)
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn do_parse(envelope: Self::Envelope) -> Result<Self> {
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = mbuf.read_data::<GtpcHeader>(offset)?;
+
+        let flags = unsafe { header.as_ref().flags };
+        ensure!(
+            flags >> 5 == VERSION_1 || flags >> 5 == VERSION_2,
+            ParseError::new("Not a GTPv1-C or GTPv2-C message.")
+        );
+
+        // the variable portion of the header must be in the buffer too.
+        let _ = mbuf.read_data_slice::<u8>(offset, Self::header_len_of(flags))?;
+
+        Ok(Gtpc {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+        })
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn do_push(mut envelope: Self::Envelope) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let header = GtpcHeader::default();
+        let len = Self::header_len_of(header.flags);
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, len)?;
+        mbuf.write_data_slice(offset, &[0u8; 12][..len])?;
+        let header = mbuf.write_data(offset, &header)?;
+
+        Ok(Gtpc {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+        })
+    }
+
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let offset = self.offset();
+        let len = self.header_len();
+        self.mbuf_mut().shrink(offset, len)?;
+        Ok(self.envelope.into_owned())
+    }
+
+    #[inline]
+    fn reconcile(&mut self) {
+        // v1 length excludes the mandatory 8 octets, v2 only the first 4.
+        let excluded = match self.version() {
+            VERSION_1 => 8,
+            _ => GtpcHeader::size_of(),
+        };
+        let len = (self.len() - excluded) as u16;
+        if self.length() != len {
+            self.set_length(len);
+        }
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::Ethernet;
+    use crate::Mbuf;
+
+    #[rustfmt::skip]
+    const GTPV2C_ECHO_REQUEST: [u8; 55] = [
+        // ** ethernet header
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x08, 0x00,
+        // ** IPv4 header
+        0x45, 0x00, 0x00, 0x29,
+        0x00, 0x00, 0x40, 0x00,
+        0x40, 0x11, 0x00, 0x00,
+        0x0a, 0x00, 0x00, 0x01,
+        0x0a, 0x00, 0x00, 0x02,
+        // ** UDP header
+        // src_port = 2123, dst_port = 2123
+        0x08, 0x4b, 0x08, 0x4b,
+        // length = 21, checksum = 0
+        0x00, 0x15, 0x00, 0x00,
+        // ** GTPv2-C header
+        // version = 2, no TEID, echo request, length = 9
+        0x40, 0x01, 0x00, 0x09,
+        // sequence = 1, spare
+        0x00, 0x00, 0x01, 0x00,
+        // ** recovery IE
+        0x03, 0x00, 0x01, 0x00, 0x05,
+    ];
+
+    #[test]
+    fn size_of_gtpc_header() {
+        assert_eq!(4, GtpcHeader::size_of());
+    }
+
+    #[nb2::test]
+    fn parse_gtpv2c_packet() {
+        let packet = Mbuf::from_bytes(&GTPV2C_ECHO_REQUEST).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        assert!(Gtpc::is_gtpc(&udp));
+
+        let gtpc = udp.parse::<Gtpc<Ipv4>>().unwrap();
+        assert_eq!(2, gtpc.version());
+        assert_eq!(1, gtpc.message_type());
+        assert_eq!(9, gtpc.length());
+        assert_eq!(None, gtpc.teid());
+        assert_eq!(Some(1), gtpc.sequence());
+        assert_eq!(8, gtpc.header_len());
+        assert_eq!(5, gtpc.payload_len());
+    }
+
+    #[nb2::test]
+    fn mark_udp_dirty_on_edit() {
+        let packet = Mbuf::from_bytes(&GTPV2C_ECHO_REQUEST).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        let mut gtpc = udp.parse::<Gtpc<Ipv4>>().unwrap();
+        assert!(!gtpc.envelope().is_dirty());
+
+        gtpc.set_sequence(2);
+        assert!(gtpc.envelope().is_dirty());
+
+        gtpc.cascade();
+        assert!(!gtpc.envelope().is_dirty());
+        assert!(gtpc.envelope().verify_checksum());
+    }
+
+    #[nb2::test]
+    fn push_gtpc_packet() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv4 = ethernet.push::<Ipv4>().unwrap();
+        let udp = ipv4.push::<Udp<Ipv4>>().unwrap();
+        let mut gtpc = udp.push::<Gtpc<Ipv4>>().unwrap();
+
+        gtpc.set_message_type(32);
+        gtpc.set_teid(0x1234_5678);
+        gtpc.set_sequence(0xab_cdef);
+        gtpc.cascade();
+
+        assert_eq!(2, gtpc.version());
+        assert_eq!(32, gtpc.message_type());
+        assert_eq!(Some(0x1234_5678), gtpc.teid());
+        assert_eq!(Some(0xab_cdef), gtpc.sequence());
+        assert_eq!(12, gtpc.header_len());
+        assert_eq!(8, gtpc.length());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::batch::Responder;
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Message, Icmpv6Packet, Icmpv6Parse, Icmpv6Types};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::ip::ProtocolNumbers;
+use crate::packets::{
+    checksum, data_slice, data_slice_mut, EtherTypes, Ethernet, Packet, ParseError,
+};
+use crate::stats::Anomaly;
+use crate::{ensure, Mbuf, Result};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+// ICMPv4 echo message types, RFC 792.
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+
+// the type, code, checksum, identifier and sequence number.
+const ICMPV4_ECHO_LEN: usize = 8;
+
+// the TTL and hop limit of the replies.
+const REPLY_HOP_LIMIT: u8 = 64;
+
+/// Answers the ICMPv4 and ICMPv6 echo requests sent to a set of addresses,
+/// so the appliance can be pinged.
+///
+/// The request is turned into the reply in place. The MAC and IP addresses
+/// are swapped, the type is changed to echo reply and the checksums are
+/// updated incrementally, so the data is not read. The identifier,
+/// sequence number and data are echoed back unchanged.
+///
+/// The responder is cheap to clone, the clones share the same addresses.
+///
+/// # Example
+///
+/// ```
+/// let echo = EchoResponder::new(vec!["10.0.0.1".parse()?, "fe80::1".parse()?]);
+///
+/// runtime.add_pipeline_to_port("eth0", move |q| {
+///     Poll::new(q.clone())
+///         .respond(echo.clone(), q.clone())
+///         .map(forward)
+///         .send(q)
+/// })?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct EchoResponder {
+    addrs: Arc<HashSet<IpAddr>>,
+}
+
+impl EchoResponder {
+    /// Creates a new responder for echo requests sent to `addrs`.
+    pub fn new<I: IntoIterator<Item = IpAddr>>(addrs: I) -> Self {
+        EchoResponder {
+            addrs: Arc::new(addrs.into_iter().collect()),
+        }
+    }
+
+    /// Returns whether the responder answers for the address.
+    #[inline]
+    pub fn is_local(&self, addr: IpAddr) -> bool {
+        self.addrs.contains(&addr)
+    }
+
+    fn is_echo_request_v4(&self, ethernet: &Ethernet) -> bool {
+        ethernet.peek::<Ipv4>().ok().map_or(false, |ipv4| {
+            ipv4.protocol() == ProtocolNumbers::Icmpv4
+                && ipv4.fragment_offset() == 0
+                && !ipv4.more_fragments()
+                && self.is_local(ipv4.dst().into())
+                && icmpv4_len(&ipv4) >= ICMPV4_ECHO_LEN
+                && data_slice(ipv4.mbuf(), icmpv4_offset(&ipv4), ICMPV4_ECHO_LEN).first()
+                    == Some(&ICMPV4_ECHO_REQUEST)
+        })
+    }
+
+    fn is_echo_request_v6(&self, ethernet: &Ethernet) -> bool {
+        ethernet.peek::<Ipv6>().ok().map_or(false, |ipv6| {
+            self.is_local(ipv6.dst().into())
+                && match ipv6.clone().parse_icmpv6() {
+                    Ok(Icmpv6Message::EchoRequest(_)) => true,
+                    _ => false,
+                }
+        })
+    }
+
+    fn reply_v4(ethernet: Ethernet) -> Result<Mbuf> {
+        let mut ipv4 = ethernet.parse::<Ipv4>()?;
+        let offset = icmpv4_offset(&ipv4);
+        let len = icmpv4_len(&ipv4);
+        ensure!(
+            len >= ICMPV4_ECHO_LEN && data_slice(ipv4.mbuf(), offset, len).len() == len,
+            ParseError::with_anomaly(
+                Anomaly::Truncated,
+                "Packet has a truncated ICMPv4 echo request."
+            )
+        );
+
+        let src = ipv4.src();
+        let dst = ipv4.dst();
+        ipv4.set_src(dst);
+        ipv4.set_dst(src);
+        ipv4.set_ttl(REPLY_HOP_LIMIT);
+
+        let icmpv4 = data_slice_mut(ipv4.mbuf_mut(), offset, len);
+        let old = u16::from_be_bytes([icmpv4[0], icmpv4[1]]);
+        icmpv4[0] = ICMPV4_ECHO_REPLY;
+        let new = u16::from_be_bytes([icmpv4[0], icmpv4[1]]);
+
+        // only the type changes, the rest of the message is echoed back.
+        let old_checksum = u16::from_be_bytes([icmpv4[2], icmpv4[3]]);
+        let sum = checksum::compute_inc(old_checksum, &[old], &[new]).to_be_bytes();
+        icmpv4[2] = sum[0];
+        icmpv4[3] = sum[1];
+
+        ipv4.cascade();
+        let mut ethernet = ipv4.deparse();
+        ethernet.swap_addresses();
+        Ok(ethernet.reset())
+    }
+
+    fn reply_v6(ethernet: Ethernet) -> Result<Mbuf> {
+        let mut ipv6 = ethernet.parse::<Ipv6>()?;
+        let src = ipv6.src();
+        let dst = ipv6.dst();
+        ipv6.set_src(dst);
+        ipv6.set_dst(src);
+        ipv6.set_hop_limit(REPLY_HOP_LIMIT);
+
+        let mut icmpv6 = ipv6.parse::<Icmpv6<Ipv6, ()>>()?;
+        let old = u16::from_be_bytes([icmpv6.msg_type().0, icmpv6.code()]);
+        icmpv6.set_msg_type(Icmpv6Types::EchoReply);
+        icmpv6.set_code(0);
+
+        // the pseudo-header sum does not change with the addresses swapped.
+        let new = u16::from_be_bytes([Icmpv6Types::EchoReply.0, 0]);
+        icmpv6.update_checksum(&[old], &[new]);
+
+        let mut ethernet = icmpv6.deparse().deparse();
+        ethernet.swap_addresses();
+        Ok(ethernet.reset())
+    }
+}
+
+impl Responder for EchoResponder {
+    fn matches(&self, mbuf: &Mbuf) -> bool {
+        mbuf.peek::<Ethernet>()
+            .ok()
+            .map_or(false, |ethernet| match ethernet.ether_type() {
+                EtherTypes::Ipv4 => self.is_echo_request_v4(&ethernet),
+                EtherTypes::Ipv6 => self.is_echo_request_v6(&ethernet),
+                _ => false,
+            })
+    }
+
+    fn reply(&self, mbuf: Mbuf) -> Result<Mbuf> {
+        let mut ethernet = mbuf.parse::<Ethernet>()?;
+        // the reply lengths would include the padding of a short request.
+        ethernet.strip_padding()?;
+
+        match ethernet.ether_type() {
+            EtherTypes::Ipv4 => EchoResponder::reply_v4(ethernet),
+            _ => EchoResponder::reply_v6(ethernet),
+        }
+    }
+}
+
+/// Returns the offset of the ICMPv4 message, past the IPv4 options.
+#[inline]
+fn icmpv4_offset(ipv4: &Ipv4) -> usize {
+    ipv4.offset() + ipv4.ihl() as usize * 4
+}
+
+/// Returns the length of the ICMPv4 message, by the IPv4 total length.
+#[inline]
+fn icmpv4_len(ipv4: &Ipv4) -> usize {
+    (ipv4.offset() + ipv4.total_length() as usize).saturating_sub(icmpv4_offset(ipv4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::icmp::v4::ICMPV4_PACKET;
+    use crate::packets::icmp::v6::EchoRequest;
+    use crate::packets::MacAddr;
+    use std::net::Ipv6Addr;
+
+    #[nb2::test]
+    fn reply_to_icmpv4_echo() {
+        let echo = EchoResponder::new(vec!["174.137.42.77".parse().unwrap()]);
+        let packet = Mbuf::from_bytes(&ICMPV4_PACKET).unwrap();
+        assert!(echo.matches(&packet));
+
+        let reply = echo.reply(packet).unwrap();
+        let ethernet = reply.parse::<Ethernet>().unwrap();
+        assert_eq!(
+            MacAddr::new(0x00, 0x0c, 0x29, 0x34, 0x0b, 0xde),
+            ethernet.dst()
+        );
+
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert_eq!("174.137.42.77", ipv4.src().to_string());
+        assert_eq!("192.168.158.139", ipv4.dst().to_string());
+        assert!(ipv4.verify_checksum());
+
+        let offset = icmpv4_offset(&ipv4);
+        let icmpv4 = data_slice(ipv4.mbuf(), offset, ipv4.payload_len());
+        assert_eq!(ICMPV4_ECHO_REPLY, icmpv4[0]);
+        // the identifier and sequence number are echoed back.
+        assert_eq!(&ICMPV4_PACKET[38..42], &icmpv4[4..8]);
+        assert_eq!(0, checksum::compute(0, icmpv4));
+    }
+
+    #[nb2::test]
+    fn ignore_echo_to_other_address() {
+        let echo = EchoResponder::new(vec!["10.0.0.1".parse().unwrap()]);
+        let packet = Mbuf::from_bytes(&ICMPV4_PACKET).unwrap();
+        assert!(!echo.matches(&packet));
+    }
+
+    #[nb2::test]
+    fn reject_truncated_icmpv4_echo() {
+        // only the type, code and checksum, total length = 24.
+        let mut bytes = ICMPV4_PACKET[..38].to_vec();
+        bytes[17] = 0x18;
+
+        let echo = EchoResponder::new(vec!["174.137.42.77".parse().unwrap()]);
+        let packet = Mbuf::from_bytes(&bytes).unwrap();
+        assert!(!echo.matches(&packet));
+        let err = echo.reply(packet).unwrap_err();
+        assert_eq!(Some(Anomaly::Truncated), Anomaly::of(&err));
+    }
+
+    #[nb2::test]
+    fn reply_to_icmpv6_echo() {
+        let local: Ipv6Addr = "fe80::1".parse().unwrap();
+        let remote: Ipv6Addr = "fe80::2".parse().unwrap();
+
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let mut ipv6 = ethernet.push::<Ipv6>().unwrap();
+        ipv6.set_src(remote);
+        ipv6.set_dst(local);
+        let mut request = ipv6.push::<Icmpv6<Ipv6, EchoRequest>>().unwrap();
+        request.set_identifier(42);
+        request.set_seq_no(7);
+        request.cascade();
+
+        let echo = EchoResponder::new(vec![local.into()]);
+        let packet = request.reset();
+        assert!(echo.matches(&packet));
+
+        let reply = echo.reply(packet).unwrap();
+        let ethernet = reply.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        assert_eq!(local, ipv6.src());
+        assert_eq!(remote, ipv6.dst());
+
+        if let Ok(Icmpv6Message::EchoReply(reply)) = ipv6.parse_icmpv6() {
+            assert_eq!(42, reply.identifier());
+            assert_eq!(7, reply.seq_no());
+            assert!(reply.verify_checksum());
+        } else {
+            panic!("not an echo reply");
+        }
+    }
+}
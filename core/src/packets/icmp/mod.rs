@@ -1,2 +1,10 @@
 pub mod v4;
 pub mod v6;
+
+mod echo;
+mod rate_limit;
+mod time_exceeded;
+
+pub use self::echo::*;
+pub use self::rate_limit::*;
+pub use self::time_exceeded::*;
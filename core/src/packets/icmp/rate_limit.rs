@@ -0,0 +1,264 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A token bucket.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The destinations checked for eviction per new destination, so a flood
+/// of new destinations costs a bounded amount of work per message.
+const EVICT_SCAN: usize = 8;
+
+struct Buckets {
+    global: Bucket,
+    per_dst: HashMap<IpAddr, Bucket>,
+    // the destinations tracked, oldest first.
+    order: VecDeque<IpAddr>,
+}
+
+/// Rate limiter for generating ICMP and ICMPv6 error messages.
+///
+/// RFC 4443, section 2.4 (f) requires an IPv6 node to limit the rate of
+/// the ICMPv6 error messages it originates, so that a flood of offending
+/// packets does not turn the node into an amplifier. The limiter keeps a
+/// token bucket per destination, the source of the offending packet, and
+/// an overall bucket for all destinations combined. An error message is
+/// allowed only if both buckets have a token.
+///
+/// The limiter is cheap to clone. All the clones share the same buckets,
+/// so a single limiter can be handed out to the pipelines on every core.
+///
+/// # Example
+///
+/// ```
+/// let limiter = IcmpRateLimiter::new(10, 20, 1000);
+///
+/// if limiter.allow(ipv6.src().into()) {
+///     // generate the packet too big message.
+/// }
+/// ```
+#[derive(Clone)]
+pub struct IcmpRateLimiter {
+    rate: f64,
+    burst: f64,
+    global_rate: f64,
+    max_dsts: usize,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl IcmpRateLimiter {
+    /// The maximum number of destinations tracked at the same time.
+    pub const DEFAULT_MAX_DESTINATIONS: usize = 4096;
+
+    /// Creates a new limiter allowing `rate` messages per second to each
+    /// destination with bursts of up to `burst` messages, and no more than
+    /// `global_rate` messages per second overall.
+    pub fn new(rate: u32, burst: u32, global_rate: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        let global_rate = f64::from(global_rate);
+
+        IcmpRateLimiter {
+            rate: f64::from(rate),
+            burst,
+            global_rate,
+            max_dsts: Self::DEFAULT_MAX_DESTINATIONS,
+            buckets: Arc::new(Mutex::new(Buckets {
+                global: Bucket {
+                    tokens: global_rate.max(burst),
+                    updated: Instant::now(),
+                },
+                per_dst: HashMap::new(),
+                order: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Sets the maximum number of destinations tracked at the same time.
+    ///
+    /// When the limit is reached, a destination whose bucket is full is
+    /// forgotten to make room for a new one. Only a few of the oldest
+    /// destinations are checked each time, the ones still limited are
+    /// checked again later. If none can be forgotten, the message to the
+    /// new destination is denied.
+    pub fn max_destinations(mut self, max_dsts: usize) -> Self {
+        self.max_dsts = max_dsts;
+        self
+    }
+
+    /// Returns whether an error message can be sent to `dst` now, and
+    /// takes a token if so.
+    #[inline]
+    pub fn allow(&self, dst: IpAddr) -> bool {
+        self.allow_at(dst, Instant::now())
+    }
+
+    /// Returns whether an error message can be sent to `dst` at `now`,
+    /// and takes a token if so.
+    pub fn allow_at(&self, dst: IpAddr, now: Instant) -> bool {
+        let mut guard = self.buckets.lock().unwrap();
+        let buckets = &mut *guard;
+        let global_burst = self.global_rate.max(self.burst);
+
+        refill(&mut buckets.global, now, self.global_rate, global_burst);
+        if buckets.global.tokens < 1.0 {
+            return false;
+        }
+
+        let burst = self.burst;
+        if !buckets.per_dst.contains_key(&dst) {
+            if buckets.per_dst.len() >= self.max_dsts && !self.evict(buckets, now) {
+                return false;
+            }
+
+            buckets.order.push_back(dst);
+            buckets.per_dst.insert(
+                dst,
+                Bucket {
+                    tokens: burst,
+                    updated: now,
+                },
+            );
+        }
+
+        let bucket = buckets.per_dst.get_mut(&dst).unwrap();
+        refill(bucket, now, self.rate, burst);
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+
+        bucket.tokens -= 1.0;
+        buckets.global.tokens -= 1.0;
+        true
+    }
+
+    /// Forgets one of the oldest destinations whose bucket has refilled
+    /// completely, returning whether one was forgotten.
+    fn evict(&self, buckets: &mut Buckets, now: Instant) -> bool {
+        for _ in 0..EVICT_SCAN.min(buckets.order.len()) {
+            let dst = buckets.order.pop_front().unwrap();
+            let bucket = buckets.per_dst.get_mut(&dst).unwrap();
+            refill(bucket, now, self.rate, self.burst);
+
+            if bucket.tokens >= self.burst {
+                buckets.per_dst.remove(&dst);
+                return true;
+            }
+
+            // still limited, checked again after the others.
+            buckets.order.push_back(dst);
+        }
+
+        false
+    }
+}
+
+/// Adds the tokens accumulated since the last update.
+#[inline]
+fn refill(bucket: &mut Bucket, now: Instant, rate: f64, burst: f64) {
+    if now > bucket.updated {
+        let elapsed = now.duration_since(bucket.updated);
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        bucket.tokens = (bucket.tokens + secs * rate).min(burst);
+        bucket.updated = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv6Addr;
+    use std::time::Duration;
+
+    fn addr(last: u16) -> IpAddr {
+        IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last))
+    }
+
+    #[test]
+    fn limit_per_destination() {
+        let limiter = IcmpRateLimiter::new(1, 2, 1000);
+        let now = Instant::now();
+
+        assert!(limiter.allow_at(addr(1), now));
+        assert!(limiter.allow_at(addr(1), now));
+        assert!(!limiter.allow_at(addr(1), now));
+
+        // other destinations have their own bucket.
+        assert!(limiter.allow_at(addr(2), now));
+
+        // one token is added back every second.
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.allow_at(addr(1), later));
+        assert!(!limiter.allow_at(addr(1), later));
+    }
+
+    #[test]
+    fn limit_overall() {
+        let limiter = IcmpRateLimiter::new(10, 10, 2);
+        let now = Instant::now();
+
+        // the overall bucket holds at least a burst worth of tokens.
+        for last in 1..=10 {
+            assert!(limiter.allow_at(addr(last), now));
+        }
+        assert!(!limiter.allow_at(addr(11), now));
+
+        // but refills at the overall rate.
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.allow_at(addr(11), later));
+        assert!(limiter.allow_at(addr(12), later));
+        assert!(!limiter.allow_at(addr(13), later));
+    }
+
+    #[test]
+    fn shared_between_clones() {
+        let limiter = IcmpRateLimiter::new(1, 1, 1000);
+        let clone = limiter.clone();
+        let now = Instant::now();
+
+        assert!(limiter.allow_at(addr(1), now));
+        assert!(!clone.allow_at(addr(1), now));
+    }
+
+    #[test]
+    fn evict_full_buckets() {
+        let limiter = IcmpRateLimiter::new(1, 1, 1000).max_destinations(1);
+        let now = Instant::now();
+
+        assert!(limiter.allow_at(addr(1), now));
+        // the first bucket is still empty and cannot be evicted.
+        assert!(!limiter.allow_at(addr(2), now));
+
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.allow_at(addr(2), later));
+    }
+
+    #[test]
+    fn evict_a_few_at_a_time() {
+        let scan = EVICT_SCAN as u16;
+        let limiter = IcmpRateLimiter::new(1, 1, 1000).max_destinations(EVICT_SCAN * 2);
+        let now = Instant::now();
+
+        // the oldest destinations are the last to refill.
+        for last in 0..scan {
+            assert!(limiter.allow_at(addr(last), now + Duration::from_millis(500)));
+        }
+        for last in scan..scan * 2 {
+            assert!(limiter.allow_at(addr(last), now));
+        }
+
+        // only the oldest, still limited, are checked the first time.
+        let later = now + Duration::from_millis(1200);
+        assert!(!limiter.allow_at(addr(100), later));
+        assert!(limiter.allow_at(addr(100), later));
+        assert_eq!(
+            EVICT_SCAN * 2,
+            limiter.buckets.lock().unwrap().per_dst.len()
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use super::IcmpRateLimiter;
+use crate::batch::Responder;
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Types};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::{Ipv6, Ipv6Packet, IPV6_MIN_MTU};
+use crate::packets::ip::ProtocolNumbers;
+use crate::packets::{
+    checksum, data_slice, data_slice_mut, EtherType, EtherTypes, Ethernet, Packet,
+};
+use crate::{Mbuf, Result};
+use failure::Fail;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// ICMPv4 time exceeded type, and the error types never answered with
+// another error, RFC 1812 section 4.3.2.7.
+const ICMPV4_TIME_EXCEEDED: u8 = 11;
+const ICMPV4_ERRORS: [u8; 5] = [3, 4, 5, 11, 12];
+
+// RFC 1812 limits the ICMPv4 error messages to 576 bytes.
+const ICMPV4_MAX_LEN: usize = 576;
+
+// the IP header and the ICMP type, code, checksum and unused fields.
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const ICMP_HEADER_LEN: usize = 8;
+
+// the TTL and hop limit of the error messages.
+const ERROR_HOP_LIMIT: u8 = 64;
+
+/// Error indicating the time exceeded message was not sent, because of the
+/// rate limit. The expired packet is dropped anyway.
+#[derive(Debug, Fail)]
+#[fail(display = "Time exceeded message to {} rate limited.", _0)]
+pub struct TimeExceededRateLimited(pub IpAddr);
+
+/// Error indicating the packet is neither IPv4 nor IPv6, or the responder
+/// has no address of its family to source the time exceeded message from.
+#[derive(Debug, Fail)]
+#[fail(display = "No time exceeded source address for {}.", _0)]
+pub struct TimeExceededNoSource(pub EtherType);
+
+/// Answers the packets that expire in transit with an ICMPv4 or ICMPv6
+/// time exceeded message, so the router shows up in traceroute.
+///
+/// A packet matches when its TTL or hop limit is `1` or less, meaning it
+/// would expire on this hop. The expired packet is replaced by the error
+/// message, which quotes as much of the original packet as fits in 576
+/// bytes for ICMPv4, RFC 1812, and in the minimum IPv6 MTU for ICMPv6,
+/// RFC 4443. The message is sourced from the first address of the family
+/// given to the responder. A family without an address is not answered.
+///
+/// No error is sent for non-first fragments, multicast or broadcast
+/// destinations, unspecified or multicast sources, and ICMP error
+/// messages. The messages are rate limited per source of the expired
+/// packets, and an expired packet over the limit is dropped.
+///
+/// Place the responder before the TTL is decremented, and after the
+/// packets addressed to the router itself are delivered.
+///
+/// # Example
+///
+/// ```
+/// let limiter = IcmpRateLimiter::new(10, 20, 1000);
+/// let expired = TimeExceededResponder::new(vec!["10.0.0.1".parse()?], limiter);
+///
+/// runtime.add_pipeline_to_port("eth0", move |q| {
+///     Poll::new(q.clone())
+///         .respond(expired.clone(), q.clone())
+///         .map(forward)
+///         .send(q)
+/// })?;
+/// ```
+#[derive(Clone)]
+pub struct TimeExceededResponder {
+    src_v4: Option<Ipv4Addr>,
+    src_v6: Option<Ipv6Addr>,
+    limiter: IcmpRateLimiter,
+}
+
+impl TimeExceededResponder {
+    /// Creates a new responder that sources the messages from `addrs`, and
+    /// limits their rate with `limiter`.
+    pub fn new<I: IntoIterator<Item = IpAddr>>(addrs: I, limiter: IcmpRateLimiter) -> Self {
+        let mut src_v4 = None;
+        let mut src_v6 = None;
+        for addr in addrs {
+            match addr {
+                IpAddr::V4(addr) => src_v4 = src_v4.or(Some(addr)),
+                IpAddr::V6(addr) => src_v6 = src_v6.or(Some(addr)),
+            }
+        }
+
+        TimeExceededResponder {
+            src_v4,
+            src_v6,
+            limiter,
+        }
+    }
+
+    fn is_expiring_v4(&self, ethernet: &Ethernet) -> bool {
+        self.src_v4.is_some()
+            && ethernet.peek::<Ipv4>().ok().map_or(false, |ipv4| {
+                let src = ipv4.src();
+                let dst = ipv4.dst();
+
+                ipv4.ttl() <= 1
+                    && ipv4.fragment_offset() == 0
+                    && !(dst.is_multicast() || dst.is_broadcast())
+                    && !(src.is_unspecified() || src.is_multicast())
+                    && (ipv4.protocol() != ProtocolNumbers::Icmpv4
+                        || data_slice(ipv4.mbuf(), ipv4.payload_offset(), 1)
+                            .first()
+                            .map_or(false, |t| !ICMPV4_ERRORS.contains(t)))
+            })
+    }
+
+    fn is_expiring_v6(&self, ethernet: &Ethernet) -> bool {
+        self.src_v6.is_some()
+            && ethernet.peek::<Ipv6>().ok().map_or(false, |ipv6| {
+                let src = ipv6.src();
+
+                ipv6.hop_limit() <= 1
+                    && !ipv6.dst().is_multicast()
+                    && !(src.is_unspecified() || src.is_multicast())
+                    // ICMPv6 error messages have the types below 128.
+                    && (ipv6.next_header() != ProtocolNumbers::Icmpv6
+                        || data_slice(ipv6.mbuf(), ipv6.payload_offset(), 1)
+                            .first()
+                            .map_or(false, |t| *t >= 128))
+            })
+    }
+
+    fn reply_v4(&self, ethernet: Ethernet, src: Ipv4Addr) -> Result<Mbuf> {
+        let ipv4 = ethernet.parse::<Ipv4>()?;
+        let dst = ipv4.src();
+        if !self.limiter.allow(dst.into()) {
+            return Err(TimeExceededRateLimited(dst.into()).into());
+        }
+
+        let quote_len = ipv4
+            .len()
+            .min(ICMPV4_MAX_LEN - IPV4_HEADER_LEN - ICMP_HEADER_LEN);
+        let quote = data_slice(ipv4.mbuf(), ipv4.offset(), quote_len).to_vec();
+        let mut ethernet = ipv4.deparse();
+        ethernet.swap_addresses();
+
+        let mut reply = Mbuf::new()?.push::<Ethernet>()?;
+        reply.set_src(ethernet.src());
+        reply.set_dst(ethernet.dst());
+
+        let mut reply = reply.push::<Ipv4>()?;
+        reply.set_src(src);
+        reply.set_dst(dst);
+        reply.set_ttl(ERROR_HOP_LIMIT);
+        reply.set_protocol(ProtocolNumbers::Icmpv4);
+
+        let offset = reply.payload_offset();
+        let len = ICMP_HEADER_LEN + quote.len();
+        reply.mbuf_mut().extend(offset, len)?;
+        let icmpv4 = data_slice_mut(reply.mbuf_mut(), offset, len);
+        icmpv4[0] = ICMPV4_TIME_EXCEEDED;
+        icmpv4[ICMP_HEADER_LEN..].copy_from_slice(&quote);
+        let sum = checksum::compute(0, icmpv4).to_be_bytes();
+        icmpv4[2] = sum[0];
+        icmpv4[3] = sum[1];
+
+        reply.cascade();
+        Ok(reply.reset())
+    }
+
+    fn reply_v6(&self, ethernet: Ethernet, src: Ipv6Addr) -> Result<Mbuf> {
+        let ipv6 = ethernet.parse::<Ipv6>()?;
+        let dst = ipv6.src();
+        if !self.limiter.allow(dst.into()) {
+            return Err(TimeExceededRateLimited(dst.into()).into());
+        }
+
+        let quote_len = ipv6
+            .len()
+            .min(IPV6_MIN_MTU - IPV6_HEADER_LEN - ICMP_HEADER_LEN);
+        let quote = data_slice(ipv6.mbuf(), ipv6.offset(), quote_len).to_vec();
+        let mut ethernet = ipv6.deparse();
+        ethernet.swap_addresses();
+
+        let mut reply = Mbuf::new()?.push::<Ethernet>()?;
+        reply.set_src(ethernet.src());
+        reply.set_dst(ethernet.dst());
+
+        let mut reply = reply.push::<Ipv6>()?;
+        reply.set_src(src);
+        reply.set_dst(dst);
+        reply.set_hop_limit(ERROR_HOP_LIMIT);
+        reply.set_next_header(ProtocolNumbers::Icmpv6);
+
+        let offset = reply.payload_offset();
+        let len = ICMP_HEADER_LEN + quote.len();
+        reply.mbuf_mut().extend(offset, len)?;
+        let icmpv6 = data_slice_mut(reply.mbuf_mut(), offset, len);
+        icmpv6[0] = Icmpv6Types::TimeExceeded.0;
+        icmpv6[ICMP_HEADER_LEN..].copy_from_slice(&quote);
+
+        // sets the payload length before the checksum is computed over the
+        // pseudo-header.
+        reply.cascade();
+        let mut icmpv6 = reply.parse::<Icmpv6<Ipv6, ()>>()?;
+        icmpv6.cascade();
+        Ok(icmpv6.reset())
+    }
+}
+
+impl Responder for TimeExceededResponder {
+    fn matches(&self, mbuf: &Mbuf) -> bool {
+        mbuf.peek::<Ethernet>()
+            .ok()
+            .map_or(false, |ethernet| match ethernet.ether_type() {
+                EtherTypes::Ipv4 => self.is_expiring_v4(&ethernet),
+                EtherTypes::Ipv6 => self.is_expiring_v6(&ethernet),
+                _ => false,
+            })
+    }
+
+    fn reply(&self, mbuf: Mbuf) -> Result<Mbuf> {
+        let mut ethernet = mbuf.parse::<Ethernet>()?;
+        // the padding is not part of the quoted packet.
+        ethernet.strip_padding()?;
+
+        // `reply` may be called on a packet that did not match.
+        match (ethernet.ether_type(), self.src_v4, self.src_v6) {
+            (EtherTypes::Ipv4, Some(src), _) => self.reply_v4(ethernet, src),
+            (EtherTypes::Ipv6, _, Some(src)) => self.reply_v6(ethernet, src),
+            (ether_type, _, _) => Err(TimeExceededNoSource(ether_type).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v6::IPV6_PACKET;
+    use crate::packets::UDP_PACKET;
+    use crate::testils::PacketExt;
+
+    fn expiring_v4() -> Mbuf {
+        let mut ipv4 = Mbuf::from_bytes(&UDP_PACKET).unwrap().into_v4();
+        ipv4.set_ttl(1);
+        ipv4.cascade();
+        ipv4.reset()
+    }
+
+    #[nb2::test]
+    fn reply_to_expiring_ipv4() {
+        let responder = TimeExceededResponder::new(
+            vec!["10.0.0.1".parse().unwrap()],
+            IcmpRateLimiter::new(10, 10, 1000),
+        );
+        let packet = expiring_v4();
+        assert!(responder.matches(&packet));
+        let original = packet.clone().into_v4();
+        let src = original.src();
+        let quoted = data_slice(original.mbuf(), original.offset(), original.len()).to_vec();
+
+        let reply = responder.reply(packet).unwrap().into_v4();
+        assert_eq!("10.0.0.1", reply.src().to_string());
+        assert_eq!(src, reply.dst());
+        assert_eq!(ProtocolNumbers::Icmpv4, reply.protocol());
+        assert!(reply.verify_checksum());
+
+        let icmpv4 = data_slice(reply.mbuf(), reply.payload_offset(), reply.payload_len());
+        assert_eq!(ICMPV4_TIME_EXCEEDED, icmpv4[0]);
+        assert_eq!(&quoted[..], &icmpv4[ICMP_HEADER_LEN..]);
+        assert_eq!(0, checksum::compute(0, icmpv4));
+    }
+
+    #[nb2::test]
+    fn ignore_packets_not_expiring() {
+        let responder = TimeExceededResponder::new(
+            vec!["10.0.0.1".parse().unwrap()],
+            IcmpRateLimiter::new(10, 10, 1000),
+        );
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        assert!(!responder.matches(&packet));
+
+        // no IPv6 source address to answer from.
+        let mut ipv6 = Mbuf::from_bytes(&IPV6_PACKET).unwrap().into_v6();
+        ipv6.set_hop_limit(1);
+        let packet = ipv6.reset();
+        assert!(!responder.matches(&packet));
+        assert!(responder.reply(packet).is_err());
+
+        // not an IP packet.
+        let mut ethernet = Mbuf::from_bytes(&UDP_PACKET)
+            .unwrap()
+            .parse::<Ethernet>()
+            .unwrap();
+        ethernet.set_ether_type(EtherTypes::Arp);
+        assert!(responder.reply(ethernet.reset()).is_err());
+    }
+
+    #[nb2::test]
+    fn rate_limit_replies() {
+        let responder = TimeExceededResponder::new(
+            vec!["10.0.0.1".parse().unwrap()],
+            IcmpRateLimiter::new(1, 1, 1000),
+        );
+
+        assert!(responder.reply(expiring_v4()).is_ok());
+        assert!(responder.reply(expiring_v4()).is_err());
+    }
+
+    #[nb2::test]
+    fn reply_to_expiring_ipv6() {
+        let responder = TimeExceededResponder::new(
+            vec!["2001:db8::1".parse().unwrap()],
+            IcmpRateLimiter::new(10, 10, 1000),
+        );
+        let mut ipv6 = Mbuf::from_bytes(&IPV6_PACKET).unwrap().into_v6();
+        ipv6.set_hop_limit(1);
+        let src = ipv6.src();
+        let packet = ipv6.reset();
+        assert!(responder.matches(&packet));
+
+        let reply = responder.reply(packet).unwrap().into_v6();
+        assert_eq!("2001:db8::1", reply.src().to_string());
+        assert_eq!(src, reply.dst());
+
+        let icmpv6 = reply.parse::<Icmpv6<Ipv6, ()>>().unwrap();
+        assert_eq!(Icmpv6Types::TimeExceeded, icmpv6.msg_type());
+        assert_eq!(IPV6_PACKET.len() - 14, icmpv6.len() - ICMP_HEADER_LEN);
+        assert!(icmpv6.verify_checksum());
+    }
+}
@@ -1,7 +1,6 @@
 use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Payload, Icmpv6Type, Icmpv6Types};
 use crate::packets::ip::v6::Ipv6Packet;
-use crate::packets::Packet;
-use crate::{Result, SizeOf};
+use crate::packets::{be16, Packet};
 use std::fmt;
 
 /*  From https://tools.ietf.org/html/rfc4443#section-4.1
@@ -31,8 +30,8 @@ use std::fmt;
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C, packed)]
 pub struct EchoRequest {
-    identifier: u16,
-    seq_no: u16,
+    identifier: be16,
+    seq_no: be16,
 }
 
 impl Icmpv6Payload for EchoRequest {
@@ -44,56 +43,24 @@ impl Icmpv6Payload for EchoRequest {
 impl<E: Ipv6Packet> Icmpv6<E, EchoRequest> {
     #[inline]
     pub fn identifier(&self) -> u16 {
-        u16::from_be(self.payload().identifier)
+        self.payload().identifier.get()
     }
 
+    /// Sets the identifier, and updates the checksum incrementally.
     #[inline]
     pub fn set_identifier(&mut self, identifier: u16) {
-        self.payload_mut().identifier = u16::to_be(identifier);
+        self.update_payload(|payload| payload.identifier = identifier.into());
     }
 
     #[inline]
     pub fn seq_no(&self) -> u16 {
-        u16::from_be(self.payload().seq_no)
+        self.payload().seq_no.get()
     }
 
+    /// Sets the sequence number, and updates the checksum incrementally.
     #[inline]
     pub fn set_seq_no(&mut self, seq_no: u16) {
-        self.payload_mut().seq_no = u16::to_be(seq_no);
-    }
-
-    /// Returns the offset where the data field in the message body starts
-    #[inline]
-    fn data_offset(&self) -> usize {
-        self.payload_offset() + EchoRequest::size_of()
-    }
-
-    /// Returns the length of the data field in the message body
-    #[inline]
-    fn data_len(&self) -> usize {
-        self.payload_len() - EchoRequest::size_of()
-    }
-
-    #[inline]
-    pub fn data(&self) -> &[u8] {
-        if let Ok(data) = self
-            .mbuf()
-            .read_data_slice(self.data_offset(), self.data_len())
-        {
-            // TODO: fix this unowned reference
-            unsafe { &*data.as_ptr() }
-        } else {
-            unreachable!()
-        }
-    }
-
-    #[inline]
-    pub fn set_data(&mut self, data: &[u8]) -> Result<()> {
-        let offset = self.data_offset();
-        let len = data.len() as isize - self.data_len() as isize;
-        self.mbuf_mut().resize(offset, len)?;
-        self.mbuf_mut().write_data_slice(offset, data)?;
-        Ok(())
+        self.update_payload(|payload| payload.seq_no = seq_no.into());
     }
 }
 
@@ -115,9 +82,29 @@ impl<E: Ipv6Packet> fmt::Debug for Icmpv6<E, EchoRequest> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::Ethernet;
+    use crate::{Mbuf, SizeOf};
 
     #[test]
     fn size_of_echo_request() {
         assert_eq!(4, EchoRequest::size_of());
     }
+
+    #[nb2::test]
+    fn update_checksum_on_set() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let ipv6 = ethernet.push::<Ipv6>().unwrap();
+        let mut request = ipv6.push::<Icmpv6<Ipv6, EchoRequest>>().unwrap();
+        request.set_data(&[1, 2, 3]).unwrap();
+        request.cascade();
+
+        request.set_identifier(42);
+        request.set_seq_no(7);
+        assert!(request.verify_checksum());
+
+        request.set_data(&[4, 5, 6, 7, 8]).unwrap();
+        assert!(request.verify_checksum());
+    }
 }
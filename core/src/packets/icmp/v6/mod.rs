@@ -10,7 +10,10 @@ pub use self::too_big::*;
 
 use crate::packets::ip::v6::Ipv6Packet;
 use crate::packets::ip::ProtocolNumbers;
-use crate::packets::{checksum, CondRc, Header, Packet, ParseError};
+use crate::packets::{
+    be16, checksum, data_slice, data_slice_mut, replace_data_slice, CondRc, Header, Packet,
+    ParseError,
+};
 use crate::{Result, SizeOf};
 use std::fmt;
 use std::ptr::NonNull;
@@ -55,6 +58,7 @@ pub mod Icmpv6Types {
     use super::Icmpv6Type;
 
     pub const PacketTooBig: Icmpv6Type = Icmpv6Type(2);
+    pub const TimeExceeded: Icmpv6Type = Icmpv6Type(3);
     pub const EchoRequest: Icmpv6Type = Icmpv6Type(128);
     pub const EchoReply: Icmpv6Type = Icmpv6Type(129);
 
@@ -73,6 +77,7 @@ impl fmt::Display for Icmpv6Type {
             "{}",
             match *self {
                 Icmpv6Types::PacketTooBig => "Packet Too Big".to_string(),
+                Icmpv6Types::TimeExceeded => "Time Exceeded".to_string(),
                 Icmpv6Types::EchoRequest => "Echo Request".to_string(),
                 Icmpv6Types::EchoReply => "Echo Reply".to_string(),
                 Icmpv6Types::RouterSolicitation => "Router Solicitation".to_string(),
@@ -92,7 +97,7 @@ impl fmt::Display for Icmpv6Type {
 pub struct Icmpv6Header {
     msg_type: u8,
     code: u8,
-    checksum: u16,
+    checksum: be16,
 }
 
 impl Header for Icmpv6Header {}
@@ -108,6 +113,9 @@ pub trait Icmpv6Payload: Clone + Default + SizeOf {
 }
 
 /// ICMPv6 unit payload `()`.
+///
+/// The unit payload makes no assumption about the message body, so any
+/// message parses with it regardless of its type and code.
 impl Icmpv6Payload for () {
     fn msg_type() -> Icmpv6Type {
         // Unit payload does not have a type
@@ -123,6 +131,10 @@ pub trait Icmpv6Packet<E: Ipv6Packet, P: Icmpv6Payload>:
     fn payload(&self) -> &P;
 
     /// Returns a mutable reference to the fixed payload
+    ///
+    /// The changes made through the reference are not tracked, so the
+    /// checksum is recomputed over the whole message on `cascade`. The
+    /// setters of the payloads update the checksum incrementally instead.
     fn payload_mut(&mut self) -> &mut P;
 
     #[inline]
@@ -135,19 +147,37 @@ pub trait Icmpv6Packet<E: Ipv6Packet, P: Icmpv6Payload>:
         self.header().code
     }
 
+    /// Sets the code, and updates the checksum incrementally.
     #[inline]
     fn set_code(&mut self, code: u8) {
-        self.header_mut().code = code
+        let old = u16::from_be_bytes([self.header().msg_type, self.code()]);
+        self.header_mut().code = code;
+        let new = u16::from_be_bytes([self.header().msg_type, code]);
+        self.update_checksum(&[old], &[new]);
     }
 
     #[inline]
     fn checksum(&self) -> u16 {
-        u16::from_be(self.header().checksum)
+        self.header().checksum.get()
+    }
+
+    /// Returns whether the checksum is valid.
+    ///
+    /// ICMPv6 checksums are not validated by ethernet devices, so the
+    /// checksum is always verified in software.
+    #[inline]
+    fn verify_checksum(&self) -> bool {
+        let data = data_slice(self.mbuf(), self.offset(), self.len());
+        let pseudo_header_sum = self
+            .envelope()
+            .pseudo_header(data.len() as u16, ProtocolNumbers::Icmpv6)
+            .sum();
+        checksum::compute(pseudo_header_sum, data) == 0
     }
 
     #[inline]
     fn compute_checksum(&mut self) {
-        self.header_mut().checksum = 0;
+        self.header_mut().checksum = be16::default();
 
         if let Ok(data) = self.mbuf().read_data_slice(self.offset(), self.len()) {
             let data = unsafe { data.as_ref() };
@@ -156,12 +186,23 @@ pub trait Icmpv6Packet<E: Ipv6Packet, P: Icmpv6Payload>:
                 .pseudo_header(data.len() as u16, ProtocolNumbers::Icmpv6)
                 .sum();
             let checksum = checksum::compute(pseudo_header_sum, data);
-            self.header_mut().checksum = u16::to_be(checksum);
+            self.header_mut().checksum = checksum.into();
         } else {
             // we are reading till the end of buffer, should never run out
             unreachable!()
         }
     }
+
+    /// Updates the checksum incrementally for a change of 16-bit words of
+    /// the message, or of the pseudo-header, from `old` to `new`.
+    ///
+    /// The checksum stays valid without recomputing it over the whole
+    /// message on `cascade`, as long as it was valid before the change.
+    #[inline]
+    fn update_checksum(&mut self, old: &[u16], new: &[u16]) {
+        let checksum = checksum::compute_inc(self.checksum(), old, new);
+        self.header_mut().checksum = checksum.into();
+    }
 }
 
 /// ICMPv6 packet.
@@ -171,11 +212,113 @@ pub struct Icmpv6<E: Ipv6Packet, P: Icmpv6Payload> {
     header: NonNull<Icmpv6Header>,
     payload: NonNull<P>,
     offset: usize,
+    // whether the message changed without the checksum being updated.
+    dirty: bool,
+    // the sum of the addresses of the pseudo-header, as of the last time
+    // the checksum was updated.
+    addrs_sum: u16,
 }
 
+/// Returns the sum of the addresses and the next header of the
+/// pseudo-header, without the length.
+///
+/// The length is updated with the message, so the sum only changes when
+/// the addresses of the envelope are rewritten.
+#[inline]
+fn addrs_sum<E: Ipv6Packet>(envelope: &E) -> u16 {
+    envelope.pseudo_header(0, ProtocolNumbers::Icmpv6).sum()
+}
+
+/// The message body following the fixed payload.
+///
+/// `payload` refers to the fixed portion of the message body. The bytes
+/// that follow it, such as the data of an echo request or the invoking
+/// packet of an error message, are accessed as `data`.
+impl<E: Ipv6Packet, P: Icmpv6Payload> Icmpv6<E, P> {
+    /// Modifies the fixed payload with `f`, and updates the checksum
+    /// incrementally for the change.
+    ///
+    /// The payloads are an even number of bytes after the header, so the
+    /// sums line up with the words of the full checksum.
+    #[inline]
+    pub(crate) fn update_payload<F: FnOnce(&mut P)>(&mut self, f: F) {
+        let offset = self.payload_offset();
+        let old = checksum::sum(data_slice(self.mbuf(), offset, P::size_of()));
+        f(unsafe { self.payload.as_mut() });
+        let new = checksum::sum(data_slice(self.mbuf(), offset, P::size_of()));
+        self.update_checksum(&[old], &[new]);
+    }
+
+    /// Recomputes the checksum over the whole message.
+    #[inline]
+    fn refresh_checksum(&mut self) {
+        self.compute_checksum();
+        self.addrs_sum = addrs_sum(self.envelope());
+        self.dirty = false;
+    }
+
+    /// Returns the offset where the data in the message body starts.
+    #[inline]
+    fn data_offset(&self) -> usize {
+        self.payload_offset() + P::size_of()
+    }
+
+    /// Returns the length of the data in the message body.
+    #[inline]
+    fn data_len(&self) -> usize {
+        self.payload_len().saturating_sub(P::size_of())
+    }
+
+    /// Returns the data that follows the fixed payload.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        data_slice(self.mbuf(), self.data_offset(), self.data_len())
+    }
+
+    /// Returns the data that follows the fixed payload as a mutable slice.
+    ///
+    /// The changes are not tracked, so the checksum is recomputed over the
+    /// whole message on `cascade`.
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.dirty = true;
+        let offset = self.data_offset();
+        let len = self.data_len();
+        data_slice_mut(self.mbuf_mut(), offset, len)
+    }
+
+    /// Replaces the data that follows the fixed payload.
+    ///
+    /// The buffer is resized to fit the new data, and the checksum is
+    /// updated incrementally for the new data and message length.
+    #[inline]
+    pub fn set_data(&mut self, data: &[u8]) -> Result<()> {
+        let offset = self.data_offset();
+        let len = self.data_len();
+        let old_sum = checksum::sum(data_slice(self.mbuf(), offset, len));
+        let old_len = self.len() as u16;
+
+        replace_data_slice(self.mbuf_mut(), offset, len, data)?;
+
+        // the data is at the end of the message, so an odd trailing byte
+        // is padded the same way in the full checksum.
+        let new_sum = checksum::sum(data);
+        let new_len = self.len() as u16;
+        self.update_checksum(&[old_sum, old_len], &[new_sum, new_len]);
+        Ok(())
+    }
+}
+
+/// ICMPv6 packet with an unknown payload.
+///
+/// An alias of the unit payload `()`, for the messages whose type is not
+/// modeled by nb2 or is not known yet.
+pub type Unknown = ();
+
 /// ICMPv6 packet with unit payload.
 ///
-/// Use unit payload `()` when the payload type is not known yet.
+/// Use unit payload `()`, or its alias `Unknown`, when the payload type is
+/// not known yet.
 ///
 /// # Example
 ///
@@ -185,6 +328,38 @@ pub struct Icmpv6<E: Ipv6Packet, P: Icmpv6Payload> {
 /// }
 /// ```
 impl<E: Ipv6Packet> Icmpv6<E, ()> {
+    /// Returns the raw message body, everything after the type, code and
+    /// checksum fields.
+    #[inline]
+    pub fn body(&self) -> &[u8] {
+        self.data()
+    }
+
+    /// Returns the raw message body as a mutable slice.
+    #[inline]
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        self.data_mut()
+    }
+
+    /// Sets the message type, and updates the checksum incrementally.
+    ///
+    /// The message body is not changed, so the caller is responsible for
+    /// making it match the new type.
+    #[inline]
+    pub fn set_msg_type(&mut self, msg_type: Icmpv6Type) {
+        let old = u16::from_be_bytes([self.header().msg_type, self.code()]);
+        self.header_mut().msg_type = msg_type.0;
+        let new = u16::from_be_bytes([msg_type.0, self.code()]);
+        self.update_checksum(&[old], &[new]);
+    }
+
+    /// Returns whether the message body is long enough for the fixed
+    /// portion of the typed payload `P`.
+    #[inline]
+    pub fn fits<P: Icmpv6Payload>(&self) -> bool {
+        self.payload_len() >= P::size_of()
+    }
+
     /// Downcasts from unit payload to typed payload
     ///
     /// # Example
@@ -218,6 +393,7 @@ impl<E: Ipv6Packet, P: Icmpv6Payload> Icmpv6Packet<E, P> for Icmpv6<E, P> {
     }
 
     fn payload_mut(&mut self) -> &mut P {
+        self.dirty = true;
         unsafe { self.payload.as_mut() }
     }
 }
@@ -260,12 +436,17 @@ impl<E: Ipv6Packet, P: Icmpv6Payload> Packet for Icmpv6<E, P> {
         let offset = envelope.payload_offset();
         let header = mbuf.read_data(offset)?;
         let payload = mbuf.read_data(offset + Self::Header::size_of())?;
+        let addrs_sum = addrs_sum(&envelope);
+        // the addresses changed but not cascaded are not in the checksum.
+        let dirty = envelope.is_dirty();
 
         Ok(Icmpv6 {
             envelope: CondRc::new(envelope),
             header,
             payload,
             offset,
+            dirty,
+            addrs_sum,
         })
     }
 
@@ -284,6 +465,8 @@ impl<E: Ipv6Packet, P: Icmpv6Payload> Packet for Icmpv6<E, P> {
             header,
             payload,
             offset,
+            dirty: true,
+            addrs_sum: 0,
         };
 
         packet.header_mut().msg_type = P::msg_type().0;
@@ -303,9 +486,33 @@ impl<E: Ipv6Packet, P: Icmpv6Payload> Packet for Icmpv6<E, P> {
     }
 
     #[inline]
-    default fn cascade(&mut self) {
-        self.compute_checksum();
-        self.envelope_mut().cascade();
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// The setters update the checksum incrementally as they go, so only
+    /// a rewrite of the addresses of the envelope is left to fold into the
+    /// checksum. The checksum is recomputed over the whole message only
+    /// after the changes that are not tracked, such as through
+    /// `payload_mut`, `data_mut`, or an NDP option edited outside of
+    /// `update_option` and marked with `mark_dirty`.
+    #[inline]
+    default fn reconcile(&mut self) {
+        if self.dirty {
+            self.refresh_checksum();
+        } else {
+            let old = self.addrs_sum;
+            let new = addrs_sum(self.envelope());
+            if old != new {
+                self.update_checksum(&[old], &[new]);
+                self.addrs_sum = new;
+            }
+        }
     }
 
     #[inline]
@@ -322,8 +529,9 @@ pub enum Icmpv6Message<E: Ipv6Packet> {
     NeighborSolicitation(Icmpv6<E, NeighborSolicitation>),
     RouterAdvertisement(Icmpv6<E, RouterAdvertisement>),
     RouterSolicitation(Icmpv6<E, RouterSolicitation>),
-    /// an ICMPv6 message with undefined payload
-    Undefined(Icmpv6<E, ()>),
+    /// an ICMPv6 message with a type nb2 does not model, or with a body
+    /// too short for its type
+    Undefined(Icmpv6<E, Unknown>),
 }
 
 /// ICMPv6 helper functions for IPv6 packets
@@ -347,6 +555,22 @@ pub trait Icmpv6Parse {
     fn parse_icmpv6(self) -> Result<Icmpv6Message<Self::Envelope>>;
 }
 
+/// Downcasts the message to the payload of its type, or returns it as
+/// undefined if the body is too short for the type.
+///
+/// A known type with a truncated body is not an error, so the packet can
+/// still be counted or forwarded.
+fn downcast<E: Ipv6Packet, P: Icmpv6Payload>(
+    icmpv6: Icmpv6<E, ()>,
+    message: fn(Icmpv6<E, P>) -> Icmpv6Message<E>,
+) -> Result<Icmpv6Message<E>> {
+    if icmpv6.fits::<P>() {
+        icmpv6.downcast::<P>().map(message)
+    } else {
+        Ok(Icmpv6Message::Undefined(icmpv6))
+    }
+}
+
 impl<T: Ipv6Packet> Icmpv6Parse for T {
     type Envelope = T;
 
@@ -354,29 +578,19 @@ impl<T: Ipv6Packet> Icmpv6Parse for T {
         if self.next_proto() == ProtocolNumbers::Icmpv6 {
             let icmpv6 = self.parse::<Icmpv6<Self::Envelope, ()>>()?;
             match icmpv6.msg_type() {
-                Icmpv6Types::EchoRequest => {
-                    let packet = icmpv6.downcast::<EchoRequest>()?;
-                    Ok(Icmpv6Message::EchoRequest(packet))
-                }
-                Icmpv6Types::EchoReply => {
-                    let packet = icmpv6.downcast::<EchoReply>()?;
-                    Ok(Icmpv6Message::EchoReply(packet))
-                }
+                Icmpv6Types::EchoRequest => downcast(icmpv6, Icmpv6Message::EchoRequest),
+                Icmpv6Types::EchoReply => downcast(icmpv6, Icmpv6Message::EchoReply),
                 Icmpv6Types::NeighborAdvertisement => {
-                    let packet = icmpv6.downcast::<NeighborAdvertisement>()?;
-                    Ok(Icmpv6Message::NeighborAdvertisement(packet))
+                    downcast(icmpv6, Icmpv6Message::NeighborAdvertisement)
                 }
                 Icmpv6Types::NeighborSolicitation => {
-                    let packet = icmpv6.downcast::<NeighborSolicitation>()?;
-                    Ok(Icmpv6Message::NeighborSolicitation(packet))
+                    downcast(icmpv6, Icmpv6Message::NeighborSolicitation)
                 }
                 Icmpv6Types::RouterAdvertisement => {
-                    let packet = icmpv6.downcast::<RouterAdvertisement>()?;
-                    Ok(Icmpv6Message::RouterAdvertisement(packet))
+                    downcast(icmpv6, Icmpv6Message::RouterAdvertisement)
                 }
                 Icmpv6Types::RouterSolicitation => {
-                    let packet = icmpv6.downcast::<RouterSolicitation>()?;
-                    Ok(Icmpv6Message::RouterSolicitation(packet))
+                    downcast(icmpv6, Icmpv6Message::RouterSolicitation)
                 }
                 _ => Ok(Icmpv6Message::Undefined(icmpv6)),
             }
@@ -436,6 +650,23 @@ mod tests {
         assert_eq!(0x01f0, icmpv6.checksum());
     }
 
+    #[nb2::test]
+    fn icmpv6_data() {
+        let packet = Mbuf::from_bytes(&ICMPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let mut icmpv6 = ipv6.parse::<Icmpv6<Ipv6, ()>>().unwrap();
+
+        assert_eq!(&[0, 0, 0, 0], icmpv6.data());
+
+        icmpv6.data_mut()[3] = 1;
+        assert_eq!(&[0, 0, 0, 1], icmpv6.data());
+
+        icmpv6.set_data(&[1, 2]).unwrap();
+        assert_eq!(&[1, 2], icmpv6.data());
+        assert_eq!(6, icmpv6.len());
+    }
+
     #[nb2::test]
     fn downcast_icmpv6() {
         let packet = Mbuf::from_bytes(&ROUTER_ADVERT_PACKET).unwrap();
@@ -461,6 +692,54 @@ mod tests {
         assert_eq!(expected, icmpv6.checksum());
     }
 
+    #[nb2::test]
+    fn verify_checksum() {
+        let packet = Mbuf::from_bytes(&ROUTER_ADVERT_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let mut icmpv6 = ipv6.parse::<Icmpv6<Ipv6, ()>>().unwrap();
+        assert!(icmpv6.verify_checksum());
+
+        icmpv6.set_code(1);
+        assert!(!icmpv6.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn icmpv6_unknown_body() {
+        let packet = Mbuf::from_bytes(&ICMPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let mut icmpv6 = ipv6.parse::<Icmpv6<Ipv6, Unknown>>().unwrap();
+
+        assert_eq!(&[0, 0, 0, 0], icmpv6.body());
+
+        icmpv6.set_msg_type(Icmpv6Type::new(200));
+        icmpv6.body_mut()[0] = 1;
+        icmpv6.cascade();
+
+        assert_eq!(Icmpv6Type::new(200), icmpv6.msg_type());
+        assert_eq!(&[1, 0, 0, 0], icmpv6.body());
+        assert!(icmpv6.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn truncated_icmpv6_is_undefined() {
+        let packet = Mbuf::from_bytes(&ICMPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let mut icmpv6 = ipv6.parse::<Icmpv6<Ipv6, Unknown>>().unwrap();
+
+        // a neighbor advertisement needs 20 bytes of body, not 4.
+        icmpv6.set_msg_type(Icmpv6Types::NeighborAdvertisement);
+        assert!(!icmpv6.fits::<NeighborAdvertisement>());
+
+        if let Ok(Icmpv6Message::Undefined(icmpv6)) = icmpv6.deparse().parse_icmpv6() {
+            assert_eq!(Icmpv6Types::NeighborAdvertisement, icmpv6.msg_type());
+        } else {
+            panic!("bad packet");
+        }
+    }
+
     #[nb2::test]
     fn matchable_icmpv6_packets() {
         let packet = Mbuf::from_bytes(&ICMPV6_PACKET).unwrap();
@@ -1,18 +1,20 @@
 mod neighbor_advert;
 mod neighbor_solicit;
 mod options;
+mod responder;
 mod router_advert;
 mod router_solicit;
 
 pub use self::neighbor_advert::*;
 pub use self::neighbor_solicit::*;
 pub use self::options::*;
+pub use self::responder::*;
 pub use self::router_advert::*;
 pub use self::router_solicit::*;
 
 use super::{Icmpv6, Icmpv6Packet, Icmpv6Payload};
 use crate::packets::ip::v6::Ipv6Packet;
-use crate::packets::Packet;
+use crate::packets::{checksum, data_slice, Packet};
 use crate::Result;
 
 /// NDP message payload marker.
@@ -27,6 +29,22 @@ pub trait NdpPacket<E: Ipv6Packet, P: NdpPayload>: Icmpv6Packet<E, P> {
 
     /// Add option to NDP messaged.
     fn push_option<T: NdpOption>(&mut self) -> Result<T>;
+
+    /// Modifies an option of the message with `f`, and updates the
+    /// checksum incrementally for the change.
+    ///
+    /// The options are modified in place without going through the
+    /// packet, so the edits made outside of `update_option` must be
+    /// followed by `mark_dirty` for the checksum to be recomputed on
+    /// `cascade`. So is an option that does not know where it is in the
+    /// message. `f` must not change the length of the option.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// advert.update_option(&mut mtu, |mtu| mtu.set_mtu(1280));
+    /// ```
+    fn update_option<T: NdpOption, F: FnOnce(&mut T)>(&mut self, option: &mut T, f: F);
 }
 
 impl<E: Ipv6Packet, P: NdpPayload> NdpPacket<E, P> for Icmpv6<E, P>
@@ -40,8 +58,27 @@ where
     }
 
     fn push_option<T: NdpOption>(&mut self) -> Result<T> {
+        // the option is written by the caller after it is pushed.
+        self.mark_dirty();
         T::do_push(self.mbuf_mut())
     }
+
+    fn update_option<T: NdpOption, F: FnOnce(&mut T)>(&mut self, option: &mut T, f: F) {
+        let len = usize::from(option.length()) * 8;
+        if len == 0 {
+            f(option);
+            self.mark_dirty();
+            return;
+        }
+
+        // the options are aligned to 8 octets, so the sums line up with
+        // the words of the full checksum.
+        let offset = option.offset();
+        let old = checksum::sum(data_slice(self.mbuf(), offset, len));
+        f(option);
+        let new = checksum::sum(data_slice(self.mbuf(), offset, len));
+        self.update_checksum(&[old], &[new]);
+    }
 }
 
 #[cfg(test)]
@@ -1,3 +1,4 @@
+use crate::packets::be16;
 use crate::packets::icmp::v6::ndp::NdpPayload;
 use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Payload, Icmpv6Type, Icmpv6Types};
 use crate::packets::ip::v6::Ipv6Packet;
@@ -83,7 +84,7 @@ const O_FLAG: u8 = 0b0010_0000;
 pub struct NeighborAdvertisement {
     flags: u8,
     reserved1: u8,
-    reserved2: u16,
+    reserved2: be16,
     target_addr: Ipv6Addr,
 }
 
@@ -92,7 +93,7 @@ impl Default for NeighborAdvertisement {
         NeighborAdvertisement {
             flags: 0,
             reserved1: 0,
-            reserved2: 0,
+            reserved2: be16::default(),
             target_addr: Ipv6Addr::UNSPECIFIED,
         }
     }
@@ -116,12 +117,12 @@ impl<E: Ipv6Packet> Icmpv6<E, NeighborAdvertisement> {
 
     #[inline]
     pub fn set_router(&mut self) {
-        self.payload_mut().flags |= R_FLAG;
+        self.update_payload(|payload| payload.flags |= R_FLAG);
     }
 
     #[inline]
     pub fn unset_router(&mut self) {
-        self.payload_mut().flags &= !R_FLAG;
+        self.update_payload(|payload| payload.flags &= !R_FLAG);
     }
 
     #[inline]
@@ -131,12 +132,12 @@ impl<E: Ipv6Packet> Icmpv6<E, NeighborAdvertisement> {
 
     #[inline]
     pub fn set_solicited(&mut self) {
-        self.payload_mut().flags |= S_FLAG;
+        self.update_payload(|payload| payload.flags |= S_FLAG);
     }
 
     #[inline]
     pub fn unset_solicited(&mut self) {
-        self.payload_mut().flags &= !S_FLAG;
+        self.update_payload(|payload| payload.flags &= !S_FLAG);
     }
 
     #[inline]
@@ -146,12 +147,12 @@ impl<E: Ipv6Packet> Icmpv6<E, NeighborAdvertisement> {
 
     #[inline]
     pub fn set_override(&mut self) {
-        self.payload_mut().flags |= O_FLAG;
+        self.update_payload(|payload| payload.flags |= O_FLAG);
     }
 
     #[inline]
     pub fn unset_override(&mut self) {
-        self.payload_mut().flags &= !O_FLAG;
+        self.update_payload(|payload| payload.flags &= !O_FLAG);
     }
 
     #[inline]
@@ -159,9 +160,10 @@ impl<E: Ipv6Packet> Icmpv6<E, NeighborAdvertisement> {
         self.payload().target_addr
     }
 
+    /// Sets the target address, and updates the checksum incrementally.
     #[inline]
     pub fn set_target_addr(&mut self, target_addr: Ipv6Addr) {
-        self.payload_mut().target_addr = target_addr
+        self.update_payload(|payload| payload.target_addr = target_addr);
     }
 }
 
@@ -1,3 +1,4 @@
+use crate::packets::be32;
 use crate::packets::icmp::v6::ndp::NdpPayload;
 use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Payload, Icmpv6Type, Icmpv6Types};
 use crate::packets::ip::v6::Ipv6Packet;
@@ -47,14 +48,14 @@ use std::net::Ipv6Addr;
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct NeighborSolicitation {
-    reserved: u32,
+    reserved: be32,
     target_addr: Ipv6Addr,
 }
 
 impl Default for NeighborSolicitation {
     fn default() -> NeighborSolicitation {
         NeighborSolicitation {
-            reserved: 0,
+            reserved: be32::default(),
             target_addr: Ipv6Addr::UNSPECIFIED,
         }
     }
@@ -73,7 +74,7 @@ impl NdpPayload for NeighborSolicitation {}
 impl<E: Ipv6Packet> Icmpv6<E, NeighborSolicitation> {
     #[inline]
     pub fn reserved(&self) -> u32 {
-        u32::from_be(self.payload().reserved)
+        self.payload().reserved.get()
     }
 
     #[inline]
@@ -81,9 +82,10 @@ impl<E: Ipv6Packet> Icmpv6<E, NeighborSolicitation> {
         self.payload().target_addr
     }
 
+    /// Sets the target address, and updates the checksum incrementally.
     #[inline]
     pub fn set_target_addr(&mut self, target_addr: Ipv6Addr) {
-        self.payload_mut().target_addr = target_addr
+        self.update_payload(|payload| payload.target_addr = target_addr);
     }
 }
 
@@ -1,6 +1,7 @@
 use super::{NdpOption, SOURCE_LINK_LAYER_ADDR, TARGET_LINK_LAYER_ADDR};
 use crate::net::MacAddr;
 use crate::packets::ParseError;
+use crate::stats::Anomaly;
 use crate::{ensure, Mbuf, Result, SizeOf};
 use std::fmt;
 use std::ptr::NonNull;
@@ -62,7 +63,10 @@ impl LinkLayerAddress {
 
         ensure!(
             unsafe { fields.as_ref().length } == (LinkLayerAddressFields::size_of() as u8 / 8),
-            ParseError::new("Invalid link-layer address option length.")
+            ParseError::with_anomaly(
+                Anomaly::BadLength,
+                "Invalid link-layer address option length."
+            )
         );
 
         Ok(LinkLayerAddress { fields, offset })
@@ -124,6 +128,16 @@ impl fmt::Debug for LinkLayerAddress {
 }
 
 impl NdpOption for LinkLayerAddress {
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
     #[inline]
     fn do_push(mbuf: &mut Mbuf) -> Result<Self>
     where
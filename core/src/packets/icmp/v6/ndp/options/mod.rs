@@ -7,6 +7,7 @@ pub use self::mtu::*;
 pub use self::prefix_info::*;
 
 use crate::packets::ParseError;
+use crate::stats::Anomaly;
 use crate::{Mbuf, Result};
 use fallible_iterator::FallibleIterator;
 
@@ -27,6 +28,23 @@ pub enum NdpOptions {
 }
 
 pub trait NdpOption {
+    /// Returns the message buffer offset for the option.
+    ///
+    /// For the options that do not implement it and `length`, the edits
+    /// made with `update_option` have the checksum recomputed on `cascade`
+    /// instead of updated in place.
+    #[inline]
+    fn offset(&self) -> usize {
+        0
+    }
+
+    /// Returns the length of the option in units of 8 octets, `0` if
+    /// unknown.
+    #[inline]
+    fn length(&self) -> u8 {
+        0
+    }
+
     #[doc(hidden)]
     fn do_push(mbuf: &mut Mbuf) -> Result<Self>
     where
@@ -57,7 +75,10 @@ impl<'a> FallibleIterator for NdpOptionsIterator<'a> {
                 unsafe { self.mbuf.read_data::<[u8; 2]>(self.offset)?.as_ref() };
 
             if length == 0 {
-                Err(ParseError::new("NDP option has zero length.").into())
+                Err(
+                    ParseError::with_anomaly(Anomaly::BadLength, "NDP option has zero length.")
+                        .into(),
+                )
             } else {
                 let option = match option_type {
                     SOURCE_LINK_LAYER_ADDR => {
@@ -175,7 +196,8 @@ mod tests {
         let ipv6 = ethernet.parse::<Ipv6>().unwrap();
 
         if let Ok(Icmpv6Message::RouterAdvertisement(advert)) = ipv6.parse_icmpv6() {
-            assert!(advert.options().next().is_err());
+            let err = advert.options().next().unwrap_err();
+            assert_eq!(Some(Anomaly::BadLength), Anomaly::of(&err));
         } else {
             panic!("bad packet");
         }
@@ -1,5 +1,6 @@
 use super::{NdpOption, MTU};
-use crate::packets::ParseError;
+use crate::packets::{be16, be32, ParseError};
+use crate::stats::Anomaly;
 use crate::{ensure, Mbuf, Result, SizeOf};
 use std::fmt;
 use std::ptr::NonNull;
@@ -32,8 +33,8 @@ use std::ptr::NonNull;
 struct MtuFields {
     option_type: u8,
     length: u8,
-    reserved: u16,
-    mtu: u32,
+    reserved: be16,
+    mtu: be32,
 }
 
 impl Default for MtuFields {
@@ -41,8 +42,8 @@ impl Default for MtuFields {
         MtuFields {
             option_type: MTU,
             length: 1,
-            reserved: 0,
-            mtu: 0,
+            reserved: be16::default(),
+            mtu: be32::default(),
         }
     }
 }
@@ -61,7 +62,7 @@ impl Mtu {
 
         ensure!(
             unsafe { fields.as_ref().length } == (MtuFields::size_of() as u8 / 8),
-            ParseError::new("Invalid MTU option length.")
+            ParseError::with_anomaly(Anomaly::BadLength, "Invalid MTU option length.")
         );
 
         Ok(Mtu { fields, offset })
@@ -92,11 +93,11 @@ impl Mtu {
     }
 
     pub fn mtu(&self) -> u32 {
-        u32::from_be(self.fields().mtu)
+        self.fields().mtu.get()
     }
 
     pub fn set_mtu(&mut self, mtu: u32) {
-        self.fields_mut().mtu = u32::to_be(mtu);
+        self.fields_mut().mtu = mtu.into();
     }
 }
 
@@ -111,6 +112,16 @@ impl fmt::Debug for Mtu {
 }
 
 impl NdpOption for Mtu {
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
     #[inline]
     fn do_push(mbuf: &mut Mbuf) -> Result<Self>
     where
@@ -1,5 +1,6 @@
 use super::{NdpOption, PREFIX_INFORMATION};
-use crate::packets::ParseError;
+use crate::packets::{be32, ParseError};
+use crate::stats::Anomaly;
 use crate::{ensure, Mbuf, Result, SizeOf};
 use std::fmt;
 use std::net::Ipv6Addr;
@@ -102,9 +103,9 @@ struct PrefixInformationFields {
     length: u8,
     prefix_length: u8,
     flags: u8,
-    valid_lifetime: u32,
-    preferred_lifetime: u32,
-    reserved: u32,
+    valid_lifetime: be32,
+    preferred_lifetime: be32,
+    reserved: be32,
     prefix: Ipv6Addr,
 }
 
@@ -115,9 +116,9 @@ impl Default for PrefixInformationFields {
             length: 4,
             prefix_length: 0,
             flags: 0,
-            valid_lifetime: 0,
-            preferred_lifetime: 0,
-            reserved: 0,
+            valid_lifetime: be32::default(),
+            preferred_lifetime: be32::default(),
+            reserved: be32::default(),
             prefix: Ipv6Addr::UNSPECIFIED,
         }
     }
@@ -137,7 +138,10 @@ impl PrefixInformation {
 
         ensure!(
             unsafe { fields.as_ref().length } == (PrefixInformationFields::size_of() as u8 / 8),
-            ParseError::new("Invalid prefix information option length.")
+            ParseError::with_anomaly(
+                Anomaly::BadLength,
+                "Invalid prefix information option length."
+            )
         );
 
         Ok(PrefixInformation { fields, offset })
@@ -210,22 +214,22 @@ impl PrefixInformation {
 
     #[inline]
     pub fn valid_lifetime(&self) -> u32 {
-        u32::from_be(self.fields().valid_lifetime)
+        self.fields().valid_lifetime.get()
     }
 
     #[inline]
     pub fn set_valid_lifetime(&mut self, valid_lifetime: u32) {
-        self.fields_mut().valid_lifetime = u32::to_be(valid_lifetime);
+        self.fields_mut().valid_lifetime = valid_lifetime.into();
     }
 
     #[inline]
     pub fn preferred_lifetime(&self) -> u32 {
-        u32::from_be(self.fields().preferred_lifetime)
+        self.fields().preferred_lifetime.get()
     }
 
     #[inline]
     pub fn set_preferred_lifetime(&mut self, preferred_lifetime: u32) {
-        self.fields_mut().preferred_lifetime = u32::to_be(preferred_lifetime);
+        self.fields_mut().preferred_lifetime = preferred_lifetime.into();
     }
 
     #[inline]
@@ -255,6 +259,16 @@ impl fmt::Debug for PrefixInformation {
 }
 
 impl NdpOption for PrefixInformation {
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn length(&self) -> u8 {
+        self.fields().length
+    }
+
     #[inline]
     fn do_push(mbuf: &mut Mbuf) -> Result<Self>
     where
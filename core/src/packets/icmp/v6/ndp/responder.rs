@@ -0,0 +1,263 @@
+use super::{LinkLayerAddress, NdpPacket, NeighborAdvertisement, TARGET_LINK_LAYER_ADDR};
+use crate::batch::Responder;
+use crate::net::{Cidr, Ipv6Cidr, MacAddr};
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Message, Icmpv6Packet, Icmpv6Parse};
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::{EtherTypes, Ethernet, Packet, ParseError};
+use crate::{Mbuf, Result};
+use std::collections::HashSet;
+use std::net::Ipv6Addr;
+use std::sync::Arc;
+
+// NDP messages are only accepted from and sent to on-link neighbors.
+const NDP_HOP_LIMIT: u8 = 255;
+
+// the all-nodes multicast address, ff02::1.
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+/// Answers the neighbor solicitations for a set of IPv6 addresses and
+/// prefixes with neighbor advertisements, so the appliance is reachable
+/// on an IPv6 LAN without a kernel stack.
+///
+/// The advertisements carry the target link-layer address option with
+/// the responder's MAC. A solicitation for duplicate address detection,
+/// sent from the unspecified address, is answered to all nodes. All the
+/// other solicitations are answered to the soliciting node, with the
+/// solicited flag set.
+///
+/// The override flag is set for the addresses, but not for the prefixes.
+/// Answering for a prefix is a form of proxying, and RFC 4861 asks that a
+/// proxy does not override the cache entries of the real owners.
+///
+/// # Example
+///
+/// ```
+/// let ndp = NeighborResponder::new(port.mac_addr())
+///     .address("2001:db8::1".parse()?)
+///     .prefix("2001:db8:1::/64".parse()?);
+///
+/// Poll::new(q.clone()).respond(ndp, q.clone()).map(forward).send(q)
+/// ```
+#[derive(Clone, Debug)]
+pub struct NeighborResponder {
+    mac: MacAddr,
+    addrs: Arc<HashSet<Ipv6Addr>>,
+    prefixes: Arc<Vec<Ipv6Cidr>>,
+    router: bool,
+}
+
+impl NeighborResponder {
+    /// Creates a new responder that advertises `mac` as the link-layer
+    /// address.
+    pub fn new(mac: MacAddr) -> Self {
+        NeighborResponder {
+            mac,
+            addrs: Arc::new(HashSet::new()),
+            prefixes: Arc::new(vec![]),
+            router: false,
+        }
+    }
+
+    /// Adds an address to answer for.
+    pub fn address(mut self, addr: Ipv6Addr) -> Self {
+        Arc::make_mut(&mut self.addrs).insert(addr);
+        self
+    }
+
+    /// Adds a prefix to answer for all the addresses in.
+    pub fn prefix(mut self, prefix: Ipv6Cidr) -> Self {
+        Arc::make_mut(&mut self.prefixes).push(prefix);
+        self
+    }
+
+    /// Sets whether the advertisements have the router flag set. The
+    /// default is `false`.
+    pub fn router(mut self, router: bool) -> Self {
+        self.router = router;
+        self
+    }
+
+    /// Returns whether the responder answers for the address, and if so,
+    /// whether it is one of the addresses rather than in one of the
+    /// prefixes.
+    fn lookup(&self, addr: Ipv6Addr) -> Option<bool> {
+        if self.addrs.contains(&addr) {
+            Some(true)
+        } else if self.prefixes.iter().any(|prefix| prefix.contains(addr)) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the target of a valid neighbor solicitation the responder
+    /// answers for, and the source of the solicitation.
+    fn solicited_target(&self, ethernet: &Ethernet) -> Option<(Ipv6Addr, Ipv6Addr)> {
+        let ipv6 = ethernet.peek::<Ipv6>().ok()?;
+        if ipv6.hop_limit() != NDP_HOP_LIMIT {
+            return None;
+        }
+
+        let src = ipv6.src();
+        match ipv6.clone().parse_icmpv6() {
+            Ok(Icmpv6Message::NeighborSolicitation(solicit)) => {
+                let target = solicit.target_addr();
+                if solicit.code() == 0 && !target.is_multicast() && self.lookup(target).is_some() {
+                    Some((target, src))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Responder for NeighborResponder {
+    fn matches(&self, mbuf: &Mbuf) -> bool {
+        mbuf.peek::<Ethernet>().ok().map_or(false, |ethernet| {
+            ethernet.ether_type() == EtherTypes::Ipv6 && self.solicited_target(&ethernet).is_some()
+        })
+    }
+
+    fn reply(&self, mbuf: Mbuf) -> Result<Mbuf> {
+        let ethernet = mbuf.peek::<Ethernet>()?;
+        let (target, src) = self
+            .solicited_target(&ethernet)
+            .ok_or_else(|| ParseError::new("Not a neighbor solicitation to answer."))?;
+        let exact = self.lookup(target) == Some(true);
+
+        // a solicitation from the unspecified address is for duplicate
+        // address detection, the answer goes to all nodes.
+        let dad = src.is_unspecified();
+        let (dst_mac, dst) = if dad {
+            (MacAddr::new(0x33, 0x33, 0, 0, 0, 1), ALL_NODES)
+        } else {
+            (ethernet.src(), src)
+        };
+
+        let reply = Mbuf::new()?;
+        let mut ethernet = reply.push::<Ethernet>()?;
+        ethernet.set_src(self.mac);
+        ethernet.set_dst(dst_mac);
+
+        let mut ipv6 = ethernet.push::<Ipv6>()?;
+        ipv6.set_src(target);
+        ipv6.set_dst(dst);
+        ipv6.set_hop_limit(NDP_HOP_LIMIT);
+
+        let mut advert = ipv6.push::<Icmpv6<Ipv6, NeighborAdvertisement>>()?;
+        advert.set_target_addr(target);
+        if self.router {
+            advert.set_router();
+        }
+        if !dad {
+            advert.set_solicited();
+        }
+        if exact {
+            advert.set_override();
+        }
+
+        let mut option: LinkLayerAddress = advert.push_option()?;
+        option.set_option_type(TARGET_LINK_LAYER_ADDR);
+        option.set_addr(self.mac);
+
+        advert.cascade();
+        Ok(advert.reset())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::icmp::v6::ndp::{NdpOptions, NeighborSolicitation};
+    use fallible_iterator::FallibleIterator;
+
+    fn mac() -> MacAddr {
+        MacAddr::new(0x02, 0, 0, 0, 0, 1)
+    }
+
+    fn solicit(src: Ipv6Addr, target: Ipv6Addr) -> Mbuf {
+        let packet = Mbuf::new().unwrap();
+        let mut ethernet = packet.push::<Ethernet>().unwrap();
+        ethernet.set_src(MacAddr::new(0x02, 0, 0, 0, 0, 2));
+        let mut ipv6 = ethernet.push::<Ipv6>().unwrap();
+        ipv6.set_src(src);
+        ipv6.set_dst("ff02::1:ff00:1".parse().unwrap());
+        ipv6.set_hop_limit(NDP_HOP_LIMIT);
+        let mut solicit = ipv6.push::<Icmpv6<Ipv6, NeighborSolicitation>>().unwrap();
+        solicit.set_target_addr(target);
+        solicit.cascade();
+        solicit.reset()
+    }
+
+    fn parse_advert(mbuf: Mbuf) -> Icmpv6<Ipv6, NeighborAdvertisement> {
+        let ethernet = mbuf.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        match ipv6.parse_icmpv6() {
+            Ok(Icmpv6Message::NeighborAdvertisement(advert)) => advert,
+            _ => panic!("not a neighbor advertisement"),
+        }
+    }
+
+    #[nb2::test]
+    fn answer_solicitation() {
+        let ndp = NeighborResponder::new(mac()).address("2001:db8::1".parse().unwrap());
+        let packet = solicit(
+            "2001:db8::2".parse().unwrap(),
+            "2001:db8::1".parse().unwrap(),
+        );
+        assert!(ndp.matches(&packet));
+
+        let advert = parse_advert(ndp.reply(packet).unwrap());
+        assert_eq!("2001:db8::1", advert.target_addr().to_string());
+        assert_eq!("2001:db8::2", advert.envelope().dst().to_string());
+        assert_eq!(NDP_HOP_LIMIT, advert.envelope().hop_limit());
+        assert!(advert.solicited());
+        assert!(advert.r#override());
+        assert!(!advert.router());
+        assert!(advert.verify_checksum());
+
+        let mut options = advert.options();
+        match options.next() {
+            Ok(Some(NdpOptions::TargetLinkLayerAddress(option))) => {
+                assert_eq!(mac(), option.addr())
+            }
+            _ => panic!("missing target link-layer address option"),
+        }
+    }
+
+    #[nb2::test]
+    fn answer_for_prefix_without_override() {
+        let ndp = NeighborResponder::new(mac()).prefix("2001:db8:1::/64".parse().unwrap());
+        let packet = solicit(
+            "2001:db8:1::2".parse().unwrap(),
+            "2001:db8:1::99".parse().unwrap(),
+        );
+        assert!(ndp.matches(&packet));
+
+        let advert = parse_advert(ndp.reply(packet).unwrap());
+        assert!(advert.solicited());
+        assert!(!advert.r#override());
+    }
+
+    #[nb2::test]
+    fn answer_duplicate_address_detection() {
+        let ndp = NeighborResponder::new(mac()).address("2001:db8::1".parse().unwrap());
+        let packet = solicit(Ipv6Addr::UNSPECIFIED, "2001:db8::1".parse().unwrap());
+
+        let advert = parse_advert(ndp.reply(packet).unwrap());
+        assert_eq!(ALL_NODES, advert.envelope().dst());
+        assert!(!advert.solicited());
+    }
+
+    #[nb2::test]
+    fn ignore_other_targets() {
+        let ndp = NeighborResponder::new(mac()).address("2001:db8::1".parse().unwrap());
+        let packet = solicit(
+            "2001:db8::2".parse().unwrap(),
+            "2001:db8::3".parse().unwrap(),
+        );
+        assert!(!ndp.matches(&packet));
+    }
+}
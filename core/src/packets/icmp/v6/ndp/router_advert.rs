@@ -1,6 +1,7 @@
 use crate::packets::icmp::v6::ndp::NdpPayload;
 use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Payload, Icmpv6Type, Icmpv6Types};
 use crate::packets::ip::v6::Ipv6Packet;
+use crate::packets::{be16, be32};
 use std::fmt;
 
 /*  From https://tools.ietf.org/html/rfc4861#section-4.2
@@ -109,9 +110,9 @@ use std::fmt;
 pub struct RouterAdvertisement {
     current_hop_limit: u8,
     flags: u8,
-    router_lifetime: u16,
-    reachable_time: u32,
-    retrans_timer: u32,
+    router_lifetime: be16,
+    reachable_time: be32,
+    retrans_timer: be32,
 }
 
 impl Icmpv6Payload for RouterAdvertisement {
@@ -135,7 +136,7 @@ impl<E: Ipv6Packet> Icmpv6<E, RouterAdvertisement> {
 
     #[inline]
     pub fn set_current_hop_limit(&mut self, current_hop_limit: u8) {
-        self.payload_mut().current_hop_limit = current_hop_limit;
+        self.update_payload(|payload| payload.current_hop_limit = current_hop_limit);
     }
 
     #[inline]
@@ -145,12 +146,12 @@ impl<E: Ipv6Packet> Icmpv6<E, RouterAdvertisement> {
 
     #[inline]
     pub fn set_managed_addr_cfg(&mut self) {
-        self.payload_mut().flags |= M_FLAG;
+        self.update_payload(|payload| payload.flags |= M_FLAG);
     }
 
     #[inline]
     pub fn unset_managed_addr_cfg(&mut self) {
-        self.payload_mut().flags &= !M_FLAG;
+        self.update_payload(|payload| payload.flags &= !M_FLAG);
     }
 
     #[inline]
@@ -160,43 +161,43 @@ impl<E: Ipv6Packet> Icmpv6<E, RouterAdvertisement> {
 
     #[inline]
     pub fn set_other_cfg(&mut self) {
-        self.payload_mut().flags |= O_FLAG;
+        self.update_payload(|payload| payload.flags |= O_FLAG);
     }
 
     #[inline]
     pub fn unset_other_cfg(&mut self) {
-        self.payload_mut().flags &= !O_FLAG;
+        self.update_payload(|payload| payload.flags &= !O_FLAG);
     }
 
     #[inline]
     pub fn router_lifetime(&self) -> u16 {
         // TODO: should these times be translated to duration?
-        u16::from_be(self.payload().router_lifetime)
+        self.payload().router_lifetime.get()
     }
 
     #[inline]
     pub fn set_router_lifetime(&mut self, router_lifetime: u16) {
-        self.payload_mut().router_lifetime = u16::to_be(router_lifetime);
+        self.update_payload(|payload| payload.router_lifetime = router_lifetime.into());
     }
 
     #[inline]
     pub fn reachable_time(&self) -> u32 {
-        u32::from_be(self.payload().reachable_time)
+        self.payload().reachable_time.get()
     }
 
     #[inline]
     pub fn set_reachable_time(&mut self, reachable_time: u32) {
-        self.payload_mut().reachable_time = u32::to_be(reachable_time);
+        self.update_payload(|payload| payload.reachable_time = reachable_time.into());
     }
 
     #[inline]
     pub fn retrans_timer(&self) -> u32 {
-        u32::from_be(self.payload().retrans_timer)
+        self.payload().retrans_timer.get()
     }
 
     #[inline]
     pub fn set_retrans_timer(&mut self, retrans_timer: u32) {
-        self.payload_mut().retrans_timer = u32::to_be(retrans_timer);
+        self.update_payload(|payload| payload.retrans_timer = retrans_timer.into());
     }
 }
 
@@ -301,6 +302,34 @@ mod tests {
         }
     }
 
+    #[nb2::test]
+    fn reconcile_incrementally() {
+        let packet = Mbuf::from_bytes(&ROUTER_ADVERT_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        if let Ok(Icmpv6Message::RouterAdvertisement(mut advert)) = ipv6.parse_icmpv6() {
+            advert.set_router_lifetime(0);
+            advert.unset_other_cfg();
+            advert.set_code(1);
+            assert!(!advert.is_dirty());
+            assert!(advert.verify_checksum());
+
+            // the rewritten source is folded into the checksum on cascade.
+            advert.envelope_mut().set_src("fe80::1".parse().unwrap());
+            advert.cascade();
+            assert!(!advert.is_dirty());
+            assert!(advert.verify_checksum());
+
+            advert.payload_mut().current_hop_limit = 1;
+            assert!(advert.is_dirty());
+            advert.cascade();
+            assert!(advert.verify_checksum());
+        } else {
+            panic!("not a router advertisement packet");
+        }
+    }
+
     #[nb2::test]
     fn find_source_link_layer_address() {
         let packet = Mbuf::from_bytes(&ROUTER_ADVERT_PACKET).unwrap();
@@ -327,4 +356,34 @@ mod tests {
             panic!("not a router advertisement packet");
         }
     }
+
+    #[nb2::test]
+    fn update_option_checksum() {
+        let packet = Mbuf::from_bytes(&ROUTER_ADVERT_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        if let Ok(Icmpv6Message::RouterAdvertisement(mut advert)) = ipv6.parse_icmpv6() {
+            let mut options = vec![];
+            let mut iter = advert.options();
+            while let Ok(Some(option)) = iter.next() {
+                options.push(option);
+            }
+
+            for option in options.iter_mut() {
+                match option {
+                    NdpOptions::Mtu(mtu) => advert.update_option(mtu, |mtu| mtu.set_mtu(1280)),
+                    NdpOptions::SourceLinkLayerAddress(addr) => {
+                        advert.update_option(addr, |addr| addr.set_addr(MacAddr::UNSPECIFIED))
+                    }
+                    _ => (),
+                }
+            }
+
+            assert_ne!(0xf50c, advert.checksum());
+            assert!(advert.verify_checksum());
+        } else {
+            panic!("not a router advertisement packet");
+        }
+    }
 }
@@ -1,3 +1,4 @@
+use crate::packets::be32;
 use crate::packets::icmp::v6::ndp::NdpPayload;
 use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Payload, Icmpv6Type, Icmpv6Types};
 use crate::packets::ip::v6::Ipv6Packet;
@@ -33,7 +34,7 @@ use std::fmt;
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C, packed)]
 pub struct RouterSolicitation {
-    reserved: u32,
+    reserved: be32,
 }
 
 impl Icmpv6Payload for RouterSolicitation {
@@ -49,7 +50,7 @@ impl NdpPayload for RouterSolicitation {}
 impl<E: Ipv6Packet> Icmpv6<E, RouterSolicitation> {
     #[inline]
     pub fn reserved(&self) -> u32 {
-        u32::from_be(self.payload().reserved)
+        self.payload().reserved.get()
     }
 }
 
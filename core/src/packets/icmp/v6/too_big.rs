@@ -1,6 +1,6 @@
 use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Payload, Icmpv6Type, Icmpv6Types};
 use crate::packets::ip::v6::Ipv6Packet;
-use crate::packets::{EthernetHeader, Packet};
+use crate::packets::{be32, EthernetHeader, Packet};
 use crate::SizeOf;
 use std::fmt;
 
@@ -25,7 +25,7 @@ use std::fmt;
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C, packed)]
 pub struct PacketTooBig {
-    mtu: u32,
+    mtu: be32,
 }
 
 impl Icmpv6Payload for PacketTooBig {
@@ -37,12 +37,12 @@ impl Icmpv6Payload for PacketTooBig {
 impl<E: Ipv6Packet> Icmpv6<E, PacketTooBig> {
     #[inline]
     pub fn mtu(&self) -> u32 {
-        u32::from_be(self.payload().mtu)
+        self.payload().mtu.get()
     }
 
     #[inline]
     pub fn set_mtu(&mut self, mtu: u32) {
-        self.payload_mut().mtu = u32::to_be(mtu);
+        self.update_payload(|payload| payload.mtu = mtu.into());
     }
 }
 
@@ -62,14 +62,15 @@ impl<E: Ipv6Packet> fmt::Display for Icmpv6<E, PacketTooBig> {
 
 impl<E: Ipv6Packet> Packet for Icmpv6<E, PacketTooBig> {
     #[inline]
-    fn cascade(&mut self) {
+    fn reconcile(&mut self) {
         // assuming inside an ethernet frame
         let max_len = self.mtu() as usize + EthernetHeader::size_of();
         // only err if nothing to trim, ignore the result
         let _ = self.mbuf_mut().truncate(max_len);
 
-        self.compute_checksum();
-        self.envelope_mut().cascade();
+        // the truncation changes the message, so the checksum is always
+        // recomputed.
+        self.refresh_checksum();
     }
 }
 
@@ -33,14 +33,59 @@ pub mod ProtocolNumbers {
     // User Datagram Protocol.
     pub const Udp: ProtocolNumber = ProtocolNumber(0x11);
 
+    // IPv6 Hop-by-Hop Option.
+    pub const Ipv6HopByHop: ProtocolNumber = ProtocolNumber(0x00);
+
     // Routing Header for IPv6.
     pub const Ipv6Route: ProtocolNumber = ProtocolNumber(0x2B);
 
+    // Fragment Header for IPv6.
+    pub const Ipv6Frag: ProtocolNumber = ProtocolNumber(0x2C);
+
+    // Authentication Header.
+    pub const Ah: ProtocolNumber = ProtocolNumber(0x33);
+
+    // Destination Options for IPv6.
+    pub const Ipv6Opts: ProtocolNumber = ProtocolNumber(0x3C);
+
     // Internet Control Message Protocol for IPv6.
     pub const Icmpv6: ProtocolNumber = ProtocolNumber(0x3A);
 
     // Internet Control Message Protocol for IPv4.
     pub const Icmpv4: ProtocolNumber = ProtocolNumber(0x01);
+
+    // Layer Two Tunneling Protocol Version 3.
+    pub const L2tp: ProtocolNumber = ProtocolNumber(0x73);
+
+    // Internet Group Management Protocol.
+    pub const Igmp: ProtocolNumber = ProtocolNumber(0x02);
+
+    // IPv4 encapsulation.
+    pub const IpInIp: ProtocolNumber = ProtocolNumber(0x04);
+
+    // IPv6 encapsulation.
+    pub const Ipv6Encap: ProtocolNumber = ProtocolNumber(0x29);
+
+    // No Next Header for IPv6.
+    pub const Ipv6NoNxt: ProtocolNumber = ProtocolNumber(0x3B);
+
+    // Generic Routing Encapsulation.
+    pub const Gre: ProtocolNumber = ProtocolNumber(0x2F);
+
+    // Encapsulating Security Payload.
+    pub const Esp: ProtocolNumber = ProtocolNumber(0x32);
+
+    // Open Shortest Path First.
+    pub const Ospf: ProtocolNumber = ProtocolNumber(0x59);
+
+    // Protocol Independent Multicast.
+    pub const Pim: ProtocolNumber = ProtocolNumber(0x67);
+
+    // Virtual Router Redundancy Protocol.
+    pub const Vrrp: ProtocolNumber = ProtocolNumber(0x70);
+
+    // Stream Control Transmission Protocol.
+    pub const Sctp: ProtocolNumber = ProtocolNumber(0x84);
 }
 
 impl fmt::Display for ProtocolNumber {
@@ -52,7 +97,22 @@ impl fmt::Display for ProtocolNumber {
                 ProtocolNumbers::Tcp => "TCP".to_string(),
                 ProtocolNumbers::Udp => "UDP".to_string(),
                 ProtocolNumbers::Ipv6Route => "IPv6 Route".to_string(),
+                ProtocolNumbers::Ipv6Frag => "IPv6 Fragment".to_string(),
+                ProtocolNumbers::Ah => "AH".to_string(),
+                ProtocolNumbers::Ipv6Opts => "IPv6 Options".to_string(),
                 ProtocolNumbers::Icmpv6 => "ICMPv6".to_string(),
+                ProtocolNumbers::L2tp => "L2TP".to_string(),
+                ProtocolNumbers::Icmpv4 => "ICMP".to_string(),
+                ProtocolNumbers::Igmp => "IGMP".to_string(),
+                ProtocolNumbers::IpInIp => "IP-in-IP".to_string(),
+                ProtocolNumbers::Ipv6Encap => "IPv6 Encapsulation".to_string(),
+                ProtocolNumbers::Ipv6NoNxt => "IPv6 No Next Header".to_string(),
+                ProtocolNumbers::Gre => "GRE".to_string(),
+                ProtocolNumbers::Esp => "ESP".to_string(),
+                ProtocolNumbers::Ospf => "OSPF".to_string(),
+                ProtocolNumbers::Pim => "PIM".to_string(),
+                ProtocolNumbers::Vrrp => "VRRP".to_string(),
+                ProtocolNumbers::Sctp => "SCTP".to_string(),
                 _ => format!("0x{:02x}", self.0),
             }
         )
@@ -185,6 +245,41 @@ impl Flow {
         self.protocol = protocol
     }
 
+    /// Returns a 20-bit IPv6 flow label derived from the 5-tuple.
+    ///
+    /// Follows the stateless hashing approach of RFC 6437, section 3, so
+    /// all the packets of a flow get the same label and ECMP or RSS
+    /// hashing on the label keeps them on the same path. The label is
+    /// never `0`, which would mean the packet is unlabeled.
+    pub fn flow_label(&self) -> u32 {
+        // 32-bit FNV-1a over the tuple.
+        let mut hash: u32 = 0x811c_9dc5;
+        let mut feed = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash ^= u32::from(byte);
+                hash = hash.wrapping_mul(0x0100_0193);
+            }
+        };
+
+        match self.src_ip() {
+            IpAddr::V4(ip) => feed(&ip.octets()),
+            IpAddr::V6(ip) => feed(&ip.octets()),
+        }
+        match self.dst_ip() {
+            IpAddr::V4(ip) => feed(&ip.octets()),
+            IpAddr::V6(ip) => feed(&ip.octets()),
+        }
+        feed(&self.src_port().to_be_bytes());
+        feed(&self.dst_port().to_be_bytes());
+        feed(&[self.protocol().0]);
+
+        // folds the upper bits in rather than truncating them.
+        match (hash ^ (hash >> 20)) & 0x000f_ffff {
+            0 => 1,
+            label => label,
+        }
+    }
+
     #[inline]
     pub fn reverse(&self) -> Self {
         Flow {
@@ -225,5 +320,28 @@ mod tests {
         assert_eq!("IPv6 Route", ProtocolNumbers::Ipv6Route.to_string());
         assert_eq!("ICMPv6", ProtocolNumbers::Icmpv6.to_string());
         assert_eq!("0x00", ProtocolNumber::new(0).to_string());
+        assert_eq!("OSPF", ProtocolNumbers::Ospf.to_string());
+        assert_eq!("GRE", ProtocolNumbers::Gre.to_string());
+        // 253 is reserved for experimentation, never assigned.
+        assert_eq!("0xfd", ProtocolNumber::new(253).to_string());
+    }
+
+    #[test]
+    fn flow_label_from_flow() {
+        let flow = Flow::new(
+            "2001:db8::1".parse().unwrap(),
+            "2001:db8::2".parse().unwrap(),
+            3464,
+            80,
+            ProtocolNumbers::Tcp,
+        );
+
+        let label = flow.flow_label();
+        assert!(label != 0);
+        assert!(label <= 0x000f_ffff);
+
+        let mut other = flow;
+        other.set_src_port(3465);
+        assert!(label != other.flow_label());
     }
 }
@@ -1,7 +1,8 @@
-use crate::packets::checksum::PseudoHeader;
+use crate::packets::checksum::{self, PseudoHeader};
 use crate::packets::ip::{IpAddrMismatchError, IpPacket, ProtocolNumber};
-use crate::packets::{CondRc, EtherTypes, Ethernet, Header, Packet};
-use crate::{Result, SizeOf};
+use crate::packets::{be16, data_slice, CondRc, EtherTypes, Ethernet, Header, Packet, ParseError};
+use crate::stats::Anomaly;
+use crate::{ensure, Result, RxChecksum, SizeOf};
 use std::fmt;
 use std::net::{IpAddr, Ipv4Addr};
 use std::ptr::NonNull;
@@ -136,12 +137,12 @@ const FLAGS_MF: u16 = 0b0010_0000_0000_0000;
 pub struct Ipv4Header {
     version_ihl: u8,
     dscp_ecn: u8,
-    total_length: u16,
-    identification: u16,
-    flags_to_frag_offset: u16,
+    total_length: be16,
+    identification: be16,
+    flags_to_frag_offset: be16,
     ttl: u8,
     protocol: u8,
-    checksum: u16,
+    checksum: be16,
     src: Ipv4Addr,
     dst: Ipv4Addr,
 }
@@ -151,12 +152,12 @@ impl Default for Ipv4Header {
         Ipv4Header {
             version_ihl: 0x45,
             dscp_ecn: 0,
-            total_length: 0,
-            identification: 0,
-            flags_to_frag_offset: 0,
+            total_length: be16::default(),
+            identification: be16::default(),
+            flags_to_frag_offset: be16::default(),
             ttl: 0,
             protocol: 0,
-            checksum: 0,
+            checksum: be16::default(),
             src: Ipv4Addr::UNSPECIFIED,
             dst: Ipv4Addr::UNSPECIFIED,
         }
@@ -171,6 +172,7 @@ pub struct Ipv4 {
     envelope: CondRc<Ethernet>,
     header: NonNull<Ipv4Header>,
     offset: usize,
+    dirty: bool,
 }
 
 impl Ipv4 {
@@ -185,10 +187,30 @@ impl Ipv4 {
         self.header().version_ihl & 0x0f
     }
 
-    #[allow(dead_code)]
     #[inline]
     fn set_ihl(&mut self, ihl: u8) {
-        self.header_mut().version_ihl = (self.header().version_ihl & 0x0f) | (ihl & 0x0f);
+        self.header_mut().version_ihl = (self.header().version_ihl & 0xf0) | (ihl & 0x0f);
+    }
+
+    /// Returns the length of the options, as stated by the IHL.
+    #[inline]
+    pub fn options_len(&self) -> usize {
+        (self.ihl() as usize * 4).saturating_sub(Ipv4Header::size_of())
+    }
+
+    /// Removes the options from the header.
+    ///
+    /// The payload is always parsed right after the fixed header, so the
+    /// options need to be removed before parsing the payload of a packet
+    /// that has them.
+    pub fn strip_options(&mut self) -> Result<()> {
+        let len = self.options_len();
+        if len > 0 {
+            let offset = self.payload_offset();
+            self.mbuf_mut().shrink(offset, len)?;
+            self.set_ihl(5);
+        }
+        Ok(())
     }
 
     #[inline]
@@ -213,74 +235,81 @@ impl Ipv4 {
 
     #[inline]
     pub fn total_length(&self) -> u16 {
-        u16::from_be(self.header().total_length)
+        self.header().total_length.get()
     }
 
     #[inline]
     fn set_total_length(&mut self, total_length: u16) {
-        self.header_mut().total_length = u16::to_be(total_length);
+        self.header_mut().total_length = total_length.into();
     }
 
     #[inline]
     pub fn identification(&self) -> u16 {
-        u16::from_be(self.header().identification)
+        self.header().identification.get()
     }
 
     #[inline]
     pub fn set_identification(&mut self, identification: u16) {
-        self.header_mut().identification = u16::to_be(identification);
+        self.header_mut().identification = identification.into();
     }
 
     #[inline]
     pub fn dont_fragment(&self) -> bool {
-        u16::from_be(self.header().flags_to_frag_offset) & FLAGS_DF != 0
+        self.header().flags_to_frag_offset.get() & FLAGS_DF != 0
     }
 
     #[inline]
     pub fn set_dont_fragment(&mut self) {
         self.header_mut().flags_to_frag_offset =
-            u16::to_be(u16::from_be(self.header().flags_to_frag_offset) | FLAGS_DF);
+            (self.header().flags_to_frag_offset.get() | FLAGS_DF).into();
     }
 
     #[inline]
     pub fn unset_dont_fragment(&mut self) {
         self.header_mut().flags_to_frag_offset =
-            u16::to_be(u16::from_be(self.header().flags_to_frag_offset) & !FLAGS_DF);
+            (self.header().flags_to_frag_offset.get() & !FLAGS_DF).into();
     }
 
     #[inline]
     pub fn more_fragments(&self) -> bool {
-        u16::from_be(self.header().flags_to_frag_offset) & FLAGS_MF != 0
+        self.header().flags_to_frag_offset.get() & FLAGS_MF != 0
     }
 
     #[inline]
     pub fn set_more_fragments(&mut self) {
         self.header_mut().flags_to_frag_offset =
-            u16::to_be(u16::from_be(self.header().flags_to_frag_offset) | FLAGS_MF);
+            (self.header().flags_to_frag_offset.get() | FLAGS_MF).into();
     }
 
     #[inline]
     pub fn unset_more_fragments(&mut self) {
         self.header_mut().flags_to_frag_offset =
-            u16::to_be(u16::from_be(self.header().flags_to_frag_offset) & !FLAGS_MF);
+            (self.header().flags_to_frag_offset.get() & !FLAGS_MF).into();
     }
 
     #[inline]
     pub fn clear_flags(&mut self) {
         self.header_mut().flags_to_frag_offset =
-            u16::to_be(u16::from_be(self.header().flags_to_frag_offset) & !0xe000);
+            (self.header().flags_to_frag_offset.get() & !0xe000).into();
     }
 
+    /// Returns whether the packet is a fragment, either with more fragments
+    /// to follow or with a non-zero offset.
+    #[inline]
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments() || self.fragment_offset() != 0
+    }
+
+    /// Returns the fragment offset, in units of 8 bytes.
     #[inline]
     pub fn fragment_offset(&self) -> u16 {
-        u16::from_be(self.header().flags_to_frag_offset) & 0x1fff
+        self.header().flags_to_frag_offset.get() & 0x1fff
     }
 
     #[inline]
     pub fn set_fragment_offset(&mut self, offset: u16) {
-        self.header_mut().flags_to_frag_offset = u16::to_be(
-            (u16::from_be(self.header().flags_to_frag_offset) & 0xe000) | (offset & 0x1fff),
-        );
+        self.header_mut().flags_to_frag_offset =
+            ((self.header().flags_to_frag_offset.get() & 0xe000) | (offset & 0x1fff)).into();
     }
 
     #[inline]
@@ -305,13 +334,41 @@ impl Ipv4 {
 
     #[inline]
     pub fn checksum(&self) -> u16 {
-        u16::from_be(self.header().checksum)
+        self.header().checksum.get()
     }
 
-    #[allow(dead_code)]
     #[inline]
     fn set_checksum(&mut self, checksum: u16) {
-        self.header_mut().checksum = u16::to_be(checksum);
+        self.header_mut().checksum = checksum.into();
+    }
+
+    #[inline]
+    fn compute_checksum(&mut self) {
+        self.set_checksum(0);
+
+        let len = self.ihl() as usize * 4;
+        let data = data_slice(self.mbuf(), self.offset(), len);
+        let checksum = checksum::compute(0, data);
+        self.set_checksum(checksum);
+    }
+
+    /// Returns whether the header checksum is valid.
+    ///
+    /// If the ethernet device already validated the checksum on receive,
+    /// its result is used. Otherwise the checksum is verified in software.
+    /// The hardware result is stale once the header is modified, so this
+    /// should be called before any mutation.
+    #[inline]
+    pub fn verify_checksum(&self) -> bool {
+        match self.mbuf().rx_ip_checksum() {
+            RxChecksum::Good => true,
+            RxChecksum::Bad => false,
+            RxChecksum::Unknown => {
+                let len = self.ihl() as usize * 4;
+                let data = data_slice(self.mbuf(), self.offset(), len);
+                len >= Self::Header::size_of() && checksum::compute(0, data) == 0
+            }
+        }
     }
 
     #[inline]
@@ -381,6 +438,7 @@ impl Packet for Ipv4 {
     #[doc(hidden)]
     #[inline]
     fn header_mut(&mut self) -> &mut Self::Header {
+        self.dirty = true;
         unsafe { self.header.as_mut() }
     }
 
@@ -389,17 +447,38 @@ impl Packet for Ipv4 {
         self.offset
     }
 
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     #[doc(hidden)]
     #[inline]
     fn do_parse(envelope: Self::Envelope) -> Result<Self> {
         let mbuf = envelope.mbuf();
         let offset = envelope.payload_offset();
-        let header = mbuf.read_data(offset)?;
+        let header = mbuf.read_data::<Ipv4Header>(offset)?;
+        ensure!(
+            unsafe { header.as_ref() }.version_ihl >> 4 == 4,
+            ParseError::with_anomaly(Anomaly::BadVersion, "Not an IPv4 packet.")
+        );
+        // the device already checked the header, the software check is
+        // left to `verify_checksum`.
+        ensure!(
+            mbuf.rx_ip_checksum() != RxChecksum::Bad,
+            ParseError::with_anomaly(Anomaly::BadChecksum, "Packet has a bad IPv4 checksum.")
+        );
 
         Ok(Ipv4 {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: false,
         })
     }
 
@@ -418,6 +497,7 @@ impl Packet for Ipv4 {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: true,
         })
     }
 
@@ -430,11 +510,16 @@ impl Packet for Ipv4 {
     }
 
     #[inline]
-    fn cascade(&mut self) {
-        // TODO: fix header checksum
+    fn reconcile(&mut self) {
         let len = self.len() as u16;
-        self.set_total_length(len);
-        self.envelope_mut().cascade();
+        if self.total_length() != len {
+            self.set_total_length(len);
+        }
+
+        if self.dirty {
+            self.compute_checksum();
+            self.dirty = false;
+        }
     }
 
     #[inline]
@@ -531,6 +616,26 @@ mod tests {
         assert_eq!("139.133.233.2", ipv4.dst().to_string());
     }
 
+    #[nb2::test]
+    fn verify_ipv4_checksum() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert!(ipv4.verify_checksum());
+
+        ipv4.set_ttl(1);
+        assert!(!ipv4.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn reject_bad_rx_checksum() {
+        let mut packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        packet.set_rx_ip_checksum(RxChecksum::Bad);
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let err = ethernet.parse::<Ipv4>().unwrap_err();
+        assert_eq!(Some(Anomaly::BadChecksum), Anomaly::of(&err));
+    }
+
     #[nb2::test]
     fn parse_ipv4_setter_checks() {
         let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
@@ -546,9 +651,17 @@ mod tests {
         assert_eq!(true, ipv4.more_fragments());
         ipv4.set_fragment_offset(5);
         assert_eq!(5, ipv4.fragment_offset());
+        assert_eq!(true, ipv4.is_fragment());
         ipv4.clear_flags();
         assert_eq!(false, ipv4.dont_fragment());
         assert_eq!(false, ipv4.more_fragments());
+        assert_eq!(true, ipv4.is_fragment());
+        ipv4.set_fragment_offset(0);
+        assert_eq!(false, ipv4.is_fragment());
+
+        // Identification
+        ipv4.set_identification(1234);
+        assert_eq!(1234, ipv4.identification());
 
         // DSCP & ECN
         assert_eq!(0, ipv4.dscp());
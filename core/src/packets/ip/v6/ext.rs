@@ -0,0 +1,552 @@
+use crate::packets::checksum::PseudoHeader;
+use crate::packets::ip::v6::Ipv6Packet;
+use crate::packets::ip::{IpPacket, ProtocolNumber, ProtocolNumbers};
+use crate::packets::{CondRc, Header, Packet, ParseError};
+use crate::stats::Anomaly;
+use crate::{Mbuf, Result, SizeOf};
+use std::fmt;
+use std::net::IpAddr;
+use std::ptr::NonNull;
+
+/*  From https://tools.ietf.org/html/rfc8200#section-4
+    and https://tools.ietf.org/html/rfc6564#section-4
+    Uniform Format for IPv6 Extension Headers
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |  Next Header  |  Hdr Ext Len  |                               |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+                               +
+    |                                                               |
+    .                                                               .
+    .                  Header Specific Data                         .
+    .                                                               .
+    |                                                               |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    Next Header         8-bit selector.  Identifies the type of header
+                        immediately following the extension header.
+
+    Hdr Ext Len         8-bit unsigned integer.  Length of the extension
+                        header in 8-octet units, not including the first
+                        8 octets.
+
+    The Authentication Header is the exception, its length is in 4-octet
+    units, not including the first 8 octets.
+    https://tools.ietf.org/html/rfc4302#section-2.2
+
+    The Fragment Header is the other exception, it is always 8 octets and
+    its second octet is reserved.
+    https://tools.ietf.org/html/rfc8200#section-4.5
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |  Next Header  |   Reserved    |      Fragment Offset    |Res|M|
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                         Identification                        |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+*/
+
+/// The leading fields shared by all the IPv6 extension headers.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct ExtensionHeader {
+    next_header: u8,
+    hdr_ext_len: u8,
+}
+
+impl Header for ExtensionHeader {}
+
+/// Returns whether the protocol is an IPv6 extension header that can be
+/// skipped over by its length field.
+///
+/// ESP is not one, everything after the ESP header is encrypted.
+#[inline]
+pub fn is_ipv6_extension(proto: ProtocolNumber) -> bool {
+    match proto {
+        ProtocolNumbers::Ipv6HopByHop
+        | ProtocolNumbers::Ipv6Route
+        | ProtocolNumbers::Ipv6Frag
+        | ProtocolNumbers::Ah
+        | ProtocolNumbers::Ipv6Opts => true,
+        // mobility, host identity, shim6 and the two experimental values
+        // from rfc 7045, they all use the uniform format.
+        ProtocolNumber(135) | ProtocolNumber(139) | ProtocolNumber(140) => true,
+        ProtocolNumber(253) | ProtocolNumber(254) => true,
+        _ => false,
+    }
+}
+
+/// Reads the length of the extension header at `offset`.
+#[inline]
+fn extension_len(mbuf: &Mbuf, offset: usize, proto: ProtocolNumber) -> Result<usize> {
+    let header = mbuf.read_data::<ExtensionHeader>(offset)?;
+    let hdr_ext_len = unsafe { header.as_ref().hdr_ext_len } as usize;
+
+    match proto {
+        ProtocolNumbers::Ah => Ok((hdr_ext_len + 2) * 4),
+        ProtocolNumbers::Ipv6Frag => Ok(8),
+        _ => Ok((hdr_ext_len + 1) * 8),
+    }
+}
+
+/// Reads the fragment offset of the fragment header at `offset`, in
+/// 8-octet units.
+#[inline]
+fn fragment_offset(mbuf: &Mbuf, offset: usize) -> Result<u16> {
+    let bytes = mbuf.read_data_slice::<u8>(offset + 2, 2)?;
+    let bytes = unsafe { bytes.as_ref() };
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]) >> 3)
+}
+
+/// A chain of IPv6 extension headers that nb2 does not model.
+///
+/// The chain is walked generically by the next header and length fields
+/// of each extension header, so the upper layer protocol can be reached
+/// regardless of what extension headers the packet carries. The chain
+/// ends at the first header that is not an extension header.
+///
+/// The chain of a fragment other than the first ends at the fragment
+/// header, what follows is the middle of the fragmented payload. Its next
+/// protocol is `Ipv6NoNxt`, while its next header is still that of the
+/// fragment header.
+///
+/// # Example
+///
+/// ```
+/// let ipv6 = ethernet.parse::<Ipv6>()?;
+/// if is_ipv6_extension(ipv6.next_header()) {
+///     let extensions = ipv6.parse::<Ipv6Extensions<Ipv6>>()?;
+///     let tcp = extensions.parse::<Tcp<Ipv6Extensions<Ipv6>>>()?;
+/// }
+/// ```
+///
+/// # Remarks
+///
+/// The source and destination addresses are those of the envelope. If the
+/// chain contains a routing header, the final destination is not used in
+/// the pseudo-header checksum. Use `SegmentRouting` for packets with a
+/// segment routing header.
+#[derive(Clone)]
+pub struct Ipv6Extensions<E: Ipv6Packet> {
+    envelope: CondRc<E>,
+    header: NonNull<ExtensionHeader>,
+    // the last extension header in the chain, which holds the next header
+    // of the whole chain.
+    last: NonNull<ExtensionHeader>,
+    offset: usize,
+    len: usize,
+    // whether the chain ends at the fragment header of a fragment other
+    // than the first.
+    later_fragment: bool,
+}
+
+impl<E: Ipv6Packet> Ipv6Extensions<E> {
+    /// Returns the types of the extension headers in the chain, in order.
+    pub fn extensions(&self) -> Vec<ProtocolNumber> {
+        let mut extensions = vec![];
+        let mut proto = self.envelope().next_header();
+        let mut offset = self.offset;
+
+        while offset < self.offset + self.len {
+            extensions.push(proto);
+            // the chain was already walked once when parsed.
+            let header = unsafe {
+                self.mbuf()
+                    .read_data::<ExtensionHeader>(offset)
+                    .unwrap()
+                    .as_ref()
+                    .next_header
+            };
+            offset += extension_len(self.mbuf(), offset, proto).unwrap();
+            proto = ProtocolNumber::new(header);
+        }
+
+        extensions
+    }
+
+    /// Returns whether the packet is a fragment other than the first, so
+    /// carries no upper layer header.
+    #[inline]
+    pub fn is_later_fragment(&self) -> bool {
+        self.later_fragment
+    }
+}
+
+impl<E: Ipv6Packet> fmt::Debug for Ipv6Extensions<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ipv6 extensions")
+            .field(
+                "extensions",
+                &self
+                    .extensions()
+                    .iter()
+                    .map(|p| format!("{}", p))
+                    .collect::<Vec<_>>(),
+            )
+            .field("next_header", &format!("{}", self.next_header()))
+            .field("later_fragment", &self.is_later_fragment())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: Ipv6Packet> Packet for Ipv6Extensions<E> {
+    type Header = ExtensionHeader;
+    type Envelope = E;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header(&self) -> &Self::Header {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header_mut(&mut self) -> &mut Self::Header {
+        unsafe { self.header.as_mut() }
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The header length is the length of the whole chain.
+    #[inline]
+    fn header_len(&self) -> usize {
+        self.len
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn do_parse(envelope: Self::Envelope) -> Result<Self> {
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+
+        let mut proto = envelope.next_header();
+        let mut next = offset;
+        let mut last = None;
+        let mut later_fragment = false;
+
+        while is_ipv6_extension(proto) && !later_fragment {
+            let header = mbuf.read_data::<ExtensionHeader>(next)?;
+            if proto == ProtocolNumbers::Ipv6Frag {
+                // the headers after the fragment header are only in the
+                // first fragment.
+                later_fragment = fragment_offset(mbuf, next)? != 0;
+            }
+            next += extension_len(mbuf, next, proto)?;
+            proto = ProtocolNumber::new(unsafe { header.as_ref().next_header });
+            last = Some(header);
+        }
+
+        match last {
+            Some(last) => {
+                let header = mbuf.read_data(offset)?;
+                let len = next - offset;
+
+                // the last header must be entirely in the buffer.
+                if next > mbuf.data_len() {
+                    return Err(ParseError::with_anomaly(
+                        Anomaly::Truncated,
+                        "Packet has truncated extension headers.",
+                    )
+                    .into());
+                }
+
+                Ok(Ipv6Extensions {
+                    envelope: CondRc::new(envelope),
+                    header,
+                    last,
+                    offset,
+                    len,
+                    later_fragment,
+                })
+            }
+            None => Err(ParseError::new("Packet has no IPv6 extension headers.").into()),
+        }
+    }
+
+    /// Pushes an empty destination options header.
+    #[doc(hidden)]
+    #[inline]
+    fn do_push(mut envelope: Self::Envelope) -> Result<Self> {
+        // next header, hdr ext len and a 6-byte PadN option.
+        const EMPTY_OPTIONS: [u8; 8] = [0, 0, 1, 4, 0, 0, 0, 0];
+
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, EMPTY_OPTIONS.len())?;
+        mbuf.write_data_slice(offset, &EMPTY_OPTIONS)?;
+        let header = mbuf.read_data(offset)?;
+
+        let mut packet = Ipv6Extensions {
+            envelope: CondRc::new(envelope),
+            header,
+            last: header,
+            offset,
+            len: EMPTY_OPTIONS.len(),
+            later_fragment: false,
+        };
+
+        packet.set_next_header(packet.envelope().next_header());
+        packet
+            .envelope_mut()
+            .set_next_header(ProtocolNumbers::Ipv6Opts);
+
+        Ok(packet)
+    }
+
+    /// Removes the whole chain of extension headers.
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let offset = self.offset();
+        let len = self.header_len();
+        let next_header = self.next_header();
+        self.mbuf_mut().shrink(offset, len)?;
+        self.envelope_mut().set_next_header(next_header);
+        Ok(self.envelope.into_owned())
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope.into_owned()
+    }
+}
+
+impl<E: Ipv6Packet> IpPacket for Ipv6Extensions<E> {
+    #[inline]
+    fn next_proto(&self) -> ProtocolNumber {
+        if self.later_fragment {
+            ProtocolNumbers::Ipv6NoNxt
+        } else {
+            self.next_header()
+        }
+    }
+
+    #[inline]
+    fn set_next_proto(&mut self, proto: ProtocolNumber) {
+        self.set_next_header(proto);
+    }
+
+    #[inline]
+    fn src(&self) -> IpAddr {
+        self.envelope().src()
+    }
+
+    #[inline]
+    fn set_src(&mut self, src: IpAddr) -> Result<()> {
+        self.envelope_mut().set_src(src)
+    }
+
+    #[inline]
+    fn dst(&self) -> IpAddr {
+        self.envelope().dst()
+    }
+
+    #[inline]
+    fn set_dst(&mut self, dst: IpAddr) -> Result<()> {
+        self.envelope_mut().set_dst(dst)
+    }
+
+    #[inline]
+    fn pseudo_header(&self, packet_len: u16, protocol: ProtocolNumber) -> PseudoHeader {
+        self.envelope().pseudo_header(packet_len, protocol)
+    }
+}
+
+impl<E: Ipv6Packet> Ipv6Packet for Ipv6Extensions<E> {
+    /// Returns the next header of the last extension header in the chain.
+    #[inline]
+    fn next_header(&self) -> ProtocolNumber {
+        ProtocolNumber::new(unsafe { self.last.as_ref().next_header })
+    }
+
+    #[inline]
+    fn set_next_header(&mut self, next_header: ProtocolNumber) {
+        unsafe {
+            self.last.as_mut().next_header = next_header.0;
+        }
+    }
+}
+
+#[cfg(any(test, feature = "testils"))]
+#[rustfmt::skip]
+pub const IPV6_EXTENSIONS_PACKET: [u8; 90] = [
+    // ** ethernet header
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x86, 0xDD,
+    // ** IPv6 header
+    0x60, 0x00, 0x00, 0x00,
+    // payload length
+    0x00, 0x24,
+    // next header (hop-by-hop)
+    0x00,
+    0x02,
+    0x20, 0x01, 0x0d, 0xb8, 0x85, 0xa3, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    0x20, 0x01, 0x0d, 0xb8, 0x85, 0xa3, 0x00, 0x00, 0x00, 0x00, 0x8a, 0x2e, 0x03, 0x70, 0x73, 0x34,
+    // ** hop-by-hop options header
+    // next header (destination options), hdr ext len (8 bytes)
+    0x3c, 0x00,
+    // PadN
+    0x01, 0x04, 0x00, 0x00, 0x00, 0x00,
+    // ** destination options header
+    // next header (udp), hdr ext len (16 bytes)
+    0x11, 0x01,
+    // unrecognized option type 0x1e, skipped by the receiver
+    0x1e, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // ** UDP header
+    // src port = 39376, dst port = 1087
+    0x99, 0xd0, 0x04, 0x3f,
+    // length = 12, checksum
+    0x00, 0x0c, 0x00, 0x00,
+    // ** UDP payload
+    0x01, 0x02, 0x03, 0x04
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v6::{Ipv6, IPV6_PACKET};
+    use crate::packets::{Ethernet, Udp};
+
+    #[test]
+    fn size_of_extension_header() {
+        assert_eq!(2, ExtensionHeader::size_of());
+    }
+
+    #[nb2::test]
+    fn skip_extension_headers() {
+        let packet = Mbuf::from_bytes(&IPV6_EXTENSIONS_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        assert!(is_ipv6_extension(ipv6.next_header()));
+
+        let extensions = ipv6.parse::<Ipv6Extensions<Ipv6>>().unwrap();
+        assert_eq!(24, extensions.header_len());
+        assert_eq!(ProtocolNumbers::Udp, extensions.next_header());
+        assert_eq!(
+            vec![ProtocolNumbers::Ipv6HopByHop, ProtocolNumbers::Ipv6Opts],
+            extensions.extensions()
+        );
+
+        let udp = extensions.parse::<Udp<Ipv6Extensions<Ipv6>>>().unwrap();
+        assert_eq!(39376, udp.src_port());
+        assert_eq!(1087, udp.dst_port());
+        assert_eq!(12, udp.length());
+    }
+
+    #[nb2::test]
+    fn parse_without_extension_headers() {
+        let packet = Mbuf::from_bytes(&IPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert!(ipv6.parse::<Ipv6Extensions<Ipv6>>().is_err());
+    }
+
+    #[nb2::test]
+    fn parse_truncated_extension_headers() {
+        // cuts off the end of the destination options header.
+        let packet = Mbuf::from_bytes(&IPV6_EXTENSIONS_PACKET[..70]).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        assert!(ipv6.parse::<Ipv6Extensions<Ipv6>>().is_err());
+    }
+
+    #[rustfmt::skip]
+    const FRAGMENT_HEADER: [u8; 8] = [
+        // next header (udp), reserved
+        0x11, 0x00,
+        // fragment offset, more fragments
+        0x00, 0x01,
+        // identification
+        0x00, 0x00, 0x00, 0x2a,
+    ];
+
+    // replaces the destination options header of `IPV6_EXTENSIONS_PACKET`
+    // with a fragment header at the fragment offset.
+    fn fragment(offset: u16) -> Vec<u8> {
+        let mut header = FRAGMENT_HEADER;
+        header[2..4].copy_from_slice(&(offset << 3 | 1).to_be_bytes());
+
+        let mut bytes = IPV6_EXTENSIONS_PACKET[..62].to_vec();
+        bytes[19] = 0x1c;
+        bytes[62 - 8] = ProtocolNumbers::Ipv6Frag.0;
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&IPV6_EXTENSIONS_PACKET[78..]);
+        bytes
+    }
+
+    #[nb2::test]
+    fn skip_first_fragment_header() {
+        let packet = Mbuf::from_bytes(&fragment(0)).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        let extensions = ipv6.parse::<Ipv6Extensions<Ipv6>>().unwrap();
+        assert_eq!(16, extensions.header_len());
+        assert!(!extensions.is_later_fragment());
+        assert_eq!(ProtocolNumbers::Udp, extensions.next_proto());
+        assert_eq!(
+            vec![ProtocolNumbers::Ipv6HopByHop, ProtocolNumbers::Ipv6Frag],
+            extensions.extensions()
+        );
+
+        let udp = extensions.parse::<Udp<Ipv6Extensions<Ipv6>>>().unwrap();
+        assert_eq!(39376, udp.src_port());
+        assert_eq!(1087, udp.dst_port());
+    }
+
+    #[nb2::test]
+    fn stop_at_later_fragment_header() {
+        let packet = Mbuf::from_bytes(&fragment(185)).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+
+        let extensions = ipv6.parse::<Ipv6Extensions<Ipv6>>().unwrap();
+        assert_eq!(16, extensions.header_len());
+        assert!(extensions.is_later_fragment());
+        assert_eq!(ProtocolNumbers::Udp, extensions.next_header());
+        assert_eq!(ProtocolNumbers::Ipv6NoNxt, extensions.next_proto());
+        assert_eq!(12, extensions.payload_len());
+    }
+
+    #[nb2::test]
+    fn push_and_remove_extension_headers() {
+        let packet = Mbuf::from_bytes(&IPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let len = ipv6.len();
+
+        let extensions = ipv6.push::<Ipv6Extensions<Ipv6>>().unwrap();
+        assert_eq!(8, extensions.header_len());
+        assert_eq!(ProtocolNumbers::Tcp, extensions.next_header());
+        assert_eq!(
+            ProtocolNumbers::Ipv6Opts,
+            extensions.envelope().next_header()
+        );
+        assert_eq!(len + 8, extensions.envelope().len());
+
+        let ipv6 = extensions.remove().unwrap();
+        assert_eq!(ProtocolNumbers::Tcp, ipv6.next_header());
+        assert_eq!(len, ipv6.len());
+    }
+}
@@ -1,11 +1,17 @@
+mod ext;
 mod srh;
 
+pub use self::ext::*;
 pub use self::srh::*;
 
 use crate::packets::checksum::PseudoHeader;
-use crate::packets::ip::{IpAddrMismatchError, IpPacket, ProtocolNumber};
-use crate::packets::{CondRc, EtherTypes, Ethernet, Header, Packet};
-use crate::{Result, SizeOf};
+use crate::packets::ip::{IpAddrMismatchError, IpPacket, ProtocolNumber, ProtocolNumbers};
+use crate::packets::{
+    be16, be32, data_slice, data_slice_mut, CondRc, EtherTypes, Ethernet, Header, Packet,
+    ParseError,
+};
+use crate::stats::Anomaly;
+use crate::{ensure, Result, SizeOf};
 use std::fmt;
 use std::net::{IpAddr, Ipv6Addr};
 use std::ptr::NonNull;
@@ -24,6 +30,12 @@ pub trait Ipv6Packet: IpPacket {
 /// https://tools.ietf.org/html/rfc2460#section-5
 pub const IPV6_MIN_MTU: usize = 1280;
 
+// the jumbo payload option type and data length.
+//
+// https://tools.ietf.org/html/rfc2675#section-2
+const JUMBO_OPTION: u8 = 0xC2;
+const JUMBO_OPTION_LEN: u8 = 4;
+
 /*  From https://tools.ietf.org/html/rfc8200#section-3
     and https://tools.ietf.org/html/rfc3168 (succeeding traffic class)
     IPv6 Header Format
@@ -114,8 +126,8 @@ const FLOW: u32 = 0xfffff;
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct Ipv6Header {
-    version_to_flow_label: u32,
-    payload_length: u16,
+    version_to_flow_label: be32,
+    payload_length: be16,
     next_header: u8,
     hop_limit: u8,
     src: Ipv6Addr,
@@ -125,8 +137,8 @@ pub struct Ipv6Header {
 impl Default for Ipv6Header {
     fn default() -> Ipv6Header {
         Ipv6Header {
-            version_to_flow_label: u32::to_be(6 << 28),
-            payload_length: 0,
+            version_to_flow_label: be32::new(6 << 28),
+            payload_length: be16::default(),
             next_header: 0,
             hop_limit: 0,
             src: Ipv6Addr::UNSPECIFIED,
@@ -143,61 +155,63 @@ pub struct Ipv6 {
     envelope: CondRc<Ethernet>,
     header: NonNull<Ipv6Header>,
     offset: usize,
+    dirty: bool,
 }
 
 impl Ipv6 {
     #[inline]
     pub fn version(&self) -> u8 {
         // Protocol Version, should always be `6`
-        ((u32::from_be(self.header().version_to_flow_label) & 0xf000_0000) >> 28) as u8
+        ((self.header().version_to_flow_label.get() & 0xf000_0000) >> 28) as u8
     }
 
     #[inline]
     pub fn dscp(&self) -> u8 {
-        ((u32::from_be(self.header().version_to_flow_label) & DSCP) >> 22) as u8
+        ((self.header().version_to_flow_label.get() & DSCP) >> 22) as u8
     }
 
     #[inline]
     pub fn set_dscp(&mut self, dscp: u8) {
-        self.header_mut().version_to_flow_label = u32::to_be(
-            (u32::from_be(self.header().version_to_flow_label) & !DSCP)
-                | ((u32::from(dscp) << 22) & DSCP),
-        );
+        self.header_mut().version_to_flow_label = ((self.header().version_to_flow_label.get()
+            & !DSCP)
+            | ((u32::from(dscp) << 22) & DSCP))
+            .into();
     }
 
     #[inline]
     pub fn ecn(&self) -> u8 {
-        ((u32::from_be(self.header().version_to_flow_label) & ECN) >> 20) as u8
+        ((self.header().version_to_flow_label.get() & ECN) >> 20) as u8
     }
 
     #[inline]
     pub fn set_ecn(&mut self, ecn: u8) {
-        self.header_mut().version_to_flow_label = u32::to_be(
-            (u32::from_be(self.header().version_to_flow_label) & !ECN)
-                | ((u32::from(ecn) << 20) & ECN),
-        );
+        self.header_mut().version_to_flow_label =
+            ((self.header().version_to_flow_label.get() & !ECN) | ((u32::from(ecn) << 20) & ECN))
+                .into();
     }
 
+    /// Returns the 20-bit flow label.
     #[inline]
     pub fn flow_label(&self) -> u32 {
-        u32::from_be(self.header().version_to_flow_label) & FLOW
+        self.header().version_to_flow_label.get() & FLOW
     }
 
+    /// Sets the flow label. Only the lower 20 bits of `flow_label` are
+    /// used. To label the packet from its 5-tuple, use `Flow::flow_label`.
     #[inline]
     pub fn set_flow_label(&mut self, flow_label: u32) {
-        self.header_mut().version_to_flow_label = u32::to_be(
-            (u32::from_be(self.header().version_to_flow_label) & !FLOW) | (flow_label & FLOW),
-        );
+        self.header_mut().version_to_flow_label =
+            ((self.header().version_to_flow_label.get() & !FLOW) | (flow_label & FLOW)).into();
     }
 
     #[inline]
     pub fn payload_length(&self) -> u16 {
-        u16::from_be(self.header().payload_length)
+        self.header().payload_length.get()
     }
 
     #[inline]
     fn set_payload_length(&mut self, payload_length: u16) {
-        self.header_mut().payload_length = u16::to_be(payload_length);
+        self.header_mut().payload_length = payload_length.into();
     }
 
     #[inline]
@@ -229,6 +243,142 @@ impl Ipv6 {
     pub fn set_dst(&mut self, dst: Ipv6Addr) {
         self.header_mut().dst = dst;
     }
+
+    /// Returns the offset of the jumbo payload length, if the packet has a
+    /// jumbo payload option.
+    ///
+    /// The option can only be in the hop-by-hop options header, which can
+    /// only immediately follow the IPv6 header.
+    fn jumbo_option_offset(&self) -> Option<usize> {
+        if self.next_header() != ProtocolNumbers::Ipv6HopByHop {
+            return None;
+        }
+
+        let offset = self.payload_offset();
+        let header = data_slice(self.mbuf(), offset, 2);
+        if header.len() < 2 {
+            return None;
+        }
+
+        let end = offset + (header[1] as usize + 1) * 8;
+        let mut option = offset + 2;
+        while option < end {
+            match data_slice(self.mbuf(), option, 2) {
+                // Pad1 is the only option without a length.
+                [0, _] => option += 1,
+                [JUMBO_OPTION, JUMBO_OPTION_LEN] => return Some(option + 2),
+                [_, len] => option += 2 + *len as usize,
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    /// Returns the payload length of a jumbogram, carried in the jumbo
+    /// payload option instead of the payload length field, which is `0`.
+    /// `None` if the packet is not a jumbogram.
+    ///
+    /// The length includes the hop-by-hop options header.
+    #[inline]
+    pub fn jumbo_payload_length(&self) -> Option<u32> {
+        let offset = self.jumbo_option_offset()?;
+        match data_slice(self.mbuf(), offset, 4) {
+            [a, b, c, d] => Some(u32::from_be_bytes([*a, *b, *c, *d])),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the packet is a valid jumbogram.
+    ///
+    /// The payload length field must be `0`, the jumbo payload length must
+    /// be over 65,535, and there must be no fragment header, as required
+    /// by RFC 2675.
+    #[inline]
+    pub fn is_jumbogram(&self) -> bool {
+        self.payload_length() == 0
+            && self
+                .jumbo_payload_length()
+                .map_or(false, |len| len > u32::from(u16::max_value()))
+            && !self.has_fragment_header()
+    }
+
+    /// Returns whether there's a fragment header in the chain of extension
+    /// headers.
+    fn has_fragment_header(&self) -> bool {
+        let mut proto = self.next_header();
+        let mut offset = self.payload_offset();
+
+        while is_ipv6_extension(proto) {
+            if proto == ProtocolNumbers::Ipv6Frag {
+                return true;
+            }
+
+            // the chain runs past the buffer.
+            let header = data_slice(self.mbuf(), offset, 2);
+            if header.len() < 2 {
+                return false;
+            }
+
+            offset += match proto {
+                ProtocolNumbers::Ah => (header[1] as usize + 2) * 4,
+                _ => (header[1] as usize + 1) * 8,
+            };
+            proto = ProtocolNumber::new(header[0]);
+        }
+
+        false
+    }
+
+    /// Returns the payload length, whether the packet is a jumbogram or not.
+    #[inline]
+    pub fn full_payload_length(&self) -> u32 {
+        if self.is_jumbogram() {
+            self.jumbo_payload_length().unwrap_or_default()
+        } else {
+            u32::from(self.payload_length())
+        }
+    }
+
+    /// Makes the packet a jumbogram, by inserting a hop-by-hop options
+    /// header with the jumbo payload option.
+    ///
+    /// The lengths are set by `cascade`. A payload over 64KB does not fit
+    /// in a single buffer, the packet has to be a chain of segments. A
+    /// smaller payload, or a fragment header, makes an invalid jumbogram.
+    /// It's a noop if the packet already has the jumbo payload option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packet already has a hop-by-hop options
+    /// header without the jumbo option, or if the buffer cannot be
+    /// extended.
+    pub fn set_jumbogram(&mut self) -> Result<()> {
+        if self.jumbo_option_offset().is_some() {
+            return Ok(());
+        }
+
+        ensure!(
+            self.next_header() != ProtocolNumbers::Ipv6HopByHop,
+            ParseError::new("Packet already has a hop-by-hop options header.")
+        );
+
+        let offset = self.payload_offset();
+        let options = [
+            self.next_header().0,
+            0,
+            JUMBO_OPTION,
+            JUMBO_OPTION_LEN,
+            0,
+            0,
+            0,
+            0,
+        ];
+        self.mbuf_mut().extend(offset, options.len())?;
+        self.mbuf_mut().write_data_slice(offset, &options)?;
+        self.set_next_header(ProtocolNumbers::Ipv6HopByHop);
+        Ok(())
+    }
 }
 
 impl fmt::Debug for Ipv6 {
@@ -272,6 +422,7 @@ impl Packet for Ipv6 {
     #[doc(hidden)]
     #[inline]
     fn header_mut(&mut self) -> &mut Self::Header {
+        self.dirty = true;
         unsafe { self.header.as_mut() }
     }
 
@@ -280,17 +431,32 @@ impl Packet for Ipv6 {
         self.offset
     }
 
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     #[doc(hidden)]
     #[inline]
     fn do_parse(envelope: Self::Envelope) -> Result<Self> {
         let mbuf = envelope.mbuf();
         let offset = envelope.payload_offset();
-        let header = mbuf.read_data(offset)?;
+        let header = mbuf.read_data::<Ipv6Header>(offset)?;
+        ensure!(
+            unsafe { header.as_ref() }.version_to_flow_label.get() >> 28 == 6,
+            ParseError::with_anomaly(Anomaly::BadVersion, "Not an IPv6 packet.")
+        );
 
         Ok(Ipv6 {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: false,
         })
     }
 
@@ -309,6 +475,7 @@ impl Packet for Ipv6 {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: true,
         })
     }
 
@@ -320,11 +487,30 @@ impl Packet for Ipv6 {
         Ok(self.envelope.into_owned())
     }
 
+    /// Sets the payload length. The length of a jumbogram is set in its
+    /// jumbo payload option, and it includes the segments chained to the
+    /// buffer.
     #[inline]
-    fn cascade(&mut self) {
-        let len = self.payload_len() as u16;
-        self.set_payload_length(len);
-        self.envelope_mut().cascade();
+    fn reconcile(&mut self) {
+        if let Some(offset) = self.jumbo_option_offset() {
+            let len = (self.mbuf().pkt_len() - self.payload_offset()) as u32;
+            let data = data_slice_mut(self.mbuf_mut(), offset, 4);
+            if data.len() == 4 {
+                data.copy_from_slice(&len.to_be_bytes());
+            }
+            if self.payload_length() != 0 {
+                self.set_payload_length(0);
+            }
+        } else {
+            let len = self.payload_len() as u16;
+            if self.payload_length() != len {
+                self.set_payload_length(len);
+            }
+        }
+
+        // there's no header checksum, the flag is only kept so upper layer
+        // checksums can tell whether the pseudo-header changed.
+        self.dirty = false;
     }
 
     #[inline]
@@ -462,6 +648,59 @@ mod tests {
         assert_eq!("2001:db8:85a3::8a2e:370:7334", ipv6.dst().to_string());
     }
 
+    #[nb2::test]
+    fn make_jumbogram() {
+        let packet = Mbuf::from_bytes(&IPV6_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        assert!(!ipv6.is_jumbogram());
+        assert_eq!(24, ipv6.full_payload_length());
+
+        ipv6.set_jumbogram().unwrap();
+        ipv6.cascade();
+
+        assert_eq!(0, ipv6.payload_length());
+        assert_eq!(Some(32), ipv6.jumbo_payload_length());
+        // too short to be a valid jumbogram.
+        assert!(!ipv6.is_jumbogram());
+
+        let extensions = ipv6.parse::<Ipv6Extensions<Ipv6>>().unwrap();
+        assert_eq!(vec![ProtocolNumbers::Ipv6HopByHop], extensions.extensions());
+        assert_eq!(ProtocolNumbers::Tcp, extensions.next_header());
+
+        // not a jumbogram, the hop-by-hop header has no jumbo option.
+        let packet = Mbuf::from_bytes(&IPV6_EXTENSIONS_PACKET).unwrap();
+        let mut ipv6 = packet.parse::<Ethernet>().unwrap().parse::<Ipv6>().unwrap();
+        assert_eq!(None, ipv6.jumbo_payload_length());
+        assert!(ipv6.set_jumbogram().is_err());
+    }
+
+    #[nb2::test]
+    fn validate_jumbogram() {
+        let packet = Mbuf::from_bytes(&IPV6_PACKET).unwrap();
+        let mut ipv6 = packet.parse::<Ethernet>().unwrap().parse::<Ipv6>().unwrap();
+        ipv6.set_jumbogram().unwrap();
+
+        // the jumbo payload length, after the option type and length.
+        let offset = ipv6.payload_offset() + 4;
+        ipv6.mbuf_mut()
+            .write_data_slice(offset, &70_000u32.to_be_bytes())
+            .unwrap();
+        assert!(ipv6.is_jumbogram());
+        assert_eq!(70_000, ipv6.full_payload_length());
+
+        ipv6.set_payload_length(8);
+        assert!(!ipv6.is_jumbogram());
+        ipv6.set_payload_length(0);
+
+        // a fragment header after the hop-by-hop options.
+        let offset = ipv6.payload_offset();
+        ipv6.mbuf_mut()
+            .write_data(offset, &ProtocolNumbers::Ipv6Frag.0)
+            .unwrap();
+        assert!(!ipv6.is_jumbogram());
+    }
+
     #[nb2::test]
     fn parse_ipv6_setter_checks() {
         let packet = Mbuf::from_bytes(&IPV6_PACKET).unwrap();
@@ -478,6 +717,13 @@ mod tests {
         assert_eq!(10, ipv6.dscp());
         assert_eq!(3, ipv6.ecn());
         assert_eq!(0, ipv6.flow_label());
+        ipv6.set_flow_label(0x1_2345);
+        assert_eq!(10, ipv6.dscp());
+        assert_eq!(3, ipv6.ecn());
+        assert_eq!(0x1_2345, ipv6.flow_label());
+        ipv6.set_flow_label(0xfff0_0001);
+        assert_eq!(6, ipv6.version());
+        assert_eq!(1, ipv6.flow_label());
     }
 
     #[nb2::test]
@@ -1,8 +1,9 @@
 use crate::packets::checksum::PseudoHeader;
 use crate::packets::ip::v6::Ipv6Packet;
 use crate::packets::ip::{IpPacket, ProtocolNumber, ProtocolNumbers};
-use crate::packets::{CondRc, Header, Packet, ParseError};
-use crate::{Result, SizeOf};
+use crate::packets::{be16, CondRc, Header, Packet, ParseError};
+use crate::stats::Anomaly;
+use crate::{ensure, Result, SizeOf};
 use failure::Fail;
 use std::fmt;
 use std::net::{IpAddr, Ipv6Addr};
@@ -101,7 +102,7 @@ pub struct SegmentRoutingHeader {
     segments_left: u8,
     last_entry: u8,
     flags: u8,
-    tag: u16,
+    tag: be16,
 }
 
 impl Default for SegmentRoutingHeader {
@@ -113,7 +114,7 @@ impl Default for SegmentRoutingHeader {
             segments_left: 0,
             last_entry: 0,
             flags: 0,
-            tag: 0,
+            tag: be16::default(),
         }
     }
 }
@@ -125,6 +126,14 @@ impl Header for SegmentRoutingHeader {}
 #[fail(display = "Segment list length must be greater than 0")]
 pub struct BadSegmentsError;
 
+/// Error when the segments left cannot be advanced to the next segment.
+#[derive(Debug, Fail)]
+#[fail(
+    display = "Cannot advance {} segments left in a list of {} segments.",
+    _0, _1
+)]
+pub struct SegmentsLeftError(u8, usize);
+
 #[derive(Clone)]
 pub struct SegmentRouting<E: Ipv6Packet> {
     envelope: CondRc<E>,
@@ -164,12 +173,50 @@ impl<E: Ipv6Packet> SegmentRouting<E> {
     /// # Remarks
     ///
     /// Should also call `set_dst` on `Ipv6` to keep the packet's destination
-    /// in sync with the segment routing header.
+    /// in sync with the segment routing header. `advance` does both.
     #[inline]
     pub fn set_segments_left(&mut self, segments_left: u8) {
         self.header_mut().segments_left = segments_left;
     }
 
+    /// Advances the packet to the next segment, and returns it.
+    ///
+    /// Decrements segments left and sets the destination of the IPv6
+    /// header to the segment it then points to, as an SR endpoint does
+    /// when processing the header. The Tcp and Udp checksums are not
+    /// affected, their pseudo header uses the final destination.
+    ///
+    /// # Errors
+    ///
+    /// If segments left is `0`, or exceeds the number of segments, the
+    /// packet is not modified and `SegmentsLeftError` is returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone()).map(|packet| {
+    ///     let mut srh = packet
+    ///         .parse::<Ethernet>()?
+    ///         .parse::<Ipv6>()?
+    ///         .parse::<SegmentRouting<Ipv6>>()?;
+    ///     srh.advance()?;
+    ///     Ok(srh)
+    /// });
+    /// ```
+    pub fn advance(&mut self) -> Result<Ipv6Addr> {
+        let segments_left = self.segments_left();
+        let len = self.segments().len();
+        ensure!(
+            segments_left > 0 && segments_left as usize <= len,
+            SegmentsLeftError(segments_left, len)
+        );
+
+        let next = self.segments()[segments_left as usize - 1];
+        self.set_segments_left(segments_left - 1);
+        self.envelope_mut().set_dst(IpAddr::V6(next))?;
+        Ok(next)
+    }
+
     #[inline]
     pub fn last_entry(&self) -> u8 {
         self.header().last_entry
@@ -187,12 +234,12 @@ impl<E: Ipv6Packet> SegmentRouting<E> {
 
     #[inline]
     pub fn tag(&self) -> u16 {
-        u16::from_be(self.header().tag)
+        self.header().tag.get()
     }
 
     #[inline]
     pub fn set_tag(&mut self, tag: u16) {
-        self.header_mut().tag = u16::to_be(tag);
+        self.header_mut().tag = tag.into();
     }
 
     #[inline]
@@ -200,6 +247,28 @@ impl<E: Ipv6Packet> SegmentRouting<E> {
         unsafe { self.segments.as_ref() }
     }
 
+    /// Returns the segment list as a mutable slice.
+    ///
+    /// The segments are modified in place and the buffer is not resized,
+    /// for example to replace the active segment. To add or remove
+    /// segments, use `set_segments` instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let index = srh.segments_left() as usize;
+    /// srh.segments_mut()[index] = next_sid;
+    /// ```
+    ///
+    /// # Remarks
+    ///
+    /// The first segment is the final destination, replacing it affects the
+    /// Tcp and Udp checksum calculations the same way `set_segments` does.
+    #[inline]
+    pub fn segments_mut(&mut self) -> &mut [Ipv6Addr] {
+        unsafe { self.segments.as_mut() }
+    }
+
     /// Sets the segment list.
     ///
     /// # Examples
@@ -311,7 +380,11 @@ impl<E: Ipv6Packet> Packet for SegmentRouting<E> {
                 offset,
             })
         } else {
-            Err(ParseError::new("Packet has inconsistent segment list length.").into())
+            Err(ParseError::with_anomaly(
+                Anomaly::BadLength,
+                "Packet has inconsistent segment list length.",
+            )
+            .into())
         }
     }
 
@@ -387,12 +460,7 @@ impl<E: Ipv6Packet> IpPacket for SegmentRouting<E> {
     #[inline]
     fn set_dst(&mut self, dst: IpAddr) -> Result<()> {
         if let IpAddr::V6(v6_dst) = dst {
-            let mut segments = vec![v6_dst];
-            for segment in self.segments().iter().skip(1) {
-                segments.push(*segment)
-            }
-
-            self.set_segments(&segments)?;
+            self.segments_mut()[0] = v6_dst;
 
             if self.segments_left() == 0 {
                 self.envelope_mut().set_dst(dst)
@@ -575,6 +643,48 @@ mod tests {
         assert_eq!(3464, tcp.src_port())
     }
 
+    #[nb2::test]
+    fn modify_segments_in_place() {
+        let packet = Mbuf::from_bytes(&SRH_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let mut srh = ipv6.parse::<SegmentRouting<Ipv6>>().unwrap();
+        let len = srh.len();
+
+        let sid: Ipv6Addr = "::2".parse().unwrap();
+        srh.segments_mut()[1] = sid;
+        assert_eq!(len, srh.len());
+        assert_eq!(3, srh.segments().len());
+
+        // reparses to read the segment back from the buffer.
+        let ipv6 = srh.deparse();
+        let srh = ipv6.parse::<SegmentRouting<Ipv6>>().unwrap();
+        assert_eq!(sid, srh.segments()[1]);
+    }
+
+    #[nb2::test]
+    fn advance_to_next_segment() {
+        let mut bytes = SRH_PACKET;
+        // segments left
+        bytes[57] = 2;
+        let packet = Mbuf::from_bytes(&bytes).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv6 = ethernet.parse::<Ipv6>().unwrap();
+        let mut srh = ipv6.parse::<SegmentRouting<Ipv6>>().unwrap();
+        let segments = srh.segments().to_vec();
+
+        assert_eq!(segments[1], srh.advance().unwrap());
+        assert_eq!(1, srh.segments_left());
+        assert_eq!(IpAddr::V6(segments[1]), srh.envelope().dst());
+
+        assert_eq!(segments[0], srh.advance().unwrap());
+        assert_eq!(0, srh.segments_left());
+        assert_eq!(IpAddr::V6(segments[0]), srh.envelope().dst());
+
+        assert!(srh.advance().is_err());
+        assert_eq!(IpAddr::V6(segments[0]), srh.envelope().dst());
+    }
+
     #[nb2::test]
     fn check_checksum() {
         let packet = Mbuf::from_bytes(&SRH_PACKET).unwrap();
@@ -0,0 +1,389 @@
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::Ipv6;
+use crate::packets::ip::{IpPacket, ProtocolNumbers};
+use crate::packets::{be16, be32, data_slice, CondRc, Ethernet, Header, Packet, ParseError, Udp};
+use crate::{ensure, Result, SizeOf};
+use failure::Fail;
+use std::fmt;
+use std::ptr::NonNull;
+
+/*  From https://tools.ietf.org/html/rfc3931#section-4.1
+    L2TPv3 Session Header Over IP
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                           Session ID                          |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |               Cookie (optional, maximum 64 bits)...
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+                                                                    |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    L2TPv3 Session Header over UDP
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |T|x|x|x|x|x|x|x|x|x|x|x|  Ver  |             Reserved          |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                           Session ID                          |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |               Cookie (optional, maximum 64 bits)...
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+                                                                    |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    Session ID  A non-zero identifier for the session. Over IP, a session
+                ID of zero marks a control message.
+
+    Cookie      An optional 4 or 8 octets field, whose length is negotiated
+                for the session and is not carried in the packet.
+
+    T           Over UDP, a set T bit marks a control message.
+
+    Ver         Must be 3.
+*/
+
+/// The well-known UDP port of L2TP.
+pub const L2TP_PORT: u16 = 1701;
+
+const VERSION_3: u16 = 3;
+const T_FLAG: u16 = 0x8000;
+
+/// Error when the length of a cookie is not allowed.
+#[derive(Debug, Fail)]
+#[fail(display = "Cookie length must be 0, 4 or 8, not {}.", _0)]
+pub struct BadCookieLength(usize);
+
+/// A packet that can carry L2TPv3 data messages.
+///
+/// L2TPv3 runs either directly over IPv4 and IPv6, or over UDP.
+pub trait L2tpv3Transport: Packet {
+    /// The length of the transport specific fields preceding the session
+    /// ID.
+    const PREFIX_LEN: usize;
+
+    /// Marks the payload as L2TPv3 when a session header is pushed.
+    #[doc(hidden)]
+    fn mark_l2tpv3(&mut self);
+}
+
+impl L2tpv3Transport for Ipv4 {
+    const PREFIX_LEN: usize = 0;
+
+    #[inline]
+    fn mark_l2tpv3(&mut self) {
+        self.set_next_proto(ProtocolNumbers::L2tp);
+    }
+}
+
+impl L2tpv3Transport for Ipv6 {
+    const PREFIX_LEN: usize = 0;
+
+    #[inline]
+    fn mark_l2tpv3(&mut self) {
+        self.set_next_proto(ProtocolNumbers::L2tp);
+    }
+}
+
+impl<E: IpPacket> L2tpv3Transport for Udp<E> {
+    const PREFIX_LEN: usize = 4;
+
+    #[inline]
+    fn mark_l2tpv3(&mut self) {
+        // noop, the ports are for the caller to pick.
+    }
+}
+
+/// L2TPv3 session header.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct L2tpv3Header {
+    session_id: be32,
+}
+
+impl Header for L2tpv3Header {}
+
+/// L2TPv3 data message, carrying an Ethernet pseudowire.
+///
+/// Only data messages are supported. Parsing a control message fails.
+///
+/// The length of the cookie is agreed on by the two ends when the session
+/// is set up. A newly parsed packet assumes no cookie, use `with_cookie_len`
+/// to apply the negotiated length before reading the payload.
+///
+/// # Example
+///
+/// ```
+/// let l2tp = ipv4.parse::<L2tpv3<Ipv4>>()?.with_cookie_len(8)?;
+/// if l2tp.session_id() == session_id && l2tp.cookie() == cookie {
+///     let ethernet = l2tp.decapsulate()?;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct L2tpv3<E: L2tpv3Transport> {
+    envelope: CondRc<E>,
+    header: NonNull<L2tpv3Header>,
+    offset: usize,
+    cookie_len: usize,
+}
+
+impl<E: L2tpv3Transport> L2tpv3<E> {
+    #[inline]
+    pub fn session_id(&self) -> u32 {
+        self.header().session_id.get()
+    }
+
+    #[inline]
+    pub fn set_session_id(&mut self, session_id: u32) {
+        self.header_mut().session_id = session_id.into();
+    }
+
+    #[inline]
+    fn cookie_offset(&self) -> usize {
+        self.offset + E::PREFIX_LEN + L2tpv3Header::size_of()
+    }
+
+    #[inline]
+    pub fn cookie(&self) -> &[u8] {
+        data_slice(self.mbuf(), self.cookie_offset(), self.cookie_len)
+    }
+
+    /// Applies the negotiated cookie length to a parsed packet.
+    ///
+    /// The buffer is not changed. Use `set_cookie` to change the cookie.
+    pub fn with_cookie_len(mut self, len: usize) -> Result<Self> {
+        ensure!(len == 0 || len == 4 || len == 8, BadCookieLength(len));
+        if len > 0 {
+            let _ = self
+                .mbuf()
+                .read_data_slice::<u8>(self.cookie_offset(), len)?;
+        }
+        self.cookie_len = len;
+        Ok(self)
+    }
+
+    /// Sets the cookie, resizing the header if the length changes.
+    pub fn set_cookie(&mut self, cookie: &[u8]) -> Result<()> {
+        let len = cookie.len();
+        ensure!(len == 0 || len == 4 || len == 8, BadCookieLength(len));
+
+        let offset = self.cookie_offset();
+        let delta = len as isize - self.cookie_len as isize;
+        if delta != 0 {
+            self.mbuf_mut().resize(offset, delta)?;
+            self.cookie_len = len;
+        }
+        if len > 0 {
+            self.mbuf_mut().write_data_slice(offset, cookie)?;
+        }
+        Ok(())
+    }
+
+    /// Strips the outer headers and returns the pseudowire Ethernet frame.
+    pub fn decapsulate(self) -> Result<Ethernet> {
+        let len = self.payload_offset();
+        let mut mbuf = self.reset();
+        mbuf.shrink(0, len)?;
+        mbuf.parse::<Ethernet>()
+    }
+}
+
+impl<E: L2tpv3Transport> fmt::Debug for L2tpv3<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("l2tpv3")
+            .field("session_id", &self.session_id())
+            .field("cookie", &self.cookie())
+            .finish()
+    }
+}
+
+impl<E: L2tpv3Transport> Packet for L2tpv3<E> {
+    type Envelope = E;
+    type Header = L2tpv3Header;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header(&self) -> &Self::Header {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header_mut(&mut self) -> &mut Self::Header {
+        unsafe { self.header.as_mut() }
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        E::PREFIX_LEN + L2tpv3Header::size_of() + self.cookie_len
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn do_parse(envelope: Self::Envelope) -> Result<Self> {
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+
+        if E::PREFIX_LEN > 0 {
+            let flags = unsafe { mbuf.read_data::<be16>(offset)?.as_ref().get() };
+            ensure!(
+                flags & 0x000f == VERSION_3,
+                ParseError::new("Not a L2TPv3 message.")
+            );
+            ensure!(
+                flags & T_FLAG == 0,
+                ParseError::new("Not a L2TPv3 data message.")
+            );
+        }
+
+        let header = mbuf.read_data::<L2tpv3Header>(offset + E::PREFIX_LEN)?;
+        ensure!(
+            unsafe { header.as_ref() }.session_id.get() != 0,
+            ParseError::new("Not a L2TPv3 data message.")
+        );
+
+        Ok(L2tpv3 {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+            cookie_len: 0,
+        })
+    }
+
+    /// Pushes a session header without a cookie.
+    #[doc(hidden)]
+    #[inline]
+    fn do_push(mut envelope: Self::Envelope) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, E::PREFIX_LEN + Self::Header::size_of())?;
+        if E::PREFIX_LEN > 0 {
+            // version 3, the reserved field is zero.
+            mbuf.write_data(offset, &be16::new(VERSION_3))?;
+            mbuf.write_data(offset + 2, &be16::default())?;
+        }
+        let header = mbuf.write_data(offset + E::PREFIX_LEN, &Self::Header::default())?;
+
+        envelope.mark_l2tpv3();
+
+        Ok(L2tpv3 {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+            cookie_len: 0,
+        })
+    }
+
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let offset = self.offset();
+        let len = self.header_len();
+        self.mbuf_mut().shrink(offset, len)?;
+        Ok(self.envelope.into_owned())
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::{EtherTypes, EthernetHeader, UDP_PACKET};
+    use crate::Mbuf;
+    use std::net::Ipv4Addr;
+
+    // wraps the UDP packet in an Ethernet pseudowire over IPv4.
+    fn encapsulate(cookie: &[u8]) -> L2tpv3<Ipv4> {
+        let mut packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        packet.extend(0, EthernetHeader::size_of()).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut ipv4 = ethernet.push::<Ipv4>().unwrap();
+        ipv4.set_src(Ipv4Addr::new(10, 0, 0, 1));
+        ipv4.set_dst(Ipv4Addr::new(10, 0, 0, 2));
+        let mut l2tp = ipv4.push::<L2tpv3<Ipv4>>().unwrap();
+        l2tp.set_session_id(0x1234_5678);
+        l2tp.set_cookie(cookie).unwrap();
+        l2tp.cascade();
+        l2tp
+    }
+
+    #[test]
+    fn size_of_l2tpv3_header() {
+        assert_eq!(4, L2tpv3Header::size_of());
+    }
+
+    #[nb2::test]
+    fn push_l2tpv3_over_ipv4() {
+        let l2tp = encapsulate(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert_eq!(ProtocolNumbers::L2tp, l2tp.envelope().next_proto());
+        assert_eq!(0x1234_5678, l2tp.session_id());
+        assert_eq!(&[1, 2, 3, 4, 5, 6, 7, 8], l2tp.cookie());
+        assert_eq!(12, l2tp.header_len());
+        assert_eq!(UDP_PACKET.len(), l2tp.payload_len());
+    }
+
+    #[nb2::test]
+    fn parse_and_decapsulate_l2tpv3() {
+        let packet = encapsulate(&[1, 2, 3, 4]).reset();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let l2tp = ipv4.parse::<L2tpv3<Ipv4>>().unwrap();
+
+        // the cookie length is not in the packet.
+        assert!(l2tp.cookie().is_empty());
+        let l2tp = l2tp.with_cookie_len(4).unwrap();
+        assert_eq!(&[1, 2, 3, 4], l2tp.cookie());
+
+        let inner = l2tp.decapsulate().unwrap();
+        assert_eq!(EtherTypes::Ipv4, inner.ether_type());
+        assert_eq!(UDP_PACKET.len(), inner.len());
+        assert!(inner.parse::<Ipv4>().unwrap().parse::<Udp<Ipv4>>().is_ok());
+    }
+
+    #[nb2::test]
+    fn push_l2tpv3_over_udp() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        udp.set_dst_port(L2TP_PORT);
+        let mut l2tp = udp.push::<L2tpv3<Udp<Ipv4>>>().unwrap();
+        l2tp.set_session_id(7);
+        l2tp.cascade();
+
+        let udp = l2tp.deparse();
+        let l2tp = udp.parse::<L2tpv3<Udp<Ipv4>>>().unwrap();
+        assert_eq!(7, l2tp.session_id());
+        assert_eq!(8, l2tp.header_len());
+    }
+
+    #[test]
+    fn bad_cookie_length() {
+        assert_eq!(
+            "Cookie length must be 0, 4 or 8, not 3.",
+            BadCookieLength(3).to_string()
+        );
+    }
+}
@@ -76,11 +76,21 @@ impl Packet for Mbuf {
         Ok(self)
     }
 
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        false
+    }
+
     #[inline]
     fn cascade(&mut self) {
         // noop
     }
 
+    #[inline]
+    fn cascade_depth(&mut self, _depth: usize) {
+        // noop
+    }
+
     #[inline]
     fn deparse(self) -> Self::Envelope {
         self
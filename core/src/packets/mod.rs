@@ -1,20 +1,47 @@
+mod any;
+mod bgp;
+mod builder;
 pub mod checksum;
 mod ethernet;
+mod gtpc;
 pub mod icmp;
 pub mod ip;
+mod l2tpv3;
 mod mbuf;
+mod ospf;
+mod pppoe;
+mod raw;
+mod registry;
 mod tcp;
+mod types;
 mod udp;
+mod walk;
 
+pub use self::any::*;
+pub use self::bgp::*;
+pub use self::builder::*;
 pub use self::ethernet::*;
+pub use self::gtpc::*;
+pub use self::l2tpv3::*;
+pub use self::ospf::*;
+pub use self::pppoe::*;
+pub use self::raw::*;
+pub use self::registry::{
+    register_ether_type, register_ether_type_with, register_ip_protocol, BuiltinParserError,
+};
 pub use self::tcp::*;
+pub use self::types::*;
 pub use self::udp::*;
+pub use self::walk::*;
 
+use crate::stats::Anomaly;
 use crate::{Mbuf, Result, SizeOf};
 use failure::Fail;
 use std::fmt;
 use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
 use std::ops::{Deref, DerefMut};
+use std::ptr;
 use std::rc::Rc;
 
 /// Packet header marker trait.
@@ -149,16 +176,114 @@ pub trait Packet: Clone {
     where
         Self: Sized;
 
+    /// Returns whether the packet has been modified since it was parsed or
+    /// last cascaded.
+    ///
+    /// Packets that do not track modifications are always considered
+    /// modified.
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        true
+    }
+
+    /// Marks the packet as modified, for the changes made to its bytes
+    /// through the mbuf, so `cascade` fixes up its lengths and checksums.
+    ///
+    /// Packets that do not track modifications ignore it.
+    #[inline]
+    fn mark_dirty(&mut self) {
+        // noop
+    }
+
+    /// Fixes up the lengths and checksums of this packet only.
+    ///
+    /// Implementations should only recompute what is affected by the
+    /// modifications made to the packet and its envelope.
+    #[doc(hidden)]
+    #[inline]
+    fn reconcile(&mut self) {
+        // noop
+    }
+
     /// Cascades the changes recursively through the layers.
     ///
     /// An upper layer change to message buffer size can have cascading
     /// effects on a lower layer packet header. This call recursively ensures
-    /// such changes are propogated through all the layers.
+    /// such changes are propogated through all the layers. Only the fields
+    /// affected by the changes are recomputed.
     #[inline]
     fn cascade(&mut self) {
+        self.reconcile();
         self.envelope_mut().cascade();
     }
 
+    /// Cascades the changes through at most `depth` layers, starting with
+    /// this packet.
+    ///
+    /// A `depth` of `1` only fixes up this packet. Use it when the changes
+    /// are known to not affect the lower layers, for example rewriting a
+    /// TCP port on a packet whose IP header and length are untouched.
+    #[inline]
+    fn cascade_depth(&mut self, depth: usize) {
+        if depth > 0 {
+            self.reconcile();
+            self.envelope_mut().cascade_depth(depth - 1);
+        }
+    }
+
+    /// Edits the packet as a transaction.
+    ///
+    /// If `f` fails, the packet is rolled back to what it was before the
+    /// call, its data, length and metadata included, and the error is
+    /// returned. Use it for a series of edits where a later step can fail,
+    /// such as an insert running out of tailroom after the headers are
+    /// already rewritten, so the packet is not left half modified.
+    ///
+    /// The rollback copies the packet data before the edits. A chained
+    /// packet is pulled up into one segment first. If `f` replaces the
+    /// packet with one on another buffer, nothing is rolled back.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone()).map(|packet| {
+    ///     let mut v4 = packet.parse::<Ethernet>()?.parse::<Ipv4>()?;
+    ///     // forwards the packet as is if the option cannot be inserted.
+    ///     let _ = v4.transaction(|v4| {
+    ///         v4.set_ttl(v4.ttl() - 1);
+    ///         let offset = v4.payload_offset();
+    ///         v4.mbuf_mut().extend(offset, 8)
+    ///     });
+    ///     Ok(v4)
+    /// });
+    /// ```
+    fn transaction<U, F>(&mut self, f: F) -> Result<U>
+    where
+        F: FnOnce(&mut Self) -> Result<U>,
+        Self: Sized,
+    {
+        let snapshot = self.mbuf_mut().snapshot()?;
+        // a bitwise copy of the packet, its header pointers are valid again
+        // once the buffer is restored. it's never dropped, it only takes
+        // the place of the packet if the packet still owns the buffer.
+        let saved = ManuallyDrop::new(unsafe { ptr::read(self) });
+
+        match f(self) {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                // `f` may have replaced the packet and freed the buffer,
+                // then the copy's pointers are dangling and it's left out.
+                if self.mbuf().is_snapshot_of(&snapshot) {
+                    self.mbuf_mut().restore(snapshot);
+                    // the packet in place owns the same buffer as the copy,
+                    // forgetting it keeps the buffer from being freed.
+                    mem::forget(mem::replace(self, ManuallyDrop::into_inner(saved)));
+                }
+                Err(err)
+            }
+        }
+    }
+
     /// Deparses the packet and returns its envelope.
     fn deparse(self) -> Self::Envelope;
 
@@ -263,14 +388,76 @@ impl<T: Packet> DerefMut for CondRc<T> {
     }
 }
 
+/// Returns `len` bytes of the buffer starting at `offset`.
+///
+/// An empty slice is returned if the range is out of the buffer's bounds.
+#[inline]
+pub(crate) fn data_slice(mbuf: &Mbuf, offset: usize, len: usize) -> &[u8] {
+    if len == 0 {
+        return &[];
+    }
+
+    match mbuf.read_data_slice(offset, len) {
+        Ok(data) => unsafe { &*data.as_ptr() },
+        Err(_) => &[],
+    }
+}
+
+/// Returns a mutable slice of `len` bytes of the buffer starting at `offset`.
+///
+/// An empty slice is returned if the range is out of the buffer's bounds.
+#[inline]
+pub(crate) fn data_slice_mut(mbuf: &mut Mbuf, offset: usize, len: usize) -> &mut [u8] {
+    if len == 0 {
+        return &mut [];
+    }
+
+    match mbuf.read_data_slice(offset, len) {
+        Ok(data) => unsafe { &mut *data.as_ptr() },
+        Err(_) => &mut [],
+    }
+}
+
+/// Replaces the `len` bytes of the buffer starting at `offset` with `data`.
+///
+/// The buffer is resized if `data` is not the same length as the bytes it
+/// replaces.
+#[inline]
+pub(crate) fn replace_data_slice(
+    mbuf: &mut Mbuf,
+    offset: usize,
+    len: usize,
+    data: &[u8],
+) -> Result<()> {
+    let delta = data.len() as isize - len as isize;
+    if delta != 0 {
+        mbuf.resize(offset, delta)?;
+    }
+    if !data.is_empty() {
+        mbuf.write_data_slice(offset, data)?;
+    }
+    Ok(())
+}
+
 /// Error when packet failed to parse.
 #[derive(Debug, Fail)]
-#[fail(display = "{}", _0)]
-pub struct ParseError(String);
+#[fail(display = "{}", _1)]
+pub struct ParseError(Anomaly, String);
 
 impl ParseError {
+    /// Creates an error for a packet that is not of the expected protocol.
     pub fn new(msg: &str) -> ParseError {
-        ParseError(msg.into())
+        ParseError(Anomaly::UnexpectedProtocol, msg.into())
+    }
+
+    /// Creates an error for a packet with a protocol anomaly.
+    pub fn with_anomaly(anomaly: Anomaly, msg: &str) -> ParseError {
+        ParseError(anomaly, msg.into())
+    }
+
+    /// Returns the anomaly that made the packet fail to parse.
+    pub fn anomaly(&self) -> Anomaly {
+        self.0
     }
 }
 
@@ -280,6 +467,7 @@ mod tests {
     use crate::net::MacAddr;
     use crate::packets::ip::v4::Ipv4;
     use crate::packets::{Udp, UDP_PACKET};
+    use crate::testils::FaultInjector;
 
     #[nb2::test]
     fn parse_and_reset_packet() {
@@ -318,4 +506,51 @@ mod tests {
         let v4_4 = udp_2.envelope();
         assert_eq!(v4_4.ttl(), 25);
     }
+
+    #[nb2::test]
+    fn roll_back_failed_transaction() {
+        // the first extend is the one of `from_bytes`.
+        let _faults = FaultInjector::new().fail_extend_at(2);
+
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut v4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let result = v4.transaction(|v4| {
+            v4.set_ttl(25);
+            let offset = v4.payload_offset();
+            v4.mbuf_mut().extend(offset, 8)
+        });
+        assert!(result.is_err());
+        assert_eq!(255, v4.ttl());
+        assert_eq!(UDP_PACKET.len(), v4.mbuf().data_len());
+
+        v4.transaction(|v4| {
+            v4.set_ttl(25);
+            let offset = v4.payload_offset();
+            v4.mbuf_mut().extend(offset, 8)
+        })
+        .unwrap();
+        assert_eq!(25, v4.ttl());
+        assert_eq!(UDP_PACKET.len() + 8, v4.mbuf().data_len());
+    }
+
+    #[nb2::test]
+    fn replace_packet_in_failed_transaction() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut v4 = ethernet.parse::<Ipv4>().unwrap();
+
+        let result: Result<()> = v4.transaction(|v4| {
+            let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+            let mut other = packet.parse::<Ethernet>()?.parse::<Ipv4>()?;
+            other.set_ttl(25);
+            // drops the packet and its buffer.
+            *v4 = other;
+            Err(ParseError::new("Packet is replaced.").into())
+        });
+        assert!(result.is_err());
+        // kept as replaced, the original buffer is gone.
+        assert_eq!(25, v4.ttl());
+    }
 }
@@ -0,0 +1,437 @@
+use crate::packets::ip::{IpPacket, ProtocolNumbers};
+use crate::packets::{be16, be32, CondRc, Header, Packet, ParseError};
+use crate::{ensure, Result, SizeOf};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ptr::NonNull;
+
+/*  From https://tools.ietf.org/html/rfc2328#appendix-A.3.1
+    OSPFv2 Packet Header
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |   Version #   |     Type      |         Packet length         |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                          Router ID                            |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                           Area ID                             |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |           Checksum            |             AuType            |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                       Authentication                          |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                       Authentication                          |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    From https://tools.ietf.org/html/rfc5340#appendix-A.3.1
+    OSPFv3 Packet Header
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |   Version #   |     Type      |         Packet length         |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                         Router ID                             |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                          Area ID                              |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |          Checksum             |  Instance ID  |      0        |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    Packet length   The length of the OSPF protocol packet in bytes,
+                    including the header.
+
+    Router ID       The Router ID of the packet's source.
+
+    Area ID         The area the packet belongs to. 0.0.0.0 is the
+                    backbone.
+*/
+
+/// The IPv4 multicast group of all the OSPF routers.
+pub const ALL_SPF_ROUTERS_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 5);
+
+/// The IPv6 multicast group of all the OSPF routers.
+pub const ALL_SPF_ROUTERS_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 5);
+
+/// The IPv4 multicast group of the designated routers.
+pub const ALL_D_ROUTERS_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 6);
+
+/// The IPv6 multicast group of the designated routers.
+pub const ALL_D_ROUTERS_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 6);
+
+const VERSION_2: u8 = 2;
+const VERSION_3: u8 = 3;
+const V2_HEADER_LEN: usize = 24;
+const V3_HEADER_LEN: usize = 16;
+
+/// Type of OSPF packet.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct OspfType(pub u8);
+
+impl OspfType {
+    pub fn new(value: u8) -> Self {
+        OspfType(value)
+    }
+}
+
+/// Supported OSPF packet types.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod OspfTypes {
+    use super::OspfType;
+
+    pub const Hello: OspfType = OspfType(1);
+    pub const DatabaseDescription: OspfType = OspfType(2);
+    pub const LinkStateRequest: OspfType = OspfType(3);
+    pub const LinkStateUpdate: OspfType = OspfType(4);
+    pub const LinkStateAck: OspfType = OspfType(5);
+}
+
+impl fmt::Display for OspfType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                OspfTypes::Hello => "Hello".to_string(),
+                OspfTypes::DatabaseDescription => "Database Description".to_string(),
+                OspfTypes::LinkStateRequest => "Link State Request".to_string(),
+                OspfTypes::LinkStateUpdate => "Link State Update".to_string(),
+                OspfTypes::LinkStateAck => "Link State Ack".to_string(),
+                _ => format!("{}", self.0),
+            }
+        )
+    }
+}
+
+/// The leading fields shared by the OSPFv2 and OSPFv3 headers.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, packed)]
+pub struct OspfHeader {
+    version: u8,
+    msg_type: u8,
+    length: be16,
+    router_id: be32,
+    area_id: be32,
+}
+
+impl Header for OspfHeader {}
+
+/// OSPF packet, OSPFv2 over IPv4 or OSPFv3 over IPv6.
+///
+/// Only the header is parsed, enough to classify the routing protocol
+/// traffic and punt it to the control plane. The packet bodies, the
+/// checksum and the authentication are not interpreted, the adjacencies
+/// are for the control plane to handle.
+///
+/// # Example
+///
+/// ```
+/// if Ospf::is_ospf(&ipv4) {
+///     let ospf = ipv4.parse::<Ospf<Ipv4>>()?;
+///     debug!(router_id = ?ospf.router_id(), area_id = ?ospf.area_id());
+///     control_plane.transmit(vec![ospf.reset()]);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Ospf<E: IpPacket> {
+    envelope: CondRc<E>,
+    header: NonNull<OspfHeader>,
+    offset: usize,
+}
+
+impl<E: IpPacket> Ospf<E> {
+    /// Returns whether the IP packet carries OSPF.
+    #[inline]
+    pub fn is_ospf(ip: &E) -> bool {
+        ip.next_proto() == ProtocolNumbers::Ospf
+    }
+
+    /// Returns the version, `2` for OSPFv2 and `3` for OSPFv3.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.header().version
+    }
+
+    #[inline]
+    pub fn msg_type(&self) -> OspfType {
+        OspfType::new(self.header().msg_type)
+    }
+
+    #[inline]
+    pub fn set_msg_type(&mut self, msg_type: OspfType) {
+        self.header_mut().msg_type = msg_type.0;
+    }
+
+    /// Returns whether the packet is a hello, which the neighbors are
+    /// discovered and kept alive with.
+    #[inline]
+    pub fn is_hello(&self) -> bool {
+        self.msg_type() == OspfTypes::Hello
+    }
+
+    /// Returns the packet length, including the header.
+    #[inline]
+    pub fn length(&self) -> u16 {
+        self.header().length.get()
+    }
+
+    #[inline]
+    pub fn router_id(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.header().router_id.get())
+    }
+
+    #[inline]
+    pub fn set_router_id(&mut self, router_id: Ipv4Addr) {
+        self.header_mut().router_id = u32::from(router_id).into();
+    }
+
+    #[inline]
+    pub fn area_id(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.header().area_id.get())
+    }
+
+    #[inline]
+    pub fn set_area_id(&mut self, area_id: Ipv4Addr) {
+        self.header_mut().area_id = u32::from(area_id).into();
+    }
+
+    /// Returns whether the packet belongs to the backbone area.
+    #[inline]
+    pub fn is_backbone(&self) -> bool {
+        self.area_id().is_unspecified()
+    }
+
+    #[inline]
+    fn header_len_of(version: u8) -> usize {
+        if version == VERSION_2 {
+            V2_HEADER_LEN
+        } else {
+            V3_HEADER_LEN
+        }
+    }
+}
+
+impl<E: IpPacket> fmt::Debug for Ospf<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ospf")
+            .field("version", &self.version())
+            .field("type", &format!("{}", self.msg_type()))
+            .field("length", &self.length())
+            .field("router_id", &self.router_id())
+            .field("area_id", &self.area_id())
+            .field("$offset", &self.offset())
+            .field("$len", &self.len())
+            .field("$header_len", &self.header_len())
+            .finish()
+    }
+}
+
+impl<E: IpPacket> Packet for Ospf<E> {
+    type Envelope = E;
+    type Header = OspfHeader;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header(&self) -> &Self::Header {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header_mut(&mut self) -> &mut Self::Header {
+        unsafe { self.header.as_mut() }
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        Self::header_len_of(self.version())
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn do_parse(envelope: Self::Envelope) -> Result<Self> {
+        ensure!(
+            Ospf::is_ospf(&envelope),
+            ParseError::new("Not an OSPF packet.")
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = mbuf.read_data::<OspfHeader>(offset)?;
+
+        let version = unsafe { header.as_ref().version };
+        ensure!(
+            version == VERSION_2 || version == VERSION_3,
+            ParseError::new("Not an OSPFv2 or OSPFv3 packet.")
+        );
+
+        // the rest of the header must be in the buffer too.
+        let _ = mbuf.read_data_slice::<u8>(offset, Self::header_len_of(version))?;
+
+        Ok(Ospf {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+        })
+    }
+
+    /// Pushes an empty hello header, OSPFv2 over IPv4 and OSPFv3 over
+    /// IPv6. The checksum and the authentication are left for the caller.
+    #[doc(hidden)]
+    #[inline]
+    fn do_push(mut envelope: Self::Envelope) -> Result<Self> {
+        let offset = envelope.payload_offset();
+        let version = match envelope.src() {
+            IpAddr::V4(_) => VERSION_2,
+            IpAddr::V6(_) => VERSION_3,
+        };
+        let len = Self::header_len_of(version);
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, len)?;
+        mbuf.write_data_slice(offset, &[0u8; V2_HEADER_LEN][..len])?;
+        let header = mbuf.write_data(
+            offset,
+            &OspfHeader {
+                version,
+                msg_type: OspfTypes::Hello.0,
+                length: (len as u16).into(),
+                ..OspfHeader::default()
+            },
+        )?;
+
+        envelope.set_next_proto(ProtocolNumbers::Ospf);
+
+        Ok(Ospf {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+        })
+    }
+
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let offset = self.offset();
+        let len = self.header_len();
+        self.mbuf_mut().shrink(offset, len)?;
+        Ok(self.envelope.into_owned())
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::ip::v6::Ipv6;
+    use crate::packets::Ethernet;
+    use crate::Mbuf;
+
+    #[rustfmt::skip]
+    const OSPFV2_HELLO_PACKET: [u8; 78] = [
+        // ** ethernet header
+        0x01, 0x00, 0x5e, 0x00, 0x00, 0x05,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x08, 0x00,
+        // ** IPv4 header
+        0x45, 0xc0, 0x00, 0x40,
+        0x00, 0x00, 0x00, 0x00,
+        // ttl = 1, protocol = OSPF
+        0x01, 0x59, 0x00, 0x00,
+        0x0a, 0x00, 0x00, 0x01,
+        0xe0, 0x00, 0x00, 0x05,
+        // ** OSPF header
+        // version = 2, hello, length = 44
+        0x02, 0x01, 0x00, 0x2c,
+        // router id = 1.1.1.1
+        0x01, 0x01, 0x01, 0x01,
+        // area id = 0.0.0.1
+        0x00, 0x00, 0x00, 0x01,
+        // checksum, no authentication
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        // ** hello
+        // network mask = 255.255.255.0
+        0xff, 0xff, 0xff, 0x00,
+        // hello interval = 10, options, priority = 1
+        0x00, 0x0a, 0x02, 0x01,
+        // dead interval = 40
+        0x00, 0x00, 0x00, 0x28,
+        // designated and backup designated routers
+        0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn size_of_ospf_header() {
+        assert_eq!(12, OspfHeader::size_of());
+    }
+
+    #[nb2::test]
+    fn parse_ospfv2_hello() {
+        let packet = Mbuf::from_bytes(&OSPFV2_HELLO_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert!(Ospf::is_ospf(&ipv4));
+        assert_eq!(ALL_SPF_ROUTERS_V4, ipv4.dst());
+
+        let ospf = ipv4.parse::<Ospf<Ipv4>>().unwrap();
+        assert_eq!(2, ospf.version());
+        assert!(ospf.is_hello());
+        assert_eq!(44, ospf.length());
+        assert_eq!(Ipv4Addr::new(1, 1, 1, 1), ospf.router_id());
+        assert_eq!(Ipv4Addr::new(0, 0, 0, 1), ospf.area_id());
+        assert!(!ospf.is_backbone());
+        assert_eq!(24, ospf.header_len());
+        assert_eq!(20, ospf.payload_len());
+    }
+
+    #[nb2::test]
+    fn push_ospfv3_header() {
+        let packet = Mbuf::new().unwrap();
+        let ethernet = packet.push::<Ethernet>().unwrap();
+        let mut ipv6 = ethernet.push::<Ipv6>().unwrap();
+        ipv6.set_dst(ALL_SPF_ROUTERS_V6);
+        let mut ospf = ipv6.push::<Ospf<Ipv6>>().unwrap();
+        ospf.set_router_id(Ipv4Addr::new(2, 2, 2, 2));
+        ospf.set_msg_type(OspfTypes::LinkStateUpdate);
+
+        assert_eq!(3, ospf.version());
+        assert_eq!(16, ospf.header_len());
+        assert_eq!(OspfTypes::LinkStateUpdate, ospf.msg_type());
+        assert!(ospf.is_backbone());
+
+        let ipv6 = ospf.deparse();
+        assert_eq!(ProtocolNumbers::Ospf, ipv6.next_proto());
+        assert!(ipv6.parse::<Ospf<Ipv6>>().is_ok());
+    }
+
+    #[nb2::test]
+    fn parse_non_ospf_packet() {
+        let packet = Mbuf::from_bytes(&crate::packets::UDP_PACKET).unwrap();
+        let ipv4 = packet.parse::<Ethernet>().unwrap().parse::<Ipv4>().unwrap();
+        assert!(ipv4.parse::<Ospf<Ipv4>>().is_err());
+    }
+}
@@ -0,0 +1,447 @@
+use crate::packets::{
+    be16, data_slice, data_slice_mut, CondRc, EtherTypes, Ethernet, Header, Packet, ParseError,
+};
+use crate::{ensure, Result, SizeOf};
+use std::fmt;
+use std::ptr::NonNull;
+
+/*  From https://tools.ietf.org/html/rfc2516#section-4
+    PPPoE Packet Format
+
+     0                   1                   2                   3
+     0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |  VER  | TYPE  |      CODE     |          SESSION_ID           |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |            LENGTH             |           payload             ~
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+    VER         MUST be set to 0x1.
+
+    TYPE        MUST be set to 0x1.
+
+    CODE        Defined for the Discovery and PPP Session stages.
+
+    SESSION_ID  Together with the Ethernet SOURCE_ADDR and DESTINATION_ADDR,
+                uniquely defines a PPP session. 0xffff is reserved.
+
+    LENGTH      The length of the PPPoE payload. It does not include the
+                length of the Ethernet or PPPoE headers.
+
+    In the session stage, the payload is a PPP frame starting with the
+    2-octet PPP protocol field (RFC 1661), followed by the encapsulated
+    datagram.
+*/
+
+const VER_TYPE: u8 = 0x11;
+
+/// PPPoE code.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod PppoeCodes {
+    // session data
+    pub const Session: u8 = 0x00;
+    // PPPoE Active Discovery Offer
+    pub const Pado: u8 = 0x07;
+    // PPPoE Active Discovery Initiation
+    pub const Padi: u8 = 0x09;
+    // PPPoE Active Discovery Request
+    pub const Padr: u8 = 0x19;
+    // PPPoE Active Discovery Session-confirmation
+    pub const Pads: u8 = 0x65;
+    // PPPoE Active Discovery Terminate
+    pub const Padt: u8 = 0xa7;
+}
+
+/// The protocol encapsulated in the PPP frame.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, packed)]
+pub struct PppProtocol(pub u16);
+
+impl PppProtocol {
+    pub fn new(value: u16) -> Self {
+        PppProtocol(value)
+    }
+}
+
+/// Supported PPP protocols.
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod PppProtocols {
+    use super::PppProtocol;
+
+    // Internet Protocol version 4
+    pub const Ipv4: PppProtocol = PppProtocol(0x0021);
+    // Internet Protocol version 6
+    pub const Ipv6: PppProtocol = PppProtocol(0x0057);
+    // Link Control Protocol
+    pub const Lcp: PppProtocol = PppProtocol(0xc021);
+    // Internet Protocol Control Protocol
+    pub const Ipcp: PppProtocol = PppProtocol(0x8021);
+    // IPv6 Control Protocol
+    pub const Ipv6cp: PppProtocol = PppProtocol(0x8057);
+}
+
+impl fmt::Display for PppProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                PppProtocols::Ipv4 => "IPv4".to_string(),
+                PppProtocols::Ipv6 => "IPv6".to_string(),
+                PppProtocols::Lcp => "LCP".to_string(),
+                PppProtocols::Ipcp => "IPCP".to_string(),
+                PppProtocols::Ipv6cp => "IPV6CP".to_string(),
+                _ => {
+                    let p = self.0;
+                    format!("0x{:04x}", p)
+                }
+            }
+        )
+    }
+}
+
+/// PPPoE header.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, packed)]
+pub struct PppoeHeader {
+    ver_type: u8,
+    code: u8,
+    session_id: be16,
+    length: be16,
+}
+
+impl Default for PppoeHeader {
+    fn default() -> PppoeHeader {
+        PppoeHeader {
+            ver_type: VER_TYPE,
+            code: PppoeCodes::Session,
+            session_id: be16::default(),
+            length: be16::default(),
+        }
+    }
+}
+
+impl Header for PppoeHeader {}
+
+/// PPPoE discovery or session frame.
+///
+/// In the session stage, the PPP protocol field is treated as part of
+/// the header, so the payload is the encapsulated datagram.
+///
+/// Pushing a `Pppoe` packet onto an Ethernet frame that carries IP
+/// inserts a session header between the two, and removing it restores
+/// the IP ether type. Use them to encapsulate and decapsulate subscriber
+/// traffic.
+///
+/// # Example
+///
+/// ```
+/// let mut pppoe = ethernet.push::<Pppoe>()?;
+/// pppoe.set_session_id(session_id);
+/// pppoe.cascade();
+///
+/// let ipv4 = pppoe.remove()?.parse::<Ipv4>()?;
+/// ```
+#[derive(Clone)]
+pub struct Pppoe {
+    envelope: CondRc<Ethernet>,
+    header: NonNull<PppoeHeader>,
+    offset: usize,
+}
+
+impl Pppoe {
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.header().ver_type >> 4
+    }
+
+    #[inline]
+    pub fn pppoe_type(&self) -> u8 {
+        self.header().ver_type & 0x0f
+    }
+
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.header().code
+    }
+
+    #[inline]
+    pub fn set_code(&mut self, code: u8) {
+        self.header_mut().code = code;
+    }
+
+    #[inline]
+    pub fn session_id(&self) -> u16 {
+        self.header().session_id.get()
+    }
+
+    #[inline]
+    pub fn set_session_id(&mut self, session_id: u16) {
+        self.header_mut().session_id = session_id.into();
+    }
+
+    #[inline]
+    pub fn length(&self) -> u16 {
+        self.header().length.get()
+    }
+
+    #[inline]
+    fn set_length(&mut self, length: u16) {
+        self.header_mut().length = length.into();
+    }
+
+    /// Returns whether the frame is in the session stage.
+    #[inline]
+    pub fn is_session(&self) -> bool {
+        self.envelope().ether_type() == EtherTypes::PppoeSession
+    }
+
+    /// Returns the PPP protocol of a session frame.
+    #[inline]
+    pub fn ppp_protocol(&self) -> Option<PppProtocol> {
+        if self.is_session() {
+            let offset = self.offset + PppoeHeader::size_of();
+            let bytes = data_slice(self.mbuf(), offset, 2);
+            Some(PppProtocol(u16::from_be_bytes([bytes[0], bytes[1]])))
+        } else {
+            None
+        }
+    }
+
+    /// Sets the PPP protocol of a session frame.
+    ///
+    /// Does nothing if the frame is in the discovery stage.
+    #[inline]
+    pub fn set_ppp_protocol(&mut self, protocol: PppProtocol) {
+        if self.is_session() {
+            let offset = self.offset + PppoeHeader::size_of();
+            data_slice_mut(self.mbuf_mut(), offset, 2).copy_from_slice(&protocol.0.to_be_bytes());
+        }
+    }
+}
+
+impl fmt::Debug for Pppoe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("pppoe")
+            .field("version", &self.version())
+            .field("type", &self.pppoe_type())
+            .field("code", &format!("0x{:02x}", self.code()))
+            .field("session_id", &self.session_id())
+            .field("length", &self.length())
+            .field(
+                "ppp_protocol",
+                &self.ppp_protocol().map(|protocol| protocol.to_string()),
+            )
+            .finish()
+    }
+}
+
+impl Packet for Pppoe {
+    type Envelope = Ethernet;
+    type Header = PppoeHeader;
+
+    #[inline]
+    fn envelope(&self) -> &Self::Envelope {
+        &self.envelope
+    }
+
+    #[inline]
+    fn envelope_mut(&mut self) -> &mut Self::Envelope {
+        &mut self.envelope
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header(&self) -> &Self::Header {
+        unsafe { self.header.as_ref() }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn header_mut(&mut self) -> &mut Self::Header {
+        unsafe { self.header.as_mut() }
+    }
+
+    #[inline]
+    fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    fn header_len(&self) -> usize {
+        if self.is_session() {
+            PppoeHeader::size_of() + 2
+        } else {
+            PppoeHeader::size_of()
+        }
+    }
+
+    #[doc(hidden)]
+    #[inline]
+    fn do_parse(envelope: Self::Envelope) -> Result<Self> {
+        let ether_type = envelope.ether_type();
+        ensure!(
+            ether_type == EtherTypes::PppoeDiscovery || ether_type == EtherTypes::PppoeSession,
+            ParseError::new("Not a PPPoE frame.")
+        );
+
+        let mbuf = envelope.mbuf();
+        let offset = envelope.payload_offset();
+        let header = mbuf.read_data(offset)?;
+
+        if ether_type == EtherTypes::PppoeSession {
+            // the PPP protocol field must be in the buffer too.
+            let _ = mbuf.read_data_slice::<u8>(offset + PppoeHeader::size_of(), 2)?;
+        }
+
+        Ok(Pppoe {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+        })
+    }
+
+    /// Pushes a session header, with the PPP protocol derived from the
+    /// ether type of the envelope.
+    #[doc(hidden)]
+    #[inline]
+    fn do_push(mut envelope: Self::Envelope) -> Result<Self> {
+        let protocol = match envelope.ether_type() {
+            EtherTypes::Ipv4 => PppProtocols::Ipv4,
+            EtherTypes::Ipv6 => PppProtocols::Ipv6,
+            _ => PppProtocol::default(),
+        };
+
+        let offset = envelope.payload_offset();
+        let mbuf = envelope.mbuf_mut();
+
+        mbuf.extend(offset, PppoeHeader::size_of() + 2)?;
+        let header = mbuf.write_data(offset, &PppoeHeader::default())?;
+        mbuf.write_data(offset + PppoeHeader::size_of(), &be16::new(protocol.0))?;
+
+        envelope.set_ether_type(EtherTypes::PppoeSession);
+
+        Ok(Pppoe {
+            envelope: CondRc::new(envelope),
+            header,
+            offset,
+        })
+    }
+
+    /// Removes the PPPoE header and the PPP protocol field.
+    ///
+    /// If the session frame carries IP, the ether type of the envelope is
+    /// set back to IPv4 or IPv6.
+    #[inline]
+    fn remove(mut self) -> Result<Self::Envelope> {
+        let ether_type = match self.ppp_protocol() {
+            Some(PppProtocols::Ipv4) => Some(EtherTypes::Ipv4),
+            Some(PppProtocols::Ipv6) => Some(EtherTypes::Ipv6),
+            _ => None,
+        };
+
+        let offset = self.offset();
+        let len = self.header_len();
+        self.mbuf_mut().shrink(offset, len)?;
+
+        let mut envelope = self.envelope.into_owned();
+        if let Some(ether_type) = ether_type {
+            envelope.set_ether_type(ether_type);
+        }
+        Ok(envelope)
+    }
+
+    #[inline]
+    fn reconcile(&mut self) {
+        // the PPP protocol field is part of the PPPoE payload.
+        let len = (self.len() - PppoeHeader::size_of()) as u16;
+        if self.length() != len {
+            self.set_length(len);
+        }
+    }
+
+    #[inline]
+    fn deparse(self) -> Self::Envelope {
+        self.envelope.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::UDP_PACKET;
+    use crate::Mbuf;
+
+    #[rustfmt::skip]
+    const PADI_PACKET: [u8; 24] = [
+        // ** ethernet header
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+        0x88, 0x63,
+        // ** PPPoE header
+        // ver = 1, type = 1, code = PADI, session_id = 0
+        0x11, 0x09, 0x00, 0x00,
+        // length = 4
+        0x00, 0x04,
+        // ** service-name tag, empty
+        0x01, 0x01, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn size_of_pppoe_header() {
+        assert_eq!(6, PppoeHeader::size_of());
+    }
+
+    #[test]
+    fn ppp_protocol_to_string() {
+        assert_eq!("IPv4", PppProtocols::Ipv4.to_string());
+        assert_eq!("LCP", PppProtocols::Lcp.to_string());
+        assert_eq!("0x0001", PppProtocol::new(1).to_string());
+    }
+
+    #[nb2::test]
+    fn parse_pppoe_discovery_packet() {
+        let packet = Mbuf::from_bytes(&PADI_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let pppoe = ethernet.parse::<Pppoe>().unwrap();
+
+        assert_eq!(1, pppoe.version());
+        assert_eq!(1, pppoe.pppoe_type());
+        assert_eq!(PppoeCodes::Padi, pppoe.code());
+        assert_eq!(0, pppoe.session_id());
+        assert_eq!(4, pppoe.length());
+        assert!(!pppoe.is_session());
+        assert_eq!(None, pppoe.ppp_protocol());
+        assert_eq!(4, pppoe.payload_len());
+    }
+
+    #[nb2::test]
+    fn parse_non_pppoe_packet() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        assert!(ethernet.parse::<Pppoe>().is_err());
+    }
+
+    #[nb2::test]
+    fn push_and_remove_pppoe_session() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let mut pppoe = ethernet.push::<Pppoe>().unwrap();
+        pppoe.set_session_id(0x1234);
+        pppoe.cascade();
+
+        assert_eq!(EtherTypes::PppoeSession, pppoe.envelope().ether_type());
+        assert_eq!(Some(PppProtocols::Ipv4), pppoe.ppp_protocol());
+        assert_eq!(0x1234, pppoe.session_id());
+        // the IPv4 packet plus the PPP protocol field.
+        assert_eq!(40, pppoe.length());
+
+        let ethernet = pppoe.remove().unwrap();
+        assert_eq!(EtherTypes::Ipv4, ethernet.ether_type());
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        assert_eq!(38, ipv4.total_length());
+    }
+}
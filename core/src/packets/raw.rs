@@ -0,0 +1,391 @@
+use crate::ffi;
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+use crate::packets::{data_slice, EtherType, EtherTypes};
+use crate::{Mbuf, PacketType};
+
+// the ethernet header, without the VLAN tags.
+const ETHERNET_HEADER_LEN: usize = 14;
+const VLAN_TAG_LEN: usize = 4;
+const IPV6_HEADER_LEN: usize = 40;
+
+// the limit on the extension headers skipped, for the malformed chains.
+const MAX_IPV6_EXTENSIONS: usize = 8;
+
+// the largest header lengths the buffer can record, the widths of the
+// `l2_len` and `l3_len` bit fields.
+const MAX_L2_LEN: usize = 127;
+const MAX_L3_LEN: usize = 511;
+
+/// A view of the layer offsets of a packet, found without parsing it.
+///
+/// `classify` reads the few fields that locate the layers, the ether type,
+/// the IPv4 header length and the IPv6 next headers, the way
+/// `rte_net_get_ptype` does. No typed packets are created and nothing is
+/// validated beyond the bounds of the buffer. It is for the apps that only
+/// count or forward, for which a full parse is overhead. To read or change
+/// the headers, parse the packet instead.
+///
+/// 802.1Q and 802.1ad tags are skipped, and `ether_type` is the type of
+/// the payload after the tags.
+///
+/// # Example
+///
+/// ```
+/// let raw = RawPacket::classify(&mbuf);
+/// if raw.protocol() == Some(ProtocolNumbers::Tcp) {
+///     tcp_bytes += raw.l4().len();
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct RawPacket<'a> {
+    mbuf: &'a Mbuf,
+    ether_type: EtherType,
+    l3_offset: usize,
+    protocol: Option<ProtocolNumber>,
+    l4_offset: Option<usize>,
+}
+
+impl<'a> RawPacket<'a> {
+    /// Classifies the packet in the buffer.
+    ///
+    /// When the device classified the packet as a plain IPv4 or IPv6 packet,
+    /// or the packet was classified before with `RawPacket::cache`, the
+    /// offsets are taken from `Mbuf::packet_type` without reading the
+    /// headers. The header lengths are cleared as the packet is received,
+    /// so the offsets of another packet the buffer carried before are not
+    /// used. Lengths set with `Mbuf::set_header_lens` are trusted the same
+    /// way as the cached ones.
+    pub fn classify(mbuf: &'a Mbuf) -> Self {
+        if let Some(raw) = RawPacket::from_packet_type(mbuf) {
+            return raw;
+        }
+
+        let mut offset = ETHERNET_HEADER_LEN;
+        let mut ether_type = read_u16(mbuf, 12).map(EtherType::new);
+
+        while let Some(EtherTypes::Vlan) | Some(EtherTypes::Qinq) = ether_type {
+            ether_type = read_u16(mbuf, offset + 2).map(EtherType::new);
+            offset += VLAN_TAG_LEN;
+        }
+
+        let mut raw = RawPacket {
+            mbuf,
+            ether_type: ether_type.unwrap_or_default(),
+            l3_offset: offset,
+            protocol: None,
+            l4_offset: None,
+        };
+
+        match raw.ether_type {
+            EtherTypes::Ipv4 => raw.classify_ipv4(),
+            EtherTypes::Ipv6 => raw.classify_ipv6(),
+            _ => (),
+        }
+
+        raw
+    }
+
+    /// Classifies the packet and caches the offsets of its layers in the
+    /// buffer, so the later `classify` of the packet, in the following
+    /// stages of the pipeline, do not read the headers again.
+    ///
+    /// The offsets are cached the way DPDK records them, as the packet
+    /// type and the header lengths of the buffer. Only IP packets with a
+    /// TCP, UDP, SCTP or ICMP header are cached. Inserting or removing a
+    /// header resets the packet type, which invalidates the cache.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let batch = Poll::new(q.clone())
+    ///     .map(|mut packet| {
+    ///         RawPacket::cache(&mut packet);
+    ///         Ok(packet)
+    ///     })
+    ///     .filter(|packet| RawPacket::classify(packet).protocol() == Some(ProtocolNumbers::Udp))
+    ///     .for_each(|packet| {
+    ///         udp_bytes += RawPacket::classify(packet).l4().len();
+    ///         Ok(())
+    ///     });
+    /// ```
+    pub fn cache(mbuf: &mut Mbuf) {
+        let raw = RawPacket::classify(mbuf);
+        if let Some((ptype, l2_len, l3_len)) = raw.packet_type() {
+            mbuf.set_packet_type(ptype);
+            mbuf.set_header_lens(l2_len, l3_len);
+        }
+    }
+
+    // the packet type and the header lengths describing the classification.
+    fn packet_type(&self) -> Option<(PacketType, usize, usize)> {
+        let l2_len = self.l3_offset;
+        let l3_len = self.l4_offset? - self.l3_offset;
+        if l2_len > MAX_L2_LEN || l3_len > MAX_L3_LEN {
+            return None;
+        }
+
+        let l2 = match l2_len {
+            ETHERNET_HEADER_LEN => ffi::RTE_PTYPE_L2_ETHER,
+            18 => ffi::RTE_PTYPE_L2_ETHER_VLAN,
+            _ => ffi::RTE_PTYPE_L2_ETHER_QINQ,
+        };
+        let l3 = match (self.ether_type, l3_len) {
+            (EtherTypes::Ipv4, 20) => ffi::RTE_PTYPE_L3_IPV4,
+            (EtherTypes::Ipv4, _) => ffi::RTE_PTYPE_L3_IPV4_EXT,
+            (EtherTypes::Ipv6, IPV6_HEADER_LEN) => ffi::RTE_PTYPE_L3_IPV6,
+            (EtherTypes::Ipv6, _) => ffi::RTE_PTYPE_L3_IPV6_EXT_UNKNOWN,
+            _ => return None,
+        };
+        let l4 = match self.protocol? {
+            ProtocolNumbers::Tcp => ffi::RTE_PTYPE_L4_TCP,
+            ProtocolNumbers::Udp => ffi::RTE_PTYPE_L4_UDP,
+            ProtocolNumbers::Sctp => ffi::RTE_PTYPE_L4_SCTP,
+            ProtocolNumbers::Icmpv4 | ProtocolNumbers::Icmpv6 => ffi::RTE_PTYPE_L4_ICMP,
+            _ => return None,
+        };
+
+        Some((PacketType(l2 | l3 | l4), l2_len, l3_len))
+    }
+
+    fn from_packet_type(mbuf: &'a Mbuf) -> Option<Self> {
+        let ptype = mbuf.packet_type();
+        if ptype.is_tunnel() {
+            return None;
+        }
+
+        let ether_type = ptype.l3()?;
+        let protocol = ptype.l4()?;
+
+        let (l3_offset, l3_len) = match mbuf.header_lens() {
+            // cached by `RawPacket::cache`.
+            (l2_len, l3_len) if l2_len > 0 && l3_len > 0 => (l2_len, l3_len),
+            _ if ptype.is_plain_ether() && ptype.is_l3_fixed_len() => {
+                let l3_len = if ether_type == EtherTypes::Ipv4 {
+                    20
+                } else {
+                    IPV6_HEADER_LEN
+                };
+                (ETHERNET_HEADER_LEN, l3_len)
+            }
+            _ => return None,
+        };
+
+        Some(RawPacket {
+            mbuf,
+            ether_type,
+            l3_offset,
+            protocol: Some(protocol),
+            l4_offset: Some(l3_offset + l3_len),
+        })
+    }
+
+    fn classify_ipv4(&mut self) {
+        let header = data_slice(self.mbuf, self.l3_offset, 20);
+        if header.is_empty() || header[0] >> 4 != 4 {
+            return;
+        }
+
+        self.protocol = Some(ProtocolNumber::new(header[9]));
+
+        // only the first fragment carries the L4 header.
+        let fragment_offset = u16::from_be_bytes([header[6], header[7]]) & 0x1fff;
+        let ihl = (header[0] & 0x0f) as usize * 4;
+        if fragment_offset == 0 && ihl >= 20 {
+            self.l4_offset = Some(self.l3_offset + ihl);
+        }
+    }
+
+    fn classify_ipv6(&mut self) {
+        let header = data_slice(self.mbuf, self.l3_offset, IPV6_HEADER_LEN);
+        if header.is_empty() || header[0] >> 4 != 6 {
+            return;
+        }
+
+        let mut next_header = ProtocolNumber::new(header[6]);
+        let mut offset = self.l3_offset + IPV6_HEADER_LEN;
+
+        for _ in 0..MAX_IPV6_EXTENSIONS {
+            let ext = data_slice(self.mbuf, offset, 4);
+            if ext.is_empty() {
+                break;
+            }
+
+            let len = match next_header {
+                ProtocolNumbers::Ipv6HopByHop
+                | ProtocolNumbers::Ipv6Route
+                | ProtocolNumbers::Ipv6Opts => (ext[1] as usize + 1) * 8,
+                ProtocolNumbers::Ah => (ext[1] as usize + 2) * 4,
+                ProtocolNumbers::Ipv6Frag => {
+                    // only the first fragment carries the L4 header.
+                    if u16::from_be_bytes([ext[2], ext[3]]) & 0xfff8 != 0 {
+                        self.protocol = Some(ProtocolNumber::new(ext[0]));
+                        return;
+                    }
+                    8
+                }
+                _ => break,
+            };
+
+            next_header = ProtocolNumber::new(ext[0]);
+            offset += len;
+        }
+
+        self.protocol = Some(next_header);
+        self.l4_offset = Some(offset);
+    }
+
+    /// Returns the ether type of the payload, after the VLAN tags.
+    #[inline]
+    pub fn ether_type(&self) -> EtherType {
+        self.ether_type
+    }
+
+    /// Returns the offset of the L3 header, which is also the length of the
+    /// L2 headers.
+    #[inline]
+    pub fn l3_offset(&self) -> usize {
+        self.l3_offset
+    }
+
+    /// Returns the protocol of the L4 header, for IP packets. For IPv6,
+    /// this is the header after the extension headers.
+    #[inline]
+    pub fn protocol(&self) -> Option<ProtocolNumber> {
+        self.protocol
+    }
+
+    /// Returns the offset of the L4 header, for IP packets that are not a
+    /// non-first fragment.
+    #[inline]
+    pub fn l4_offset(&self) -> Option<usize> {
+        self.l4_offset
+    }
+
+    /// Returns the bytes from the L3 header to the end of the buffer.
+    #[inline]
+    pub fn l3(&self) -> &'a [u8] {
+        self.tail(Some(self.l3_offset))
+    }
+
+    /// Returns the bytes from the L4 header to the end of the buffer, empty
+    /// when there is no L4 header.
+    #[inline]
+    pub fn l4(&self) -> &'a [u8] {
+        self.tail(self.l4_offset)
+    }
+
+    fn tail(&self, offset: Option<usize>) -> &'a [u8] {
+        match offset {
+            Some(offset) if offset < self.mbuf.data_len() => {
+                data_slice(self.mbuf, offset, self.mbuf.data_len() - offset)
+            }
+            _ => &[],
+        }
+    }
+}
+
+#[inline]
+fn read_u16(mbuf: &Mbuf, offset: usize) -> Option<u16> {
+    let bytes = data_slice(mbuf, offset, 2);
+    if bytes.is_empty() {
+        None
+    } else {
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v6::{IPV6_EXTENSIONS_PACKET, SRH_PACKET};
+    use crate::packets::{TCP_PACKET, UDP_PACKET};
+    use crate::{ffi, PacketType};
+
+    #[nb2::test]
+    fn classify_ipv4() {
+        let packet = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        let raw = RawPacket::classify(&packet);
+
+        assert_eq!(EtherTypes::Ipv4, raw.ether_type());
+        assert_eq!(14, raw.l3_offset());
+        assert_eq!(Some(ProtocolNumbers::Tcp), raw.protocol());
+        assert_eq!(Some(34), raw.l4_offset());
+        assert_eq!(TCP_PACKET.len() - 34, raw.l4().len());
+    }
+
+    #[nb2::test]
+    fn classify_vlan_tagged() {
+        let mut bytes = UDP_PACKET[..12].to_vec();
+        bytes.extend_from_slice(&[0x81, 0x00, 0x00, 0x64]);
+        bytes.extend_from_slice(&UDP_PACKET[12..]);
+        let packet = Mbuf::from_bytes(&bytes).unwrap();
+        let raw = RawPacket::classify(&packet);
+
+        assert_eq!(EtherTypes::Ipv4, raw.ether_type());
+        assert_eq!(18, raw.l3_offset());
+        assert_eq!(Some(ProtocolNumbers::Udp), raw.protocol());
+        assert_eq!(Some(38), raw.l4_offset());
+    }
+
+    #[nb2::test]
+    fn classify_ipv6_extensions() {
+        let packet = Mbuf::from_bytes(&IPV6_EXTENSIONS_PACKET).unwrap();
+        let raw = RawPacket::classify(&packet);
+
+        assert_eq!(EtherTypes::Ipv6, raw.ether_type());
+        // past the 8 bytes of hop-by-hop and 16 bytes of destination options.
+        assert_eq!(Some(ProtocolNumbers::Udp), raw.protocol());
+        assert_eq!(Some(14 + 40 + 8 + 16), raw.l4_offset());
+
+        let packet = Mbuf::from_bytes(&SRH_PACKET).unwrap();
+        let raw = RawPacket::classify(&packet);
+        assert_eq!(Some(ProtocolNumbers::Tcp), raw.protocol());
+    }
+
+    #[nb2::test]
+    fn classify_with_packet_type() {
+        let mut packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        // a device would never claim TCP, the headers are not read.
+        packet.set_packet_type(PacketType(
+            ffi::RTE_PTYPE_L2_ETHER | ffi::RTE_PTYPE_L3_IPV4 | ffi::RTE_PTYPE_L4_TCP,
+        ));
+        let raw = RawPacket::classify(&packet);
+
+        assert_eq!(EtherTypes::Ipv4, raw.ether_type());
+        assert_eq!(Some(ProtocolNumbers::Tcp), raw.protocol());
+        assert_eq!(Some(34), raw.l4_offset());
+    }
+
+    #[nb2::test]
+    fn classify_from_cache() {
+        let mut packet = Mbuf::from_bytes(&IPV6_EXTENSIONS_PACKET).unwrap();
+        RawPacket::cache(&mut packet);
+        assert!(!packet.packet_type().is_unknown());
+        assert_eq!((14, 40 + 8 + 16), packet.header_lens());
+
+        // the headers are not read again, clobbering the next header of
+        // the IPv6 header goes unnoticed.
+        packet.write_data(20, &0xffu8).unwrap();
+        let raw = RawPacket::classify(&packet);
+        assert_eq!(EtherTypes::Ipv6, raw.ether_type());
+        assert_eq!(Some(ProtocolNumbers::Udp), raw.protocol());
+        assert_eq!(Some(14 + 40 + 8 + 16), raw.l4_offset());
+
+        // inserting a header invalidates the cache.
+        packet.extend(14, 4).unwrap();
+        assert!(packet.packet_type().is_unknown());
+    }
+
+    #[nb2::test]
+    fn classify_not_ip() {
+        let mut bytes = UDP_PACKET.to_vec();
+        bytes[12] = 0x08;
+        bytes[13] = 0x06;
+        let packet = Mbuf::from_bytes(&bytes).unwrap();
+        let raw = RawPacket::classify(&packet);
+
+        assert_eq!(EtherTypes::Arp, raw.ether_type());
+        assert_eq!(None, raw.protocol());
+        assert!(raw.l4().is_empty());
+    }
+}
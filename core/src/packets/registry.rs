@@ -0,0 +1,198 @@
+use crate::packets::ip::{ProtocolNumber, ProtocolNumbers};
+use crate::packets::{EtherType, EtherTypes, Ethernet, Packet};
+use crate::{ensure, Result};
+use failure::Fail;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+type EtherDissector = Arc<dyn Fn(&Ethernet) -> Option<usize> + Send + Sync>;
+type IpDissector = Arc<dyn Fn(&[u8]) -> Option<usize> + Send + Sync>;
+
+#[derive(Default)]
+struct Registry {
+    ether_types: HashMap<EtherType, (&'static str, EtherDissector)>,
+    protocols: HashMap<ProtocolNumber, (&'static str, IpDissector)>,
+}
+
+lazy_static! {
+    // the parsers registered by the application. written at startup and
+    // read by the walker.
+    static ref REGISTRY: RwLock<Registry> = RwLock::new(Registry::default());
+}
+
+/// Error indicating a protocol already has a built-in parser.
+#[derive(Debug, Fail)]
+#[fail(display = "{} has a built-in parser.", _0)]
+pub struct BuiltinParserError(String);
+
+/// Registers a packet type as the parser of a custom ether type.
+///
+/// `Mbuf::layers` then reports the frames of the ether type as a
+/// `Layer::Custom` named `name`, followed by their payload. Registering
+/// the same ether type again replaces the parser.
+///
+/// # Errors
+///
+/// Returns `BuiltinParserError` for the ether types nb2 already parses.
+///
+/// # Example
+///
+/// ```
+/// // `Ecpri` implements `Packet<Envelope = Ethernet>`.
+/// register_ether_type::<Ecpri>(EtherType::new(0xaefe), "Ecpri")?;
+/// ```
+pub fn register_ether_type<T>(ether_type: EtherType, name: &'static str) -> Result<()>
+where
+    T: Packet<Envelope = Ethernet>,
+{
+    register_ether_type_with(ether_type, name, |ethernet| {
+        ethernet.peek::<T>().ok().map(|packet| packet.header_len())
+    })
+}
+
+/// Registers a function as the parser of a custom ether type.
+///
+/// The function returns the length of the header that follows the
+/// ethernet header, or `None` if the frame is not valid.
+///
+/// # Errors
+///
+/// Returns `BuiltinParserError` for the ether types nb2 already parses.
+pub fn register_ether_type_with<F>(ether_type: EtherType, name: &'static str, f: F) -> Result<()>
+where
+    F: Fn(&Ethernet) -> Option<usize> + Send + Sync + 'static,
+{
+    ensure!(
+        ether_type != EtherTypes::Ipv4
+            && ether_type != EtherTypes::Ipv6
+            && ether_type != EtherTypes::PppoeDiscovery
+            && ether_type != EtherTypes::PppoeSession,
+        BuiltinParserError(format!("{}", ether_type))
+    );
+
+    REGISTRY
+        .write()
+        .unwrap()
+        .ether_types
+        .insert(ether_type, (name, Arc::new(f)));
+    Ok(())
+}
+
+/// Registers a function as the parser of a custom IP protocol.
+///
+/// The function is given the payload of the IPv4 or IPv6 packet and
+/// returns the length of the header, or `None` if the payload is not
+/// valid. `Mbuf::layers` then reports the header as a `Layer::Custom`
+/// named `name`, followed by the rest of the payload.
+///
+/// # Errors
+///
+/// Returns `BuiltinParserError` for the protocols nb2 already parses.
+///
+/// # Example
+///
+/// ```
+/// // SCTP has a 12-byte common header.
+/// register_ip_protocol(ProtocolNumber::new(132), "Sctp", |payload| {
+///     if payload.len() >= 12 {
+///         Some(12)
+///     } else {
+///         None
+///     }
+/// })?;
+/// ```
+pub fn register_ip_protocol<F>(protocol: ProtocolNumber, name: &'static str, f: F) -> Result<()>
+where
+    F: Fn(&[u8]) -> Option<usize> + Send + Sync + 'static,
+{
+    ensure!(
+        protocol != ProtocolNumbers::Tcp
+            && protocol != ProtocolNumbers::Udp
+            && protocol != ProtocolNumbers::Icmpv6,
+        BuiltinParserError(format!("{}", protocol))
+    );
+
+    REGISTRY
+        .write()
+        .unwrap()
+        .protocols
+        .insert(protocol, (name, Arc::new(f)));
+    Ok(())
+}
+
+/// Returns the name and the header length of the custom layer after the
+/// ethernet header, if its ether type is registered.
+pub(crate) fn dissect_ether_type(ethernet: &Ethernet) -> Option<(&'static str, usize)> {
+    let registry = REGISTRY.read().unwrap();
+    let (name, f) = registry.ether_types.get(&ethernet.ether_type())?;
+    f(ethernet).map(|len| (*name, len))
+}
+
+/// Returns the name and the header length of the custom layer in the IP
+/// payload, if its protocol is registered.
+pub(crate) fn dissect_ip_protocol(
+    protocol: ProtocolNumber,
+    payload: &[u8],
+) -> Option<(&'static str, usize)> {
+    let registry = REGISTRY.read().unwrap();
+    let (name, f) = registry.protocols.get(&protocol)?;
+    f(payload).map(|len| (*name, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::{Layer, UDP_PACKET};
+    use crate::Mbuf;
+
+    fn stack(packet: &Mbuf) -> Vec<Layer> {
+        packet.layers().iter().map(|info| info.layer).collect()
+    }
+
+    #[test]
+    fn reject_builtin_parsers() {
+        assert!(register_ether_type_with(EtherTypes::Ipv4, "Ipv4", |_| None).is_err());
+        assert!(register_ip_protocol(ProtocolNumbers::Tcp, "Tcp", |_| None).is_err());
+    }
+
+    #[nb2::test]
+    fn walk_custom_ether_type() {
+        // a local experimental ether type, with a 4-byte header.
+        register_ether_type_with(EtherType::new(0x88b5), "Exp", |_| Some(4)).unwrap();
+
+        let packet = Mbuf::new().unwrap();
+        let mut ethernet = packet.push::<Ethernet>().unwrap();
+        ethernet.set_ether_type(EtherType::new(0x88b5));
+        let offset = ethernet.payload_offset();
+        ethernet.mbuf_mut().extend(offset, 10).unwrap();
+        let packet = ethernet.reset();
+
+        assert_eq!(
+            vec![Layer::Ethernet, Layer::Custom("Exp"), Layer::Payload],
+            stack(&packet)
+        );
+        assert_eq!(6, packet.layers()[2].len);
+    }
+
+    #[nb2::test]
+    fn walk_custom_ip_protocol() {
+        // an experimental protocol, with an 8-byte header.
+        register_ip_protocol(ProtocolNumber::new(253), "Exp253", |_| Some(8)).unwrap();
+
+        let mut bytes = UDP_PACKET;
+        bytes[23] = 253;
+        let packet = Mbuf::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            vec![
+                Layer::Ethernet,
+                Layer::Ipv4,
+                Layer::Custom("Exp253"),
+                Layer::Payload
+            ],
+            stack(&packet)
+        );
+        assert_eq!(10, packet.layers()[3].len);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::packets::ip::{Flow, IpPacket, ProtocolNumbers};
-use crate::packets::{checksum, CondRc, Header, Packet};
-use crate::{Result, SizeOf};
+use crate::packets::{
+    be16, be32, checksum, data_slice, data_slice_mut, replace_data_slice, CondRc, Header, Packet,
+};
+use crate::{Result, RxChecksum, SizeOf};
 use std::fmt;
 use std::net::IpAddr;
 use std::ptr::NonNull;
@@ -115,29 +117,29 @@ use std::ptr::NonNull;
 #[derive(Clone, Copy, Debug)]
 #[repr(C, packed)]
 pub struct TcpHeader {
-    src_port: u16,
-    dst_port: u16,
-    seq_no: u32,
-    ack_no: u32,
+    src_port: be16,
+    dst_port: be16,
+    seq_no: be32,
+    ack_no: be32,
     offset_to_ns: u8,
     flags: u8,
-    window: u16,
-    checksum: u16,
-    urgent_pointer: u16,
+    window: be16,
+    checksum: be16,
+    urgent_pointer: be16,
 }
 
 impl Default for TcpHeader {
     fn default() -> TcpHeader {
         TcpHeader {
-            src_port: 0,
-            dst_port: 0,
-            seq_no: 0,
-            ack_no: 0,
+            src_port: be16::default(),
+            dst_port: be16::default(),
+            seq_no: be32::default(),
+            ack_no: be32::default(),
             offset_to_ns: 5 << 4,
             flags: 0,
-            window: 0,
-            checksum: 0,
-            urgent_pointer: 0,
+            window: be16::default(),
+            checksum: be16::default(),
+            urgent_pointer: be16::default(),
         }
     }
 }
@@ -159,47 +161,48 @@ pub struct Tcp<E: IpPacket> {
     envelope: CondRc<E>,
     header: NonNull<TcpHeader>,
     offset: usize,
+    dirty: bool,
 }
 
 impl<E: IpPacket> Tcp<E> {
     #[inline]
     pub fn src_port(&self) -> u16 {
-        u16::from_be(self.header().src_port)
+        self.header().src_port.get()
     }
 
     #[inline]
     pub fn set_src_port(&mut self, src_port: u16) {
-        self.header_mut().src_port = u16::to_be(src_port);
+        self.header_mut().src_port = src_port.into();
     }
 
     #[inline]
     pub fn dst_port(&self) -> u16 {
-        u16::from_be(self.header().dst_port)
+        self.header().dst_port.get()
     }
 
     #[inline]
     pub fn set_dst_port(&mut self, dst_port: u16) {
-        self.header_mut().dst_port = u16::to_be(dst_port);
+        self.header_mut().dst_port = dst_port.into();
     }
 
     #[inline]
     pub fn seq_no(&self) -> u32 {
-        u32::from_be(self.header().seq_no)
+        self.header().seq_no.get()
     }
 
     #[inline]
     pub fn set_seq_no(&mut self, seq_no: u32) {
-        self.header_mut().seq_no = u32::to_be(seq_no);
+        self.header_mut().seq_no = seq_no.into();
     }
 
     #[inline]
     pub fn ack_no(&self) -> u32 {
-        u32::from_be(self.header().ack_no)
+        self.header().ack_no.get()
     }
 
     #[inline]
     pub fn set_ack_no(&mut self, ack_no: u32) {
-        self.header_mut().ack_no = u32::to_be(ack_no);
+        self.header_mut().ack_no = ack_no.into();
     }
 
     #[inline]
@@ -214,6 +217,17 @@ impl<E: IpPacket> Tcp<E> {
         self.header_mut().offset_to_ns = (self.header().offset_to_ns & 0x0f) | (data_offset << 4);
     }
 
+    /// Returns the 3 reserved bits between the data offset and the flags.
+    #[inline]
+    pub fn reserved(&self) -> u8 {
+        (self.header().offset_to_ns & 0x0e) >> 1
+    }
+
+    #[inline]
+    pub fn clear_reserved(&mut self) {
+        self.header_mut().offset_to_ns = self.header().offset_to_ns & !0x0e;
+    }
+
     #[inline]
     pub fn ns(&self) -> bool {
         (self.header().offset_to_ns & 0x01) != 0
@@ -356,32 +370,32 @@ impl<E: IpPacket> Tcp<E> {
 
     #[inline]
     pub fn window(&self) -> u16 {
-        u16::from_be(self.header().window)
+        self.header().window.get()
     }
 
     #[inline]
     pub fn set_window(&mut self, window: u16) {
-        self.header_mut().window = u16::to_be(window);
+        self.header_mut().window = window.into();
     }
 
     #[inline]
     pub fn checksum(&self) -> u16 {
-        u16::from_be(self.header().checksum)
+        self.header().checksum.get()
     }
 
     #[inline]
     fn set_checksum(&mut self, checksum: u16) {
-        self.header_mut().checksum = u16::to_be(checksum);
+        self.header_mut().checksum = checksum.into();
     }
 
     #[inline]
     pub fn urgent_pointer(&self) -> u16 {
-        u16::from_be(self.header().urgent_pointer)
+        self.header().urgent_pointer.get()
     }
 
     #[inline]
     pub fn set_urgent_pointer(&mut self, urgent_pointer: u16) {
-        self.header_mut().urgent_pointer = u16::to_be(urgent_pointer);
+        self.header_mut().urgent_pointer = urgent_pointer.into();
     }
 
     #[inline]
@@ -415,6 +429,61 @@ impl<E: IpPacket> Tcp<E> {
         Ok(())
     }
 
+    /// Returns the offset and length of the segment data. Unlike the
+    /// payload offset, the options are accounted for.
+    #[inline]
+    fn segment_data_range(&self) -> (usize, usize) {
+        let header_len = (self.data_offset() as usize * 4).min(self.len());
+        (self.offset() + header_len, self.len() - header_len)
+    }
+
+    /// Returns the segment data following the header and options.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        let (offset, len) = self.segment_data_range();
+        data_slice(self.mbuf(), offset, len)
+    }
+
+    /// Returns the segment data following the header and options as a
+    /// mutable slice.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        self.dirty = true;
+        let (offset, len) = self.segment_data_range();
+        data_slice_mut(self.mbuf_mut(), offset, len)
+    }
+
+    /// Replaces the segment data following the header and options.
+    ///
+    /// The buffer is resized to fit the new data. The checksum is not
+    /// updated until `cascade` is invoked.
+    #[inline]
+    pub fn set_payload(&mut self, data: &[u8]) -> Result<()> {
+        self.dirty = true;
+        let (offset, len) = self.segment_data_range();
+        replace_data_slice(self.mbuf_mut(), offset, len, data)
+    }
+
+    /// Returns whether the checksum is valid.
+    ///
+    /// If the ethernet device already validated the checksum on receive,
+    /// its result is used. Otherwise the checksum is verified in software.
+    #[inline]
+    pub fn verify_checksum(&self) -> bool {
+        match self.mbuf().rx_l4_checksum() {
+            RxChecksum::Good => true,
+            RxChecksum::Bad => false,
+            RxChecksum::Unknown => {
+                let data = data_slice(self.mbuf(), self.offset(), self.len());
+                let pseudo_header_sum = self
+                    .envelope()
+                    .pseudo_header(data.len() as u16, ProtocolNumbers::Tcp)
+                    .sum();
+                checksum::compute(pseudo_header_sum, data) == 0
+            }
+        }
+    }
+
     #[inline]
     fn compute_checksum(&mut self) {
         self.set_checksum(0);
@@ -484,6 +553,7 @@ impl<E: IpPacket> Packet for Tcp<E> {
     #[doc(hidden)]
     #[inline]
     fn header_mut(&mut self) -> &mut Self::Header {
+        self.dirty = true;
         unsafe { self.header.as_mut() }
     }
 
@@ -492,6 +562,16 @@ impl<E: IpPacket> Packet for Tcp<E> {
         self.offset
     }
 
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     #[doc(hidden)]
     #[inline]
     fn do_parse(envelope: Self::Envelope) -> Result<Self> {
@@ -503,6 +583,7 @@ impl<E: IpPacket> Packet for Tcp<E> {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: false,
         })
     }
 
@@ -521,6 +602,7 @@ impl<E: IpPacket> Packet for Tcp<E> {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: true,
         })
     }
 
@@ -533,9 +615,12 @@ impl<E: IpPacket> Packet for Tcp<E> {
     }
 
     #[inline]
-    fn cascade(&mut self) {
+    fn reconcile(&mut self) {
+        // the checksum covers the payload, which can be modified through
+        // the mbuf without going through the packet, so it's always
+        // recomputed.
         self.compute_checksum();
-        self.envelope_mut().cascade();
+        self.dirty = false;
     }
 
     #[inline]
@@ -618,6 +703,56 @@ mod tests {
         assert!(!tcp.fin());
     }
 
+    #[nb2::test]
+    fn verify_tcp_checksum() {
+        let packet = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp<Ipv4>>().unwrap();
+        assert!(tcp.verify_checksum());
+
+        tcp.set_window(1024);
+        assert!(!tcp.verify_checksum());
+
+        tcp.cascade();
+        assert!(tcp.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn tcp_payload() {
+        let packet = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp<Ipv4>>().unwrap();
+
+        // the options are not part of the payload
+        assert!(tcp.payload().is_empty());
+
+        tcp.set_payload(b"hello").unwrap();
+        assert_eq!(b"hello", tcp.payload());
+        assert_eq!(29, tcp.len());
+
+        tcp.payload_mut()[0] = b'j';
+        assert_eq!(b"jello", tcp.payload());
+    }
+
+    #[nb2::test]
+    fn cascade_payload_extended_through_mbuf() {
+        let packet = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut tcp = ipv4.parse::<Tcp<Ipv4>>().unwrap();
+
+        let offset = tcp.payload_offset() + tcp.payload_len();
+        tcp.mbuf_mut().extend(offset, 5).unwrap();
+        tcp.mbuf_mut().write_data_slice(offset, b"hello").unwrap();
+        assert!(!tcp.is_dirty());
+
+        tcp.cascade();
+        assert!(tcp.verify_checksum());
+        assert!(tcp.envelope().verify_checksum());
+    }
+
     #[nb2::test]
     fn tcp_flow_v4() {
         let packet = Mbuf::from_bytes(&TCP_PACKET).unwrap();
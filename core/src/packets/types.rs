@@ -0,0 +1,115 @@
+#![allow(non_camel_case_types)]
+
+use std::fmt;
+
+// generates an unsigned integer type stored in network byte order.
+macro_rules! be_type {
+    ($name:ident, $prim:ty, $doc:expr) => {
+        #[doc = $doc]
+        ///
+        /// The value is stored in network byte order so the type can be
+        /// used directly in a packet header struct, and converted to and
+        /// from host byte order on access.
+        #[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
+        #[repr(transparent)]
+        pub struct $name($prim);
+
+        impl $name {
+            /// Creates a new value from an integer in host byte order.
+            #[inline]
+            pub const fn new(value: $prim) -> Self {
+                $name(value.to_be())
+            }
+
+            /// Returns the value in host byte order.
+            #[inline]
+            pub fn get(self) -> $prim {
+                <$prim>::from_be(self.0)
+            }
+
+            /// Sets the value from an integer in host byte order.
+            #[inline]
+            pub fn set(&mut self, value: $prim) {
+                self.0 = value.to_be();
+            }
+
+            /// Returns the value as stored, in network byte order.
+            #[inline]
+            pub fn to_raw(self) -> $prim {
+                self.0
+            }
+        }
+
+        impl From<$prim> for $name {
+            #[inline]
+            fn from(value: $prim) -> Self {
+                $name::new(value)
+            }
+        }
+
+        impl From<$name> for $prim {
+            #[inline]
+            fn from(value: $name) -> Self {
+                value.get()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Debug::fmt(&self.get(), f)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.get(), f)
+            }
+        }
+    };
+}
+
+be_type!(
+    be16,
+    u16,
+    "A 16-bit unsigned integer in network byte order."
+);
+be_type!(
+    be32,
+    u32,
+    "A 32-bit unsigned integer in network byte order."
+);
+be_type!(
+    be64,
+    u64,
+    "A 64-bit unsigned integer in network byte order."
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SizeOf;
+
+    #[test]
+    fn size_of_be_types() {
+        assert_eq!(2, be16::size_of());
+        assert_eq!(4, be32::size_of());
+        assert_eq!(8, be64::size_of());
+    }
+
+    #[test]
+    fn stored_in_network_byte_order() {
+        let value = be16::new(0x1234);
+        assert_eq!(0x1234, value.get());
+        assert_eq!([0x12, 0x34], value.to_raw().to_ne_bytes());
+
+        let value = be32::new(0x1234_5678);
+        assert_eq!([0x12, 0x34, 0x56, 0x78], value.to_raw().to_ne_bytes());
+        assert_eq!(0x1234_5678, u32::from(value));
+    }
+
+    #[test]
+    fn debug_shows_host_value() {
+        assert_eq!("4660", format!("{:?}", be16::new(0x1234)));
+        assert_eq!("4660", be64::new(0x1234).to_string());
+    }
+}
@@ -1,6 +1,8 @@
 use crate::packets::ip::{Flow, IpPacket, ProtocolNumbers};
-use crate::packets::{checksum, CondRc, Header, Packet};
-use crate::{Result, SizeOf};
+use crate::packets::{
+    be16, checksum, data_slice, data_slice_mut, replace_data_slice, CondRc, Header, Packet,
+};
+use crate::{Result, RxChecksum, SizeOf};
 use std::fmt;
 use std::net::IpAddr;
 use std::ptr::NonNull;
@@ -61,10 +63,10 @@ use std::ptr::NonNull;
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(C)]
 pub struct UdpHeader {
-    src_port: u16,
-    dst_port: u16,
-    length: u16,
-    checksum: u16,
+    src_port: be16,
+    dst_port: be16,
+    length: be16,
+    checksum: be16,
 }
 
 impl Header for UdpHeader {}
@@ -75,42 +77,43 @@ pub struct Udp<E: IpPacket> {
     envelope: CondRc<E>,
     header: NonNull<UdpHeader>,
     offset: usize,
+    dirty: bool,
 }
 
 impl<E: IpPacket> Udp<E> {
     #[inline]
     pub fn src_port(&self) -> u16 {
-        u16::from_be(self.header().src_port)
+        self.header().src_port.get()
     }
 
     #[inline]
     pub fn set_src_port(&mut self, src_port: u16) {
-        self.header_mut().src_port = u16::to_be(src_port);
+        self.header_mut().src_port = src_port.into();
     }
 
     #[inline]
     pub fn dst_port(&self) -> u16 {
-        u16::from_be(self.header().dst_port)
+        self.header().dst_port.get()
     }
 
     #[inline]
     pub fn set_dst_port(&mut self, dst_port: u16) {
-        self.header_mut().dst_port = u16::to_be(dst_port);
+        self.header_mut().dst_port = dst_port.into();
     }
 
     #[inline]
     pub fn length(&self) -> u16 {
-        u16::from_be(self.header().length)
+        self.header().length.get()
     }
 
     #[inline]
     fn set_length(&mut self, length: u16) {
-        self.header_mut().length = u16::to_be(length);
+        self.header_mut().length = length.into();
     }
 
     #[inline]
     pub fn checksum(&self) -> u16 {
-        u16::from_be(self.header().checksum)
+        self.header().checksum.get()
     }
 
     #[inline]
@@ -120,15 +123,15 @@ impl<E: IpPacket> Udp<E> {
         // transmitter generated no checksum. To set the checksum value to
         // `0`, use `no_checksum` instead of `set_checksum`.
         self.header_mut().checksum = match checksum {
-            0 => 0xFFFF,
-            _ => u16::to_be(checksum),
+            0 => be16::new(0xFFFF),
+            _ => checksum.into(),
         }
     }
 
     /// Sets checksum to 0 indicating no checksum generated.
     #[inline]
     pub fn no_checksum(&mut self) {
-        self.header_mut().checksum = 0;
+        self.header_mut().checksum = be16::default();
     }
 
     #[inline]
@@ -162,6 +165,59 @@ impl<E: IpPacket> Udp<E> {
         Ok(())
     }
 
+    /// Returns the datagram's data octets.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        data_slice(self.mbuf(), self.payload_offset(), self.payload_len())
+    }
+
+    /// Returns the datagram's data octets as a mutable slice.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        self.dirty = true;
+        let offset = self.payload_offset();
+        let len = self.payload_len();
+        data_slice_mut(self.mbuf_mut(), offset, len)
+    }
+
+    /// Replaces the datagram's data octets.
+    ///
+    /// The buffer is resized to fit the new data. The length and checksum
+    /// are not updated until `cascade` is invoked.
+    #[inline]
+    pub fn set_payload(&mut self, data: &[u8]) -> Result<()> {
+        self.dirty = true;
+        let offset = self.payload_offset();
+        let len = self.payload_len();
+        replace_data_slice(self.mbuf_mut(), offset, len, data)
+    }
+
+    /// Returns whether the checksum is valid.
+    ///
+    /// If the ethernet device already validated the checksum on receive,
+    /// its result is used. Otherwise the checksum is verified in software.
+    /// A zero checksum over IPv4 means none was generated, and is always
+    /// valid.
+    #[inline]
+    pub fn verify_checksum(&self) -> bool {
+        match self.mbuf().rx_l4_checksum() {
+            RxChecksum::Good => true,
+            RxChecksum::Bad => false,
+            RxChecksum::Unknown => {
+                if self.checksum() == 0 {
+                    return self.envelope().src().is_ipv4();
+                }
+
+                let data = data_slice(self.mbuf(), self.offset(), self.len());
+                let pseudo_header_sum = self
+                    .envelope()
+                    .pseudo_header(data.len() as u16, ProtocolNumbers::Udp)
+                    .sum();
+                checksum::compute(pseudo_header_sum, data) == 0
+            }
+        }
+    }
+
     #[inline]
     fn compute_checksum(&mut self) {
         self.no_checksum();
@@ -218,6 +274,7 @@ impl<E: IpPacket> Packet for Udp<E> {
     #[doc(hidden)]
     #[inline]
     fn header_mut(&mut self) -> &mut Self::Header {
+        self.dirty = true;
         unsafe { self.header.as_mut() }
     }
 
@@ -226,6 +283,16 @@ impl<E: IpPacket> Packet for Udp<E> {
         self.offset
     }
 
+    #[inline]
+    fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    #[inline]
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
     #[doc(hidden)]
     #[inline]
     fn do_parse(envelope: Self::Envelope) -> Result<Self> {
@@ -237,6 +304,7 @@ impl<E: IpPacket> Packet for Udp<E> {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: false,
         })
     }
 
@@ -255,6 +323,7 @@ impl<E: IpPacket> Packet for Udp<E> {
             envelope: CondRc::new(envelope),
             header,
             offset,
+            dirty: true,
         })
     }
 
@@ -267,11 +336,17 @@ impl<E: IpPacket> Packet for Udp<E> {
     }
 
     #[inline]
-    fn cascade(&mut self) {
+    fn reconcile(&mut self) {
         let len = self.len() as u16;
-        self.set_length(len);
+        if self.length() != len {
+            self.set_length(len);
+        }
+
+        // the checksum covers the payload, which can be modified through
+        // the mbuf without going through the packet, so it's always
+        // recomputed.
         self.compute_checksum();
-        self.envelope_mut().cascade();
+        self.dirty = false;
     }
 
     #[inline]
@@ -334,6 +409,42 @@ mod tests {
         assert_eq!(0x7228, udp.checksum());
     }
 
+    #[nb2::test]
+    fn verify_udp_checksum() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        assert!(udp.verify_checksum());
+
+        udp.set_dst_port(53);
+        assert!(!udp.verify_checksum());
+
+        udp.no_checksum();
+        assert!(udp.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn udp_payload() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+
+        assert_eq!(b"hellohello", udp.payload());
+
+        udp.payload_mut()[0] = b'j';
+        assert_eq!(b"jellohello", udp.payload());
+
+        udp.set_payload(b"world").unwrap();
+        assert_eq!(b"world", udp.payload());
+        udp.cascade();
+        assert_eq!(13, udp.length());
+
+        udp.set_payload(&[]).unwrap();
+        assert!(udp.payload().is_empty());
+    }
+
     #[nb2::test]
     fn udp_flow_v4() {
         let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
@@ -372,6 +483,59 @@ mod tests {
         assert!(udp.set_src_ip(Ipv6Addr::UNSPECIFIED.into()).is_err());
     }
 
+    #[nb2::test]
+    fn cascade_only_dirty_layers() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+        assert!(!udp.is_dirty());
+
+        udp.set_dst_port(53);
+        assert!(udp.is_dirty());
+        assert!(!udp.envelope().is_dirty());
+
+        udp.cascade();
+        assert!(!udp.is_dirty());
+        assert!(udp.verify_checksum());
+        assert!(udp.envelope().verify_checksum());
+    }
+
+    #[nb2::test]
+    fn cascade_payload_edited_through_mbuf() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+
+        let offset = udp.payload_offset();
+        udp.mbuf_mut().write_data_slice(offset, b"jello").unwrap();
+        assert!(!udp.is_dirty());
+        assert!(!udp.verify_checksum());
+
+        udp.cascade();
+        assert!(udp.verify_checksum());
+    }
+
+    #[nb2::test]
+    fn cascade_to_depth() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let ethernet = packet.parse::<Ethernet>().unwrap();
+        let ipv4 = ethernet.parse::<Ipv4>().unwrap();
+        let mut udp = ipv4.parse::<Udp<Ipv4>>().unwrap();
+
+        udp.envelope_mut().set_ttl(1);
+        udp.cascade_depth(1);
+
+        // the ipv4 header is not fixed up
+        assert!(udp.envelope().is_dirty());
+        assert!(!udp.envelope().verify_checksum());
+
+        udp.cascade();
+        assert!(!udp.envelope().is_dirty());
+        assert!(udp.envelope().verify_checksum());
+    }
+
     #[nb2::test]
     fn compute_checksum() {
         let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
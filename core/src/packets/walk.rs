@@ -0,0 +1,344 @@
+use crate::packets::icmp::v6::{Icmpv6, Icmpv6Packet, Icmpv6Type};
+use crate::packets::ip::v4::Ipv4;
+use crate::packets::ip::v6::{is_ipv6_extension, Ipv6, Ipv6Extensions, Ipv6Packet, SegmentRouting};
+use crate::packets::ip::{IpPacket, ProtocolNumbers};
+use crate::packets::registry::{dissect_ether_type, dissect_ip_protocol};
+use crate::packets::{
+    data_slice, EtherTypes, Ethernet, Gtpc, L2tpv3, Packet, Pppoe, Tcp, Udp, L2TP_PORT,
+};
+use crate::Mbuf;
+use std::fmt;
+use std::ops::Deref;
+
+/// A protocol layer recognized by the walker.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Layer {
+    Ethernet,
+    Pppoe,
+    Ipv4,
+    Ipv6,
+    Ipv6Extensions,
+    SegmentRouting,
+    Tcp,
+    Udp,
+    Icmpv6(Icmpv6Type),
+    Gtpc,
+    L2tpv3,
+    /// A layer parsed by a parser registered with `register_ether_type`
+    /// or `register_ip_protocol`, by name.
+    Custom(&'static str),
+    /// The bytes after the last recognized layer.
+    Payload,
+}
+
+/// A layer of the packet and where it is in the buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LayerInfo {
+    pub layer: Layer,
+    /// The offset of the layer from the start of the buffer.
+    pub offset: usize,
+    /// The header length of the layer, or the length of the bytes for
+    /// `Layer::Payload`.
+    pub len: usize,
+}
+
+/// The stack of layers of a packet, from the outermost in.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Layers(Vec<LayerInfo>);
+
+impl Layers {
+    /// Returns whether the packet has the layer.
+    pub fn contains(&self, layer: Layer) -> bool {
+        self.find(layer).is_some()
+    }
+
+    /// Returns the first occurrence of the layer.
+    pub fn find(&self, layer: Layer) -> Option<&LayerInfo> {
+        self.0.iter().find(|info| info.layer == layer)
+    }
+
+    #[inline]
+    fn push<T: Packet>(&mut self, layer: Layer, packet: &T) {
+        self.0.push(LayerInfo {
+            layer,
+            offset: packet.offset(),
+            len: packet.header_len(),
+        });
+    }
+
+    /// Adds a custom layer at the start of the payload of its envelope,
+    /// then the rest of the payload.
+    fn push_custom<T: Packet>(&mut self, name: &'static str, len: usize, envelope: &T) {
+        let len = len.min(envelope.payload_len());
+        self.0.push(LayerInfo {
+            layer: Layer::Custom(name),
+            offset: envelope.payload_offset(),
+            len,
+        });
+
+        if envelope.payload_len() > len {
+            self.0.push(LayerInfo {
+                layer: Layer::Payload,
+                offset: envelope.payload_offset() + len,
+                len: envelope.payload_len() - len,
+            });
+        }
+    }
+
+    /// Adds the payload of the innermost layer, if there is any.
+    fn push_payload<T: Packet>(&mut self, packet: &T) {
+        if packet.payload_len() > 0 {
+            self.0.push(LayerInfo {
+                layer: Layer::Payload,
+                offset: packet.payload_offset(),
+                len: packet.payload_len(),
+            });
+        }
+    }
+}
+
+impl Deref for Layers {
+    type Target = [LayerInfo];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Formats the layers as `Ethernet > Ipv6 > SegmentRouting > Tcp`.
+impl fmt::Display for Layers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self
+            .0
+            .iter()
+            .map(|info| match info.layer {
+                Layer::Icmpv6(msg_type) => format!("Icmpv6({})", msg_type),
+                Layer::Custom(name) => name.to_owned(),
+                layer => format!("{:?}", layer),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", names.join(" > "))
+    }
+}
+
+impl Mbuf {
+    /// Parses the packet as far as the built-in and the registered parsers
+    /// can and returns a summary of the stack of layers.
+    ///
+    /// A layer that fails to parse ends the walk, its bytes are reported
+    /// as `Layer::Payload`. The walk only reads the buffer, the packet is
+    /// not modified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let layers = packet.layers();
+    /// if layers.contains(Layer::SegmentRouting) {
+    ///     println!("{}", layers);
+    /// }
+    /// ```
+    pub fn layers(&self) -> Layers {
+        let mut layers = Layers::default();
+
+        match self.peek::<Ethernet>() {
+            Ok(ethernet) => walk_ethernet(&ethernet, &mut layers),
+            Err(_) => layers.push_payload(self),
+        }
+
+        layers
+    }
+}
+
+fn walk_ethernet(ethernet: &Ethernet, layers: &mut Layers) {
+    layers.push(Layer::Ethernet, ethernet);
+
+    match ethernet.ether_type() {
+        EtherTypes::Ipv4 => {
+            if let Ok(ipv4) = ethernet.peek::<Ipv4>() {
+                layers.push(Layer::Ipv4, &*ipv4);
+                return walk_transport(&*ipv4, layers);
+            }
+        }
+        EtherTypes::Ipv6 => {
+            if let Ok(ipv6) = ethernet.peek::<Ipv6>() {
+                layers.push(Layer::Ipv6, &*ipv6);
+                return walk_ipv6(&ipv6, layers);
+            }
+        }
+        EtherTypes::PppoeDiscovery | EtherTypes::PppoeSession => {
+            if let Ok(pppoe) = ethernet.peek::<Pppoe>() {
+                layers.push(Layer::Pppoe, &*pppoe);
+                return layers.push_payload(&*pppoe);
+            }
+        }
+        _ => {
+            if let Some((name, len)) = dissect_ether_type(ethernet) {
+                return layers.push_custom(name, len, ethernet);
+            }
+        }
+    }
+
+    layers.push_payload(ethernet);
+}
+
+// the chain of extension headers is unrolled by hand, the packet types
+// are generic over their envelope and cannot be walked recursively.
+fn walk_ipv6(ipv6: &Ipv6, layers: &mut Layers) {
+    if ipv6.next_header() == ProtocolNumbers::Ipv6Route {
+        if let Ok(srh) = ipv6.peek::<SegmentRouting<Ipv6>>() {
+            layers.push(Layer::SegmentRouting, &*srh);
+
+            if is_ipv6_extension(srh.next_header()) {
+                if let Ok(extensions) = srh.peek::<Ipv6Extensions<SegmentRouting<Ipv6>>>() {
+                    layers.push(Layer::Ipv6Extensions, &*extensions);
+                    return walk_ipv6_upper(&*extensions, layers);
+                }
+            }
+
+            return walk_ipv6_upper(&*srh, layers);
+        }
+    }
+
+    if is_ipv6_extension(ipv6.next_header()) {
+        if let Ok(extensions) = ipv6.peek::<Ipv6Extensions<Ipv6>>() {
+            layers.push(Layer::Ipv6Extensions, &*extensions);
+            return walk_ipv6_upper(&*extensions, layers);
+        }
+    }
+
+    walk_ipv6_upper(ipv6, layers)
+}
+
+fn walk_ipv6_upper<E: Ipv6Packet>(ip: &E, layers: &mut Layers) {
+    if ip.next_proto() == ProtocolNumbers::Icmpv6 {
+        if let Ok(icmpv6) = ip.peek::<Icmpv6<E, ()>>() {
+            layers.push(Layer::Icmpv6(icmpv6.msg_type()), &*icmpv6);
+            return layers.push_payload(&*icmpv6);
+        }
+    }
+
+    walk_transport(ip, layers)
+}
+
+fn walk_transport<E: IpPacket>(ip: &E, layers: &mut Layers) {
+    match ip.next_proto() {
+        ProtocolNumbers::Tcp => {
+            if let Ok(tcp) = ip.peek::<Tcp<E>>() {
+                layers.push(Layer::Tcp, &*tcp);
+                return layers.push_payload(&*tcp);
+            }
+        }
+        ProtocolNumbers::Udp => {
+            if let Ok(udp) = ip.peek::<Udp<E>>() {
+                layers.push(Layer::Udp, &*udp);
+                return walk_udp(&udp, layers);
+            }
+        }
+        protocol => {
+            let payload = data_slice(ip.mbuf(), ip.payload_offset(), ip.payload_len());
+            if let Some((name, len)) = dissect_ip_protocol(protocol, payload) {
+                return layers.push_custom(name, len, ip);
+            }
+        }
+    }
+
+    layers.push_payload(ip);
+}
+
+fn walk_udp<E: IpPacket>(udp: &Udp<E>, layers: &mut Layers) {
+    if Gtpc::is_gtpc(udp) {
+        if let Ok(gtpc) = udp.peek::<Gtpc<E>>() {
+            layers.push(Layer::Gtpc, &*gtpc);
+            return layers.push_payload(&*gtpc);
+        }
+    } else if udp.dst_port() == L2TP_PORT || udp.src_port() == L2TP_PORT {
+        if let Ok(l2tp) = udp.peek::<L2tpv3<Udp<E>>>() {
+            layers.push(Layer::L2tpv3, &*l2tp);
+            return layers.push_payload(&*l2tp);
+        }
+    }
+
+    layers.push_payload(udp);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::icmp::v6::ICMPV6_PACKET;
+    use crate::packets::ip::v6::{IPV6_EXTENSIONS_PACKET, SRH_PACKET};
+    use crate::packets::UDP_PACKET;
+
+    fn stack(layers: &Layers) -> Vec<Layer> {
+        layers.iter().map(|info| info.layer).collect()
+    }
+
+    #[nb2::test]
+    fn walk_udp_packet() {
+        let packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        let layers = packet.layers();
+
+        assert_eq!(
+            vec![Layer::Ethernet, Layer::Ipv4, Layer::Udp, Layer::Payload],
+            stack(&layers)
+        );
+        assert_eq!(
+            LayerInfo {
+                layer: Layer::Udp,
+                offset: 34,
+                len: 8
+            },
+            *layers.find(Layer::Udp).unwrap()
+        );
+        assert_eq!("Ethernet > Ipv4 > Udp > Payload", layers.to_string());
+    }
+
+    #[nb2::test]
+    fn walk_srh_packet() {
+        let packet = Mbuf::from_bytes(&SRH_PACKET).unwrap();
+        let layers = packet.layers();
+
+        assert_eq!(Layer::Ethernet, layers[0].layer);
+        assert_eq!(Layer::Ipv6, layers[1].layer);
+        assert_eq!(Layer::SegmentRouting, layers[2].layer);
+        assert_eq!(Layer::Tcp, layers[3].layer);
+        assert_eq!(54, layers[2].offset);
+    }
+
+    #[nb2::test]
+    fn walk_extension_headers() {
+        let packet = Mbuf::from_bytes(&IPV6_EXTENSIONS_PACKET).unwrap();
+        let layers = packet.layers();
+
+        assert_eq!(
+            vec![
+                Layer::Ethernet,
+                Layer::Ipv6,
+                Layer::Ipv6Extensions,
+                Layer::Udp,
+                Layer::Payload
+            ],
+            stack(&layers)
+        );
+    }
+
+    #[nb2::test]
+    fn walk_icmpv6_packet() {
+        let packet = Mbuf::from_bytes(&ICMPV6_PACKET).unwrap();
+        let layers = packet.layers();
+
+        assert!(layers.contains(Layer::Icmpv6(Icmpv6Type::new(0xff))));
+    }
+
+    #[nb2::test]
+    fn walk_stops_at_bad_layer() {
+        // cuts the udp header in half.
+        let packet = Mbuf::from_bytes(&UDP_PACKET[..38]).unwrap();
+        let layers = packet.layers();
+
+        assert_eq!(
+            vec![Layer::Ethernet, Layer::Ipv4, Layer::Payload],
+            stack(&layers)
+        );
+        assert_eq!(4, layers[2].len);
+    }
+}
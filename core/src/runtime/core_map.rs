@@ -1,5 +1,5 @@
 use super::MempoolMap2;
-use crate::dpdk::{CoreId, MEMPOOL};
+use crate::dpdk::{CoreId, HEADROOM, MEMPOOL};
 use crate::{debug, error, ffi, info, Result};
 use failure::Fail;
 use futures::Future;
@@ -194,9 +194,11 @@ impl<'a> CoreMapBuilder<'a> {
         // first initializes the master core, which the current running
         // thread should be affinitized to.
         let socket_id = self.master_core.socket_id();
-        let mempool = self.mempools.get_raw(socket_id)?;
+        let mempool = self.mempools.get(socket_id)?;
+        let headroom = mempool.headroom() as u16;
 
-        let (master_thread, core_executor) = init_master_core(self.master_core, mempool)?;
+        let (master_thread, core_executor) =
+            init_master_core(self.master_core, mempool.raw_mut(), headroom)?;
 
         // adds the master core to the map. tasks can be spawned onto the
         // master core like any other cores.
@@ -214,8 +216,9 @@ impl<'a> CoreMapBuilder<'a> {
             // reference in a sendable pointer because we are sending it to
             // a background thread
             let socket_id = core_id.socket_id();
-            let mempool = self.mempools.get_raw(socket_id)?;
-            let ptr = SendablePtr(mempool);
+            let mempool = self.mempools.get(socket_id)?;
+            let headroom = mempool.headroom() as u16;
+            let ptr = SendablePtr(mempool.raw_mut());
 
             // creates a synchronous channel so we can retrieve the executor for
             // the background core.
@@ -226,7 +229,7 @@ impl<'a> CoreMapBuilder<'a> {
             let join = thread::spawn(move || {
                 debug!("spawned background thread {:?}.", thread::current().id());
 
-                match init_background_core(core_id, ptr.0) {
+                match init_background_core(core_id, ptr.0, headroom) {
                     Ok((mut thread, park, shutdown, executor)) => {
                         info!("initialized thread on {:?}.", core_id);
 
@@ -275,12 +278,14 @@ impl<'a> CoreMapBuilder<'a> {
 fn init_master_core(
     id: CoreId,
     mempool: *mut ffi::rte_mempool,
+    headroom: u16,
 ) -> Result<(MasterExecutor, CoreExecutor)> {
     // affinitize the running thread to this core.
     id.set_thread_affinity()?;
 
     // sets the mempool
     MEMPOOL.with(|tls| tls.set(mempool));
+    HEADROOM.with(|tls| tls.set(headroom));
 
     // starts a reactor so we can receive signals on the master core.
     let reactor = Reactor::new()?;
@@ -315,6 +320,7 @@ fn init_master_core(
 fn init_background_core(
     id: CoreId,
     mempool: *mut ffi::rte_mempool,
+    headroom: u16,
 ) -> Result<(
     CurrentThread<Timer<ParkThread>>,
     Park,
@@ -326,6 +332,7 @@ fn init_background_core(
 
     // sets the mempool
     MEMPOOL.with(|tls| tls.set(mempool));
+    HEADROOM.with(|tls| tls.set(headroom));
 
     // starts a per-core timer so we can schedule timed tasks.
     let park = ParkThread::new();
@@ -0,0 +1,103 @@
+use crate::dpdk::{CoreId, PortQueue, RingRx, RingTx};
+use crate::net::MacAddr;
+use crate::Mbuf;
+use failure::Fail;
+use serde::Deserialize;
+
+/// The default capacity of the rings between the port cores and the
+/// workers in pipeline mode.
+pub const DEFAULT_RING_SIZE: usize = 1024;
+
+/// How the cores of the runtime process the packets.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionMode {
+    /// Each core of a port receives, processes and transmits the packets
+    /// of its own queue. The default.
+    RunToCompletion,
+    /// The cores of a port only receive and transmit. The packets are
+    /// spread by flow across worker cores through rings, and the workers
+    /// hand them back to the port cores to transmit.
+    Pipeline,
+}
+
+impl Default for ExecutionMode {
+    fn default() -> Self {
+        ExecutionMode::RunToCompletion
+    }
+}
+
+/// Execution errors.
+#[derive(Debug, Fail)]
+pub enum ExecutionError {
+    /// The pipeline mode has no worker cores.
+    #[fail(display = "Pipeline mode needs at least one worker core.")]
+    NoWorkers,
+
+    /// A worker core is also assigned to a port.
+    #[fail(display = "{:?} cannot be both a worker and a port core.", _0)]
+    WorkerIsPortCore(CoreId),
+}
+
+/// The queue a worker pipeline receives from and transmits through.
+///
+/// In run-to-completion mode, it is the port queue of the core. In
+/// pipeline mode, it is a pair of rings to and from a port core. Either
+/// way, it is both a `PacketRx` and a `PacketTx`, so the same pipeline
+/// runs in both modes.
+#[derive(Clone)]
+pub struct WorkerQueue {
+    inner: Inner,
+    mac_addr: MacAddr,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Port(PortQueue),
+    Rings(RingRx, RingTx),
+}
+
+impl WorkerQueue {
+    pub(crate) fn port(q: PortQueue) -> Self {
+        WorkerQueue {
+            mac_addr: q.mac_addr(),
+            inner: Inner::Port(q),
+        }
+    }
+
+    pub(crate) fn rings(rx: RingRx, tx: RingTx, mac_addr: MacAddr) -> Self {
+        WorkerQueue {
+            inner: Inner::Rings(rx, tx),
+            mac_addr,
+        }
+    }
+
+    /// Returns the MAC address of the port.
+    pub fn mac_addr(&self) -> MacAddr {
+        self.mac_addr
+    }
+
+    /// Returns whether the queue is a port queue, run to completion.
+    pub fn is_port(&self) -> bool {
+        match self.inner {
+            Inner::Port(_) => true,
+            Inner::Rings(..) => false,
+        }
+    }
+
+    /// Receives a burst of packets.
+    pub(crate) fn receive(&self) -> Vec<Mbuf> {
+        match &self.inner {
+            Inner::Port(q) => q.receive(),
+            Inner::Rings(rx, _) => rx.dequeue(),
+        }
+    }
+
+    /// Transmits the packets.
+    pub(crate) fn transmit(&self, packets: Vec<Mbuf>) {
+        match &self.inner {
+            Inner::Port(q) => q.transmit(packets),
+            Inner::Rings(_, tx) => tx.enqueue(packets),
+        }
+    }
+}
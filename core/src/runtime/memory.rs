@@ -0,0 +1,433 @@
+use crate::dpdk::{CoreId, SocketId};
+use crate::ffi;
+use crate::settings::{MempoolSettings, RuntimeSettings};
+use crate::{debug, ensure, warn, Result};
+use failure::Fail;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::path::Path;
+
+// where the kernel reports the hugepages of each NUMA node.
+const NODE_ROOT: &str = "/sys/devices/system/node";
+
+// where the kernel reports the hugepages of a system without NUMA.
+const SYSTEM_ROOT: &str = "/sys/kernel/mm";
+
+// per object overhead of the mempool, the object header and trailer and
+// the slot in the ring, rounded up.
+const MEMPOOL_OBJ_OVERHEAD: usize = 64;
+
+/// Memory errors.
+#[derive(Debug, Fail)]
+pub enum MemoryError {
+    /// The system has no free hugepages.
+    #[fail(
+        display = "No free hugepages. Reserve some with 'echo 1024 > /sys/kernel/mm/hugepages/hugepages-2048kB/nr_hugepages'."
+    )]
+    NoHugepages,
+
+    /// A node does not have enough free hugepages for its mempools.
+    #[fail(
+        display = "Need {} {}MB pages on node {}, only {} are free.",
+        need, page_mb, node, free
+    )]
+    InsufficientHugepages {
+        node: usize,
+        page_mb: usize,
+        need: usize,
+        free: usize,
+    },
+}
+
+/// The hugepages of one size on one NUMA node.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Hugepages {
+    pub node: usize,
+    /// The size of a page in KB.
+    pub size_kb: usize,
+    /// The number of pages reserved.
+    pub total: usize,
+    /// The number of reserved pages not in use yet.
+    pub free: usize,
+}
+
+impl Hugepages {
+    /// Returns the free memory in bytes.
+    pub fn free_bytes(&self) -> usize {
+        self.free * self.size_kb * 1024
+    }
+}
+
+/// How the devices address the memory.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IovaMode {
+    /// Physical addresses.
+    Pa,
+    /// Virtual addresses, through an IOMMU.
+    Va,
+    /// Either, the devices do not care.
+    DontCare,
+}
+
+/// The memory setup of the system, gathered at startup.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryInfo {
+    pub hugepages: Vec<Hugepages>,
+    /// The number of memory channels, `None` before the EAL is initialized.
+    pub channels: Option<usize>,
+    /// The IOVA mode, `None` before the EAL is initialized.
+    pub iova_mode: Option<IovaMode>,
+}
+
+impl MemoryInfo {
+    /// Reads the hugepages of the system.
+    pub(crate) fn gather() -> Self {
+        let hugepages = read_node_hugepages(Path::new(NODE_ROOT))
+            .filter(|pages| !pages.is_empty())
+            .or_else(|| read_hugepages(&Path::new(SYSTEM_ROOT).join("hugepages"), 0))
+            .unwrap_or_default();
+
+        MemoryInfo {
+            hugepages,
+            channels: None,
+            iova_mode: None,
+        }
+    }
+
+    /// Adds what the EAL decided to the memory setup.
+    pub(crate) fn gather_eal(&mut self) {
+        let channels = unsafe { ffi::rte_memory_get_nchannel() } as usize;
+        // 0 when the EAL detected nothing and was not told.
+        self.channels = if channels > 0 { Some(channels) } else { None };
+
+        self.iova_mode = Some(match unsafe { ffi::rte_eal_iova_mode() } {
+            ffi::rte_iova_mode::RTE_IOVA_PA => IovaMode::Pa,
+            ffi::rte_iova_mode::RTE_IOVA_VA => IovaMode::Va,
+            _ => IovaMode::DontCare,
+        });
+    }
+
+    /// Returns the free hugepage memory of a node, in bytes.
+    pub fn free_bytes(&self, node: usize) -> usize {
+        self.hugepages
+            .iter()
+            .filter(|pages| pages.node == node)
+            .map(Hugepages::free_bytes)
+            .sum()
+    }
+
+    /// Checks that there are hugepages to initialize the EAL with.
+    ///
+    /// The check is skipped with a warning when the hugepages could not be
+    /// read, the EAL will tell. It is not done at all under `--no-huge`.
+    ///
+    /// # Errors
+    ///
+    /// If there are no free hugepages, `MemoryError::NoHugepages` is
+    /// returned.
+    pub(crate) fn check_available(&self) -> Result<()> {
+        if self.hugepages.is_empty() {
+            warn!("unable to read the hugepages, not checked.");
+            return Ok(());
+        }
+
+        ensure!(
+            self.hugepages.iter().any(|pages| pages.free > 0),
+            MemoryError::NoHugepages
+        );
+        Ok(())
+    }
+
+    /// Checks that each node has enough free hugepages for the mempools
+    /// that will be created on it. `demand` is in bytes, by node.
+    ///
+    /// # Errors
+    ///
+    /// If a node is short, `MemoryError::InsufficientHugepages` is returned
+    /// with the number of pages needed.
+    pub(crate) fn check_demand(&self, demand: &HashMap<usize, usize>) -> Result<()> {
+        // the info is incomplete, let the EAL decide.
+        if self.hugepages.is_empty() {
+            return Ok(());
+        }
+
+        for (&node, &bytes) in demand.iter() {
+            let free = self.free_bytes(node);
+
+            if bytes > free {
+                // reports in the largest page size reserved on the node.
+                let size_kb = self
+                    .hugepages
+                    .iter()
+                    .filter(|pages| pages.node == node)
+                    .map(|pages| pages.size_kb)
+                    .max()
+                    .unwrap_or(2048);
+                let page = size_kb * 1024;
+
+                return Err(MemoryError::InsufficientHugepages {
+                    node,
+                    page_mb: size_kb / 1024,
+                    need: (bytes + page - 1) / page,
+                    free: free / page,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for MemoryInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for pages in self.hugepages.iter() {
+            write!(
+                f,
+                "node{}: {}/{} {}kB pages free, ",
+                pages.node, pages.free, pages.total, pages.size_kb
+            )?;
+        }
+        match self.channels {
+            Some(channels) => write!(f, "{} memory channels, ", channels)?,
+            None => write!(f, "unknown memory channels, ")?,
+        }
+        match self.iova_mode {
+            Some(mode) => write!(f, "iova mode {:?}", mode),
+            None => write!(f, "unknown iova mode"),
+        }
+    }
+}
+
+/// Returns the hugepage memory needed by a mempool, in bytes.
+pub(crate) fn mempool_bytes(settings: &MempoolSettings) -> usize {
+    let obj_size = mem::size_of::<ffi::rte_mbuf>()
+        + settings.headroom()
+        + settings.dataroom()
+        + MEMPOOL_OBJ_OVERHEAD;
+    settings.capacity * obj_size
+}
+
+/// Returns the hugepage memory each NUMA node needs for the mempools, in
+/// bytes.
+pub(crate) fn hugepage_demand<F>(config: &RuntimeSettings, socket_of: F) -> HashMap<usize, usize>
+where
+    F: Fn(CoreId) -> SocketId,
+{
+    // `SocketId::ANY` allocates from any node, counted as node 0.
+    let node_of = |core_id| socket_of(core_id).raw().max(0) as usize;
+
+    let nodes = config
+        .all_cores()
+        .into_iter()
+        .map(node_of)
+        .collect::<HashSet<_>>();
+
+    let mut demand = HashMap::new();
+    for node in nodes {
+        *demand.entry(node).or_insert(0) += mempool_bytes(&config.mempool);
+    }
+
+    // a dedicated mempool goes on the socket of the port, which is unknown
+    // until the port is probed. the socket of its first core is a good guess.
+    for port in config.ports.iter() {
+        if let (Some(mempool), Some(&core_id)) = (&port.mempool, port.cores.first()) {
+            *demand.entry(node_of(core_id)).or_insert(0) += mempool_bytes(mempool);
+        }
+    }
+
+    demand
+}
+
+/// Reads the hugepages of every NUMA node under `root`.
+fn read_node_hugepages(root: &Path) -> Option<Vec<Hugepages>> {
+    let mut hugepages = vec![];
+
+    for entry in fs::read_dir(root).ok()? {
+        let entry = entry.ok()?;
+        let name = entry.file_name();
+        let node = name
+            .to_str()
+            .and_then(|name| name.trim_start_matches("node").parse::<usize>().ok());
+
+        if let Some(node) = node {
+            if let Some(pages) = read_hugepages(&entry.path().join("hugepages"), node) {
+                hugepages.extend(pages);
+            }
+        }
+    }
+
+    hugepages.sort_by_key(|pages| (pages.node, pages.size_kb));
+    Some(hugepages)
+}
+
+/// Reads the hugepages of every size in a `hugepages` directory.
+fn read_hugepages(dir: &Path, node: usize) -> Option<Vec<Hugepages>> {
+    let read =
+        |path: &Path| -> Option<usize> { fs::read_to_string(path).ok()?.trim().parse().ok() };
+    let mut hugepages = vec![];
+
+    for entry in fs::read_dir(dir).ok()? {
+        let entry = entry.ok()?;
+        let name = entry.file_name();
+        // named as `hugepages-2048kB`.
+        let size_kb = name.to_str().and_then(|name| {
+            name.trim_start_matches("hugepages-")
+                .trim_end_matches("kB")
+                .parse()
+                .ok()
+        });
+
+        if let Some(size_kb) = size_kb {
+            let total = read(&entry.path().join("nr_hugepages"));
+            let free = read(&entry.path().join("free_hugepages"));
+
+            // unreadable counts are unknown, not zero.
+            match (total, free) {
+                (Some(total), Some(free)) => {
+                    let pages = Hugepages {
+                        node,
+                        size_kb,
+                        total,
+                        free,
+                    };
+                    debug!(?pages);
+                    hugepages.push(pages);
+                }
+                _ => warn!("unable to read {}.", entry.path().display()),
+            }
+        }
+    }
+
+    hugepages.sort_by_key(|pages| pages.size_kb);
+    Some(hugepages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::PortSettings;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn fake_sysfs(name: &str, nodes: &[(usize, usize, usize)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("nb2-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for &(node, total, free) in nodes {
+            let dir = root.join(format!("node{}/hugepages/hugepages-2048kB", node));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("nr_hugepages"), format!("{}\n", total)).unwrap();
+            fs::write(dir.join("free_hugepages"), format!("{}\n", free)).unwrap();
+        }
+
+        root
+    }
+
+    #[test]
+    fn read_hugepages_by_node() {
+        let root = fake_sysfs("read", &[(0, 1024, 1000), (1, 512, 0)]);
+        let hugepages = read_node_hugepages(&root).unwrap();
+
+        assert_eq!(
+            vec![
+                Hugepages {
+                    node: 0,
+                    size_kb: 2048,
+                    total: 1024,
+                    free: 1000
+                },
+                Hugepages {
+                    node: 1,
+                    size_kb: 2048,
+                    total: 512,
+                    free: 0
+                }
+            ],
+            hugepages
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn check_hugepage_demand() {
+        let root = fake_sysfs("demand", &[(0, 1024, 1000), (1, 16, 4)]);
+        let info = MemoryInfo {
+            hugepages: read_node_hugepages(&root).unwrap(),
+            ..Default::default()
+        };
+        assert!(info.check_available().is_ok());
+
+        let mb = 1024 * 1024;
+
+        // 10 2MB pages on node 1, only 4 free.
+        let mut demand = HashMap::new();
+        demand.insert(1, 20 * mb);
+        let err = info.check_demand(&demand).unwrap_err();
+        assert_eq!(
+            "Need 10 2MB pages on node 1, only 4 are free.",
+            err.to_string()
+        );
+
+        let mut demand = HashMap::new();
+        demand.insert(0, 1000 * mb);
+        assert!(info.check_demand(&demand).is_ok());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn hugepage_demand_by_node() {
+        let mut config = RuntimeSettings::default();
+        config.mempool.capacity = 1000;
+        config.ports.push(PortSettings {
+            name: "eth0".to_owned(),
+            cores: vec![CoreId::new(1)],
+            mempool: Some(MempoolSettings {
+                capacity: 500,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let demand = hugepage_demand(&config, |_| SocketId::ANY);
+
+        // the shared mempool and the dedicated one, both on node 0.
+        let shared = mempool_bytes(&config.mempool);
+        assert_eq!(1, demand.len());
+        assert_eq!(shared + shared / 2, demand[&0]);
+    }
+
+    #[test]
+    fn no_free_hugepages() {
+        let info = MemoryInfo {
+            hugepages: vec![Hugepages {
+                node: 0,
+                size_kb: 2048,
+                total: 0,
+                free: 0,
+            }],
+            ..Default::default()
+        };
+        assert!(info.check_available().is_err());
+    }
+
+    #[test]
+    fn unreadable_hugepages() {
+        let root = fake_sysfs("unreadable", &[(0, 1024, 1000)]);
+        let dir = root.join("node0/hugepages/hugepages-2048kB");
+        fs::remove_file(dir.join("free_hugepages")).unwrap();
+
+        let info = MemoryInfo {
+            hugepages: read_node_hugepages(&root).unwrap(),
+            ..Default::default()
+        };
+        assert!(info.hugepages.is_empty());
+        assert!(info.check_available().is_ok());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
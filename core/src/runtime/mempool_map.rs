@@ -1,4 +1,5 @@
 use crate::dpdk::{Mempool, SocketId};
+use crate::settings::MempoolSettings;
 use crate::{debug, ffi, info, Result};
 use failure::Fail;
 use std::collections::HashMap;
@@ -15,11 +16,17 @@ pub struct MempoolMap {
 
 impl MempoolMap {
     /// Creates a `MempoolMap` for all the sockets listed.
-    pub fn new(capacity: usize, cache_size: usize, sockets: &[SocketId]) -> Result<MempoolMap> {
+    pub fn new(settings: &MempoolSettings, sockets: &[SocketId]) -> Result<MempoolMap> {
         let mut inner = HashMap::new();
 
         for &socket_id in sockets.iter() {
-            let pool = Mempool::new(capacity, cache_size, socket_id)?;
+            let pool = Mempool::with_headroom(
+                settings.capacity,
+                settings.cache_size,
+                settings.headroom(),
+                settings.dataroom(),
+                socket_id,
+            )?;
             info!("created {}.", pool.name());
             debug!(?pool);
 
@@ -57,6 +64,19 @@ impl<'a> MempoolMap2<'a> {
             .ok_or_else(|| MempoolNotFound(socket_id).into())
             .map(|pool| pool.raw_mut())
     }
+
+    /// Returns a mutable reference to the mempool corresponding to the
+    /// socket id.
+    ///
+    /// # Errors
+    ///
+    /// If the value is not found, `MempoolNotFound` is returned.
+    pub fn get(&mut self, socket_id: SocketId) -> Result<&mut Mempool> {
+        self.inner
+            .get_mut(&socket_id)
+            .map(|pool| &mut **pool)
+            .ok_or_else(|| MempoolNotFound(socket_id).into())
+    }
 }
 
 impl<'a> Default for MempoolMap2<'a> {
@@ -1,13 +1,24 @@
 mod core_map;
+mod execution;
+mod memory;
 mod mempool_map;
+mod validate;
 
 pub use self::core_map::*;
+pub use self::execution::*;
+pub use self::memory::*;
 pub use self::mempool_map::*;
-
-use super::Pipeline;
-use crate::dpdk::{self, CoreId, KniError, KniRx, Port, PortBuilder, PortError, PortQueue};
-use crate::settings::RuntimeSettings;
-use crate::{debug, ensure, info, Result};
+pub use self::validate::*;
+
+use crate::batch::{self, Batch, Distribution, Pipeline, Poll, Scheduler};
+use crate::dpdk::{
+    self, AclHandle, ControlProtocol, CoreId, KniError, KniRx, Port, PortBuilder, PortError,
+    PortInfo, PortQueue, Ring,
+};
+use crate::journal::{self, Event};
+use crate::net::Acl;
+use crate::settings::{self, RuntimeSettings, SettingsDiff, DEFAULT_TX_BACKOFF};
+use crate::{debug, ensure, info, warn, Result};
 use futures::{future, stream, Future, StreamExt};
 use libc;
 use std::collections::{HashMap, HashSet};
@@ -18,6 +29,9 @@ use tokio_net::driver;
 use tokio_net::signal::unix::{self, SignalKind};
 use tokio_timer::{timer, Interval};
 
+/// How often the links of the ports are checked for changes.
+const LINK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Supported Unix signals.
 #[derive(Copy, Clone, Debug)]
 pub enum UnixSignal {
@@ -30,8 +44,12 @@ pub enum UnixSignal {
 pub struct Runtime {
     ports: Vec<Port>,
     mempools: MempoolMap,
+    // must be dropped after the ports that receive into them.
+    port_mempools: Vec<MempoolMap>,
     core_map: CoreMap,
+    memory: MemoryInfo,
     on_signal: Arc<dyn Fn(UnixSignal) -> bool>,
+    on_reload: Option<(String, Arc<dyn Fn(&SettingsDiff) -> Result<()>>)>,
     config: RuntimeSettings,
 }
 
@@ -39,16 +57,34 @@ impl Runtime {
     /// Builds a runtime from config settings.
     #[allow(clippy::cognitive_complexity)]
     pub fn build(config: RuntimeSettings) -> Result<Self> {
+        // fails early rather than halfway through the EAL initialization.
+        let mut memory = MemoryInfo::gather();
+        if !config.no_huge() {
+            memory.check_available()?;
+        }
+
+        // a crash of the runtime leaves the journal on stderr.
+        journal::dump_on_panic();
+
         info!("initializing EAL...");
         dpdk::eal_init(config.to_eal_args())?;
 
+        memory.gather_eal();
+        info!("memory: {}.", memory);
+
+        for port in dpdk::port_inventory() {
+            info!("detected {}.", port);
+        }
+
         let cores = config.all_cores();
+        if !config.no_huge() {
+            memory.check_demand(&hugepage_demand(&config, |core_id| core_id.socket_id()))?;
+        }
 
         info!("initializing mempools...");
         let mut sockets = cores.iter().map(CoreId::socket_id).collect::<HashSet<_>>();
         let sockets = sockets.drain().collect::<Vec<_>>();
-        let mut mempools =
-            MempoolMap::new(config.mempool.capacity, config.mempool.cache_size, &sockets)?;
+        let mut mempools = MempoolMap::new(&config.mempool, &sockets)?;
 
         info!("intializing cores...");
         let core_map = CoreMapBuilder::new()
@@ -65,15 +101,39 @@ impl Runtime {
 
         info!("initializing ports...");
         let mut ports = vec![];
+        let mut port_mempools = vec![];
         for conf in config.ports.iter() {
-            let port = PortBuilder::new(conf.name.clone(), conf.device.clone())?
-                .cores(&conf.cores)?
-                .mempools(mempools.borrow_mut())
+            let mut builder = PortBuilder::new(conf.name.clone(), conf.device.clone())?;
+            builder.cores(&conf.cores)?;
+
+            // a port with its own mempool settings gets a dedicated mempool
+            // on the port's socket.
+            let mut dedicated = match &conf.mempool {
+                Some(settings) => Some(MempoolMap::new(settings, &[builder.socket_id()])?),
+                None => None,
+            };
+            let pools = match dedicated.as_mut() {
+                Some(dedicated) => dedicated.borrow_mut(),
+                None => mempools.borrow_mut(),
+            };
+
+            let port = builder
+                .mempools(pools)
                 .rx_tx_queue_capacity(conf.rxd, conf.txd)?
+                .fcs(
+                    conf.rx_fcs.unwrap_or_default(),
+                    conf.tx_append_fcs.unwrap_or_default(),
+                )?
+                .tx_checksum_fixup(conf.tx_checksum_fixup.unwrap_or_default())
+                .tx_backoff(
+                    conf.tx_retries.unwrap_or_default(),
+                    Duration::from_micros(conf.tx_backoff.unwrap_or(DEFAULT_TX_BACKOFF)),
+                )
                 .finish(conf.kni.unwrap_or_default())?;
 
             debug!(?port);
             ports.push(port);
+            port_mempools.extend(dedicated);
         }
 
         info!("runtime ready.");
@@ -81,12 +141,29 @@ impl Runtime {
         Ok(Runtime {
             ports,
             mempools,
+            port_mempools,
             core_map,
+            memory,
             on_signal: Arc::new(|_| true),
+            on_reload: None,
             config,
         })
     }
 
+    /// Returns the hugepages, memory channels and IOVA mode the runtime
+    /// was initialized with.
+    pub fn memory_info(&self) -> &MemoryInfo {
+        &self.memory
+    }
+
+    /// Returns the ports the EAL detected, including the ones the runtime
+    /// is not configured to use.
+    ///
+    /// The link state is read at the time of the call.
+    pub fn inventory(&self) -> Vec<PortInfo> {
+        dpdk::port_inventory()
+    }
+
     #[inline]
     fn get_port(&self, name: &str) -> Result<&Port> {
         self.ports
@@ -157,6 +234,71 @@ impl Runtime {
         self
     }
 
+    /// Reloads the config file at `filename` on `SIGHUP`, instead of
+    /// passing the signal to the signal handler.
+    ///
+    /// The reloaded settings are compared with the running ones. The
+    /// changes that can be applied live are applied, and the ones that
+    /// require a restart are logged as warnings and otherwise ignored, so
+    /// they are reported again on the next reload. Then `f` is called with
+    /// the differences, for the application to reload its own settings,
+    /// such as its rule sets. A config file that fails to load leaves the
+    /// running settings untouched.
+    ///
+    /// A control command can trigger the same reload by raising `SIGHUP`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// Runtime::build(config)?
+    ///     .set_on_reload(&filename, move |_| {
+    ///         rules.replace(load_rules(&filename)?);
+    ///         Ok(())
+    ///     })
+    ///     .execute()
+    /// ```
+    pub fn set_on_reload<F>(&mut self, filename: &str, f: F) -> &mut Self
+    where
+        F: Fn(&SettingsDiff) -> Result<()> + 'static,
+    {
+        self.on_reload = Some((filename.to_owned(), Arc::new(f)));
+        self
+    }
+
+    /// Attaches an ingress ACL to a port, applied to the packets received
+    /// on the port before the pipelines see them. See `Port::set_acl`.
+    ///
+    /// The pipelines of the port can replace the list later through
+    /// `PortQueue::set_acl`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let acl = Acl::new(
+    ///     &[AclRule::new(AclAction::Deny)
+    ///         .protocol(ProtocolNumbers::Tcp)
+    ///         .dst_ports(23, 23)],
+    ///     AclAction::Permit,
+    /// )?;
+    ///
+    /// Runtime::build(config)?
+    ///     .set_port_acl("eth1", acl)?
+    ///     .add_pipeline_to_port("eth1", install)?
+    ///     .execute()
+    /// ```
+    pub fn set_port_acl(&mut self, port: &str, acl: Acl) -> Result<&mut Self> {
+        let port = self.get_port(port)?;
+        port.set_acl(Some(acl));
+        info!("attached ingress acl to port {}.", port.name());
+        Ok(self)
+    }
+
+    /// Returns a handle to the ingress ACL of a port, for a control plane
+    /// to replace the list while the runtime executes.
+    pub fn acl_handle(&self, port: &str) -> Result<AclHandle> {
+        Ok(self.get_port(port)?.acl_handle())
+    }
+
     /// Installs a pipeline to a port. The pipeline will run on all the
     /// cores assigned to the port.
     ///
@@ -191,6 +333,197 @@ impl Runtime {
         }
 
         info!("installed pipeline for port {}.", port.name());
+        record_installed(format!("port {}", port.name()));
+
+        Ok(self)
+    }
+
+    /// Installs a pipeline to a group of ports. The pipeline will run on
+    /// all the cores the ports have in common.
+    ///
+    /// `ports` are the logical names that identify the ports. The
+    /// `installer` is a closure that takes in a hashmap of `PortQueue`s,
+    /// one for each port, and returns a `Pipeline` that will be spawned
+    /// onto the thread executor. Use it for pipelines that forward between
+    /// ports, such as a transparent bridge.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// Runtime::build(config)?
+    ///     .add_pipeline_to_ports(&["eth1", "eth2"], |qs| {
+    ///         let eth1 = batch::splice(qs["eth1"].clone(), qs["eth2"].clone());
+    ///         let eth2 = batch::splice(qs["eth2"].clone(), qs["eth1"].clone());
+    ///         Scheduler::new().add("eth1", eth1).add("eth2", eth2)
+    ///     })?
+    ///     .execute()
+    /// ```
+    pub fn add_pipeline_to_ports<T: Future<Output = ()> + 'static, F>(
+        &mut self,
+        ports: &[&str],
+        installer: F,
+    ) -> Result<&mut Self>
+    where
+        F: Fn(HashMap<String, PortQueue>) -> T + Send + Sync + 'static,
+    {
+        let ports = ports
+            .iter()
+            .map(|&name| self.get_port(name))
+            .collect::<Result<Vec<_>>>()?;
+
+        // the cores that have a queue for every port.
+        let mut cores = ports
+            .first()
+            .map(|p| p.queues().keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        cores.retain(|core_id| ports.iter().all(|p| p.queues().contains_key(core_id)));
+        cores.sort();
+
+        let names = ports
+            .iter()
+            .map(|p| p.name().to_owned())
+            .collect::<Vec<_>>();
+        ensure!(!cores.is_empty(), PortError::NoSharedCore(names));
+
+        let f = Arc::new(installer);
+
+        for core_id in cores {
+            let f = f.clone();
+            let port_qs = ports
+                .iter()
+                .map(|p| (p.name().to_owned(), p.queues()[&core_id].clone()))
+                .collect::<HashMap<_, _>>();
+            let thread = &self.get_core(core_id)?.thread;
+
+            // spawns the bootstrap. we want the bootstrapping to execute on the
+            // target core instead of the master core.
+            thread.spawn(future::lazy(move |_| {
+                let fut = f(port_qs);
+                current_thread::spawn(fut);
+            }))?;
+
+            debug!("installed pipeline on port_qs for {:?}.", core_id);
+        }
+
+        info!("installed pipeline for ports {:?}.", names);
+        record_installed(format!("ports {:?}", names));
+
+        Ok(self)
+    }
+
+    /// Installs a pipeline to a port, run in the execution mode of the
+    /// runtime settings.
+    ///
+    /// `port` is the logical name that identifies the port. The `installer`
+    /// is a closure that takes in a `WorkerQueue` and returns a `Pipeline`
+    /// that will be spawned onto the thread executor. The pipeline receives
+    /// from and transmits through the queue, so the same installer works
+    /// in either mode.
+    ///
+    /// In run-to-completion mode, the pipeline runs on all the cores
+    /// assigned to the port, same as `add_pipeline_to_port`. In pipeline
+    /// mode, the cores of the port only receive and transmit. The received
+    /// packets are spread by flow hash to the pipelines on the worker cores
+    /// through rings. Each worker transmits through a ring to one of the
+    /// port cores, so the packets of a flow stay in order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // [execution]
+    /// //     mode = "pipeline"
+    /// //     workers = [3, 4, 5]
+    /// Runtime::build(config)?
+    ///     .add_worker_pipeline("eth1", |q| {
+    ///         Poll::new(q.clone())
+    ///             .map(|packet| packet.parse::<Ethernet>()?.parse::<Ipv4>())
+    ///             .map(nat)
+    ///             .send(q)
+    ///     })?
+    ///     .execute()
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// In pipeline mode, if there is no worker, `ExecutionError::NoWorkers`
+    /// is returned. If a worker is also a core of the port,
+    /// `ExecutionError::WorkerIsPortCore` is returned.
+    pub fn add_worker_pipeline<T: Future<Output = ()> + 'static, F>(
+        &mut self,
+        port: &str,
+        installer: F,
+    ) -> Result<&mut Self>
+    where
+        F: Fn(WorkerQueue) -> T + Send + Sync + 'static,
+    {
+        let (workers, ring_size) = match &self.config.execution {
+            Some(execution) if execution.mode == ExecutionMode::Pipeline => {
+                (execution.workers(), execution.ring_size())
+            }
+            _ => return self.add_pipeline_to_port(port, move |q| installer(WorkerQueue::port(q))),
+        };
+        ensure!(!workers.is_empty(), ExecutionError::NoWorkers);
+
+        let port = self.get_port(port)?;
+        if let Some(&core_id) = workers.iter().find(|&c| port.queues().contains_key(c)) {
+            return Err(ExecutionError::WorkerIsPortCore(core_id).into());
+        }
+
+        let mut port_cores = port.queues().keys().cloned().collect::<Vec<_>>();
+        port_cores.sort();
+
+        // one ring into each worker, and one ring out to each port core.
+        let inbound = workers
+            .iter()
+            .map(|core_id| Ring::new(ring_size, core_id.socket_id()).map(Ring::split))
+            .collect::<Result<Vec<_>>>()?;
+        let outbound = port_cores
+            .iter()
+            .map(|core_id| Ring::new(ring_size, core_id.socket_id()).map(Ring::split))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (core_id, (_, ring_rx)) in port_cores.iter().zip(&outbound) {
+            let port_q = port.queues()[core_id].clone();
+            let ring_txs = inbound.iter().map(|(tx, _)| tx.clone()).collect::<Vec<_>>();
+            let ring_rx = ring_rx.clone();
+            let name = port.name().to_owned();
+            let thread = &self.get_core(*core_id)?.thread;
+
+            thread.spawn(future::lazy(move |_| {
+                let rx = Poll::new(port_q.clone()).distribute(Distribution::FlowHash, ring_txs);
+                let tx = batch::splice(ring_rx, port_q);
+                let fut = Scheduler::new()
+                    .add(&format!("{}-rx", name), rx)
+                    .add(&format!("{}-tx", name), tx);
+                current_thread::spawn(fut);
+            }))?;
+
+            debug!("installed io pipeline on port_q for {:?}.", core_id);
+        }
+
+        let f = Arc::new(installer);
+        let mac_addr = port.mac_addr();
+
+        for (index, (core_id, (_, ring_rx))) in workers.iter().zip(&inbound).enumerate() {
+            let f = f.clone();
+            let (ring_tx, _) = &outbound[index % outbound.len()];
+            let q = WorkerQueue::rings(ring_rx.clone(), ring_tx.clone(), mac_addr);
+            let thread = &self.get_core(*core_id)?.thread;
+
+            thread.spawn(future::lazy(move |_| {
+                let fut = f(q);
+                current_thread::spawn(fut);
+            }))?;
+
+            debug!("installed worker pipeline for {:?}.", core_id);
+        }
+
+        info!(
+            "installed pipeline for port {} on workers {:?}.",
+            port.name(),
+            workers
+        );
+        record_installed(format!("port {} on workers {:?}", port.name(), workers));
 
         Ok(self)
     }
@@ -246,10 +579,43 @@ impl Runtime {
         }))?;
 
         info!("installed kni rx pipeline for port {}.", port.name());
+        record_installed(format!("kni rx of port {}", port.name()));
 
         Ok(self)
     }
 
+    /// Steers the control protocols received on a port to the queue of a
+    /// dedicated slow path core, so the fast path cores of the port never
+    /// see the control traffic.
+    ///
+    /// `core` is the logical id of the slow path core, which must be one of
+    /// the cores assigned to the port. Install a pipeline on that core with
+    /// `add_pipeline_to_core` to handle the control traffic.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// Runtime::build(config)?
+    ///     .steer_to_core("eth0", &[ControlProtocol::Arp, ControlProtocol::Bgp], 3)?
+    ///     .add_pipeline_to_core(3, slow_path)?
+    ///     .execute()
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the device does not support the `rte_flow` rules needed,
+    /// `FlowError::Rejected` is returned.
+    pub fn steer_to_core(
+        &mut self,
+        port: &str,
+        protocols: &[ControlProtocol],
+        core: usize,
+    ) -> Result<&mut Self> {
+        let core_id = CoreId::new(core);
+        self.get_port_mut(port)?.steer(protocols, core_id)?;
+        Ok(self)
+    }
+
     /// Installs a pipeline to a core. All the ports the core is assigned
     /// to will be available to the pipeline.
     ///
@@ -276,6 +642,7 @@ impl Runtime {
         }))?;
 
         info!("installed pipeline for core {:?}.", core_id);
+        record_installed(format!("{:?}", core_id));
 
         Ok(self)
     }
@@ -319,6 +686,7 @@ impl Runtime {
         }))?;
 
         info!("installed periodic pipeline for core {:?}.", core_id);
+        record_installed(format!("{:?}, periodic", core_id));
 
         Ok(self)
     }
@@ -351,6 +719,7 @@ impl Runtime {
         }))?;
 
         info!("installed periodic task for core {:?}.", core_id);
+        record_installed(format!("{:?}, periodic task", core_id));
 
         Ok(self)
     }
@@ -371,7 +740,8 @@ impl Runtime {
 
         debug!("waiting for {} seconds...", timeout);
         let _timer = timer::set_default(&timer);
-        thread.block_on(delay);
+        let links = monitor_links(&self.ports);
+        thread.block_on(future::select(Box::pin(delay), Box::pin(links)));
         info!("timed out after {} seconds.", timeout);
 
         Ok(())
@@ -387,9 +757,18 @@ impl Runtime {
         let stream = stream::select(stream::select(sighup, sigint), sigterm);
 
         // passes each signal through the `on_signal` closure, and discard
-        // any that shouldn't stop the execution.
+        // any that shouldn't stop the execution. a reload never stops it.
         let f = self.on_signal.clone();
-        let mut stream = stream.filter(|&signal| future::ready(f(signal)));
+        let on_reload = self.on_reload.clone();
+        let config = &mut self.config;
+        let ports = &self.ports;
+        let mut stream = stream.filter(move |&signal| match (signal, &on_reload) {
+            (UnixSignal::SIGHUP, Some((filename, on_reload))) => {
+                reload(filename, config, ports, &**on_reload);
+                future::ready(false)
+            }
+            _ => future::ready(f(signal)),
+        });
 
         let MasterExecutor {
             ref reactor,
@@ -404,7 +783,8 @@ impl Runtime {
         debug!("waiting for a Unix signal...");
         let _guard = driver::set_default(&reactor);
         let _timer = timer::set_default(&timer);
-        let _ = thread.block_on(stream.next());
+        let links = monitor_links(ports);
+        let _ = thread.block_on(future::select(stream.next(), Box::pin(links)));
         info!("signaled to stop.");
 
         Ok(())
@@ -495,6 +875,68 @@ impl Runtime {
     }
 }
 
+/// Returns a future checking the links of the ports every
+/// `LINK_CHECK_INTERVAL` on the master core, so their changes are recorded
+/// in the journal. It never completes.
+fn monitor_links(ports: &[Port]) -> impl Future<Output = ()> + '_ {
+    Interval::new_interval(LINK_CHECK_INTERVAL).for_each(move |_| {
+        ports.iter().for_each(Port::check_link);
+        future::ready(())
+    })
+}
+
+/// Reloads the config file, and applies the changes that can be applied
+/// live to the running settings.
+fn reload(
+    filename: &str,
+    config: &mut RuntimeSettings,
+    ports: &[Port],
+    on_reload: &dyn Fn(&SettingsDiff) -> Result<()>,
+) {
+    info!("reloading {}...", filename);
+
+    let new = match settings::load_config_file(filename) {
+        Ok(new) => new,
+        Err(err) => {
+            warn!(message = "failed to reload config.", ?err);
+            return;
+        }
+    };
+
+    let diff = config.diff(&new);
+    for change in diff.changes() {
+        if change.live {
+            info!("applied {}.", change);
+        } else {
+            warn!("not applied {}.", change);
+        }
+    }
+
+    // only the transmit retries of the ports are applied live.
+    for port in ports.iter() {
+        if let (Some(old), Some(new)) = (
+            config.ports.iter_mut().find(|p| p.name == port.name()),
+            new.ports.iter().find(|p| p.name == port.name()),
+        ) {
+            old.tx_retries = new.tx_retries;
+            old.tx_backoff = new.tx_backoff;
+            port.set_tx_backoff(
+                new.tx_retries.unwrap_or_default(),
+                Duration::from_micros(new.tx_backoff.unwrap_or(DEFAULT_TX_BACKOFF)),
+            );
+        }
+    }
+
+    if let Err(err) = on_reload(&diff) {
+        warn!(message = "application failed to reload.", ?err);
+    }
+}
+
+/// Records the installation of a pipeline in the journal.
+fn record_installed(target: String) {
+    journal::record_event(Event::PipelineInstalled { target });
+}
+
 impl Drop for Runtime {
     fn drop(&mut self) {
         debug!("freeing EAL.");
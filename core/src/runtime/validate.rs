@@ -0,0 +1,314 @@
+use super::Runtime;
+use crate::dpdk::{CoreId, SocketId, RX_BURST_MAX};
+use crate::settings::RuntimeSettings;
+use crate::{debug, info, Result};
+use std::collections::HashMap;
+use std::fmt;
+
+// the largest per core cache a mempool supports, `RTE_MEMPOOL_CACHE_MAX_SIZE`.
+const MEMPOOL_CACHE_MAX_SIZE: usize = 512;
+
+/// The outcome of a validation check.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CheckStatus {
+    /// The check passed.
+    Pass,
+    /// The check passed, but the setup may not work as intended.
+    Warn(String),
+    /// The check failed.
+    Fail(String),
+}
+
+/// A single check of the validation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+}
+
+/// The result of `Runtime::validate`, one check per stage.
+///
+/// The stages that depend on a failed stage are not checked.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    checks: Vec<Check>,
+}
+
+impl ValidationReport {
+    fn check(&mut self, name: &str, status: CheckStatus) {
+        debug!(check = name, ?status);
+        self.checks.push(Check {
+            name: name.to_owned(),
+            status,
+        });
+    }
+
+    fn check_result<T>(&mut self, name: &str, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.check(name, CheckStatus::Pass);
+                Some(value)
+            }
+            Err(err) => {
+                self.check(name, CheckStatus::Fail(err.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Returns all the checks, in the order they ran.
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Returns the checks that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|check| match check.status {
+            CheckStatus::Fail(_) => true,
+            _ => false,
+        })
+    }
+
+    /// Returns whether all the checks passed, warnings included.
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+/// Formats the report one check per line, for example
+/// `[fail] mempool socket0: needs 4352 mbufs, has capacity for 2047.`
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in self.checks.iter() {
+            match &check.status {
+                CheckStatus::Pass => writeln!(f, "[pass] {}", check.name)?,
+                CheckStatus::Warn(msg) => writeln!(f, "[warn] {}: {}", check.name, msg)?,
+                CheckStatus::Fail(msg) => writeln!(f, "[fail] {}: {}", check.name, msg)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks the cache size against the limits of the mempool library.
+fn check_mempool_cache(capacity: usize, cache_size: usize) -> CheckStatus {
+    if cache_size > MEMPOOL_CACHE_MAX_SIZE {
+        CheckStatus::Fail(format!(
+            "cache size {} exceeds the maximum of {}.",
+            cache_size, MEMPOOL_CACHE_MAX_SIZE
+        ))
+    } else if cache_size * 3 / 2 > capacity {
+        CheckStatus::Fail(format!(
+            "cache size {} is too large for capacity {}, must be at most capacity / 1.5.",
+            cache_size, capacity
+        ))
+    } else {
+        CheckStatus::Pass
+    }
+}
+
+/// Returns the number of mbufs each socket needs to fill all the port
+/// queues and core caches, and to have a burst in flight on every core.
+fn mempool_demand<F>(config: &RuntimeSettings, socket_of: F) -> HashMap<SocketId, usize>
+where
+    F: Fn(CoreId) -> SocketId,
+{
+    let mut demand = HashMap::new();
+
+    for core_id in config.all_cores() {
+        *demand.entry(socket_of(core_id)).or_insert(0) += config.mempool.cache_size + RX_BURST_MAX;
+    }
+
+    // the ports with a dedicated mempool are checked separately.
+    for port in config.ports.iter().filter(|port| port.mempool.is_none()) {
+        for &core_id in port.cores.iter() {
+            *demand.entry(socket_of(core_id)).or_insert(0) += port.rxd + port.txd;
+        }
+    }
+
+    demand
+}
+
+fn check_mempool_demand(capacity: usize, demand: usize) -> CheckStatus {
+    if demand > capacity {
+        CheckStatus::Fail(format!(
+            "needs {} mbufs, has capacity for {}.",
+            demand, capacity
+        ))
+    } else if demand > capacity / 2 {
+        CheckStatus::Warn(format!(
+            "needs {} of the {} mbufs just to fill the queues, leaving little for the pipelines.",
+            demand, capacity
+        ))
+    } else {
+        CheckStatus::Pass
+    }
+}
+
+impl Runtime {
+    /// Runs the initialization of the runtime without starting any traffic
+    /// and returns a report of what would fail.
+    ///
+    /// The EAL is initialized, the ports are probed and configured, the
+    /// mempools are created and checked against the demand of the port
+    /// queues, and the pipelines are installed by `installer`. The ports
+    /// are never started and the cores are shut down before returning.
+    /// Use it for preflight checks of a deployment or to test configs in
+    /// CI.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let report = Runtime::validate(config, |runtime| {
+    ///     runtime.add_pipeline_to_port("eth0", install)?;
+    ///     Ok(())
+    /// });
+    /// print!("{}", report);
+    /// std::process::exit(if report.is_ok() { 0 } else { 1 });
+    /// ```
+    ///
+    /// # Remarks
+    ///
+    /// The EAL can only be initialized once per process, so the runtime
+    /// cannot be built again after the validation. Run the validation in
+    /// its own process.
+    pub fn validate<F>(config: RuntimeSettings, installer: F) -> ValidationReport
+    where
+        F: FnOnce(&mut Runtime) -> Result<()>,
+    {
+        let mut report = ValidationReport::default();
+
+        report.check(
+            "settings: ports",
+            if config.ports.is_empty() {
+                CheckStatus::Fail("at least one port is required.".to_owned())
+            } else {
+                CheckStatus::Pass
+            },
+        );
+        report.check(
+            "settings: mempool cache",
+            check_mempool_cache(config.mempool.capacity, config.mempool.cache_size),
+        );
+
+        info!("validating runtime...");
+        let mut runtime = match report.check_result("runtime build", Runtime::build(config)) {
+            Some(runtime) => runtime,
+            None => return report,
+        };
+
+        for port in runtime.ports.iter() {
+            let mut cores = port.queues().keys().cloned().collect::<Vec<_>>();
+            cores.sort();
+            report.check(
+                &format!("port {} on {:?}", port.name(), cores),
+                CheckStatus::Pass,
+            );
+        }
+
+        let capacity = runtime.config.mempool.capacity;
+        let mut demand = mempool_demand(&runtime.config, |core_id| core_id.socket_id())
+            .into_iter()
+            .collect::<Vec<_>>();
+        demand.sort_by_key(|&(socket_id, _)| socket_id.raw());
+        for (socket_id, demand) in demand {
+            report.check(
+                &format!("mempool {:?}", socket_id),
+                check_mempool_demand(capacity, demand),
+            );
+        }
+
+        for port in runtime.config.ports.iter() {
+            if let Some(mempool) = &port.mempool {
+                let demand = port.cores.len() * (port.rxd + mempool.cache_size + RX_BURST_MAX);
+                report.check(
+                    &format!("mempool {}", port.name),
+                    check_mempool_demand(mempool.capacity, demand),
+                );
+            }
+        }
+
+        let installed = installer(&mut runtime);
+        report.check_result("pipelines", installed);
+
+        runtime.stop_parked_cores();
+        info!("runtime validated.");
+
+        report
+    }
+
+    /// Shuts down the cores that were never unparked.
+    fn stop_parked_cores(&mut self) {
+        for (core_id, core) in &mut self.core_map.cores {
+            if let Some(trigger) = core.shutdown.take() {
+                // triggers the shutdown first, so the core stops right away
+                // once unparked.
+                trigger.shutdown();
+                if let Some(unpark) = &core.unpark {
+                    unpark.unpark();
+                }
+                let _ = core.join.take().unwrap().join();
+                debug!("stopped {:?}.", core_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::PortSettings;
+
+    #[test]
+    fn mempool_cache_limits() {
+        assert_eq!(CheckStatus::Pass, check_mempool_cache(65535, 256));
+        assert!(match check_mempool_cache(65535, 1024) {
+            CheckStatus::Fail(_) => true,
+            _ => false,
+        });
+        assert!(match check_mempool_cache(255, 256) {
+            CheckStatus::Fail(_) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn mempool_demand_by_socket() {
+        let mut config = RuntimeSettings::default();
+        config.mempool.cache_size = 32;
+        config.ports.push(PortSettings {
+            name: "eth0".to_owned(),
+            cores: vec![CoreId::new(1), CoreId::new(2)],
+            rxd: 512,
+            txd: 256,
+            ..Default::default()
+        });
+
+        let demand = mempool_demand(&config, |_| SocketId::ANY);
+
+        // three cores with caches and bursts, two queue pairs.
+        assert_eq!(3 * (32 + 32) + 2 * (512 + 256), demand[&SocketId::ANY]);
+
+        assert_eq!(CheckStatus::Pass, check_mempool_demand(65535, 1728));
+        assert!(match check_mempool_demand(1023, 1728) {
+            CheckStatus::Fail(_) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn report_is_ok() {
+        let mut report = ValidationReport::default();
+        report.check("a", CheckStatus::Pass);
+        report.check("b", CheckStatus::Warn("meh.".to_owned()));
+        assert!(report.is_ok());
+
+        report.check("c", CheckStatus::Fail("nope.".to_owned()));
+        assert!(!report.is_ok());
+        assert_eq!(1, report.failures().count());
+        assert_eq!(
+            "[pass] a\n[warn] b: meh.\n[fail] c: nope.\n",
+            report.to_string()
+        );
+    }
+}
@@ -1,15 +1,19 @@
-use crate::dpdk::CoreId;
+use crate::dpdk::{CoreId, PortId, RxFcs, RxQueueIndex, TxQueueIndex};
 use crate::net::{Ipv4Cidr, Ipv6Cidr, MacAddr};
+use crate::runtime::{ExecutionMode, DEFAULT_RING_SIZE};
 use clap::clap_app;
 use config::{Config, ConfigError, File, FileFormat};
 use regex::Regex;
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use std::str::FromStr;
 
 pub const DEFAULT_MEMPOOL_CAPACITY: usize = 65535;
+pub const DEFAULT_MEMPOOL_DATAROOM: usize = 2048;
+pub const DEFAULT_MEMPOOL_HEADROOM: usize = 128;
 pub const DEFAULT_PORT_RXD: usize = 128;
 pub const DEFAULT_PORT_TXD: usize = 128;
+pub const DEFAULT_TX_BACKOFF: u64 = 10;
 
 // make `CoreId` serde deserializable.
 impl<'de> Deserialize<'de> for CoreId {
@@ -22,6 +26,79 @@ impl<'de> Deserialize<'de> for CoreId {
     }
 }
 
+// make `CoreId` serde serializable.
+impl Serialize for CoreId {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        (self.raw() as usize).serialize(serializer)
+    }
+}
+
+// make `PortId` serde deserializable.
+impl<'de> Deserialize<'de> for PortId {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let i = <u16>::deserialize(deserializer)?;
+        Ok(PortId::new(i))
+    }
+}
+
+// make `PortId` serde serializable.
+impl Serialize for PortId {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.raw().serialize(serializer)
+    }
+}
+
+// make `RxQueueIndex` serde deserializable.
+impl<'de> Deserialize<'de> for RxQueueIndex {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let i = <u16>::deserialize(deserializer)?;
+        Ok(RxQueueIndex::new(i))
+    }
+}
+
+// make `RxQueueIndex` serde serializable.
+impl Serialize for RxQueueIndex {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.raw().serialize(serializer)
+    }
+}
+
+// make `TxQueueIndex` serde deserializable.
+impl<'de> Deserialize<'de> for TxQueueIndex {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let i = <u16>::deserialize(deserializer)?;
+        Ok(TxQueueIndex::new(i))
+    }
+}
+
+// make `TxQueueIndex` serde serializable.
+impl Serialize for TxQueueIndex {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.raw().serialize(serializer)
+    }
+}
+
 // make `MacAddr` serde deserializable.
 impl<'de> Deserialize<'de> for MacAddr {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
@@ -33,6 +110,16 @@ impl<'de> Deserialize<'de> for MacAddr {
     }
 }
 
+// make `MacAddr` serde serializable.
+impl Serialize for MacAddr {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 // make `Ipv4Cidr` serde deserializable.
 impl<'de> Deserialize<'de> for Ipv4Cidr {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -44,6 +131,16 @@ impl<'de> Deserialize<'de> for Ipv4Cidr {
     }
 }
 
+// make `Ipv4Cidr` serde serializable.
+impl Serialize for Ipv4Cidr {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 // make `Ipv6Cidr` serde deserializable.
 impl<'de> Deserialize<'de> for Ipv6Cidr {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -55,6 +152,16 @@ impl<'de> Deserialize<'de> for Ipv6Cidr {
     }
 }
 
+// make `Ipv6Cidr` serde serializable.
+impl Serialize for Ipv6Cidr {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
 /// Runtime settings.
 #[derive(Deserialize)]
 pub struct RuntimeSettings {
@@ -89,6 +196,10 @@ pub struct RuntimeSettings {
     /// If set, the application will stop after the duration expires. Useful
     /// for setting a timeout for integration tests.
     pub duration: Option<u64>,
+
+    /// How the cores process the packets of the pipelines installed with
+    /// `Runtime::add_worker_pipeline`. The default is run-to-completion.
+    pub execution: Option<ExecutionSettings>,
 }
 
 impl RuntimeSettings {
@@ -102,6 +213,10 @@ impl RuntimeSettings {
             cores.extend(port.cores.iter());
         });
 
+        if let Some(execution) = &self.execution {
+            cores.extend(execution.workers());
+        }
+
         cores.sort();
         cores.dedup();
         cores
@@ -160,6 +275,15 @@ impl RuntimeSettings {
             .filter(|p| p.kni.unwrap_or_default())
             .count()
     }
+
+    /// Returns whether the EAL runs without hugepages, with `--no-huge` in
+    /// the additional DPDK args.
+    pub(crate) fn no_huge(&self) -> bool {
+        self.dpdk_args
+            .as_ref()
+            .map(|args| args.split_ascii_whitespace().any(|arg| arg == "--no-huge"))
+            .unwrap_or(false)
+    }
 }
 
 impl Default for RuntimeSettings {
@@ -172,6 +296,7 @@ impl Default for RuntimeSettings {
             ports: vec![],
             dpdk_args: None,
             duration: None,
+            execution: None,
         }
     }
 }
@@ -190,6 +315,9 @@ impl fmt::Debug for RuntimeSettings {
         if let Some(duration) = &self.duration {
             d.field("duration", duration);
         }
+        if let Some(execution) = &self.execution {
+            d.field("execution", execution);
+        }
         d.finish()
     }
 }
@@ -206,7 +334,44 @@ pub struct MempoolSettings {
     /// the library will try to limit the accesses to the common lockless
     /// pool. The cache can be disabled if the argument is set to 0. The
     /// default is `0`.
+    ///
+    /// The size can be at most `512`, and at most the capacity divided by
+    /// `1.5`. `stats::mempool_cache_stats` reports how often the cache
+    /// serves the allocations, to help pick a size.
     pub cache_size: usize,
+
+    /// The size of the data space in each Mbuf, not including the headroom.
+    /// Set it to fit the largest frame, for example `9216` for jumbo frames.
+    /// The default is `2048`.
+    pub dataroom: Option<usize>,
+
+    /// The size of the headroom in front of the data of the Mbufs the
+    /// application allocates, for pushing outer headers with
+    /// `Mbuf::prepend`. Set it to fit the largest encapsulation, for
+    /// example `256` for a stack of tunnels. The default and the minimum
+    /// is `128`.
+    ///
+    /// The headroom is only reserved by `Mbuf::new`, `Mbuf::from_bytes`
+    /// and `Mbuf::alloc_bulk`. The devices don't honor it, received
+    /// packets always have the default headroom of `128`. Pushing a larger
+    /// outer header onto a received packet moves the packet, with
+    /// `Mbuf::extend`.
+    ///
+    /// Only the headroom of the `[mempool]` settings applies to the
+    /// allocations, port mempools only hold received packets.
+    pub headroom: Option<usize>,
+}
+
+impl MempoolSettings {
+    /// Returns the data room size, or the default if not set.
+    pub(crate) fn dataroom(&self) -> usize {
+        self.dataroom.unwrap_or(DEFAULT_MEMPOOL_DATAROOM)
+    }
+
+    /// Returns the headroom size, or the default if not set.
+    pub(crate) fn headroom(&self) -> usize {
+        self.headroom.unwrap_or(DEFAULT_MEMPOOL_HEADROOM)
+    }
 }
 
 impl Default for MempoolSettings {
@@ -214,6 +379,8 @@ impl Default for MempoolSettings {
         MempoolSettings {
             capacity: DEFAULT_MEMPOOL_CAPACITY,
             cache_size: 0,
+            dataroom: None,
+            headroom: None,
         }
     }
 }
@@ -223,10 +390,54 @@ impl fmt::Debug for MempoolSettings {
         f.debug_struct("mempool")
             .field("capacity", &self.capacity)
             .field("cache_size", &self.cache_size)
+            .field("dataroom", &self.dataroom())
+            .field("headroom", &self.headroom())
             .finish()
     }
 }
 
+/// Execution settings.
+#[derive(Deserialize)]
+pub struct ExecutionSettings {
+    /// Either `run_to_completion` or `pipeline`.
+    pub mode: ExecutionMode,
+
+    /// The worker cores, in pipeline mode. Workers cannot be assigned to
+    /// a port. Ignored in run-to-completion mode.
+    pub workers: Option<Vec<CoreId>>,
+
+    /// The capacity of the rings between the port cores and the workers,
+    /// in pipeline mode. The default is `1024`.
+    pub ring_size: Option<usize>,
+}
+
+impl ExecutionSettings {
+    /// Returns the worker cores, or none in run-to-completion mode.
+    pub(crate) fn workers(&self) -> Vec<CoreId> {
+        match self.mode {
+            ExecutionMode::Pipeline => self.workers.clone().unwrap_or_default(),
+            ExecutionMode::RunToCompletion => vec![],
+        }
+    }
+
+    /// Returns the ring capacity, or the default if not set.
+    pub(crate) fn ring_size(&self) -> usize {
+        self.ring_size.unwrap_or(DEFAULT_RING_SIZE)
+    }
+}
+
+impl fmt::Debug for ExecutionSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("execution");
+        d.field("mode", &self.mode);
+        if self.mode == ExecutionMode::Pipeline {
+            d.field("workers", &self.workers())
+                .field("ring_size", &self.ring_size());
+        }
+        d.finish()
+    }
+}
+
 /// Port settings.
 #[derive(Deserialize)]
 pub struct PortSettings {
@@ -259,6 +470,35 @@ pub struct PortSettings {
     /// port can exchange packets with the kernel networking stack. The
     /// default is `false`.
     pub kni: Option<bool>,
+
+    /// How the ethernet FCS of received frames is handled. Can be `strip`,
+    /// `keep` or `soft_strip`. The default is `strip`.
+    pub rx_fcs: Option<RxFcs>,
+
+    /// Whether to compute and append the ethernet FCS in software before
+    /// transmitting, for virtual devices that don't. The default is `false`.
+    pub tx_append_fcs: Option<bool>,
+
+    /// Whether to recompute the IPv4, TCP and UDP checksums in software
+    /// before transmitting, emulating checksum offload on virtual devices.
+    /// The default is `false`.
+    pub tx_checksum_fixup: Option<bool>,
+
+    /// The number of times a burst is offered again to a transmit queue
+    /// that took none of its packets, before the packets are dropped. The
+    /// default is `0`, dropping them right away.
+    pub tx_retries: Option<u32>,
+
+    /// The wait in microseconds before the first retry of a full transmit
+    /// queue, doubled on every retry after, up to `100`. The default is
+    /// `10`.
+    pub tx_backoff: Option<u64>,
+
+    /// A mempool dedicated to the port for receiving packets, instead of
+    /// the shared mempool of the port's socket. Use it when the port needs
+    /// a different buffer size than the other ports, such as jumbo frames.
+    /// The default is to use the shared mempool.
+    pub mempool: Option<MempoolSettings>,
 }
 
 impl Default for PortSettings {
@@ -271,6 +511,12 @@ impl Default for PortSettings {
             rxd: DEFAULT_PORT_RXD,
             txd: DEFAULT_PORT_TXD,
             kni: None,
+            rx_fcs: None,
+            tx_append_fcs: None,
+            tx_checksum_fixup: None,
+            tx_retries: None,
+            tx_backoff: None,
+            mempool: None,
         }
     }
 }
@@ -287,7 +533,18 @@ impl fmt::Debug for PortSettings {
             .field("rxd", &self.rxd)
             .field("txd", &self.txd)
             .field("kni", &self.kni.unwrap_or_default())
-            .finish()
+            .field("rx_fcs", &self.rx_fcs.unwrap_or_default())
+            .field("tx_append_fcs", &self.tx_append_fcs.unwrap_or_default())
+            .field(
+                "tx_checksum_fixup",
+                &self.tx_checksum_fixup.unwrap_or_default(),
+            )
+            .field("tx_retries", &self.tx_retries.unwrap_or_default())
+            .field("tx_backoff", &self.tx_backoff.unwrap_or(DEFAULT_TX_BACKOFF));
+        if let Some(mempool) = &self.mempool {
+            d.field("mempool", mempool);
+        }
+        d.finish()
     }
 }
 
@@ -317,16 +574,294 @@ pub fn load_config() -> Result<RuntimeSettings, ConfigError> {
     .get_matches();
 
     let filename = matches.value_of("file").unwrap();
+    load_config_file(filename)
+}
 
+/// Loads the app config from the TOML file at `filename`.
+///
+/// Use when the application parses its own command-line arguments.
+pub fn load_config_file(filename: &str) -> Result<RuntimeSettings, ConfigError> {
     let mut config = Config::new();
     config.merge(File::from_str(DEFAULT_TOML, FileFormat::Toml))?;
     config.merge(File::with_name(filename))?;
     config.try_into()
 }
 
+/// A setting that differs between two versions of the settings.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SettingChange {
+    /// The path of the setting, for example `ports.eth0.kni`.
+    pub key: String,
+    pub old: String,
+    pub new: String,
+    /// Whether the change is applied to the running runtime. Otherwise the
+    /// application must be restarted for it to take effect.
+    pub live: bool,
+}
+
+impl fmt::Display for SettingChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.key, self.old, self.new)?;
+        if !self.live {
+            write!(f, " (requires restart)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The differences between two versions of the runtime settings.
+#[derive(Clone, Debug, Default)]
+pub struct SettingsDiff {
+    changes: Vec<SettingChange>,
+}
+
+impl SettingsDiff {
+    /// Records the setting if its values differ, as they are displayed in
+    /// the settings.
+    fn compare<T: fmt::Debug>(&mut self, key: String, old: &T, new: &T, live: bool) {
+        let old = format!("{:?}", old);
+        let new = format!("{:?}", new);
+        if old != new {
+            self.changes.push(SettingChange {
+                key,
+                old,
+                new,
+                live,
+            });
+        }
+    }
+
+    /// Records a port added or removed.
+    fn presence(&mut self, port: &str, old: &str, new: &str) {
+        self.changes.push(SettingChange {
+            key: format!("ports.{}", port),
+            old: old.to_owned(),
+            new: new.to_owned(),
+            live: false,
+        });
+    }
+
+    /// Returns whether the settings are the same.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the settings that changed.
+    pub fn changes(&self) -> &[SettingChange] {
+        &self.changes
+    }
+
+    /// Returns whether any of the changes requires a restart.
+    pub fn requires_restart(&self) -> bool {
+        self.changes.iter().any(|change| !change.live)
+    }
+}
+
+impl RuntimeSettings {
+    /// Returns the settings that differ in `new`.
+    ///
+    /// The ports are matched by name. The retries of a full transmit queue
+    /// are applied live, and the rest requires a restart, as the EAL, the
+    /// mempools and the queues are set up once.
+    pub fn diff(&self, new: &RuntimeSettings) -> SettingsDiff {
+        let mut diff = SettingsDiff::default();
+        let key = |name: &str| name.to_owned();
+
+        diff.compare(key("app_name"), &self.app_name, &new.app_name, false);
+        diff.compare(
+            key("master_core"),
+            &self.master_core,
+            &new.master_core,
+            false,
+        );
+        diff.compare(key("cores"), &self.cores, &new.cores, false);
+        diff.compare(key("mempool"), &self.mempool, &new.mempool, false);
+        diff.compare(key("dpdk_args"), &self.dpdk_args, &new.dpdk_args, false);
+        diff.compare(key("duration"), &self.duration, &new.duration, false);
+        diff.compare(key("execution"), &self.execution, &new.execution, false);
+
+        for old in self.ports.iter() {
+            let key = |field: &str| format!("ports.{}.{}", old.name, field);
+
+            match new.ports.iter().find(|port| port.name == old.name) {
+                Some(new) => {
+                    diff.compare(key("device"), &old.device, &new.device, false);
+                    diff.compare(key("args"), &old.args, &new.args, false);
+                    diff.compare(key("cores"), &old.cores, &new.cores, false);
+                    diff.compare(key("rxd"), &old.rxd, &new.rxd, false);
+                    diff.compare(key("txd"), &old.txd, &new.txd, false);
+                    diff.compare(
+                        key("kni"),
+                        &old.kni.unwrap_or_default(),
+                        &new.kni.unwrap_or_default(),
+                        false,
+                    );
+                    diff.compare(
+                        key("rx_fcs"),
+                        &old.rx_fcs.unwrap_or_default(),
+                        &new.rx_fcs.unwrap_or_default(),
+                        false,
+                    );
+                    diff.compare(
+                        key("tx_append_fcs"),
+                        &old.tx_append_fcs.unwrap_or_default(),
+                        &new.tx_append_fcs.unwrap_or_default(),
+                        false,
+                    );
+                    diff.compare(
+                        key("tx_checksum_fixup"),
+                        &old.tx_checksum_fixup.unwrap_or_default(),
+                        &new.tx_checksum_fixup.unwrap_or_default(),
+                        false,
+                    );
+                    diff.compare(
+                        key("tx_retries"),
+                        &old.tx_retries.unwrap_or_default(),
+                        &new.tx_retries.unwrap_or_default(),
+                        true,
+                    );
+                    diff.compare(
+                        key("tx_backoff"),
+                        &old.tx_backoff.unwrap_or(DEFAULT_TX_BACKOFF),
+                        &new.tx_backoff.unwrap_or(DEFAULT_TX_BACKOFF),
+                        true,
+                    );
+                    diff.compare(key("mempool"), &old.mempool, &new.mempool, false);
+                }
+                None => diff.presence(&old.name, "present", "removed"),
+            }
+        }
+
+        for port in new.ports.iter() {
+            if self.ports.iter().all(|old| old.name != port.name) {
+                diff.presence(&port.name, "absent", "added");
+            }
+        }
+
+        diff
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::net::Cidr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct UserSettings {
+        core: CoreId,
+        port: PortId,
+        rxq: RxQueueIndex,
+        txq: TxQueueIndex,
+        mac: MacAddr,
+        v4: Ipv4Cidr,
+        v6: Ipv6Cidr,
+    }
+
+    #[test]
+    fn diff_reloaded_settings() {
+        let load = |toml: &str| -> RuntimeSettings {
+            let mut config = Config::new();
+            config
+                .merge(File::from_str(DEFAULT_TOML, FileFormat::Toml))
+                .unwrap();
+            config
+                .merge(File::from_str(toml, FileFormat::Toml))
+                .unwrap();
+            config.try_into().unwrap()
+        };
+
+        let old = load(
+            r#"
+                [[ports]]
+                    name = "eth0"
+                    device = "net_null0"
+                    cores = [1]
+                    rxd = 128
+                    txd = 128
+            "#,
+        );
+        let new = load(
+            r#"
+                [[ports]]
+                    name = "eth0"
+                    device = "net_null0"
+                    cores = [1]
+                    rxd = 128
+                    txd = 128
+                    kni = true
+                    tx_retries = 3
+
+                [[ports]]
+                    name = "eth1"
+                    device = "net_null1"
+                    cores = [2]
+                    rxd = 128
+                    txd = 128
+            "#,
+        );
+
+        assert!(old.diff(&old).is_empty());
+
+        let diff = old.diff(&new);
+        let changes = diff
+            .changes()
+            .iter()
+            .map(|change| (change.key.as_str(), change.live))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("ports.eth0.kni", false),
+                ("ports.eth0.tx_retries", true),
+                ("ports.eth1", false)
+            ],
+            changes
+        );
+        assert!(diff.requires_restart());
+        assert_eq!(
+            "ports.eth0.kni: false -> true (requires restart)",
+            diff.changes()[0].to_string()
+        );
+    }
+
+    #[test]
+    fn network_types_serde_round_trip() {
+        let mut config = Config::new();
+        config
+            .merge(File::from_str(
+                r#"
+                    core = 2
+                    port = 1
+                    rxq = 3
+                    txq = 3
+                    mac = "00:00:00:00:00:01"
+                    v4 = "10.0.0.0/24"
+                    v6 = "2001:db8::/32"
+                "#,
+                FileFormat::Toml,
+            ))
+            .unwrap();
+        let settings: UserSettings = config.try_into().unwrap();
+
+        assert_eq!(CoreId::new(2), settings.core);
+        assert_eq!(1, settings.port.raw());
+        assert_eq!(RxQueueIndex::new(3), settings.rxq);
+        assert_eq!(TxQueueIndex::new(3), settings.txq);
+        assert_eq!(MacAddr::new(0, 0, 0, 0, 0, 1), settings.mac);
+        assert_eq!(
+            Ipv4Cidr::new(Ipv4Addr::new(10, 0, 0, 0), 24).unwrap(),
+            settings.v4
+        );
+        assert_eq!(
+            Ipv6Cidr::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32).unwrap(),
+            settings.v6
+        );
+
+        let config = Config::try_from(&settings).unwrap();
+        let round_trip: UserSettings = config.try_into().unwrap();
+        assert_eq!(settings, round_trip);
+    }
 
     #[test]
     fn config_to_eal_args() {
@@ -342,6 +877,7 @@ mod tests {
                     [mempool]
                         capacity = 255
                         cache_size = 16
+                        headroom = 256
 
                     [[ports]]
                         name = "nic1"
@@ -357,6 +893,11 @@ mod tests {
                         cores = [0, 4]
                         rxd = 32
                         txd = 32
+
+                        [ports.mempool]
+                            capacity = 1023
+                            cache_size = 0
+                            dataroom = 9216
                 "#,
                 FileFormat::Toml,
             ))
@@ -379,6 +920,57 @@ mod tests {
                 "eal:8"
             ],
             settings.to_eal_args().as_slice(),
-        )
+        );
+
+        assert_eq!(2048, settings.mempool.dataroom());
+        assert_eq!(256, settings.mempool.headroom());
+        assert!(settings.ports[0].mempool.is_none());
+        assert_eq!(9216, settings.ports[1].mempool.as_ref().unwrap().dataroom());
+    }
+
+    #[test]
+    fn no_huge_in_dpdk_args() {
+        let mut settings = RuntimeSettings::default();
+        assert!(!settings.no_huge());
+
+        settings.dpdk_args = Some("-m 512 --no-huge".to_owned());
+        assert!(settings.no_huge());
+
+        settings.dpdk_args = Some("--no-hugepages-here".to_owned());
+        assert!(!settings.no_huge());
+    }
+
+    #[test]
+    fn pipeline_mode_workers() {
+        let mut config = Config::new();
+        config
+            .merge(File::from_str(DEFAULT_TOML, FileFormat::Toml))
+            .unwrap();
+        config
+            .merge(File::from_str(
+                r#"
+                    [execution]
+                        mode = "pipeline"
+                        workers = [3, 4]
+
+                    [[ports]]
+                        name = "nic1"
+                        device = "0000:00:01.0"
+                        cores = [1, 2]
+                        rxd = 32
+                        txd = 32
+                "#,
+                FileFormat::Toml,
+            ))
+            .unwrap();
+        let settings: RuntimeSettings = config.try_into().unwrap();
+
+        let execution = settings.execution.as_ref().unwrap();
+        assert_eq!(ExecutionMode::Pipeline, execution.mode);
+        assert_eq!(DEFAULT_RING_SIZE, execution.ring_size());
+        assert_eq!(
+            (0..5).map(CoreId::new).collect::<Vec<_>>(),
+            settings.all_cores()
+        );
     }
 }
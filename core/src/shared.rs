@@ -0,0 +1,189 @@
+use crate::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+struct Inner<T> {
+    value: Mutex<Arc<T>>,
+    version: AtomicUsize,
+    // held by the writers for the whole of a store or an update, so an
+    // update is not lost to a concurrent one.
+    writer: Mutex<()>,
+}
+
+/// A value shared by the pipelines of all cores and replaced atomically.
+///
+/// Use for read-mostly state, like a lookup table loaded from a file, that
+/// is occasionally replaced as a whole by the control plane. The pipelines
+/// read the value through a `SharedReader`, which holds on to the current
+/// value and only checks for a new one when refreshed, usually once per
+/// batch. A pipeline never sees a partially updated value, and the old
+/// value is freed once the last reader moves on.
+///
+/// # Example
+///
+/// ```
+/// let routes = Shared::new(load_routes("routes.csv")?);
+///
+/// let mut reader = routes.reader();
+/// let batch = Poll::new(q.clone())
+///     .for_each(move |packet| {
+///         reader.refresh();
+///         lookup(reader.get(), packet)
+///     });
+///
+/// // on reload, the pipelines pick up the new routes on their next batch.
+/// routes.store(load_routes("routes.csv")?);
+/// ```
+pub struct Shared<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Shared<T> {
+    /// Creates a new shared value.
+    pub fn new(value: T) -> Self {
+        Shared {
+            inner: Arc::new(Inner {
+                value: Mutex::new(Arc::new(value)),
+                version: AtomicUsize::new(0),
+                writer: Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Returns the current value.
+    pub fn load(&self) -> Arc<T> {
+        self.inner.value.lock().unwrap().clone()
+    }
+
+    /// Replaces the value.
+    pub fn store(&self, value: T) {
+        let _writer = self.inner.writer.lock().unwrap();
+        self.replace(value);
+    }
+
+    /// Replaces the value with the one `f` builds from the current value.
+    ///
+    /// The value is left as is if `f` fails. Updates and stores are
+    /// serialized, no other writer replaces the value while `f` runs, so
+    /// keep `f` short.
+    pub fn update<F: FnOnce(&T) -> Result<T>>(&self, f: F) -> Result<()> {
+        let _writer = self.inner.writer.lock().unwrap();
+        let value = f(&self.load())?;
+        self.replace(value);
+        Ok(())
+    }
+
+    fn replace(&self, value: T) {
+        *self.inner.value.lock().unwrap() = Arc::new(value);
+        self.inner.version.fetch_add(1, Ordering::Release);
+    }
+
+    /// Returns the number of times the value has been replaced.
+    pub fn version(&self) -> usize {
+        self.inner.version.load(Ordering::Acquire)
+    }
+
+    /// Creates a new reader of the value.
+    pub fn reader(&self) -> SharedReader<T> {
+        let version = self.version();
+        SharedReader {
+            inner: self.inner.clone(),
+            value: self.load(),
+            version,
+        }
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Shared {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// A reader of a `Shared` value, owned by a single pipeline.
+pub struct SharedReader<T> {
+    inner: Arc<Inner<T>>,
+    value: Arc<T>,
+    version: usize,
+}
+
+impl<T> SharedReader<T> {
+    /// Picks up the new value if it has been replaced since the last
+    /// refresh. Costs a single atomic load when it has not.
+    #[inline]
+    pub fn refresh(&mut self) {
+        let version = self.inner.version.load(Ordering::Acquire);
+        if version != self.version {
+            self.value = self.inner.value.lock().unwrap().clone();
+            self.version = version;
+        }
+    }
+
+    /// Returns the value as of the last refresh.
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Clone for SharedReader<T> {
+    fn clone(&self) -> Self {
+        SharedReader {
+            inner: self.inner.clone(),
+            value: self.value.clone(),
+            version: self.version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn refresh_on_store() {
+        let shared = Shared::new(1);
+        let mut reader = shared.reader();
+
+        shared.store(2);
+        assert_eq!(1, *reader.get());
+        reader.refresh();
+        assert_eq!(2, *reader.get());
+        assert_eq!(1, shared.version());
+    }
+
+    #[test]
+    fn keep_value_on_failed_update() {
+        let shared = Shared::new(1);
+
+        assert!(shared.update(|v| Ok(v + 1)).is_ok());
+        assert!(shared
+            .update(|_| Err(failure::err_msg("failed to load")))
+            .is_err());
+        assert_eq!(2, *shared.load());
+    }
+
+    #[test]
+    fn serialize_concurrent_updates() {
+        let shared = Shared::new(0);
+
+        let threads = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        shared.update(|v| Ok(v + 1)).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        // no update is lost.
+        assert_eq!(4000, *shared.load());
+        assert_eq!(4000, shared.version());
+    }
+}
@@ -0,0 +1,147 @@
+use super::per_core::PerCore;
+use crate::dpdk::PortId;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The counters of the packets an ingress ACL rule matched, by the action
+/// taken.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct AclCounts {
+    /// The packets passed on unchanged.
+    pub permitted: u64,
+    /// The packets dropped.
+    pub denied: u64,
+    /// The packets passed on with a mark.
+    pub marked: u64,
+}
+
+impl AclCounts {
+    fn add(&mut self, other: &AclCounts) {
+        self.permitted += other.permitted;
+        self.denied += other.denied;
+        self.marked += other.marked;
+    }
+}
+
+// the rule is `None` for the default action of the list.
+type Counters = Mutex<HashMap<(PortId, Option<usize>), AclCounts>>;
+
+lazy_static! {
+    // the counters of every core that has received through an ACL.
+    static ref CORES: PerCore<Counters> = PerCore::default();
+}
+
+thread_local! {
+    // the counters of the current core. the lock is only contended when
+    // the counters are read.
+    static COUNTERS: Arc<Counters> = CORES.register();
+}
+
+/// Records the packets of a burst that matched the rule of the ingress ACL
+/// of the port.
+pub(crate) fn record_acl(port_id: PortId, rule: Option<usize>, counts: &AclCounts) {
+    COUNTERS.with(|counters| {
+        counters
+            .lock()
+            .unwrap()
+            .entry((port_id, rule))
+            .or_default()
+            .add(counts)
+    });
+}
+
+/// Clears the counters of the ingress ACL of the port, when its list is
+/// replaced and the positions refer to other rules.
+pub(crate) fn reset_acl(port_id: PortId) {
+    CORES.for_each(|_, counters| {
+        counters
+            .lock()
+            .unwrap()
+            .retain(|&(port, _), _| port != port_id)
+    });
+}
+
+/// Returns the ingress ACL counters aggregated across all the cores.
+///
+/// The rules are counted by their position in the list. The counters of a
+/// port are cleared when its list is replaced.
+pub fn acl_stats() -> AclStats {
+    let mut stats = AclStats::default();
+
+    CORES.for_each(|_, counters| {
+        for (&key, counts) in counters.lock().unwrap().iter() {
+            stats.0.entry(key).or_default().add(counts);
+        }
+    });
+
+    stats
+}
+
+/// A snapshot of the ingress ACL counters by port and rule.
+#[derive(Clone, Debug, Default)]
+pub struct AclStats(HashMap<(PortId, Option<usize>), AclCounts>);
+
+impl AclStats {
+    /// Returns the counters of the rule of the port, or of the default
+    /// action if `rule` is `None`.
+    pub fn get(&self, port_id: PortId, rule: Option<usize>) -> AclCounts {
+        self.0.get(&(port_id, rule)).cloned().unwrap_or_default()
+    }
+
+    /// Returns the counters of all the rules of the port.
+    pub fn port(&self, port_id: PortId) -> AclCounts {
+        let mut total = AclCounts::default();
+        self.0
+            .iter()
+            .filter(|((port, _), _)| *port == port_id)
+            .for_each(|(_, counts)| total.add(counts));
+        total
+    }
+
+    /// Returns an iterator over the ports, rules and their counters.
+    pub fn iter(&self) -> impl Iterator<Item = (PortId, Option<usize>, AclCounts)> + '_ {
+        self.0
+            .iter()
+            .map(|(&(port_id, rule), &counts)| (port_id, rule, counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_acl_by_rule() {
+        // tests run in parallel, so use a port no other test records.
+        let port_id = PortId::new(0x7ff4);
+
+        let denied = AclCounts {
+            denied: 3,
+            ..Default::default()
+        };
+        let marked = AclCounts {
+            marked: 2,
+            ..Default::default()
+        };
+        record_acl(port_id, Some(0), &denied);
+        record_acl(port_id, Some(0), &denied);
+        record_acl(port_id, None, &marked);
+
+        let stats = acl_stats();
+        assert_eq!(6, stats.get(port_id, Some(0)).denied);
+        assert_eq!(2, stats.get(port_id, None).marked);
+        assert_eq!(AclCounts::default(), stats.get(port_id, Some(1)));
+        assert_eq!(
+            AclCounts {
+                permitted: 0,
+                denied: 6,
+                marked: 2
+            },
+            stats.port(port_id)
+        );
+
+        reset_acl(port_id);
+        assert_eq!(AclCounts::default(), acl_stats().port(port_id));
+    }
+}
@@ -0,0 +1,179 @@
+use super::per_core::PerCore;
+use crate::dpdk::{BufferError, PortId};
+use crate::packets::ParseError;
+use failure::Error;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Kind of protocol anomaly that makes a packet fail to parse.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Anomaly {
+    /// The packet is shorter than its headers.
+    Truncated,
+    /// The IP version does not match the header.
+    BadVersion,
+    /// A length field is inconsistent with the header or the packet.
+    BadLength,
+    /// A checksum is invalid.
+    BadChecksum,
+    /// The packet is not of the expected protocol.
+    UnexpectedProtocol,
+}
+
+impl Anomaly {
+    /// Returns the anomaly behind the error, or `None` if the error is not
+    /// a parse failure.
+    pub fn of(err: &Error) -> Option<Anomaly> {
+        if let Some(err) = err.downcast_ref::<ParseError>() {
+            Some(err.anomaly())
+        } else if let Some(err) = err.downcast_ref::<BufferError>() {
+            match err {
+                BufferError::BadOffset(..) | BufferError::OutOfBuffer(..) => {
+                    Some(Anomaly::Truncated)
+                }
+                BufferError::NotResized => None,
+            }
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anomaly::Truncated => write!(f, "truncated"),
+            Anomaly::BadVersion => write!(f, "bad_version"),
+            Anomaly::BadLength => write!(f, "bad_length"),
+            Anomaly::BadChecksum => write!(f, "bad_checksum"),
+            Anomaly::UnexpectedProtocol => write!(f, "unexpected_protocol"),
+        }
+    }
+}
+
+type Counters = Mutex<HashMap<(PortId, Anomaly), u64>>;
+
+lazy_static! {
+    // the counters of every core that has recorded an anomaly.
+    static ref CORES: PerCore<Counters> = PerCore::default();
+}
+
+thread_local! {
+    // the counters of the current core. the lock is only contended when
+    // the counters are read.
+    static COUNTERS: Arc<Counters> = CORES.register();
+}
+
+/// Records a protocol anomaly of a packet received on `port_id` on the
+/// current core.
+pub fn record_anomaly(port_id: PortId, anomaly: Anomaly) {
+    COUNTERS.with(|counters| {
+        *counters
+            .lock()
+            .unwrap()
+            .entry((port_id, anomaly))
+            .or_insert(0) += 1;
+    });
+}
+
+/// Records the anomaly behind the error a packet failed with, if it is a
+/// parse failure of a packet received on a port.
+#[inline]
+pub(crate) fn record_failure(port_id: Option<PortId>, err: &Error) {
+    if let (Some(port_id), Some(anomaly)) = (port_id, Anomaly::of(err)) {
+        record_anomaly(port_id, anomaly);
+    }
+}
+
+/// Returns the anomaly counts aggregated across all the cores.
+///
+/// The packets that fail to parse in a `map`, `filter_map` or `replace`
+/// are counted, by the port they were received on, as well as the
+/// anomalies the application records with `record_anomaly`.
+pub fn anomaly_stats() -> AnomalyStats {
+    let mut stats = AnomalyStats::default();
+
+    CORES.for_each(|_, counters| {
+        for (&key, &count) in counters.lock().unwrap().iter() {
+            *stats.0.entry(key).or_insert(0) += count;
+        }
+    });
+
+    stats
+}
+
+/// A snapshot of the anomaly counts by port.
+#[derive(Clone, Debug, Default)]
+pub struct AnomalyStats(HashMap<(PortId, Anomaly), u64>);
+
+impl AnomalyStats {
+    /// Returns the number of packets received on the port with the
+    /// anomaly.
+    pub fn get(&self, port_id: PortId, anomaly: Anomaly) -> u64 {
+        self.0.get(&(port_id, anomaly)).cloned().unwrap_or(0)
+    }
+
+    /// Returns the number of packets received on the port with any
+    /// anomaly.
+    pub fn port_total(&self, port_id: PortId) -> u64 {
+        self.0
+            .iter()
+            .filter(|((port, _), _)| *port == port_id)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Returns the total number of packets with an anomaly.
+    pub fn total(&self) -> u64 {
+        self.0.values().sum()
+    }
+
+    /// Returns an iterator over the counts by port and anomaly.
+    pub fn iter(&self) -> impl Iterator<Item = (PortId, Anomaly, u64)> + '_ {
+        self.0
+            .iter()
+            .map(|(&(port_id, anomaly), &count)| (port_id, anomaly, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        let err: Error = ParseError::with_anomaly(Anomaly::BadVersion, "bad version").into();
+        assert_eq!(Some(Anomaly::BadVersion), Anomaly::of(&err));
+
+        let err: Error = BufferError::OutOfBuffer(20, 10).into();
+        assert_eq!(Some(Anomaly::Truncated), Anomaly::of(&err));
+
+        let err: Error = ParseError::new("Not a PPPoE frame.").into();
+        assert_eq!(Some(Anomaly::UnexpectedProtocol), Anomaly::of(&err));
+
+        let err = failure::err_msg("application error");
+        assert_eq!(None, Anomaly::of(&err));
+    }
+
+    #[test]
+    fn count_anomalies_by_port() {
+        // tests run in parallel, so use a port no other test records.
+        let port = PortId::new(0x7ff1);
+
+        record_anomaly(port, Anomaly::Truncated);
+        record_anomaly(port, Anomaly::Truncated);
+        record_failure(Some(port), &BufferError::BadOffset(100, 60).into());
+        record_failure(Some(port), &failure::err_msg("application error"));
+        record_failure(None, &BufferError::BadOffset(100, 60).into());
+        std::thread::spawn(move || record_anomaly(port, Anomaly::BadChecksum))
+            .join()
+            .unwrap();
+
+        let stats = anomaly_stats();
+        assert_eq!(3, stats.get(port, Anomaly::Truncated));
+        assert_eq!(1, stats.get(port, Anomaly::BadChecksum));
+        assert_eq!(4, stats.port_total(port));
+    }
+}
@@ -0,0 +1,92 @@
+use super::per_core::PerCore;
+use crate::dpdk::CoreId;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Mempool cache lookups of one core.
+#[derive(Debug, Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+lazy_static! {
+    // the counters of every core that has allocated from a mempool cache.
+    static ref CORES: PerCore<CacheCounters> = PerCore::default();
+}
+
+thread_local! {
+    // the counters of the current core. only the owning core writes.
+    static COUNTERS: Arc<CacheCounters> = CORES.register();
+}
+
+/// Records an allocation on the current core that was either served from
+/// the core's mempool cache or had to go to the shared pool.
+#[inline]
+pub(crate) fn record_cache_lookup(hit: bool) {
+    COUNTERS.with(|counters| {
+        if hit {
+            counters.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            counters.misses.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Returns the mempool cache lookups of the application's allocations, by
+/// core.
+///
+/// Only the allocations made with `Mbuf::new` and `Mbuf::alloc_bulk` are
+/// counted. The buffers the devices allocate on receive are not. Nothing
+/// is counted when the mempool cache is disabled, with a `cache_size` of
+/// `0` in the mempool settings.
+///
+/// A low hit ratio on a core that generates packets means the cache is
+/// too small for the bursts it allocates. Raise the `cache_size`.
+pub fn mempool_cache_stats() -> HashMap<CoreId, CacheStats> {
+    let mut map = HashMap::new();
+
+    CORES.for_each(|core_id, counters| {
+        let stats = map.entry(core_id).or_insert_with(CacheStats::default);
+        stats.hits += counters.hits.load(Ordering::Relaxed);
+        stats.misses += counters.misses.load(Ordering::Relaxed);
+    });
+
+    map
+}
+
+/// A snapshot of the mempool cache lookups of a core.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// The number of allocations served from the core's cache.
+    pub hits: u64,
+    /// The number of allocations that went to the shared pool.
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Returns the ratio of allocations served from the cache, from `0.0`
+    /// to `1.0`.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total > 0 {
+            self.hits as f64 / total as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_hit_ratio() {
+        let stats = CacheStats { hits: 3, misses: 1 };
+        assert_eq!(0.75, stats.hit_ratio());
+        assert_eq!(0.0, CacheStats::default().hit_ratio());
+    }
+}
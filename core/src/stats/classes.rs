@@ -0,0 +1,89 @@
+use super::per_core::PerCore;
+use crate::dpdk::PacketMeta;
+use crate::Mbuf;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Packet and byte counters by traffic class, written by a single core.
+#[derive(Debug, Default)]
+pub(super) struct ClassCounters {
+    packets: [AtomicU64; PacketMeta::CLASSES],
+    bytes: [AtomicU64; PacketMeta::CLASSES],
+}
+
+impl ClassCounters {
+    /// Records a packet of `len` bytes of the traffic class.
+    #[inline]
+    pub(super) fn record(&self, class: usize, len: usize) {
+        // only the owning core writes, relaxed is enough.
+        self.packets[class].fetch_add(1, Ordering::Relaxed);
+        self.bytes[class].fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// Adds the counters to the snapshots of the traffic classes.
+    pub(super) fn add_to(&self, stats: &mut [ClassStats; PacketMeta::CLASSES]) {
+        for (class, stats) in stats.iter_mut().enumerate() {
+            stats.packets += self.packets[class].load(Ordering::Relaxed);
+            stats.bytes += self.bytes[class].load(Ordering::Relaxed);
+        }
+    }
+}
+
+lazy_static! {
+    // the counters of every core that has transmitted packets.
+    static ref CORES: PerCore<ClassCounters> = PerCore::default();
+}
+
+thread_local! {
+    // the counters of the current core. only the owning core writes.
+    static COUNTERS: Arc<ClassCounters> = CORES.register();
+}
+
+/// Records the packets handed to a port for transmit, by the traffic class
+/// in their metadata.
+#[inline]
+pub(crate) fn record_class_tx(packets: &[Mbuf]) {
+    COUNTERS.with(|counters| {
+        for packet in packets {
+            counters.record(packet.meta().traffic_class as usize, packet.pkt_len());
+        }
+    });
+}
+
+/// Returns the packets and bytes transmitted on all the ports, by traffic
+/// class, aggregated across all the cores.
+pub fn class_stats() -> [ClassStats; PacketMeta::CLASSES] {
+    let mut stats = [ClassStats::default(); PacketMeta::CLASSES];
+
+    CORES.for_each(|_, counters| counters.add_to(&mut stats));
+
+    stats
+}
+
+/// A snapshot of the transmit counters of a traffic class.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ClassStats {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::UDP_PACKET;
+
+    #[nb2::test]
+    fn record_by_traffic_class() {
+        let before = class_stats();
+
+        let mut packet = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        packet.set_meta(PacketMeta::default().with_traffic_class(3));
+        record_class_tx(&[packet, Mbuf::from_bytes(&UDP_PACKET).unwrap()]);
+
+        let after = class_stats();
+        assert_eq!(1, after[3].packets - before[3].packets);
+        assert_eq!(UDP_PACKET.len() as u64, after[3].bytes - before[3].bytes);
+        assert_eq!(1, after[0].packets - before[0].packets);
+    }
+}
@@ -0,0 +1,116 @@
+use super::per_core::PerCore;
+use crate::dpdk::CoreId;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Polling cycles of one core.
+#[derive(Debug, Default)]
+struct CoreCounters {
+    busy: AtomicU64,
+    idle: AtomicU64,
+}
+
+lazy_static! {
+    // the counters of every core that has polled for packets.
+    static ref CORES: PerCore<CoreCounters> = PerCore::default();
+}
+
+thread_local! {
+    // the counters of the current core. only the owning core writes.
+    static COUNTERS: Arc<CoreCounters> = CORES.register();
+}
+
+/// Records a poll of a pipeline on the current core that took `cycles`.
+///
+/// The poll is busy if it processed at least one packet, and idle if it
+/// came back empty handed.
+#[inline]
+pub(crate) fn record_poll(cycles: u64, busy: bool) {
+    COUNTERS.with(|counters| {
+        if busy {
+            counters.busy.fetch_add(cycles, Ordering::Relaxed);
+        } else {
+            counters.idle.fetch_add(cycles, Ordering::Relaxed);
+        }
+    });
+}
+
+/// Returns the polling cycles of the cores that run pipelines, by core.
+pub fn core_stats() -> HashMap<CoreId, CoreStats> {
+    let mut map = HashMap::new();
+
+    CORES.for_each(|core_id, counters| {
+        let stats = map.entry(core_id).or_insert_with(CoreStats::default);
+        stats.busy_cycles += counters.busy.load(Ordering::Relaxed);
+        stats.idle_cycles += counters.idle.load(Ordering::Relaxed);
+    });
+
+    map
+}
+
+/// A snapshot of the polling cycles of a core.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CoreStats {
+    /// The TSC cycles spent on polls that processed packets.
+    pub busy_cycles: u64,
+    /// The TSC cycles spent on polls that found no packets.
+    pub idle_cycles: u64,
+}
+
+impl CoreStats {
+    /// Returns the percentage of the polling cycles spent processing
+    /// packets, from `0.0` to `100.0`.
+    ///
+    /// A core that is close to 100% is saturated. A low utilization means
+    /// the core is mostly spinning on empty queues.
+    pub fn utilization(&self) -> f64 {
+        let total = self.busy_cycles + self.idle_cycles;
+        if total > 0 {
+            self.busy_cycles as f64 * 100.0 / total as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the difference between this and an earlier snapshot, to
+    /// compute the utilization over an interval.
+    pub fn since(&self, earlier: &CoreStats) -> CoreStats {
+        CoreStats {
+            busy_cycles: self.busy_cycles.wrapping_sub(earlier.busy_cycles),
+            idle_cycles: self.idle_cycles.wrapping_sub(earlier.idle_cycles),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_utilization() {
+        let stats = CoreStats {
+            busy_cycles: 300,
+            idle_cycles: 100,
+        };
+        assert_eq!(75.0, stats.utilization());
+        assert_eq!(0.0, CoreStats::default().utilization());
+
+        let later = CoreStats {
+            busy_cycles: 400,
+            idle_cycles: 400,
+        };
+        assert_eq!(25.0, later.since(&stats).utilization());
+    }
+
+    #[nb2::test]
+    fn record_busy_and_idle_polls() {
+        record_poll(30, true);
+        record_poll(10, false);
+
+        let stats = core_stats()[&CoreId::current()];
+        assert!(stats.busy_cycles >= 30);
+        assert!(stats.idle_cycles >= 10);
+    }
+}
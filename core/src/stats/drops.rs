@@ -0,0 +1,200 @@
+use super::per_core::PerCore;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Reason a packet is dropped by the pipeline.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DropReason {
+    /// The packet cannot be parsed.
+    ParseError,
+    /// The TTL or hop limit of the packet is exhausted.
+    TtlExceeded,
+    /// The packet is denied by an access control list.
+    AclDeny,
+    /// There is no route to the destination of the packet.
+    NoRoute,
+    /// The packet has an invalid checksum.
+    ChecksumInvalid,
+    /// The source address of the packet fails the reverse path check.
+    SpoofedSource,
+    /// The ring to the downstream pipeline is full.
+    RingFull,
+    /// The downstream pipeline is congested and the packet is dropped
+    /// early, before the ring is full.
+    Backpressure,
+    /// The packet has header fields inconsistent with each other or with
+    /// the buffer, or a combination of TCP flags no stack sends.
+    Malformed,
+    /// The source or destination address of the packet is a bogon.
+    Martian,
+    /// The packet is part of a flood to a blocked destination.
+    Flood,
+    /// The payload of the packet matches a content rule.
+    Content,
+    /// The link layer address of the next hop of the packet is not
+    /// resolved.
+    Unresolved,
+    /// The transmit queue of the port stays full after the retries.
+    TxFull,
+    /// The scheduler queue of the traffic class of the packet is full.
+    QueueFull,
+    /// The packet is rejected by the filter attached to a hook point, or
+    /// by a plugin.
+    Filtered,
+    /// The packet is a copy of a packet seen shortly before.
+    Duplicate,
+    /// The packet is lost on purpose by a network impairment.
+    Impaired,
+    /// An application defined reason.
+    User(u32),
+}
+
+impl DropReason {
+    /// Returns the number of the reason.
+    ///
+    /// The predefined reasons are numbered from `1`. User defined reasons
+    /// are numbered from `1000` so they never collide with predefined ones.
+    pub fn code(self) -> u64 {
+        match self {
+            DropReason::ParseError => 1,
+            DropReason::TtlExceeded => 2,
+            DropReason::AclDeny => 3,
+            DropReason::NoRoute => 4,
+            DropReason::ChecksumInvalid => 5,
+            DropReason::SpoofedSource => 6,
+            DropReason::RingFull => 7,
+            DropReason::Backpressure => 8,
+            DropReason::Malformed => 9,
+            DropReason::Martian => 10,
+            DropReason::Flood => 11,
+            DropReason::Content => 12,
+            DropReason::Unresolved => 13,
+            DropReason::TxFull => 14,
+            DropReason::QueueFull => 15,
+            DropReason::Filtered => 16,
+            DropReason::Duplicate => 17,
+            DropReason::Impaired => 18,
+            DropReason::User(code) => 1000 + u64::from(code),
+        }
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DropReason::ParseError => write!(f, "parse_error"),
+            DropReason::TtlExceeded => write!(f, "ttl_exceeded"),
+            DropReason::AclDeny => write!(f, "acl_deny"),
+            DropReason::NoRoute => write!(f, "no_route"),
+            DropReason::ChecksumInvalid => write!(f, "checksum_invalid"),
+            DropReason::SpoofedSource => write!(f, "spoofed_source"),
+            DropReason::RingFull => write!(f, "ring_full"),
+            DropReason::Backpressure => write!(f, "backpressure"),
+            DropReason::Malformed => write!(f, "malformed"),
+            DropReason::Martian => write!(f, "martian"),
+            DropReason::Flood => write!(f, "flood"),
+            DropReason::Content => write!(f, "content"),
+            DropReason::Unresolved => write!(f, "unresolved"),
+            DropReason::TxFull => write!(f, "tx_full"),
+            DropReason::QueueFull => write!(f, "queue_full"),
+            DropReason::Filtered => write!(f, "filtered"),
+            DropReason::Duplicate => write!(f, "duplicate"),
+            DropReason::Impaired => write!(f, "impaired"),
+            DropReason::User(code) => write!(f, "user_{}", code),
+        }
+    }
+}
+
+type Counters = Mutex<HashMap<DropReason, u64>>;
+
+lazy_static! {
+    // the counters of every core that has recorded a drop.
+    static ref CORES: PerCore<Counters> = PerCore::default();
+}
+
+thread_local! {
+    // the counters of the current core. the lock is only contended when
+    // the counters are read.
+    static COUNTERS: Arc<Counters> = CORES.register();
+}
+
+/// Records a dropped packet on the current core.
+#[inline]
+pub fn record_drop(reason: DropReason) {
+    record_drops(reason, 1)
+}
+
+/// Records `count` dropped packets on the current core.
+pub fn record_drops(reason: DropReason, count: u64) {
+    COUNTERS.with(|counters| {
+        *counters.lock().unwrap().entry(reason).or_insert(0) += count;
+    });
+}
+
+/// Returns the drop counts aggregated across all the cores.
+pub fn drop_stats() -> DropStats {
+    let mut stats = DropStats::default();
+
+    CORES.for_each(|_, counters| {
+        for (&reason, &count) in counters.lock().unwrap().iter() {
+            *stats.0.entry(reason).or_insert(0) += count;
+        }
+    });
+
+    stats
+}
+
+/// A snapshot of the drop counts by reason.
+#[derive(Clone, Debug, Default)]
+pub struct DropStats(HashMap<DropReason, u64>);
+
+impl DropStats {
+    /// Returns the number of packets dropped for the reason.
+    pub fn get(&self, reason: DropReason) -> u64 {
+        self.0.get(&reason).cloned().unwrap_or(0)
+    }
+
+    /// Returns the total number of packets dropped.
+    pub fn total(&self) -> u64 {
+        self.0.values().sum()
+    }
+
+    /// Returns an iterator over the reasons and their counts.
+    pub fn iter(&self) -> impl Iterator<Item = (DropReason, u64)> + '_ {
+        self.0.iter().map(|(&reason, &count)| (reason, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn drop_reason_to_string() {
+        assert_eq!("acl_deny", DropReason::AclDeny.to_string());
+        assert_eq!("user_7", DropReason::User(7).to_string());
+        assert_eq!(1007, DropReason::User(7).code());
+    }
+
+    #[test]
+    fn aggregate_drops_across_cores() {
+        // tests run in parallel, so use reasons no other test records.
+        let reason = DropReason::User(0xdead_0001);
+        let other = DropReason::User(0xdead_0002);
+
+        record_drop(reason);
+        thread::spawn(move || {
+            record_drops(reason, 2);
+            record_drop(other);
+        })
+        .join()
+        .unwrap();
+
+        let stats = drop_stats();
+        assert_eq!(3, stats.get(reason));
+        assert_eq!(1, stats.get(other));
+    }
+}
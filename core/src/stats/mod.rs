@@ -0,0 +1,28 @@
+//! Counters for observing the packet processing.
+//!
+//! Counters are recorded on the core that processes the packets, without
+//! contention with the other cores, and aggregated across all the cores
+//! when read.
+
+mod acl;
+mod anomalies;
+mod caches;
+mod classes;
+mod cores;
+mod drops;
+mod per_core;
+mod pipelines;
+mod profile;
+mod traffic;
+mod tx_queues;
+
+pub use self::acl::*;
+pub use self::anomalies::*;
+pub use self::caches::*;
+pub use self::classes::*;
+pub use self::cores::*;
+pub use self::drops::*;
+pub use self::pipelines::*;
+pub use self::profile::*;
+pub use self::traffic::*;
+pub use self::tx_queues::*;
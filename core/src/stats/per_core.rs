@@ -0,0 +1,42 @@
+use crate::dpdk::CoreId;
+use std::sync::{Arc, Mutex};
+
+/// The counters of every core, of one kind.
+///
+/// Each core records into its own counters, held in a thread local that
+/// registers them on first use with `register`, so the cores don't
+/// contend with each other. The counters are only read across the cores
+/// to aggregate them, with `for_each`.
+pub(crate) struct PerCore<T> {
+    cores: Mutex<Vec<(CoreId, Arc<T>)>>,
+}
+
+impl<T: Default> PerCore<T> {
+    /// Registers new counters for the current core.
+    pub(crate) fn register(&self) -> Arc<T> {
+        let counters = Arc::new(T::default());
+        self.cores
+            .lock()
+            .unwrap()
+            .push((CoreId::current(), counters.clone()));
+        counters
+    }
+}
+
+impl<T> PerCore<T> {
+    /// Calls `f` with the counters of every core registered so far, and
+    /// the core they belong to.
+    pub(crate) fn for_each<F: FnMut(CoreId, &T)>(&self, mut f: F) {
+        for (core_id, counters) in self.cores.lock().unwrap().iter() {
+            f(*core_id, counters);
+        }
+    }
+}
+
+impl<T> Default for PerCore<T> {
+    fn default() -> Self {
+        PerCore {
+            cores: Mutex::new(vec![]),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Counters of one scheduled pipeline instance.
+#[derive(Debug, Default)]
+pub(crate) struct PipelineCounters {
+    runs: AtomicU64,
+    skips: AtomicU64,
+    cycles: AtomicU64,
+}
+
+impl PipelineCounters {
+    /// Records a run that consumed `cycles`.
+    #[inline]
+    pub(crate) fn record_run(&self, cycles: u64) {
+        // only the owning core writes, relaxed is enough.
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.cycles.fetch_add(cycles, Ordering::Relaxed);
+    }
+
+    /// Records a turn the pipeline did not get to run.
+    #[inline]
+    pub(crate) fn record_skip(&self) {
+        self.skips.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+lazy_static! {
+    // the counters of every scheduled pipeline, by name.
+    static ref PIPELINES: Mutex<Vec<(String, Arc<PipelineCounters>)>> = Mutex::new(vec![]);
+}
+
+/// Registers the counters of a new pipeline instance.
+pub(crate) fn register_pipeline(name: &str) -> Arc<PipelineCounters> {
+    let counters = Arc::new(PipelineCounters::default());
+    PIPELINES
+        .lock()
+        .unwrap()
+        .push((name.to_owned(), counters.clone()));
+    counters
+}
+
+/// Returns the stats of the scheduled pipelines, by name.
+///
+/// A pipeline installed on more than one core has its instances
+/// aggregated under the same name.
+pub fn pipeline_stats() -> HashMap<String, PipelineStats> {
+    let mut map = HashMap::new();
+
+    for (name, counters) in PIPELINES.lock().unwrap().iter() {
+        let stats = map
+            .entry(name.clone())
+            .or_insert_with(PipelineStats::default);
+        stats.runs += counters.runs.load(Ordering::Relaxed);
+        stats.skips += counters.skips.load(Ordering::Relaxed);
+        stats.cycles += counters.cycles.load(Ordering::Relaxed);
+    }
+
+    map
+}
+
+/// A snapshot of the counters of a scheduled pipeline.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PipelineStats {
+    /// The number of times the pipeline has run.
+    pub runs: u64,
+    /// The number of turns the pipeline was skipped for being over budget.
+    pub skips: u64,
+    /// The TSC cycles consumed by the pipeline.
+    pub cycles: u64,
+}
+
+impl PipelineStats {
+    /// Returns the average cycles consumed per run.
+    pub fn cycles_per_run(&self) -> u64 {
+        if self.runs > 0 {
+            self.cycles / self.runs
+        } else {
+            0
+        }
+    }
+}
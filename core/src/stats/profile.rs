@@ -0,0 +1,232 @@
+use super::per_core::PerCore;
+use lazy_static::lazy_static;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The number of buckets, one per power of two plus one for zero.
+const BUCKETS: usize = 65;
+
+/// Returns the largest value that falls in the bucket.
+#[inline]
+fn upper_bound(bucket: usize) -> u64 {
+    match bucket {
+        0 => 0,
+        _ => u64::max_value() >> (64 - bucket),
+    }
+}
+
+/// A histogram of cycle counts, bucketed by powers of two.
+///
+/// Bucket `i` counts the samples in the range `[2^(i-1), 2^i)`, with
+/// bucket `0` counting the samples of zero cycles.
+#[derive(Clone)]
+pub struct CycleHistogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl CycleHistogram {
+    /// Records a sample.
+    #[inline]
+    pub fn record(&mut self, cycles: u64) {
+        self.buckets[(64 - cycles.leading_zeros()) as usize] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(cycles);
+        self.max = self.max.max(cycles);
+    }
+
+    /// Adds the samples of another histogram to this one.
+    pub fn merge(&mut self, other: &CycleHistogram) {
+        for (a, b) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *a += b;
+        }
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the number of samples.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the total cycles of all the samples.
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// Returns the largest sample.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Returns the mean of the samples.
+    pub fn mean(&self) -> u64 {
+        if self.count > 0 {
+            self.sum / self.count
+        } else {
+            0
+        }
+    }
+
+    /// Returns an upper bound of the `p`th percentile, where `p` is
+    /// between `0.0` and `1.0`.
+    ///
+    /// The value is the upper bound of the bucket the percentile falls
+    /// in, so it is accurate to within a factor of two.
+    pub fn percentile(&self, p: f64) -> u64 {
+        let rank = (self.count as f64 * p).ceil() as u64;
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank && n > 0 {
+                return upper_bound(i);
+            }
+        }
+        self.max
+    }
+
+    /// Returns an iterator over the non-empty buckets, as the upper bound
+    /// of the bucket and its count.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(i, &n)| (upper_bound(i), n))
+    }
+}
+
+impl Default for CycleHistogram {
+    fn default() -> Self {
+        CycleHistogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+}
+
+impl std::fmt::Debug for CycleHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CycleHistogram")
+            .field("count", &self.count)
+            .field("mean", &self.mean())
+            .field("p50", &self.percentile(0.5))
+            .field("p99", &self.percentile(0.99))
+            .field("max", &self.max)
+            .finish()
+    }
+}
+
+type Histograms = Mutex<HashMap<&'static str, CycleHistogram>>;
+
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    // the histograms of every core that has recorded a sample.
+    static ref CORES: PerCore<Histograms> = PerCore::default();
+}
+
+thread_local! {
+    static HISTOGRAMS: Arc<Histograms> = CORES.register();
+
+    // cycles spent in the nested operators of the one being sampled.
+    static NESTED: Cell<u64> = Cell::new(0);
+}
+
+/// Turns the per-operator profiling on or off.
+///
+/// Profiling is off by default. When off, the profiling points in the
+/// pipelines do not sample the TSC.
+pub fn set_profiling(enabled: bool) {
+    PROFILING.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether the per-operator profiling is on.
+#[inline]
+pub fn is_profiling() -> bool {
+    PROFILING.load(Ordering::Relaxed)
+}
+
+/// Runs `f` and records the cycles spent in it under `name`, excluding
+/// the cycles recorded by the profiling points nested inside `f`.
+#[inline]
+pub(crate) fn sample<T, F: FnOnce() -> T>(name: &'static str, f: F) -> T {
+    let outer = NESTED.with(|nested| nested.replace(0));
+    let start = crate::dpdk::tsc();
+    let result = f();
+    let elapsed = crate::dpdk::tsc().wrapping_sub(start);
+    let inner = NESTED.with(|nested| nested.replace(outer + elapsed));
+
+    HISTOGRAMS.with(|histograms| {
+        histograms
+            .lock()
+            .unwrap()
+            .entry(name)
+            .or_insert_with(CycleHistogram::default)
+            .record(elapsed.saturating_sub(inner));
+    });
+
+    result
+}
+
+/// Returns the cycle histograms of the profiled operators, aggregated
+/// across all the cores.
+pub fn operator_stats() -> HashMap<&'static str, CycleHistogram> {
+    let mut map = HashMap::new();
+
+    CORES.for_each(|_, histograms| {
+        for (&name, histogram) in histograms.lock().unwrap().iter() {
+            map.entry(name)
+                .or_insert_with(CycleHistogram::default)
+                .merge(histogram);
+        }
+    });
+
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_histogram_percentiles() {
+        let mut histogram = CycleHistogram::default();
+        for cycles in 1..=100 {
+            histogram.record(cycles);
+        }
+
+        assert_eq!(100, histogram.count());
+        assert_eq!(50, histogram.mean());
+        assert_eq!(100, histogram.max());
+        // 50 falls in the [32, 64) bucket.
+        assert_eq!(63, histogram.percentile(0.5));
+        assert_eq!(127, histogram.percentile(0.99));
+    }
+
+    #[test]
+    fn sample_excludes_nested_cycles() {
+        let spin = |cycles: u64| {
+            let start = crate::dpdk::tsc();
+            while crate::dpdk::tsc().wrapping_sub(start) < cycles {}
+        };
+
+        sample("profile_test_outer", || {
+            spin(1000);
+            sample("profile_test_inner", || spin(1_000_000));
+        });
+
+        let stats = operator_stats();
+        let outer = &stats["profile_test_outer"];
+        let inner = &stats["profile_test_inner"];
+        assert!(inner.sum() >= 1_000_000);
+        assert!(outer.sum() < inner.sum());
+    }
+}
@@ -0,0 +1,245 @@
+use super::classes::{ClassCounters, ClassStats};
+use crate::dpdk::{tsc_hz, PacketMeta};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{self, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The largest size of each packet size bucket, in bytes, the buckets of
+/// RMON. The sizes include the 4 bytes of the FCS, as in RMON, though the
+/// packets are counted without it. The last bucket counts the packets
+/// larger than `1518` bytes.
+pub const SIZE_BUCKETS: [usize; 6] = [64, 127, 255, 511, 1023, 1518];
+
+const BUCKETS: usize = SIZE_BUCKETS.len() + 1;
+
+// the length of the FCS, stripped on receive and appended on transmit.
+const FCS_LEN: usize = 4;
+
+/// Returns the bucket of the size of a packet without its FCS.
+#[inline]
+fn bucket(len: usize) -> usize {
+    let len = len + FCS_LEN;
+    SIZE_BUCKETS
+        .iter()
+        .position(|&max| len <= max)
+        .unwrap_or(SIZE_BUCKETS.len())
+}
+
+/// The counts of the last window that ended, of every traffic class.
+///
+/// Written by the owning core under a sequence lock, so a reader never
+/// sees the counts of one window with the length of another.
+#[derive(Debug, Default)]
+struct Window {
+    // odd while a write is in progress.
+    seq: AtomicU64,
+    packets: [AtomicU64; PacketMeta::CLASSES],
+    bytes: [AtomicU64; PacketMeta::CLASSES],
+    cycles: AtomicU64,
+}
+
+impl Window {
+    #[inline]
+    fn store(&self, counts: &[(u64, u64); PacketMeta::CLASSES], cycles: u64) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        atomic::fence(Ordering::Release);
+
+        for (class, &(packets, bytes)) in counts.iter().enumerate() {
+            self.packets[class].store(packets, Ordering::Relaxed);
+            self.bytes[class].store(bytes, Ordering::Relaxed);
+        }
+        self.cycles.store(cycles, Ordering::Relaxed);
+
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    fn load(&self) -> ([(u64, u64); PacketMeta::CLASSES], u64) {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                let mut counts = [(0, 0); PacketMeta::CLASSES];
+                for (class, counts) in counts.iter_mut().enumerate() {
+                    counts.0 = self.packets[class].load(Ordering::Relaxed);
+                    counts.1 = self.bytes[class].load(Ordering::Relaxed);
+                }
+                let cycles = self.cycles.load(Ordering::Relaxed);
+
+                atomic::fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return (counts, cycles);
+                }
+            }
+            atomic::spin_loop_hint();
+        }
+    }
+}
+
+/// Counters of one characterization point, by traffic class.
+#[derive(Debug, Default)]
+pub(crate) struct TrafficCounters {
+    totals: ClassCounters,
+    sizes: [[AtomicU64; BUCKETS]; PacketMeta::CLASSES],
+    window: Window,
+}
+
+impl TrafficCounters {
+    /// Records a packet of `len` bytes.
+    #[inline]
+    pub(crate) fn record(&self, class: usize, len: usize) {
+        // only the owning core writes, relaxed is enough.
+        self.totals.record(class, len);
+        self.sizes[class][bucket(len)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the packets and bytes of every class of a window of
+    /// `cycles` that ended.
+    #[inline]
+    pub(crate) fn record_window(&self, counts: &[(u64, u64); PacketMeta::CLASSES], cycles: u64) {
+        self.window.store(counts, cycles);
+    }
+}
+
+lazy_static! {
+    // the counters of every characterization point, by name.
+    static ref TRAFFIC: Mutex<Vec<(String, Arc<TrafficCounters>)>> = Mutex::new(vec![]);
+}
+
+/// Registers the counters of a new characterization point.
+pub(crate) fn register_traffic(name: &str) -> Arc<TrafficCounters> {
+    let counters = Arc::new(TrafficCounters::default());
+    TRAFFIC
+        .lock()
+        .unwrap()
+        .push((name.to_owned(), counters.clone()));
+    counters
+}
+
+/// Returns the traffic seen by the characterization points, by name and
+/// by traffic class.
+///
+/// A point installed on more than one core, or more than one point with
+/// the same name, has its counters aggregated under the name, and its
+/// rates added up.
+pub fn traffic_stats() -> HashMap<String, [TrafficStats; PacketMeta::CLASSES]> {
+    let mut map = HashMap::new();
+    let hz = tsc_hz() as f64;
+
+    for (name, counters) in TRAFFIC.lock().unwrap().iter() {
+        let stats = map
+            .entry(name.clone())
+            .or_insert([TrafficStats::default(); PacketMeta::CLASSES]);
+
+        let mut totals = [ClassStats::default(); PacketMeta::CLASSES];
+        counters.totals.add_to(&mut totals);
+        let (window, cycles) = counters.window.load();
+
+        for (class, stats) in stats.iter_mut().enumerate() {
+            stats.packets += totals[class].packets;
+            stats.bytes += totals[class].bytes;
+            for (a, b) in stats.sizes.iter_mut().zip(counters.sizes[class].iter()) {
+                *a += b.load(Ordering::Relaxed);
+            }
+
+            if cycles > 0 {
+                let secs = cycles as f64 / hz;
+                let (packets, bytes) = window[class];
+                stats.packet_rate += packets as f64 / secs;
+                stats.bit_rate += (bytes * 8) as f64 / secs;
+            }
+        }
+    }
+
+    map
+}
+
+/// A snapshot of the traffic of a class at a characterization point.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TrafficStats {
+    /// The number of packets seen.
+    pub packets: u64,
+    /// The number of bytes seen.
+    pub bytes: u64,
+    /// The number of packets seen in each bucket of `SIZE_BUCKETS`, and of
+    /// the packets larger than the last bucket.
+    pub sizes: [u64; BUCKETS],
+    /// The packets per second over the last window that ended.
+    pub packet_rate: f64,
+    /// The bits per second over the last window that ended.
+    pub bit_rate: f64,
+}
+
+impl TrafficStats {
+    /// Adds the traffic of another class or point to this one.
+    pub fn merge(&mut self, other: &TrafficStats) {
+        self.packets += other.packets;
+        self.bytes += other.bytes;
+        for (a, b) in self.sizes.iter_mut().zip(other.sizes.iter()) {
+            *a += b;
+        }
+        self.packet_rate += other.packet_rate;
+        self.bit_rate += other.bit_rate;
+    }
+
+    /// Returns the mean size of the packets.
+    pub fn mean_size(&self) -> u64 {
+        if self.packets > 0 {
+            self.bytes / self.packets
+        } else {
+            0
+        }
+    }
+
+    /// Returns an iterator over the size buckets, as the largest size of
+    /// the bucket and its count. The last bucket has no largest size.
+    pub fn sizes(&self) -> impl Iterator<Item = (Option<usize>, u64)> + '_ {
+        SIZE_BUCKETS
+            .iter()
+            .map(|&max| Some(max))
+            .chain(Some(None))
+            .zip(self.sizes.iter().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_by_size() {
+        assert_eq!(0, bucket(42));
+        assert_eq!(0, bucket(60));
+        assert_eq!(1, bucket(61));
+        assert_eq!(5, bucket(1514));
+        assert_eq!(6, bucket(1515));
+        assert_eq!(6, bucket(9000));
+    }
+
+    #[nb2::test]
+    fn aggregate_by_name() {
+        let first = register_traffic("traffic_test");
+        let second = register_traffic("traffic_test");
+        first.record(0, 60);
+        first.record(0, 1500);
+        second.record(2, 9000);
+        let mut window = [(0, 0); PacketMeta::CLASSES];
+        window[2] = (10, 1000);
+        second.record_window(&window, tsc_hz());
+
+        let stats = &traffic_stats()["traffic_test"];
+        assert_eq!(2, stats[0].packets);
+        assert_eq!(780, stats[0].mean_size());
+        assert_eq!(
+            vec![1, 0, 0, 0, 0, 1, 0],
+            stats[0].sizes().map(|(_, n)| n).collect::<Vec<_>>()
+        );
+        assert_eq!(1, stats[2].sizes[6]);
+        assert!((stats[2].packet_rate - 10.0).abs() < 1e-6);
+        assert!((stats[2].bit_rate - 8000.0).abs() < 1e-6);
+
+        let mut total = TrafficStats::default();
+        stats.iter().for_each(|class| total.merge(class));
+        assert_eq!(3, total.packets);
+    }
+}
@@ -0,0 +1,129 @@
+use super::per_core::PerCore;
+use crate::dpdk::{PortId, TxQueueIndex};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The counters of a transmit queue that could not take all the packets
+/// offered to it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TxFullCounts {
+    /// The bursts the queue stayed full for after all the retries.
+    pub full: u64,
+    /// The retries after the queue took none of the packets.
+    pub retries: u64,
+    /// The packets dropped because the queue stayed full.
+    pub dropped: u64,
+}
+
+type Counters = Mutex<HashMap<(PortId, TxQueueIndex), TxFullCounts>>;
+
+lazy_static! {
+    // the counters of every core that has found a transmit queue full.
+    static ref CORES: PerCore<Counters> = PerCore::default();
+}
+
+thread_local! {
+    // the counters of the current core. the lock is only contended when
+    // the counters are read.
+    static COUNTERS: Arc<Counters> = CORES.register();
+}
+
+#[inline]
+fn update<F: FnOnce(&mut TxFullCounts)>(port_id: PortId, txq_index: TxQueueIndex, f: F) {
+    COUNTERS.with(|counters| {
+        f(counters
+            .lock()
+            .unwrap()
+            .entry((port_id, txq_index))
+            .or_default())
+    });
+}
+
+/// Records a burst the transmit queue stayed full for after all the retries.
+pub(crate) fn record_tx_full(port_id: PortId, txq_index: TxQueueIndex) {
+    update(port_id, txq_index, |counts| counts.full += 1);
+}
+
+/// Records a retry of a burst the transmit queue took none of.
+pub(crate) fn record_tx_retry(port_id: PortId, txq_index: TxQueueIndex) {
+    update(port_id, txq_index, |counts| counts.retries += 1);
+}
+
+/// Records the packets dropped because the transmit queue stayed full.
+pub(crate) fn record_tx_dropped(port_id: PortId, txq_index: TxQueueIndex, count: u64) {
+    update(port_id, txq_index, |counts| counts.dropped += count);
+}
+
+/// Returns the transmit queue counters aggregated across all the cores.
+///
+/// Only the queues that have been full at least once are counted. A queue
+/// often full is a sign of congestion at the egress, where the retries of
+/// the port settings, `tx_retries` and `tx_backoff`, trade latency for
+/// fewer drops.
+pub fn tx_queue_stats() -> TxQueueStats {
+    let mut stats = TxQueueStats::default();
+
+    CORES.for_each(|_, counters| {
+        for (&key, counts) in counters.lock().unwrap().iter() {
+            let total = stats.0.entry(key).or_default();
+            total.full += counts.full;
+            total.retries += counts.retries;
+            total.dropped += counts.dropped;
+        }
+    });
+
+    stats
+}
+
+/// A snapshot of the transmit queue counters by queue.
+#[derive(Clone, Debug, Default)]
+pub struct TxQueueStats(HashMap<(PortId, TxQueueIndex), TxFullCounts>);
+
+impl TxQueueStats {
+    /// Returns the counters of the transmit queue of the port.
+    pub fn get(&self, port_id: PortId, txq_index: TxQueueIndex) -> TxFullCounts {
+        self.0
+            .get(&(port_id, txq_index))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns an iterator over the queues and their counters.
+    pub fn iter(&self) -> impl Iterator<Item = (PortId, TxQueueIndex, TxFullCounts)> + '_ {
+        self.0
+            .iter()
+            .map(|(&(port_id, txq_index), &counts)| (port_id, txq_index, counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_tx_full_by_queue() {
+        // tests run in parallel, so use a port no other test records.
+        let port_id = PortId::new(0x7ff3);
+        let txq_index = TxQueueIndex::new(1);
+
+        record_tx_full(port_id, txq_index);
+        record_tx_retry(port_id, txq_index);
+        record_tx_retry(port_id, txq_index);
+        record_tx_dropped(port_id, txq_index, 5);
+
+        let stats = tx_queue_stats();
+        assert_eq!(
+            TxFullCounts {
+                full: 1,
+                retries: 2,
+                dropped: 5
+            },
+            stats.get(port_id, txq_index)
+        );
+        assert_eq!(
+            TxFullCounts::default(),
+            stats.get(port_id, TxQueueIndex::new(0))
+        );
+    }
+}
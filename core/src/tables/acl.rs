@@ -0,0 +1,147 @@
+use super::{
+    ActionSchema, FieldMatch, FieldSchema, MatchKind, TableEntry, TableError, TableSchema,
+    TableTarget,
+};
+use crate::net::{Acl, AclAction, AclRule, Cidr, Ipv4Cidr, Ipv6Cidr};
+use crate::packets::ip::ProtocolNumber;
+use crate::{stats, AclHandle, Result};
+use std::net::IpAddr;
+
+/// The ingress ACL of a port as a table.
+///
+/// The entries match the fields `src` and `dst` by prefix, `protocol`
+/// exactly and `src_port` and `dst_port` by range, all optional. The
+/// actions are `permit`, `deny` and `mark(mark)`. The entries are ordered
+/// by priority, and the packets not matching any entry take the default
+/// action. The counters are the ones of `stats::acl_stats`, cleared with
+/// every update.
+pub struct AclTable {
+    handle: AclHandle,
+    default: AclAction,
+}
+
+impl AclTable {
+    /// Creates the table of the ACL, with the action of the packets that
+    /// match no entry.
+    pub fn new(handle: AclHandle, default: AclAction) -> Self {
+        AclTable { handle, default }
+    }
+}
+
+impl TableTarget for AclTable {
+    fn schema(&self) -> TableSchema {
+        let field = |name, kind| FieldSchema {
+            name,
+            kind,
+            required: false,
+        };
+
+        TableSchema {
+            fields: vec![
+                field("src", MatchKind::Lpm),
+                field("dst", MatchKind::Lpm),
+                field("protocol", MatchKind::Exact),
+                field("src_port", MatchKind::Range),
+                field("dst_port", MatchKind::Range),
+            ],
+            actions: vec![
+                ActionSchema {
+                    name: "permit",
+                    params: &[],
+                },
+                ActionSchema {
+                    name: "deny",
+                    params: &[],
+                },
+                ActionSchema {
+                    name: "mark",
+                    params: &["mark"],
+                },
+            ],
+            priorities: true,
+        }
+    }
+
+    fn apply(&self, entries: &[TableEntry]) -> Result<()> {
+        let rules = entries.iter().map(to_rule).collect::<Result<Vec<_>>>()?;
+        let acl = Acl::new(&rules, self.default)?;
+        self.handle.set(Some(acl));
+        Ok(())
+    }
+
+    fn counters(&self, entries: &[TableEntry]) -> Option<Vec<u64>> {
+        let stats = stats::acl_stats();
+        let port_id = self.handle.port_id();
+        Some(
+            (0..entries.len())
+                .map(|rule| {
+                    let counts = stats.get(port_id, Some(rule));
+                    counts.permitted + counts.denied + counts.marked
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Converts an entry, already checked against the schema, to a rule.
+fn to_rule(entry: &TableEntry) -> Result<AclRule> {
+    let action = match entry.action.as_str() {
+        "permit" => AclAction::Permit,
+        "deny" => AclAction::Deny,
+        _ => {
+            let mark = entry.params[0]
+                .as_uint()
+                .filter(|&mark| mark <= u64::from(u32::max_value()))
+                .ok_or_else(|| {
+                    TableError::InvalidParam("mark".to_owned(), entry.params[0].to_string())
+                })?;
+            AclAction::Mark(mark as u32)
+        }
+    };
+
+    let mut rule = AclRule::new(action);
+    for (name, m) in entry.matches.iter() {
+        rule = match (name.as_str(), m) {
+            ("src", &FieldMatch::Lpm(IpAddr::V4(addr), len)) => {
+                rule.src_v4(Ipv4Cidr::new(addr, len)?)
+            }
+            ("src", &FieldMatch::Lpm(IpAddr::V6(addr), len)) => {
+                rule.src_v6(Ipv6Cidr::new(addr, len)?)
+            }
+            ("dst", &FieldMatch::Lpm(IpAddr::V4(addr), len)) => {
+                rule.dst_v4(Ipv4Cidr::new(addr, len)?)
+            }
+            ("dst", &FieldMatch::Lpm(IpAddr::V6(addr), len)) => {
+                rule.dst_v6(Ipv6Cidr::new(addr, len)?)
+            }
+            ("protocol", FieldMatch::Exact(value)) => {
+                let protocol = value
+                    .as_uint()
+                    .filter(|&protocol| protocol <= 0xff)
+                    .ok_or_else(|| {
+                        TableError::InvalidMatch("protocol".to_owned(), value.to_string())
+                    })?;
+                rule.protocol(ProtocolNumber::new(protocol as u8))
+            }
+            ("src_port", &FieldMatch::Range(first, last)) => {
+                let (first, last) = to_ports("src_port", first, last)?;
+                rule.src_ports(first, last)
+            }
+            ("dst_port", &FieldMatch::Range(first, last)) => {
+                let (first, last) = to_ports("dst_port", first, last)?;
+                rule.dst_ports(first, last)
+            }
+            _ => unreachable!("checked against the schema"),
+        };
+    }
+
+    Ok(rule)
+}
+
+fn to_ports(field: &str, first: u64, last: u64) -> Result<(u16, u16)> {
+    if last <= 0xffff {
+        Ok((first as u16, last as u16))
+    } else {
+        Err(TableError::InvalidMatch(field.to_owned(), format!("range {}-{}", first, last)).into())
+    }
+}
@@ -0,0 +1,635 @@
+//! A uniform abstraction for the rule tables of the dataplane.
+//!
+//! Modeled on the tables of P4Runtime, every rule-programming surface, the
+//! ingress ACLs, the routes and the NAT mappings, is a `Table` of entries.
+//! An entry matches fields by kind, exact, longest prefix or range, and
+//! names an action with its parameters and a priority. The table checks
+//! the entries against its schema, keeps them, and hands the whole list to
+//! its `TableTarget` to compile into the dataplane state after every
+//! change. A controller or a CLI then programs any table the same way,
+//! by name, through `table`. The static mappings are all the NAT state of
+//! the dataplane, there are no NAT pools to program.
+//!
+//! The routes and the NAT mappings tables only write the keys of their
+//! own entries, so the values the application adds to the same `Shared`
+//! outside of the table are kept.
+//!
+//! A new table plugs in by implementing `TableTarget`, a schema and the
+//! compilation of the entries, and registering it with `register`.
+//!
+//! # Example
+//!
+//! ```
+//! tables::register(Table::new(
+//!     "acl.eth1",
+//!     AclTable::new(runtime.acl_handle("eth1")?, AclAction::Permit),
+//! ));
+//!
+//! let acl = tables::table("acl.eth1").unwrap();
+//! acl.write(Update::Insert(TableEntry {
+//!     matches: vec![
+//!         ("dst".to_owned(), FieldMatch::Lpm("10.0.0.0".parse()?, 8)),
+//!         ("protocol".to_owned(), FieldMatch::Exact(Value::Uint(6))),
+//!         ("dst_port".to_owned(), FieldMatch::Range(22, 22)),
+//!     ],
+//!     action: "deny".to_owned(),
+//!     params: vec![],
+//!     priority: 10,
+//! }))?;
+//! ```
+
+mod acl;
+mod nat;
+mod routes;
+
+pub use self::acl::AclTable;
+pub use self::nat::NatTable;
+pub use self::routes::RoutesTable;
+
+use crate::journal::{self, Event};
+use crate::{ensure, Result};
+use failure::Fail;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Error indicating an entry or an update is invalid for the table.
+#[derive(Debug, Fail)]
+pub enum TableError {
+    /// There is no table of the name.
+    #[fail(display = "Table '{}' is not found.", _0)]
+    UnknownTable(String),
+
+    /// The schema of the table has no field of the name.
+    #[fail(display = "Unknown field '{}'.", _0)]
+    UnknownField(String),
+
+    /// The field is matched by another kind than the one of the schema.
+    #[fail(display = "Field '{}' is matched as {:?}.", _0, _1)]
+    WrongKind(String, MatchKind),
+
+    /// The field is matched more than once.
+    #[fail(display = "Field '{}' is matched more than once.", _0)]
+    DuplicateField(String),
+
+    /// The field is required but not matched.
+    #[fail(display = "Field '{}' is required.", _0)]
+    MissingField(String),
+
+    /// The value of a match is invalid for the field.
+    #[fail(display = "Invalid match of field '{}': {}.", _0, _1)]
+    InvalidMatch(String, String),
+
+    /// The schema of the table has no action of the name.
+    #[fail(display = "Unknown action '{}'.", _0)]
+    UnknownAction(String),
+
+    /// The value of a parameter is invalid for the action.
+    #[fail(display = "Invalid parameter of action '{}': {}.", _0, _1)]
+    InvalidParam(String, String),
+
+    /// The action has another number of parameters.
+    #[fail(display = "Action '{}' takes {} parameters.", _0, _1)]
+    WrongParams(String, usize),
+
+    /// The table does not order the entries by priority, and the entry has
+    /// one.
+    #[fail(display = "Table does not support priorities.")]
+    PriorityNotSupported,
+
+    /// An entry with the same matches and priority is already in the table.
+    #[fail(display = "Entry already exists.")]
+    Exists,
+
+    /// There is no entry with the matches and priority in the table.
+    #[fail(display = "Entry is not found.")]
+    NotFound,
+}
+
+/// How a field is matched, the match kinds of P4.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MatchKind {
+    Exact,
+    Lpm,
+    Range,
+}
+
+/// The value of a field or of an action parameter.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Value {
+    Uint(u64),
+    Addr(IpAddr),
+}
+
+impl Value {
+    /// Returns the value as an integer.
+    pub fn as_uint(self) -> Option<u64> {
+        match self {
+            Value::Uint(value) => Some(value),
+            Value::Addr(_) => None,
+        }
+    }
+
+    /// Returns the value as an address.
+    pub fn as_addr(self) -> Option<IpAddr> {
+        match self {
+            Value::Uint(_) => None,
+            Value::Addr(addr) => Some(addr),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Uint(value) => write!(f, "{}", value),
+            Value::Addr(addr) => write!(f, "{}", addr),
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Failed to parse '{}' as value.", _0)]
+pub struct ValueParseError(String);
+
+impl FromStr for Value {
+    type Err = ValueParseError;
+
+    /// Parses an address, or else an integer.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        s.parse::<IpAddr>()
+            .map(Value::Addr)
+            .or_else(|_| s.parse::<u64>().map(Value::Uint))
+            .map_err(|_| ValueParseError(s.to_owned()))
+    }
+}
+
+/// The match of a field of an entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldMatch {
+    /// The field is the value.
+    Exact(Value),
+    /// The address is in the prefix, of the address and length.
+    Lpm(IpAddr, usize),
+    /// The field is in `low..=high`.
+    Range(u64, u64),
+}
+
+impl FieldMatch {
+    /// Returns the kind of the match.
+    pub fn kind(&self) -> MatchKind {
+        match self {
+            FieldMatch::Exact(_) => MatchKind::Exact,
+            FieldMatch::Lpm(..) => MatchKind::Lpm,
+            FieldMatch::Range(..) => MatchKind::Range,
+        }
+    }
+
+    fn check(&self) -> std::result::Result<(), String> {
+        match *self {
+            FieldMatch::Lpm(IpAddr::V4(_), len) if len > 32 => Err(format!("length {}", len)),
+            FieldMatch::Lpm(IpAddr::V6(_), len) if len > 128 => Err(format!("length {}", len)),
+            FieldMatch::Range(low, high) if low > high => Err(format!("range {}-{}", low, high)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A field of a table schema.
+#[derive(Clone, Debug)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    pub kind: MatchKind,
+    /// Whether every entry must match the field. An entry that leaves
+    /// out an optional field matches any value of it.
+    pub required: bool,
+}
+
+/// An action of a table schema.
+#[derive(Clone, Debug)]
+pub struct ActionSchema {
+    pub name: &'static str,
+    /// The names of the parameters, in order.
+    pub params: &'static [&'static str],
+}
+
+/// The fields and actions of a table.
+#[derive(Clone, Debug)]
+pub struct TableSchema {
+    pub fields: Vec<FieldSchema>,
+    pub actions: Vec<ActionSchema>,
+    /// Whether the entries are ordered by priority. The entries of a table
+    /// without priorities must have the priority `0`.
+    pub priorities: bool,
+}
+
+/// An entry of a table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableEntry {
+    /// The matches, by field name.
+    pub matches: Vec<(String, FieldMatch)>,
+    pub action: String,
+    /// The parameters of the action, in the order of the schema.
+    pub params: Vec<Value>,
+    /// The higher the priority, the earlier the entry matches.
+    pub priority: u32,
+}
+
+impl TableEntry {
+    /// Returns the match of the field.
+    pub fn field(&self, name: &str) -> Option<&FieldMatch> {
+        self.matches
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, m)| m)
+    }
+
+    /// Returns whether the entries have the same key, the matches and the
+    /// priority.
+    fn same_key(&self, other: &TableEntry) -> bool {
+        self.priority == other.priority
+            && self.matches.len() == other.matches.len()
+            && self
+                .matches
+                .iter()
+                .all(|(name, m)| other.field(name) == Some(m))
+    }
+}
+
+impl fmt::Display for TableEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, m) in self.matches.iter() {
+            match m {
+                FieldMatch::Exact(value) => write!(f, "{}={} ", name, value)?,
+                FieldMatch::Lpm(addr, len) => write!(f, "{}={}/{} ", name, addr, len)?,
+                FieldMatch::Range(low, high) => write!(f, "{}={}-{} ", name, low, high)?,
+            }
+        }
+
+        let params = self
+            .params
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "{}({}) priority {}",
+            self.action,
+            params.join(", "),
+            self.priority
+        )
+    }
+}
+
+/// A change to the entries of a table.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Update {
+    /// Adds the entry.
+    Insert(TableEntry),
+    /// Replaces the action of the entry with the same key.
+    Modify(TableEntry),
+    /// Removes the entry with the same key. The action is ignored.
+    Delete(TableEntry),
+}
+
+/// What a table programs.
+pub trait TableTarget: Send + Sync + 'static {
+    /// Returns the schema of the table.
+    fn schema(&self) -> TableSchema;
+
+    /// Compiles the entries, checked against the schema and highest
+    /// priority first, into the dataplane state, replacing the state of
+    /// the previous entries.
+    ///
+    /// # Errors
+    ///
+    /// The update is rejected, and the previous state left in place.
+    fn apply(&self, entries: &[TableEntry]) -> Result<()>;
+
+    /// Returns the packets each entry matched, by position, if the
+    /// dataplane counts them.
+    fn counters(&self, _entries: &[TableEntry]) -> Option<Vec<u64>> {
+        None
+    }
+}
+
+/// A table of entries, programming a target.
+pub struct Table {
+    name: String,
+    schema: TableSchema,
+    target: Box<dyn TableTarget>,
+    // highest priority first, then in the order inserted.
+    entries: Mutex<Vec<TableEntry>>,
+}
+
+impl Table {
+    /// Creates an empty table of the target. The target is not applied
+    /// until the first update.
+    pub fn new<T: TableTarget>(name: &str, target: T) -> Self {
+        Table {
+            name: name.to_owned(),
+            schema: target.schema(),
+            target: Box::new(target),
+            entries: Mutex::new(vec![]),
+        }
+    }
+
+    /// Returns the name of the table.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the schema of the table.
+    pub fn schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    /// Returns the entries of the table, highest priority first.
+    pub fn entries(&self) -> Vec<TableEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Returns the entries of the table with the packets they matched, or
+    /// `None` if the table does not count them.
+    pub fn counters(&self) -> Vec<(TableEntry, Option<u64>)> {
+        let entries = self.entries();
+        match self.target.counters(&entries) {
+            Some(counts) => entries
+                .into_iter()
+                .zip(counts.into_iter().map(Some))
+                .collect(),
+            None => entries.into_iter().map(|entry| (entry, None)).collect(),
+        }
+    }
+
+    /// Applies the update.
+    ///
+    /// # Errors
+    ///
+    /// If the entry does not fit the schema, or the key of the entry is
+    /// already in the table for an insert or not in it for a modify or a
+    /// delete, `TableError` is returned. If the target fails to apply the
+    /// new entries, its error is returned. Either way, the table is left
+    /// as it was.
+    pub fn write(&self, update: Update) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut new = entries.clone();
+
+        match &update {
+            Update::Insert(entry) => {
+                self.check(entry)?;
+                ensure!(!new.iter().any(|e| e.same_key(entry)), TableError::Exists);
+                // after the entries of the same priority.
+                let pos = new
+                    .iter()
+                    .position(|e| e.priority < entry.priority)
+                    .unwrap_or_else(|| new.len());
+                new.insert(pos, entry.clone());
+            }
+            Update::Modify(entry) => {
+                self.check(entry)?;
+                let pos = new
+                    .iter()
+                    .position(|e| e.same_key(entry))
+                    .ok_or(TableError::NotFound)?;
+                new[pos] = entry.clone();
+            }
+            Update::Delete(entry) => {
+                let pos = new
+                    .iter()
+                    .position(|e| e.same_key(entry))
+                    .ok_or(TableError::NotFound)?;
+                new.remove(pos);
+            }
+        }
+
+        self.target.apply(&new)?;
+        *entries = new;
+
+        if let Update::Insert(entry) = update {
+            journal::record_event(Event::RuleAdded {
+                table: self.name.clone(),
+                rule: entry.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks the entry against the schema.
+    fn check(&self, entry: &TableEntry) -> Result<()> {
+        for (i, (name, m)) in entry.matches.iter().enumerate() {
+            let field = self
+                .schema
+                .fields
+                .iter()
+                .find(|field| field.name == name.as_str())
+                .ok_or_else(|| TableError::UnknownField(name.clone()))?;
+            ensure!(
+                field.kind == m.kind(),
+                TableError::WrongKind(name.clone(), field.kind)
+            );
+            ensure!(
+                !entry.matches[..i].iter().any(|(other, _)| other == name),
+                TableError::DuplicateField(name.clone())
+            );
+            m.check()
+                .map_err(|err| TableError::InvalidMatch(name.clone(), err))?;
+        }
+
+        for field in self.schema.fields.iter().filter(|field| field.required) {
+            ensure!(
+                entry.field(field.name).is_some(),
+                TableError::MissingField(field.name.to_owned())
+            );
+        }
+
+        let action = self
+            .schema
+            .actions
+            .iter()
+            .find(|action| action.name == entry.action)
+            .ok_or_else(|| TableError::UnknownAction(entry.action.clone()))?;
+        ensure!(
+            action.params.len() == entry.params.len(),
+            TableError::WrongParams(entry.action.clone(), action.params.len())
+        );
+
+        ensure!(
+            self.schema.priorities || entry.priority == 0,
+            TableError::PriorityNotSupported
+        );
+
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref TABLES: RwLock<HashMap<String, Arc<Table>>> = RwLock::new(HashMap::new());
+}
+
+/// Registers the table under its name, replacing the table of the same
+/// name.
+pub fn register(table: Table) -> Arc<Table> {
+    let table = Arc::new(table);
+    TABLES
+        .write()
+        .unwrap()
+        .insert(table.name().to_owned(), table.clone());
+    table
+}
+
+/// Returns the table of the name.
+///
+/// # Errors
+///
+/// If there is no table of the name, `TableError::UnknownTable` is
+/// returned.
+pub fn table(name: &str) -> Result<Arc<Table>> {
+    TABLES
+        .read()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| TableError::UnknownTable(name.to_owned()).into())
+}
+
+/// Returns the names of the tables registered.
+pub fn tables() -> Vec<String> {
+    let mut names = TABLES.read().unwrap().keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A target that keeps the actions of the entries it is applied with.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl TableTarget for Recorder {
+        fn schema(&self) -> TableSchema {
+            TableSchema {
+                fields: vec![
+                    FieldSchema {
+                        name: "dst",
+                        kind: MatchKind::Lpm,
+                        required: true,
+                    },
+                    FieldSchema {
+                        name: "port",
+                        kind: MatchKind::Range,
+                        required: false,
+                    },
+                ],
+                actions: vec![
+                    ActionSchema {
+                        name: "drop",
+                        params: &[],
+                    },
+                    ActionSchema {
+                        name: "mark",
+                        params: &["mark"],
+                    },
+                ],
+                priorities: true,
+            }
+        }
+
+        fn apply(&self, entries: &[TableEntry]) -> Result<()> {
+            *self.0.lock().unwrap() = entries.iter().map(|e| e.action.clone()).collect();
+            Ok(())
+        }
+    }
+
+    fn entry(prefix: &str, action: &str, priority: u32) -> TableEntry {
+        TableEntry {
+            matches: vec![(
+                "dst".to_owned(),
+                FieldMatch::Lpm(prefix.parse().unwrap(), 8),
+            )],
+            action: action.to_owned(),
+            params: if action == "mark" {
+                vec![Value::Uint(7)]
+            } else {
+                vec![]
+            },
+            priority,
+        }
+    }
+
+    #[test]
+    fn order_by_priority() {
+        let recorder = Recorder::default();
+        let table = Table::new("test", recorder.clone());
+
+        table
+            .write(Update::Insert(entry("10.0.0.0", "drop", 1)))
+            .unwrap();
+        table
+            .write(Update::Insert(entry("11.0.0.0", "mark", 5)))
+            .unwrap();
+        table
+            .write(Update::Insert(entry("12.0.0.0", "drop", 1)))
+            .unwrap();
+        assert_eq!(vec!["mark", "drop", "drop"], *recorder.0.lock().unwrap());
+
+        table
+            .write(Update::Modify(entry("12.0.0.0", "mark", 1)))
+            .unwrap();
+        table
+            .write(Update::Delete(entry("11.0.0.0", "drop", 5)))
+            .unwrap();
+        assert_eq!(vec!["drop", "mark"], *recorder.0.lock().unwrap());
+        assert_eq!(2, table.entries().len());
+    }
+
+    #[test]
+    fn reject_invalid_entries() {
+        let table = Table::new("test", Recorder::default());
+        table
+            .write(Update::Insert(entry("10.0.0.0", "drop", 1)))
+            .unwrap();
+
+        assert!(table
+            .write(Update::Insert(entry("10.0.0.0", "drop", 1)))
+            .is_err());
+        assert!(table
+            .write(Update::Modify(entry("11.0.0.0", "drop", 1)))
+            .is_err());
+        assert!(table
+            .write(Update::Insert(entry("11.0.0.0", "reject", 1)))
+            .is_err());
+
+        let mut no_param = entry("11.0.0.0", "mark", 1);
+        no_param.params.clear();
+        assert!(table.write(Update::Insert(no_param)).is_err());
+
+        let mut no_dst = entry("11.0.0.0", "drop", 1);
+        no_dst.matches = vec![("port".to_owned(), FieldMatch::Range(80, 22))];
+        assert!(table.write(Update::Insert(no_dst)).is_err());
+
+        let mut exact = entry("11.0.0.0", "drop", 1);
+        exact
+            .matches
+            .push(("port".to_owned(), FieldMatch::Exact(Value::Uint(80))));
+        assert!(table.write(Update::Insert(exact)).is_err());
+
+        assert_eq!(1, table.entries().len());
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(Value::Uint(22), "22".parse().unwrap());
+        assert_eq!(
+            Value::Addr("10.0.0.1".parse().unwrap()),
+            "10.0.0.1".parse().unwrap()
+        );
+        assert!("eth0".parse::<Value>().is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use super::{
+    ActionSchema, FieldMatch, FieldSchema, MatchKind, TableEntry, TableError, TableSchema,
+    TableTarget,
+};
+use crate::shared::Shared;
+use crate::Result;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// Static NAT mappings, of internal addresses to external ones, as a
+/// table.
+///
+/// The entries match the field `internal` exactly, and have the action
+/// `translate(external)`. Every update stores new mappings, the pipelines
+/// see them on their next `refresh`.
+///
+/// The table only writes the internal addresses of its entries. The
+/// mappings the application adds itself are left in place, unless an
+/// entry has the same internal address, and then the entry replaces it.
+pub struct NatTable {
+    mappings: Shared<HashMap<IpAddr, IpAddr>>,
+    // the internal addresses of the entries last applied.
+    applied: Mutex<Vec<IpAddr>>,
+}
+
+impl NatTable {
+    /// Creates the table of the mappings. The mappings already in place
+    /// are kept.
+    pub fn new(mappings: Shared<HashMap<IpAddr, IpAddr>>) -> Self {
+        NatTable {
+            mappings,
+            applied: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl TableTarget for NatTable {
+    fn schema(&self) -> TableSchema {
+        TableSchema {
+            fields: vec![FieldSchema {
+                name: "internal",
+                kind: MatchKind::Exact,
+                required: true,
+            }],
+            actions: vec![ActionSchema {
+                name: "translate",
+                params: &["external"],
+            }],
+            priorities: false,
+        }
+    }
+
+    fn apply(&self, entries: &[TableEntry]) -> Result<()> {
+        let mut applied = self.applied.lock().unwrap();
+        let mut new = Vec::with_capacity(entries.len());
+
+        for entry in entries {
+            let internal = match entry.field("internal") {
+                Some(FieldMatch::Exact(value)) => value.as_addr().ok_or_else(|| {
+                    TableError::InvalidMatch("internal".to_owned(), value.to_string())
+                })?,
+                _ => unreachable!("checked against the schema"),
+            };
+            let external = entry.params[0].as_addr().ok_or_else(|| {
+                TableError::InvalidParam("external".to_owned(), entry.params[0].to_string())
+            })?;
+            new.push((internal, external));
+        }
+
+        self.mappings.update(|current| {
+            let mut mappings = current.clone();
+            for internal in applied.iter() {
+                mappings.remove(internal);
+            }
+            mappings.extend(new.iter().cloned());
+            Ok(mappings)
+        })?;
+
+        *applied = new.into_iter().map(|(internal, _)| internal).collect();
+        Ok(())
+    }
+}
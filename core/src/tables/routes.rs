@@ -0,0 +1,198 @@
+use super::{
+    ActionSchema, FieldMatch, FieldSchema, MatchKind, TableEntry, TableError, TableSchema,
+    TableTarget,
+};
+use crate::net::{Cidr, Ipv4Cidr, Ipv6Cidr, RouteTable};
+use crate::shared::Shared;
+use crate::Result;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+/// The prefix of a route.
+#[derive(Clone, Debug)]
+enum Prefix {
+    V4(Ipv4Cidr),
+    V6(Ipv6Cidr),
+}
+
+/// A route table of next hops as a table.
+///
+/// The entries match the field `dst` by prefix, and have the action
+/// `forward(next_hop)`. The longest prefix wins, so the entries have no
+/// priority. Every update stores a new route table, the pipelines see it
+/// on their next `refresh`.
+///
+/// The table only writes the prefixes of its entries. The routes the
+/// application adds to the route table itself are left in place, unless
+/// an entry has the same prefix, and then the entry replaces it.
+pub struct RoutesTable {
+    routes: Shared<RouteTable<IpAddr>>,
+    // the prefixes of the entries last applied.
+    applied: Mutex<Vec<Prefix>>,
+}
+
+impl RoutesTable {
+    /// Creates the table of the routes. The routes already in the route
+    /// table are kept.
+    pub fn new(routes: Shared<RouteTable<IpAddr>>) -> Self {
+        RoutesTable {
+            routes,
+            applied: Mutex::new(vec![]),
+        }
+    }
+}
+
+impl TableTarget for RoutesTable {
+    fn schema(&self) -> TableSchema {
+        TableSchema {
+            fields: vec![FieldSchema {
+                name: "dst",
+                kind: MatchKind::Lpm,
+                required: true,
+            }],
+            actions: vec![ActionSchema {
+                name: "forward",
+                params: &["next_hop"],
+            }],
+            priorities: false,
+        }
+    }
+
+    fn apply(&self, entries: &[TableEntry]) -> Result<()> {
+        let mut applied = self.applied.lock().unwrap();
+        let new = entries.iter().map(to_route).collect::<Result<Vec<_>>>()?;
+
+        self.routes.update(|current| {
+            let mut routes = current.clone();
+            for prefix in applied.iter() {
+                match prefix {
+                    Prefix::V4(cidr) => routes.remove_v4(cidr),
+                    Prefix::V6(cidr) => routes.remove_v6(cidr),
+                };
+            }
+            for (prefix, next_hop) in new.iter() {
+                match prefix {
+                    Prefix::V4(cidr) => routes.insert_v4(cidr.clone(), *next_hop),
+                    Prefix::V6(cidr) => routes.insert_v6(cidr.clone(), *next_hop),
+                };
+            }
+            Ok(routes)
+        })?;
+
+        *applied = new.into_iter().map(|(prefix, _)| prefix).collect();
+        Ok(())
+    }
+}
+
+/// Converts an entry, already checked against the schema, to a route.
+fn to_route(entry: &TableEntry) -> Result<(Prefix, IpAddr)> {
+    let next_hop = entry.params[0].as_addr().ok_or_else(|| {
+        TableError::InvalidParam("next_hop".to_owned(), entry.params[0].to_string())
+    })?;
+
+    let prefix = match entry.field("dst") {
+        Some(&FieldMatch::Lpm(IpAddr::V4(addr), len)) => Prefix::V4(Ipv4Cidr::new(addr, len)?),
+        Some(&FieldMatch::Lpm(IpAddr::V6(addr), len)) => Prefix::V6(Ipv6Cidr::new(addr, len)?),
+        _ => unreachable!("checked against the schema"),
+    };
+
+    Ok((prefix, next_hop))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tables::{Table, Update, Value};
+
+    #[test]
+    fn program_routes() {
+        let routes = Shared::new(RouteTable::new());
+        let table = Table::new("routes", RoutesTable::new(routes.clone()));
+
+        let route = |prefix: &str, len, next_hop: &str| TableEntry {
+            matches: vec![(
+                "dst".to_owned(),
+                FieldMatch::Lpm(prefix.parse().unwrap(), len),
+            )],
+            action: "forward".to_owned(),
+            params: vec![Value::Addr(next_hop.parse().unwrap())],
+            priority: 0,
+        };
+
+        table
+            .write(Update::Insert(route("10.0.0.0", 8, "192.168.0.1")))
+            .unwrap();
+        table
+            .write(Update::Insert(route("10.1.0.0", 16, "192.168.0.2")))
+            .unwrap();
+
+        let addr = "10.1.2.3".parse().unwrap();
+        assert_eq!(
+            Some(&"192.168.0.2".parse().unwrap()),
+            routes.load().lookup(addr)
+        );
+
+        table
+            .write(Update::Delete(route("10.1.0.0", 16, "192.168.0.2")))
+            .unwrap();
+        assert_eq!(
+            Some(&"192.168.0.1".parse().unwrap()),
+            routes.load().lookup(addr)
+        );
+
+        // next hops are addresses, and the longest prefix needs no priority.
+        let mut entry = route("10.2.0.0", 16, "192.168.0.3");
+        entry.params = vec![Value::Uint(3)];
+        assert!(table.write(Update::Insert(entry)).is_err());
+        let mut entry = route("10.2.0.0", 16, "192.168.0.3");
+        entry.priority = 1;
+        assert!(table.write(Update::Insert(entry)).is_err());
+        assert_eq!(1, routes.load().len());
+    }
+
+    #[test]
+    fn keep_routes_of_application() {
+        let mut initial = RouteTable::new();
+        initial.insert_v4(
+            "10.0.0.0/8".parse().unwrap(),
+            "192.168.0.1".parse().unwrap(),
+        );
+        let routes = Shared::new(initial);
+        let table = Table::new("routes", RoutesTable::new(routes.clone()));
+
+        let entry = TableEntry {
+            matches: vec![(
+                "dst".to_owned(),
+                FieldMatch::Lpm("10.1.0.0".parse().unwrap(), 16),
+            )],
+            action: "forward".to_owned(),
+            params: vec![Value::Addr("192.168.0.2".parse().unwrap())],
+            priority: 0,
+        };
+        table.write(Update::Insert(entry.clone())).unwrap();
+
+        // added by the application after the table.
+        routes
+            .update(|current| {
+                let mut routes = current.clone();
+                routes.insert_v4(
+                    "11.0.0.0/8".parse().unwrap(),
+                    "192.168.0.3".parse().unwrap(),
+                );
+                Ok(routes)
+            })
+            .unwrap();
+
+        table.write(Update::Delete(entry)).unwrap();
+        let routes = routes.load();
+        assert_eq!(2, routes.len());
+        assert_eq!(
+            Some(&"192.168.0.1".parse().unwrap()),
+            routes.lookup("10.1.2.3".parse().unwrap())
+        );
+        assert_eq!(
+            Some(&"192.168.0.3".parse().unwrap()),
+            routes.lookup("11.1.2.3".parse().unwrap())
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::dpdk::BufferError;
+use crate::Result;
+use failure::Fail;
+use std::cell::Cell;
+
+/// Error returned by an injected allocation failure.
+#[derive(Debug, Fail)]
+#[fail(display = "Injected mbuf allocation failure.")]
+pub struct InjectedAllocFailure;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct FaultPlan {
+    allocs: usize,
+    allocated: usize,
+    extends: usize,
+    fail_alloc_at: Option<usize>,
+    fail_alloc_over: Option<usize>,
+    fail_extend_at: Option<usize>,
+}
+
+thread_local! {
+    // the faults to inject on the current thread, `None` when not injecting.
+    static FAULTS: Cell<Option<FaultPlan>> = Cell::new(None);
+}
+
+fn update_plan<F: FnOnce(&mut FaultPlan)>(f: F) {
+    FAULTS.with(|faults| {
+        if let Some(mut plan) = faults.get() {
+            f(&mut plan);
+            faults.set(Some(plan));
+        }
+    })
+}
+
+/// Injects failures into the mbuf allocations and buffer extensions of the
+/// current thread, to exercise the error paths of the pipelines.
+///
+/// The faults are injected until the injector is dropped. Allocations and
+/// extensions are counted from `1`, including the ones `Mbuf::from_bytes`
+/// makes, and an `Mbuf::alloc_bulk` counts as one allocation of all its
+/// buffers. The allocations that are not failed go to the test
+/// mempool as usual.
+///
+/// # Example
+///
+/// ```
+/// #[nb2::test]
+/// fn drop_on_alloc_failure() {
+///     let _faults = FaultInjector::new().fail_alloc_at(2);
+///
+///     assert!(Mbuf::new().is_ok());
+///     assert!(Mbuf::new().is_err());
+///     assert!(Mbuf::new().is_ok());
+/// }
+/// ```
+pub struct FaultInjector {
+    _private: (),
+}
+
+impl FaultInjector {
+    /// Starts injecting faults on the current thread, with no failure
+    /// configured yet.
+    pub fn new() -> Self {
+        FAULTS.with(|faults| faults.set(Some(FaultPlan::default())));
+        FaultInjector { _private: () }
+    }
+
+    /// Fails the `nth` allocation.
+    pub fn fail_alloc_at(self, nth: usize) -> Self {
+        update_plan(|plan| plan.fail_alloc_at = Some(nth));
+        self
+    }
+
+    /// Fails every allocation that would take the number of buffers
+    /// allocated over `threshold`.
+    pub fn fail_alloc_over(self, threshold: usize) -> Self {
+        update_plan(|plan| plan.fail_alloc_over = Some(threshold));
+        self
+    }
+
+    /// Fails the `nth` extension of a buffer with `BufferError::NotResized`.
+    pub fn fail_extend_at(self, nth: usize) -> Self {
+        update_plan(|plan| plan.fail_extend_at = Some(nth));
+        self
+    }
+
+    /// Returns the number of buffers successfully allocated so far.
+    pub fn allocated(&self) -> usize {
+        FAULTS.with(|faults| faults.get().map_or(0, |plan| plan.allocated))
+    }
+}
+
+impl Default for FaultInjector {
+    fn default() -> Self {
+        FaultInjector::new()
+    }
+}
+
+impl Drop for FaultInjector {
+    fn drop(&mut self) {
+        FAULTS.with(|faults| faults.set(None));
+    }
+}
+
+/// Counts an allocation of `n` buffers, failing it if configured to.
+#[inline]
+pub(crate) fn check_alloc(n: usize) -> Result<()> {
+    let mut failed = false;
+    update_plan(|plan| {
+        plan.allocs += 1;
+        failed = plan.fail_alloc_at == Some(plan.allocs)
+            || plan
+                .fail_alloc_over
+                .map_or(false, |threshold| plan.allocated + n > threshold);
+        if !failed {
+            plan.allocated += n;
+        }
+    });
+
+    if failed {
+        Err(InjectedAllocFailure.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Counts an extension of a buffer, failing it if configured to.
+#[inline]
+pub(crate) fn check_extend() -> Result<()> {
+    let mut failed = false;
+    update_plan(|plan| {
+        plan.extends += 1;
+        failed = plan.fail_extend_at == Some(plan.extends);
+    });
+
+    if failed {
+        Err(BufferError::NotResized.into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mbuf;
+
+    #[nb2::test]
+    fn fail_nth_alloc() {
+        let faults = FaultInjector::new().fail_alloc_at(2);
+
+        let first = Mbuf::new();
+        assert!(first.is_ok());
+        assert!(Mbuf::new().is_err());
+        assert!(Mbuf::alloc_bulk(2).is_ok());
+        assert_eq!(3, faults.allocated());
+    }
+
+    #[nb2::test]
+    fn fail_alloc_over_threshold() {
+        let _faults = FaultInjector::new().fail_alloc_over(4);
+
+        let bulk = Mbuf::alloc_bulk(3).unwrap();
+        assert!(Mbuf::alloc_bulk(2).is_err());
+        let last = Mbuf::new();
+        assert!(last.is_ok());
+        assert!(Mbuf::new().is_err());
+        drop(bulk);
+    }
+
+    #[nb2::test]
+    fn fail_nth_extend() {
+        let _faults = FaultInjector::new().fail_extend_at(2);
+
+        let mut mbuf = Mbuf::new().unwrap();
+        assert!(mbuf.extend(0, 10).is_ok());
+        assert!(mbuf.extend(0, 10).is_err());
+        assert!(mbuf.extend(0, 10).is_ok());
+        assert_eq!(20, mbuf.data_len());
+    }
+
+    #[nb2::test]
+    fn stop_on_drop() {
+        {
+            let _faults = FaultInjector::new().fail_alloc_over(0);
+            assert!(Mbuf::new().is_err());
+        }
+        assert!(Mbuf::new().is_ok());
+    }
+}
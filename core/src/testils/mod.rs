@@ -1,5 +1,9 @@
+mod faults;
 mod packet;
+mod pcap;
 pub mod proptest;
+mod rxtx;
+mod snapshot;
 
 pub mod byte_arrays {
     pub use crate::packets::icmp::v4::ICMPV4_PACKET;
@@ -9,9 +13,15 @@ pub mod byte_arrays {
     pub use crate::packets::UDP_PACKET;
 }
 
+pub use self::faults::{FaultInjector, InjectedAllocFailure};
 pub use self::packet::*;
+pub use self::pcap::*;
+pub use self::rxtx::*;
+pub use self::snapshot::*;
 pub use crate::dpdk::{Mempool, SocketId, MEMPOOL};
 
+pub(crate) use self::faults::{check_alloc, check_extend};
+
 use crate::dpdk::eal_init;
 use std::sync::Once;
 
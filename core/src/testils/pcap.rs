@@ -0,0 +1,168 @@
+use crate::{ensure, Mbuf, Result};
+use failure::Fail;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// Error indicating the pcap file cannot be read.
+#[derive(Debug, Fail)]
+pub enum PcapError {
+    /// The file does not start with a pcap magic number.
+    #[fail(display = "Bad pcap magic number 0x{:08x}.", _0)]
+    BadMagic(u32),
+
+    /// The captured packets are not Ethernet frames.
+    #[fail(display = "Unsupported pcap link type {}.", _0)]
+    UnsupportedLinkType(u32),
+}
+
+/// Reader of packets from a libpcap capture.
+///
+/// Reads the classic pcap format, with either microsecond or nanosecond
+/// timestamps and in either byte order. Only Ethernet captures are
+/// supported. Each captured frame is copied into a newly allocated
+/// `Mbuf`, exactly as captured, including any padding or trailer.
+///
+/// # Example
+///
+/// ```
+/// for packet in PcapReader::open(corpus("ipv4.pcap"))? {
+///     let ethernet = packet?.parse::<Ethernet>()?;
+///     ...
+/// }
+/// ```
+pub struct PcapReader<R: Read> {
+    reader: R,
+    swapped: bool,
+}
+
+impl PcapReader<BufReader<File>> {
+    /// Opens the pcap file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        PcapReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Creates a new reader, consuming the pcap global header.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header)?;
+
+        let magic = read_u32(&header[..4], false);
+        let swapped = match magic {
+            MAGIC_MICROS | MAGIC_NANOS => false,
+            _ => match magic.swap_bytes() {
+                MAGIC_MICROS | MAGIC_NANOS => true,
+                _ => return Err(PcapError::BadMagic(magic).into()),
+            },
+        };
+
+        let link_type = read_u32(&header[20..], swapped);
+        ensure!(
+            link_type == LINKTYPE_ETHERNET,
+            PcapError::UnsupportedLinkType(link_type)
+        );
+
+        Ok(PcapReader { reader, swapped })
+    }
+
+    fn next_packet(&mut self) -> Result<Option<Mbuf>> {
+        let mut header = [0u8; 16];
+
+        // a clean end of file can only happen between records.
+        if self.reader.read(&mut header[..1])? == 0 {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut header[1..])?;
+
+        // only the captured portion of the frame is in the file.
+        let incl_len = read_u32(&header[8..12], self.swapped) as usize;
+        let mut data = vec![0u8; incl_len];
+        self.reader.read_exact(&mut data)?;
+
+        Mbuf::from_bytes(&data).map(Some)
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = Result<Mbuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_packet() {
+            Ok(Some(mbuf)) => Some(Ok(mbuf)),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], swapped: bool) -> u32 {
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    if swapped {
+        value.swap_bytes()
+    } else {
+        value
+    }
+}
+
+/// Loads all the packets in the pcap file at `path`.
+pub fn load_pcap<P: AsRef<Path>>(path: P) -> Result<Vec<Mbuf>> {
+    PcapReader::open(path)?.collect()
+}
+
+/// Returns the path of a pcap file in the checked-in corpus.
+///
+/// The corpus lives in the `corpus` directory of the `nb2` crate.
+pub fn corpus(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("corpus")
+        .join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::{Ethernet, Packet, Tcp, Udp};
+
+    #[nb2::test]
+    fn load_corpus_pcap() {
+        let mut packets = load_pcap(corpus("ipv4.pcap")).unwrap();
+        assert_eq!(2, packets.len());
+
+        // the first frame is padded to the ethernet minimum.
+        let tcp = packets.pop().unwrap();
+        let udp = packets.pop().unwrap();
+        assert_eq!(60, udp.data_len());
+
+        let udp = udp
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap()
+            .parse::<Udp<Ipv4>>()
+            .unwrap();
+        assert_eq!(1087, udp.dst_port());
+
+        let tcp = tcp
+            .parse::<Ethernet>()
+            .unwrap()
+            .parse::<Ipv4>()
+            .unwrap()
+            .parse::<Tcp<Ipv4>>()
+            .unwrap();
+        assert_eq!(23, tcp.dst_port());
+    }
+
+    #[test]
+    fn reject_non_pcap_file() {
+        assert!(PcapReader::new(&[0u8; 24][..]).is_err());
+    }
+}
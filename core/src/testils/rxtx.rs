@@ -0,0 +1,92 @@
+use crate::batch::{PacketRx, PacketTx};
+use crate::Mbuf;
+
+/// A `PacketRx` that receives the same packet over and over.
+///
+/// Each receive allocates a full batch of new message buffers with the
+/// template bytes, so pipelines can be driven at a steady rate without a
+/// port. The mempool must have room for at least one batch.
+pub struct RepeatRx {
+    data: Vec<u8>,
+    batch_size: usize,
+}
+
+impl RepeatRx {
+    /// Creates a new source of `batch_size` copies of `data` per receive.
+    pub fn new(data: &[u8], batch_size: usize) -> Self {
+        RepeatRx {
+            data: data.to_vec(),
+            batch_size,
+        }
+    }
+}
+
+impl PacketRx for RepeatRx {
+    fn receive(&mut self) -> Vec<Mbuf> {
+        (0..self.batch_size)
+            .filter_map(|_| Mbuf::from_bytes(&self.data).ok())
+            .collect()
+    }
+}
+
+/// A `PacketTx` that drops everything it transmits.
+///
+/// The packets are returned to the mempool in bulk, similar to a transmit
+/// on a `net_null` port.
+#[derive(Default)]
+pub struct NullTx {
+    count: usize,
+}
+
+impl NullTx {
+    /// Creates a new sink.
+    pub fn new() -> Self {
+        NullTx::default()
+    }
+
+    /// Returns the number of packets transmitted so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl PacketTx for NullTx {
+    fn transmit(&mut self, packets: Vec<Mbuf>) {
+        if !packets.is_empty() {
+            self.count += packets.len();
+            Mbuf::free_bulk(packets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::{Batch, Pipeline, Poll};
+    use crate::packets::{Ethernet, Packet};
+    use crate::testils::byte_arrays::UDP_PACKET;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[nb2::test]
+    fn run_pipeline_to_null_tx() {
+        let mut rx = RepeatRx::new(&UDP_PACKET, 4);
+        let mut tx = NullTx::new();
+        tx.transmit(rx.receive());
+        tx.transmit(vec![]);
+        assert_eq!(4, tx.count());
+
+        let seen = Rc::new(Cell::new(0));
+        let counter = seen.clone();
+        let mut pipeline = Poll::new(rx)
+            .map(move |packet| {
+                counter.set(counter.get() + 1);
+                packet.parse::<Ethernet>()
+            })
+            .send(NullTx::new());
+
+        pipeline.run_once();
+        pipeline.run_once();
+        assert_eq!(8, seen.get());
+    }
+}
@@ -0,0 +1,361 @@
+use crate::batch::{PacketRx, PacketTx, Pipeline, Poll};
+use crate::packets::ip::ProtocolNumbers;
+use crate::packets::{data_slice, EtherTypes, RawPacket};
+use crate::{Mbuf, Result};
+use failure::Fail;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Error indicating the emitted packets differ from the snapshot.
+#[derive(Debug, Fail)]
+pub enum SnapshotError {
+    /// A different number of packets was emitted.
+    #[fail(display = "Expected {} packets, {} were emitted.", _0, _1)]
+    CountMismatch(usize, usize),
+
+    /// A packet has a different length.
+    #[fail(display = "Packet {} is {} bytes, expected {}.", _0, _1, _2)]
+    LengthMismatch(usize, usize, usize),
+
+    /// A packet has different bytes.
+    #[fail(display = "Packet {} differs at byte {}.", _0, _1)]
+    DataMismatch(usize, usize),
+
+    /// The snapshot file is not valid.
+    #[fail(display = "Invalid snapshot file at line {}.", _0)]
+    BadFile(usize),
+
+    /// The snapshot file does not exist.
+    #[fail(
+        display = "Snapshot {:?} not found, set NB2_UPDATE_SNAPSHOTS to create it.",
+        _0
+    )]
+    Missing(PathBuf),
+}
+
+/// The environment variable that stores the emitted packets as the new
+/// snapshots instead of comparing them.
+pub const UPDATE_SNAPSHOTS: &str = "NB2_UPDATE_SNAPSHOTS";
+
+/// A volatile field that is ignored when comparing to the snapshot.
+///
+/// The masked bytes are zeroed before the packets are stored and compared.
+/// The fields that are not in a packet, for example the IPv4 checksum of an
+/// IPv6 packet, are left alone.
+#[derive(Clone, Copy, Debug)]
+pub enum Mask {
+    /// The identification of IPv4 packets.
+    Ipv4Identification,
+    /// The header checksum of IPv4 packets.
+    Ipv4Checksum,
+    /// The flow label of IPv6 packets.
+    Ipv6FlowLabel,
+    /// The checksum of TCP, UDP and ICMP packets.
+    L4Checksum,
+    /// `len` bytes at `offset` from the start of the frame.
+    Bytes(usize, usize),
+}
+
+impl Mask {
+    fn apply(self, raw: &RawPacket<'_>, data: &mut [u8]) {
+        let ipv4 = raw.ether_type() == EtherTypes::Ipv4;
+        let ipv6 = raw.ether_type() == EtherTypes::Ipv6;
+        let l3 = raw.l3_offset();
+
+        let (offset, len) = match self {
+            Mask::Ipv4Identification if ipv4 => (l3 + 4, 2),
+            Mask::Ipv4Checksum if ipv4 => (l3 + 10, 2),
+            Mask::Ipv6FlowLabel if ipv6 => {
+                // the flow label is the lower 20 bits of the first word.
+                if let Some(byte) = data.get_mut(l3 + 1) {
+                    *byte &= 0xf0;
+                }
+                (l3 + 2, 2)
+            }
+            Mask::L4Checksum => match (raw.protocol(), raw.l4_offset()) {
+                (Some(ProtocolNumbers::Tcp), Some(l4)) => (l4 + 16, 2),
+                (Some(ProtocolNumbers::Udp), Some(l4)) => (l4 + 6, 2),
+                (Some(ProtocolNumbers::Icmpv4), Some(l4))
+                | (Some(ProtocolNumbers::Icmpv6), Some(l4)) => (l4 + 2, 2),
+                _ => return,
+            },
+            Mask::Bytes(offset, len) => (offset, len),
+            _ => return,
+        };
+
+        let end = (offset + len).min(data.len());
+        if offset < end {
+            data[offset..end].iter_mut().for_each(|byte| *byte = 0);
+        }
+    }
+}
+
+/// Snapshot comparison of the packets emitted by a pipeline.
+///
+/// The pipeline under test is fed a fixed set of input packets, and the
+/// packets it emits are compared byte for byte against the expected
+/// packets stored in `<dir>/<name>.snap`, the directory given by the
+/// caller. Volatile fields, like checksums or IDs, are masked out of the
+/// comparison.
+///
+/// A missing snapshot file is a failure. When the `NB2_UPDATE_SNAPSHOTS`
+/// environment variable is set, the emitted packets are written as the new
+/// snapshot instead. Review the file before checking it in.
+///
+/// The file has one packet per line, in hex. Lines starting with `#` are
+/// comments.
+///
+/// # Example
+///
+/// ```
+/// #[nb2::test]
+/// fn nat_outbound() {
+///     let input = load_pcap(corpus("ipv4.pcap")).unwrap();
+///
+///     Snapshot::new(concat!(env!("CARGO_MANIFEST_DIR"), "/snapshots"), "nat_outbound")
+///         .mask(Mask::Ipv4Checksum)
+///         .mask(Mask::L4Checksum)
+///         .assert_pipeline(input, |batch, tx| {
+///             batch.map(nat_outbound).send(tx)
+///         });
+/// }
+/// ```
+pub struct Snapshot {
+    name: String,
+    dir: PathBuf,
+    masks: Vec<Mask>,
+}
+
+impl Snapshot {
+    /// Creates a new snapshot stored in `dir`.
+    ///
+    /// The directory is usually under the manifest directory of the crate
+    /// with the tests, `env!("CARGO_MANIFEST_DIR")` expanded in that crate.
+    pub fn new<P: AsRef<Path>>(dir: P, name: &str) -> Self {
+        Snapshot {
+            name: name.to_owned(),
+            dir: dir.as_ref().to_path_buf(),
+            masks: vec![],
+        }
+    }
+
+    /// Adds a volatile field to ignore.
+    pub fn mask(mut self, mask: Mask) -> Self {
+        self.masks.push(mask);
+        self
+    }
+
+    /// Returns the path of the snapshot file.
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(format!("{}.snap", self.name))
+    }
+
+    /// Returns the bytes of the packets with the masked fields zeroed,
+    /// freeing the packets.
+    pub fn capture(&self, packets: Vec<Mbuf>) -> Vec<Vec<u8>> {
+        let captured = packets
+            .iter()
+            .map(|mbuf| {
+                let mut data = data_slice(mbuf, 0, mbuf.data_len()).to_vec();
+                let raw = RawPacket::classify(mbuf);
+                self.masks
+                    .iter()
+                    .for_each(|mask| mask.apply(&raw, &mut data));
+                data
+            })
+            .collect();
+
+        Mbuf::free_bulk(packets);
+        captured
+    }
+
+    /// Compares the packets to the snapshot, or stores them as the new
+    /// snapshot if `NB2_UPDATE_SNAPSHOTS` is set.
+    pub fn verify(&self, packets: Vec<Mbuf>) -> Result<()> {
+        if env::var_os(UPDATE_SNAPSHOTS).is_some() {
+            self.store(packets)
+        } else {
+            self.compare(packets)
+        }
+    }
+
+    /// Stores the packets as the new snapshot.
+    pub fn store(&self, packets: Vec<Mbuf>) -> Result<()> {
+        let actual = self.capture(packets);
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(), encode(&self.name, &actual))?;
+        Ok(())
+    }
+
+    /// Compares the packets to the snapshot.
+    ///
+    /// # Errors
+    ///
+    /// If the snapshot file does not exist, or the packets differ from the
+    /// snapshot, `SnapshotError` is returned.
+    pub fn compare(&self, packets: Vec<Mbuf>) -> Result<()> {
+        let actual = self.capture(packets);
+        let path = self.path();
+        if !path.exists() {
+            return Err(SnapshotError::Missing(path).into());
+        }
+
+        let expected = decode(&fs::read_to_string(&path)?)?;
+        if expected.len() != actual.len() {
+            return Err(SnapshotError::CountMismatch(expected.len(), actual.len()).into());
+        }
+
+        for (i, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+            if expected.len() != actual.len() {
+                return Err(SnapshotError::LengthMismatch(i, actual.len(), expected.len()).into());
+            }
+            if let Some(offset) = expected.iter().zip(actual.iter()).position(|(a, b)| a != b) {
+                return Err(SnapshotError::DataMismatch(i, offset).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Asserts the packets match the snapshot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the packets differ from the snapshot.
+    pub fn assert_matches(&self, packets: Vec<Mbuf>) {
+        if let Err(err) = self.verify(packets) {
+            panic!("snapshot '{}' mismatch: {}", self.name, err);
+        }
+    }
+
+    /// Runs the pipeline built by `build` once over the input packets, and
+    /// asserts the packets it sends match the snapshot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the emitted packets differ from the snapshot.
+    pub fn assert_pipeline<F, P>(&self, input: Vec<Mbuf>, build: F)
+    where
+        F: FnOnce(Poll<Receiver<Mbuf>>, Sender<Mbuf>) -> P,
+        P: Pipeline,
+    {
+        self.assert_matches(run_pipeline(input, build));
+    }
+}
+
+/// Runs the pipeline built by `build` once over the input packets, and
+/// returns the packets it sends.
+pub fn run_pipeline<F, P>(input: Vec<Mbuf>, build: F) -> Vec<Mbuf>
+where
+    F: FnOnce(Poll<Receiver<Mbuf>>, Sender<Mbuf>) -> P,
+    P: Pipeline,
+{
+    let (mut input_tx, input_rx) = mpsc::channel();
+    input_tx.transmit(input);
+    let (output_tx, mut output_rx) = mpsc::channel();
+
+    let mut pipeline = build(Poll::new(input_rx), output_tx);
+    pipeline.run_once();
+    output_rx.receive()
+}
+
+fn encode(name: &str, packets: &[Vec<u8>]) -> String {
+    let mut out = format!("# snapshot {}, {} packets\n", name, packets.len());
+    for packet in packets {
+        for byte in packet {
+            let _ = write!(out, "{:02x}", byte);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn decode(content: &str) -> Result<Vec<Vec<u8>>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('#'))
+        .map(|(n, line)| {
+            let line = line.trim();
+            if line.len() % 2 != 0 {
+                return Err(SnapshotError::BadFile(n + 1).into());
+            }
+            (0..line.len())
+                .step_by(2)
+                .map(|i| {
+                    u8::from_str_radix(&line[i..i + 2], 16)
+                        .map_err(|_| SnapshotError::BadFile(n + 1).into())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::Batch;
+    use crate::packets::ip::v4::Ipv4;
+    use crate::packets::{Ethernet, Packet};
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+    use crate::testils::PacketExt;
+
+    fn input() -> Vec<Mbuf> {
+        vec![
+            Mbuf::from_bytes(&UDP_PACKET).unwrap(),
+            Mbuf::from_bytes(&TCP_PACKET).unwrap(),
+        ]
+    }
+
+    #[nb2::test]
+    fn mask_volatile_fields() {
+        let dir = env::temp_dir().join(format!("nb2-snapshot-{}", std::process::id()));
+        let snapshot = Snapshot::new(&dir, "decrement_ttl")
+            .mask(Mask::Ipv4Identification)
+            .mask(Mask::Ipv4Checksum);
+        let _ = fs::remove_file(snapshot.path());
+
+        let decrement_ttl = |batch: Poll<Receiver<Mbuf>>, tx: Sender<Mbuf>| {
+            batch
+                .map(|packet| {
+                    let mut v4 = packet.parse::<Ethernet>()?.parse::<Ipv4>()?;
+                    v4.set_ttl(v4.ttl() - 1);
+                    v4.cascade();
+                    Ok(v4)
+                })
+                .send(tx)
+        };
+
+        // a missing snapshot fails, unless explicitly stored.
+        let emitted = run_pipeline(input(), decrement_ttl);
+        assert!(snapshot.compare(emitted).is_err());
+        snapshot
+            .store(run_pipeline(input(), decrement_ttl))
+            .unwrap();
+        snapshot.assert_pipeline(input(), decrement_ttl);
+
+        // a different identification is masked, a different ttl is not.
+        let mut tcp = Mbuf::from_bytes(&TCP_PACKET).unwrap().into_v4();
+        tcp.set_identification(0x1234);
+        let changed = vec![Mbuf::from_bytes(&UDP_PACKET).unwrap(), tcp.reset()];
+        snapshot.assert_pipeline(changed, decrement_ttl);
+
+        let emitted = run_pipeline(input(), |batch, tx| batch.send(tx));
+        assert!(snapshot.compare(emitted).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn decode_snapshot_file() {
+        let content = encode("test", &[vec![0xde, 0xad], vec![0xbe, 0xef, 0x00]]);
+        assert_eq!(
+            vec![vec![0xde, 0xad], vec![0xbe, 0xef, 0x00]],
+            decode(&content).unwrap()
+        );
+        assert!(decode("abc").is_err());
+    }
+}
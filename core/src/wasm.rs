@@ -0,0 +1,439 @@
+//! WebAssembly plugins for packet operators.
+//!
+//! A plugin is a WebAssembly module, written in any language that targets
+//! it, that inspects and modifies the frames of a pipeline. A pipeline
+//! declares where a plugin runs with `Batch::wasm_plugin`, and the plugin
+//! is loaded, replaced or unloaded by name at run time with `load` and
+//! `unload`. Each core runs its own instance of the module, and picks up
+//! the new module with the next packet.
+//!
+//! The modules run in an interpreter with no imports, so a plugin only has
+//! access to its own memory and the frame handed to it. A module that
+//! imports anything is rejected when loaded. The modules are metered, a
+//! call that runs more than `STEP_LIMIT` instructions traps, and so does a
+//! start function when the module is loaded.
+//!
+//! A plugin exports
+//!
+//! * `memory`, its linear memory.
+//! * `nb2_buffer() -> i32`, the offset of a buffer in its memory the frames
+//!   are copied to.
+//! * `nb2_buffer_len() -> i32`, the capacity of the buffer.
+//! * `nb2_process(len: i32) -> i32`, called with the length of the frame
+//!   in the buffer. Returns `0` to pass the frame as is, a negative value to
+//!   drop it, or the new length of the frame if it is modified in the
+//!   buffer.
+//!
+//! The feature is enabled with `wasm`.
+
+use crate::packets::data_slice;
+use crate::{ensure, Mbuf, Result};
+use failure::Fail;
+use lazy_static::lazy_static;
+use parity_wasm::elements;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use wasmi::{
+    Externals, FuncInstance, FuncRef, HostError, ImportsBuilder, MemoryRef, Module,
+    ModuleImportResolver, ModuleInstance, ModuleRef, RuntimeArgs, RuntimeValue, Signature, Trap,
+    TrapKind, ValueType,
+};
+
+/// The instructions a call into a plugin may run before it traps.
+pub const STEP_LIMIT: u64 = 1_000_000;
+
+// the metering function imported by the instrumented modules, the only
+// import a plugin gets.
+const GAS_MODULE: &str = "env";
+const GAS_FUNC: &str = "gas";
+const GAS_INDEX: usize = 0;
+
+/// Error indicating a plugin failed.
+#[derive(Debug, Fail)]
+pub enum WasmError {
+    /// The module is invalid or cannot be instantiated, for example
+    /// because it has imports.
+    #[fail(display = "Failed to load the module: {}.", _0)]
+    Load(String),
+
+    /// The module does not export the function or the memory.
+    #[fail(display = "Module does not export '{}'.", _0)]
+    MissingExport(&'static str),
+
+    /// The frame is longer than the buffer of the plugin.
+    #[fail(display = "Frame of {} bytes exceeds the plugin buffer.", _0)]
+    FrameTooLong(usize),
+
+    /// The plugin trapped, or returned an invalid value.
+    #[fail(display = "Plugin failed: {}.", _0)]
+    Trap(String),
+
+    /// The plugin ran more than `STEP_LIMIT` instructions.
+    #[fail(display = "Plugin '{}' exceeded its step limit.", _0)]
+    StepLimit(&'static str),
+}
+
+/// The trap raised by the meter when the steps run out.
+#[derive(Debug)]
+struct OutOfSteps;
+
+impl fmt::Display for OutOfSteps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "out of steps")
+    }
+}
+
+impl HostError for OutOfSteps {}
+
+/// Counts down the steps of a call, the instrumented module reports the
+/// cost of each block before running it.
+struct Meter {
+    remaining: u64,
+}
+
+impl Meter {
+    fn new() -> Self {
+        Meter {
+            remaining: STEP_LIMIT,
+        }
+    }
+}
+
+impl Externals for Meter {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> std::result::Result<Option<RuntimeValue>, Trap> {
+        match index {
+            GAS_INDEX => {
+                let steps = u64::from(args.nth_checked::<u32>(0)?);
+                if steps > self.remaining {
+                    self.remaining = 0;
+                    return Err(TrapKind::Host(Box::new(OutOfSteps)).into());
+                }
+                self.remaining -= steps;
+                Ok(None)
+            }
+            _ => Err(TrapKind::Unreachable.into()),
+        }
+    }
+}
+
+/// Resolves the metering function of the instrumented modules.
+struct MeterResolver;
+
+impl ModuleImportResolver for MeterResolver {
+    fn resolve_func(
+        &self,
+        field_name: &str,
+        _signature: &Signature,
+    ) -> std::result::Result<FuncRef, wasmi::Error> {
+        match field_name {
+            GAS_FUNC => Ok(FuncInstance::alloc_host(
+                Signature::new(&[ValueType::I32][..], None),
+                GAS_INDEX,
+            )),
+            _ => Err(wasmi::Error::Instantiation(format!(
+                "Export {} not found",
+                field_name
+            ))),
+        }
+    }
+}
+
+/// Returns whether the trap is the meter running out of steps.
+fn is_out_of_steps(err: &wasmi::Error) -> bool {
+    err.as_host_error()
+        .and_then(|err| err.downcast_ref::<OutOfSteps>())
+        .is_some()
+}
+
+/// Parses the module and instruments it with the metering.
+fn metered_module(bytes: &[u8]) -> Result<Module> {
+    let module = elements::deserialize_buffer::<elements::Module>(bytes)
+        .map_err(|err| WasmError::Load(err.to_string()))?;
+
+    // the metering is the only import, a plugin has access to nothing.
+    let imports = module
+        .import_section()
+        .map_or(0, |section| section.entries().len());
+    ensure!(
+        imports == 0,
+        WasmError::Load("the module has imports".to_owned())
+    );
+
+    let module = pwasm_utils::inject_gas_counter(module, &Default::default())
+        .map_err(|_| WasmError::Load("the module cannot be metered".to_owned()))?;
+    Module::from_parity_wasm_module(module).map_err(|err| WasmError::Load(err.to_string()).into())
+}
+
+/// What a plugin decides for a frame.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    /// The frame goes on unchanged.
+    Pass,
+    /// The frame is dropped.
+    Drop,
+    /// The frame is rewritten by the plugin, to the new length.
+    Modified(usize),
+}
+
+/// An instance of a plugin.
+///
+/// The instance is bound to the core it is created on.
+pub struct Plugin {
+    instance: ModuleRef,
+    memory: MemoryRef,
+    buffer: u32,
+    buffer_len: usize,
+    // the frame passed in and out of the plugin memory.
+    scratch: Vec<u8>,
+}
+
+impl Plugin {
+    /// Instantiates the module.
+    ///
+    /// # Errors
+    ///
+    /// If the module is invalid, has imports, does not export the operator
+    /// interface or its start function runs out of steps, `WasmError` is
+    /// returned.
+    pub fn new(bytes: &[u8]) -> Result<Self> {
+        let module = metered_module(bytes)?;
+        let imports = ImportsBuilder::new().with_resolver(GAS_MODULE, &MeterResolver);
+        let instance = ModuleInstance::new(&module, &imports)
+            .map_err(|err| WasmError::Load(err.to_string()))?
+            .run_start(&mut Meter::new())
+            .map_err(|trap| match trap.kind() {
+                TrapKind::Host(err) if err.downcast_ref::<OutOfSteps>().is_some() => {
+                    WasmError::StepLimit("start")
+                }
+                _ => WasmError::Load(trap.to_string()),
+            })?;
+
+        let memory = instance
+            .export_by_name("memory")
+            .and_then(|export| export.as_memory().cloned())
+            .ok_or_else(|| WasmError::MissingExport("memory"))?;
+
+        let mut plugin = Plugin {
+            instance,
+            memory,
+            buffer: 0,
+            buffer_len: 0,
+            scratch: vec![],
+        };
+
+        plugin.buffer = plugin.call("nb2_buffer", &[])? as u32;
+        plugin.buffer_len = plugin.call("nb2_buffer_len", &[])? as usize;
+        ensure!(
+            instance_has(&plugin.instance, "nb2_process"),
+            WasmError::MissingExport("nb2_process")
+        );
+
+        Ok(plugin)
+    }
+
+    fn call(&self, name: &'static str, args: &[RuntimeValue]) -> Result<i32> {
+        ensure!(
+            instance_has(&self.instance, name),
+            WasmError::MissingExport(name)
+        );
+
+        match self.instance.invoke_export(name, args, &mut Meter::new()) {
+            Ok(Some(RuntimeValue::I32(value))) => Ok(value),
+            Ok(_) => Err(WasmError::Trap(format!("{} did not return an i32", name)).into()),
+            Err(ref err) if is_out_of_steps(err) => Err(WasmError::StepLimit(name).into()),
+            Err(err) => Err(WasmError::Trap(err.to_string()).into()),
+        }
+    }
+
+    /// Runs the plugin on the frame, and rewrites the frame if the plugin
+    /// modifies it.
+    ///
+    /// # Errors
+    ///
+    /// If the frame does not fit in the buffer of the plugin, or the plugin
+    /// traps, `WasmError` is returned. If the frame cannot be resized to
+    /// the new length, `BufferError` is returned.
+    pub fn process(&mut self, mbuf: &mut Mbuf) -> Result<Verdict> {
+        let len = mbuf.data_len();
+        ensure!(len <= self.buffer_len, WasmError::FrameTooLong(len));
+
+        self.memory
+            .set(self.buffer, data_slice(mbuf, 0, len))
+            .map_err(|err| WasmError::Trap(err.to_string()))?;
+
+        let verdict = match self.call("nb2_process", &[RuntimeValue::I32(len as i32)])? {
+            0 => Verdict::Pass,
+            v if v < 0 => Verdict::Drop,
+            v => {
+                let new_len = v as usize;
+                ensure!(new_len <= self.buffer_len, WasmError::FrameTooLong(new_len));
+
+                self.scratch.resize(new_len, 0);
+                self.memory
+                    .get_into(self.buffer, &mut self.scratch)
+                    .map_err(|err| WasmError::Trap(err.to_string()))?;
+
+                if new_len > len {
+                    mbuf.extend(len, new_len - len)?;
+                } else if new_len < len {
+                    mbuf.truncate(new_len)?;
+                }
+                mbuf.write_data_slice(0, &self.scratch)?;
+                Verdict::Modified(new_len)
+            }
+        };
+
+        Ok(verdict)
+    }
+}
+
+fn instance_has(instance: &ModuleRef, name: &str) -> bool {
+    instance
+        .export_by_name(name)
+        .and_then(|export| export.as_func().cloned())
+        .is_some()
+}
+
+/// The module loaded under a name.
+pub(crate) struct PluginSlot {
+    // bumped every time the module changes, so the pipelines only take the
+    // lock when there's a new module.
+    version: AtomicUsize,
+    bytes: Mutex<Option<Arc<Vec<u8>>>>,
+}
+
+impl PluginSlot {
+    /// Returns the version of the module loaded.
+    #[inline]
+    pub(crate) fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Returns the module loaded.
+    pub(crate) fn bytes(&self) -> Option<Arc<Vec<u8>>> {
+        self.bytes.lock().unwrap().clone()
+    }
+
+    fn set(&self, bytes: Option<Arc<Vec<u8>>>) {
+        *self.bytes.lock().unwrap() = bytes;
+        self.version.fetch_add(1, Ordering::Release);
+    }
+}
+
+lazy_static! {
+    static ref PLUGINS: RwLock<HashMap<String, Arc<PluginSlot>>> = RwLock::new(HashMap::new());
+}
+
+/// Returns the slot of the name, creating it if it is new.
+pub(crate) fn plugin_slot(name: &str) -> Arc<PluginSlot> {
+    if let Some(slot) = PLUGINS.read().unwrap().get(name) {
+        return slot.clone();
+    }
+
+    PLUGINS
+        .write()
+        .unwrap()
+        .entry(name.to_owned())
+        .or_insert_with(|| {
+            Arc::new(PluginSlot {
+                version: AtomicUsize::new(0),
+                bytes: Mutex::new(None),
+            })
+        })
+        .clone()
+}
+
+/// Loads the module as the plugin of the name, replacing the module loaded
+/// before.
+///
+/// # Errors
+///
+/// The module is instantiated once to check it. If it fails, `WasmError`
+/// is returned and the plugin loaded before stays in place.
+pub fn load(name: &str, bytes: Vec<u8>) -> Result<()> {
+    Plugin::new(&bytes)?;
+    plugin_slot(name).set(Some(Arc::new(bytes)));
+    Ok(())
+}
+
+/// Unloads the plugin of the name. The frames pass through unchanged
+/// afterwards.
+pub fn unload(name: &str) {
+    if let Some(slot) = PLUGINS.read().unwrap().get(name) {
+        slot.set(None);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::testils::byte_arrays::{TCP_PACKET, UDP_PACKET};
+
+    /// A plugin that drops the UDP frames, by the protocol of the IPv4
+    /// header at offset 23.
+    #[rustfmt::skip]
+    pub(crate) const DROP_UDP: [u8; 123] = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // types, `() -> i32` and `(i32) -> i32`.
+        0x01, 0x0a, 0x02, 0x60, 0x00, 0x01, 0x7f, 0x60, 0x01, 0x7f, 0x01, 0x7f,
+        // functions.
+        0x03, 0x04, 0x03, 0x00, 0x00, 0x01,
+        // a page of memory.
+        0x05, 0x03, 0x01, 0x00, 0x01,
+        // exports.
+        0x07, 0x36, 0x04,
+        0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00,
+        0x0a, 0x6e, 0x62, 0x32, 0x5f, 0x62, 0x75, 0x66, 0x66, 0x65, 0x72, 0x00, 0x00,
+        0x0e, 0x6e, 0x62, 0x32, 0x5f, 0x62, 0x75, 0x66, 0x66, 0x65, 0x72, 0x5f, 0x6c, 0x65,
+        0x6e, 0x00, 0x01,
+        0x0b, 0x6e, 0x62, 0x32, 0x5f, 0x70, 0x72, 0x6f, 0x63, 0x65, 0x73, 0x73, 0x00, 0x02,
+        // code, the buffer at 1024 to the end of the page.
+        0x0a, 0x22, 0x03,
+        0x05, 0x00, 0x41, 0x80, 0x08, 0x0b,
+        0x06, 0x00, 0x41, 0x80, 0xf8, 0x03, 0x0b,
+        // `if load8_u(1024 + 23) == 17 { -1 } else { 0 }`
+        0x13, 0x00, 0x41, 0x00, 0x2d, 0x00, 0x97, 0x08, 0x41, 0x11, 0x46, 0x04, 0x7f, 0x41,
+        0x7f, 0x05, 0x41, 0x00, 0x0b, 0x0b,
+    ];
+
+    #[nb2::test]
+    fn run_plugin() {
+        let mut plugin = Plugin::new(&DROP_UDP).unwrap();
+
+        let mut udp = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        assert_eq!(Verdict::Drop, plugin.process(&mut udp).unwrap());
+
+        let mut tcp = Mbuf::from_bytes(&TCP_PACKET).unwrap();
+        assert_eq!(Verdict::Pass, plugin.process(&mut tcp).unwrap());
+    }
+
+    #[nb2::test]
+    fn trap_runaway_plugin() {
+        // `nb2_process` loops forever.
+        let mut bytes = DROP_UDP[..88].to_vec();
+        bytes.extend_from_slice(&[0x18, 0x03]);
+        bytes.extend_from_slice(&DROP_UDP[90..103]);
+        bytes.extend_from_slice(&[0x09, 0x00, 0x03, 0x40, 0x0c, 0x00, 0x0b, 0x41, 0x00, 0x0b]);
+        let mut plugin = Plugin::new(&bytes).unwrap();
+
+        let mut udp = Mbuf::from_bytes(&UDP_PACKET).unwrap();
+        assert!(plugin.process(&mut udp).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_module() {
+        assert!(load("wasm-test", vec![0x00, 0x61, 0x73, 0x6d]).is_err());
+        assert!(plugin_slot("wasm-test").bytes().is_none());
+
+        load("wasm-test", DROP_UDP.to_vec()).unwrap();
+        assert!(plugin_slot("wasm-test").bytes().is_some());
+        unload("wasm-test");
+        assert!(plugin_slot("wasm-test").bytes().is_none());
+    }
+}
@@ -29,7 +29,7 @@ fn main() {
         .whitelist_type(r"(rte|cmdline|ether|eth|arp|vlan|vxlan)_.*")
         .whitelist_function(r"(_rte|rte|cmdline|lcore|ether|eth|arp|is)_.*")
         .whitelist_var(
-            r"(RTE|CMDLINE|ETHER|ARP|VXLAN|BONDING|LCORE|MEMPOOL|ARP|PKT|EXT_ATTACHED|IND_ATTACHED|lcore|rte|cmdline|per_lcore)_.*",
+            r"(RTE|DEV|CMDLINE|ETHER|ARP|VXLAN|BONDING|LCORE|MEMPOOL|ARP|PKT|EXT_ATTACHED|IND_ATTACHED|lcore|rte|cmdline|per_lcore)_.*",
         )
         .derive_copy(true)
         .derive_debug(true)
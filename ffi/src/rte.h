@@ -1,8 +1,14 @@
 /// known issues:
 // 1. https://github.com/rust-lang/rust/issues/54341
 
+#include <rte_cycles.h>
 #include <rte_eal.h>
 #include <rte_errno.h>
 #include <rte_ethdev.h>
+#include <rte_flow.h>
+#include <rte_hash.h>
+#include <rte_hash_crc.h>
 #include <rte_kni.h>
 #include <rte_lcore.h>
+#include <rte_reorder.h>
+#include <rte_ring.h>
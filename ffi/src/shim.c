@@ -1,8 +1,15 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+
+#include <rte_cycles.h>
 #include <rte_errno.h>
 #include <rte_ethdev.h>
+#include <rte_hash_crc.h>
 #include <rte_lcore.h>
+#include <rte_log.h>
 #include <rte_mbuf.h>
 #include <rte_mempool.h>
+#include <rte_ring.h>
 
 int _rte_errno(void) {
     return rte_errno;
@@ -31,6 +38,14 @@ int _rte_pktmbuf_alloc_bulk(
     return rte_pktmbuf_alloc_bulk(pool, mbufs, count);
 }
 
+char *_rte_pktmbuf_prepend(struct rte_mbuf *m, uint16_t len) {
+    return rte_pktmbuf_prepend(m, len);
+}
+
+char *_rte_pktmbuf_adj(struct rte_mbuf *m, uint16_t len) {
+    return rte_pktmbuf_adj(m, len);
+}
+
 void _rte_mempool_put_bulk(
     struct rte_mempool *mp,
     void *const *obj_table,
@@ -53,3 +68,84 @@ uint16_t _rte_eth_tx_burst(
     uint16_t nb_pkts) {
     return rte_eth_tx_burst(port_id, queue_id, tx_pkts, nb_pkts);
 }
+
+uint64_t _rte_rdtsc(void) {
+    return rte_rdtsc();
+}
+
+unsigned _rte_ring_enqueue_burst(
+    struct rte_ring *r,
+    void *const *obj_table,
+    unsigned n,
+    unsigned *free_space) {
+    return rte_ring_enqueue_burst(r, obj_table, n, free_space);
+}
+
+unsigned _rte_ring_dequeue_burst(
+    struct rte_ring *r,
+    void **obj_table,
+    unsigned n,
+    unsigned *available) {
+    return rte_ring_dequeue_burst(r, obj_table, n, available);
+}
+
+unsigned _rte_ring_count(const struct rte_ring *r) {
+    return rte_ring_count(r);
+}
+
+uint32_t _rte_mbuf_packet_type(const struct rte_mbuf *m) {
+    return m->packet_type;
+}
+
+void _rte_mbuf_set_packet_type(struct rte_mbuf *m, uint32_t packet_type) {
+    m->packet_type = packet_type;
+}
+
+uint64_t _rte_mbuf_udata64(const struct rte_mbuf *m) {
+    return m->udata64;
+}
+
+void _rte_mbuf_set_udata64(struct rte_mbuf *m, uint64_t udata64) {
+    m->udata64 = udata64;
+}
+
+uint32_t _rte_mbuf_header_lens(const struct rte_mbuf *m) {
+    return (uint32_t)m->l2_len << 16 | m->l3_len;
+}
+
+void _rte_mbuf_set_header_lens(struct rte_mbuf *m, uint16_t l2_len, uint16_t l3_len) {
+    m->l2_len = l2_len;
+    m->l3_len = l3_len;
+}
+
+uint32_t _rte_hash_crc(const void *data, uint32_t data_len, uint32_t init_val) {
+    return rte_hash_crc(data, data_len, init_val);
+}
+
+static _rte_log_callback log_callback;
+
+static ssize_t _rte_log_write(void *cookie, const char *buf, size_t size) {
+    (void)cookie;
+    log_callback(rte_log_cur_msg_loglevel(), rte_log_cur_msg_logtype(), buf, size);
+    return size;
+}
+
+int _rte_openlog_callback(_rte_log_callback callback) {
+    cookie_io_functions_t io = {
+        .read = NULL,
+        .write = _rte_log_write,
+        .seek = NULL,
+        .close = NULL,
+    };
+
+    FILE *stream = fopencookie(NULL, "w", io);
+    if (stream == NULL) {
+        return -1;
+    }
+
+    // flushes every line, so a message is never merged with the next.
+    setvbuf(stream, NULL, _IOLBF, 0);
+
+    log_callback = callback;
+    return rte_openlog_stream(stream);
+}
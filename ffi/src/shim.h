@@ -1,5 +1,8 @@
+#include <stddef.h>
+#include <rte_cycles.h>
 #include <rte_mbuf.h>
 #include <rte_mempool.h>
+#include <rte_ring.h>
 
 /**
  * Error number value, stored per-thread, which can be queried after
@@ -36,6 +39,16 @@ int _rte_pktmbuf_alloc_bulk(
     struct rte_mbuf **mbufs,
     unsigned count);
 
+/**
+ * Prepend len bytes to an mbuf data area.
+ */
+char *_rte_pktmbuf_prepend(struct rte_mbuf *m, uint16_t len);
+
+/**
+ * Remove len bytes at the beginning of an mbuf.
+ */
+char *_rte_pktmbuf_adj(struct rte_mbuf *m, uint16_t len);
+
 /**
  * Put several objects back in the mempool.
  */
@@ -63,3 +76,79 @@ uint16_t _rte_eth_tx_burst(
     uint16_t queue_id,
     struct rte_mbuf **tx_pkts,
     uint16_t nb_pkts);
+
+/**
+ * Read the time base register.
+ */
+uint64_t _rte_rdtsc(void);
+
+/**
+ * Enqueue several objects on a ring, as many as there is room for.
+ */
+unsigned _rte_ring_enqueue_burst(
+    struct rte_ring *r,
+    void *const *obj_table,
+    unsigned n,
+    unsigned *free_space);
+
+/**
+ * Dequeue several objects from a ring, up to a maximum number.
+ */
+unsigned _rte_ring_dequeue_burst(
+    struct rte_ring *r,
+    void **obj_table,
+    unsigned n,
+    unsigned *available);
+
+/**
+ * Return the number of entries in a ring.
+ */
+unsigned _rte_ring_count(const struct rte_ring *r);
+
+/**
+ * Return the packet type of an mbuf, as classified by the device.
+ */
+uint32_t _rte_mbuf_packet_type(const struct rte_mbuf *m);
+
+/**
+ * Set the packet type of an mbuf.
+ */
+void _rte_mbuf_set_packet_type(struct rte_mbuf *m, uint32_t packet_type);
+
+/**
+ * Return the application data word of an mbuf.
+ */
+uint64_t _rte_mbuf_udata64(const struct rte_mbuf *m);
+
+/**
+ * Set the application data word of an mbuf.
+ */
+void _rte_mbuf_set_udata64(struct rte_mbuf *m, uint64_t udata64);
+
+/**
+ * Return the L2 and L3 header lengths of an mbuf, packed as l2_len << 16 |
+ * l3_len.
+ */
+uint32_t _rte_mbuf_header_lens(const struct rte_mbuf *m);
+
+/**
+ * Set the L2 and L3 header lengths of an mbuf.
+ */
+void _rte_mbuf_set_header_lens(struct rte_mbuf *m, uint16_t l2_len, uint16_t l3_len);
+
+/**
+ * Calculate a hash value of the key using the CRC32 instructions.
+ */
+uint32_t _rte_hash_crc(const void *data, uint32_t data_len, uint32_t init_val);
+
+/**
+ * Callback receiving the log messages, with the level and the type of the
+ * message.
+ */
+typedef void (*_rte_log_callback)(uint32_t level, uint32_t logtype, const char *msg, size_t len);
+
+/**
+ * Redirect the log messages to a callback instead of stderr. The messages
+ * are passed on line by line.
+ */
+int _rte_openlog_callback(_rte_log_callback callback);
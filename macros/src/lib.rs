@@ -4,7 +4,7 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, ItemFn};
+use syn::{parse_macro_input, AttributeArgs, ItemFn, Lit, Meta, NestedMeta};
 
 /// Procedural macro for running DPDK based tests.
 ///
@@ -67,10 +67,26 @@ pub fn test(_args: TokenStream, input: TokenStream) -> TokenStream {
 ///     });
 /// }
 /// ```
+///
+/// Benches that hold on to more than 15 buffers at a time, for example
+/// a whole pipeline processing large batches, can raise the capacity.
+///
+/// ```
+/// #[nb2::bench(mempool_capacity = 511)]
+/// fn pipeline_benchmark(c: &mut Criterion) {
+///     ...
+/// }
+/// ```
 #[proc_macro_attribute]
-pub fn bench(_args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn bench(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
     let input = parse_macro_input!(input as ItemFn);
 
+    let capacity = match mempool_capacity(&args) {
+        Ok(capacity) => capacity,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
     let ret = &input.sig.output;
     let name = &input.sig.ident;
     let inputs = &input.sig.inputs;
@@ -79,7 +95,7 @@ pub fn bench(_args: TokenStream, input: TokenStream) -> TokenStream {
     let result = quote! {
         fn #name(#inputs) #ret {
             ::nb2::testils::cargo_test_init();
-            let mut mempool = ::nb2::testils::Mempool::new(15, 0, ::nb2::testils::SocketId::ANY).unwrap();
+            let mut mempool = ::nb2::testils::Mempool::new(#capacity, 0, ::nb2::testils::SocketId::ANY).unwrap();
             ::nb2::testils::MEMPOOL.with(|tls| tls.set(mempool.raw_mut()));
 
             #body
@@ -91,3 +107,27 @@ pub fn bench(_args: TokenStream, input: TokenStream) -> TokenStream {
 
     result.into()
 }
+
+/// Parses the optional `mempool_capacity = N` argument, defaults to 15.
+fn mempool_capacity(args: &AttributeArgs) -> syn::Result<usize> {
+    let mut capacity = 15;
+
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("mempool_capacity") => {
+                match &nv.lit {
+                    Lit::Int(lit) => capacity = lit.base10_parse()?,
+                    lit => {
+                        return Err(syn::Error::new_spanned(
+                            lit,
+                            "mempool_capacity must be an integer",
+                        ))
+                    }
+                }
+            }
+            _ => return Err(syn::Error::new_spanned(arg, "unknown bench argument")),
+        }
+    }
+
+    Ok(capacity)
+}